@@ -1,31 +1,42 @@
 #[macro_use]
 extern crate log;
 
+#[cfg(feature = "cli")]
 use clap::Parser;
 
-use std::any::Any;
-use std::sync::Arc;
 use std::time::Duration;
 use zeroconf::prelude::*;
-use zeroconf::{MdnsBrowser, ServiceDiscovery, ServiceType};
+use zeroconf::{MdnsBrowser, ServiceType};
 
 /// Example of a simple mDNS browser
-#[derive(Parser, Debug)]
-#[command(author, version, about)]
+#[derive(Debug)]
+#[cfg_attr(feature = "cli", derive(Parser))]
+#[cfg_attr(feature = "cli", command(author, version, about))]
 struct Args {
     /// Name of the service type to browse
-    #[clap(short, long, default_value = "http")]
+    #[cfg_attr(feature = "cli", clap(short, long, default_value = "http"))]
     name: String,
 
     /// Protocol of the service type to browse
-    #[clap(short, long, default_value = "tcp")]
+    #[cfg_attr(feature = "cli", clap(short, long, default_value = "tcp"))]
     protocol: String,
 
     /// Sub-type of the service type to browse
-    #[clap(short, long)]
+    #[cfg_attr(feature = "cli", clap(short, long))]
     sub_type: Option<String>,
 }
 
+#[cfg(not(feature = "cli"))]
+impl Args {
+    fn parse() -> Self {
+        Self {
+            name: "http".to_string(),
+            protocol: "tcp".to_string(),
+            sub_type: None,
+        }
+    }
+}
+
 fn main() -> zeroconf::Result<()> {
     env_logger::init();
 
@@ -45,24 +56,19 @@ fn main() -> zeroconf::Result<()> {
 
     let mut browser = MdnsBrowser::new(service_type);
 
-    browser.set_service_discovered_callback(Box::new(on_service_discovered));
+    browser.set_on_discovered(|result| {
+        info!(
+            "Service discovered: {:?}",
+            result.expect("service discovery failed")
+        );
+
+        // ...
+    });
 
     let event_loop = browser.browse_services()?;
 
     loop {
         // calling `poll()` will keep this browser alive
-        event_loop.poll(Duration::from_secs(0))?;
+        event_loop.poll(Duration::from_secs(1))?;
     }
 }
-
-fn on_service_discovered(
-    result: zeroconf::Result<ServiceDiscovery>,
-    _context: Option<Arc<dyn Any>>,
-) {
-    info!(
-        "Service discovered: {:?}",
-        result.expect("service discovery failed")
-    );
-
-    // ...
-}
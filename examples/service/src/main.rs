@@ -1,33 +1,39 @@
 #[macro_use]
 extern crate log;
 
+#[cfg(feature = "cli")]
 use clap::Parser;
 
-use std::any::Any;
-use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use zeroconf::prelude::*;
-use zeroconf::{MdnsService, ServiceRegistration, ServiceType, TxtRecord};
+use zeroconf::{MdnsService, ServiceType, TxtRecord};
 
-#[derive(Parser, Debug)]
-#[command(author, version, about)]
+#[derive(Debug)]
+#[cfg_attr(feature = "cli", derive(Parser))]
+#[cfg_attr(feature = "cli", command(author, version, about))]
 struct Args {
     /// Name of the service type to register
-    #[clap(short, long, default_value = "http")]
+    #[cfg_attr(feature = "cli", clap(short, long, default_value = "http"))]
     name: String,
 
     /// Protocol of the service type to register
-    #[clap(short, long, default_value = "tcp")]
+    #[cfg_attr(feature = "cli", clap(short, long, default_value = "tcp"))]
     protocol: String,
 
     /// Sub-types of the service type to register
-    #[clap(short, long)]
+    #[cfg_attr(feature = "cli", clap(short, long))]
     sub_types: Vec<String>,
 }
 
-#[derive(Default, Debug)]
-pub struct Context {
-    service_name: String,
+#[cfg(not(feature = "cli"))]
+impl Args {
+    fn parse() -> Self {
+        Self {
+            name: "http".to_string(),
+            protocol: "tcp".to_string(),
+            sub_types: vec![],
+        }
+    }
 }
 
 fn main() -> zeroconf::Result<()> {
@@ -43,44 +49,28 @@ fn main() -> zeroconf::Result<()> {
     let service_type = ServiceType::with_sub_types(&name, &protocol, sub_types)?;
     let mut service = MdnsService::new(service_type, 8080);
     let mut txt_record = TxtRecord::new();
-    let context: Arc<Mutex<Context>> = Arc::default();
+    let mut registrations = 0;
 
     txt_record.insert("foo", "bar")?;
 
     service.set_name("zeroconf_example_service");
-    service.set_registered_callback(Box::new(on_service_registered));
-    service.set_context(Box::new(context));
     service.set_txt_record(txt_record);
 
-    let event_loop = service.register()?;
-
-    loop {
-        // calling `poll()` will keep this service alive
-        event_loop.poll(Duration::from_secs(0))?;
-    }
-}
-
-fn on_service_registered(
-    result: zeroconf::Result<ServiceRegistration>,
-    context: Option<Arc<dyn Any>>,
-) {
-    let service = result.expect("failed to register service");
+    service.set_on_registered(move |result| {
+        let service = result.expect("failed to register service");
 
-    info!("Service registered: {:?}", service);
+        // closures may keep state of their own
+        registrations += 1;
 
-    let context = context
-        .as_ref()
-        .expect("could not get context")
-        .downcast_ref::<Arc<Mutex<Context>>>()
-        .expect("error down-casting context")
-        .clone();
+        info!("Service registered ({}): {:?}", registrations, service);
 
-    context
-        .lock()
-        .expect("failed to obtain context lock")
-        .service_name = service.name().to_string();
+        // ...
+    });
 
-    info!("Context: {:?}", context);
+    let event_loop = service.register()?;
 
-    // ...
+    loop {
+        // calling `poll()` will keep this service alive
+        event_loop.poll(Duration::from_secs(1))?;
+    }
 }
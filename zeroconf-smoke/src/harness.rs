@@ -0,0 +1,105 @@
+//! Deadline-driven polling of one or more event loops
+
+use crate::report::{Outcome, Report};
+use std::time::{Duration, Instant};
+use zeroconf::prelude::*;
+use zeroconf::EventLoop;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Runs the steps of a smoke test, polling every registered event loop until each step either
+/// completes or its deadline elapses.
+pub struct Harness {
+    deadline: Duration,
+    event_loops: Vec<EventLoop>,
+    report: Report,
+}
+
+impl Harness {
+    pub fn new(backend: &'static str, deadline: Duration) -> Self {
+        Self {
+            deadline,
+            event_loops: vec![],
+            report: Report::new(backend),
+        }
+    }
+
+    /// Keeps the specified event loop alive and polls it for the remainder of the run.
+    pub fn attach(&mut self, event_loop: EventLoop) {
+        self.event_loops.push(event_loop);
+    }
+
+    /// Runs the step `name`, which is started by `start` and is complete once `check` returns
+    /// `Some`. Returns the value produced by the step, or `None` if it failed.
+    pub fn step<T, S, C>(&mut self, name: &'static str, start: S, check: C) -> Option<T>
+    where
+        S: FnOnce(&mut Self) -> Result<(), String>,
+        C: FnMut() -> Option<Result<T, String>>,
+    {
+        info!("{}: starting", name);
+
+        let started = Instant::now();
+
+        if let Err(reason) = start(self) {
+            self.report
+                .record(name, Outcome::Failed(reason), started.elapsed());
+            return None;
+        }
+
+        match self.wait(started, check) {
+            Ok(value) => {
+                self.report.record(name, Outcome::Passed, started.elapsed());
+                Some(value)
+            }
+            Err(reason) => {
+                self.report
+                    .record(name, Outcome::Failed(reason), started.elapsed());
+                None
+            }
+        }
+    }
+
+    /// Polls every registered event loop until `check` returns `Some`, or fails once the deadline
+    /// has elapsed since `started`.
+    pub fn wait<T, C>(&self, started: Instant, mut check: C) -> Result<T, String>
+    where
+        C: FnMut() -> Option<Result<T, String>>,
+    {
+        loop {
+            if let Some(result) = check() {
+                return result;
+            }
+
+            if started.elapsed() > self.deadline {
+                return Err(format!("deadline of {:?} exceeded", self.deadline));
+            }
+
+            self.poll()
+                .map_err(|e| format!("event loop failed: {}", e))?;
+        }
+    }
+
+    /// Records that the step `name` was not run.
+    pub fn skip(&mut self, name: &'static str, reason: &str) {
+        self.report.skip(name, reason);
+    }
+
+    pub fn into_report(self) -> Report {
+        self.report
+    }
+
+    fn poll(&self) -> zeroconf::Result<()> {
+        if self.event_loops.is_empty() {
+            std::thread::sleep(POLL_INTERVAL);
+            return Ok(());
+        }
+
+        let timeout = POLL_INTERVAL / self.event_loops.len() as u32;
+
+        for event_loop in &self.event_loops {
+            event_loop.poll(timeout)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,448 @@
+//! End-to-end smoke test of the `zeroconf` public API.
+//!
+//! Registers a local-only service with a TXT record and sub-type, then browses, resolves and looks
+//! up the host of that service through the system mDNS daemon. The TXT record is then updated and
+//! browsed for again, and the service is unregistered and expected to be reported as removed.
+//! Every step has a deadline, and a report is printed once the run is over. The process exits
+//! with a nonzero status if any step failed.
+//!
+//! Usage: `zeroconf-smoke [--deadline-secs <n>] [--json]`
+
+#[macro_use]
+extern crate log;
+
+mod harness;
+mod report;
+
+use harness::Harness;
+use std::process;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use zeroconf::prelude::*;
+use zeroconf::{
+    BrowserEvent, HostResolution, MdnsBrowser, MdnsHostResolver, MdnsResolver, MdnsService,
+    NetworkInterface, ServiceDiscovery, ServiceRegistration, ServiceType, TxtRecord,
+};
+
+const DEFAULT_DEADLINE: Duration = Duration::from_secs(10);
+const SERVICE_TYPE_NAME: &str = "zcsmoke";
+const SERVICE_PROTOCOL: &str = "tcp";
+const SERVICE_SUB_TYPE: &str = "probe";
+const SERVICE_PORT: u16 = 48123;
+const INTERFACE: NetworkInterface = NetworkInterface::LocalOnly;
+
+const STEPS: [&str; 6] = [
+    "register",
+    "browse",
+    "resolve",
+    "host_lookup",
+    "update",
+    "unregister",
+];
+
+#[cfg(target_os = "linux")]
+const BACKEND: &str = "avahi";
+#[cfg(not(target_os = "linux"))]
+const BACKEND: &str = "bonjour";
+
+type Shared<T> = Arc<Mutex<T>>;
+
+/// A running browser, along with the events it has reported so far
+struct Browse {
+    _browser: MdnsBrowser,
+    events: Shared<Vec<BrowserEvent>>,
+}
+
+#[derive(Debug)]
+struct Options {
+    deadline: Duration,
+    json: bool,
+}
+
+fn main() {
+    env_logger::init();
+
+    let options = match parse_args(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}", e);
+            eprintln!("usage: zeroconf-smoke [--deadline-secs <n>] [--json]");
+            process::exit(2);
+        }
+    };
+
+    let report = run(options.deadline).into_report();
+
+    if options.json {
+        print_json(&report);
+    } else {
+        println!("{}", report);
+    }
+
+    if !report.is_success() {
+        process::exit(1);
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options {
+        deadline: DEFAULT_DEADLINE,
+        json: false,
+    };
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--deadline-secs" => {
+                let secs = args
+                    .next()
+                    .ok_or("--deadline-secs requires a value")?
+                    .parse::<u64>()
+                    .map_err(|e| format!("invalid --deadline-secs: {}", e))?;
+
+                options.deadline = Duration::from_secs(secs);
+            }
+            "--json" => options.json = true,
+            other => return Err(format!("unrecognized argument: {}", other)),
+        }
+    }
+
+    Ok(options)
+}
+
+#[cfg(feature = "json")]
+fn print_json(report: &report::Report) {
+    println!(
+        "{}",
+        serde_json::to_string_pretty(report).expect("could not serialize report")
+    );
+}
+
+#[cfg(not(feature = "json"))]
+fn print_json(report: &report::Report) {
+    eprintln!("--json requires the `json` feature, falling back to text output");
+    println!("{}", report);
+}
+
+fn service_type() -> zeroconf::Result<ServiceType> {
+    ServiceType::with_sub_types(SERVICE_TYPE_NAME, SERVICE_PROTOCOL, vec![SERVICE_SUB_TYPE])
+}
+
+fn txt_record() -> zeroconf::Result<TxtRecord> {
+    let mut txt = TxtRecord::new();
+    txt.insert("smoke", "1")?;
+    txt.insert("pid", &process::id().to_string())?;
+    Ok(txt)
+}
+
+/// The TXT record the service is updated to by the `update` step
+fn updated_txt_record() -> zeroconf::Result<TxtRecord> {
+    let mut txt = txt_record()?;
+    txt.insert("smoke", "2")?;
+    Ok(txt)
+}
+
+fn take<T>(shared: &Shared<Option<zeroconf::Result<T>>>) -> Option<Result<T, String>> {
+    shared
+        .lock()
+        .expect("failed to obtain lock")
+        .take()
+        .map(|r| r.map_err(|e| e.to_string()))
+}
+
+fn run(deadline: Duration) -> Harness {
+    let mut harness = Harness::new(BACKEND, deadline);
+    let service_name = format!("zeroconf-smoke-{}", process::id());
+
+    let mut service = match service_type() {
+        Ok(service_type) => MdnsService::new(service_type, SERVICE_PORT),
+        Err(e) => {
+            harness.step::<(), _, _>("register", |_| Err(e.to_string()), || None);
+            return harness;
+        }
+    };
+
+    let registered: Shared<Option<zeroconf::Result<ServiceRegistration>>> = Arc::default();
+
+    let registration = harness.step(
+        "register",
+        |harness| {
+            let registered = registered.clone();
+
+            service.set_name(&service_name);
+            service.set_network_interface(INTERFACE);
+            service.set_txt_record(txt_record().map_err(|e| e.to_string())?);
+            service.set_registered_callback(Box::new(move |result, _| {
+                *registered.lock().expect("failed to obtain lock") = Some(result);
+            }));
+
+            harness.attach(service.register().map_err(|e| e.to_string())?);
+
+            Ok(())
+        },
+        || take(&registered),
+    );
+
+    let registration = match registration {
+        Some(registration) => registration,
+        None => {
+            skip_remaining(&mut harness, &STEPS[1..]);
+            return harness;
+        }
+    };
+
+    let browse = start_browse(&mut harness, "browse", &registration, |_| txt_record());
+
+    let (_browse, discovery) = match browse {
+        Some(browse) => browse,
+        None => {
+            skip_remaining(&mut harness, &STEPS[2..]);
+            return harness;
+        }
+    };
+
+    resolve(&mut harness, &registration);
+    host_lookup(&mut harness, &discovery);
+
+    let browse = update(&mut harness, &service, &registration, &registered);
+
+    let browse = match browse {
+        Some(browse) => browse,
+        None => {
+            skip_remaining(&mut harness, &STEPS[5..]);
+            return harness;
+        }
+    };
+
+    unregister(&mut harness, service, &registration, &browse);
+
+    harness
+}
+
+fn skip_remaining(harness: &mut Harness, steps: &[&'static str]) {
+    for step in steps {
+        harness.skip(step, "a previous step failed");
+    }
+}
+
+/// Runs the step `name`, which browses for the service of this run until it is found with the TXT
+/// record returned by `prepare`. The browser is started once `prepare` has returned, and is
+/// returned for the steps that follow.
+fn start_browse<P>(
+    harness: &mut Harness,
+    name: &'static str,
+    registration: &ServiceRegistration,
+    prepare: P,
+) -> Option<(Browse, ServiceDiscovery)>
+where
+    P: FnOnce(&mut Harness) -> zeroconf::Result<TxtRecord>,
+{
+    let discovered: Shared<Vec<zeroconf::Result<ServiceDiscovery>>> = Arc::default();
+    let events: Shared<Vec<BrowserEvent>> = Arc::default();
+    let mut browser = MdnsBrowser::new(service_type().ok()?);
+    let expected: Shared<Option<TxtRecord>> = Arc::default();
+
+    let discovery = harness.step(
+        name,
+        |harness| {
+            let txt = prepare(harness).map_err(|e| e.to_string())?;
+            *expected.lock().expect("failed to obtain lock") = Some(txt);
+
+            let discovered = discovered.clone();
+            let events = events.clone();
+
+            browser.set_network_interface(INTERFACE);
+
+            browser.set_service_discovered_callback(Box::new(move |result, _| {
+                discovered
+                    .lock()
+                    .expect("failed to obtain lock")
+                    .push(result);
+            }));
+
+            browser.set_browser_event_callback(Box::new(move |event, _| {
+                events.lock().expect("failed to obtain lock").push(event);
+            }));
+
+            harness.attach(browser.browse_services().map_err(|e| e.to_string())?);
+
+            Ok(())
+        },
+        || {
+            let found = discovered
+                .lock()
+                .expect("failed to obtain lock")
+                .drain(..)
+                .find(|d| match d {
+                    Ok(d) => d.name() == registration.name(),
+                    Err(_) => true,
+                })?;
+
+            match found {
+                Ok(discovery) => {
+                    let expected = expected.lock().expect("failed to obtain lock");
+                    Some(check_discovery(&discovery, expected.as_ref()?).map(|_| discovery))
+                }
+                Err(e) => Some(Err(e.to_string())),
+            }
+        },
+    )?;
+
+    let browse = Browse {
+        _browser: browser,
+        events,
+    };
+
+    Some((browse, discovery))
+}
+
+/// Replaces the TXT record of the registered service through its `Deferrer`, and browses again
+/// once it has been replaced until the service is found with the new record.
+fn update(
+    harness: &mut Harness,
+    service: &MdnsService,
+    registration: &ServiceRegistration,
+    registered: &Shared<Option<zeroconf::Result<ServiceRegistration>>>,
+) -> Option<Browse> {
+    let updated: Shared<Option<zeroconf::Result<()>>> = Arc::default();
+
+    let (browse, _) = start_browse(harness, "update", registration, |harness| {
+        let sink = updated.clone();
+
+        service.deferrer().defer(move |service| {
+            let txt = updated_txt_record()?;
+            service.set_txt_record(txt)?;
+            *sink.lock().expect("failed to obtain lock") = Some(Ok(()));
+            Ok(())
+        });
+
+        // the closure is executed by the event loop of the service, and its errors are passed to
+        // the registered callback
+        harness
+            .wait(Instant::now(), || {
+                take(&updated).or_else(|| match take(registered) {
+                    Some(Err(e)) => Some(Err(e)),
+                    _ => None,
+                })
+            })
+            .map_err(|e| format!("could not update TXT record: {}", e))?;
+
+        updated_txt_record()
+    })?;
+
+    Some(browse)
+}
+
+/// Drops the service and waits for `browse` to report it as removed.
+fn unregister(
+    harness: &mut Harness,
+    service: MdnsService,
+    registration: &ServiceRegistration,
+    browse: &Browse,
+) {
+    harness.step(
+        "unregister",
+        |_| {
+            drop(service);
+            Ok(())
+        },
+        || {
+            let events = browse.events.lock().expect("failed to obtain lock");
+
+            events
+                .iter()
+                .any(|event| match event {
+                    BrowserEvent::ServiceRemoved(key) => key.name() == registration.name(),
+                    _ => false,
+                })
+                .then_some(Ok(()))
+        },
+    );
+}
+
+fn check_discovery(discovery: &ServiceDiscovery, txt: &TxtRecord) -> Result<(), String> {
+    if *discovery.port() != SERVICE_PORT {
+        return Err(format!(
+            "expected port {} but found {}",
+            SERVICE_PORT,
+            discovery.port()
+        ));
+    }
+
+    let expected = txt.to_map();
+
+    let actual = discovery.txt().map(|txt| txt.to_map()).unwrap_or_default();
+
+    if expected != actual {
+        return Err(format!(
+            "expected TXT record {:?} but found {:?}",
+            expected, actual
+        ));
+    }
+
+    Ok(())
+}
+
+fn resolve(harness: &mut Harness, registration: &ServiceRegistration) {
+    let resolved: Shared<Option<zeroconf::Result<ServiceDiscovery>>> = Arc::default();
+    let mut resolver = MdnsResolver::new();
+
+    harness.step(
+        "resolve",
+        |harness| {
+            let resolved = resolved.clone();
+
+            resolver.set_network_interface(INTERFACE);
+
+            resolver.set_service_resolved_callback(Box::new(move |result, _| {
+                resolved
+                    .lock()
+                    .expect("failed to obtain lock")
+                    .get_or_insert(result);
+            }));
+
+            let service_type =
+                ServiceType::new(SERVICE_TYPE_NAME, SERVICE_PROTOCOL).map_err(|e| e.to_string())?;
+
+            harness.attach(
+                resolver
+                    .resolve(registration.name(), service_type, registration.domain())
+                    .map_err(|e| e.to_string())?,
+            );
+
+            Ok(())
+        },
+        || {
+            take(&resolved).map(|r| {
+                r.and_then(|d| check_discovery(&d, &txt_record().map_err(|e| e.to_string())?))
+            })
+        },
+    );
+}
+
+fn host_lookup(harness: &mut Harness, discovery: &ServiceDiscovery) {
+    let resolved: Shared<Option<zeroconf::Result<HostResolution>>> = Arc::default();
+    let mut resolver = MdnsHostResolver::new();
+
+    harness.step(
+        "host_lookup",
+        |harness| {
+            let resolved = resolved.clone();
+
+            resolver.set_host_resolved_callback(Box::new(move |result, _| {
+                resolved
+                    .lock()
+                    .expect("failed to obtain lock")
+                    .get_or_insert(result);
+            }));
+
+            harness.attach(
+                resolver
+                    .resolve(discovery.host_name())
+                    .map_err(|e| e.to_string())?,
+            );
+
+            Ok(())
+        },
+        || take(&resolved).map(|r| r.map(|_| ())),
+    );
+}
@@ -0,0 +1,101 @@
+//! Structured report of a smoke test run
+
+use std::fmt;
+use std::time::Duration;
+
+/// The outcome of a single step
+#[cfg_attr(feature = "json", derive(serde::Serialize))]
+#[cfg_attr(
+    feature = "json",
+    serde(tag = "status", content = "detail", rename_all = "lowercase")
+)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Passed,
+    Failed(String),
+    Skipped(String),
+}
+
+/// A step that has been run (or skipped) by the harness
+#[cfg_attr(feature = "json", derive(serde::Serialize))]
+#[derive(Debug, Clone)]
+pub struct StepReport {
+    pub name: &'static str,
+    pub outcome: Outcome,
+    pub elapsed_ms: u64,
+}
+
+/// Report of every step in a smoke test run, in the order they were run
+#[cfg_attr(feature = "json", derive(serde::Serialize))]
+#[derive(Debug, Default, Clone)]
+pub struct Report {
+    pub backend: &'static str,
+    pub steps: Vec<StepReport>,
+}
+
+impl Report {
+    pub fn new(backend: &'static str) -> Self {
+        Self {
+            backend,
+            steps: vec![],
+        }
+    }
+
+    pub fn record(&mut self, name: &'static str, outcome: Outcome, elapsed: Duration) {
+        match &outcome {
+            Outcome::Passed => info!("{}: passed in {:?}", name, elapsed),
+            Outcome::Failed(reason) => error!("{}: failed in {:?}: {}", name, elapsed, reason),
+            Outcome::Skipped(reason) => warn!("{}: skipped: {}", name, reason),
+        }
+
+        self.steps.push(StepReport {
+            name,
+            outcome,
+            elapsed_ms: elapsed.as_millis() as u64,
+        });
+    }
+
+    pub fn skip(&mut self, name: &'static str, reason: &str) {
+        self.record(name, Outcome::Skipped(reason.to_string()), Duration::ZERO);
+    }
+
+    /// Returns true if no step has failed
+    pub fn is_success(&self) -> bool {
+        !self
+            .steps
+            .iter()
+            .any(|s| matches!(s.outcome, Outcome::Failed(_)))
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "zeroconf smoke test ({})", self.backend)?;
+
+        for step in &self.steps {
+            let (status, detail) = match &step.outcome {
+                Outcome::Passed => ("PASS", None),
+                Outcome::Failed(reason) => ("FAIL", Some(reason)),
+                Outcome::Skipped(reason) => ("SKIP", Some(reason)),
+            };
+
+            write!(
+                f,
+                "  [{}] {:<16} {:>6}ms",
+                status, step.name, step.elapsed_ms
+            )?;
+
+            if let Some(detail) = detail {
+                write!(f, "  {}", detail)?;
+            }
+
+            writeln!(f)?;
+        }
+
+        write!(
+            f,
+            "result: {}",
+            if self.is_success() { "ok" } else { "FAILED" }
+        )
+    }
+}
@@ -0,0 +1,106 @@
+//! Measures how many resolved services per second are turned into a `ServiceDiscovery`, which is
+//! what the Avahi browser does for every service it resolves.
+//!
+//! Run with `cargo bench --bench resolve`.
+
+#[cfg(all(target_os = "linux", not(feature = "test-mock")))]
+mod avahi {
+    use criterion::{Criterion, Throughput};
+    use libc::c_char;
+    use std::ffi::CString;
+    use std::ptr;
+    use zeroconf::avahi::{avahi_util, resolver};
+    use zeroconf::LookupFlags;
+
+    /// A TXT record like the ones announced by Chromecasts
+    const TXT: &[(&str, &str)] = &[
+        ("id", "4b2f4a1c9e8d7f6a5b4c3d2e1f0a9b8c"),
+        ("cd", "1A2B3C4D5E6F7A8B9C0D1E2F3A4B5C6D"),
+        ("rm", ""),
+        ("ve", "05"),
+        ("md", "Chromecast"),
+        ("ic", "/setup/icon.png"),
+        ("fn", "Living Room TV"),
+        ("ca", "465413"),
+        ("st", "0"),
+        ("bs", "FA8FCA7A1B2C"),
+        ("nf", "1"),
+        ("rs", ""),
+    ];
+
+    pub fn resolve(c: &mut Criterion) {
+        let name = CString::new("Chromecast-4b2f4a1c9e8d7f6a5b4c3d2e1f0a9b8c").unwrap();
+        let kind = CString::new("_googlecast._tcp").unwrap();
+        let domain = CString::new("local").unwrap();
+        let host_name = CString::new("4b2f4a1c-9e8d-7f6a-5b4c-3d2e1f0a9b8c.local").unwrap();
+        let address = avahi_util::avahi_address("192.168.1.42".parse().unwrap());
+
+        let mut txt = ptr::null_mut();
+
+        for (key, value) in TXT {
+            let key = CString::new(*key).unwrap();
+            let value = CString::new(*value).unwrap();
+
+            txt = unsafe {
+                avahi_sys::avahi_string_list_add_pair(
+                    txt,
+                    key.as_ptr() as *const c_char,
+                    value.as_ptr() as *const c_char,
+                )
+            };
+        }
+
+        let mut group = c.benchmark_group("resolve");
+        group.throughput(Throughput::Elements(1));
+
+        // the TXT record is only decoded when it is accessed, which most consumers never do
+        for (id, flags, access_txt) in [
+            ("service_discovery", LookupFlags::NONE, false),
+            ("service_discovery_txt_accessed", LookupFlags::NONE, true),
+            ("service_discovery_no_txt", LookupFlags::NO_TXT, false),
+        ] {
+            let txt = if flags.contains(LookupFlags::NO_TXT) {
+                ptr::null_mut()
+            } else {
+                txt
+            };
+
+            group.bench_function(id, |b| {
+                b.iter(|| {
+                    let discovery = unsafe {
+                        resolver::service_discovery(
+                            avahi_sys::AVAHI_IF_UNSPEC,
+                            host_name.as_ptr(),
+                            &address,
+                            name.as_ptr(),
+                            kind.as_ptr(),
+                            domain.as_ptr(),
+                            8009,
+                            txt,
+                            flags,
+                        )
+                    }
+                    .unwrap();
+
+                    if access_txt {
+                        assert!(discovery.txt().is_some());
+                    }
+
+                    discovery
+                })
+            });
+        }
+
+        group.finish();
+
+        unsafe { avahi_sys::avahi_string_list_free(txt) };
+    }
+}
+
+#[cfg(all(target_os = "linux", not(feature = "test-mock")))]
+criterion::criterion_group!(benches, avahi::resolve);
+#[cfg(all(target_os = "linux", not(feature = "test-mock")))]
+criterion::criterion_main!(benches);
+
+#[cfg(any(not(target_os = "linux"), feature = "test-mock"))]
+fn main() {}
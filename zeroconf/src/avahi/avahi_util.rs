@@ -1,36 +1,58 @@
 //! Utilities related to Avahi
 
+use super::string_list::AvahiString;
 use crate::ffi::c_str;
 use avahi_sys::{
-    avahi_address_snprint, avahi_alternative_service_name, avahi_strerror, AvahiAddress,
-    AvahiClient,
+    avahi_address_snprint, avahi_alternative_service_name, avahi_service_name_join, avahi_strerror,
+    AvahiAddress, AvahiClient, AvahiClientState, AvahiLookupFlags, AvahiProtocol,
+    AvahiPublishFlags,
 };
 use libc::c_char;
-use std::ffi::CStr;
+use std::ffi::{CStr, CString};
+use std::net::IpAddr;
 
-use crate::{NetworkInterface, Result, ServiceType};
+use crate::{
+    DaemonState, LookupFlags, NetworkInterface, Protocol, RegistrationFlags, Result, ServiceType,
+};
 
 /// Converts the specified `*const AvahiAddress` to a `String`.
 ///
-/// The new `String` is constructed through allocating a new `CString`, passing it to
-/// `avahi_address_snprint` and then converting it to a Rust-type `String`.
+/// The address is printed with `avahi_address_snprint` into a buffer on the stack, so the
+/// returned `String` is the only allocation.
 ///
 /// # Safety
 /// This function is unsafe because of internal Avahi calls and raw pointer dereference.
 pub unsafe fn avahi_address_to_string(addr: *const AvahiAddress) -> String {
     assert_not_null!(addr);
 
-    let addr_str = c_string!(alloc(avahi_sys::AVAHI_ADDRESS_STR_MAX as usize));
+    let mut buf = [0 as c_char; avahi_sys::AVAHI_ADDRESS_STR_MAX as usize];
+
+    avahi_address_snprint(buf.as_mut_ptr(), buf.len(), addr);
 
-    avahi_address_snprint(
-        addr_str.as_ptr() as *mut c_char,
-        avahi_sys::AVAHI_ADDRESS_STR_MAX as usize,
-        addr,
-    );
+    c_str::raw_to_str(buf.as_ptr()).to_string()
+}
 
-    String::from(c_str::to_str(&addr_str))
-        .trim_matches(char::from(0))
-        .to_string()
+/// Converts the specified `IpAddr` to an `AvahiAddress`.
+pub fn avahi_address(address: IpAddr) -> AvahiAddress {
+    match address {
+        IpAddr::V4(v4) => AvahiAddress {
+            proto: avahi_sys::AVAHI_PROTO_INET,
+            data: avahi_sys::AvahiAddress__bindgen_ty_1 {
+                // stored in network byte order
+                ipv4: avahi_sys::AvahiIPv4Address {
+                    address: u32::from_ne_bytes(v4.octets()),
+                },
+            },
+        },
+        IpAddr::V6(v6) => AvahiAddress {
+            proto: avahi_sys::AVAHI_PROTO_INET6,
+            data: avahi_sys::AvahiAddress__bindgen_ty_1 {
+                ipv6: avahi_sys::AvahiIPv6Address {
+                    address: v6.octets(),
+                },
+            },
+        },
+    }
 }
 
 /// Returns the `&str` message associated with the specified error code.
@@ -51,13 +73,45 @@ pub unsafe fn get_last_error<'a>(client: *mut AvahiClient) -> &'a str {
     get_error(avahi_sys::avahi_client_errno(client))
 }
 
+/// Converts the specified `AvahiClientState` to the [`DaemonState`] reported to users, if it is
+/// one. The host name registration states of the daemon are not reported.
+///
+/// [`DaemonState`]: ../../daemon/enum.DaemonState.html
+pub fn daemon_state(state: AvahiClientState) -> Option<DaemonState> {
+    match state {
+        avahi_sys::AvahiClientState_AVAHI_CLIENT_CONNECTING => Some(DaemonState::Connecting),
+        avahi_sys::AvahiClientState_AVAHI_CLIENT_S_RUNNING => Some(DaemonState::Running),
+        avahi_sys::AvahiClientState_AVAHI_CLIENT_FAILURE => Some(DaemonState::Failed),
+        _ => None,
+    }
+}
+
 /// Converts the specified [`NetworkInterface`] to the Avahi expected value.
 ///
+/// Avahi has no notion of local-only services, so `NetworkInterface::LocalOnly` maps to the
+/// loopback interface.
+///
 /// [`NetworkInterface`]: ../../enum.NetworkInterface.html
 pub fn interface_index(interface: NetworkInterface) -> i32 {
     match interface {
         NetworkInterface::Unspec => avahi_sys::AVAHI_IF_UNSPEC,
         NetworkInterface::AtIndex(i) => i as i32,
+        NetworkInterface::LocalOnly => loopback_index(),
+    }
+}
+
+/// Returns the index of the first loopback interface.
+fn loopback_index() -> i32 {
+    let loopback = crate::interfaces()
+        .ok()
+        .and_then(|interfaces| interfaces.into_iter().find(|i| *i.loopback()));
+
+    match loopback {
+        Some(interface) => *interface.index() as i32,
+        None => {
+            warn!("no loopback interface found, binding to all interfaces");
+            avahi_sys::AVAHI_IF_UNSPEC
+        }
     }
 }
 
@@ -69,6 +123,68 @@ pub fn interface_from_index(index: i32) -> NetworkInterface {
     }
 }
 
+/// Converts the specified [`Protocol`] to the Avahi expected value.
+///
+/// [`Protocol`]: ../../enum.Protocol.html
+pub fn protocol(protocol: Protocol) -> AvahiProtocol {
+    match protocol {
+        Protocol::Any => avahi_sys::AVAHI_PROTO_UNSPEC,
+        Protocol::V4 => avahi_sys::AVAHI_PROTO_INET,
+        Protocol::V6 => avahi_sys::AVAHI_PROTO_INET6,
+    }
+}
+
+/// Converts the specified [`RegistrationFlags`] to the Avahi expected value. Every flag is
+/// supported by Avahi.
+///
+/// [`RegistrationFlags`]: ../../struct.RegistrationFlags.html
+pub fn publish_flags(flags: RegistrationFlags) -> AvahiPublishFlags {
+    [
+        (
+            RegistrationFlags::NO_PROBE,
+            avahi_sys::AvahiPublishFlags_AVAHI_PUBLISH_NO_PROBE,
+        ),
+        (
+            RegistrationFlags::NO_ANNOUNCE,
+            avahi_sys::AvahiPublishFlags_AVAHI_PUBLISH_NO_ANNOUNCE,
+        ),
+        (
+            RegistrationFlags::UPDATE,
+            avahi_sys::AvahiPublishFlags_AVAHI_PUBLISH_UPDATE,
+        ),
+        (
+            RegistrationFlags::USE_MULTICAST,
+            avahi_sys::AvahiPublishFlags_AVAHI_PUBLISH_USE_MULTICAST,
+        ),
+    ]
+    .iter()
+    .filter(|(flag, _)| flags.contains(*flag))
+    .fold(0, |acc, (_, publish_flag)| acc | publish_flag)
+}
+
+/// Converts the specified [`LookupFlags`] to the `AvahiLookupFlags` they correspond to.
+///
+/// [`LookupFlags`]: ../../struct.LookupFlags.html
+pub fn lookup_flags(flags: LookupFlags) -> AvahiLookupFlags {
+    [
+        (
+            LookupFlags::USE_MULTICAST,
+            avahi_sys::AvahiLookupFlags_AVAHI_LOOKUP_USE_MULTICAST,
+        ),
+        (
+            LookupFlags::NO_TXT,
+            avahi_sys::AvahiLookupFlags_AVAHI_LOOKUP_NO_TXT,
+        ),
+        (
+            LookupFlags::NO_ADDRESS,
+            avahi_sys::AvahiLookupFlags_AVAHI_LOOKUP_NO_ADDRESS,
+        ),
+    ]
+    .iter()
+    .filter(|(flag, _)| flags.contains(*flag))
+    .fold(0, |acc, (_, lookup_flag)| acc | lookup_flag)
+}
+
 /// Executes the specified closure and returns a formatted `Result`
 ///
 /// # Safety
@@ -85,23 +201,18 @@ pub unsafe fn sys_exec<F: FnOnce() -> i32>(func: F, message: &str) -> Result<()>
 
 /// Formats the specified `ServiceType` as a `String` for use with Avahi
 pub fn format_service_type(service_type: &ServiceType) -> String {
-    format!("_{}._{}", service_type.name(), service_type.protocol())
+    service_type.to_string()
 }
 
-/// Formats the specified `ServiceType` as a `String` for browsing Avahi services
+/// Formats the specified `ServiceType` as a `String` for browsing Avahi services. Avahi can only
+/// browse by a single sub-type, so any sub-type but the first is ignored.
 pub fn format_browser_type(service_type: &ServiceType) -> String {
     let kind = format_service_type(service_type);
-    let sub_types = service_type.sub_types();
-
-    if sub_types.is_empty() {
-        return kind;
-    }
 
-    if sub_types.len() > 1 {
-        warn!("browsing by multiple sub-types is not supported on Avahi devices, using first sub-type only");
+    match service_type.sub_types().first() {
+        Some(sub_type) => format_sub_type(sub_type, &kind),
+        None => kind,
     }
-
-    format_sub_type(&sub_types[0], &kind)
 }
 
 /// Formats the specified `sub_type` string as a `String` for use with Avahi
@@ -114,12 +225,43 @@ pub fn format_sub_type(sub_type: &str, kind: &str) -> String {
     )
 }
 
-/// Returns an alternative service name for the specified `CStr`
+/// Returns the alternative service name Avahi chooses for `name` after a collision, or `None` if
+/// `name` is not a valid service name.
 ///
 /// # Safety
 /// This function is unsafe because of the call to `avahi_alternative_service_name`.
-pub unsafe fn alternative_service_name(name: &CStr) -> &CStr {
-    CStr::from_ptr(avahi_alternative_service_name(name.as_ptr()))
+pub unsafe fn alternative_service_name(name: &CStr) -> Option<String> {
+    AvahiString::from(avahi_alternative_service_name(name.as_ptr())).to_string_lossy()
+}
+
+/// Joins the specified service instance `name`, `kind` and `domain` into a fully qualified,
+/// escaped domain name. If no domain is specified, `local` is used.
+///
+/// # Safety
+/// This function is unsafe because of the call to `avahi_service_name_join`.
+pub unsafe fn join_service_name(
+    name: &CStr,
+    kind: &CStr,
+    domain: Option<&CStr>,
+) -> Result<CString> {
+    let local = c_string!("local");
+    let domain = domain.unwrap_or(&local);
+    let mut buffer = vec![0 as c_char; avahi_sys::AVAHI_DOMAIN_NAME_MAX as usize];
+
+    sys_exec(
+        || {
+            avahi_service_name_join(
+                buffer.as_mut_ptr(),
+                buffer.len(),
+                name.as_ptr(),
+                kind.as_ptr(),
+                domain.as_ptr(),
+            )
+        },
+        "could not join service name",
+    )?;
+
+    Ok(CStr::from_ptr(buffer.as_ptr()).to_owned())
 }
 
 #[cfg(test)]
@@ -130,6 +272,42 @@ mod tests {
         AVAHI_PROTO_INET6,
     };
 
+    #[test]
+    fn lookup_flags_maps_each_flag() {
+        assert_eq!(lookup_flags(LookupFlags::NONE), 0);
+        assert_eq!(
+            lookup_flags(LookupFlags::USE_MULTICAST | LookupFlags::NO_TXT),
+            avahi_sys::AvahiLookupFlags_AVAHI_LOOKUP_USE_MULTICAST
+                | avahi_sys::AvahiLookupFlags_AVAHI_LOOKUP_NO_TXT
+        );
+        assert_eq!(
+            lookup_flags(LookupFlags::NO_ADDRESS),
+            avahi_sys::AvahiLookupFlags_AVAHI_LOOKUP_NO_ADDRESS
+        );
+    }
+
+    #[test]
+    fn publish_flags_maps_each_flag() {
+        assert_eq!(publish_flags(RegistrationFlags::NONE), 0);
+        assert_eq!(
+            publish_flags(RegistrationFlags::NO_PROBE | RegistrationFlags::USE_MULTICAST),
+            avahi_sys::AvahiPublishFlags_AVAHI_PUBLISH_NO_PROBE
+                | avahi_sys::AvahiPublishFlags_AVAHI_PUBLISH_USE_MULTICAST
+        );
+        assert_eq!(
+            publish_flags(RegistrationFlags::NO_ANNOUNCE | RegistrationFlags::UPDATE),
+            avahi_sys::AvahiPublishFlags_AVAHI_PUBLISH_NO_ANNOUNCE
+                | avahi_sys::AvahiPublishFlags_AVAHI_PUBLISH_UPDATE
+        );
+    }
+
+    #[test]
+    fn protocol_maps_each_protocol() {
+        assert_eq!(protocol(Protocol::Any), avahi_sys::AVAHI_PROTO_UNSPEC);
+        assert_eq!(protocol(Protocol::V4), AVAHI_PROTO_INET);
+        assert_eq!(protocol(Protocol::V6), AVAHI_PROTO_INET6);
+    }
+
     #[test]
     fn sys_exec_returns_ok_for_success() {
         assert!(unsafe { sys_exec(|| 0, "test") }.is_ok());
@@ -156,6 +334,14 @@ mod tests {
         assert_eq!(interface_index(NetworkInterface::AtIndex(1)), 1);
     }
 
+    #[test]
+    fn interface_index_returns_loopback_for_local_only() {
+        assert_eq!(
+            interface_index(NetworkInterface::LocalOnly),
+            interface_index(NetworkInterface::by_name("lo").unwrap())
+        );
+    }
+
     #[test]
     fn interface_from_index_returns_unspec_for_avahi_unspec() {
         assert_eq!(
@@ -169,6 +355,26 @@ mod tests {
         assert_eq!(interface_from_index(1), NetworkInterface::AtIndex(1));
     }
 
+    #[test]
+    fn daemon_state_reports_connection_states_only() {
+        assert_eq!(
+            daemon_state(avahi_sys::AvahiClientState_AVAHI_CLIENT_CONNECTING),
+            Some(DaemonState::Connecting)
+        );
+        assert_eq!(
+            daemon_state(avahi_sys::AvahiClientState_AVAHI_CLIENT_S_RUNNING),
+            Some(DaemonState::Running)
+        );
+        assert_eq!(
+            daemon_state(avahi_sys::AvahiClientState_AVAHI_CLIENT_FAILURE),
+            Some(DaemonState::Failed)
+        );
+        assert_eq!(
+            daemon_state(avahi_sys::AvahiClientState_AVAHI_CLIENT_S_REGISTERING),
+            None
+        );
+    }
+
     #[test]
     fn format_service_type_returns_valid_string() {
         assert_eq!(
@@ -216,6 +422,26 @@ mod tests {
         );
     }
 
+    #[test]
+    fn join_service_name_defaults_to_local_domain() {
+        let name =
+            unsafe { join_service_name(&c_string!("foo.bar"), &c_string!("_http._tcp"), None) };
+
+        assert_eq!(
+            name.unwrap().to_str().unwrap(),
+            "foo\\.bar._http._tcp.local"
+        );
+    }
+
+    #[test]
+    fn avahi_address_round_trips() {
+        for address in ["192.168.100.100", "fe80::1234:5678:9abc:def0"] {
+            let avahi_address = avahi_address(address.parse().unwrap());
+
+            unsafe { assert_eq!(avahi_address_to_string(&avahi_address), address) };
+        }
+    }
+
     #[test]
     fn address_to_string_returns_correct_ipv4_string() {
         let ipv4_addr = AvahiAddress {
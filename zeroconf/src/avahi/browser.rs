@@ -4,75 +4,258 @@ use super::avahi_util;
 use super::client::{ManagedAvahiClient, ManagedAvahiClientParams};
 use super::poll::ManagedAvahiSimplePoll;
 use super::raw_browser::{ManagedAvahiServiceBrowser, ManagedAvahiServiceBrowserParams};
-use super::{
-    resolver::{
-        ManagedAvahiServiceResolver, ManagedAvahiServiceResolverParams, ServiceResolverSet,
-    },
-    string_list::ManagedAvahiStringList,
+use super::resolver::{
+    self, ManagedAvahiServiceResolver, ManagedAvahiServiceResolverParams, PendingResolve,
+    ServiceResolverSet,
 };
-use crate::ffi::{c_str, AsRaw, FromRaw};
+use super::type_browser::{ManagedAvahiServiceTypeBrowser, ManagedAvahiServiceTypeBrowserParams};
+use crate::cache::{CachedService, ServiceCache};
+use crate::clock::{Clock, SystemClock};
+use crate::dedup::Deduplicator;
+use crate::error::{Error, ErrorContext, Operation, ResultExt};
+use crate::ffi::weak::{self, WeakUserdata};
+use crate::ffi::{self, c_str, CallbackContext, UnwrapOrNull};
+use crate::filter::BrowserFilter;
+use crate::interface_watcher::{self, InterfaceWatcher};
 use crate::prelude::*;
+use crate::service_type;
+use crate::settle::SettleTracker;
+use crate::span::Span;
 use crate::Result;
 use crate::{
-    EventLoop, NetworkInterface, ServiceDiscoveredCallback, ServiceDiscovery, ServiceType,
-    TxtRecord,
+    BackendInfo, BrowserEvent, BrowserEventCallback, BrowserStats, DaemonState,
+    DaemonStateCallback, EventLoop, Id, LookupFlags, NetworkInterface, Protocol, ResolveFailure,
+    ServiceDiscoveredCallback, ServiceDiscovery, ServiceKey, ServiceType, SettledCallback,
+    DEFAULT_RESCAN_DEADLINE,
 };
 use avahi_sys::{
     AvahiAddress, AvahiBrowserEvent, AvahiClient, AvahiClientFlags, AvahiClientState, AvahiIfIndex,
-    AvahiLookupResultFlags, AvahiProtocol, AvahiResolverEvent, AvahiServiceBrowser,
-    AvahiServiceResolver, AvahiStringList,
+    AvahiLookupFlags, AvahiLookupResultFlags, AvahiProtocol, AvahiResolverEvent,
+    AvahiServiceBrowser, AvahiServiceResolver, AvahiServiceTypeBrowser, AvahiStringList,
 };
 use libc::{c_char, c_void};
 use std::any::Any;
 use std::ffi::CString;
+use std::fmt;
 use std::str::FromStr;
-use std::sync::Arc;
-use std::{fmt, ptr};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
 
+/// The context is shared with the callbacks through a [`WeakUserdata`], so that callbacks do
+/// nothing once the browser has been dropped.
+///
+/// [`WeakUserdata`]: ../../ffi/weak/struct.WeakUserdata.html
 #[derive(Debug)]
 pub struct AvahiMdnsBrowser {
-    context: Box<AvahiBrowserContext>,
-    client: Option<Arc<ManagedAvahiClient>>,
+    id: Id,
+    context: Arc<Mutex<AvahiBrowserContext>>,
+    user_context: Option<Arc<dyn Any + Send + Sync>>,
+    domain: Option<String>,
     poll: Option<Arc<ManagedAvahiSimplePoll>>,
 }
 
 impl TMdnsBrowser for AvahiMdnsBrowser {
     fn new(service_type: ServiceType) -> Self {
+        let context =
+            AvahiBrowserContext::new(c_string!(avahi_util::format_browser_type(&service_type)));
+
+        let id = {
+            let mut context = lock(&context);
+            context.meta_query = service_type.is_meta_query();
+            context.id
+        };
+
+        if service_type.sub_types().len() > 1 {
+            warn!(
+                "[{}] browsing by multiple sub-types is not supported on Avahi devices, using first sub-type only",
+                id
+            );
+        }
+
         Self {
-            client: None,
+            id,
+            context,
+            user_context: None,
+            domain: None,
             poll: None,
-            context: Box::new(AvahiBrowserContext::new(
-                c_string!(avahi_util::format_browser_type(&service_type)),
-                avahi_sys::AVAHI_IF_UNSPEC,
-            )),
         }
     }
 
+    fn id(&self) -> Id {
+        self.id
+    }
+
     fn set_network_interface(&mut self, interface: NetworkInterface) {
-        self.context.interface_index = avahi_util::interface_index(interface);
+        self.lock().interface = interface;
     }
 
     fn network_interface(&self) -> NetworkInterface {
-        avahi_util::interface_from_index(self.context.interface_index)
+        self.lock().interface
+    }
+
+    fn set_domain(&mut self, domain: &str) {
+        self.domain = Some(domain.to_string());
+        self.lock().domain = Some(c_string!(domain));
+    }
+
+    fn domain(&self) -> Option<&str> {
+        self.domain.as_deref()
+    }
+
+    fn set_protocol(&mut self, protocol: Protocol) {
+        self.lock().protocol = protocol;
+    }
+
+    fn protocol(&self) -> Protocol {
+        self.lock().protocol
+    }
+
+    fn set_lookup_flags(&mut self, flags: LookupFlags) {
+        self.lock().lookup_flags = flags;
+    }
+
+    fn lookup_flags(&self) -> LookupFlags {
+        self.lock().lookup_flags
+    }
+
+    fn set_deduplicate(&mut self, deduplicate: bool) {
+        self.lock().dedup = deduplicate.then(Deduplicator::default);
+    }
+
+    fn deduplicate(&self) -> bool {
+        self.lock().dedup.is_some()
+    }
+
+    fn set_name_filter(&mut self, name_filter: impl Fn(&str) -> bool + Send + 'static) {
+        self.lock().filter.set_name_filter(Box::new(name_filter));
+    }
+
+    fn set_discovery_filter(
+        &mut self,
+        discovery_filter: impl Fn(&ServiceDiscovery) -> bool + Send + 'static,
+    ) {
+        self.lock()
+            .filter
+            .set_discovery_filter(Box::new(discovery_filter));
+    }
+
+    fn set_max_concurrent_resolves(&mut self, max_concurrent_resolves: usize) {
+        self.lock()
+            .resolvers
+            .set_max_concurrent(max_concurrent_resolves);
+    }
+
+    fn max_concurrent_resolves(&self) -> usize {
+        self.lock().resolvers.max_concurrent()
+    }
+
+    fn set_resolve_timeout(&mut self, timeout: Duration) {
+        self.lock().resolvers.set_timeout(timeout);
+    }
+
+    fn resolve_timeout(&self) -> Duration {
+        self.lock().resolvers.timeout()
+    }
+
+    fn set_rescan_deadline(&mut self, deadline: Duration) {
+        self.lock().rescan_deadline = deadline;
+    }
+
+    fn rescan_deadline(&self) -> Duration {
+        self.lock().rescan_deadline
+    }
+
+    fn set_grace_period(&mut self, grace_period: Duration) {
+        self.lock()
+            .update_cache(|c| c.set_grace_period(grace_period));
+    }
+
+    fn grace_period(&self) -> Duration {
+        let context = self.lock();
+        let cache = context.cache.lock().unwrap_or_else(PoisonError::into_inner);
+
+        cache.grace_period()
     }
 
     fn set_service_discovered_callback(
         &mut self,
         service_discovered_callback: Box<ServiceDiscoveredCallback>,
     ) {
-        self.context.service_discovered_callback = Some(service_discovered_callback);
+        self.lock().service_discovered_callback =
+            Some(Arc::new(Mutex::new(service_discovered_callback)));
+    }
+
+    fn set_browser_event_callback(&mut self, browser_event_callback: Box<BrowserEventCallback>) {
+        self.lock().browser_event_callback = Some(Arc::new(Mutex::new(browser_event_callback)));
+    }
+
+    fn set_context(&mut self, context: Box<dyn Any + Send + Sync>) {
+        let context = Arc::from(context);
+        self.user_context = Some(Arc::clone(&context));
+        self.lock().user_context = Some(context);
+    }
+
+    fn context(&self) -> Option<&(dyn Any + Send + Sync)> {
+        self.user_context.as_ref().map(|c| c.as_ref())
+    }
+
+    fn on_settled(&mut self, deadline: Duration, settled_callback: Box<SettledCallback>) {
+        let mut context = self.lock();
+
+        context.settle = Some(Arc::new(Mutex::new(SettleTracker::new(
+            deadline,
+            settled_callback,
+            context.clock.clone(),
+        ))));
+    }
+
+    fn set_wait_for_daemon(&mut self, wait_for_daemon: bool) -> Result<()> {
+        self.lock().wait_for_daemon = wait_for_daemon;
+        Ok(())
     }
 
-    fn set_context(&mut self, context: Box<dyn Any>) {
-        self.context.user_context = Some(Arc::from(context));
+    fn wait_for_daemon(&self) -> bool {
+        self.lock().wait_for_daemon
     }
 
-    fn context(&self) -> Option<&dyn Any> {
-        self.context.user_context.as_ref().map(|c| c.as_ref())
+    fn set_daemon_state_callback(&mut self, daemon_state_callback: Box<DaemonStateCallback>) {
+        self.lock().daemon_state_callback = Some(Arc::new(Mutex::new(daemon_state_callback)));
     }
 
     fn browse_services(&mut self) -> Result<EventLoop> {
-        debug!("Browsing services: {:?}", self);
+        let span = {
+            let mut context = self.lock();
+
+            let span = span!(
+                "browse",
+                id = %context.id,
+                service_type = c_str::to_str(&context.kind)
+            );
+            context.span = span.clone();
+
+            context.update_settle(|s| s.start());
+
+            if let Some(dedup) = &mut context.dedup {
+                dedup.clear();
+            }
+
+            context.filter.clear();
+            context.update_cache(|c| c.reset());
+            context.interfaces = interface_watcher::watch();
+
+            // a previous browse is torn down, the browsers and resolvers before the client they
+            // belong to, once dispatching has finished if this is called from a callback
+            ffi::drop_after_dispatch(context.browser.take());
+            ffi::drop_after_dispatch(context.type_browser.take());
+            context.resolvers.clear();
+            ffi::drop_after_dispatch(context.client.take());
+
+            span
+        };
+
+        let _entered = span.enter();
+
+        debug!("[{}] Browsing services: {:?}", self.id, self);
 
         self.poll = Some(Arc::new(unsafe { ManagedAvahiSimplePoll::new() }?));
 
@@ -82,68 +265,314 @@ impl TMdnsBrowser for AvahiMdnsBrowser {
             .ok_or("could not get poll as ref")?
             .clone();
 
-        let client_params = ManagedAvahiClientParams::builder()
-            .poll(poll)
-            .flags(AvahiClientFlags(0))
-            .callback(Some(client_callback))
-            .userdata(self.context.as_raw())
-            .build()?;
-
-        self.client = Some(Arc::new(unsafe { ManagedAvahiClient::new(client_params) }?));
-
-        self.context.client.clone_from(&self.client);
+        unsafe { connect(&self.context, poll) }
+            .with_context(|| lock(&self.context).error_context())?;
 
-        unsafe {
-            if let Err(e) = create_browser(&mut self.context) {
-                self.context.invoke_callback(Err(e));
-            }
-        }
+        // releases the context passed to the client callback invoked by `connect()`
+        ffi::drop_dispatched();
 
-        Ok(EventLoop::new(
+        let event_loop = EventLoop::new(
             self.poll
                 .as_ref()
                 .ok_or("could not get poll as ref")?
                 .clone(),
-        ))
+        );
+
+        debug!("[{}] Event loop: {}", self.id, event_loop.id());
+
+        let event_loop = event_loop.with_resolve_timeouts(ResolveTimeouts::new(&self.context));
+
+        Ok(match &self.lock().settle {
+            Some(settle) => event_loop.with_settle_tracker(settle.clone()),
+            None => event_loop,
+        })
+    }
+
+    fn rescan(&mut self) -> Result<()> {
+        let mut context = self.lock();
+
+        if context.client.is_none() {
+            return Err("browser has not been started".into());
+        }
+
+        // the browser is created once the daemon is running
+        if !context.is_browsing() {
+            return Ok(());
+        }
+
+        debug!("[{}] Rescanning services", context.id);
+
+        let now = context.clock.now();
+        let deadline = context.rescan_deadline;
+
+        context.update_cache(|c| c.rescan_started(deadline, now));
+
+        // free the current browser before creating its replacement, once dispatching has finished
+        // if this is called from one of its callbacks
+        ffi::drop_after_dispatch(context.browser.take());
+        ffi::drop_after_dispatch(context.type_browser.take());
+
+        if let Some(dedup) = &mut context.dedup {
+            dedup.clear();
+        }
+
+        context.filter.clear();
+
+        unsafe { create_browser(&mut context) }
+    }
+
+    fn stats(&self) -> BrowserStats {
+        let context = self.lock();
+        let stats = *context.stats.lock().unwrap_or_else(PoisonError::into_inner);
+
+        stats.with_active_resolvers(context.resolvers.len())
+    }
+
+    fn known_services(&self) -> Vec<CachedService> {
+        let context = self.lock();
+        let cache = context.cache.lock().unwrap_or_else(PoisonError::into_inner);
+
+        cache.snapshot()
+    }
+
+    fn backend_info(&self) -> Result<BackendInfo> {
+        let context = self.lock();
+        let client = context
+            .client
+            .as_ref()
+            .ok_or("browser has not been started")?;
+
+        unsafe { client.backend_info() }
+    }
+}
+
+impl AvahiMdnsBrowser {
+    /// Sets the [`Clock`] the settle deadline and the resolve timeouts are measured with. Must be
+    /// called before `on_settled()`.
+    ///
+    /// [`Clock`]: ../../clock/trait.Clock.html
+    #[cfg(test)]
+    pub(crate) fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.lock().clock = clock;
+    }
+
+    fn lock(&self) -> MutexGuard<'_, AvahiBrowserContext> {
+        lock(&self.context)
     }
 }
 
+fn lock(context: &Mutex<AvahiBrowserContext>) -> MutexGuard<'_, AvahiBrowserContext> {
+    context.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
 #[derive(FromRaw, AsRaw)]
 struct AvahiBrowserContext {
+    id: Id,
     client: Option<Arc<ManagedAvahiClient>>,
     resolvers: ServiceResolverSet,
-    service_discovered_callback: Option<Box<ServiceDiscoveredCallback>>,
-    user_context: Option<Arc<dyn Any>>,
-    interface_index: AvahiIfIndex,
+    service_discovered_callback: Option<Arc<Mutex<Box<ServiceDiscoveredCallback>>>>,
+    browser_event_callback: Option<Arc<Mutex<Box<BrowserEventCallback>>>>,
+    daemon_state_callback: Option<Arc<Mutex<Box<DaemonStateCallback>>>>,
+    user_context: Option<Arc<dyn Any + Send + Sync>>,
+    interface: NetworkInterface,
+    protocol: Protocol,
+    /// The domain to browse and resolve services in, the default domains if `None`
+    domain: Option<CString>,
+    wait_for_daemon: bool,
+    lookup_flags: LookupFlags,
+    dedup: Option<Deduplicator>,
+    filter: BrowserFilter,
     kind: CString,
+    meta_query: bool,
     browser: Option<ManagedAvahiServiceBrowser>,
+    type_browser: Option<ManagedAvahiServiceTypeBrowser>,
+    settle: Option<Arc<Mutex<SettleTracker>>>,
+    clock: Arc<dyn Clock>,
+    stats: Mutex<BrowserStats>,
+    /// The services reported to the callbacks, updated before they are invoked
+    cache: Mutex<ServiceCache>,
+    rescan_deadline: Duration,
+    /// Reports the interfaces that went down or came up while browsing
+    interfaces: Option<InterfaceWatcher>,
+    /// Whether the browser has reported `AVAHI_BROWSER_ALL_FOR_NOW` since it was created
+    all_for_now: bool,
+    /// Whether results have been delivered since the previous `Flushed`
+    pending_flush: bool,
+    span: Span,
+    /// Passed to every Avahi object above, which must be freed before it
+    userdata: WeakUserdata<AvahiBrowserContext>,
 }
 
 impl AvahiBrowserContext {
-    fn new(kind: CString, interface_index: AvahiIfIndex) -> Self {
-        Self {
-            client: None,
-            resolvers: ServiceResolverSet::default(),
-            service_discovered_callback: None,
-            user_context: None,
-            interface_index,
-            kind,
-            browser: None,
+    fn new(kind: CString) -> Arc<Mutex<Self>> {
+        Arc::new_cyclic(|context| {
+            Mutex::new(Self {
+                id: Id::next(),
+                client: None,
+                resolvers: ServiceResolverSet::default(),
+                service_discovered_callback: None,
+                browser_event_callback: None,
+                daemon_state_callback: None,
+                user_context: None,
+                interface: NetworkInterface::Unspec,
+                protocol: Protocol::Any,
+                domain: None,
+                wait_for_daemon: false,
+                lookup_flags: LookupFlags::NONE,
+                dedup: None,
+                filter: BrowserFilter::default(),
+                kind,
+                meta_query: false,
+                browser: None,
+                type_browser: None,
+                settle: None,
+                clock: Arc::new(SystemClock),
+                stats: Mutex::default(),
+                cache: Mutex::default(),
+                rescan_deadline: DEFAULT_RESCAN_DEADLINE,
+                interfaces: None,
+                all_for_now: false,
+                pending_flush: false,
+                span: Span::none(),
+                userdata: WeakUserdata::new(context.clone()),
+            })
+        })
+    }
+
+    fn is_browsing(&self) -> bool {
+        self.browser.is_some() || self.type_browser.is_some()
+    }
+
+    /// Reports `Flushed` if results have been delivered since the previous one, the browser has
+    /// reported all services it knows of for now and none of them is still being resolved.
+    /// Avahi does not batch results, so this stands in for Bonjour's
+    /// `kDNSServiceFlagsMoreComing`.
+    fn flush(&mut self) {
+        if self.pending_flush && self.all_for_now && self.resolvers.is_idle() {
+            self.pending_flush = false;
+            self.invoke_browser_event_callback(BrowserEvent::Flushed);
         }
     }
 
+    fn update_settle(&self, f: impl FnOnce(&mut SettleTracker)) {
+        if let Some(settle) = &self.settle {
+            f(&mut settle
+                .lock()
+                .expect("should have been able to obtain lock on settle tracker"));
+        }
+    }
+
+    fn update_stats(&self, f: impl FnOnce(&mut BrowserStats)) {
+        f(&mut self.stats.lock().unwrap_or_else(PoisonError::into_inner));
+    }
+
+    fn update_cache(&self, f: impl FnOnce(&mut ServiceCache)) {
+        f(&mut self.cache.lock().unwrap_or_else(PoisonError::into_inner));
+    }
+
+    /// Removes the services that have not been reported again by the deadline of a rescan, or
+    /// that have been degraded for longer than the grace period, from the cache, and returns
+    /// their keys.
+    fn expired_services(&self) -> Vec<ServiceKey> {
+        let now = self.clock.now();
+
+        self.cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .expired(now)
+    }
+
+    /// Marks the services found on the interfaces that went down since the previous call as
+    /// degraded, or restores those on the interfaces that came back up, and returns the events to
+    /// report for them.
+    fn reachability_changes(&mut self) -> Vec<BrowserEvent> {
+        let changes = match &mut self.interfaces {
+            Some(interfaces) => interfaces.changes(),
+            None => return vec![],
+        };
+
+        let now = self.clock.now();
+
+        self.cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .interfaces_changed(changes, now)
+    }
+
     fn invoke_callback(&self, result: Result<ServiceDiscovery>) {
-        if let Some(f) = &self.service_discovered_callback {
-            f(result, self.user_context.clone());
+        if result.is_err() {
+            self.update_stats(|s| s.failed());
+        }
+
+        if let Some(f) = self.service_discovered_callback.clone() {
+            let user_context = self.user_context.clone();
+
+            ffi::invoke_unlocked(move || {
+                let mut f = f.lock().unwrap_or_else(PoisonError::into_inner);
+                f(result, user_context);
+            });
         } else {
-            warn!("attempted to invoke browser callback but none was set");
+            warn!(
+                "[{}] attempted to invoke browser callback but none was set",
+                self.id
+            );
+        }
+    }
+
+    /// Passes an error of the browse itself, rather than of resolving a service, to the callback.
+    fn invoke_browse_error(&self, error: Error) {
+        self.invoke_callback(Err(error.with_context(self.error_context())))
+    }
+
+    fn error_context(&self) -> ErrorContext {
+        ErrorContext::new(Operation::Browse).with_service_type(c_str::to_str(&self.kind))
+    }
+
+    fn invoke_browser_event_callback(&self, event: BrowserEvent) {
+        debug!("[{}] Browser event: {:?}", self.id, event);
+
+        if let Some(f) = self.browser_event_callback.clone() {
+            let user_context = self.user_context.clone();
+
+            ffi::invoke_unlocked(move || {
+                let f = f.lock().unwrap_or_else(PoisonError::into_inner);
+                f(event, user_context);
+            });
+        }
+    }
+
+    fn invoke_daemon_state_callback(&self, state: DaemonState) {
+        debug!("[{}] Daemon state: {:?}", self.id, state);
+
+        if let Some(f) = self.daemon_state_callback.clone() {
+            let user_context = self.user_context.clone();
+
+            ffi::invoke_unlocked(move || {
+                let f = f.lock().unwrap_or_else(PoisonError::into_inner);
+                f(state, user_context);
+            });
         }
     }
 }
 
+impl CallbackContext for AvahiBrowserContext {
+    fn id(&self) -> Id {
+        self.id
+    }
+
+    fn invoke_error(&self, error: Error) {
+        self.invoke_callback(Err(error))
+    }
+
+    fn span(&self) -> Option<&Span> {
+        Some(&self.span)
+    }
+}
+
 impl fmt::Debug for AvahiBrowserContext {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("AvahiBrowserContext")
+            .field("id", &self.id)
             .field("resolvers", &self.resolvers)
             .finish()
     }
@@ -154,23 +583,108 @@ unsafe extern "C" fn client_callback(
     state: AvahiClientState,
     userdata: *mut c_void,
 ) {
-    let context = AvahiBrowserContext::from_raw(userdata);
+    weak::guard_weak(userdata, |context: &mut AvahiBrowserContext| {
+        if state == avahi_sys::AvahiClientState_AVAHI_CLIENT_FAILURE {
+            context.invoke_daemon_state_callback(DaemonState::Failed);
+            context.invoke_browse_error(avahi_util::get_last_error(client).into());
+        } else if context.client.is_some() {
+            handle_client_state(context, state);
+        }
+    })
+}
+
+/// Creates the client of the browser on `poll` and starts browsing if the daemon is ready. When
+/// waiting for the daemon, the client waits for it instead of failing if it is not running.
+///
+/// The context is not locked while the client is created, as `avahi_client_new()` invokes the
+/// client callback before returning.
+unsafe fn connect(
+    context: &Mutex<AvahiBrowserContext>,
+    poll: Arc<ManagedAvahiSimplePoll>,
+) -> Result<()> {
+    let (flags, userdata) = {
+        let context = lock(context);
+
+        let flags = if context.wait_for_daemon {
+            AvahiClientFlags::AVAHI_CLIENT_NO_FAIL
+        } else {
+            AvahiClientFlags(0)
+        };
+
+        (flags, context.userdata.as_raw())
+    };
+
+    let client_params = ManagedAvahiClientParams::builder()
+        .poll(poll)
+        .flags(flags)
+        .callback(Some(client_callback))
+        .userdata(userdata)
+        .build()?;
+
+    let client = ManagedAvahiClient::new(client_params)?;
+    let state = client.state();
+
+    let mut context = lock(context);
+    context.client = Some(Arc::new(client));
+
+    // the client callback is invoked from `avahi_client_new()` before the client has been
+    // stored in the context, so the initial state has to be handled here
+    handle_client_state(&mut context, state);
 
-    if state == avahi_sys::AvahiClientState_AVAHI_CLIENT_FAILURE {
-        context.invoke_callback(Err(avahi_util::get_last_error(client).into()));
+    Ok(())
+}
+
+/// Reports the state of the daemon and starts browsing once it is running.
+unsafe fn handle_client_state(context: &mut AvahiBrowserContext, state: AvahiClientState) {
+    if let Some(daemon_state) = avahi_util::daemon_state(state) {
+        context.invoke_daemon_state_callback(daemon_state);
+    }
+
+    if state == avahi_sys::AvahiClientState_AVAHI_CLIENT_S_RUNNING && !context.is_browsing() {
+        if let Err(e) = create_browser(context) {
+            context.invoke_browse_error(e);
+        }
     }
 }
 
 unsafe fn create_browser(context: &mut AvahiBrowserContext) -> Result<()> {
+    context.all_for_now = false;
+    context.pending_flush = false;
+
+    if context.meta_query {
+        return create_type_browser(context);
+    }
+
     context.browser = Some(ManagedAvahiServiceBrowser::new(
         ManagedAvahiServiceBrowserParams::builder()
-            .interface(context.interface_index)
-            .protocol(avahi_sys::AVAHI_PROTO_UNSPEC)
+            .interface(avahi_util::interface_index(context.interface))
+            .protocol(avahi_util::protocol(context.protocol))
             .kind(context.kind.as_ptr())
-            .domain(ptr::null_mut())
-            .flags(0)
+            .domain(context.domain.as_ref().map(|d| d.as_ptr()).unwrap_or_null())
+            .flags(browse_flags(context))
             .callback(Some(browse_callback))
-            .userdata(context.as_raw())
+            .userdata(context.userdata.as_raw())
+            .client(Arc::clone(
+                context
+                    .client
+                    .as_ref()
+                    .ok_or("could not get client as ref")?,
+            ))
+            .build()?,
+    )?);
+
+    Ok(())
+}
+
+unsafe fn create_type_browser(context: &mut AvahiBrowserContext) -> Result<()> {
+    context.type_browser = Some(ManagedAvahiServiceTypeBrowser::new(
+        ManagedAvahiServiceTypeBrowserParams::builder()
+            .interface(avahi_util::interface_index(context.interface))
+            .protocol(avahi_util::protocol(context.protocol))
+            .domain(context.domain.as_ref().map(|d| d.as_ptr()).unwrap_or_null())
+            .flags(browse_flags(context))
+            .callback(Some(type_browse_callback))
+            .userdata(context.userdata.as_raw())
             .client(Arc::clone(
                 context
                     .client
@@ -183,6 +697,59 @@ unsafe fn create_browser(context: &mut AvahiBrowserContext) -> Result<()> {
     Ok(())
 }
 
+/// Returns the `AvahiLookupFlags` to create browsers with. Browsers reject the flags that only
+/// apply to resolvers.
+fn browse_flags(context: &AvahiBrowserContext) -> AvahiLookupFlags {
+    avahi_util::lookup_flags(context.lookup_flags)
+        & avahi_sys::AvahiLookupFlags_AVAHI_LOOKUP_USE_MULTICAST
+}
+
+unsafe extern "C" fn type_browse_callback(
+    _browser: *mut AvahiServiceTypeBrowser,
+    _interface: AvahiIfIndex,
+    _protocol: AvahiProtocol,
+    event: AvahiBrowserEvent,
+    kind: *const c_char,
+    _domain: *const c_char,
+    _flags: AvahiLookupResultFlags,
+    userdata: *mut c_void,
+) {
+    weak::guard_weak(userdata, |context: &mut AvahiBrowserContext| {
+        context.update_stats(|s| s.event_received());
+
+        match event {
+            avahi_sys::AvahiBrowserEvent_AVAHI_BROWSER_NEW => match parse_type(kind) {
+                Ok(service_type) => context
+                    .invoke_browser_event_callback(BrowserEvent::ServiceTypeFound(service_type)),
+                Err(e) => context.invoke_callback(Err(e)),
+            },
+            avahi_sys::AvahiBrowserEvent_AVAHI_BROWSER_REMOVE => match parse_type(kind) {
+                Ok(service_type) => context
+                    .invoke_browser_event_callback(BrowserEvent::ServiceTypeRemoved(service_type)),
+                Err(e) => warn!(
+                    "[{}] ignoring removal of unidentifiable service type: {}",
+                    context.id, e
+                ),
+            },
+            avahi_sys::AvahiBrowserEvent_AVAHI_BROWSER_ALL_FOR_NOW => {
+                context.update_settle(|s| s.enumerated());
+                context.invoke_browser_event_callback(BrowserEvent::AllForNow);
+            }
+            avahi_sys::AvahiBrowserEvent_AVAHI_BROWSER_CACHE_EXHAUSTED => {
+                context.invoke_browser_event_callback(BrowserEvent::CacheExhausted)
+            }
+            avahi_sys::AvahiBrowserEvent_AVAHI_BROWSER_FAILURE => {
+                context.invoke_browse_error("service type browser failure".into())
+            }
+            _ => {}
+        };
+    })
+}
+
+unsafe fn parse_type(kind: *const c_char) -> Result<ServiceType> {
+    service_type::parse_advertised(c_str::try_raw_to_str(kind, "service type")?)
+}
+
 unsafe extern "C" fn browse_callback(
     _browser: *mut AvahiServiceBrowser,
     interface: AvahiIfIndex,
@@ -194,21 +761,89 @@ unsafe extern "C" fn browse_callback(
     _flags: AvahiLookupResultFlags,
     userdata: *mut c_void,
 ) {
-    let context = AvahiBrowserContext::from_raw(userdata);
+    weak::guard_weak(userdata, |context: &mut AvahiBrowserContext| {
+        context.update_stats(|s| s.event_received());
 
-    match event {
-        avahi_sys::AvahiBrowserEvent_AVAHI_BROWSER_NEW => {
-            if let Err(e) = handle_browser_new(context, interface, protocol, name, kind, domain) {
-                context.invoke_callback(Err(e));
+        match event {
+            avahi_sys::AvahiBrowserEvent_AVAHI_BROWSER_NEW => {
+                match handle_browser_new(context, interface, protocol, name, kind, domain) {
+                    Ok(()) => context.update_settle(|s| s.resolution_started()),
+                    Err(e) => context.invoke_callback(Err(e)),
+                }
+
+                start_resolvers(context);
             }
+            avahi_sys::AvahiBrowserEvent_AVAHI_BROWSER_REMOVE => {
+                let result =
+                    handle_browser_remove(context, interface, protocol, name, kind, domain);
+
+                if let Err(e) = result {
+                    warn!(
+                        "[{}] ignoring removal of unidentifiable service: {}",
+                        context.id, e
+                    );
+                }
+            }
+            avahi_sys::AvahiBrowserEvent_AVAHI_BROWSER_ALL_FOR_NOW => {
+                context.update_settle(|s| s.enumerated());
+                context.all_for_now = true;
+                context.flush();
+                context.invoke_browser_event_callback(BrowserEvent::AllForNow);
+            }
+            avahi_sys::AvahiBrowserEvent_AVAHI_BROWSER_CACHE_EXHAUSTED => {
+                context.invoke_browser_event_callback(BrowserEvent::CacheExhausted)
+            }
+            avahi_sys::AvahiBrowserEvent_AVAHI_BROWSER_FAILURE => {
+                context.invoke_browse_error("browser failure".into())
+            }
+            _ => {}
+        };
+    })
+}
+
+unsafe fn handle_browser_remove(
+    context: &mut AvahiBrowserContext,
+    interface: AvahiIfIndex,
+    protocol: AvahiProtocol,
+    name: *const c_char,
+    kind: *const c_char,
+    domain: *const c_char,
+) -> Result<()> {
+    let name = c_str::try_raw_to_str(name, "service name")?;
+    let kind = c_str::try_raw_to_str(kind, "service type")?;
+    let service_type = ServiceType::from_str(kind)?;
+    let domain = c_str::try_raw_to_str(domain, "service domain")?;
+
+    // a service that is gone before its turn to be resolved came would only time out
+    let cancelled = context
+        .resolvers
+        .cancel(interface, protocol, name, kind, domain);
+
+    context.update_settle(|s| {
+        for _ in 0..cancelled {
+            s.resolution_finished();
         }
-        avahi_sys::AvahiBrowserEvent_AVAHI_BROWSER_FAILURE => {
-            context.invoke_callback(Err("browser failure".into()))
-        }
-        _ => {}
+
+        s.removed(name, &service_type, domain)
+    });
+
+    let key = ServiceKey::new(name, &service_type, domain);
+    let interface = avahi_util::interface_from_index(interface);
+
+    let report = match &mut context.dedup {
+        Some(dedup) => dedup.removed(&key, interface),
+        None => true,
     };
+
+    if report && context.filter.removed(&key) {
+        context.update_cache(|c| c.reported_removed(&key));
+        context.invoke_browser_event_callback(BrowserEvent::ServiceRemoved(key));
+    }
+
+    Ok(())
 }
 
+/// Queues a new service to be resolved by `start_resolvers()`.
 unsafe fn handle_browser_new(
     context: &mut AvahiBrowserContext,
     interface: AvahiIfIndex,
@@ -217,114 +852,1067 @@ unsafe fn handle_browser_new(
     kind: *const c_char,
     domain: *const c_char,
 ) -> Result<()> {
-    let raw_context = context.as_raw();
+    let pending = PendingResolve::new(interface, protocol, name, kind, domain)?;
+
+    if !context.filter.found(c_str::to_str(&pending.name)) {
+        debug!(
+            "[{}] Ignoring filtered service: {}",
+            context.id,
+            pending.name.to_string_lossy()
+        );
+        return Ok(());
+    }
+
+    if let Some(dedup) = &mut context.dedup {
+        dedup.found(pending.key()?, avahi_util::interface_from_index(interface));
+    }
+
+    context.resolvers.queue(pending);
+
+    Ok(())
+}
+
+/// Starts a resolver for each queued service until the maximum number of resolvers is active. A
+/// service that cannot be resolved is reported to the callback.
+unsafe fn start_resolvers(context: &mut AvahiBrowserContext) {
+    while let Some(pending) = context.resolvers.next_pending() {
+        let error_context = pending.error_context();
 
+        if let Err(e) = start_resolver(context, pending) {
+            context.update_settle(|s| s.resolution_finished());
+            context.invoke_callback(Err(e.with_context(error_context)));
+        }
+    }
+}
+
+unsafe fn start_resolver(context: &mut AvahiBrowserContext, pending: PendingResolve) -> Result<()> {
     let client = context
         .client
         .as_ref()
         .ok_or("expected initialized client")?;
 
-    context.resolvers.insert(ManagedAvahiServiceResolver::new(
+    let resolver = ManagedAvahiServiceResolver::new(
         ManagedAvahiServiceResolverParams::builder()
             .client(client.clone())
-            .interface(interface)
-            .protocol(protocol)
-            .name(name)
-            .kind(kind)
-            .domain(domain)
-            .aprotocol(avahi_sys::AVAHI_PROTO_UNSPEC)
-            .flags(0)
+            .interface(pending.interface)
+            .protocol(pending.protocol)
+            .name(pending.name.as_ptr())
+            .kind(pending.kind.as_ptr())
+            .domain(context.domain.as_ref().unwrap_or(&pending.domain).as_ptr())
+            .aprotocol(avahi_util::protocol(context.protocol))
+            .flags(avahi_util::lookup_flags(context.lookup_flags))
             .callback(Some(resolve_callback))
-            .userdata(raw_context)
+            .userdata(context.userdata.as_raw())
             .build()?,
-    )?);
+    )?;
+
+    let now = context.clock.now();
+    context.resolvers.insert(resolver, pending, now);
 
     Ok(())
 }
 
-unsafe extern "C" fn resolve_callback(
-    resolver: *mut AvahiServiceResolver,
-    _interface: AvahiIfIndex,
-    _protocol: AvahiProtocol,
-    event: AvahiResolverEvent,
-    name: *const c_char,
-    kind: *const c_char,
-    domain: *const c_char,
-    host_name: *const c_char,
-    addr: *const AvahiAddress,
+/// Gives up on the services that have not been resolved within the resolve timeout, and starts
+/// resolving the services queued in their place.
+unsafe fn expire_resolvers(context: &mut AvahiBrowserContext) {
+    let now = context.clock.now();
+    let expired = context.resolvers.expire(now);
+
+    if expired.is_empty() {
+        return;
+    }
+
+    for pending in expired {
+        context.update_settle(|s| s.resolution_finished());
+
+        match pending.key() {
+            Ok(key) => {
+                debug!("[{}] Resolve timed out: {:?}", context.id, key);
+
+                context.invoke_browser_event_callback(BrowserEvent::ResolveFailed {
+                    key,
+                    reason: ResolveFailure::Timeout,
+                });
+            }
+            Err(e) => context.invoke_callback(Err(e)),
+        }
+    }
+
+    start_resolvers(context);
+    context.pending_flush = true;
+    context.flush();
+}
+
+/// Reports the services whose network interface went down or came back up.
+fn track_interfaces(context: &mut AvahiBrowserContext) {
+    for event in context.reachability_changes() {
+        context.invoke_browser_event_callback(event);
+    }
+}
+
+/// Reports the removal of the services that have not been reported again by the deadline of a
+/// rescan, or that have been degraded for longer than the grace period.
+fn expire_services(context: &mut AvahiBrowserContext) {
+    for key in context.expired_services() {
+        debug!("[{}] Service expired: {:?}", context.id, key);
+        context.invoke_browser_event_callback(BrowserEvent::ServiceRemoved(key));
+    }
+}
+
+/// Gives up on the resolvers of a browser that have timed out on behalf of
+/// `AvahiEventLoop::poll()`.
+pub(crate) struct ResolveTimeouts {
+    userdata: WeakUserdata<AvahiBrowserContext>,
+}
+
+impl ResolveTimeouts {
+    fn new(context: &Arc<Mutex<AvahiBrowserContext>>) -> Self {
+        Self {
+            userdata: WeakUserdata::new(Arc::downgrade(context)),
+        }
+    }
+
+    /// Frees the resolvers that have timed out, reports the services whose network interface went
+    /// down or came back up, and removes the services that have not been reported again by the
+    /// deadline of a rescan or that have been degraded for longer than the grace period. Does
+    /// nothing if the browser has been dropped.
+    ///
+    /// # Safety
+    /// This function is unsafe because it calls C functions. It must not be called from within an
+    /// Avahi callback.
+    pub(crate) unsafe fn run(&self) {
+        weak::guard_weak(self.userdata.as_raw(), |context| {
+            expire_resolvers(context);
+            track_interfaces(context);
+            expire_services(context);
+        });
+
+        // releases the context if the browser was dropped by a callback
+        ffi::drop_dispatched();
+    }
+}
+
+unsafe extern "C" fn resolve_callback(
+    resolver: *mut AvahiServiceResolver,
+    interface: AvahiIfIndex,
+    _protocol: AvahiProtocol,
+    event: AvahiResolverEvent,
+    name: *const c_char,
+    kind: *const c_char,
+    domain: *const c_char,
+    host_name: *const c_char,
+    addr: *const AvahiAddress,
     port: u16,
     txt: *mut AvahiStringList,
     _flags: AvahiLookupResultFlags,
     userdata: *mut c_void,
 ) {
-    let name = c_str::raw_to_str(name);
-    let kind = c_str::raw_to_str(kind);
-    let domain = c_str::raw_to_str(domain);
+    weak::guard_weak(userdata, |context: &mut AvahiBrowserContext| {
+        let span = resolver::resolve_span(interface, name, kind);
+        let _entered = span.enter();
 
-    let context = AvahiBrowserContext::from_raw(userdata);
+        context.update_stats(|s| s.event_received());
 
-    match event {
-        avahi_sys::AvahiResolverEvent_AVAHI_RESOLVER_FAILURE => {
-            context.invoke_callback(Err(format!(
-                "failed to resolve service `{}` of type `{}` in domain `{}`",
-                name, kind, domain
-            )
-            .into()));
-        }
-        avahi_sys::AvahiResolverEvent_AVAHI_RESOLVER_FOUND => {
-            let result = handle_resolver_found(
-                context,
-                c_str::raw_to_str(host_name),
-                addr,
-                name,
-                kind,
-                domain,
-                port,
-                txt,
-            );
+        match event {
+            avahi_sys::AvahiResolverEvent_AVAHI_RESOLVER_FAILURE => {
+                let error = resolver::resolve_failure(name, kind, domain);
+                context.invoke_callback(Err(
+                    error.with_context(resolver::resolve_context(name, kind))
+                ));
+            }
+            avahi_sys::AvahiResolverEvent_AVAHI_RESOLVER_FOUND => {
+                let result = handle_resolver_found(
+                    context, interface, host_name, addr, name, kind, domain, port, txt,
+                );
 
-            if let Err(e) = result {
-                context.invoke_callback(Err(e));
+                if let Err(e) = result {
+                    context.invoke_callback(Err(
+                        e.with_context(resolver::resolve_context(name, kind))
+                    ));
+                }
             }
-        }
-        _ => {}
-    };
+            _ => {}
+        };
 
-    context.resolvers.remove_raw(resolver);
+        context.update_settle(|s| s.resolution_finished());
+        context.resolvers.remove_raw(resolver);
+        start_resolvers(context);
+        context.pending_flush = true;
+        context.flush();
+    })
 }
 
 #[allow(clippy::too_many_arguments)]
 unsafe fn handle_resolver_found(
-    context: &AvahiBrowserContext,
-    host_name: &str,
+    context: &mut AvahiBrowserContext,
+    interface: AvahiIfIndex,
+    host_name: *const c_char,
     addr: *const AvahiAddress,
-    name: &str,
-    kind: &str,
-    domain: &str,
+    name: *const c_char,
+    kind: *const c_char,
+    domain: *const c_char,
     port: u16,
     txt: *mut AvahiStringList,
 ) -> Result<()> {
-    let address = avahi_util::avahi_address_to_string(addr);
+    let result = resolver::service_discovery(
+        interface,
+        host_name,
+        addr,
+        name,
+        kind,
+        domain,
+        port,
+        txt,
+        context.lookup_flags,
+    )?;
 
-    let txt = if txt.is_null() {
-        None
-    } else {
-        Some(TxtRecord::from(ManagedAvahiStringList::clone_raw(txt)))
-    };
+    debug!("[{}] Service resolved: {:?}", context.id, result);
 
-    let result = ServiceDiscovery::builder()
-        .name(name.to_string())
-        .service_type(ServiceType::from_str(kind)?)
-        .domain(domain.to_string())
-        .host_name(host_name.to_string())
-        .address(address)
-        .port(port)
-        .txt(txt)
-        .build()?;
+    if !context.filter.resolved(&result) {
+        debug!("[{}] Ignoring filtered service: {:?}", context.id, result);
+        return Ok(());
+    }
+
+    if let Some(dedup) = &mut context.dedup {
+        if !dedup.resolved(&result) {
+            debug!("[{}] Ignoring duplicate: {:?}", context.id, result);
+            return Ok(());
+        }
+    }
 
-    debug!("Service resolved: {:?}", result);
+    let now = context.clock.now();
 
+    context.update_settle(|s| s.resolved(&result));
+    context.update_cache(|c| c.reported(&result, now));
     context.invoke_callback(Ok(result));
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use std::ptr;
+    use std::sync::mpsc;
+
+    type Results = Arc<Mutex<Vec<Result<ServiceDiscovery>>>>;
+
+    fn context() -> (Arc<Mutex<AvahiBrowserContext>>, Results) {
+        let results: Results = Arc::default();
+        let sink = results.clone();
+
+        let context = AvahiBrowserContext::new(c_string!("_http._tcp"));
+
+        lock(&context).service_discovered_callback =
+            Some(Arc::new(Mutex::new(Box::new(move |result, _| {
+                sink.lock().unwrap().push(result)
+            }))));
+
+        (context, results)
+    }
+
+    /// Returns the userdata passed to the callbacks, without keeping the context locked.
+    fn userdata(context: &Mutex<AvahiBrowserContext>) -> *mut c_void {
+        lock(context).userdata.as_raw()
+    }
+
+    unsafe fn resolve(
+        context: &Mutex<AvahiBrowserContext>,
+        event: AvahiResolverEvent,
+        name: *const c_char,
+        host_name: *const c_char,
+    ) {
+        resolve_with(context, ptr::null_mut(), event, name, host_name)
+    }
+
+    unsafe fn resolve_with(
+        context: &Mutex<AvahiBrowserContext>,
+        resolver: *mut AvahiServiceResolver,
+        event: AvahiResolverEvent,
+        name: *const c_char,
+        host_name: *const c_char,
+    ) {
+        let kind = c_string!("_http._tcp");
+        let domain = c_string!("local");
+
+        resolve_callback(
+            resolver,
+            avahi_sys::AVAHI_IF_UNSPEC,
+            avahi_sys::AVAHI_PROTO_UNSPEC,
+            event,
+            name,
+            kind.as_ptr(),
+            domain.as_ptr(),
+            host_name,
+            ptr::null(),
+            8080,
+            ptr::null_mut(),
+            0,
+            userdata(context),
+        );
+    }
+
+    #[test]
+    fn browsing_waits_for_running_daemon() {
+        let (context, results) = context();
+        let states: Arc<Mutex<Vec<DaemonState>>> = Arc::default();
+        let sink = states.clone();
+
+        lock(&context).daemon_state_callback =
+            Some(Arc::new(Mutex::new(Box::new(move |state, _| {
+                sink.lock().unwrap().push(state)
+            }))));
+
+        unsafe {
+            let mut context = lock(&context);
+
+            handle_client_state(
+                &mut context,
+                avahi_sys::AvahiClientState_AVAHI_CLIENT_CONNECTING,
+            );
+            handle_client_state(
+                &mut context,
+                avahi_sys::AvahiClientState_AVAHI_CLIENT_S_REGISTERING,
+            );
+        }
+
+        assert!(lock(&context).browser.is_none());
+        assert!(results.lock().unwrap().is_empty());
+        assert_eq!(*states.lock().unwrap(), vec![DaemonState::Connecting]);
+    }
+
+    #[test]
+    fn queued_service_is_cancelled_on_removal() {
+        let (context, results) = context();
+        let name = c_string!("foo");
+        let kind = c_string!("_http._tcp");
+        let domain = c_string!("local");
+
+        let mut context = lock(&context);
+
+        unsafe {
+            for handle in [handle_browser_new, handle_browser_remove] {
+                handle(
+                    &mut context,
+                    avahi_sys::AVAHI_IF_UNSPEC,
+                    avahi_sys::AVAHI_PROTO_INET,
+                    name.as_ptr(),
+                    kind.as_ptr(),
+                    domain.as_ptr(),
+                )
+                .unwrap();
+            }
+        }
+
+        assert!(context.resolvers.next_pending().is_none());
+        assert!(results.lock().unwrap().is_empty());
+    }
+
+    /// Connects the browser to the daemon, as resolvers can only be started by a running client.
+    unsafe fn connect(context: &Mutex<AvahiBrowserContext>) {
+        let client = ManagedAvahiClient::new(
+            ManagedAvahiClientParams::builder()
+                .poll(Arc::new(ManagedAvahiSimplePoll::new().unwrap()))
+                .flags(AvahiClientFlags(0))
+                .callback(None)
+                .userdata(ptr::null_mut())
+                .build()
+                .unwrap(),
+        )
+        .unwrap();
+
+        lock(context).client = Some(Arc::new(client));
+    }
+
+    unsafe fn browse_new(context: &Mutex<AvahiBrowserContext>, name: &str) {
+        let name = c_string!(name);
+        let kind = c_string!("_http._tcp");
+        let domain = c_string!("local");
+
+        browse_callback(
+            ptr::null_mut(),
+            avahi_sys::AVAHI_IF_UNSPEC,
+            avahi_sys::AVAHI_PROTO_INET,
+            avahi_sys::AvahiBrowserEvent_AVAHI_BROWSER_NEW,
+            name.as_ptr(),
+            kind.as_ptr(),
+            domain.as_ptr(),
+            0,
+            userdata(context),
+        );
+    }
+
+    /// Returns the names of the services that are being resolved, in order.
+    fn resolving(context: &Mutex<AvahiBrowserContext>) -> Vec<String> {
+        let mut names = lock(context)
+            .resolvers
+            .active()
+            .into_iter()
+            .map(|(_, name)| name)
+            .collect::<Vec<_>>();
+
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn browse_resolves_at_most_max_concurrent_resolves_services() {
+        let (context, results) = context();
+
+        lock(&context).resolvers.set_max_concurrent(2);
+
+        unsafe {
+            connect(&context);
+
+            for name in ["a", "b", "c", "d"] {
+                browse_new(&context, name);
+            }
+        }
+
+        assert_eq!(resolving(&context), ["a", "b"]);
+        assert_eq!(lock(&context).resolvers.len(), 2);
+
+        let finished = |context: &Mutex<AvahiBrowserContext>, name: &str| {
+            let (resolver, _) = lock(context)
+                .resolvers
+                .active()
+                .into_iter()
+                .find(|(_, n)| n == name)
+                .unwrap();
+
+            let name = c_string!(name);
+
+            unsafe {
+                resolve_with(
+                    context,
+                    resolver,
+                    avahi_sys::AvahiResolverEvent_AVAHI_RESOLVER_FAILURE,
+                    name.as_ptr(),
+                    ptr::null(),
+                )
+            };
+        };
+
+        finished(&context, "a");
+
+        assert_eq!(resolving(&context), ["b", "c"]);
+
+        finished(&context, "c");
+        finished(&context, "b");
+
+        assert_eq!(resolving(&context), ["d"]);
+        assert_eq!(results.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn resolve_timeout_is_measured_with_browser_clock() {
+        let (context, _results) = context();
+        let clock = Arc::new(MockClock::new());
+        let events: Arc<Mutex<Vec<BrowserEvent>>> = Arc::default();
+        let sink = events.clone();
+
+        {
+            let mut context = lock(&context);
+
+            context.clock = clock.clone();
+            context.resolvers.set_max_concurrent(1);
+            context.resolvers.set_timeout(Duration::from_secs(5));
+            context.browser_event_callback =
+                Some(Arc::new(Mutex::new(Box::new(move |event, _| {
+                    sink.lock().unwrap().push(event)
+                }))));
+        }
+
+        unsafe {
+            connect(&context);
+            browse_new(&context, "a");
+            browse_new(&context, "b");
+        }
+
+        clock.advance(Duration::from_secs(4));
+        unsafe { expire_resolvers(&mut lock(&context)) };
+
+        assert_eq!(resolving(&context), ["a"]);
+        assert!(events.lock().unwrap().is_empty());
+
+        clock.advance(Duration::from_secs(1));
+        unsafe { expire_resolvers(&mut lock(&context)) };
+
+        assert_eq!(resolving(&context), ["b"]);
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![BrowserEvent::ResolveFailed {
+                key: ServiceKey::new("a", &ServiceType::new("http", "tcp").unwrap(), "local"),
+                reason: ResolveFailure::Timeout,
+            }]
+        );
+    }
+
+    #[test]
+    fn resolve_failure_with_null_name_is_reported() {
+        let (context, results) = context();
+
+        unsafe {
+            resolve(
+                &context,
+                avahi_sys::AvahiResolverEvent_AVAHI_RESOLVER_FAILURE,
+                ptr::null(),
+                ptr::null(),
+            )
+        };
+
+        assert_eq!(
+            *results.lock().unwrap(),
+            vec![Err(Error::from(
+                "failed to resolve service `<null>` of type `_http._tcp` in domain `local`"
+            )
+            .with_context(
+                ErrorContext::new(Operation::Resolve).with_service_type("_http._tcp")
+            ))]
+        );
+    }
+
+    #[test]
+    fn resolve_failure_is_counted_in_stats() {
+        let (context, _results) = context();
+
+        assert_eq!(
+            *lock(&context).stats.lock().unwrap(),
+            BrowserStats::default()
+        );
+
+        unsafe {
+            resolve(
+                &context,
+                avahi_sys::AvahiResolverEvent_AVAHI_RESOLVER_FAILURE,
+                ptr::null(),
+                ptr::null(),
+            )
+        };
+
+        let stats = *lock(&context).stats.lock().unwrap();
+
+        assert_eq!(*stats.events_received(), 1);
+        assert_eq!(*stats.failures(), 1);
+        assert!(stats.last_event().is_some());
+    }
+
+    #[test]
+    fn resolve_found_with_null_host_name_is_reported() {
+        let (context, results) = context();
+        let name = c_string!("foo");
+
+        unsafe {
+            resolve(
+                &context,
+                avahi_sys::AvahiResolverEvent_AVAHI_RESOLVER_FOUND,
+                name.as_ptr(),
+                ptr::null(),
+            )
+        };
+
+        assert_eq!(
+            results.lock().unwrap()[0].as_ref().unwrap_err().to_string(),
+            "resolve of `foo` (_http._tcp) failed: missing host name"
+        );
+    }
+
+    #[test]
+    fn resolve_found_with_null_address_is_reported() {
+        let (context, results) = context();
+        let name = c_string!("foo");
+        let host_name = c_string!("foo.local");
+
+        unsafe {
+            resolve(
+                &context,
+                avahi_sys::AvahiResolverEvent_AVAHI_RESOLVER_FOUND,
+                name.as_ptr(),
+                host_name.as_ptr(),
+            )
+        };
+
+        assert_eq!(
+            results.lock().unwrap()[0].as_ref().unwrap_err().to_string(),
+            "resolve of `foo` (_http._tcp) failed: missing service address"
+        );
+    }
+
+    #[test]
+    fn resolve_found_without_address_is_reported_if_address_is_not_looked_up() {
+        let (context, results) = context();
+        let name = c_string!("foo");
+        let host_name = c_string!("foo.local");
+
+        lock(&context).lookup_flags = LookupFlags::NO_ADDRESS;
+
+        unsafe {
+            resolve(
+                &context,
+                avahi_sys::AvahiResolverEvent_AVAHI_RESOLVER_FOUND,
+                name.as_ptr(),
+                host_name.as_ptr(),
+            )
+        };
+
+        let results = results.lock().unwrap();
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].as_ref().unwrap().address(), "");
+    }
+
+    #[test]
+    fn browse_flags_only_keep_multicast() {
+        let (context, _) = context();
+
+        lock(&context).lookup_flags =
+            LookupFlags::USE_MULTICAST | LookupFlags::NO_TXT | LookupFlags::NO_ADDRESS;
+
+        assert_eq!(
+            browse_flags(&lock(&context)),
+            avahi_sys::AvahiLookupFlags_AVAHI_LOOKUP_USE_MULTICAST
+        );
+    }
+
+    #[test]
+    fn browser_remove_with_null_name_is_ignored() {
+        let (context, results) = context();
+        let kind = c_string!("_http._tcp");
+
+        lock(&context).settle = Some(Arc::new(Mutex::new(SettleTracker::new(
+            Duration::from_secs(5),
+            Box::new(|_| {}),
+            Arc::new(SystemClock),
+        ))));
+
+        unsafe {
+            browse_callback(
+                ptr::null_mut(),
+                avahi_sys::AVAHI_IF_UNSPEC,
+                avahi_sys::AVAHI_PROTO_UNSPEC,
+                avahi_sys::AvahiBrowserEvent_AVAHI_BROWSER_REMOVE,
+                ptr::null(),
+                kind.as_ptr(),
+                ptr::null(),
+                0,
+                userdata(&context),
+            )
+        };
+
+        assert!(results.lock().unwrap().is_empty());
+    }
+
+    unsafe fn remove(context: &Mutex<AvahiBrowserContext>, interface: AvahiIfIndex) {
+        let name = c_string!("foo");
+        let kind = c_string!("_http._tcp");
+        let domain = c_string!("local");
+
+        browse_callback(
+            ptr::null_mut(),
+            interface,
+            avahi_sys::AVAHI_PROTO_UNSPEC,
+            avahi_sys::AvahiBrowserEvent_AVAHI_BROWSER_REMOVE,
+            name.as_ptr(),
+            kind.as_ptr(),
+            domain.as_ptr(),
+            0,
+            userdata(context),
+        );
+    }
+
+    fn foo() -> ServiceKey {
+        ServiceKey::new("foo", &ServiceType::new("http", "tcp").unwrap(), "local")
+    }
+
+    fn removed_foo() -> BrowserEvent {
+        BrowserEvent::ServiceRemoved(foo())
+    }
+
+    #[test]
+    fn every_browser_remove_is_reported() {
+        let (context, _) = context();
+        let events: Arc<Mutex<Vec<BrowserEvent>>> = Arc::default();
+        let sink = events.clone();
+
+        lock(&context).browser_event_callback =
+            Some(Arc::new(Mutex::new(Box::new(move |event, _| {
+                sink.lock().unwrap().push(event)
+            }))));
+
+        unsafe {
+            remove(&context, 1);
+            remove(&context, 2);
+        }
+
+        assert_eq!(*events.lock().unwrap(), vec![removed_foo(), removed_foo()]);
+    }
+
+    #[test]
+    fn deduplicated_service_is_reported_once() {
+        let (context, results) = context();
+        let events: Arc<Mutex<Vec<BrowserEvent>>> = Arc::default();
+        let sink = events.clone();
+        let name = c_string!("foo");
+        let host_name = c_string!("foo.local");
+        lock(&context).lookup_flags = LookupFlags::NO_ADDRESS;
+        lock(&context).browser_event_callback =
+            Some(Arc::new(Mutex::new(Box::new(move |event, _| {
+                sink.lock().unwrap().push(event)
+            }))));
+
+        {
+            let mut context = lock(&context);
+            let dedup = context.dedup.insert(Deduplicator::default());
+
+            for index in &[1, 2] {
+                let interface = NetworkInterface::AtIndex(*index);
+                dedup.found(foo(), interface);
+            }
+        }
+
+        unsafe {
+            for _ in 0..2 {
+                resolve(
+                    &context,
+                    avahi_sys::AvahiResolverEvent_AVAHI_RESOLVER_FOUND,
+                    name.as_ptr(),
+                    host_name.as_ptr(),
+                );
+            }
+
+            remove(&context, 1);
+            remove(&context, 2);
+        }
+
+        let events = events.lock().unwrap();
+        let removals = events
+            .iter()
+            .filter(|e| matches!(e, BrowserEvent::ServiceRemoved(_)))
+            .collect::<Vec<_>>();
+
+        assert_eq!(results.lock().unwrap().len(), 1);
+        assert_eq!(removals, vec![&removed_foo()]);
+    }
+
+    unsafe fn all_for_now(context: &Mutex<AvahiBrowserContext>) {
+        browse_callback(
+            ptr::null_mut(),
+            avahi_sys::AVAHI_IF_UNSPEC,
+            avahi_sys::AVAHI_PROTO_UNSPEC,
+            avahi_sys::AvahiBrowserEvent_AVAHI_BROWSER_ALL_FOR_NOW,
+            ptr::null(),
+            ptr::null(),
+            ptr::null(),
+            0,
+            userdata(context),
+        );
+    }
+
+    #[test]
+    fn results_are_flushed_once_all_for_now_and_resolving_has_drained() {
+        let (context, results) = context();
+        let events: Arc<Mutex<Vec<BrowserEvent>>> = Arc::default();
+        let sink = events.clone();
+
+        lock(&context).browser_event_callback =
+            Some(Arc::new(Mutex::new(Box::new(move |event, _| {
+                sink.lock().unwrap().push(event)
+            }))));
+
+        lock(&context).resolvers.set_max_concurrent(2);
+
+        let host_name = c_string!("foo.local");
+
+        unsafe {
+            connect(&context);
+
+            for name in ["a", "b", "c"] {
+                browse_new(&context, name);
+            }
+
+            all_for_now(&context);
+
+            for name in ["a", "b", "c"] {
+                let (resolver, _) = lock(&context)
+                    .resolvers
+                    .active()
+                    .into_iter()
+                    .find(|(_, n)| n == name)
+                    .unwrap();
+
+                let name = c_string!(name);
+
+                resolve_with(
+                    &context,
+                    resolver,
+                    avahi_sys::AvahiResolverEvent_AVAHI_RESOLVER_FOUND,
+                    name.as_ptr(),
+                    host_name.as_ptr(),
+                );
+            }
+        }
+
+        assert_eq!(results.lock().unwrap().len(), 3);
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![BrowserEvent::AllForNow, BrowserEvent::Flushed]
+        );
+    }
+
+    #[test]
+    fn results_before_all_for_now_are_flushed_with_it() {
+        let (context, results) = context();
+        let events: Arc<Mutex<Vec<BrowserEvent>>> = Arc::default();
+        let sink = events.clone();
+
+        lock(&context).browser_event_callback =
+            Some(Arc::new(Mutex::new(Box::new(move |event, _| {
+                sink.lock().unwrap().push(event)
+            }))));
+
+        let host_name = c_string!("foo.local");
+
+        unsafe {
+            for name in ["a", "b"] {
+                let name = c_string!(name);
+
+                resolve(
+                    &context,
+                    avahi_sys::AvahiResolverEvent_AVAHI_RESOLVER_FOUND,
+                    name.as_ptr(),
+                    host_name.as_ptr(),
+                );
+            }
+
+            let name = c_string!("c");
+
+            resolve(
+                &context,
+                avahi_sys::AvahiResolverEvent_AVAHI_RESOLVER_FAILURE,
+                name.as_ptr(),
+                ptr::null(),
+            );
+        }
+
+        assert_eq!(results.lock().unwrap().len(), 3);
+        assert!(events.lock().unwrap().is_empty());
+
+        unsafe { all_for_now(&context) };
+
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![BrowserEvent::Flushed, BrowserEvent::AllForNow]
+        );
+    }
+
+    #[test]
+    fn all_for_now_and_cache_exhausted_are_reported() {
+        let (context, results) = context();
+        let events: Arc<Mutex<Vec<BrowserEvent>>> = Arc::default();
+        let sink = events.clone();
+
+        lock(&context).browser_event_callback =
+            Some(Arc::new(Mutex::new(Box::new(move |event, _| {
+                sink.lock().unwrap().push(event)
+            }))));
+
+        for event in &[
+            avahi_sys::AvahiBrowserEvent_AVAHI_BROWSER_CACHE_EXHAUSTED,
+            avahi_sys::AvahiBrowserEvent_AVAHI_BROWSER_ALL_FOR_NOW,
+        ] {
+            unsafe {
+                browse_callback(
+                    ptr::null_mut(),
+                    avahi_sys::AVAHI_IF_UNSPEC,
+                    avahi_sys::AVAHI_PROTO_UNSPEC,
+                    *event,
+                    ptr::null(),
+                    ptr::null(),
+                    ptr::null(),
+                    0,
+                    userdata(&context),
+                )
+            };
+        }
+
+        assert!(results.lock().unwrap().is_empty());
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![BrowserEvent::CacheExhausted, BrowserEvent::AllForNow]
+        );
+    }
+
+    #[test]
+    fn service_types_are_reported() {
+        let (context, results) = context();
+        let events: Arc<Mutex<Vec<BrowserEvent>>> = Arc::default();
+        let sink = events.clone();
+
+        lock(&context).meta_query = true;
+        lock(&context).browser_event_callback =
+            Some(Arc::new(Mutex::new(Box::new(move |event, _| {
+                sink.lock().unwrap().push(event)
+            }))));
+
+        let kind = c_string!("_http._tcp");
+        let domain = c_string!("local");
+
+        for event in &[
+            avahi_sys::AvahiBrowserEvent_AVAHI_BROWSER_NEW,
+            avahi_sys::AvahiBrowserEvent_AVAHI_BROWSER_REMOVE,
+        ] {
+            unsafe {
+                type_browse_callback(
+                    ptr::null_mut(),
+                    avahi_sys::AVAHI_IF_UNSPEC,
+                    avahi_sys::AVAHI_PROTO_UNSPEC,
+                    *event,
+                    kind.as_ptr(),
+                    domain.as_ptr(),
+                    0,
+                    userdata(&context),
+                )
+            };
+        }
+
+        let service_type = ServiceType::new("http", "tcp").unwrap();
+
+        assert!(results.lock().unwrap().is_empty());
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                BrowserEvent::ServiceTypeFound(service_type.clone()),
+                BrowserEvent::ServiceTypeRemoved(service_type)
+            ]
+        );
+    }
+
+    #[test]
+    fn callbacks_after_drop_are_ignored() {
+        let (context, results) = context();
+        let userdata = WeakUserdata::new(Arc::downgrade(&context));
+
+        drop(context);
+
+        let name = c_string!("foo");
+        let kind = c_string!("_http._tcp");
+        let domain = c_string!("local");
+
+        unsafe {
+            browse_callback(
+                ptr::null_mut(),
+                avahi_sys::AVAHI_IF_UNSPEC,
+                avahi_sys::AVAHI_PROTO_UNSPEC,
+                avahi_sys::AvahiBrowserEvent_AVAHI_BROWSER_NEW,
+                name.as_ptr(),
+                kind.as_ptr(),
+                domain.as_ptr(),
+                0,
+                userdata.as_raw(),
+            );
+            client_callback(
+                ptr::null_mut(),
+                avahi_sys::AvahiClientState_AVAHI_CLIENT_FAILURE,
+                userdata.as_raw(),
+            );
+        }
+
+        assert!(results.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn browser_dropped_from_callback_is_freed_after_dispatch() {
+        let slot: Arc<Mutex<Option<AvahiMdnsBrowser>>> = Arc::default();
+        let handle = slot.clone();
+
+        let mut browser = AvahiMdnsBrowser::new(ServiceType::new("http", "tcp").unwrap());
+
+        browser
+            .set_browser_event_callback(Box::new(move |_, _| drop(handle.lock().unwrap().take())));
+
+        let context = Arc::downgrade(&browser.context);
+        let userdata = WeakUserdata::new(context.clone());
+
+        *slot.lock().unwrap() = Some(browser);
+
+        unsafe {
+            browse_callback(
+                ptr::null_mut(),
+                avahi_sys::AVAHI_IF_UNSPEC,
+                avahi_sys::AVAHI_PROTO_UNSPEC,
+                avahi_sys::AvahiBrowserEvent_AVAHI_BROWSER_ALL_FOR_NOW,
+                ptr::null(),
+                ptr::null(),
+                ptr::null(),
+                0,
+                userdata.as_raw(),
+            )
+        };
+
+        assert!(slot.lock().unwrap().is_none());
+        assert!(context.upgrade().is_some());
+
+        ffi::drop_dispatched();
+
+        assert!(context.upgrade().is_none());
+    }
+
+    #[test]
+    fn browser_methods_can_be_called_from_its_callbacks() {
+        let slot: Arc<Mutex<Option<AvahiMdnsBrowser>>> = Arc::default();
+        let handle = slot.clone();
+        let (tx, rx) = mpsc::channel();
+
+        let mut browser = AvahiMdnsBrowser::new(ServiceType::new("http", "tcp").unwrap());
+
+        browser.set_browser_event_callback(Box::new(move |event, _| {
+            let mut browser = handle.lock().unwrap();
+            let browser = browser.as_mut().unwrap();
+
+            // the context of the browser is not locked while its callbacks are invoked
+            browser.set_resolve_timeout(Duration::from_secs(1));
+            tx.send((event, browser.stats())).unwrap();
+        }));
+
+        let userdata = WeakUserdata::new(Arc::downgrade(&browser.context));
+
+        *slot.lock().unwrap() = Some(browser);
+
+        unsafe {
+            browse_callback(
+                ptr::null_mut(),
+                avahi_sys::AVAHI_IF_UNSPEC,
+                avahi_sys::AVAHI_PROTO_UNSPEC,
+                avahi_sys::AvahiBrowserEvent_AVAHI_BROWSER_ALL_FOR_NOW,
+                ptr::null(),
+                ptr::null(),
+                ptr::null(),
+                0,
+                userdata.as_raw(),
+            )
+        };
+
+        let (event, stats) = rx.try_recv().unwrap();
+
+        assert_eq!(event, BrowserEvent::AllForNow);
+        assert_eq!(*stats.events_received(), 1);
+        assert_eq!(
+            slot.lock().unwrap().as_ref().unwrap().resolve_timeout(),
+            Duration::from_secs(1)
+        );
+    }
+
+    #[test]
+    fn configured_domain_is_kept_for_browser_and_resolvers() {
+        let mut browser = AvahiMdnsBrowser::new(ServiceType::new("http", "tcp").unwrap());
+
+        assert_eq!(browser.domain(), None);
+        assert_eq!(lock(&browser.context).domain, None);
+
+        browser.set_domain("local");
+
+        assert_eq!(browser.domain(), Some("local"));
+        assert_eq!(lock(&browser.context).domain, Some(c_string!("local")));
+    }
+}
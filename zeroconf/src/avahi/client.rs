@@ -1,15 +1,21 @@
 //! Rust friendly `AvahiClient` wrappers/helpers
 
+use std::path::PathBuf;
 use std::sync::Arc;
 
 use super::{avahi_util, poll::ManagedAvahiSimplePoll};
+use crate::daemon::{self, Diagnosis};
+use crate::error::Error;
 use crate::ffi::c_str;
-use crate::Result;
+use crate::prelude::*;
+use crate::{BackendInfo, BackendKind, DaemonState, Result};
 use avahi_sys::{
-    avahi_client_free, avahi_client_get_host_name, avahi_client_new, avahi_simple_poll_get,
-    AvahiClient, AvahiClientCallback, AvahiClientFlags,
+    avahi_client_free, avahi_client_get_domain_name, avahi_client_get_host_name,
+    avahi_client_get_host_name_fqdn, avahi_client_get_state, avahi_client_get_version_string,
+    avahi_client_new, avahi_simple_poll_get, AvahiClient, AvahiClientCallback, AvahiClientFlags,
+    AvahiClientState,
 };
-use libc::{c_int, c_void};
+use libc::{c_char, c_int, c_void};
 
 /// Wraps the `AvahiClient` type from the raw Avahi bindings.
 ///
@@ -18,7 +24,7 @@ use libc::{c_int, c_void};
 #[derive(Debug)]
 pub struct ManagedAvahiClient {
     pub(crate) inner: *mut AvahiClient,
-    _poll: Arc<ManagedAvahiSimplePoll>,
+    poll: Arc<ManagedAvahiSimplePoll>,
 }
 
 impl ManagedAvahiClient {
@@ -35,6 +41,11 @@ impl ManagedAvahiClient {
             userdata,
         }: ManagedAvahiClientParams,
     ) -> Result<Self> {
+        let span = span!("client", flags = flags.0);
+        let _entered = span.enter();
+
+        debug!("Creating client");
+
         let mut err: c_int = 0;
 
         let inner = avahi_client_new(
@@ -45,18 +56,15 @@ impl ManagedAvahiClient {
             &mut err,
         );
 
-        if inner.is_null() {
-            return Err("could not initialize AvahiClient".into());
+        if inner.is_null() || err != 0 {
+            let error = client_error(err);
+            debug!("Could not create client: {}", error);
+            return Err(error);
         }
 
-        match err {
-            0 => Ok(Self { inner, _poll: poll }),
-            _ => Err(format!(
-                "could not initialize AvahiClient: {}",
-                avahi_util::get_error(err)
-            )
-            .into()),
-        }
+        debug!("Client created");
+
+        Ok(Self { inner, poll })
     }
 
     /// Delegate function for [`avahi_client_get_host_name()`].
@@ -68,6 +76,73 @@ impl ManagedAvahiClient {
     pub unsafe fn host_name<'a>(&self) -> Result<&'a str> {
         get_host_name(self.inner)
     }
+
+    /// Delegate function for [`avahi_client_get_host_name_fqdn()`].
+    ///
+    /// [`avahi_client_get_host_name_fqdn()`]: https://avahi.org/doxygen/html/client_8h.html
+    ///
+    /// # Safety
+    /// This function is unsafe because of the raw pointer dereference.
+    pub unsafe fn host_name_fqdn<'a>(&self) -> Result<&'a str> {
+        get_client_str(
+            self.inner,
+            avahi_client_get_host_name_fqdn,
+            "fully qualified host name",
+        )
+    }
+
+    /// Delegate function for [`avahi_client_get_domain_name()`].
+    ///
+    /// [`avahi_client_get_domain_name()`]: https://avahi.org/doxygen/html/client_8h.html
+    ///
+    /// # Safety
+    /// This function is unsafe because of the raw pointer dereference.
+    pub unsafe fn domain_name<'a>(&self) -> Result<&'a str> {
+        get_client_str(self.inner, avahi_client_get_domain_name, "domain name")
+    }
+
+    /// Delegate function for [`avahi_client_get_version_string()`]. Returns the version of the
+    /// daemon, e.g. `avahi 0.8`.
+    ///
+    /// [`avahi_client_get_version_string()`]: https://avahi.org/doxygen/html/client_8h.html
+    ///
+    /// # Safety
+    /// This function is unsafe because of the raw pointer dereference.
+    pub unsafe fn version_string<'a>(&self) -> Result<&'a str> {
+        get_client_str(
+            self.inner,
+            avahi_client_get_version_string,
+            "version string",
+        )
+    }
+
+    /// Returns the [`BackendInfo`] of the daemon this client is connected to.
+    ///
+    /// [`BackendInfo`]: ../../struct.BackendInfo.html
+    ///
+    /// # Safety
+    /// This function is unsafe because of the raw pointer dereference.
+    pub unsafe fn backend_info(&self) -> Result<BackendInfo> {
+        Ok(BackendInfo::builder()
+            .kind(BackendKind::Avahi)
+            .version(self.version_string()?.to_string())
+            .build()?)
+    }
+
+    /// Delegate function for [`avahi_client_get_state()`].
+    ///
+    /// [`avahi_client_get_state()`]: https://avahi.org/doxygen/html/client_8h.html
+    ///
+    /// # Safety
+    /// This function is unsafe because of the raw pointer dereference.
+    pub unsafe fn state(&self) -> AvahiClientState {
+        avahi_client_get_state(self.inner)
+    }
+
+    /// Returns the poll this client was created with.
+    pub(crate) fn poll(&self) -> &Arc<ManagedAvahiSimplePoll> {
+        &self.poll
+    }
 }
 
 impl Drop for ManagedAvahiClient {
@@ -76,6 +151,11 @@ impl Drop for ManagedAvahiClient {
     }
 }
 
+// SAFETY: the client is shared through an `Arc` by every object created from it, and is freed by
+// whichever thread drops the last one, after all of them. libavahi-client does not lock the
+// client, and some of its `&self` methods query the daemon over its D-Bus connection. They are
+// `unsafe` and only called by the service or browser owning the client, from its methods and
+// callbacks, under the same rules as the objects created from the client.
 unsafe impl Send for ManagedAvahiClient {}
 unsafe impl Sync for ManagedAvahiClient {}
 
@@ -92,13 +172,212 @@ pub struct ManagedAvahiClientParams {
     userdata: *mut c_void,
 }
 
+/// Default address of the D-Bus system bus, used when `DBUS_SYSTEM_BUS_ADDRESS` is not set
+const DEFAULT_SYSTEM_BUS_ADDRESS: &str = "unix:path=/var/run/dbus/system_bus_socket";
+
+fn system_bus_address() -> String {
+    std::env::var("DBUS_SYSTEM_BUS_ADDRESS")
+        .unwrap_or_else(|_| DEFAULT_SYSTEM_BUS_ADDRESS.to_string())
+}
+
+/// Returns the path of the socket of a D-Bus server address such as
+/// `unix:path=/var/run/dbus/system_bus_socket`, or `None` if none of the addresses it lists is a
+/// Unix socket in the file system.
+fn bus_socket_path(address: &str) -> Option<PathBuf> {
+    address.split(';').find_map(|address| {
+        address
+            .strip_prefix("unix:")?
+            .split(',')
+            .find_map(|param| param.strip_prefix("path="))
+            .map(PathBuf::from)
+    })
+}
+
+/// Converts the error code returned by `avahi_client_new()` to an `Error`.
+unsafe fn client_error(err: c_int) -> Error {
+    match err {
+        avahi_sys::AVAHI_ERR_ACCESS_DENIED => Error::PermissionDenied {
+            detail: format!(
+                "could not connect to the Avahi daemon on system bus `{}`",
+                system_bus_address()
+            ),
+        },
+        avahi_sys::AVAHI_ERR_NO_DAEMON => Error::DaemonNotRunning {
+            detail: format!(
+                "could not connect to the Avahi daemon on system bus `{}`, check that \
+                 avahi-daemon is running",
+                system_bus_address()
+            ),
+        },
+        0 => "could not initialize AvahiClient".into(),
+        _ => format!(
+            "could not initialize AvahiClient: {}",
+            avahi_util::get_error(err)
+        )
+        .into(),
+    }
+}
+
 pub(super) unsafe fn get_host_name<'a>(client: *mut AvahiClient) -> Result<&'a str> {
+    get_client_str(client, avahi_client_get_host_name, "host name")
+}
+
+unsafe fn get_client_str<'a>(
+    client: *mut AvahiClient,
+    f: unsafe extern "C" fn(*mut AvahiClient) -> *const c_char,
+    what: &str,
+) -> Result<&'a str> {
     assert_not_null!(client);
-    let host_name = avahi_client_get_host_name(client);
+    let value = f(client);
 
-    if !host_name.is_null() {
-        Ok(c_str::raw_to_str(host_name))
+    if !value.is_null() {
+        Ok(c_str::raw_to_str(value))
     } else {
-        Err("could not get host name from AvahiClient".into())
+        Err(format!("could not get {} from AvahiClient", what).into())
+    }
+}
+
+/// Connects to the Avahi daemon and returns the fully qualified host name services are
+/// registered under by default (e.g. `myhost.local`).
+pub fn host_name() -> Result<String> {
+    unsafe {
+        let client = ManagedAvahiClient::new(
+            ManagedAvahiClientParams::builder()
+                .poll(Arc::new(ManagedAvahiSimplePoll::new()?))
+                .flags(AvahiClientFlags(0))
+                .callback(None)
+                .userdata(std::ptr::null_mut())
+                .build()?,
+        )?;
+
+        Ok(client.host_name_fqdn()?.to_string())
+    }
+}
+
+/// Connects to the Avahi daemon and returns its [`BackendInfo`].
+///
+/// [`BackendInfo`]: ../../struct.BackendInfo.html
+pub fn backend_info() -> Result<BackendInfo> {
+    unsafe {
+        let client = ManagedAvahiClient::new(
+            ManagedAvahiClientParams::builder()
+                .poll(Arc::new(ManagedAvahiSimplePoll::new()?))
+                .flags(AvahiClientFlags(0))
+                .callback(None)
+                .userdata(std::ptr::null_mut())
+                .build()?,
+        )?;
+
+        client.backend_info()
+    }
+}
+
+/// Checks the socket of the D-Bus system bus and connects to the Avahi daemon through it.
+pub fn diagnose() -> Diagnosis {
+    diagnose_socket(bus_socket_path(&system_bus_address()))
+}
+
+/// Like [`diagnose()`], but checks the socket at `socket_path` rather than the one of the system
+/// bus. The connection is still made through the system bus.
+pub(crate) fn diagnose_socket(socket_path: Option<PathBuf>) -> Diagnosis {
+    let socket_access = match &socket_path {
+        Some(path) => daemon::check_socket_access(path),
+        None => Ok(()),
+    };
+
+    let connection = unsafe {
+        ManagedAvahiSimplePoll::new().and_then(|poll| {
+            ManagedAvahiClient::new(
+                ManagedAvahiClientParams::builder()
+                    .poll(Arc::new(poll))
+                    .flags(AvahiClientFlags(0))
+                    .callback(None)
+                    .userdata(std::ptr::null_mut())
+                    .build()?,
+            )
+        })
+    };
+
+    Diagnosis::new(socket_path, socket_access, connection.map(|_| ()))
+}
+
+/// Returns true if the Avahi daemon is reachable. The client is created with
+/// `AVAHI_CLIENT_NO_FAIL`, so a missing daemon is reported through the client state rather than
+/// waited for.
+pub fn is_available() -> bool {
+    let client = unsafe {
+        ManagedAvahiSimplePoll::new().and_then(|poll| {
+            ManagedAvahiClient::new(
+                ManagedAvahiClientParams::builder()
+                    .poll(Arc::new(poll))
+                    .flags(AvahiClientFlags::AVAHI_CLIENT_NO_FAIL)
+                    .callback(None)
+                    .userdata(std::ptr::null_mut())
+                    .build()?,
+            )
+        })
+    };
+
+    match client {
+        Ok(client) => !matches!(
+            avahi_util::daemon_state(unsafe { client.state() }),
+            Some(DaemonState::Connecting) | Some(DaemonState::Failed)
+        ),
+        Err(error) => {
+            debug!("Avahi is not available: {}", error);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn client_error_returns_permission_denied_for_access_denied() {
+        let err = unsafe { client_error(avahi_sys::AVAHI_ERR_ACCESS_DENIED) };
+
+        match err {
+            Error::PermissionDenied { detail } => assert!(detail.contains(&system_bus_address())),
+            _ => panic!("expected PermissionDenied, got {:?}", err),
+        }
+    }
+
+    #[test]
+    fn bus_socket_path_finds_unix_socket() {
+        assert_eq!(
+            bus_socket_path(DEFAULT_SYSTEM_BUS_ADDRESS),
+            Some(PathBuf::from("/var/run/dbus/system_bus_socket"))
+        );
+
+        assert_eq!(
+            bus_socket_path("tcp:host=localhost,port=1234;unix:guid=abc,path=/run/bus"),
+            Some(PathBuf::from("/run/bus"))
+        );
+    }
+
+    #[test]
+    fn bus_socket_path_ignores_other_addresses() {
+        assert_eq!(bus_socket_path("unix:abstract=/tmp/dbus-abc"), None);
+        assert_eq!(bus_socket_path("tcp:host=localhost,port=1234"), None);
+    }
+
+    #[test]
+    fn client_error_returns_daemon_not_running_for_no_daemon() {
+        let err = unsafe { client_error(avahi_sys::AVAHI_ERR_NO_DAEMON) };
+
+        match err {
+            Error::DaemonNotRunning { detail } => assert!(detail.contains(&system_bus_address())),
+            _ => panic!("expected DaemonNotRunning, got {:?}", err),
+        }
+    }
+
+    #[test]
+    fn client_error_returns_description_for_other_errors() {
+        assert_eq!(
+            unsafe { client_error(avahi_sys::AVAHI_ERR_TIMEOUT) },
+            "could not initialize AvahiClient: Timeout reached".into()
+        );
     }
 }
@@ -0,0 +1,296 @@
+//! Avahi implementation for cross-platform domain browser
+
+use super::avahi_util;
+use super::client::{ManagedAvahiClient, ManagedAvahiClientParams};
+use super::poll::ManagedAvahiSimplePoll;
+use super::raw_domain_browser::{ManagedAvahiDomainBrowser, ManagedAvahiDomainBrowserParams};
+use crate::error::Error;
+use crate::ffi::{self, c_str, AsRaw, CallbackContext};
+use crate::prelude::*;
+use crate::{
+    DomainEvent, DomainEventCallback, DomainType, EventLoop, Id, NetworkInterface, Result,
+};
+use avahi_sys::{
+    AvahiBrowserEvent, AvahiClient, AvahiClientFlags, AvahiClientState, AvahiDomainBrowser,
+    AvahiDomainBrowserType, AvahiIfIndex, AvahiLookupResultFlags, AvahiProtocol,
+};
+use libc::{c_char, c_void};
+use std::any::Any;
+use std::sync::Arc;
+use std::{fmt, ptr};
+
+#[derive(Debug)]
+pub struct AvahiMdnsDomainBrowser {
+    context: Box<AvahiDomainBrowserContext>,
+    client: Option<Arc<ManagedAvahiClient>>,
+    poll: Option<Arc<ManagedAvahiSimplePoll>>,
+}
+
+impl TMdnsDomainBrowser for AvahiMdnsDomainBrowser {
+    fn new(domain_type: DomainType) -> Self {
+        Self {
+            client: None,
+            poll: None,
+            context: Box::new(AvahiDomainBrowserContext::new(
+                domain_type,
+                avahi_sys::AVAHI_IF_UNSPEC,
+            )),
+        }
+    }
+
+    fn id(&self) -> Id {
+        self.context.id
+    }
+
+    fn domain_type(&self) -> DomainType {
+        self.context.domain_type
+    }
+
+    fn set_network_interface(&mut self, interface: NetworkInterface) {
+        self.context.interface_index = avahi_util::interface_index(interface);
+    }
+
+    fn network_interface(&self) -> NetworkInterface {
+        avahi_util::interface_from_index(self.context.interface_index)
+    }
+
+    fn set_domain_event_callback(&mut self, domain_event_callback: Box<DomainEventCallback>) {
+        self.context.domain_event_callback = Some(domain_event_callback);
+    }
+
+    fn set_context(&mut self, context: Box<dyn Any + Send + Sync>) {
+        self.context.user_context = Some(Arc::from(context));
+    }
+
+    fn context(&self) -> Option<&(dyn Any + Send + Sync)> {
+        self.context.user_context.as_ref().map(|c| c.as_ref())
+    }
+
+    fn browse_domains(&mut self) -> Result<EventLoop> {
+        debug!("[{}] Browsing domains: {:?}", self.context.id, self);
+
+        self.context.browser = None;
+
+        self.poll = Some(Arc::new(unsafe { ManagedAvahiSimplePoll::new() }?));
+
+        let poll = self
+            .poll
+            .as_ref()
+            .ok_or("could not get poll as ref")?
+            .clone();
+
+        let client_params = ManagedAvahiClientParams::builder()
+            .poll(poll)
+            .flags(AvahiClientFlags(0))
+            .callback(Some(client_callback))
+            .userdata(self.context.as_raw())
+            .build()?;
+
+        self.client = Some(Arc::new(unsafe { ManagedAvahiClient::new(client_params) }?));
+
+        self.context.client.clone_from(&self.client);
+
+        unsafe { create_browser(&mut self.context) }?;
+
+        let event_loop = EventLoop::new(
+            self.poll
+                .as_ref()
+                .ok_or("could not get poll as ref")?
+                .clone(),
+        );
+
+        debug!("[{}] Event loop: {}", self.context.id, event_loop.id());
+
+        Ok(event_loop)
+    }
+}
+
+#[derive(FromRaw, AsRaw)]
+struct AvahiDomainBrowserContext {
+    id: Id,
+    domain_type: DomainType,
+    client: Option<Arc<ManagedAvahiClient>>,
+    browser: Option<ManagedAvahiDomainBrowser>,
+    domain_event_callback: Option<Box<DomainEventCallback>>,
+    user_context: Option<Arc<dyn Any + Send + Sync>>,
+    interface_index: AvahiIfIndex,
+}
+
+impl AvahiDomainBrowserContext {
+    fn new(domain_type: DomainType, interface_index: AvahiIfIndex) -> Self {
+        Self {
+            id: Id::next(),
+            domain_type,
+            client: None,
+            browser: None,
+            domain_event_callback: None,
+            user_context: None,
+            interface_index,
+        }
+    }
+
+    fn invoke_callback(&self, result: Result<DomainEvent>) {
+        if let Some(f) = &self.domain_event_callback {
+            f(result, self.user_context.clone());
+        } else {
+            warn!(
+                "[{}] attempted to invoke domain browser callback but none was set",
+                self.id
+            );
+        }
+    }
+}
+
+impl CallbackContext for AvahiDomainBrowserContext {
+    fn id(&self) -> Id {
+        self.id
+    }
+
+    fn invoke_error(&self, error: Error) {
+        self.invoke_callback(Err(error))
+    }
+}
+
+impl fmt::Debug for AvahiDomainBrowserContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AvahiDomainBrowserContext")
+            .field("id", &self.id)
+            .field("domain_type", &self.domain_type)
+            .field("browser", &self.browser)
+            .finish()
+    }
+}
+
+unsafe extern "C" fn client_callback(
+    client: *mut AvahiClient,
+    state: AvahiClientState,
+    userdata: *mut c_void,
+) {
+    ffi::guard(userdata, |context: &mut AvahiDomainBrowserContext| {
+        if state == avahi_sys::AvahiClientState_AVAHI_CLIENT_FAILURE {
+            context.invoke_callback(Err(avahi_util::get_last_error(client).into()));
+        }
+    })
+}
+
+unsafe fn create_browser(context: &mut AvahiDomainBrowserContext) -> Result<()> {
+    context.browser = Some(ManagedAvahiDomainBrowser::new(
+        ManagedAvahiDomainBrowserParams::builder()
+            .client(Arc::clone(
+                context
+                    .client
+                    .as_ref()
+                    .ok_or("could not get client as ref")?,
+            ))
+            .interface(context.interface_index)
+            .protocol(avahi_sys::AVAHI_PROTO_UNSPEC)
+            .domain(ptr::null())
+            .btype(browser_type(context.domain_type))
+            .flags(0)
+            .callback(Some(browse_callback))
+            .userdata(context.as_raw())
+            .build()?,
+    )?);
+
+    Ok(())
+}
+
+fn browser_type(domain_type: DomainType) -> AvahiDomainBrowserType {
+    match domain_type {
+        DomainType::Browse => avahi_sys::AvahiDomainBrowserType_AVAHI_DOMAIN_BROWSER_BROWSE,
+        DomainType::Registration => avahi_sys::AvahiDomainBrowserType_AVAHI_DOMAIN_BROWSER_REGISTER,
+    }
+}
+
+unsafe extern "C" fn browse_callback(
+    _browser: *mut AvahiDomainBrowser,
+    _interface: AvahiIfIndex,
+    _protocol: AvahiProtocol,
+    event: AvahiBrowserEvent,
+    domain: *const c_char,
+    _flags: AvahiLookupResultFlags,
+    userdata: *mut c_void,
+) {
+    ffi::guard(userdata, |context: &mut AvahiDomainBrowserContext| {
+        match event {
+            avahi_sys::AvahiBrowserEvent_AVAHI_BROWSER_NEW => context.invoke_callback(
+                c_str::try_raw_to_str(domain, "domain").map(|d| DomainEvent::Add(d.to_string())),
+            ),
+            avahi_sys::AvahiBrowserEvent_AVAHI_BROWSER_REMOVE => context.invoke_callback(
+                c_str::try_raw_to_str(domain, "domain").map(|d| DomainEvent::Remove(d.to_string())),
+            ),
+            avahi_sys::AvahiBrowserEvent_AVAHI_BROWSER_FAILURE => {
+                context.invoke_callback(Err("domain browser failure".into()))
+            }
+            _ => {}
+        };
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn browse_callback_reports_domain_events() {
+        let results: Arc<Mutex<Vec<Result<DomainEvent>>>> = Arc::default();
+        let sink = results.clone();
+        let domain = c_string!("example.com");
+
+        let mut context =
+            AvahiDomainBrowserContext::new(DomainType::Browse, avahi_sys::AVAHI_IF_UNSPEC);
+
+        context.domain_event_callback =
+            Some(Box::new(move |result, _| sink.lock().unwrap().push(result)));
+
+        for (event, domain) in [
+            (
+                avahi_sys::AvahiBrowserEvent_AVAHI_BROWSER_NEW,
+                domain.as_ptr(),
+            ),
+            (
+                avahi_sys::AvahiBrowserEvent_AVAHI_BROWSER_REMOVE,
+                domain.as_ptr(),
+            ),
+            (avahi_sys::AvahiBrowserEvent_AVAHI_BROWSER_NEW, ptr::null()),
+            (
+                avahi_sys::AvahiBrowserEvent_AVAHI_BROWSER_ALL_FOR_NOW,
+                ptr::null(),
+            ),
+        ] {
+            unsafe {
+                browse_callback(
+                    ptr::null_mut(),
+                    avahi_sys::AVAHI_IF_UNSPEC,
+                    avahi_sys::AVAHI_PROTO_UNSPEC,
+                    event,
+                    domain,
+                    0,
+                    context.as_raw(),
+                )
+            };
+        }
+
+        assert_eq!(
+            *results.lock().unwrap(),
+            vec![
+                Ok(DomainEvent::Add("example.com".to_string())),
+                Ok(DomainEvent::Remove("example.com".to_string())),
+                Err("missing domain".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn browser_type_maps_domain_type() {
+        assert_eq!(
+            browser_type(DomainType::Browse),
+            avahi_sys::AvahiDomainBrowserType_AVAHI_DOMAIN_BROWSER_BROWSE
+        );
+        assert_eq!(
+            browser_type(DomainType::Registration),
+            avahi_sys::AvahiDomainBrowserType_AVAHI_DOMAIN_BROWSER_REGISTER
+        );
+    }
+}
@@ -7,10 +7,12 @@ use crate::avahi::avahi_util;
 use crate::ffi::UnwrapMutOrNull;
 use crate::Result;
 use avahi_sys::{
-    avahi_client_errno, avahi_entry_group_add_service_strlst,
-    avahi_entry_group_add_service_subtype, avahi_entry_group_commit, avahi_entry_group_free,
-    avahi_entry_group_is_empty, avahi_entry_group_new, avahi_entry_group_reset, AvahiClient,
-    AvahiEntryGroup, AvahiEntryGroupCallback, AvahiIfIndex, AvahiProtocol, AvahiPublishFlags,
+    avahi_client_errno, avahi_entry_group_add_address, avahi_entry_group_add_record,
+    avahi_entry_group_add_service_strlst, avahi_entry_group_add_service_subtype,
+    avahi_entry_group_commit, avahi_entry_group_free, avahi_entry_group_is_empty,
+    avahi_entry_group_new, avahi_entry_group_reset, avahi_entry_group_update_service_txt_strlst,
+    AvahiAddress, AvahiClient, AvahiEntryGroup, AvahiEntryGroupCallback, AvahiIfIndex,
+    AvahiProtocol, AvahiPublishFlags,
 };
 use libc::{c_char, c_void};
 
@@ -131,6 +133,109 @@ impl ManagedAvahiEntryGroup {
         )
     }
 
+    /// Delegate function for [`avahi_entry_group_add_record()`].
+    ///
+    /// Also propagates any error returned into a `Result`.
+    ///
+    /// [`avahi_entry_group_add_record()`]: https://avahi.org/doxygen/html/publish_8h.html
+    ///
+    /// # Safety
+    /// This function is unsafe because of the call to `avahi_entry_group_add_record()`.
+    pub unsafe fn add_record(
+        &mut self,
+        AddRecordParams {
+            interface,
+            protocol,
+            flags,
+            name,
+            clazz,
+            rr_type,
+            ttl,
+            rdata,
+        }: AddRecordParams,
+    ) -> Result<()> {
+        avahi_util::sys_exec(
+            || {
+                avahi_entry_group_add_record(
+                    self.inner,
+                    interface,
+                    protocol,
+                    flags,
+                    name,
+                    clazz,
+                    rr_type,
+                    ttl,
+                    rdata.as_ptr() as *const c_void,
+                    rdata.len(),
+                )
+            },
+            "could not register record",
+        )
+    }
+
+    /// Delegate function for [`avahi_entry_group_add_address()`].
+    ///
+    /// Also propagates any error returned into a `Result`.
+    ///
+    /// [`avahi_entry_group_add_address()`]: https://avahi.org/doxygen/html/publish_8h.html
+    ///
+    /// # Safety
+    /// This function is unsafe because of the call to `avahi_entry_group_add_address()`.
+    pub unsafe fn add_address(
+        &mut self,
+        AddAddressParams {
+            interface,
+            protocol,
+            flags,
+            name,
+            address,
+        }: AddAddressParams,
+    ) -> Result<()> {
+        avahi_util::sys_exec(
+            || avahi_entry_group_add_address(self.inner, interface, protocol, flags, name, address),
+            "could not register address",
+        )
+    }
+
+    /// Delegate function for [`avahi_entry_group_update_service_txt()`].
+    ///
+    /// Replaces the TXT record of a service previously added with `add_service()`. Unlike the
+    /// other changes to a group, this takes effect without committing the group again.
+    ///
+    /// [`avahi_entry_group_update_service_txt()`]: https://avahi.org/doxygen/html/publish_8h.html
+    ///
+    /// # Safety
+    /// This function is unsafe because of the call to
+    /// `avahi_entry_group_update_service_txt_strlst()`.
+    pub unsafe fn update_service_txt(
+        &mut self,
+        UpdateServiceTxtParams {
+            interface,
+            protocol,
+            flags,
+            name,
+            kind,
+            domain,
+            txt,
+        }: UpdateServiceTxtParams,
+    ) -> Result<()> {
+        avahi_util::sys_exec(
+            || {
+                avahi_entry_group_update_service_txt_strlst(
+                    self.inner,
+                    interface,
+                    protocol,
+                    flags,
+                    name,
+                    kind,
+                    domain,
+                    txt.map(|t| t.inner()).unwrap_mut_or_null(),
+                )
+            },
+            "could not update TXT record",
+        )
+    }
+
     /// Delegate function for [`avahi_entry_group_commit()`].
     ///
     /// Also propagates any error returned into a `Result`.
@@ -164,6 +269,11 @@ impl ManagedAvahiEntryGroup {
     pub unsafe fn get_client(&self) -> *mut AvahiClient {
         avahi_sys::avahi_entry_group_get_client(self.inner)
     }
+
+    /// Returns true if this wraps `group`, e.g. the group passed to a callback.
+    pub fn is(&self, group: *mut AvahiEntryGroup) -> bool {
+        self.inner == group
+    }
 }
 
 impl Drop for ManagedAvahiEntryGroup {
@@ -172,6 +282,13 @@ impl Drop for ManagedAvahiEntryGroup {
     }
 }
 
+// SAFETY: Avahi objects are not tied to the thread that created them. Entry groups are owned by the
+// boxed context of a service or by an instance of a service group, neither of which is locked, so
+// the service and its event loop must not be used from two threads at once. Changes made from
+// other threads go through a `Deferrer` or the instance changes of the group instead, which the
+// event loop applies.
+unsafe impl Send for ManagedAvahiEntryGroup {}
+
 /// Holds parameters for initializing a new `ManagedAvahiEntryGroup` with
 /// `ManagedAvahiEntryGroup::new()`.
 ///
@@ -218,3 +335,50 @@ pub struct AddServiceSubtypeParams {
     domain: *const c_char,
     subtype: *const c_char,
 }
+
+/// Holds parameters for `ManagedAvahiEntryGroup::update_service_txt()`.
+///
+/// See [`avahi_entry_group_update_service_txt()`] for more information about these parameters.
+///
+/// [`avahi_entry_group_update_service_txt()`]: https://avahi.org/doxygen/html/publish_8h.html
+#[derive(Builder, BuilderDelegate)]
+pub struct UpdateServiceTxtParams<'a> {
+    interface: AvahiIfIndex,
+    protocol: AvahiProtocol,
+    flags: AvahiPublishFlags,
+    name: *const c_char,
+    kind: *const c_char,
+    domain: *const c_char,
+    txt: Option<&'a ManagedAvahiStringList>,
+}
+
+/// Holds parameters for `ManagedAvahiEntryGroup::add_record()`.
+///
+/// See [`avahi_entry_group_add_record()`] for more information about these parameters.
+///
+/// [`avahi_entry_group_add_record()`]: https://avahi.org/doxygen/html/publish_8h.html
+#[derive(Builder, BuilderDelegate)]
+pub struct AddRecordParams<'a> {
+    interface: AvahiIfIndex,
+    protocol: AvahiProtocol,
+    flags: AvahiPublishFlags,
+    name: *const c_char,
+    clazz: u16,
+    rr_type: u16,
+    ttl: u32,
+    rdata: &'a [u8],
+}
+
+/// Holds parameters for `ManagedAvahiEntryGroup::add_address()`.
+///
+/// See [`avahi_entry_group_add_address()`] for more information about these parameters.
+///
+/// [`avahi_entry_group_add_address()`]: https://avahi.org/doxygen/html/publish_8h.html
+#[derive(Builder, BuilderDelegate)]
+pub struct AddAddressParams<'a> {
+    interface: AvahiIfIndex,
+    protocol: AvahiProtocol,
+    flags: AvahiPublishFlags,
+    name: *const c_char,
+    address: &'a AvahiAddress,
+}
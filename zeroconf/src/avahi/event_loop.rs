@@ -1,24 +1,214 @@
-//! Event loop for running a `MdnsService` or `MdnsBrowser`.
+//! Event loop for running a `MdnsService`, `MdnsServiceGroup` or `MdnsBrowser`.
 
+use super::browser::ResolveTimeouts;
 use super::poll::ManagedAvahiSimplePoll;
-use crate::event_loop::TEventLoop;
-use crate::Result;
-use std::sync::Arc;
-use std::time::Duration;
+use super::service::DeferredRunner;
+use super::service_group::InstanceChangeRunner;
+use crate::error::{ErrorContext, Operation, ResultExt};
+use crate::event_loop::{ShutdownHandle, TEventLoop};
+use crate::settle::{self, SettleTracker};
+use crate::{ffi, Id, Result};
+use std::os::unix::io::RawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
 #[derive(new)]
 pub struct AvahiEventLoop {
     poll: Arc<ManagedAvahiSimplePoll>,
+    #[new(value = "Id::next()")]
+    id: Id,
+    #[new(default)]
+    settle: Option<Arc<Mutex<SettleTracker>>>,
+    #[new(default)]
+    deferred: Option<DeferredRunner>,
+    #[new(default)]
+    resolve_timeouts: Option<ResolveTimeouts>,
+    #[new(default)]
+    instance_changes: Option<InstanceChangeRunner>,
+    #[new(default)]
+    shutdown: Arc<AtomicBool>,
+}
+
+impl AvahiEventLoop {
+    /// Invokes the settled callback of `settle` from `poll()` once the browser has settled.
+    pub(crate) fn with_settle_tracker(mut self, settle: Arc<Mutex<SettleTracker>>) -> Self {
+        self.settle = Some(settle);
+        self
+    }
+
+    /// Executes the closures queued for a service from `poll()`, once the callbacks have returned.
+    pub(crate) fn with_deferred(mut self, deferred: DeferredRunner) -> Self {
+        self.deferred = Some(deferred);
+        self
+    }
+
+    /// Gives up on the resolutions of a browser that have timed out from `poll()`.
+    pub(crate) fn with_resolve_timeouts(mut self, resolve_timeouts: ResolveTimeouts) -> Self {
+        self.resolve_timeouts = Some(resolve_timeouts);
+        self
+    }
+
+    /// Adds and removes the instances of a service group from `poll()`.
+    pub(crate) fn with_instance_changes(mut self, instance_changes: InstanceChangeRunner) -> Self {
+        self.instance_changes = Some(instance_changes);
+        self
+    }
+
+    fn process_deferred(&self) {
+        // objects dropped from within their callbacks are freed first, so that nothing is
+        // deferred on their behalf
+        ffi::drop_dispatched();
+
+        if let Some(deferred) = &self.deferred {
+            unsafe { deferred.run() };
+        }
+
+        if let Some(instance_changes) = &self.instance_changes {
+            unsafe { instance_changes.run() };
+        }
+
+        if let Some(resolve_timeouts) = &self.resolve_timeouts {
+            unsafe { resolve_timeouts.run() };
+        }
+
+        if let Some(settle) = &self.settle {
+            settle::poll(settle, self.id);
+        }
+    }
 }
 
 impl TEventLoop for AvahiEventLoop {
+    fn id(&self) -> Id {
+        self.id
+    }
+
     /// Polls for new events.
     ///
-    /// Internally calls `ManagedAvahiSimplePoll::iterate(..)`.  
-    /// In systems where the C implementation of `poll(.., timeout)`
-    /// does not respect the `timeout` parameter, the `timeout` passed
-    /// here will have no effect -- ie will return immediately.
+    /// Internally calls `ManagedAvahiSimplePoll::iterate(..)` until a callback has been invoked
+    /// or `timeout` has elapsed. Avahi also wakes up for its own D-Bus traffic and timers, which
+    /// would otherwise make this return early without any events.
+    ///
+    /// Returns without waiting once the loop has been shut down through a `ShutdownHandle`, which
+    /// wakes up the `AvahiSimplePoll` to interrupt a call that is waiting.
     fn poll(&self, timeout: Duration) -> Result<()> {
-        unsafe { self.poll.iterate(timeout) }
+        let span = span!("poll", event_loop = %self.id);
+        let _entered = span.enter();
+
+        let start = Instant::now();
+        let deadline = start + timeout;
+        let dispatched = ffi::dispatched();
+
+        while !self.shutdown.load(Ordering::SeqCst) {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+
+            unsafe { self.poll.iterate(remaining) }
+                .with_context(|| ErrorContext::new(Operation::Poll))?;
+
+            if ffi::dispatched() != dispatched || Instant::now() >= deadline {
+                break;
+            }
+        }
+
+        trace!("[{}] Polled for {:?}", self.id, start.elapsed());
+
+        self.process_deferred();
+
+        Ok(())
+    }
+
+    fn process_events(&self) -> Result<()> {
+        unsafe { self.poll.process_events() }
+            .with_context(|| ErrorContext::new(Operation::Poll))?;
+
+        self.process_deferred();
+
+        Ok(())
+    }
+
+    fn as_raw_fd(&self) -> Option<RawFd> {
+        match self.poll.as_raw_fd() {
+            Ok(fd) => Some(fd),
+            Err(e) => {
+                error!("[{}] Could not create file descriptor: {}", self.id, e);
+                None
+            }
+        }
+    }
+
+    fn shutdown_handle(&self) -> ShutdownHandle {
+        let poll = self.poll.clone();
+        ShutdownHandle::new(self.shutdown.clone(), move || poll.wakeup())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{mem, ptr, thread};
+
+    fn event_loop() -> AvahiEventLoop {
+        AvahiEventLoop::new(Arc::new(unsafe { ManagedAvahiSimplePoll::new() }.unwrap()))
+    }
+
+    extern "C" fn ignore_signal(_: libc::c_int) {}
+
+    #[test]
+    fn try_poll_returns_immediately_when_idle() {
+        let event_loop = event_loop();
+
+        let start = Instant::now();
+
+        assert_eq!(event_loop.try_poll(), Ok(false));
+        assert!(start.elapsed() < Duration::from_millis(100));
+    }
+
+    #[test]
+    fn poll_until_continues_after_interrupt() {
+        let event_loop = event_loop();
+
+        // a handler installed without `SA_RESTART` makes the signal interrupt `poll()`
+        unsafe {
+            let mut action: libc::sigaction = mem::zeroed();
+            action.sa_sigaction = ignore_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            libc::sigaction(libc::SIGUSR1, &action, ptr::null_mut());
+        }
+
+        let polling_thread = unsafe { libc::pthread_self() };
+
+        let interrupter = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            unsafe { libc::pthread_kill(polling_thread, libc::SIGUSR1) };
+        });
+
+        let start = Instant::now();
+        let result = event_loop.poll_until(start + Duration::from_millis(300));
+
+        interrupter.join().unwrap();
+
+        assert_eq!(result, Ok(()));
+        assert!(start.elapsed() >= Duration::from_millis(300));
+    }
+
+    #[test]
+    fn shutdown_interrupts_poll() {
+        let event_loop = event_loop();
+        let handle = event_loop.shutdown_handle();
+
+        let shutdown = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            handle.shutdown();
+            Instant::now()
+        });
+
+        event_loop.poll(Duration::from_secs(10)).unwrap();
+        let requested = shutdown.join().unwrap();
+
+        assert!(requested.elapsed() < Duration::from_millis(100));
+
+        let start = Instant::now();
+        event_loop.poll(Duration::from_secs(10)).unwrap();
+
+        assert!(start.elapsed() < Duration::from_millis(100));
     }
 }
@@ -0,0 +1,93 @@
+//! Rust friendly `AvahiHostNameResolver` wrappers/helpers
+
+use std::sync::Arc;
+
+use crate::Result;
+use avahi_sys::{
+    avahi_host_name_resolver_free, avahi_host_name_resolver_new, AvahiHostNameResolver,
+    AvahiHostNameResolverCallback, AvahiIfIndex, AvahiLookupFlags, AvahiProtocol,
+};
+use libc::{c_char, c_void};
+
+use super::client::ManagedAvahiClient;
+
+/// Wraps the `AvahiHostNameResolver` type from the raw Avahi bindings.
+///
+/// This struct allocates a new `*mut AvahiHostNameResolver` when
+/// `ManagedAvahiHostNameResolver::new()` is invoked and calls the Avahi function responsible for
+/// freeing the resolver on `trait Drop`.
+#[derive(Debug)]
+pub struct ManagedAvahiHostNameResolver {
+    inner: *mut AvahiHostNameResolver,
+    _client: Arc<ManagedAvahiClient>,
+}
+
+impl ManagedAvahiHostNameResolver {
+    /// Initializes the underlying `*mut AvahiHostNameResolver` and verifies it was created;
+    /// returning `Err(String)` if unsuccessful.
+    ///
+    /// # Safety
+    /// This function is unsafe because of the raw pointer dereference.
+    pub unsafe fn new(
+        ManagedAvahiHostNameResolverParams {
+            client,
+            interface,
+            protocol,
+            name,
+            aprotocol,
+            flags,
+            callback,
+            userdata,
+        }: ManagedAvahiHostNameResolverParams,
+    ) -> Result<Self> {
+        let inner = avahi_host_name_resolver_new(
+            client.inner,
+            interface,
+            protocol,
+            name,
+            aprotocol,
+            flags,
+            callback,
+            userdata,
+        );
+
+        if inner.is_null() {
+            Err("could not initialize AvahiHostNameResolver".into())
+        } else {
+            Ok(Self {
+                inner,
+                _client: client,
+            })
+        }
+    }
+}
+
+impl Drop for ManagedAvahiHostNameResolver {
+    fn drop(&mut self) {
+        unsafe { avahi_host_name_resolver_free(self.inner) };
+    }
+}
+
+// SAFETY: Avahi objects are not tied to the thread that created them. The resolvers are owned by
+// the boxed context of an `AvahiMdnsHostResolver`, which is not locked: they are only touched by
+// its methods and by the callbacks dispatched while its event loop is polled, which must not
+// happen on two threads at once.
+unsafe impl Send for ManagedAvahiHostNameResolver {}
+
+/// Holds parameters for initializing a new `ManagedAvahiHostNameResolver` with
+/// `ManagedAvahiHostNameResolver::new()`.
+///
+/// See [`avahi_host_name_resolver_new()`] for more information about these parameters.
+///
+/// [`avahi_host_name_resolver_new()`]: https://avahi.org/doxygen/html/lookup_8h.html
+#[derive(Builder, BuilderDelegate)]
+pub struct ManagedAvahiHostNameResolverParams {
+    client: Arc<ManagedAvahiClient>,
+    interface: AvahiIfIndex,
+    protocol: AvahiProtocol,
+    name: *const c_char,
+    aprotocol: AvahiProtocol,
+    flags: AvahiLookupFlags,
+    callback: AvahiHostNameResolverCallback,
+    userdata: *mut c_void,
+}
@@ -0,0 +1,333 @@
+//! Avahi implementation for cross-platform host name resolver
+
+use super::avahi_util;
+use super::client::{ManagedAvahiClient, ManagedAvahiClientParams};
+use super::host_name_resolver::{ManagedAvahiHostNameResolver, ManagedAvahiHostNameResolverParams};
+use super::poll::ManagedAvahiSimplePoll;
+use crate::error::{Error, ErrorContext, Operation, ResultExt};
+use crate::ffi::{self, c_str, AsRaw, CallbackContext};
+use crate::prelude::*;
+use crate::{EventLoop, HostResolution, HostResolvedCallback, Id, NetworkInterface, Result};
+use avahi_sys::{
+    AvahiAddress, AvahiClient, AvahiClientFlags, AvahiClientState, AvahiHostNameResolver,
+    AvahiIfIndex, AvahiLookupResultFlags, AvahiProtocol, AvahiResolverEvent,
+};
+use libc::{c_char, c_void};
+use std::any::Any;
+use std::ffi::CString;
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub struct AvahiMdnsHostResolver {
+    context: Box<AvahiHostResolverContext>,
+    client: Option<Arc<ManagedAvahiClient>>,
+    poll: Option<Arc<ManagedAvahiSimplePoll>>,
+}
+
+impl TMdnsHostResolver for AvahiMdnsHostResolver {
+    fn new() -> Self {
+        Self {
+            client: None,
+            poll: None,
+            context: Box::new(AvahiHostResolverContext::new(avahi_sys::AVAHI_IF_UNSPEC)),
+        }
+    }
+
+    fn id(&self) -> Id {
+        self.context.id
+    }
+
+    fn set_network_interface(&mut self, interface: NetworkInterface) {
+        self.context.interface_index = avahi_util::interface_index(interface);
+    }
+
+    fn network_interface(&self) -> NetworkInterface {
+        avahi_util::interface_from_index(self.context.interface_index)
+    }
+
+    fn set_host_resolved_callback(&mut self, host_resolved_callback: Box<HostResolvedCallback>) {
+        self.context.host_resolved_callback = Some(host_resolved_callback);
+    }
+
+    fn set_context(&mut self, context: Box<dyn Any + Send + Sync>) {
+        self.context.user_context = Some(Arc::from(context));
+    }
+
+    fn context(&self) -> Option<&(dyn Any + Send + Sync)> {
+        self.context.user_context.as_ref().map(|c| c.as_ref())
+    }
+
+    fn resolve(&mut self, host_name: &str) -> Result<EventLoop> {
+        debug!(
+            "[{}] Resolving host `{}`: {:?}",
+            self.context.id, host_name, self
+        );
+
+        self.context.resolvers.clear();
+        self.context.host_name = Some(c_string!(host_name));
+
+        self.poll = Some(Arc::new(unsafe { ManagedAvahiSimplePoll::new() }?));
+
+        let poll = self
+            .poll
+            .as_ref()
+            .ok_or("could not get poll as ref")?
+            .clone();
+
+        let client_params = ManagedAvahiClientParams::builder()
+            .poll(poll)
+            .flags(AvahiClientFlags(0))
+            .callback(Some(client_callback))
+            .userdata(self.context.as_raw())
+            .build()?;
+
+        let client = unsafe { ManagedAvahiClient::new(client_params) }
+            .with_context(|| self.context.error_context())?;
+
+        self.client = Some(Arc::new(client));
+
+        self.context.client.clone_from(&self.client);
+
+        // Avahi only reports a single address per resolver, so one is created per protocol in
+        // order to surface both IPv4 and IPv6 addresses
+        for aprotocol in [avahi_sys::AVAHI_PROTO_INET, avahi_sys::AVAHI_PROTO_INET6] {
+            unsafe {
+                if let Err(e) = create_resolver(&mut self.context, aprotocol) {
+                    self.context.invoke_callback(Err(e));
+                }
+            }
+        }
+
+        let event_loop = EventLoop::new(
+            self.poll
+                .as_ref()
+                .ok_or("could not get poll as ref")?
+                .clone(),
+        );
+
+        debug!("[{}] Event loop: {}", self.context.id, event_loop.id());
+
+        Ok(event_loop)
+    }
+}
+
+#[derive(FromRaw, AsRaw)]
+struct AvahiHostResolverContext {
+    id: Id,
+    client: Option<Arc<ManagedAvahiClient>>,
+    resolvers: Vec<ManagedAvahiHostNameResolver>,
+    host_resolved_callback: Option<Box<HostResolvedCallback>>,
+    user_context: Option<Arc<dyn Any + Send + Sync>>,
+    interface_index: AvahiIfIndex,
+    host_name: Option<CString>,
+}
+
+impl AvahiHostResolverContext {
+    fn new(interface_index: AvahiIfIndex) -> Self {
+        Self {
+            id: Id::next(),
+            client: None,
+            resolvers: vec![],
+            host_resolved_callback: None,
+            user_context: None,
+            interface_index,
+            host_name: None,
+        }
+    }
+
+    fn invoke_callback(&self, result: Result<HostResolution>) {
+        let result = result.with_context(|| self.error_context());
+
+        if let Some(f) = &self.host_resolved_callback {
+            f(result, self.user_context.clone());
+        } else {
+            warn!(
+                "[{}] attempted to invoke host resolver callback but none was set",
+                self.id
+            );
+        }
+    }
+
+    fn error_context(&self) -> ErrorContext {
+        ErrorContext::new(Operation::GetAddrInfo)
+            .with_name(self.host_name.as_ref().map(c_str::to_str))
+    }
+}
+
+impl CallbackContext for AvahiHostResolverContext {
+    fn id(&self) -> Id {
+        self.id
+    }
+
+    fn invoke_error(&self, error: Error) {
+        self.invoke_callback(Err(error))
+    }
+}
+
+impl fmt::Debug for AvahiHostResolverContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AvahiHostResolverContext")
+            .field("id", &self.id)
+            .field("resolvers", &self.resolvers)
+            .field("host_name", &self.host_name)
+            .finish()
+    }
+}
+
+unsafe extern "C" fn client_callback(
+    client: *mut AvahiClient,
+    state: AvahiClientState,
+    userdata: *mut c_void,
+) {
+    ffi::guard(userdata, |context: &mut AvahiHostResolverContext| {
+        if state == avahi_sys::AvahiClientState_AVAHI_CLIENT_FAILURE {
+            context.invoke_callback(Err(avahi_util::get_last_error(client).into()));
+        }
+    })
+}
+
+unsafe fn create_resolver(
+    context: &mut AvahiHostResolverContext,
+    aprotocol: AvahiProtocol,
+) -> Result<()> {
+    let raw_context = context.as_raw();
+
+    let resolver = ManagedAvahiHostNameResolver::new(
+        ManagedAvahiHostNameResolverParams::builder()
+            .client(Arc::clone(
+                context
+                    .client
+                    .as_ref()
+                    .ok_or("could not get client as ref")?,
+            ))
+            .interface(context.interface_index)
+            .protocol(avahi_sys::AVAHI_PROTO_UNSPEC)
+            .name(
+                context
+                    .host_name
+                    .as_ref()
+                    .ok_or("expected host name")?
+                    .as_ptr(),
+            )
+            .aprotocol(aprotocol)
+            .flags(0)
+            .callback(Some(resolve_callback))
+            .userdata(raw_context)
+            .build()?,
+    )?;
+
+    context.resolvers.push(resolver);
+
+    Ok(())
+}
+
+unsafe extern "C" fn resolve_callback(
+    _resolver: *mut AvahiHostNameResolver,
+    interface: AvahiIfIndex,
+    _protocol: AvahiProtocol,
+    event: AvahiResolverEvent,
+    name: *const c_char,
+    addr: *const AvahiAddress,
+    _flags: AvahiLookupResultFlags,
+    userdata: *mut c_void,
+) {
+    ffi::guard(userdata, |context: &mut AvahiHostResolverContext| {
+        match event {
+            avahi_sys::AvahiResolverEvent_AVAHI_RESOLVER_FAILURE => {
+                context.invoke_callback(Err(format!(
+                    "failed to resolve host `{}`",
+                    c_str::raw_to_str_lossy(name)
+                )
+                .into()));
+            }
+            avahi_sys::AvahiResolverEvent_AVAHI_RESOLVER_FOUND => {
+                context.invoke_callback(handle_resolver_found(context, name, addr, interface));
+            }
+            _ => {}
+        };
+    })
+}
+
+unsafe fn handle_resolver_found(
+    context: &AvahiHostResolverContext,
+    name: *const c_char,
+    addr: *const AvahiAddress,
+    interface: AvahiIfIndex,
+) -> Result<HostResolution> {
+    let name = c_str::try_raw_to_str(name, "host name")?;
+
+    ensure_not_null!(addr, "host address");
+    let address = avahi_util::avahi_address_to_string(addr);
+
+    let result = HostResolution::builder()
+        .host_name(name.to_string())
+        .address(
+            address
+                .parse::<IpAddr>()
+                .map_err(|_| format!("could not parse address `{}`", address))?,
+        )
+        .interface(avahi_util::interface_from_index(interface))
+        .build()?;
+
+    debug!("[{}] Host resolved: {:?}", context.id, result);
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr;
+    use std::sync::Mutex;
+
+    #[test]
+    fn resolve_callback_with_null_arguments_is_reported() {
+        let results: Arc<Mutex<Vec<Result<HostResolution>>>> = Arc::default();
+        let sink = results.clone();
+        let name = c_string!("foo.local");
+
+        let mut context = AvahiHostResolverContext::new(avahi_sys::AVAHI_IF_UNSPEC);
+        context.host_resolved_callback =
+            Some(Box::new(move |result, _| sink.lock().unwrap().push(result)));
+
+        for (event, name, expected) in [
+            (
+                avahi_sys::AvahiResolverEvent_AVAHI_RESOLVER_FAILURE,
+                ptr::null(),
+                "failed to resolve host `<null>`",
+            ),
+            (
+                avahi_sys::AvahiResolverEvent_AVAHI_RESOLVER_FOUND,
+                ptr::null(),
+                "missing host name",
+            ),
+            (
+                avahi_sys::AvahiResolverEvent_AVAHI_RESOLVER_FOUND,
+                name.as_ptr(),
+                "missing host address",
+            ),
+        ] {
+            unsafe {
+                resolve_callback(
+                    ptr::null_mut(),
+                    avahi_sys::AVAHI_IF_UNSPEC,
+                    avahi_sys::AVAHI_PROTO_UNSPEC,
+                    event,
+                    name,
+                    ptr::null(),
+                    0,
+                    context.as_raw(),
+                )
+            };
+
+            assert_eq!(
+                results.lock().unwrap().pop(),
+                Some(Err(
+                    Error::from(expected).with_context(ErrorContext::new(Operation::GetAddrInfo))
+                ))
+            );
+        }
+    }
+}
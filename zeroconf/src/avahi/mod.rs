@@ -9,11 +9,21 @@
 pub mod avahi_util;
 pub mod browser;
 pub mod client;
+pub mod domain_browser;
 pub mod entry_group;
 pub mod event_loop;
+pub mod host_name_resolver;
+pub mod host_resolver;
 pub mod poll;
 pub mod raw_browser;
+pub mod raw_domain_browser;
+pub mod raw_record_browser;
+pub mod reactor;
+pub mod record_browser;
 pub mod resolver;
 pub mod service;
+pub mod service_group;
+pub mod service_resolver;
 pub mod string_list;
 pub mod txt_record;
+pub mod type_browser;
@@ -1,11 +1,15 @@
 //! Rust friendly `AvahiSimplePoll` wrappers/helpers
 
+use super::reactor::{self, PollReactor};
 use crate::Result;
 use crate::{avahi::avahi_util, error::Error};
 use avahi_sys::{
     avahi_simple_poll_free, avahi_simple_poll_iterate, avahi_simple_poll_loop,
-    avahi_simple_poll_new, AvahiSimplePoll,
+    avahi_simple_poll_new, avahi_simple_poll_set_func, avahi_simple_poll_wakeup, AvahiSimplePoll,
 };
+use libc::c_void;
+use std::os::unix::io::RawFd;
+use std::sync::Mutex;
 use std::{convert::TryInto, time::Duration};
 
 /// Wraps the `AvahiSimplePoll` type from the raw Avahi bindings.
@@ -13,7 +17,10 @@ use std::{convert::TryInto, time::Duration};
 /// This struct allocates a new `*mut AvahiSimplePoll` when `ManagedAvahiClient::new()` is invoked
 /// and calls the Avahi function responsible for freeing the poll on `trait Drop`.
 #[derive(Debug)]
-pub struct ManagedAvahiSimplePoll(*mut AvahiSimplePoll);
+pub struct ManagedAvahiSimplePoll {
+    inner: *mut AvahiSimplePoll,
+    reactor: Mutex<Option<Box<PollReactor>>>,
+}
 
 impl ManagedAvahiSimplePoll {
     /// Initializes the underlying `*mut AvahiSimplePoll` and verifies it was created; returning
@@ -26,7 +33,10 @@ impl ManagedAvahiSimplePoll {
         if poll.is_null() {
             Err("could not initialize AvahiSimplePoll".into())
         } else {
-            Ok(Self(poll))
+            Ok(Self {
+                inner: poll,
+                reactor: Mutex::default(),
+            })
         }
     }
 
@@ -38,7 +48,7 @@ impl ManagedAvahiSimplePoll {
     /// This function is unsafe because of the call to `avahi_simple_poll_loop()`.
     pub unsafe fn start_loop(&self) -> Result<()> {
         avahi_util::sys_exec(
-            || avahi_simple_poll_loop(self.0),
+            || avahi_simple_poll_loop(self.inner),
             "could not start AvahiSimplePoll",
         )
     }
@@ -55,8 +65,83 @@ impl ManagedAvahiSimplePoll {
             .try_into() // `avahi_simple_poll_iterate()` expects `sleep_time` as an i32.
             .unwrap_or(i32::MAX); // if converting to an i32 overflows, just use the largest number we can.
 
+        self.iterate_millis(sleep_time)
+    }
+
+    /// Dispatches the events that are ready without waiting for new ones.
+    ///
+    /// Once [`as_raw_fd()`] has been called, this also updates the file descriptor with the
+    /// descriptors and timeouts Avahi waits for next.
+    ///
+    /// [`as_raw_fd()`]: #method.as_raw_fd
+    ///
+    /// # Safety
+    /// This function is unsafe because of the call to `avahi_simple_poll_iterate()`.
+    pub unsafe fn process_events(&self) -> Result<()> {
+        let reactor = self
+            .reactor
+            .lock()
+            .expect("should have been able to obtain lock on reactor");
+
+        match reactor.as_ref() {
+            Some(reactor) => {
+                reactor.clear_timer();
+                reactor.set_non_blocking(true);
+
+                // the poll function returns immediately, but is passed the actual timeout
+                let result = self.iterate_millis(-1);
+
+                reactor.set_non_blocking(false);
+                result
+            }
+            None => self.iterate_millis(0),
+        }
+    }
+
+    /// Returns a file descriptor that becomes readable whenever there are events to dispatch with
+    /// [`process_events()`], for integrating the poll into an external event loop.
+    ///
+    /// The first call replaces the poll function of the `AvahiSimplePoll` with one that keeps the
+    /// descriptor up to date; it must not be called while the poll is being iterated.
+    ///
+    /// [`process_events()`]: #method.process_events
+    pub fn as_raw_fd(&self) -> Result<RawFd> {
+        let mut reactor = self
+            .reactor
+            .lock()
+            .expect("should have been able to obtain lock on reactor");
+
+        if let Some(reactor) = reactor.as_ref() {
+            return Ok(reactor.fd());
+        }
+
+        let new_reactor = Box::new(PollReactor::new()?);
+        let fd = new_reactor.fd();
+
+        unsafe {
+            avahi_simple_poll_set_func(
+                self.inner,
+                Some(reactor::poll_func),
+                &*new_reactor as *const PollReactor as *mut c_void,
+            )
+        };
+
+        *reactor = Some(new_reactor);
+
+        Ok(fd)
+    }
+
+    /// Delegate function for [`avahi_simple_poll_wakeup()`], which interrupts an `iterate()` that
+    /// is waiting for events. Unlike the other functions, it may be called from any thread.
+    ///
+    /// [`avahi_simple_poll_wakeup()`]: https://avahi.org/doxygen/html/simple-watch_8h.html
+    pub fn wakeup(&self) {
+        unsafe { avahi_simple_poll_wakeup(self.inner) };
+    }
+
+    unsafe fn iterate_millis(&self, sleep_time: i32) -> Result<()> {
         // Returns -1 on error, 0 on success and 1 if a quit request has been scheduled
-        match avahi_simple_poll_iterate(self.0, sleep_time) {
+        match avahi_simple_poll_iterate(self.inner, sleep_time) {
             0 | 1 => Ok(()),
             -1 => Err(Error::from(
                 "avahi_simple_poll_iterate(..) threw an error result",
@@ -68,15 +153,58 @@ impl ManagedAvahiSimplePoll {
     }
 
     pub(super) fn inner(&self) -> *mut AvahiSimplePoll {
-        self.0
+        self.inner
     }
 }
 
 impl Drop for ManagedAvahiSimplePoll {
     fn drop(&mut self) {
-        unsafe { avahi_simple_poll_free(self.0) };
+        // frees the poll before the reactor it may still refer to
+        unsafe { avahi_simple_poll_free(self.inner) };
     }
 }
 
+// SAFETY: the poll is shared through an `Arc` by the clients using it and their event loop.
+// `wakeup()` is the only function Avahi allows from any thread; the others are `unsafe` and left
+// to the event loop, which must not be polled from two threads at once. The reactor is behind
+// a `Mutex`.
 unsafe impl Send for ManagedAvahiSimplePoll {}
 unsafe impl Sync for ManagedAvahiSimplePoll {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    fn process_events_does_not_wait() {
+        let poll = unsafe { ManagedAvahiSimplePoll::new() }.unwrap();
+
+        poll.as_raw_fd().unwrap();
+
+        let start = Instant::now();
+        unsafe { poll.process_events() }.unwrap();
+
+        assert!(start.elapsed() < Duration::from_millis(100));
+    }
+
+    #[test]
+    fn raw_fd_is_readable_after_wakeup() {
+        let poll = unsafe { ManagedAvahiSimplePoll::new() }.unwrap();
+        let fd = poll.as_raw_fd().unwrap();
+
+        assert_eq!(poll.as_raw_fd().unwrap(), fd);
+
+        // registers the descriptors of the poll with the reactor
+        unsafe { poll.process_events() }.unwrap();
+        poll.wakeup();
+
+        let mut fds = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+
+        assert_eq!(unsafe { libc::poll(&mut fds, 1, 1000) }, 1);
+    }
+}
@@ -77,6 +77,12 @@ impl Drop for ManagedAvahiServiceBrowser {
     }
 }
 
+// SAFETY: Avahi objects are not tied to the thread that created them. The browser belongs to an
+// `AvahiBrowserContext`, whose `Mutex` is held whenever the browser is created or freed, including
+// by the `browse_callback` it invokes. Browsers replaced from within that callback are only freed
+// once it has returned.
+unsafe impl Send for ManagedAvahiServiceBrowser {}
+
 /// Holds parameters for initializing a new `ManagedAvahiServiceBrowser` with
 /// `ManagedAvahiServiceBrowser::new()`.
 ///
@@ -0,0 +1,92 @@
+//! Rust friendly `AvahiDomainBrowser` wrappers/helpers
+
+use std::sync::Arc;
+
+use crate::Result;
+use avahi_sys::{
+    avahi_domain_browser_free, avahi_domain_browser_new, AvahiDomainBrowser,
+    AvahiDomainBrowserCallback, AvahiDomainBrowserType, AvahiIfIndex, AvahiLookupFlags,
+    AvahiProtocol,
+};
+use libc::{c_char, c_void};
+
+use super::client::ManagedAvahiClient;
+
+/// Wraps the `AvahiDomainBrowser` type from the raw Avahi bindings.
+///
+/// This struct allocates a new `*mut AvahiDomainBrowser` when `ManagedAvahiDomainBrowser::new()`
+/// is invoked and calls the Avahi function responsible for freeing the browser on `trait Drop`.
+#[derive(Debug)]
+pub struct ManagedAvahiDomainBrowser {
+    inner: *mut AvahiDomainBrowser,
+    _client: Arc<ManagedAvahiClient>,
+}
+
+impl ManagedAvahiDomainBrowser {
+    /// Initializes the underlying `*mut AvahiDomainBrowser` and verifies it was created;
+    /// returning `Err(String)` if unsuccessful.
+    ///
+    /// # Safety
+    /// This function is unsafe because of the raw pointer dereference.
+    pub unsafe fn new(
+        ManagedAvahiDomainBrowserParams {
+            client,
+            interface,
+            protocol,
+            domain,
+            btype,
+            flags,
+            callback,
+            userdata,
+        }: ManagedAvahiDomainBrowserParams,
+    ) -> Result<Self> {
+        let inner = avahi_domain_browser_new(
+            client.inner,
+            interface,
+            protocol,
+            domain,
+            btype,
+            flags,
+            callback,
+            userdata,
+        );
+
+        if inner.is_null() {
+            Err("could not initialize Avahi domain browser".into())
+        } else {
+            Ok(Self {
+                inner,
+                _client: client,
+            })
+        }
+    }
+}
+
+impl Drop for ManagedAvahiDomainBrowser {
+    fn drop(&mut self) {
+        unsafe { avahi_domain_browser_free(self.inner) };
+    }
+}
+
+// SAFETY: Avahi objects are not tied to the thread that created them. The browser is owned by the
+// unlocked context of an `AvahiMdnsDomainBrowser`, so moving it along with that context is sound
+// as long as the domain browser and its event loop are not used from two threads at once.
+unsafe impl Send for ManagedAvahiDomainBrowser {}
+
+/// Holds parameters for initializing a new `ManagedAvahiDomainBrowser` with
+/// `ManagedAvahiDomainBrowser::new()`.
+///
+/// See [`avahi_domain_browser_new()`] for more information about these parameters.
+///
+/// [`avahi_domain_browser_new()`]: https://avahi.org/doxygen/html/lookup_8h.html
+#[derive(Builder, BuilderDelegate)]
+pub struct ManagedAvahiDomainBrowserParams {
+    client: Arc<ManagedAvahiClient>,
+    interface: AvahiIfIndex,
+    protocol: AvahiProtocol,
+    domain: *const c_char,
+    btype: AvahiDomainBrowserType,
+    flags: AvahiLookupFlags,
+    callback: AvahiDomainBrowserCallback,
+    userdata: *mut c_void,
+}
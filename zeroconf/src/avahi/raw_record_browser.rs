@@ -0,0 +1,94 @@
+//! Rust friendly `AvahiRecordBrowser` wrappers/helpers
+
+use std::sync::Arc;
+
+use crate::Result;
+use avahi_sys::{
+    avahi_record_browser_free, avahi_record_browser_new, AvahiIfIndex, AvahiLookupFlags,
+    AvahiProtocol, AvahiRecordBrowser, AvahiRecordBrowserCallback,
+};
+use libc::{c_char, c_void};
+
+use super::client::ManagedAvahiClient;
+
+/// Wraps the `AvahiRecordBrowser` type from the raw Avahi bindings.
+///
+/// This struct allocates a new `*mut AvahiRecordBrowser` when `ManagedAvahiRecordBrowser::new()`
+/// is invoked and calls the Avahi function responsible for freeing the browser on `trait Drop`.
+#[derive(Debug)]
+pub struct ManagedAvahiRecordBrowser {
+    inner: *mut AvahiRecordBrowser,
+    _client: Arc<ManagedAvahiClient>,
+}
+
+impl ManagedAvahiRecordBrowser {
+    /// Initializes the underlying `*mut AvahiRecordBrowser` and verifies it was created;
+    /// returning `Err(String)` if unsuccessful.
+    ///
+    /// # Safety
+    /// This function is unsafe because of the raw pointer dereference.
+    pub unsafe fn new(
+        ManagedAvahiRecordBrowserParams {
+            client,
+            interface,
+            protocol,
+            name,
+            clazz,
+            kind,
+            flags,
+            callback,
+            userdata,
+        }: ManagedAvahiRecordBrowserParams,
+    ) -> Result<Self> {
+        let inner = avahi_record_browser_new(
+            client.inner,
+            interface,
+            protocol,
+            name,
+            clazz,
+            kind,
+            flags,
+            callback,
+            userdata,
+        );
+
+        if inner.is_null() {
+            Err("could not initialize Avahi record browser".into())
+        } else {
+            Ok(Self {
+                inner,
+                _client: client,
+            })
+        }
+    }
+}
+
+impl Drop for ManagedAvahiRecordBrowser {
+    fn drop(&mut self) {
+        unsafe { avahi_record_browser_free(self.inner) };
+    }
+}
+
+// SAFETY: Avahi objects are not tied to the thread that created them. Only the unlocked context
+// of an `AvahiMdnsRecordBrowser` holds the browser, which it frees on drop or when browsing is
+// restarted; the record browser and its event loop must not be used from two threads at once.
+unsafe impl Send for ManagedAvahiRecordBrowser {}
+
+/// Holds parameters for initializing a new `ManagedAvahiRecordBrowser` with
+/// `ManagedAvahiRecordBrowser::new()`.
+///
+/// See [`avahi_record_browser_new()`] for more information about these parameters.
+///
+/// [`avahi_record_browser_new()`]: https://avahi.org/doxygen/html/lookup_8h.html
+#[derive(Builder, BuilderDelegate)]
+pub struct ManagedAvahiRecordBrowserParams {
+    client: Arc<ManagedAvahiClient>,
+    interface: AvahiIfIndex,
+    protocol: AvahiProtocol,
+    name: *const c_char,
+    clazz: u16,
+    kind: u16,
+    flags: AvahiLookupFlags,
+    callback: AvahiRecordBrowserCallback,
+    userdata: *mut c_void,
+}
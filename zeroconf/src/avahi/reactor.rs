@@ -0,0 +1,240 @@
+//! Integration of an `AvahiSimplePoll` with external event loops
+
+use crate::Result;
+use avahi_sys::pollfd;
+use libc::{c_int, c_short, c_uint, c_void};
+use std::collections::HashSet;
+use std::io;
+use std::os::unix::io::RawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::{ptr, slice};
+
+/// Mirrors the file descriptors watched by an `AvahiSimplePoll` into an epoll set, along with a
+/// timer for the next Avahi timeout, so that a single file descriptor becomes readable whenever
+/// the poll has something to dispatch.
+///
+/// The set is updated by [`poll_func()`] on every iteration of the poll, right before it waits.
+///
+/// [`poll_func()`]: fn.poll_func.html
+#[derive(Debug)]
+pub struct PollReactor {
+    epoll: RawFd,
+    timer: RawFd,
+    watched: Mutex<HashSet<RawFd>>,
+    non_blocking: AtomicBool,
+}
+
+impl PollReactor {
+    /// Creates the epoll set and the timer; returning `Err` if either could not be created.
+    pub fn new() -> Result<Self> {
+        let epoll = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+
+        if epoll < 0 {
+            return Err(os_error("epoll_create1()"));
+        }
+
+        let timer = unsafe {
+            libc::timerfd_create(
+                libc::CLOCK_MONOTONIC,
+                libc::TFD_NONBLOCK | libc::TFD_CLOEXEC,
+            )
+        };
+
+        if timer < 0 {
+            let error = os_error("timerfd_create()");
+            unsafe { libc::close(epoll) };
+            return Err(error);
+        }
+
+        let reactor = Self {
+            epoll,
+            timer,
+            watched: Mutex::default(),
+            non_blocking: AtomicBool::new(false),
+        };
+
+        if reactor.control(libc::EPOLL_CTL_ADD, timer, libc::EPOLLIN as u32) < 0 {
+            return Err(os_error("epoll_ctl()"));
+        }
+
+        Ok(reactor)
+    }
+
+    /// Returns the epoll file descriptor.
+    pub fn fd(&self) -> RawFd {
+        self.epoll
+    }
+
+    /// Sets whether [`poll_func()`] should return immediately instead of waiting for the timeout
+    /// prepared by Avahi.
+    ///
+    /// [`poll_func()`]: fn.poll_func.html
+    pub fn set_non_blocking(&self, non_blocking: bool) {
+        self.non_blocking.store(non_blocking, Ordering::SeqCst);
+    }
+
+    /// Consumes an expiration of the timer, so that it no longer makes the epoll set readable.
+    pub fn clear_timer(&self) {
+        let mut expirations = 0u64;
+
+        unsafe {
+            libc::read(
+                self.timer,
+                &mut expirations as *mut u64 as *mut c_void,
+                std::mem::size_of::<u64>(),
+            )
+        };
+    }
+
+    fn watch(&self, fds: &[pollfd]) {
+        let mut watched = self.watched.lock().unwrap_or_else(PoisonError::into_inner);
+        let current: HashSet<RawFd> = fds.iter().map(|f| f.fd).filter(|fd| *fd >= 0).collect();
+
+        // descriptors that have been closed in the meantime are already gone from the set
+        for fd in watched.difference(&current) {
+            self.control(libc::EPOLL_CTL_DEL, *fd, 0);
+        }
+
+        for f in fds.iter().filter(|f| f.fd >= 0) {
+            let events = epoll_events(f.events);
+
+            if self.control(libc::EPOLL_CTL_MOD, f.fd, events) < 0
+                && self.control(libc::EPOLL_CTL_ADD, f.fd, events) < 0
+            {
+                warn!(
+                    "Could not watch file descriptor {}: {}",
+                    f.fd,
+                    io::Error::last_os_error()
+                );
+            }
+        }
+
+        *watched = current;
+    }
+
+    /// Arms the timer to expire after `timeout` milliseconds, or disarms it if `timeout` is
+    /// negative.
+    fn arm_timer(&self, timeout: c_int) {
+        let mut spec: libc::itimerspec = unsafe { std::mem::zeroed() };
+
+        if timeout >= 0 {
+            spec.it_value.tv_sec = (timeout / 1000) as libc::time_t;
+            // a zero value would disarm the timer
+            spec.it_value.tv_nsec = ((timeout % 1000) as libc::c_long * 1_000_000).max(1);
+        }
+
+        unsafe { libc::timerfd_settime(self.timer, 0, &spec, ptr::null_mut()) };
+    }
+
+    fn control(&self, op: c_int, fd: RawFd, events: u32) -> c_int {
+        let mut event = libc::epoll_event {
+            events,
+            u64: fd as u64,
+        };
+
+        unsafe { libc::epoll_ctl(self.epoll, op, fd, &mut event) }
+    }
+}
+
+impl Drop for PollReactor {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.timer);
+            libc::close(self.epoll);
+        }
+    }
+}
+
+/// Poll function passed to `avahi_simple_poll_set_func()`, with a `PollReactor` as `userdata`.
+///
+/// Updates the reactor with the file descriptors and the timeout prepared by Avahi before
+/// delegating to `poll()`.
+///
+/// # Safety
+/// This function is unsafe because of the raw pointer dereferences.
+pub unsafe extern "C" fn poll_func(
+    ufds: *mut pollfd,
+    nfds: c_uint,
+    timeout: c_int,
+    userdata: *mut c_void,
+) -> c_int {
+    let reactor = &*(userdata as *const PollReactor);
+
+    let fds = if ufds.is_null() {
+        &[][..]
+    } else {
+        slice::from_raw_parts(ufds, nfds as usize)
+    };
+
+    reactor.watch(fds);
+    reactor.arm_timer(timeout);
+
+    let timeout = if reactor.non_blocking.load(Ordering::SeqCst) {
+        0
+    } else {
+        timeout
+    };
+
+    libc::poll(ufds as *mut libc::pollfd, nfds as libc::nfds_t, timeout)
+}
+
+fn epoll_events(events: c_short) -> u32 {
+    let mut result = 0;
+
+    if events & libc::POLLIN != 0 {
+        result |= libc::EPOLLIN;
+    }
+
+    if events & libc::POLLOUT != 0 {
+        result |= libc::EPOLLOUT;
+    }
+
+    if events & libc::POLLPRI != 0 {
+        result |= libc::EPOLLPRI;
+    }
+
+    result as u32
+}
+
+fn os_error(function: &str) -> crate::error::Error {
+    format!("{} failed: {}", function, io::Error::last_os_error()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn epoll_events_maps_poll_events() {
+        assert_eq!(epoll_events(libc::POLLIN), libc::EPOLLIN as u32);
+        assert_eq!(
+            epoll_events(libc::POLLIN | libc::POLLOUT),
+            (libc::EPOLLIN | libc::EPOLLOUT) as u32
+        );
+        assert_eq!(epoll_events(0), 0);
+    }
+
+    #[test]
+    fn expired_timer_makes_reactor_readable_until_cleared() {
+        let reactor = PollReactor::new().unwrap();
+
+        reactor.arm_timer(0);
+
+        assert!(readable(reactor.fd(), 1000));
+
+        reactor.clear_timer();
+
+        assert!(!readable(reactor.fd(), 0));
+    }
+
+    fn readable(fd: RawFd, timeout: c_int) -> bool {
+        let mut fds = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+
+        unsafe { libc::poll(&mut fds, 1, timeout) > 0 }
+    }
+}
@@ -0,0 +1,319 @@
+//! Avahi implementation for cross-platform record browser
+
+use super::avahi_util;
+use super::client::{ManagedAvahiClient, ManagedAvahiClientParams};
+use super::poll::ManagedAvahiSimplePoll;
+use super::raw_record_browser::{ManagedAvahiRecordBrowser, ManagedAvahiRecordBrowserParams};
+use crate::error::Error;
+use crate::ffi::{self, c_str, AsRaw, CallbackContext};
+use crate::prelude::*;
+use crate::{
+    EventLoop, Id, NetworkInterface, QueriedRecord, RecordEvent, RecordEventCallback, Result,
+};
+use avahi_sys::{
+    AvahiBrowserEvent, AvahiClient, AvahiClientFlags, AvahiClientState, AvahiIfIndex,
+    AvahiLookupResultFlags, AvahiProtocol, AvahiRecordBrowser,
+};
+use libc::{c_char, c_void};
+use std::any::Any;
+use std::ffi::CString;
+use std::fmt;
+use std::sync::Arc;
+
+#[derive(Debug)]
+pub struct AvahiMdnsRecordBrowser {
+    context: Box<AvahiRecordBrowserContext>,
+    client: Option<Arc<ManagedAvahiClient>>,
+    poll: Option<Arc<ManagedAvahiSimplePoll>>,
+}
+
+impl TMdnsRecordBrowser for AvahiMdnsRecordBrowser {
+    fn new() -> Self {
+        Self {
+            client: None,
+            poll: None,
+            context: Box::new(AvahiRecordBrowserContext::new(avahi_sys::AVAHI_IF_UNSPEC)),
+        }
+    }
+
+    fn id(&self) -> Id {
+        self.context.id
+    }
+
+    fn set_network_interface(&mut self, interface: NetworkInterface) {
+        self.context.interface_index = avahi_util::interface_index(interface);
+    }
+
+    fn network_interface(&self) -> NetworkInterface {
+        avahi_util::interface_from_index(self.context.interface_index)
+    }
+
+    fn set_record_event_callback(&mut self, record_event_callback: Box<RecordEventCallback>) {
+        self.context.record_event_callback = Some(record_event_callback);
+    }
+
+    fn set_context(&mut self, context: Box<dyn Any + Send + Sync>) {
+        self.context.user_context = Some(Arc::from(context));
+    }
+
+    fn context(&self) -> Option<&(dyn Any + Send + Sync)> {
+        self.context.user_context.as_ref().map(|c| c.as_ref())
+    }
+
+    fn query_record(&mut self, name: &str, rr_type: u16, rr_class: u16) -> Result<EventLoop> {
+        debug!(
+            "[{}] Querying record `{}` (type: {}, class: {}): {:?}",
+            self.context.id, name, rr_type, rr_class, self
+        );
+
+        self.context.browser = None;
+        self.context.name = Some(c_string!(name));
+
+        self.poll = Some(Arc::new(unsafe { ManagedAvahiSimplePoll::new() }?));
+
+        let poll = self
+            .poll
+            .as_ref()
+            .ok_or("could not get poll as ref")?
+            .clone();
+
+        let client_params = ManagedAvahiClientParams::builder()
+            .poll(poll)
+            .flags(AvahiClientFlags(0))
+            .callback(Some(client_callback))
+            .userdata(self.context.as_raw())
+            .build()?;
+
+        self.client = Some(Arc::new(unsafe { ManagedAvahiClient::new(client_params) }?));
+
+        self.context.client.clone_from(&self.client);
+
+        unsafe { create_browser(&mut self.context, rr_type, rr_class) }?;
+
+        let event_loop = EventLoop::new(
+            self.poll
+                .as_ref()
+                .ok_or("could not get poll as ref")?
+                .clone(),
+        );
+
+        debug!("[{}] Event loop: {}", self.context.id, event_loop.id());
+
+        Ok(event_loop)
+    }
+}
+
+#[derive(FromRaw, AsRaw)]
+struct AvahiRecordBrowserContext {
+    id: Id,
+    client: Option<Arc<ManagedAvahiClient>>,
+    browser: Option<ManagedAvahiRecordBrowser>,
+    record_event_callback: Option<Box<RecordEventCallback>>,
+    user_context: Option<Arc<dyn Any + Send + Sync>>,
+    interface_index: AvahiIfIndex,
+    name: Option<CString>,
+}
+
+impl AvahiRecordBrowserContext {
+    fn new(interface_index: AvahiIfIndex) -> Self {
+        Self {
+            id: Id::next(),
+            client: None,
+            browser: None,
+            record_event_callback: None,
+            user_context: None,
+            interface_index,
+            name: None,
+        }
+    }
+
+    fn invoke_callback(&self, result: Result<RecordEvent>) {
+        if let Some(f) = &self.record_event_callback {
+            f(result, self.user_context.clone());
+        } else {
+            warn!(
+                "[{}] attempted to invoke record browser callback but none was set",
+                self.id
+            );
+        }
+    }
+}
+
+impl CallbackContext for AvahiRecordBrowserContext {
+    fn id(&self) -> Id {
+        self.id
+    }
+
+    fn invoke_error(&self, error: Error) {
+        self.invoke_callback(Err(error))
+    }
+}
+
+impl fmt::Debug for AvahiRecordBrowserContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AvahiRecordBrowserContext")
+            .field("id", &self.id)
+            .field("browser", &self.browser)
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+unsafe extern "C" fn client_callback(
+    client: *mut AvahiClient,
+    state: AvahiClientState,
+    userdata: *mut c_void,
+) {
+    ffi::guard(userdata, |context: &mut AvahiRecordBrowserContext| {
+        if state == avahi_sys::AvahiClientState_AVAHI_CLIENT_FAILURE {
+            context.invoke_callback(Err(avahi_util::get_last_error(client).into()));
+        }
+    })
+}
+
+unsafe fn create_browser(
+    context: &mut AvahiRecordBrowserContext,
+    rr_type: u16,
+    rr_class: u16,
+) -> Result<()> {
+    context.browser = Some(ManagedAvahiRecordBrowser::new(
+        ManagedAvahiRecordBrowserParams::builder()
+            .client(Arc::clone(
+                context
+                    .client
+                    .as_ref()
+                    .ok_or("could not get client as ref")?,
+            ))
+            .interface(context.interface_index)
+            .protocol(avahi_sys::AVAHI_PROTO_UNSPEC)
+            .name(
+                context
+                    .name
+                    .as_ref()
+                    .ok_or("expected record name")?
+                    .as_ptr(),
+            )
+            .clazz(rr_class)
+            .kind(rr_type)
+            .flags(0)
+            .callback(Some(browse_callback))
+            .userdata(context.as_raw())
+            .build()?,
+    )?);
+
+    Ok(())
+}
+
+unsafe extern "C" fn browse_callback(
+    _browser: *mut AvahiRecordBrowser,
+    interface: AvahiIfIndex,
+    _protocol: AvahiProtocol,
+    event: AvahiBrowserEvent,
+    name: *const c_char,
+    clazz: u16,
+    kind: u16,
+    rdata: *const c_void,
+    size: usize,
+    _flags: AvahiLookupResultFlags,
+    userdata: *mut c_void,
+) {
+    ffi::guard(userdata, |context: &mut AvahiRecordBrowserContext| {
+        match event {
+            avahi_sys::AvahiBrowserEvent_AVAHI_BROWSER_NEW => context.invoke_callback(
+                handle_record(name, clazz, kind, rdata, size, interface).map(RecordEvent::Add),
+            ),
+            avahi_sys::AvahiBrowserEvent_AVAHI_BROWSER_REMOVE => context.invoke_callback(
+                handle_record(name, clazz, kind, rdata, size, interface).map(RecordEvent::Remove),
+            ),
+            avahi_sys::AvahiBrowserEvent_AVAHI_BROWSER_FAILURE => {
+                context.invoke_callback(Err("record browser failure".into()))
+            }
+            _ => {}
+        };
+    })
+}
+
+unsafe fn handle_record(
+    name: *const c_char,
+    clazz: u16,
+    kind: u16,
+    rdata: *const c_void,
+    size: usize,
+    interface: AvahiIfIndex,
+) -> Result<QueriedRecord> {
+    Ok(QueriedRecord::builder()
+        .name(c_str::try_raw_to_str(name, "record name")?.to_string())
+        .rr_type(kind)
+        .rr_class(clazz)
+        .rdata(ffi::raw_to_bytes(rdata, size, "record data")?)
+        .ttl(None)
+        .interface(avahi_util::interface_from_index(interface))
+        .build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr;
+    use std::sync::Mutex;
+
+    #[test]
+    fn browse_callback_reports_record_events() {
+        let results: Arc<Mutex<Vec<Result<RecordEvent>>>> = Arc::default();
+        let sink = results.clone();
+        let name = c_string!("foo._http._tcp.local");
+        let rdata = b"\x03a=1";
+
+        let mut context = AvahiRecordBrowserContext::new(avahi_sys::AVAHI_IF_UNSPEC);
+
+        context.record_event_callback =
+            Some(Box::new(move |result, _| sink.lock().unwrap().push(result)));
+
+        for (event, rdata) in [
+            (
+                avahi_sys::AvahiBrowserEvent_AVAHI_BROWSER_NEW,
+                rdata.as_ptr(),
+            ),
+            (
+                avahi_sys::AvahiBrowserEvent_AVAHI_BROWSER_REMOVE,
+                rdata.as_ptr(),
+            ),
+            (avahi_sys::AvahiBrowserEvent_AVAHI_BROWSER_NEW, ptr::null()),
+        ] {
+            unsafe {
+                browse_callback(
+                    ptr::null_mut(),
+                    avahi_sys::AVAHI_IF_UNSPEC,
+                    avahi_sys::AVAHI_PROTO_UNSPEC,
+                    event,
+                    name.as_ptr(),
+                    crate::RR_CLASS_IN,
+                    16,
+                    rdata as *const c_void,
+                    4,
+                    0,
+                    context.as_raw(),
+                )
+            };
+        }
+
+        let record = QueriedRecord::builder()
+            .name("foo._http._tcp.local".to_string())
+            .rr_type(16)
+            .rr_class(crate::RR_CLASS_IN)
+            .rdata(rdata.to_vec())
+            .ttl(None)
+            .interface(NetworkInterface::Unspec)
+            .build()
+            .unwrap();
+
+        assert_eq!(
+            *results.lock().unwrap(),
+            vec![
+                Ok(RecordEvent::Add(record.clone())),
+                Ok(RecordEvent::Remove(record)),
+                Err("missing record data".into()),
+            ]
+        );
+    }
+}
@@ -1,14 +1,27 @@
 //! Rust friendly `AvahiServiceResolver` wrappers/helpers
 
-use crate::Result;
+use crate::browser;
+use crate::error::{Error, ErrorContext, Operation};
+use crate::ffi::{self, c_str};
+use crate::prelude::*;
+use crate::resolve_queue::ResolveQueue;
+use crate::span::Span;
+use crate::{LookupFlags, Result, ServiceDiscovery, ServiceKey, ServiceType};
 use avahi_sys::{
-    avahi_service_resolver_free, avahi_service_resolver_new, AvahiIfIndex, AvahiLookupFlags,
-    AvahiProtocol, AvahiServiceResolver, AvahiServiceResolverCallback,
+    avahi_service_resolver_free, avahi_service_resolver_new, AvahiAddress, AvahiIfIndex,
+    AvahiLookupFlags, AvahiProtocol, AvahiServiceResolver, AvahiServiceResolverCallback,
+    AvahiStringList,
 };
 use libc::{c_char, c_void};
+use std::ffi::CString;
+use std::mem;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 use std::{collections::HashMap, sync::Arc};
 
+use super::avahi_util;
 use super::client::ManagedAvahiClient;
+use super::string_list::ManagedAvahiStringList;
 
 /// Wraps the `AvahiServiceResolver` type from the raw Avahi bindings.
 ///
@@ -71,6 +84,13 @@ impl Drop for ManagedAvahiServiceResolver {
     }
 }
 
+// SAFETY: Avahi objects are not tied to the thread that created them. The resolvers of a browser
+// live in the `ServiceResolverSet` of its context and are only started and freed while the
+// context's `Mutex` is held. The boxed context of an `AvahiMdnsServiceResolver` is not locked, so
+// that resolver is only sound to use as long as the resolver and its event loop are not used from
+// two threads at once.
+unsafe impl Send for ManagedAvahiServiceResolver {}
+
 /// Holds parameters for initializing a new `ManagedAvahiServiceResolver` with
 /// `ManagedAvahiServiceResolver::new()`.
 ///
@@ -91,17 +111,302 @@ pub struct ManagedAvahiServiceResolverParams {
     userdata: *mut c_void,
 }
 
+/// A service found by a browser that is waiting to be resolved. The values passed to the
+/// `AvahiServiceBrowserCallback` are copied, as they only live as long as the callback.
+#[derive(Debug)]
+pub(crate) struct PendingResolve {
+    pub interface: AvahiIfIndex,
+    pub protocol: AvahiProtocol,
+    pub name: CString,
+    pub kind: CString,
+    pub domain: CString,
+}
+
+impl PendingResolve {
+    /// Copies the values passed to an `AvahiServiceBrowserCallback` for a new service.
+    ///
+    /// # Safety
+    /// This function is unsafe because of the raw pointer dereferences.
+    pub unsafe fn new(
+        interface: AvahiIfIndex,
+        protocol: AvahiProtocol,
+        name: *const c_char,
+        kind: *const c_char,
+        domain: *const c_char,
+    ) -> Result<Self> {
+        Ok(Self {
+            interface,
+            protocol,
+            name: c_string!(c_str::try_raw_to_str(name, "service name")?),
+            kind: c_string!(c_str::try_raw_to_str(kind, "service type")?),
+            domain: c_string!(c_str::try_raw_to_str(domain, "service domain")?),
+        })
+    }
+
+    /// Returns the context of the errors of resolving the service.
+    pub fn error_context(&self) -> ErrorContext {
+        ErrorContext::new(Operation::Resolve)
+            .with_service_type(c_str::to_str(&self.kind))
+            .with_name(Some(c_str::to_str(&self.name)))
+    }
+
+    /// Returns the key of the service.
+    pub fn key(&self) -> Result<ServiceKey> {
+        Ok(ServiceKey::new(
+            c_str::to_str(&self.name),
+            &ServiceType::from_str(c_str::to_str(&self.kind))?,
+            c_str::to_str(&self.domain),
+        ))
+    }
+}
+
+/// A resolver that is active, along with the service it resolves.
+#[derive(Debug)]
+struct ActiveResolver {
+    _resolver: ManagedAvahiServiceResolver,
+    service: PendingResolve,
+    deadline: Instant,
+}
+
+/// The resolvers of a browser, along with the services that are queued until fewer than the
+/// maximum number of resolvers are active.
 #[derive(Default, Debug)]
 pub(crate) struct ServiceResolverSet {
-    resolvers: HashMap<*mut AvahiServiceResolver, ManagedAvahiServiceResolver>,
+    resolvers: HashMap<*mut AvahiServiceResolver, ActiveResolver>,
+    queue: ResolveQueue<PendingResolve>,
 }
 
+// SAFETY: the raw keys only identify the resolvers and are never dereferenced, and the resolvers
+// themselves are `Send`.
+unsafe impl Send for ServiceResolverSet {}
+
 impl ServiceResolverSet {
-    pub fn insert(&mut self, resolver: ManagedAvahiServiceResolver) {
-        self.resolvers.insert(resolver.inner, resolver);
+    /// Adds the resolver started for `service` at `now`, which is given up on by `expire()` once
+    /// the resolve timeout has passed.
+    pub fn insert(
+        &mut self,
+        resolver: ManagedAvahiServiceResolver,
+        service: PendingResolve,
+        now: Instant,
+    ) {
+        let deadline = self.queue.deadline(now);
+
+        self.resolvers.insert(
+            resolver.inner,
+            ActiveResolver {
+                _resolver: resolver,
+                service,
+                deadline,
+            },
+        );
     }
 
     pub fn remove_raw(&mut self, raw: *mut AvahiServiceResolver) {
         self.resolvers.remove(&raw);
     }
+
+    pub fn len(&self) -> usize {
+        self.resolvers.len()
+    }
+
+    /// Returns whether no service is being resolved or queued to be.
+    pub fn is_idle(&self) -> bool {
+        self.resolvers.is_empty() && self.queue.is_empty()
+    }
+
+    /// Returns the active resolvers along with the names of the services they are resolving.
+    #[cfg(test)]
+    pub fn active(&self) -> Vec<(*mut AvahiServiceResolver, String)> {
+        self.resolvers
+            .iter()
+            .map(|(raw, r)| (*raw, c_str::to_str(&r.service.name).to_string()))
+            .collect()
+    }
+
+    /// Frees the active resolvers and forgets the queued services, e.g. because browsing is
+    /// restarted. The limits are kept. The resolvers are freed once dispatching has finished if
+    /// this is called from a callback.
+    pub fn clear(&mut self) {
+        ffi::drop_after_dispatch(mem::take(&mut self.resolvers));
+        self.queue.remove_where(|_| true);
+    }
+
+    /// Queues a service to be resolved once `next_pending()` returns it.
+    pub fn queue(&mut self, pending: PendingResolve) {
+        self.queue.push(pending);
+    }
+
+    /// Returns the next queued service to resolve, if fewer than the maximum number of resolvers
+    /// are active.
+    pub fn next_pending(&mut self) -> Option<PendingResolve> {
+        self.queue.next(self.resolvers.len())
+    }
+
+    /// Removes the service from the queue, if it has not been resolved yet. Returns the number of
+    /// resolutions that were cancelled.
+    pub fn cancel(
+        &mut self,
+        interface: AvahiIfIndex,
+        protocol: AvahiProtocol,
+        name: &str,
+        kind: &str,
+        domain: &str,
+    ) -> usize {
+        self.queue.remove_where(|p| {
+            p.interface == interface
+                && p.protocol == protocol
+                && c_str::to_str(&p.name) == name
+                && c_str::to_str(&p.kind) == kind
+                && c_str::to_str(&p.domain) == domain
+        })
+    }
+
+    /// Frees the resolvers whose deadline has passed at `now`, returning the services they were
+    /// resolving.
+    pub fn expire(&mut self, now: Instant) -> Vec<PendingResolve> {
+        let expired = self
+            .resolvers
+            .iter()
+            .filter(|(_, r)| r.deadline <= now)
+            .map(|(raw, _)| *raw)
+            .collect::<Vec<_>>();
+
+        expired
+            .into_iter()
+            .filter_map(|raw| self.resolvers.remove(&raw))
+            .map(|r| r.service)
+            .collect()
+    }
+
+    pub fn set_max_concurrent(&mut self, max_concurrent: usize) {
+        self.queue.set_max_concurrent(max_concurrent);
+    }
+
+    pub fn max_concurrent(&self) -> usize {
+        self.queue.max_concurrent()
+    }
+
+    pub fn set_timeout(&mut self, timeout: Duration) {
+        self.queue.set_timeout(timeout);
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.queue.timeout()
+    }
+}
+
+/// Constructs a new `ServiceDiscovery` from the values passed to an
+/// `AvahiServiceResolverCallback` when a service has been found.
+///
+/// Returns an error if any of the required values is missing. The address is not required if
+/// `flags` contains `LookupFlags::NO_ADDRESS`, in which case it is reported as empty. A missing
+/// TXT record is reported as `None`.
+///
+/// # Safety
+/// This function is unsafe because of the raw pointer dereferences.
+#[allow(clippy::too_many_arguments)]
+pub unsafe fn service_discovery(
+    interface: AvahiIfIndex,
+    host_name: *const c_char,
+    addr: *const AvahiAddress,
+    name: *const c_char,
+    kind: *const c_char,
+    domain: *const c_char,
+    port: u16,
+    txt: *mut AvahiStringList,
+    flags: LookupFlags,
+) -> Result<ServiceDiscovery> {
+    let name = c_str::try_raw_to_str(name, "service name")?;
+    let kind = c_str::try_raw_to_str(kind, "service type")?;
+    let domain = c_str::try_raw_to_str(domain, "service domain")?;
+    let host_name = c_str::try_raw_to_str(host_name, "host name")?;
+
+    let address = if flags.contains(LookupFlags::NO_ADDRESS) {
+        String::new()
+    } else {
+        ensure_not_null!(addr, "service address");
+        browser::scoped_address(
+            avahi_util::avahi_address_to_string(addr),
+            avahi_util::interface_from_index(interface),
+        )
+    };
+
+    // the record is only decoded if it is accessed. Avahi drops empty strings when parsing a
+    // record, so the record of a service without attributes is received as an empty list.
+    let txt = if flags.contains(LookupFlags::NO_TXT) {
+        None
+    } else if txt.is_null() {
+        Some(vec![0])
+    } else {
+        Some(ManagedAvahiStringList::serialize_raw(txt))
+    };
+
+    Ok(ServiceDiscovery::builder()
+        .name(name)
+        .service_type(ServiceType::from_str(kind)?)
+        .domain(domain)
+        .host_name(host_name)
+        .address(address)
+        .port(port)
+        .txt_bytes(txt)
+        .interface(avahi_util::interface_from_index(interface))
+        .build_owned()?)
+}
+
+/// Returns the span for the resolution of the service `name` of type `kind` on `interface`, as
+/// passed to an `AvahiServiceResolverCallback`.
+///
+/// # Safety
+/// This function is unsafe because of the raw pointer dereferences.
+#[cfg_attr(not(feature = "tracing"), allow(unused_variables))]
+pub(crate) unsafe fn resolve_span(
+    interface: AvahiIfIndex,
+    name: *const c_char,
+    kind: *const c_char,
+) -> Span {
+    span!(
+        "resolve",
+        name = &*c_str::raw_to_str_lossy(name),
+        service_type = &*c_str::raw_to_str_lossy(kind),
+        interface
+    )
+}
+
+/// Constructs the error reported when an `AvahiServiceResolverCallback` signals a failure. Avahi
+/// does not guarantee that the name, type and domain are set for failures, so missing values are
+/// rendered as `<null>`.
+///
+/// # Safety
+/// This function is unsafe because of the raw pointer dereferences.
+pub(crate) unsafe fn resolve_failure(
+    name: *const c_char,
+    kind: *const c_char,
+    domain: *const c_char,
+) -> Error {
+    format!(
+        "failed to resolve service `{}` of type `{}` in domain `{}`",
+        c_str::raw_to_str_lossy(name),
+        c_str::raw_to_str_lossy(kind),
+        c_str::raw_to_str_lossy(domain)
+    )
+    .into()
+}
+
+/// Returns the context of the errors of resolving the service passed to an
+/// `AvahiServiceResolverCallback`, leaving out the values that are not set.
+///
+/// # Safety
+/// This function is unsafe because of the raw pointer dereferences.
+pub(crate) unsafe fn resolve_context(name: *const c_char, kind: *const c_char) -> ErrorContext {
+    let mut context = ErrorContext::new(Operation::Resolve);
+
+    if !kind.is_null() {
+        context = context.with_service_type(&c_str::raw_to_str_lossy(kind));
+    }
+
+    if !name.is_null() {
+        context = context.with_name(Some(&c_str::raw_to_str_lossy(name)));
+    }
+
+    context
 }
@@ -3,52 +3,65 @@
 use super::avahi_util;
 use super::client::{ManagedAvahiClient, ManagedAvahiClientParams};
 use super::entry_group::{
-    AddServiceParams, AddServiceSubtypeParams, ManagedAvahiEntryGroup, ManagedAvahiEntryGroupParams,
+    AddAddressParams, AddRecordParams, AddServiceParams, AddServiceSubtypeParams,
+    ManagedAvahiEntryGroup, ManagedAvahiEntryGroupParams, UpdateServiceTxtParams,
 };
 use super::poll::ManagedAvahiSimplePoll;
-use crate::ffi::{c_str, AsRaw, FromRaw, UnwrapOrNull};
+use crate::defer::{self, DeferredQueue};
+use crate::error::{Error, ErrorContext, Operation, ResultExt};
+use crate::ffi::{self, c_str, AsRaw, CallbackContext, DropAfterDispatch, UnwrapOrNull};
 use crate::prelude::*;
+use crate::span::Span;
+use crate::{service, service_name};
 use crate::{
-    EventLoop, NetworkInterface, Result, ServiceRegisteredCallback, ServiceRegistration,
-    ServiceType, TxtRecord,
+    BackendInfo, DaemonState, DaemonStateCallback, Deferrer, EventLoop, Id, NameChange,
+    NameChangedCallback, NetworkInterface, Protocol, RecordSharing, RegistrationFlags,
+    RegistrationState, RegistrationStateCallback, Result, ServiceRecord, ServiceRegisteredCallback,
+    ServiceRegistration, ServiceState, ServiceType, TxtRecord,
 };
 use avahi_sys::{
     AvahiClient, AvahiClientFlags, AvahiClientState, AvahiEntryGroup, AvahiEntryGroupState,
-    AvahiIfIndex,
 };
-use libc::c_void;
+use libc::{c_int, c_void};
 use std::any::Any;
 use std::ffi::{CStr, CString};
 use std::fmt::{self, Formatter};
+use std::net::IpAddr;
 use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, PoisonError, Weak};
+
+/// Number of times a service is renamed after successive name collisions before the collision is
+/// reported instead
+const MAX_RENAMES: u32 = 16;
 
 #[derive(Debug)]
 pub struct AvahiMdnsService {
     // note: this declaration order is important, it ensures that each
     // component is dropped in the correct order
-    context: Box<AvahiServiceContext>,
-    client: Option<Arc<ManagedAvahiClient>>,
+    context: DropAfterDispatch<Box<AvahiServiceContext>>,
     poll: Option<Arc<ManagedAvahiSimplePoll>>,
 }
 
 impl TMdnsService for AvahiMdnsService {
     fn new(service_type: ServiceType, port: u16) -> Self {
-        let kind = avahi_util::format_service_type(&service_type);
-
-        let sub_types = service_type
-            .sub_types()
-            .iter()
-            .map(|sub_type| c_string!(avahi_util::format_sub_type(sub_type, &kind)))
-            .collect::<Vec<_>>();
-
         Self {
-            client: None,
             poll: None,
-            context: Box::new(AvahiServiceContext::new(c_string!(kind), port, sub_types)),
+            context: DropAfterDispatch::new(Box::new(AvahiServiceContext::new(service_type, port))),
         }
     }
 
+    fn id(&self) -> Id {
+        self.context.id
+    }
+
+    fn set_port(&mut self, port: u16) {
+        self.context.port = port;
+    }
+
+    fn port(&self) -> u16 {
+        self.context.port
+    }
+
     /// Sets the name to register this service under. If no name is set, the client's host name
     /// will be used instead.
     ///
@@ -56,19 +69,55 @@ impl TMdnsService for AvahiMdnsService {
     ///
     /// [`AvahiClient::host_name()`]: client/struct.ManagedAvahiClient.html#method.host_name
     fn set_name(&mut self, name: &str) {
-        self.context.name = c_string!(name).into()
+        self.context.name = c_string!(name).into();
+        self.context.named_after_host = None;
+        self.context.default_name = false;
     }
 
     fn name(&self) -> Option<&str> {
         self.context.name.as_ref().map(c_str::to_str)
     }
 
+    fn set_name_fitting(&mut self, name_fitting: bool) {
+        self.context.name_fitting = name_fitting
+    }
+
+    fn name_fitting(&self) -> bool {
+        self.context.name_fitting
+    }
+
+    fn set_allow_name_conflicts(&mut self, allow_name_conflicts: bool) {
+        self.context.allow_name_conflicts = allow_name_conflicts
+    }
+
+    fn allow_name_conflicts(&self) -> bool {
+        self.context.allow_name_conflicts
+    }
+
+    fn set_auto_reconnect(&mut self, auto_reconnect: bool) -> Result<()> {
+        self.context.auto_reconnect = auto_reconnect;
+        Ok(())
+    }
+
+    fn auto_reconnect(&self) -> bool {
+        self.context.auto_reconnect
+    }
+
     fn set_network_interface(&mut self, interface: NetworkInterface) {
-        self.context.interface_index = avahi_util::interface_index(interface)
+        self.context.interface = interface;
     }
 
     fn network_interface(&self) -> NetworkInterface {
-        avahi_util::interface_from_index(self.context.interface_index)
+        self.context.interface
+    }
+
+    fn set_protocol(&mut self, protocol: Protocol) -> Result<()> {
+        self.context.protocol = protocol;
+        Ok(())
+    }
+
+    fn protocol(&self) -> Protocol {
+        self.context.protocol
     }
 
     fn set_domain(&mut self, domain: &str) {
@@ -87,6 +136,14 @@ impl TMdnsService for AvahiMdnsService {
         self.context.host.as_ref().map(c_str::to_str)
     }
 
+    fn set_address(&mut self, address: IpAddr) {
+        self.context.address = Some(address);
+    }
+
+    fn address(&self) -> Option<IpAddr> {
+        self.context.address
+    }
+
     fn set_txt_record(&mut self, txt_record: TxtRecord) {
         self.context.txt_record = txt_record.into()
     }
@@ -95,20 +152,116 @@ impl TMdnsService for AvahiMdnsService {
         self.context.txt_record.as_ref()
     }
 
+    fn add_record(&mut self, record: ServiceRecord) -> Result<()> {
+        record.validate()?;
+        self.context.records.push(record);
+        Ok(())
+    }
+
+    fn records(&self) -> &[ServiceRecord] {
+        &self.context.records
+    }
+
+    fn add_sub_type(&mut self, sub_type: &str) -> Result<()> {
+        self.context.service_type.add_sub_type(sub_type).map(|_| ())
+    }
+
+    fn remove_sub_type(&mut self, sub_type: &str) -> Result<()> {
+        self.context
+            .service_type
+            .remove_sub_type(sub_type)
+            .map(|_| ())
+    }
+
+    fn set_registration_flags(&mut self, flags: RegistrationFlags) -> Result<()> {
+        self.context.registration_flags = flags;
+        Ok(())
+    }
+
+    fn registration_flags(&self) -> RegistrationFlags {
+        self.context.registration_flags
+    }
+
     fn set_registered_callback(&mut self, registered_callback: Box<ServiceRegisteredCallback>) {
-        self.context.registered_callback = registered_callback.into()
+        self.context.registered_callback = Some(Mutex::new(registered_callback))
+    }
+
+    fn set_registration_state_callback(
+        &mut self,
+        registration_state_callback: Box<RegistrationStateCallback>,
+    ) {
+        self.context.registration_state_callback = registration_state_callback.into()
+    }
+
+    fn set_name_changed_callback(&mut self, name_changed_callback: Box<NameChangedCallback>) {
+        self.context.name_changed_callback = Some(name_changed_callback)
     }
 
-    fn set_context(&mut self, context: Box<dyn Any>) {
+    fn set_context(&mut self, context: Box<dyn Any + Send + Sync>) {
         self.context.user_context = Some(Arc::from(context))
     }
 
-    fn context(&self) -> Option<&dyn Any> {
+    fn context(&self) -> Option<&(dyn Any + Send + Sync)> {
         self.context.user_context.as_ref().map(|c| c.as_ref())
     }
 
+    fn deferrer(&self) -> Deferrer {
+        Deferrer::new(&self.context.deferred)
+    }
+
+    fn set_wait_for_daemon(&mut self, wait_for_daemon: bool) -> Result<()> {
+        self.context.wait_for_daemon = wait_for_daemon;
+        Ok(())
+    }
+
+    fn wait_for_daemon(&self) -> bool {
+        self.context.wait_for_daemon
+    }
+
+    fn set_daemon_state_callback(&mut self, daemon_state_callback: Box<DaemonStateCallback>) {
+        self.context.daemon_state_callback = daemon_state_callback.into()
+    }
+
     fn register(&mut self) -> Result<EventLoop> {
-        debug!("Registering service: {:?}", self);
+        let span = span!(
+            "register",
+            id = %self.context.id,
+            name = self.context.name.as_ref().map(c_str::to_str),
+            service_type = c_str::to_str(&self.context.kind)
+        );
+        let _entered = span.enter();
+        self.context.span = span.clone();
+
+        debug!("[{}] Registering service: {:?}", self.context.id, self);
+
+        if self.context.port == 0 {
+            return Err(Error::Unsupported(
+                "Avahi does not support placeholder services on port 0".to_string(),
+            ));
+        }
+
+        self.context.name_adjusted = false;
+        self.context.renames = 0;
+        self.context.registered_name = None;
+
+        if let Some(name) = &self.context.name {
+            let (fitted, adjusted) =
+                service_name::prepare(c_str::to_str(name), self.context.name_fitting)?;
+
+            if adjusted {
+                debug!(
+                    "[{}] Service name shortened to: {}",
+                    self.context.id, fitted
+                );
+                self.context.name = Some(c_string!(fitted.as_ref()));
+                self.context.name_adjusted = true;
+            }
+        }
+
+        // a previous registration is withdrawn, the group before the client it belongs to
+        self.context.group = None;
+        self.context.client = None;
+        self.context.reconnecting = false;
 
         self.poll = Some(Arc::new(unsafe { ManagedAvahiSimplePoll::new() }?));
 
@@ -118,78 +271,318 @@ impl TMdnsService for AvahiMdnsService {
             .ok_or("could not get poll as ref")?
             .clone();
 
-        let client_params = ManagedAvahiClientParams::builder()
-            .poll(poll)
-            .flags(AvahiClientFlags(0))
-            .callback(Some(client_callback))
-            .userdata(self.context.as_raw())
-            .build()?;
-
-        self.client = Some(Arc::new(unsafe { ManagedAvahiClient::new(client_params) }?));
-
-        self.context.client.clone_from(&self.client);
-
-        unsafe {
-            if let Err(e) = create_service(&mut self.context) {
-                self.context.invoke_callback(Err(e))
-            }
-        }
+        unsafe { connect(&mut self.context, poll) }
+            .with_context(|| self.context.error_context())?;
 
-        Ok(EventLoop::new(
+        let event_loop = EventLoop::new(
             self.poll
                 .as_ref()
                 .ok_or("could not get poll as ref")?
                 .clone(),
-        ))
+        );
+
+        debug!("[{}] Event loop: {}", self.context.id, event_loop.id());
+
+        Ok(event_loop.with_deferred(DeferredRunner::new(&mut self.context)))
+    }
+
+    fn state(&self) -> ServiceState {
+        *self
+            .context
+            .state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn backend_info(&self) -> Result<BackendInfo> {
+        let client = self
+            .context
+            .client
+            .as_ref()
+            .ok_or("service has not been registered")?;
+
+        unsafe { client.backend_info() }
+    }
+}
+
+/// Handle passed to closures queued with a [`Deferrer`]. See [`TDeferredService`].
+///
+/// [`Deferrer`]: ../../struct.Deferrer.html
+/// [`TDeferredService`]: ../../defer/trait.TDeferredService.html
+#[derive(Debug)]
+pub struct AvahiDeferredService<'a> {
+    context: &'a mut AvahiServiceContext,
+}
+
+impl TDeferredService for AvahiDeferredService<'_> {
+    fn name(&self) -> Option<&str> {
+        self.context.name.as_ref().map(c_str::to_str)
+    }
+
+    fn txt_record(&self) -> Option<&TxtRecord> {
+        self.context.txt_record.as_ref()
+    }
+
+    fn set_txt_record(&mut self, txt_record: TxtRecord) -> Result<()> {
+        self.context.txt_record = Some(txt_record);
+        unsafe { update_service_txt(self.context) }
+    }
+
+    fn set_name(&mut self, name: &str) -> Result<()> {
+        let (fitted, adjusted) = service_name::prepare(name, self.context.name_fitting)?;
+
+        self.context.name = Some(c_string!(fitted.as_ref()));
+        self.context.name_adjusted = adjusted;
+        self.context.named_after_host = None;
+        self.context.default_name = false;
+
+        unsafe { readd_services(self.context) }
+    }
+
+    fn add_record(&mut self, record: ServiceRecord) -> Result<()> {
+        record.validate()?;
+        self.context.records.push(record);
+
+        unsafe { readd_services(self.context) }
+    }
+
+    fn add_sub_type(&mut self, sub_type: &str) -> Result<()> {
+        // an established group cannot be committed again, so subtypes are not added to it
+        if self.context.service_type.add_sub_type(sub_type)? {
+            unsafe { readd_services(self.context) }?;
+        }
+
+        Ok(())
+    }
+
+    fn remove_sub_type(&mut self, sub_type: &str) -> Result<()> {
+        if self.context.service_type.remove_sub_type(sub_type)? {
+            unsafe { readd_services(self.context) }?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Executes the closures queued with a [`Deferrer`] on behalf of `AvahiEventLoop::poll()`.
+///
+/// [`Deferrer`]: ../../struct.Deferrer.html
+pub(crate) struct DeferredRunner {
+    context: *mut AvahiServiceContext,
+    queue: Weak<DeferredQueue>,
+}
+
+// SAFETY: the context is only dereferenced by `run()`, which `AvahiEventLoop::poll()` calls after
+// its callbacks have returned, and only while the queue shows the service is still alive. The
+// context is not locked, so the service must not be used from another thread while it is polled;
+// closures from other threads reach it through the `Mutex` of the queue instead.
+unsafe impl Send for DeferredRunner {}
+
+impl DeferredRunner {
+    fn new(context: &mut AvahiServiceContext) -> Self {
+        Self {
+            queue: Arc::downgrade(&context.deferred),
+            context,
+        }
+    }
+
+    /// Executes the queued closures. Does nothing if the service has been dropped.
+    ///
+    /// # Safety
+    /// This function is unsafe because of the raw pointer dereference. It must not be called
+    /// from within an Avahi callback.
+    pub(crate) unsafe fn run(&self) {
+        let queue = match self.queue.upgrade() {
+            Some(queue) => queue,
+            None => return,
+        };
+
+        while let Some(f) = defer::pop(&queue) {
+            let context = &mut *self.context;
+
+            debug!("[{}] Running deferred closure", context.id);
+
+            if let Err(e) = f(&mut AvahiDeferredService {
+                context: &mut *context,
+            }) {
+                context.invoke_callback(Err(e));
+            }
+        }
     }
 }
 
 #[derive(FromRaw, AsRaw)]
 struct AvahiServiceContext {
+    id: Id,
     client: Option<Arc<ManagedAvahiClient>>,
     name: Option<CString>,
+    /// Whether the service is named after the host name of the daemon, as no name has been set
+    default_name: bool,
+    /// The host name the service has been named after, so that it is renamed when the daemon
+    /// changes its host name
+    named_after_host: Option<CString>,
+    name_fitting: bool,
+    name_adjusted: bool,
+    allow_name_conflicts: bool,
+    auto_reconnect: bool,
+    reconnecting: bool,
+    renames: u32,
+    wait_for_daemon: bool,
     kind: CString,
-    sub_types: Vec<CString>,
+    service_type: ServiceType,
     port: u16,
     group: Option<ManagedAvahiEntryGroup>,
     txt_record: Option<TxtRecord>,
-    interface_index: AvahiIfIndex,
+    records: Vec<ServiceRecord>,
+    registration_flags: RegistrationFlags,
+    interface: NetworkInterface,
+    protocol: Protocol,
     domain: Option<CString>,
     host: Option<CString>,
-    registered_callback: Option<Box<ServiceRegisteredCallback>>,
-    user_context: Option<Arc<dyn Any>>,
+    address: Option<IpAddr>,
+    state: Mutex<ServiceState>,
+    /// The name the service was last registered under, to report the renames that follow
+    registered_name: Option<String>,
+    registered_callback: Option<Mutex<Box<ServiceRegisteredCallback>>>,
+    registration_state_callback: Option<Box<RegistrationStateCallback>>,
+    name_changed_callback: Option<Box<NameChangedCallback>>,
+    daemon_state_callback: Option<Box<DaemonStateCallback>>,
+    user_context: Option<Arc<dyn Any + Send + Sync>>,
+    deferred: Arc<DeferredQueue>,
+    span: Span,
 }
 
 impl AvahiServiceContext {
-    fn new(kind: CString, port: u16, sub_types: Vec<CString>) -> Self {
+    fn new(service_type: ServiceType, port: u16) -> Self {
         Self {
+            id: Id::next(),
             client: None,
             name: None,
-            kind,
+            default_name: true,
+            named_after_host: None,
+            name_fitting: false,
+            name_adjusted: false,
+            allow_name_conflicts: true,
+            auto_reconnect: false,
+            reconnecting: false,
+            renames: 0,
+            wait_for_daemon: false,
+            kind: c_string!(avahi_util::format_service_type(&service_type)),
+            service_type,
             port,
-            sub_types,
             group: None,
             txt_record: None,
-            interface_index: avahi_sys::AVAHI_IF_UNSPEC,
+            records: vec![],
+            registration_flags: RegistrationFlags::NONE,
+            interface: NetworkInterface::Unspec,
+            protocol: Protocol::Any,
             domain: None,
             host: None,
+            address: None,
+            state: Mutex::new(ServiceState::Unregistered),
+            registered_name: None,
             registered_callback: None,
+            registration_state_callback: None,
+            name_changed_callback: None,
+            daemon_state_callback: None,
             user_context: None,
+            deferred: Arc::default(),
+            span: Span::none(),
         }
     }
 
     fn invoke_callback(&self, result: Result<ServiceRegistration>) {
         if let Some(f) = &self.registered_callback {
+            let mut f = f.lock().unwrap_or_else(PoisonError::into_inner);
             f(result, self.user_context.clone());
         } else {
-            warn!("attempted to invoke service callback but none was set");
+            warn!(
+                "[{}] attempted to invoke service callback but none was set",
+                self.id
+            );
+        }
+    }
+
+    /// Passes the outcome of the registration to the registered callback and updates the
+    /// `ServiceState` accordingly. A registration under a new name is also passed to the name
+    /// changed callback.
+    fn report_registration(&mut self, result: Result<ServiceRegistration>) {
+        let result = result.with_context(|| self.error_context());
+
+        self.set_state(ServiceState::after(&result));
+
+        let change = result
+            .as_ref()
+            .ok()
+            .and_then(|registration| NameChange::track(&mut self.registered_name, registration));
+
+        self.invoke_callback(result);
+
+        if let Some(change) = change {
+            self.invoke_name_changed_callback(change);
+        }
+    }
+
+    fn error_context(&self) -> ErrorContext {
+        ErrorContext::new(Operation::Register)
+            .with_service_type(c_str::to_str(&self.kind))
+            .with_name(self.name.as_ref().map(c_str::to_str))
+    }
+
+    fn invoke_name_changed_callback(&self, change: NameChange) {
+        debug!(
+            "[{}] Service renamed from {} to {}",
+            self.id,
+            change.previous(),
+            change.name()
+        );
+
+        if let Some(f) = &self.name_changed_callback {
+            f(change, self.user_context.clone());
+        }
+    }
+
+    fn set_state(&self, state: ServiceState) {
+        *self.state.lock().unwrap_or_else(PoisonError::into_inner) = state;
+    }
+
+    fn invoke_state_callback(&self, state: RegistrationState) {
+        debug!("[{}] Registration state: {:?}", self.id, state);
+
+        self.set_state(ServiceState::Registering);
+
+        if let Some(f) = &self.registration_state_callback {
+            f(state, self.user_context.clone());
+        }
+    }
+
+    fn invoke_daemon_state_callback(&self, state: DaemonState) {
+        debug!("[{}] Daemon state: {:?}", self.id, state);
+
+        if let Some(f) = &self.daemon_state_callback {
+            f(state, self.user_context.clone());
         }
     }
 }
 
+impl CallbackContext for AvahiServiceContext {
+    fn id(&self) -> Id {
+        self.id
+    }
+
+    fn invoke_error(&self, error: Error) {
+        self.invoke_callback(Err(error))
+    }
+
+    fn span(&self) -> Option<&Span> {
+        Some(&self.span)
+    }
+}
+
 impl fmt::Debug for AvahiServiceContext {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         f.debug_struct("AvahiServiceContext")
+            .field("id", &self.id)
             .field("name", &self.name)
             .field("kind", &self.kind)
             .field("port", &self.port)
@@ -203,31 +596,135 @@ unsafe extern "C" fn client_callback(
     state: AvahiClientState,
     userdata: *mut c_void,
 ) {
-    let context = AvahiServiceContext::from_raw(userdata);
+    ffi::guard(userdata, |context: &mut AvahiServiceContext| {
+        if state == avahi_sys::AvahiClientState_AVAHI_CLIENT_FAILURE {
+            handle_client_failure(context, avahi_sys::avahi_client_errno(client));
+        } else if context.client.is_some() {
+            handle_client_state(context, state);
+        }
+    })
+}
+
+/// Creates the client of the service on `poll` and publishes the service if the daemon is ready.
+/// When waiting for the daemon or with auto-reconnect enabled, the client waits for the daemon
+/// instead of failing if it is not running.
+unsafe fn connect(
+    context: &mut AvahiServiceContext,
+    poll: Arc<ManagedAvahiSimplePoll>,
+) -> Result<()> {
+    let flags = if context.wait_for_daemon || context.auto_reconnect {
+        AvahiClientFlags::AVAHI_CLIENT_NO_FAIL
+    } else {
+        AvahiClientFlags(0)
+    };
+
+    let client_params = ManagedAvahiClientParams::builder()
+        .poll(poll)
+        .flags(flags)
+        .callback(Some(client_callback))
+        .userdata(context.as_raw())
+        .build()?;
+
+    let client = ManagedAvahiClient::new(client_params)?;
+    let state = client.state();
+
+    context.client = Some(Arc::new(client));
+
+    // the client callback is invoked from `avahi_client_new()` before the client has been
+    // stored in the context, so the initial state has to be handled here
+    handle_client_state(context, state);
+
+    Ok(())
+}
+
+/// Reports a client failure. If the connection to the daemon has been lost and auto-reconnect is
+/// enabled, the client is replaced by the event loop instead, so that it is not freed while it is
+/// dispatching this callback.
+unsafe fn handle_client_failure(context: &mut AvahiServiceContext, errno: c_int) {
+    context.invoke_daemon_state_callback(DaemonState::Failed);
+
+    if !context.auto_reconnect || errno != avahi_sys::AVAHI_ERR_DISCONNECTED {
+        context.report_registration(Err(avahi_util::get_error(errno).into()));
+        return;
+    }
+
+    warn!(
+        "[{}] Lost connection to the Avahi daemon, reconnecting",
+        context.id
+    );
+
+    context.invoke_state_callback(RegistrationState::ConnectionLost);
+
+    Deferrer::new(&context.deferred).defer(|service| reconnect(service.context));
+}
+
+/// Replaces the client after the connection to the daemon has been lost. The service is added
+/// again once the new client is running.
+unsafe fn reconnect(context: &mut AvahiServiceContext) -> Result<()> {
+    debug!("[{}] Reconnecting to the Avahi daemon", context.id);
+
+    let poll = context
+        .client
+        .as_ref()
+        .ok_or("could not get client as ref")?
+        .poll()
+        .clone();
+
+    // the group belongs to the old client and has to be freed along with it
+    context.group = None;
+    context.client = None;
+    context.reconnecting = true;
+
+    connect(context, poll)
+}
+
+/// Publishes the service once the daemon is running, and withdraws it while the daemon is
+/// (re-)registering its host name, e.g. because no network interface is up yet or because one has
+/// just appeared, or while the client waits for the daemon to become available. The daemon
+/// republishes the host name by itself, so the service is added again on its own once the daemon
+/// is running again, under the new host name if the service is named after it.
+unsafe fn handle_client_state(context: &mut AvahiServiceContext, state: AvahiClientState) {
+    if let Some(daemon_state) = avahi_util::daemon_state(state) {
+        context.invoke_daemon_state_callback(daemon_state);
+    }
 
     match state {
-        avahi_sys::AvahiServerState_AVAHI_SERVER_INVALID
-        | avahi_sys::AvahiServerState_AVAHI_SERVER_COLLISION
-        | avahi_sys::AvahiServerState_AVAHI_SERVER_FAILURE => {
-            context.invoke_callback(Err(avahi_util::get_last_error(client).into()))
+        avahi_sys::AvahiClientState_AVAHI_CLIENT_S_RUNNING => {
+            if context.reconnecting {
+                context.reconnecting = false;
+                context.invoke_state_callback(RegistrationState::Reestablished);
+            }
+
+            if let Err(e) = create_service(context) {
+                context.report_registration(Err(e))
+            }
+        }
+        avahi_sys::AvahiClientState_AVAHI_CLIENT_S_REGISTERING
+        | avahi_sys::AvahiClientState_AVAHI_CLIENT_S_COLLISION
+        | avahi_sys::AvahiClientState_AVAHI_CLIENT_CONNECTING => {
+            if let Some(group) = context.group.as_mut() {
+                group.reset();
+            }
+
+            context.invoke_state_callback(RegistrationState::Pending)
         }
         _ => {}
     }
 }
 
 unsafe fn create_service(context: &mut AvahiServiceContext) -> Result<()> {
-    if context.name.is_none() {
+    if context.default_name {
         let host_name = context
             .client
             .as_ref()
             .ok_or("expected initialized client")?
             .host_name()?;
 
-        context.name = Some(c_string!(host_name.to_string()));
+        name_after_host(context, c_string!(host_name.to_string()));
     }
 
     if context.group.is_none() {
-        debug!("Creating group");
+        debug!("[{}] Creating group", context.id);
 
         context.group = Some(ManagedAvahiEntryGroup::new(
             ManagedAvahiEntryGroupParams::builder()
@@ -258,21 +755,91 @@ unsafe fn create_service(context: &mut AvahiServiceContext) -> Result<()> {
         .ok_or("could not get name as ref")?
         .clone();
 
-    add_services(context, &name)
+    publish(context, &name)?;
+    context.invoke_state_callback(RegistrationState::Registering);
+
+    Ok(())
+}
+
+/// Names a service without a name set after `host_name`. The daemon changes its host name, e.g.
+/// after a collision with another host, while the group is withdrawn, so a service that has been
+/// named after the previous host name is added again under the new one.
+fn name_after_host(context: &mut AvahiServiceContext, host_name: CString) {
+    if context.named_after_host.as_ref() == Some(&host_name) {
+        return;
+    }
+
+    if context.named_after_host.is_some() {
+        debug!(
+            "[{}] Host name changed, renaming service to: {}",
+            context.id,
+            host_name.to_string_lossy()
+        );
+    }
+
+    context.name = Some(host_name.clone());
+    context.named_after_host = Some(host_name);
+    context.renames = 0;
+}
+
+/// Adds the services to the group under `name`, renaming them if the name collides with a
+/// service that is already published by the daemon.
+unsafe fn publish(context: &mut AvahiServiceContext, name: &CStr) -> Result<()> {
+    match add_services(context, name) {
+        Err(_) if is_local_collision(context) => handle_group_collision(context),
+        result => result,
+    }
+}
+
+/// Returns true if the last call to the daemon failed because of a local name collision.
+unsafe fn is_local_collision(context: &AvahiServiceContext) -> bool {
+    context.client.as_ref().is_some_and(|client| {
+        avahi_sys::avahi_client_errno(client.inner) == avahi_sys::AVAHI_ERR_COLLISION
+    })
 }
 
 unsafe fn add_services(context: &mut AvahiServiceContext, name: &CStr) -> Result<()> {
-    debug!("Adding service: {}", context.kind.to_string_lossy());
+    debug!(
+        "[{}] Adding service: {}",
+        context.id,
+        context.kind.to_string_lossy()
+    );
 
     let group = context
         .group
         .as_mut()
         .ok_or("could not borrow group as mut")?;
 
+    let flags = avahi_util::publish_flags(context.registration_flags);
+    let interface = avahi_util::interface_index(context.interface);
+
+    // the address record has to exist before a service can be published on its host
+    if let Some(address) = context.address {
+        let host = context.host.as_ref().ok_or(service::ADDRESS_WITHOUT_HOST)?;
+        let avahi_address = avahi_util::avahi_address(address);
+
+        debug!(
+            "[{}] Adding address: {} -> {}",
+            context.id,
+            host.to_string_lossy(),
+            address
+        );
+
+        let params = AddAddressParams::builder()
+            .interface(interface)
+            .protocol(avahi_util::protocol(context.protocol))
+            .flags(0)
+            .name(host.as_ptr())
+            .address(&avahi_address)
+            .build()?;
+
+        group.add_address(params)?;
+    }
+
     let params = AddServiceParams::builder()
-        .interface(context.interface_index)
-        .protocol(avahi_sys::AVAHI_PROTO_UNSPEC)
-        .flags(0)
+        .interface(interface)
+        .protocol(avahi_util::protocol(context.protocol))
+        .flags(flags)
         .name(name.as_ptr())
         .kind(context.kind.as_ptr())
         .domain(context.domain.as_ref().map(|d| d.as_ptr()).unwrap_or_null())
@@ -283,13 +850,22 @@ unsafe fn add_services(context: &mut AvahiServiceContext, name: &CStr) -> Result
 
     group.add_service(params)?;
 
-    for sub_type in &context.sub_types {
-        debug!("Adding service subtype: {}", sub_type.to_string_lossy());
+    for sub_type in context.service_type.sub_types() {
+        let sub_type = c_string!(avahi_util::format_sub_type(
+            sub_type,
+            c_str::to_str(&context.kind)
+        ));
+
+        debug!(
+            "[{}] Adding service subtype: {}",
+            context.id,
+            sub_type.to_string_lossy()
+        );
 
         let params = AddServiceSubtypeParams::builder()
-            .interface(context.interface_index)
-            .protocol(avahi_sys::AVAHI_PROTO_UNSPEC)
-            .flags(0)
+            .interface(interface)
+            .protocol(avahi_util::protocol(context.protocol))
+            .flags(flags)
             .name(name.as_ptr())
             .kind(context.kind.as_ptr())
             .domain(context.domain.as_ref().map(|d| d.as_ptr()).unwrap_or_null())
@@ -299,50 +875,145 @@ unsafe fn add_services(context: &mut AvahiServiceContext, name: &CStr) -> Result
         group.add_service_subtype(params)?;
     }
 
+    for record in &context.records {
+        debug!("[{}] Adding record: {:?}", context.id, record);
+
+        let record_name = match record.name() {
+            Some(record_name) => c_string!(record_name.as_str()),
+            None => avahi_util::join_service_name(name, &context.kind, context.domain.as_deref())?,
+        };
+
+        let flags = match record.sharing() {
+            RecordSharing::Unique => avahi_sys::AvahiPublishFlags_AVAHI_PUBLISH_UNIQUE,
+            RecordSharing::Shared => 0,
+        };
+
+        let params = AddRecordParams::builder()
+            .interface(interface)
+            .protocol(avahi_util::protocol(context.protocol))
+            .flags(flags)
+            .name(record_name.as_ptr())
+            .clazz(avahi_sys::AVAHI_DNS_CLASS_IN as u16)
+            .rr_type(*record.rr_type())
+            .ttl(*record.ttl())
+            .rdata(record.rdata())
+            .build()?;
+
+        group.add_record(params)?;
+    }
+
     group.commit()
 }
 
+/// Replaces the TXT record of the services in the group, if they have been added.
+unsafe fn update_service_txt(context: &mut AvahiServiceContext) -> Result<()> {
+    let group = match context.group.as_mut() {
+        Some(group) if !group.is_empty() => group,
+        _ => return Ok(()),
+    };
+
+    debug!("[{}] Updating TXT record", context.id);
+
+    let interface = avahi_util::interface_index(context.interface);
+    let params = UpdateServiceTxtParams::builder()
+        .interface(interface)
+        .protocol(avahi_util::protocol(context.protocol))
+        .flags(avahi_util::publish_flags(context.registration_flags))
+        .name(
+            context
+                .name
+                .as_ref()
+                .ok_or("could not get name as ref")?
+                .as_ptr(),
+        )
+        .kind(context.kind.as_ptr())
+        .domain(context.domain.as_ref().map(|d| d.as_ptr()).unwrap_or_null())
+        .txt(context.txt_record.as_ref().map(|t| t.inner()))
+        .build()?;
+
+    group.update_service_txt(params)
+}
+
+/// Resets the group and adds the services again under the current name, if they have been added.
+unsafe fn readd_services(context: &mut AvahiServiceContext) -> Result<()> {
+    match context.group.as_mut() {
+        Some(group) if !group.is_empty() => group.reset(),
+        _ => return Ok(()),
+    }
+
+    debug!("[{}] Re-registering service", context.id);
+
+    let name = context
+        .name
+        .as_ref()
+        .ok_or("could not get name as ref")?
+        .clone();
+
+    publish(context, &name)
+}
+
 unsafe extern "C" fn entry_group_callback(
     _group: *mut AvahiEntryGroup,
     state: AvahiEntryGroupState,
     userdata: *mut c_void,
 ) {
-    let context = AvahiServiceContext::from_raw(userdata);
-
-    let client = context
-        .client
-        .as_ref()
-        .expect("expected initialized client");
-
-    match state {
+    ffi::guard(userdata, |context: &mut AvahiServiceContext| match state {
         avahi_sys::AvahiEntryGroupState_AVAHI_ENTRY_GROUP_ESTABLISHED => {
-            context.invoke_callback(handle_group_established(context))
+            context.renames = 0;
+            context.report_registration(handle_group_established(context))
         }
         avahi_sys::AvahiEntryGroupState_AVAHI_ENTRY_GROUP_FAILURE => {
-            context.invoke_callback(Err(avahi_util::get_last_error(client.inner).into()))
+            let error = match context.client.as_ref() {
+                Some(client) => avahi_util::get_last_error(client.inner).into(),
+                None => "expected initialized client".into(),
+            };
+
+            context.report_registration(Err(error))
         }
         avahi_sys::AvahiEntryGroupState_AVAHI_ENTRY_GROUP_COLLISION => {
-            let name = context
-                .name
-                .as_ref()
-                .expect("expected initialized name")
-                .clone();
-
-            let new_name = avahi_util::alternative_service_name(name.as_c_str());
-            let result = add_services(context, new_name);
-
-            context.name = Some(new_name.into());
-
-            if let Err(e) = result {
-                context.invoke_callback(Err(e))
+            if let Err(e) = handle_group_collision(context) {
+                context.report_registration(Err(e))
             }
         }
         _ => {}
+    })
+}
+
+unsafe fn handle_group_collision(context: &mut AvahiServiceContext) -> Result<()> {
+    let name = context
+        .name
+        .as_ref()
+        .ok_or("could not get name as ref")?
+        .clone();
+
+    if !context.allow_name_conflicts || context.renames >= MAX_RENAMES {
+        return Err(Error::NameCollision {
+            name: name.to_string_lossy().into_owned(),
+        });
+    }
+
+    let new_name = c_string!(service_name::alternative_service_name(c_str::to_str(&name)));
+
+    debug!(
+        "[{}] Name collision, renaming service to: {}",
+        context.id,
+        new_name.to_string_lossy()
+    );
+
+    // the records published under the previous name have to be removed before the services are
+    // added again
+    if let Some(group) = context.group.as_mut() {
+        group.reset();
     }
+
+    context.name = Some(new_name.clone());
+    context.renames += 1;
+
+    publish(context, &new_name)
 }
 
 unsafe fn handle_group_established(context: &AvahiServiceContext) -> Result<ServiceRegistration> {
-    debug!("Group established");
+    debug!("[{}] Group established", context.id);
 
     let name = c_str::copy_raw(
         context
@@ -357,6 +1028,410 @@ unsafe fn handle_group_established(context: &AvahiServiceContext) -> Result<Serv
         .service_type(ServiceType::from_str(&c_str::copy_raw(
             context.kind.as_ptr(),
         ))?)
-        .domain("local".to_string())
+        .domain(registered_domain(context)?)
+        .name_was_adjusted(context.name_adjusted)
+        .port(context.port)
+        .host(context.host.as_ref().map(|h| c_str::to_str(h).to_string()))
+        .txt(context.txt_record.clone())
         .build()?)
 }
+
+/// Returns the domain the service was published in: the domain set with `set_domain()`, or the
+/// daemon's default domain otherwise.
+unsafe fn registered_domain(context: &AvahiServiceContext) -> Result<String> {
+    let domain = match (&context.domain, &context.client) {
+        (Some(domain), _) => c_str::to_str(domain),
+        (None, Some(client)) => client.domain_name()?,
+        (None, None) => "local",
+    };
+
+    Ok(domain.strip_suffix('.').unwrap_or(domain).to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr;
+    use std::sync::Mutex;
+
+    #[test]
+    fn collision_is_reported_if_name_conflicts_are_not_allowed() {
+        let results: Arc<Mutex<Vec<Result<ServiceRegistration>>>> = Arc::default();
+        let sink = results.clone();
+
+        let mut context = AvahiServiceContext::new(ServiceType::new("http", "tcp").unwrap(), 8080);
+        context.name = Some(c_string!("foo"));
+        context.allow_name_conflicts = false;
+        context.registered_callback = Some(Mutex::new(Box::new(move |result, _| {
+            sink.lock().unwrap().push(result)
+        })));
+
+        unsafe {
+            entry_group_callback(
+                ptr::null_mut(),
+                avahi_sys::AvahiEntryGroupState_AVAHI_ENTRY_GROUP_COLLISION,
+                context.as_raw(),
+            )
+        };
+
+        assert_eq!(
+            *results.lock().unwrap(),
+            vec![Err(Error::NameCollision {
+                name: "foo".to_string()
+            })]
+        );
+        assert_eq!(context.name, Some(c_string!("foo")));
+        assert_eq!(*context.state.lock().unwrap(), ServiceState::Collided);
+    }
+
+    #[test]
+    fn collision_is_reported_after_too_many_renames() {
+        let results: Arc<Mutex<Vec<Result<ServiceRegistration>>>> = Arc::default();
+        let sink = results.clone();
+
+        let mut context = AvahiServiceContext::new(ServiceType::new("http", "tcp").unwrap(), 8080);
+        context.name = Some(c_string!("foo #17"));
+        context.renames = MAX_RENAMES;
+        context.registered_callback = Some(Mutex::new(Box::new(move |result, _| {
+            sink.lock().unwrap().push(result)
+        })));
+
+        unsafe {
+            entry_group_callback(
+                ptr::null_mut(),
+                avahi_sys::AvahiEntryGroupState_AVAHI_ENTRY_GROUP_COLLISION,
+                context.as_raw(),
+            )
+        };
+
+        assert_eq!(
+            *results.lock().unwrap(),
+            vec![Err(Error::NameCollision {
+                name: "foo #17".to_string()
+            })]
+        );
+        assert_eq!(context.renames, MAX_RENAMES);
+        assert_eq!(*context.state.lock().unwrap(), ServiceState::Collided);
+    }
+
+    #[test]
+    fn service_is_renamed_when_host_name_changes() {
+        let mut context = AvahiServiceContext::new(ServiceType::new("http", "tcp").unwrap(), 8080);
+
+        name_after_host(&mut context, c_string!("foo"));
+
+        assert_eq!(context.name, Some(c_string!("foo")));
+
+        // a collision renames the service, which keeps its name while the host name is unchanged
+        context.name = Some(c_string!("foo #2"));
+        context.renames = 1;
+
+        name_after_host(&mut context, c_string!("foo"));
+
+        assert_eq!(context.name, Some(c_string!("foo #2")));
+        assert_eq!(context.renames, 1);
+
+        name_after_host(&mut context, c_string!("foo-2"));
+
+        assert_eq!(context.name, Some(c_string!("foo-2")));
+        assert_eq!(context.renames, 0);
+    }
+
+    #[test]
+    fn panic_in_callback_is_reported_as_error() {
+        let results: Arc<Mutex<Vec<Result<ServiceRegistration>>>> = Arc::default();
+        let sink = results.clone();
+
+        let mut context = AvahiServiceContext::new(ServiceType::new("http", "tcp").unwrap(), 8080);
+        context.name = Some(c_string!("foo"));
+        context.allow_name_conflicts = false;
+        context.registered_callback = Some(Mutex::new(Box::new(move |result, _| {
+            if let Err(Error::NameCollision { .. }) = result {
+                panic!("uh oh spaghetti-o");
+            }
+
+            sink.lock().unwrap().push(result)
+        })));
+
+        unsafe {
+            entry_group_callback(
+                ptr::null_mut(),
+                avahi_sys::AvahiEntryGroupState_AVAHI_ENTRY_GROUP_COLLISION,
+                context.as_raw(),
+            )
+        };
+
+        assert_eq!(
+            *results.lock().unwrap(),
+            vec![Err(Error::CallbackPanicked {
+                message: "uh oh spaghetti-o".to_string()
+            })]
+        );
+    }
+
+    #[test]
+    fn client_registering_reports_pending() {
+        let results: Arc<Mutex<Vec<Result<ServiceRegistration>>>> = Arc::default();
+        let states: Arc<Mutex<Vec<RegistrationState>>> = Arc::default();
+        let sink = results.clone();
+        let state_sink = states.clone();
+
+        let mut context = AvahiServiceContext::new(ServiceType::new("http", "tcp").unwrap(), 8080);
+        context.registered_callback = Some(Mutex::new(Box::new(move |result, _| {
+            sink.lock().unwrap().push(result)
+        })));
+        context.registration_state_callback = Some(Box::new(move |state, _| {
+            state_sink.lock().unwrap().push(state)
+        }));
+
+        unsafe {
+            handle_client_state(
+                &mut context,
+                avahi_sys::AvahiClientState_AVAHI_CLIENT_S_REGISTERING,
+            );
+            handle_client_state(
+                &mut context,
+                avahi_sys::AvahiClientState_AVAHI_CLIENT_S_COLLISION,
+            );
+        }
+
+        assert!(results.lock().unwrap().is_empty());
+        assert_eq!(
+            *states.lock().unwrap(),
+            vec![RegistrationState::Pending, RegistrationState::Pending]
+        );
+        assert_eq!(*context.state.lock().unwrap(), ServiceState::Registering);
+    }
+
+    #[test]
+    fn client_state_is_ignored_until_client_is_initialized() {
+        let results: Arc<Mutex<Vec<Result<ServiceRegistration>>>> = Arc::default();
+        let sink = results.clone();
+
+        let mut context = AvahiServiceContext::new(ServiceType::new("http", "tcp").unwrap(), 8080);
+        context.registered_callback = Some(Mutex::new(Box::new(move |result, _| {
+            sink.lock().unwrap().push(result)
+        })));
+
+        unsafe {
+            client_callback(
+                ptr::null_mut(),
+                avahi_sys::AvahiClientState_AVAHI_CLIENT_S_RUNNING,
+                context.as_raw(),
+            )
+        };
+
+        assert!(results.lock().unwrap().is_empty());
+        assert!(context.group.is_none());
+    }
+
+    #[test]
+    fn disconnect_schedules_reconnect_if_enabled() {
+        let results: Arc<Mutex<Vec<Result<ServiceRegistration>>>> = Arc::default();
+        let states: Arc<Mutex<Vec<RegistrationState>>> = Arc::default();
+        let sink = results.clone();
+        let state_sink = states.clone();
+
+        let mut context = AvahiServiceContext::new(ServiceType::new("http", "tcp").unwrap(), 8080);
+        context.auto_reconnect = true;
+        context.registered_callback = Some(Mutex::new(Box::new(move |result, _| {
+            sink.lock().unwrap().push(result)
+        })));
+        context.registration_state_callback = Some(Box::new(move |state, _| {
+            state_sink.lock().unwrap().push(state)
+        }));
+
+        unsafe { handle_client_failure(&mut context, avahi_sys::AVAHI_ERR_DISCONNECTED) };
+
+        assert!(results.lock().unwrap().is_empty());
+        assert_eq!(
+            *states.lock().unwrap(),
+            vec![RegistrationState::ConnectionLost]
+        );
+        assert_eq!(context.deferred.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn disconnect_is_reported_if_reconnect_is_disabled() {
+        let results: Arc<Mutex<Vec<Result<ServiceRegistration>>>> = Arc::default();
+        let sink = results.clone();
+
+        let mut context = AvahiServiceContext::new(ServiceType::new("http", "tcp").unwrap(), 8080);
+        context.registered_callback = Some(Mutex::new(Box::new(move |result, _| {
+            sink.lock().unwrap().push(result)
+        })));
+
+        unsafe { handle_client_failure(&mut context, avahi_sys::AVAHI_ERR_DISCONNECTED) };
+
+        assert_eq!(results.lock().unwrap().len(), 1);
+        assert!(results.lock().unwrap()[0].is_err());
+        assert!(context.deferred.lock().unwrap().is_empty());
+        assert_eq!(*context.state.lock().unwrap(), ServiceState::Failed);
+    }
+
+    #[test]
+    fn group_failure_is_reported_with_context() {
+        let results: Arc<Mutex<Vec<Result<ServiceRegistration>>>> = Arc::default();
+        let sink = results.clone();
+
+        let mut context = AvahiServiceContext::new(ServiceType::new("http", "tcp").unwrap(), 8080);
+        context.name = Some(c_string!("foo"));
+        context.registered_callback = Some(Mutex::new(Box::new(move |result, _| {
+            sink.lock().unwrap().push(result)
+        })));
+
+        unsafe {
+            entry_group_callback(
+                ptr::null_mut(),
+                avahi_sys::AvahiEntryGroupState_AVAHI_ENTRY_GROUP_FAILURE,
+                context.as_raw(),
+            )
+        };
+
+        let error = results.lock().unwrap().pop().unwrap().unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "register of `foo` (_http._tcp) failed: expected initialized client"
+        );
+        assert_eq!(
+            error.without_context(),
+            &Error::from("expected initialized client")
+        );
+    }
+
+    #[test]
+    fn deferred_closures_run_in_order_and_report_errors() {
+        let results: Arc<Mutex<Vec<Result<ServiceRegistration>>>> = Arc::default();
+        let sink = results.clone();
+
+        let mut context = AvahiServiceContext::new(ServiceType::new("http", "tcp").unwrap(), 8080);
+        context.name = Some(c_string!("foo"));
+        context.registered_callback = Some(Mutex::new(Box::new(move |result, _| {
+            sink.lock().unwrap().push(result)
+        })));
+
+        let deferrer = Deferrer::new(&context.deferred);
+        let runner = DeferredRunner::new(&mut context);
+
+        let mut txt = TxtRecord::new();
+        txt.insert("foo", "bar").unwrap();
+
+        deferrer.defer(move |service| {
+            assert_eq!(service.name(), Some("foo"));
+            service.set_txt_record(txt)
+        });
+        deferrer.defer(|service| service.set_name("bar"));
+        deferrer.defer(|_| Err("uh oh spaghetti-o".into()));
+
+        unsafe { runner.run() };
+
+        assert_eq!(
+            *results.lock().unwrap(),
+            vec![Err("uh oh spaghetti-o".into())]
+        );
+        assert_eq!(context.name, Some(c_string!("bar")));
+        assert_eq!(*context.state.lock().unwrap(), ServiceState::Unregistered);
+        assert_eq!(
+            context.txt_record.as_ref().and_then(|t| t.get("foo")),
+            Some("bar".to_string())
+        );
+
+        drop(context);
+        deferrer.defer(|_| panic!("should not run after the service is dropped"));
+
+        unsafe { runner.run() };
+    }
+
+    #[test]
+    fn deferred_record_is_kept_until_group_is_created() {
+        let mut context = AvahiServiceContext::new(ServiceType::new("http", "tcp").unwrap(), 8080);
+
+        let record = ServiceRecord::builder()
+            .rr_type(16)
+            .rdata(b"\x09presence=1".to_vec())
+            .build()
+            .unwrap();
+
+        AvahiDeferredService {
+            context: &mut context,
+        }
+        .add_record(record.clone())
+        .unwrap();
+
+        assert!(context.group.is_none());
+        assert_eq!(context.records, vec![record]);
+    }
+
+    #[test]
+    fn deferred_sub_types_are_kept_until_group_is_created() {
+        let mut context = AvahiServiceContext::new(ServiceType::new("http", "tcp").unwrap(), 8080);
+        let mut service = AvahiDeferredService {
+            context: &mut context,
+        };
+
+        service.add_sub_type("printer").unwrap();
+        service.add_sub_type("printer").unwrap();
+        service.add_sub_type("scan").unwrap();
+        service.remove_sub_type("printer").unwrap();
+
+        assert!(service.add_sub_type("foo.bar").is_err());
+        assert!(context.group.is_none());
+        assert_eq!(context.service_type.sub_types(), &vec!["scan".to_string()]);
+    }
+
+    #[test]
+    fn rename_after_collision_is_reported_once_established() {
+        let changes: Arc<Mutex<Vec<NameChange>>> = Arc::default();
+        let sink = changes.clone();
+
+        let mut context = AvahiServiceContext::new(ServiceType::new("http", "tcp").unwrap(), 8080);
+        context.name = Some(c_string!("foo"));
+        context.registered_callback = Some(Mutex::new(Box::new(|_, _| {})));
+        context.name_changed_callback =
+            Some(Box::new(move |change, _| sink.lock().unwrap().push(change)));
+
+        let established = |context: &mut AvahiServiceContext| unsafe {
+            entry_group_callback(
+                ptr::null_mut(),
+                avahi_sys::AvahiEntryGroupState_AVAHI_ENTRY_GROUP_ESTABLISHED,
+                context.as_raw(),
+            )
+        };
+
+        established(&mut context);
+        assert!(changes.lock().unwrap().is_empty());
+
+        // the collision handler renames the service before it is published again
+        context.name = Some(c_string!("foo (2)"));
+        established(&mut context);
+        established(&mut context);
+
+        let changes = changes.lock().unwrap();
+
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].previous(), "foo");
+        assert_eq!(changes[0].name(), "foo (2)");
+    }
+
+    #[test]
+    fn group_established_reports_configured_domain() {
+        let mut context = AvahiServiceContext::new(ServiceType::new("http", "tcp").unwrap(), 8080);
+        context.name = Some(c_string!("foo"));
+        context.domain = Some(c_string!("dns-sd.example.com."));
+
+        let registration = unsafe { handle_group_established(&context) }.unwrap();
+
+        assert_eq!(registration.domain(), "dns-sd.example.com");
+    }
+
+    #[test]
+    fn group_established_without_client_reports_local_domain() {
+        let mut context = AvahiServiceContext::new(ServiceType::new("http", "tcp").unwrap(), 8080);
+        context.name = Some(c_string!("foo"));
+
+        let registration = unsafe { handle_group_established(&context) }.unwrap();
+
+        assert_eq!(registration.domain(), "local");
+    }
+}
@@ -0,0 +1,617 @@
+//! Avahi implementation for cross-platform service group.
+//!
+//! Avahi can only withdraw an entry group as a whole, so every instance is published in an entry
+//! group of its own. All of them belong to the single client of the group, which is polled by a
+//! single event loop.
+
+use super::avahi_util;
+use super::client::{ManagedAvahiClient, ManagedAvahiClientParams};
+use super::entry_group::{
+    AddServiceParams, AddServiceSubtypeParams, ManagedAvahiEntryGroup, ManagedAvahiEntryGroupParams,
+};
+use super::poll::ManagedAvahiSimplePoll;
+use crate::error::{Error, ErrorContext, Operation, ResultExt};
+use crate::ffi::{self, c_str, AsRaw, CallbackContext, DropAfterDispatch};
+use crate::prelude::*;
+use crate::service_group::{self, Instance, InstanceChange, InstanceChanges};
+use crate::service_name;
+use crate::span::Span;
+use crate::{
+    EventLoop, Id, InstanceHandle, InstanceRegisteredCallback, NetworkInterface, Result,
+    ServiceRegistration, ServiceType, TxtRecord,
+};
+use avahi_sys::{
+    AvahiClient, AvahiClientFlags, AvahiClientState, AvahiEntryGroup, AvahiEntryGroupState,
+};
+use libc::c_void;
+use std::any::Any;
+use std::ffi::CString;
+use std::fmt::{self, Formatter};
+use std::ptr;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex, PoisonError, Weak};
+
+/// Number of times an instance is renamed after successive name collisions before the collision
+/// is reported instead
+const MAX_RENAMES: u32 = 16;
+
+#[derive(Debug)]
+pub struct AvahiMdnsServiceGroup {
+    // note: this declaration order is important, it ensures that each
+    // component is dropped in the correct order
+    context: DropAfterDispatch<Box<AvahiServiceGroupContext>>,
+    poll: Option<Arc<ManagedAvahiSimplePoll>>,
+}
+
+impl TMdnsServiceGroup for AvahiMdnsServiceGroup {
+    fn new(service_type: ServiceType) -> Self {
+        Self {
+            poll: None,
+            context: DropAfterDispatch::new(Box::new(AvahiServiceGroupContext::new(service_type))),
+        }
+    }
+
+    fn id(&self) -> Id {
+        self.context.id
+    }
+
+    fn service_type(&self) -> &ServiceType {
+        &self.context.service_type
+    }
+
+    fn set_network_interface(&mut self, interface: NetworkInterface) {
+        self.context.interface = interface;
+    }
+
+    fn network_interface(&self) -> NetworkInterface {
+        self.context.interface
+    }
+
+    fn add_instance(
+        &mut self,
+        name: &str,
+        port: u16,
+        txt_record: Option<TxtRecord>,
+    ) -> Result<InstanceHandle> {
+        if port == 0 {
+            return Err(Error::Unsupported(
+                "Avahi does not support placeholder services on port 0".to_string(),
+            ));
+        }
+
+        let instance = Instance::new(name, port, txt_record)?;
+        let handle = instance.handle;
+
+        self.context.changes.push(InstanceChange::Add(instance));
+
+        Ok(handle)
+    }
+
+    fn remove_instance(&mut self, handle: InstanceHandle) -> Result<()> {
+        if !self.instances().contains(&handle) {
+            return Err(format!("instance {} is not in this group", handle).into());
+        }
+
+        self.context.changes.push(InstanceChange::Remove(handle));
+
+        Ok(())
+    }
+
+    fn instances(&self) -> Vec<InstanceHandle> {
+        service_group::instances_after(
+            self.context.instances.iter().map(|i| i.instance.handle),
+            &self.context.changes,
+        )
+    }
+
+    fn set_registered_callback(&mut self, registered_callback: Box<InstanceRegisteredCallback>) {
+        self.context.registered_callback = Some(Mutex::new(registered_callback))
+    }
+
+    fn set_context(&mut self, context: Box<dyn Any + Send + Sync>) {
+        self.context.user_context = Some(Arc::from(context))
+    }
+
+    fn context(&self) -> Option<&(dyn Any + Send + Sync)> {
+        self.context.user_context.as_ref().map(|c| c.as_ref())
+    }
+
+    fn register(&mut self) -> Result<EventLoop> {
+        let span = span!(
+            "register",
+            id = %self.context.id,
+            service_type = c_str::to_str(&self.context.kind)
+        );
+        let _entered = span.enter();
+        self.context.span = span.clone();
+
+        debug!(
+            "[{}] Registering service group: {:?}",
+            self.context.id, self
+        );
+
+        // the groups of a previous registration belong to its client, and are freed before it
+        for instance in &mut self.context.instances {
+            instance.group = None;
+        }
+
+        self.context.client = None;
+
+        // the instances added so far are published once the client is running
+        unsafe { apply_changes(&mut self.context) };
+
+        let poll = Arc::new(unsafe { ManagedAvahiSimplePoll::new() }?);
+
+        self.poll = Some(poll.clone());
+
+        unsafe { connect(&mut self.context, poll.clone()) }
+            .with_context(|| self.context.error_context(None))?;
+
+        let event_loop = EventLoop::new(poll);
+
+        debug!("[{}] Event loop: {}", self.context.id, event_loop.id());
+
+        Ok(event_loop.with_instance_changes(InstanceChangeRunner::new(&mut self.context)))
+    }
+}
+
+/// Applies the instances added to and removed from a group on behalf of
+/// `AvahiEventLoop::poll()`.
+pub(crate) struct InstanceChangeRunner {
+    context: *mut AvahiServiceGroupContext,
+    changes: Weak<InstanceChanges>,
+}
+
+// SAFETY: the group's context is only dereferenced by `run()` from `AvahiEventLoop::poll()`, once
+// the callbacks have returned and only while the group still holds the changes. Other threads
+// only touch the `Mutex` guarding the changes, never the context itself.
+unsafe impl Send for InstanceChangeRunner {}
+
+impl InstanceChangeRunner {
+    fn new(context: &mut AvahiServiceGroupContext) -> Self {
+        Self {
+            changes: Arc::downgrade(&context.changes),
+            context,
+        }
+    }
+
+    /// Applies the changes made since the last run. Does nothing if the group has been dropped.
+    ///
+    /// # Safety
+    /// This function is unsafe because of the raw pointer dereference. It must not be called
+    /// from within an Avahi callback.
+    pub(crate) unsafe fn run(&self) {
+        if self.changes.upgrade().is_some() {
+            apply_changes(&mut *self.context);
+        }
+    }
+}
+
+/// An instance of the group, along with the entry group it is published in
+#[derive(Debug)]
+struct AvahiInstance {
+    instance: Instance,
+    /// The name the instance is published under, which differs from the name it was added with
+    /// after a collision
+    name: CString,
+    renames: u32,
+    group: Option<ManagedAvahiEntryGroup>,
+}
+
+#[derive(FromRaw, AsRaw)]
+struct AvahiServiceGroupContext {
+    id: Id,
+    client: Option<Arc<ManagedAvahiClient>>,
+    service_type: ServiceType,
+    kind: CString,
+    sub_types: Vec<CString>,
+    interface: NetworkInterface,
+    instances: Vec<AvahiInstance>,
+    changes: Arc<InstanceChanges>,
+    registered_callback: Option<Mutex<Box<InstanceRegisteredCallback>>>,
+    user_context: Option<Arc<dyn Any + Send + Sync>>,
+    span: Span,
+}
+
+impl AvahiServiceGroupContext {
+    fn new(service_type: ServiceType) -> Self {
+        let kind = avahi_util::format_service_type(&service_type);
+
+        let sub_types = service_type
+            .sub_types()
+            .iter()
+            .map(|sub_type| c_string!(avahi_util::format_sub_type(sub_type, &kind)))
+            .collect::<Vec<_>>();
+
+        Self {
+            id: Id::next(),
+            client: None,
+            service_type,
+            kind: c_string!(kind),
+            sub_types,
+            interface: NetworkInterface::Unspec,
+            instances: vec![],
+            changes: Arc::default(),
+            registered_callback: None,
+            user_context: None,
+            span: Span::none(),
+        }
+    }
+
+    fn invoke_callback(&self, handle: InstanceHandle, result: Result<ServiceRegistration>) {
+        let result = result.with_context(|| {
+            let instance = self.instances.iter().find(|i| i.instance.handle == handle);
+            self.error_context(instance.map(|i| c_str::to_str(&i.name)))
+        });
+
+        if let Some(f) = &self.registered_callback {
+            let mut f = f.lock().unwrap_or_else(PoisonError::into_inner);
+            f(handle, result, self.user_context.clone());
+        } else {
+            warn!(
+                "[{}] attempted to invoke instance callback but none was set",
+                self.id
+            );
+        }
+    }
+
+    fn error_context(&self, name: Option<&str>) -> ErrorContext {
+        ErrorContext::new(Operation::Register)
+            .with_service_type(c_str::to_str(&self.kind))
+            .with_name(name)
+    }
+
+    /// Reports `error` for every instance of the group, e.g. when the client has failed.
+    fn invoke_error_for_all(&self, error: Error) {
+        for instance in &self.instances {
+            self.invoke_callback(instance.instance.handle, Err(error.clone()));
+        }
+    }
+}
+
+impl CallbackContext for AvahiServiceGroupContext {
+    fn id(&self) -> Id {
+        self.id
+    }
+
+    fn invoke_error(&self, error: Error) {
+        self.invoke_error_for_all(error)
+    }
+
+    fn span(&self) -> Option<&Span> {
+        Some(&self.span)
+    }
+}
+
+impl fmt::Debug for AvahiServiceGroupContext {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AvahiServiceGroupContext")
+            .field("id", &self.id)
+            .field("kind", &self.kind)
+            .field("instances", &self.instances)
+            .finish()
+    }
+}
+
+/// Adds and removes the instances as requested with `add_instance()` and `remove_instance()`.
+/// Added instances are published right away if the client is running.
+unsafe fn apply_changes(context: &mut AvahiServiceGroupContext) {
+    for change in context.changes.take() {
+        match change {
+            InstanceChange::Add(instance) => {
+                debug!(
+                    "[{}] Adding instance {}: {}",
+                    context.id, instance.handle, instance.name
+                );
+
+                let handle = instance.handle;
+
+                context.instances.push(AvahiInstance {
+                    name: c_string!(instance.name.as_str()),
+                    instance,
+                    renames: 0,
+                    group: None,
+                });
+
+                if is_running(context) {
+                    if let Err(e) = publish(context, handle) {
+                        context.invoke_callback(handle, Err(e));
+                    }
+                }
+            }
+            InstanceChange::Remove(handle) => {
+                debug!("[{}] Removing instance {}", context.id, handle);
+
+                // freeing the entry group withdraws the instance, and only that instance
+                context.instances.retain(|i| i.instance.handle != handle);
+            }
+        }
+    }
+}
+
+unsafe fn is_running(context: &AvahiServiceGroupContext) -> bool {
+    context
+        .client
+        .as_ref()
+        .is_some_and(|client| client.state() == avahi_sys::AvahiClientState_AVAHI_CLIENT_S_RUNNING)
+}
+
+unsafe extern "C" fn client_callback(
+    client: *mut AvahiClient,
+    state: AvahiClientState,
+    userdata: *mut c_void,
+) {
+    ffi::guard(userdata, |context: &mut AvahiServiceGroupContext| {
+        if state == avahi_sys::AvahiClientState_AVAHI_CLIENT_FAILURE {
+            let errno = avahi_sys::avahi_client_errno(client);
+            context.invoke_error_for_all(avahi_util::get_error(errno).into());
+        } else if context.client.is_some() {
+            handle_client_state(context, state);
+        }
+    })
+}
+
+/// Creates the client of the group on `poll` and publishes the instances if the daemon is ready.
+unsafe fn connect(
+    context: &mut AvahiServiceGroupContext,
+    poll: Arc<ManagedAvahiSimplePoll>,
+) -> Result<()> {
+    let client_params = ManagedAvahiClientParams::builder()
+        .poll(poll)
+        .flags(AvahiClientFlags(0))
+        .callback(Some(client_callback))
+        .userdata(context.as_raw())
+        .build()?;
+
+    let client = ManagedAvahiClient::new(client_params)?;
+    let state = client.state();
+
+    context.client = Some(Arc::new(client));
+
+    // the client callback is invoked from `avahi_client_new()` before the client has been
+    // stored in the context, so the initial state has to be handled here
+    handle_client_state(context, state);
+
+    Ok(())
+}
+
+/// Publishes the instances once the daemon is running, and withdraws them while the daemon is
+/// (re-)registering its host name. The daemon republishes the host name by itself, so the
+/// instances are added again once it is running again.
+unsafe fn handle_client_state(context: &mut AvahiServiceGroupContext, state: AvahiClientState) {
+    match state {
+        avahi_sys::AvahiClientState_AVAHI_CLIENT_S_RUNNING => {
+            let handles = context
+                .instances
+                .iter()
+                .map(|i| i.instance.handle)
+                .collect::<Vec<_>>();
+
+            for handle in handles {
+                if let Err(e) = publish(context, handle) {
+                    context.invoke_callback(handle, Err(e));
+                }
+            }
+        }
+        avahi_sys::AvahiClientState_AVAHI_CLIENT_S_REGISTERING
+        | avahi_sys::AvahiClientState_AVAHI_CLIENT_S_COLLISION
+        | avahi_sys::AvahiClientState_AVAHI_CLIENT_CONNECTING => {
+            for instance in &mut context.instances {
+                if let Some(group) = instance.group.as_mut() {
+                    group.reset();
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Publishes the instance of `handle` in an entry group of its own, creating the group if needed.
+/// Does nothing if the instance has already been published.
+unsafe fn publish(context: &mut AvahiServiceGroupContext, handle: InstanceHandle) -> Result<()> {
+    let client = context
+        .client
+        .clone()
+        .ok_or("could not get client as ref")?;
+
+    let id = context.id;
+    let userdata = context.as_raw();
+    let instance = find_instance(context, handle)?;
+
+    if instance.group.is_none() {
+        debug!("[{}] Creating group for instance {}", id, handle);
+
+        instance.group = Some(ManagedAvahiEntryGroup::new(
+            ManagedAvahiEntryGroupParams::builder()
+                .client(client)
+                .callback(Some(entry_group_callback))
+                .userdata(userdata)
+                .build()?,
+        )?);
+    }
+
+    let group = instance
+        .group
+        .as_ref()
+        .ok_or("could not get group as ref")?;
+
+    if !group.is_empty() {
+        return Ok(());
+    }
+
+    match add_services(context, handle) {
+        Err(_) if is_local_collision(context) => handle_group_collision(context, handle),
+        result => result,
+    }
+}
+
+/// Returns true if the last call to the daemon failed because of a local name collision.
+unsafe fn is_local_collision(context: &AvahiServiceGroupContext) -> bool {
+    context.client.as_ref().is_some_and(|client| {
+        avahi_sys::avahi_client_errno(client.inner) == avahi_sys::AVAHI_ERR_COLLISION
+    })
+}
+
+fn find_instance(
+    context: &mut AvahiServiceGroupContext,
+    handle: InstanceHandle,
+) -> Result<&mut AvahiInstance> {
+    context
+        .instances
+        .iter_mut()
+        .find(|i| i.instance.handle == handle)
+        .ok_or_else(|| format!("instance {} is not in this group", handle).into())
+}
+
+unsafe fn add_services(
+    context: &mut AvahiServiceGroupContext,
+    handle: InstanceHandle,
+) -> Result<()> {
+    let interface = avahi_util::interface_index(context.interface);
+    let id = context.id;
+    let kind = context.kind.clone();
+    let sub_types = context.sub_types.clone();
+    let instance = find_instance(context, handle)?;
+
+    debug!(
+        "[{}] Adding instance {}: {}",
+        id,
+        handle,
+        instance.name.to_string_lossy()
+    );
+
+    let group = instance
+        .group
+        .as_mut()
+        .ok_or("could not borrow group as mut")?;
+
+    let params = AddServiceParams::builder()
+        .interface(interface)
+        .protocol(avahi_sys::AVAHI_PROTO_UNSPEC)
+        .flags(0)
+        .name(instance.name.as_ptr())
+        .kind(kind.as_ptr())
+        .domain(ptr::null())
+        .host(ptr::null())
+        .port(instance.instance.port)
+        .txt(instance.instance.txt_record.as_ref().map(|t| t.inner()))
+        .build()?;
+
+    group.add_service(params)?;
+
+    for sub_type in &sub_types {
+        let params = AddServiceSubtypeParams::builder()
+            .interface(interface)
+            .protocol(avahi_sys::AVAHI_PROTO_UNSPEC)
+            .flags(0)
+            .name(instance.name.as_ptr())
+            .kind(kind.as_ptr())
+            .domain(ptr::null())
+            .subtype(sub_type.as_ptr())
+            .build()?;
+
+        group.add_service_subtype(params)?;
+    }
+
+    group.commit()
+}
+
+unsafe extern "C" fn entry_group_callback(
+    group: *mut AvahiEntryGroup,
+    state: AvahiEntryGroupState,
+    userdata: *mut c_void,
+) {
+    ffi::guard(userdata, |context: &mut AvahiServiceGroupContext| {
+        let handle = match context
+            .instances
+            .iter()
+            .find(|i| i.group.as_ref().is_some_and(|g| g.is(group)))
+        {
+            Some(instance) => instance.instance.handle,
+            // the instance has been removed in the meantime
+            None => return,
+        };
+
+        match state {
+            avahi_sys::AvahiEntryGroupState_AVAHI_ENTRY_GROUP_ESTABLISHED => {
+                let result = handle_group_established(context, handle);
+                context.invoke_callback(handle, result)
+            }
+            avahi_sys::AvahiEntryGroupState_AVAHI_ENTRY_GROUP_FAILURE => {
+                let error = match context.client.as_ref() {
+                    Some(client) => avahi_util::get_last_error(client.inner).into(),
+                    None => "expected initialized client".into(),
+                };
+
+                context.invoke_callback(handle, Err(error))
+            }
+            avahi_sys::AvahiEntryGroupState_AVAHI_ENTRY_GROUP_COLLISION => {
+                if let Err(e) = handle_group_collision(context, handle) {
+                    context.invoke_callback(handle, Err(e))
+                }
+            }
+            _ => {}
+        }
+    })
+}
+
+unsafe fn handle_group_collision(
+    context: &mut AvahiServiceGroupContext,
+    handle: InstanceHandle,
+) -> Result<()> {
+    let id = context.id;
+    let instance = find_instance(context, handle)?;
+
+    if instance.renames >= MAX_RENAMES {
+        return Err(Error::NameCollision {
+            name: instance.name.to_string_lossy().into_owned(),
+        });
+    }
+
+    let new_name = c_string!(service_name::alternative_service_name(c_str::to_str(
+        &instance.name
+    )));
+
+    debug!(
+        "[{}] Name collision, renaming instance {} to: {}",
+        id,
+        handle,
+        new_name.to_string_lossy()
+    );
+
+    // only the records of this instance are removed, the other instances are in groups of
+    // their own
+    if let Some(group) = instance.group.as_mut() {
+        group.reset();
+    }
+
+    instance.name = new_name;
+    instance.renames += 1;
+
+    publish(context, handle)
+}
+
+unsafe fn handle_group_established(
+    context: &mut AvahiServiceGroupContext,
+    handle: InstanceHandle,
+) -> Result<ServiceRegistration> {
+    debug!("[{}] Group of instance {} established", context.id, handle);
+
+    let domain = match &context.client {
+        Some(client) => client.domain_name()?,
+        None => "local",
+    };
+
+    let service_type = ServiceType::from_str(&c_str::copy_raw(context.kind.as_ptr()))?;
+    let instance = find_instance(context, handle)?;
+
+    instance.renames = 0;
+
+    Ok(ServiceRegistration::builder()
+        .name(c_str::copy_raw(instance.name.as_ptr()))
+        .service_type(service_type)
+        .domain(domain.strip_suffix('.').unwrap_or(domain).to_string())
+        .port(instance.instance.port)
+        .txt(instance.instance.txt_record.clone())
+        .build()?)
+}
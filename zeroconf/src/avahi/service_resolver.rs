@@ -0,0 +1,296 @@
+//! Avahi implementation for cross-platform service resolver
+
+use super::avahi_util;
+use super::client::{ManagedAvahiClient, ManagedAvahiClientParams};
+use super::poll::ManagedAvahiSimplePoll;
+use super::resolver::{self, ManagedAvahiServiceResolver, ManagedAvahiServiceResolverParams};
+use crate::error::{Error, ErrorContext, Operation, ResultExt};
+use crate::ffi::{self, c_str, AsRaw, CallbackContext};
+use crate::prelude::*;
+use crate::span::Span;
+use crate::{
+    EventLoop, Id, LookupFlags, NetworkInterface, Result, ServiceDiscoveredCallback,
+    ServiceDiscovery, ServiceType,
+};
+use avahi_sys::{
+    AvahiAddress, AvahiClient, AvahiClientFlags, AvahiClientState, AvahiIfIndex,
+    AvahiLookupResultFlags, AvahiProtocol, AvahiResolverEvent, AvahiServiceResolver,
+    AvahiStringList,
+};
+use libc::{c_char, c_void};
+use std::any::Any;
+use std::ffi::CString;
+use std::fmt;
+use std::sync::{Arc, Mutex, PoisonError};
+
+#[derive(Debug)]
+pub struct AvahiMdnsResolver {
+    context: Box<AvahiResolverContext>,
+    client: Option<Arc<ManagedAvahiClient>>,
+    poll: Option<Arc<ManagedAvahiSimplePoll>>,
+}
+
+impl TMdnsResolver for AvahiMdnsResolver {
+    fn new() -> Self {
+        Self {
+            client: None,
+            poll: None,
+            context: Box::new(AvahiResolverContext::new(avahi_sys::AVAHI_IF_UNSPEC)),
+        }
+    }
+
+    fn id(&self) -> Id {
+        self.context.id
+    }
+
+    fn set_network_interface(&mut self, interface: NetworkInterface) {
+        self.context.interface_index = avahi_util::interface_index(interface);
+    }
+
+    fn network_interface(&self) -> NetworkInterface {
+        avahi_util::interface_from_index(self.context.interface_index)
+    }
+
+    fn set_service_resolved_callback(
+        &mut self,
+        service_resolved_callback: Box<ServiceDiscoveredCallback>,
+    ) {
+        self.context.service_resolved_callback = Some(Mutex::new(service_resolved_callback));
+    }
+
+    fn set_context(&mut self, context: Box<dyn Any + Send + Sync>) {
+        self.context.user_context = Some(Arc::from(context));
+    }
+
+    fn context(&self) -> Option<&(dyn Any + Send + Sync)> {
+        self.context.user_context.as_ref().map(|c| c.as_ref())
+    }
+
+    fn resolve(
+        &mut self,
+        name: &str,
+        service_type: ServiceType,
+        domain: &str,
+    ) -> Result<EventLoop> {
+        let span = span!(
+            "resolve",
+            id = %self.context.id,
+            name,
+            service_type = service_type.to_string().as_str(),
+            interface = self.context.interface_index
+        );
+        let _entered = span.enter();
+        self.context.span = span.clone();
+
+        debug!(
+            "[{}] Resolving service `{}` of type `{:?}` in domain `{}`: {:?}",
+            self.context.id, name, service_type, domain, self
+        );
+
+        self.context.resolver = None;
+        self.context.name = Some(c_string!(name));
+        self.context.kind = Some(c_string!(avahi_util::format_service_type(&service_type)));
+        self.context.domain = Some(c_string!(domain));
+
+        self.poll = Some(Arc::new(unsafe { ManagedAvahiSimplePoll::new() }?));
+
+        let poll = self
+            .poll
+            .as_ref()
+            .ok_or("could not get poll as ref")?
+            .clone();
+
+        let client_params = ManagedAvahiClientParams::builder()
+            .poll(poll)
+            .flags(AvahiClientFlags(0))
+            .callback(Some(client_callback))
+            .userdata(self.context.as_raw())
+            .build()?;
+
+        let client = unsafe { ManagedAvahiClient::new(client_params) }
+            .with_context(|| self.context.error_context())?;
+
+        self.client = Some(Arc::new(client));
+
+        self.context.client.clone_from(&self.client);
+
+        unsafe {
+            if let Err(e) = create_resolver(&mut self.context) {
+                self.context.invoke_callback(Err(e));
+            }
+        }
+
+        let event_loop = EventLoop::new(
+            self.poll
+                .as_ref()
+                .ok_or("could not get poll as ref")?
+                .clone(),
+        );
+
+        debug!("[{}] Event loop: {}", self.context.id, event_loop.id());
+
+        Ok(event_loop)
+    }
+}
+
+#[derive(FromRaw, AsRaw)]
+struct AvahiResolverContext {
+    id: Id,
+    client: Option<Arc<ManagedAvahiClient>>,
+    resolver: Option<ManagedAvahiServiceResolver>,
+    service_resolved_callback: Option<Mutex<Box<ServiceDiscoveredCallback>>>,
+    user_context: Option<Arc<dyn Any + Send + Sync>>,
+    interface_index: AvahiIfIndex,
+    name: Option<CString>,
+    kind: Option<CString>,
+    domain: Option<CString>,
+    span: Span,
+}
+
+impl AvahiResolverContext {
+    fn new(interface_index: AvahiIfIndex) -> Self {
+        Self {
+            id: Id::next(),
+            client: None,
+            resolver: None,
+            service_resolved_callback: None,
+            user_context: None,
+            interface_index,
+            name: None,
+            kind: None,
+            domain: None,
+            span: Span::none(),
+        }
+    }
+
+    fn invoke_callback(&self, result: Result<ServiceDiscovery>) {
+        let result = result.with_context(|| self.error_context());
+
+        if let Some(f) = &self.service_resolved_callback {
+            let mut f = f.lock().unwrap_or_else(PoisonError::into_inner);
+            f(result, self.user_context.clone());
+        } else {
+            warn!(
+                "[{}] attempted to invoke resolver callback but none was set",
+                self.id
+            );
+        }
+    }
+
+    fn error_context(&self) -> ErrorContext {
+        let context =
+            ErrorContext::new(Operation::Resolve).with_name(self.name.as_ref().map(c_str::to_str));
+
+        match &self.kind {
+            Some(kind) => context.with_service_type(c_str::to_str(kind)),
+            None => context,
+        }
+    }
+}
+
+impl CallbackContext for AvahiResolverContext {
+    fn id(&self) -> Id {
+        self.id
+    }
+
+    fn invoke_error(&self, error: Error) {
+        self.invoke_callback(Err(error))
+    }
+
+    fn span(&self) -> Option<&Span> {
+        Some(&self.span)
+    }
+}
+
+impl fmt::Debug for AvahiResolverContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("AvahiResolverContext")
+            .field("id", &self.id)
+            .field("name", &self.name)
+            .field("kind", &self.kind)
+            .field("domain", &self.domain)
+            .finish()
+    }
+}
+
+unsafe extern "C" fn client_callback(
+    client: *mut AvahiClient,
+    state: AvahiClientState,
+    userdata: *mut c_void,
+) {
+    ffi::guard(userdata, |context: &mut AvahiResolverContext| {
+        if state == avahi_sys::AvahiClientState_AVAHI_CLIENT_FAILURE {
+            context.invoke_callback(Err(avahi_util::get_last_error(client).into()));
+        }
+    })
+}
+
+unsafe fn create_resolver(context: &mut AvahiResolverContext) -> Result<()> {
+    let raw_context = context.as_raw();
+
+    context.resolver = Some(ManagedAvahiServiceResolver::new(
+        ManagedAvahiServiceResolverParams::builder()
+            .client(Arc::clone(
+                context
+                    .client
+                    .as_ref()
+                    .ok_or("could not get client as ref")?,
+            ))
+            .interface(context.interface_index)
+            .protocol(avahi_sys::AVAHI_PROTO_UNSPEC)
+            .name(context.name.as_ref().ok_or("expected name")?.as_ptr())
+            .kind(context.kind.as_ref().ok_or("expected kind")?.as_ptr())
+            .domain(context.domain.as_ref().ok_or("expected domain")?.as_ptr())
+            .aprotocol(avahi_sys::AVAHI_PROTO_UNSPEC)
+            .flags(0)
+            .callback(Some(resolve_callback))
+            .userdata(raw_context)
+            .build()?,
+    )?);
+
+    Ok(())
+}
+
+unsafe extern "C" fn resolve_callback(
+    _resolver: *mut AvahiServiceResolver,
+    interface: AvahiIfIndex,
+    _protocol: AvahiProtocol,
+    event: AvahiResolverEvent,
+    name: *const c_char,
+    kind: *const c_char,
+    domain: *const c_char,
+    host_name: *const c_char,
+    addr: *const AvahiAddress,
+    port: u16,
+    txt: *mut AvahiStringList,
+    _flags: AvahiLookupResultFlags,
+    userdata: *mut c_void,
+) {
+    ffi::guard(userdata, |context: &mut AvahiResolverContext| {
+        match event {
+            avahi_sys::AvahiResolverEvent_AVAHI_RESOLVER_FAILURE => {
+                context.invoke_callback(Err(resolver::resolve_failure(name, kind, domain)));
+            }
+            avahi_sys::AvahiResolverEvent_AVAHI_RESOLVER_FOUND => {
+                let result = resolver::service_discovery(
+                    interface,
+                    host_name,
+                    addr,
+                    name,
+                    kind,
+                    domain,
+                    port,
+                    txt,
+                    LookupFlags::NONE,
+                );
+
+                if let Ok(discovery) = &result {
+                    debug!("[{}] Service resolved: {:?}", context.id, discovery);
+                }
+
+                context.invoke_callback(result);
+            }
+            _ => {}
+        };
+    })
+}
@@ -1,15 +1,19 @@
 //! Low level interface for interacting with `AvahiStringList`.
 
 use crate::ffi::c_str;
+use crate::Result;
 use avahi_sys::{
-    avahi_free, avahi_string_list_add_pair, avahi_string_list_copy, avahi_string_list_equal,
-    avahi_string_list_find, avahi_string_list_free, avahi_string_list_get_next,
-    avahi_string_list_get_pair, avahi_string_list_length, avahi_string_list_new,
+    avahi_free, avahi_string_list_add_pair, avahi_string_list_add_pair_arbitrary,
+    avahi_string_list_copy, avahi_string_list_equal, avahi_string_list_find,
+    avahi_string_list_free, avahi_string_list_get_next, avahi_string_list_get_pair,
+    avahi_string_list_get_size, avahi_string_list_get_text, avahi_string_list_length,
+    avahi_string_list_new, avahi_string_list_parse, avahi_string_list_serialize,
     avahi_string_list_to_string, AvahiStringList,
 };
 use libc::{c_char, c_void};
+use std::ffi::CStr;
 use std::marker::PhantomData;
-use std::ptr;
+use std::{ptr, slice};
 
 /// Wraps the `AvahiStringList` pointer from the raw Avahi bindings.
 ///
@@ -37,6 +41,17 @@ impl ManagedAvahiStringList {
         self.0 = avahi_string_list_add_pair(self.0, key, value);
     }
 
+    /// Delegate function for [`avahi_string_list_add_pair_arbitrary()`].
+    ///
+    /// # Safety
+    /// This function is unsafe because it provides no guarantees about the given pointers that are
+    /// dereferenced.
+    ///
+    /// [`avahi_string_list_add_pair_arbitrary()`]: https://avahi.org/doxygen/html/strlst_8h.html
+    pub unsafe fn add_pair_arbitrary(&mut self, key: *const c_char, value: *const u8, size: usize) {
+        self.0 = avahi_string_list_add_pair_arbitrary(self.0, key, value, size);
+    }
+
     /// Delegate function for [`avahi_string_list_find()`]. Returns a new `AvahiStringListNode`.
     ///
     /// # Safety
@@ -44,7 +59,7 @@ impl ManagedAvahiStringList {
     /// dereferenced.
     ///
     /// [`avahi_string_list_find()`]: https://avahi.org/doxygen/html/strlst_8h.html#aafc54c009a2a1608b517c15a7cf29944
-    pub unsafe fn find(&mut self, key: *const c_char) -> Option<AvahiStringListNode> {
+    pub unsafe fn find(&mut self, key: *const c_char) -> Option<AvahiStringListNode<'_>> {
         let node = avahi_string_list_find(self.0, key);
 
         if !node.is_null() {
@@ -54,6 +69,65 @@ impl ManagedAvahiStringList {
         }
     }
 
+    /// Like [`find()`], but returns the last node matching `key`. The list is stored in reverse,
+    /// so this is the node that was added first.
+    ///
+    /// # Safety
+    /// This function is unsafe because it provides no guarantees about the given pointers that are
+    /// dereferenced.
+    ///
+    /// [`find()`]: #method.find
+    pub unsafe fn find_last(&mut self, key: *const c_char) -> Option<AvahiStringListNode<'_>> {
+        let mut last = ptr::null_mut();
+        let mut node = avahi_string_list_find(self.0, key);
+
+        while !node.is_null() {
+            last = node;
+            node = avahi_string_list_find((*node).next, key);
+        }
+
+        if !last.is_null() {
+            Some(AvahiStringListNode::new(last))
+        } else {
+            None
+        }
+    }
+
+    /// Removes the first node matching `key` (as found by [`avahi_string_list_find()`]) by
+    /// relinking its neighbours, leaving the order of the remaining nodes unchanged. Returns
+    /// `false` if no such node exists.
+    ///
+    /// # Safety
+    /// This function is unsafe because it provides no guarantees about the given pointers that are
+    /// dereferenced.
+    ///
+    /// [`avahi_string_list_find()`]: https://avahi.org/doxygen/html/strlst_8h.html#aafc54c009a2a1608b517c15a7cf29944
+    pub unsafe fn remove(&mut self, key: *const c_char) -> bool {
+        let node = avahi_string_list_find(self.0, key);
+
+        if node.is_null() {
+            return false;
+        }
+
+        if node == self.0 {
+            self.0 = (*node).next;
+        } else {
+            let mut prev = self.0;
+
+            while (*prev).next != node {
+                prev = (*prev).next;
+            }
+
+            (*prev).next = (*node).next;
+        }
+
+        // detach the node so that only it is freed
+        (*node).next = ptr::null_mut();
+        avahi_string_list_free(node);
+
+        true
+    }
+
     /// Delegate function for [`avahi_string_list_length()`].
     ///
     /// [`avahi_string_list_length()`]: https://avahi.org/doxygen/html/strlst_8h.html#a806c571b338e882390a180b1360c1456
@@ -74,8 +148,52 @@ impl ManagedAvahiStringList {
         avahi_string_list_to_string(self.0).into()
     }
 
+    /// Delegate function for [`avahi_string_list_parse()`]. Creates a new list from the DNS wire
+    /// format.
+    ///
+    /// # Safety
+    /// This function is unsafe because of the call to `avahi_string_list_parse()`.
+    ///
+    /// [`avahi_string_list_parse()`]: https://avahi.org/doxygen/html/strlst_8h.html
+    pub unsafe fn parse(data: &[u8]) -> Result<Self> {
+        let mut list: *mut AvahiStringList = ptr::null_mut();
+
+        if avahi_string_list_parse(data.as_ptr() as *const c_void, data.len(), &mut list) != 0 {
+            return Err("could not parse string list".into());
+        }
+
+        Ok(Self(list))
+    }
+
+    /// Delegate function for [`avahi_string_list_serialize()`]. Returns the list in the DNS wire
+    /// format.
+    ///
+    /// # Safety
+    /// This function is unsafe because of the call to `avahi_string_list_serialize()`.
+    ///
+    /// [`avahi_string_list_serialize()`]: https://avahi.org/doxygen/html/strlst_8h.html
+    pub unsafe fn serialize(&self) -> Vec<u8> {
+        Self::serialize_raw(self.0)
+    }
+
+    /// Returns the list `raw`, which is not owned by a `ManagedAvahiStringList`, in the DNS wire
+    /// format without copying the list first.
+    ///
+    /// # Safety
+    /// This function is unsafe because of the call to `avahi_string_list_serialize()`.
+    pub(crate) unsafe fn serialize_raw(raw: *mut AvahiStringList) -> Vec<u8> {
+        // a null buffer only computes the required size
+        let size = avahi_string_list_serialize(raw, ptr::null_mut(), 0);
+        let mut data = vec![0; size];
+
+        let used = avahi_string_list_serialize(raw, data.as_mut_ptr() as *mut c_void, size);
+        data.truncate(used);
+
+        data
+    }
+
     /// Returns the first node in the list.
-    pub fn head(&mut self) -> AvahiStringListNode {
+    pub fn head(&mut self) -> AvahiStringListNode<'_> {
         AvahiStringListNode::new(self.0)
     }
 
@@ -110,6 +228,8 @@ impl Drop for ManagedAvahiStringList {
     }
 }
 
+// SAFETY: the list is a plain linked list on the heap that only this value owns. It is only
+// modified through `&mut self`, and the `&self` methods only read it.
 unsafe impl Send for ManagedAvahiStringList {}
 unsafe impl Sync for ManagedAvahiStringList {}
 
@@ -151,6 +271,20 @@ impl<'a> AvahiStringListNode<'a> {
 
         AvahiPair::new(key.into(), value.into(), value_size)
     }
+
+    /// Returns the raw `key=value` string of this node, which is borrowed from the list rather
+    /// than copied like [`get_pair()`] does.
+    ///
+    /// # Safety
+    /// This function is unsafe because it dereferences the text pointer of the node.
+    ///
+    /// [`get_pair()`]: #method.get_pair
+    pub unsafe fn text(&self) -> &'a [u8] {
+        slice::from_raw_parts(
+            avahi_string_list_get_text(self.list),
+            avahi_string_list_get_size(self.list),
+        )
+    }
 }
 
 /// Represents a key-value pair in an `AvahiStringList`.
@@ -161,6 +295,23 @@ pub struct AvahiPair {
     value_size: usize,
 }
 
+impl AvahiPair {
+    /// Returns the raw bytes of the value or `None` if the pair has no value.
+    ///
+    /// # Safety
+    /// This function is unsafe because it dereferences the underlying value pointer.
+    pub unsafe fn value_bytes(&self) -> Option<&[u8]> {
+        if self.value.0.is_null() {
+            None
+        } else {
+            Some(slice::from_raw_parts(
+                self.value.0 as *const u8,
+                self.value_size,
+            ))
+        }
+    }
+}
+
 /// Represents a string value returned by `AvahiStringList`. The underlying `*mut c_char` is freed
 /// using the appropriate Avahi function.
 #[derive(new)]
@@ -178,6 +329,26 @@ impl AvahiString {
             Some(c_str::raw_to_str(self.0))
         }
     }
+
+    /// Returns true if this `AvahiString` is null or has no characters.
+    ///
+    /// # Safety
+    /// This function is unsafe because it dereferences the underlying pointer.
+    pub unsafe fn is_empty(&self) -> bool {
+        self.0.is_null() || *self.0 == 0
+    }
+
+    /// Returns a copy of this `AvahiString`, replacing invalid UTF-8, or `None` if null.
+    ///
+    /// # Safety
+    /// This function is unsafe because it dereferences the underlying pointer.
+    pub unsafe fn to_string_lossy(&self) -> Option<String> {
+        if self.0.is_null() {
+            None
+        } else {
+            Some(CStr::from_ptr(self.0).to_string_lossy().into_owned())
+        }
+    }
 }
 
 impl From<*mut c_char> for AvahiString {
@@ -367,4 +538,93 @@ mod tests {
 
         assert_eq!(map, expected);
     }
+
+    #[test]
+    fn add_pair_arbitrary_success() {
+        crate::tests::setup();
+
+        let mut list = unsafe { ManagedAvahiStringList::new() };
+        let key = c_string!("foo");
+        let value = [0xff, 0x00, 0x01];
+
+        unsafe {
+            list.add_pair_arbitrary(key.as_ptr() as *const c_char, value.as_ptr(), value.len());
+
+            let pair = list.find(key.as_ptr() as *const c_char).unwrap().get_pair();
+
+            assert_eq!(pair.value_bytes().unwrap(), &value);
+        }
+    }
+
+    #[test]
+    fn remove_relinks_nodes() {
+        crate::tests::setup();
+
+        let mut list = unsafe { ManagedAvahiStringList::new() };
+        let keys = [c_string!("a"), c_string!("b"), c_string!("c")];
+
+        unsafe {
+            for key in &keys {
+                list.add_pair(key.as_ptr() as *const c_char, ptr::null());
+            }
+
+            // list is now c -> b -> a
+            assert!(list.remove(keys[1].as_ptr() as *const c_char));
+            assert_eq!(list.to_string().as_str().unwrap(), "\"a\" \"c\"");
+
+            assert!(list.remove(keys[2].as_ptr() as *const c_char));
+            assert_eq!(list.to_string().as_str().unwrap(), "\"a\"");
+
+            assert!(list.remove(keys[0].as_ptr() as *const c_char));
+            assert_eq!(list.length(), 0);
+
+            assert!(!list.remove(keys[0].as_ptr() as *const c_char));
+        }
+    }
+
+    #[test]
+    fn find_last_returns_first_added_node() {
+        crate::tests::setup();
+
+        let mut list =
+            unsafe { ManagedAvahiStringList::parse(b"\x05a=one\x01b\x05A=two") }.unwrap();
+        let key = c_string!("a");
+
+        unsafe {
+            let first = list
+                .find_last(key.as_ptr() as *const c_char)
+                .unwrap()
+                .get_pair();
+
+            assert_eq!(first.value().as_str().unwrap(), "one");
+
+            let last = list.find(key.as_ptr() as *const c_char).unwrap().get_pair();
+
+            assert_eq!(last.value().as_str().unwrap(), "two");
+
+            let missing = c_string!("c");
+            assert!(list.find_last(missing.as_ptr() as *const c_char).is_none());
+        }
+    }
+
+    #[test]
+    fn serialize_parse_success() {
+        crate::tests::setup();
+
+        let mut list = unsafe { ManagedAvahiStringList::new() };
+        let keys = [c_string!("a"), c_string!("b")];
+
+        unsafe {
+            for key in &keys {
+                list.add_pair(key.as_ptr() as *const c_char, ptr::null());
+            }
+
+            // the list is stored in reverse, but serialized in insertion order
+            let data = list.serialize();
+            assert_eq!(data, b"\x01a\x01b");
+
+            let parsed = ManagedAvahiStringList::parse(&data).unwrap();
+            assert_eq!(parsed, list);
+        }
+    }
 }
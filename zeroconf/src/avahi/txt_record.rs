@@ -1,10 +1,11 @@
 //! Avahi implementation for cross-platform TXT record.
 
-use super::string_list::{AvahiStringListNode, ManagedAvahiStringList};
-use crate::txt_record::TTxtRecord;
+use super::string_list::{AvahiPair, AvahiStringListNode, ManagedAvahiStringList};
+use crate::txt_record::{self, TTxtRecord};
 use crate::Result;
 use libc::c_char;
 use std::cell::UnsafeCell;
+use std::ptr;
 
 pub struct AvahiTxtRecord(UnsafeCell<ManagedAvahiStringList>);
 
@@ -13,55 +14,71 @@ impl TTxtRecord for AvahiTxtRecord {
         Self(UnsafeCell::new(unsafe { ManagedAvahiStringList::new() }))
     }
 
-    fn insert(&mut self, key: &str, value: &str) -> Result<()> {
+    fn insert_bytes(&mut self, key: &str, value: &[u8]) -> Result<()> {
+        txt_record::validate_entry(key, value)?;
+
+        self.remove(key);
+
         let c_key = c_string!(key);
-        let c_value = c_string!(value);
 
         unsafe {
-            self.inner_mut().add_pair(
+            self.inner_mut().add_pair_arbitrary(
                 c_key.as_ptr() as *const c_char,
-                c_value.as_ptr() as *const c_char,
+                value.as_ptr(),
+                value.len(),
             );
         }
         Ok(())
     }
 
-    fn get(&self, key: &str) -> Option<String> {
-        let c_str = c_string!(key);
+    fn insert_key_only(&mut self, key: &str) -> Result<()> {
+        txt_record::validate_entry(key, &[])?;
+
+        self.remove(key);
+
+        let c_key = c_string!(key);
+
         unsafe {
             self.inner_mut()
-                .find(c_str.as_ptr() as *const c_char)?
+                .add_pair(c_key.as_ptr() as *const c_char, ptr::null());
+        }
+        Ok(())
+    }
+
+    fn get_bytes(&self, key: &str) -> Option<Vec<u8>> {
+        let c_str = txt_record::lookup_key(key)?;
+        unsafe {
+            self.inner_mut()
+                .find_last(c_str.as_ptr() as *const c_char)?
                 .get_pair()
-                .value()
-                .as_str()
-                .map(|s| s.to_string())
+                .value_bytes()
+                .map(|v| v.to_vec())
         }
     }
 
     fn remove(&mut self, key: &str) -> Option<String> {
-        let mut list = unsafe { ManagedAvahiStringList::new() };
-        let mut map = self.to_map();
-        let prev = map.remove(key);
-
-        for (key, value) in map {
-            let c_key = c_string!(key);
-            let c_value = c_string!(value);
-
-            unsafe {
-                list.add_pair(
-                    c_key.as_ptr() as *const c_char,
-                    c_value.as_ptr() as *const c_char,
-                );
-            }
-        }
+        let c_str = txt_record::lookup_key(key)?;
+
+        let prev = unsafe {
+            self.inner_mut()
+                .find_last(c_str.as_ptr() as *const c_char)?
+                .get_pair()
+                .value_bytes()
+                .map(|v| String::from_utf8_lossy(v).into_owned())
+                .unwrap_or_default()
+        };
 
-        self.0 = UnsafeCell::new(list);
+        while unsafe { self.inner_mut().remove(c_str.as_ptr() as *const c_char) } {}
 
-        prev
+        Some(prev)
     }
 
     fn contains_key(&self, key: &str) -> bool {
-        let c_str = c_string!(key);
+        let c_str = match txt_record::lookup_key(key) {
+            Some(c_str) => c_str,
+            None => return false,
+        };
+
         unsafe {
             self.inner_mut()
                 .find(c_str.as_ptr() as *const c_char)
@@ -70,11 +87,21 @@ impl TTxtRecord for AvahiTxtRecord {
     }
 
     fn len(&self) -> usize {
-        unsafe { self.inner().length() as usize }
+        let mut pairs = Pairs::new(self.inner_mut().head());
+        let mut len = 0;
+
+        while pairs.next_pair().is_some() {
+            len += 1;
+        }
+
+        len
     }
 
     fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (String, String)> + 'a> {
-        Box::new(Iter::new(self.inner_mut().head()))
+        Box::new(
+            Iter::new(self.inner_mut().head())
+                .map(|(key, value)| (key, String::from_utf8_lossy(&value).into_owned())),
+        )
     }
 
     fn keys<'a>(&'a self) -> Box<dyn Iterator<Item = String> + 'a> {
@@ -84,9 +111,39 @@ impl TTxtRecord for AvahiTxtRecord {
     fn values<'a>(&'a self) -> Box<dyn Iterator<Item = String> + 'a> {
         Box::new(Values(Iter::new(self.inner_mut().head())))
     }
+
+    fn iter_bytes<'a>(&'a self) -> Box<dyn Iterator<Item = (String, Vec<u8>)> + 'a> {
+        Box::new(Iter::new(self.inner_mut().head()))
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        unsafe { self.inner().serialize() }
+    }
+
+    fn from_bytes(data: &[u8]) -> Result<Self> {
+        let data = txt_record::sanitize_wire_format(data)?;
+        Ok(unsafe { ManagedAvahiStringList::parse(&data) }?.into())
+    }
 }
 
 impl AvahiTxtRecord {
+    /// Returns the value at the specified key without copying it, or `None` if no such key exists.
+    /// Keys without a value have an empty value.
+    pub(crate) fn value_ref(&self, key: &str) -> Option<&[u8]> {
+        let c_str = txt_record::lookup_key(key)?;
+
+        let text = unsafe {
+            self.inner_mut()
+                .find_last(c_str.as_ptr() as *const c_char)?
+                .text()
+        };
+
+        Some(match text.iter().position(|b| *b == b'=') {
+            Some(i) => &text[i + 1..],
+            None => &[],
+        })
+    }
+
     #[allow(clippy::mut_from_ref)]
     fn inner_mut(&self) -> &mut ManagedAvahiStringList {
         unsafe { &mut *self.0.get() }
@@ -115,44 +172,73 @@ impl PartialEq for AvahiTxtRecord {
     }
 }
 
-pub struct Iter<'a> {
+/// Walks the pairs of an `AvahiStringList`, skipping strings without a key. Those are never
+/// inserted by this crate, but records received from the network may contain them, e.g. the
+/// single empty string that stands for an empty record on the wire.
+struct Pairs<'a> {
     node: Option<AvahiStringListNode<'a>>,
 }
 
-impl<'a> Iter<'a> {
-    pub fn new(node: AvahiStringListNode<'a>) -> Self {
+impl<'a> Pairs<'a> {
+    fn new(node: AvahiStringListNode<'a>) -> Self {
         Self { node: Some(node) }
     }
-}
 
-impl Iterator for Iter<'_> {
-    type Item = (String, String);
+    fn next_pair(&mut self) -> Option<AvahiPair> {
+        loop {
+            let mut n = self.node.take()?;
 
-    fn next(&mut self) -> Option<Self::Item> {
-        let mut n = self.node.take()?;
+            if n.list().is_null() {
+                return None;
+            }
 
-        if n.list().is_null() {
-            return None;
+            let pair = unsafe { n.get_pair() };
+            self.node = unsafe { n.next() };
+
+            if !unsafe { pair.key().is_empty() } {
+                return Some(pair);
+            }
         }
+    }
+}
+
+/// Iterates over the entries of a record in insertion order.
+///
+/// `avahi_string_list_add_pair()` prepends to the list, which is therefore stored in reverse, so
+/// the entries are collected first and yielded back to front.
+pub struct Iter(std::iter::Rev<std::vec::IntoIter<(String, Vec<u8>)>>);
+
+impl Iter {
+    pub fn new(node: AvahiStringListNode<'_>) -> Self {
+        let mut pairs = Pairs::new(node);
+        let mut entries = vec![];
 
-        let pair = unsafe { n.get_pair() };
-        self.node = unsafe { n.next() };
+        while let Some(pair) = pairs.next_pair() {
+            // records received from the network may contain keys that are not valid UTF-8
+            let key = unsafe { pair.key().to_string_lossy() }.unwrap_or_default();
+
+            let value = unsafe { pair.value_bytes() }
+                .map(|v| v.to_vec())
+                .unwrap_or_default();
+
+            entries.push((key, value));
+        }
 
-        let key = unsafe { pair.key().as_str() }
-            .expect("could not key as str")
-            .to_string();
+        Self(entries.into_iter().rev())
+    }
+}
 
-        let value = unsafe { pair.value().as_str() }
-            .expect("could not get value as str")
-            .to_string();
+impl Iterator for Iter {
+    type Item = (String, Vec<u8>);
 
-        Some((key, value))
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.next()
     }
 }
 
-pub struct Keys<'a>(Iter<'a>);
+pub struct Keys(Iter);
 
-impl Iterator for Keys<'_> {
+impl Iterator for Keys {
     type Item = String;
 
     fn next(&mut self) -> Option<Self::Item> {
@@ -160,12 +246,53 @@ impl Iterator for Keys<'_> {
     }
 }
 
-pub struct Values<'a>(Iter<'a>);
+pub struct Values(Iter);
 
-impl Iterator for Values<'_> {
+impl Iterator for Values {
     type Item = String;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.0.next().map(|e| e.1)
+        self.0
+            .next()
+            .map(|e| String::from_utf8_lossy(&e.1).into_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn iter_replaces_invalid_utf8_in_received_keys() {
+        crate::tests::setup();
+
+        // bypasses `from_bytes()` like records received from the network do
+        let list = unsafe { ManagedAvahiStringList::parse(b"\x05\xffo=ba") }.unwrap();
+        let record = AvahiTxtRecord::from(list);
+
+        assert_eq!(
+            record.iter_bytes().collect::<Vec<_>>(),
+            vec![("\u{fffd}o".to_string(), b"ba".to_vec())]
+        );
+    }
+
+    #[test]
+    fn received_empty_strings_are_skipped() {
+        crate::tests::setup();
+
+        // an empty record is received as a single empty string
+        let list = unsafe { ManagedAvahiStringList::parse(b"\x00") }.unwrap();
+        let record = AvahiTxtRecord::from(list);
+
+        assert_eq!(record.len(), 0);
+        assert!(record.is_empty());
+        assert_eq!(record.iter_bytes().count(), 0);
+
+        let list = unsafe { ManagedAvahiStringList::parse(b"\x00\x03a=b\x04=foo") }.unwrap();
+        let record = AvahiTxtRecord::from(list);
+
+        assert_eq!(record.len(), 1);
+        assert_eq!(record.keys().collect::<Vec<_>>(), vec!["a".to_string()]);
+        assert_eq!(record.get_bytes(""), None);
     }
 }
@@ -0,0 +1,89 @@
+//! Rust friendly `AvahiServiceTypeBrowser` wrappers/helpers
+
+use std::sync::Arc;
+
+use crate::Result;
+use avahi_sys::{
+    avahi_service_type_browser_free, avahi_service_type_browser_new, AvahiIfIndex,
+    AvahiLookupFlags, AvahiProtocol, AvahiServiceTypeBrowser, AvahiServiceTypeBrowserCallback,
+};
+use libc::{c_char, c_void};
+
+use super::client::ManagedAvahiClient;
+
+/// Wraps the `AvahiServiceTypeBrowser` type from the raw Avahi bindings.
+///
+/// This struct allocates a new `*mut AvahiServiceTypeBrowser` when
+/// `ManagedAvahiServiceTypeBrowser::new()` is invoked and calls the Avahi function responsible
+/// for freeing the browser on `trait Drop`.
+#[derive(Debug)]
+pub struct ManagedAvahiServiceTypeBrowser {
+    inner: *mut AvahiServiceTypeBrowser,
+    _client: Arc<ManagedAvahiClient>,
+}
+
+impl ManagedAvahiServiceTypeBrowser {
+    /// Initializes the underlying `*mut AvahiServiceTypeBrowser` and verifies it was created;
+    /// returning `Err(String)` if unsuccessful.
+    ///
+    /// # Safety
+    /// This function is unsafe because of the raw pointer dereference.
+    pub unsafe fn new(
+        ManagedAvahiServiceTypeBrowserParams {
+            client,
+            interface,
+            protocol,
+            domain,
+            flags,
+            callback,
+            userdata,
+        }: ManagedAvahiServiceTypeBrowserParams,
+    ) -> Result<Self> {
+        let inner = avahi_service_type_browser_new(
+            client.inner,
+            interface,
+            protocol,
+            domain,
+            flags,
+            callback,
+            userdata,
+        );
+
+        if inner.is_null() {
+            Err("could not initialize Avahi service type browser".into())
+        } else {
+            Ok(Self {
+                inner,
+                _client: client,
+            })
+        }
+    }
+}
+
+impl Drop for ManagedAvahiServiceTypeBrowser {
+    fn drop(&mut self) {
+        unsafe { avahi_service_type_browser_free(self.inner) };
+    }
+}
+
+// SAFETY: Avahi objects are not tied to the thread that created them. The type browser of a meta
+// query is only created and freed while the `Mutex` of its `AvahiBrowserContext` is held, which
+// `type_browse_callback` takes as well.
+unsafe impl Send for ManagedAvahiServiceTypeBrowser {}
+
+/// Holds parameters for initializing a new `ManagedAvahiServiceTypeBrowser` with
+/// `ManagedAvahiServiceTypeBrowser::new()`.
+///
+/// See [`avahi_service_type_browser_new()`] for more information about these parameters.
+///
+/// [`avahi_service_type_browser_new()`]: https://avahi.org/doxygen/html/lookup_8h.html
+#[derive(Builder, BuilderDelegate)]
+pub struct ManagedAvahiServiceTypeBrowserParams {
+    client: Arc<ManagedAvahiClient>,
+    interface: AvahiIfIndex,
+    protocol: AvahiProtocol,
+    domain: *const c_char,
+    flags: AvahiLookupFlags,
+    callback: AvahiServiceTypeBrowserCallback,
+    userdata: *mut c_void,
+}
@@ -0,0 +1,110 @@
+//! Information about the mDNS stack in use, e.g. for bug reports
+
+use crate::Result;
+use std::fmt;
+
+/// The mDNS stack a [`BackendInfo`] describes.
+///
+/// [`BackendInfo`]: struct.BackendInfo.html
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BackendKind {
+    /// Avahi, used on Linux
+    Avahi,
+    /// Bonjour, used on macOS and Windows
+    Bonjour,
+    /// The in-memory backend of the `test-mock` feature
+    Mock,
+}
+
+impl fmt::Display for BackendKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            BackendKind::Avahi => "Avahi",
+            BackendKind::Bonjour => "Bonjour",
+            BackendKind::Mock => "mock",
+        })
+    }
+}
+
+/// Describes the mDNS stack and version in use, as returned by [`backend_info()`].
+///
+/// * Avahi reports a single version string (e.g. `avahi 0.8`), which is that of the daemon, so
+///   `daemon_version` is always `None`.
+/// * Bonjour reports the version of the `dns_sd.h` API the crate was built against as `version`
+///   and the version of mDNSResponder, or the Bonjour service on Windows, as `daemon_version`.
+///   Both are formatted like `1310.80.1`.
+/// * With the `test-mock` feature, `version` is the version of this crate.
+///
+/// [`backend_info()`]: fn.backend_info.html
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Getters, Builder, BuilderDelegate, Clone, PartialEq, Eq)]
+pub struct BackendInfo {
+    kind: BackendKind,
+    version: String,
+    #[builder(default)]
+    daemon_version: Option<String>,
+}
+
+impl fmt::Display for BackendInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.kind, self.version)?;
+
+        if let Some(daemon_version) = &self.daemon_version {
+            write!(f, " (daemon {})", daemon_version)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the [`BackendInfo`] of the mDNS stack in use, for capturing it in diagnostics.
+///
+/// # Note
+/// Both Avahi and Bonjour are asked for their version, so the daemon must be running. The
+/// services and browsers of this crate also expose the information of the connection they hold
+/// through `backend_info()`.
+///
+/// [`BackendInfo`]: struct.BackendInfo.html
+pub fn backend_info() -> Result<BackendInfo> {
+    #[cfg(all(target_os = "linux", not(feature = "test-mock")))]
+    return crate::avahi::client::backend_info();
+    #[cfg(all(
+        any(target_vendor = "apple", target_vendor = "pc"),
+        not(feature = "test-mock")
+    ))]
+    return crate::bonjour::bonjour_util::backend_info();
+    #[cfg(feature = "test-mock")]
+    return Ok(crate::mock::backend_info());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::*;
+
+    #[test]
+    fn display_includes_daemon_version() {
+        let info = BackendInfo::builder()
+            .kind(BackendKind::Bonjour)
+            .version("1661.0.0".to_string())
+            .daemon_version(Some("1310.80.1".to_string()))
+            .build()
+            .unwrap();
+
+        assert_eq!(info.to_string(), "Bonjour 1661.0.0 (daemon 1310.80.1)");
+    }
+
+    #[test]
+    fn backend_info_reports_non_empty_version() {
+        crate::tests::setup();
+
+        let info = backend_info().unwrap();
+
+        assert!(!info.version().is_empty());
+
+        if let Some(daemon_version) = info.daemon_version() {
+            assert!(!daemon_version.is_empty());
+        }
+    }
+}
@@ -1,26 +1,132 @@
 //! Utilities related to Bonjour
 
-use std::{ffi::CString, str::FromStr};
+use std::ffi::{CStr, CString};
+use std::net::IpAddr;
+#[cfg(target_vendor = "apple")]
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 
 use super::constants;
-use crate::{check_valid_characters, lstrip_underscore, NetworkInterface, Result, ServiceType};
-use bonjour_sys::DNSServiceErrorType;
+use super::service_ref::{self, ManagedDNSServiceRef};
+#[cfg(target_vendor = "apple")]
+use crate::daemon;
+use crate::daemon::Diagnosis;
+use crate::error::Error;
+use crate::prelude::*;
+use crate::{
+    BackendInfo, BackendKind, NetworkInterface, Protocol, RegistrationFlags, Result, ServiceType,
+};
+use bonjour_sys::{sockaddr, DNSServiceErrorType, DNSServiceFlags, DNSServiceProtocol};
+#[cfg(target_vendor = "pc")]
+use bonjour_sys::{sockaddr_in, sockaddr_in6};
+#[cfg(target_vendor = "apple")]
+use libc::{sockaddr_in, sockaddr_in6, AF_INET, AF_INET6};
+
+// Winsock address families, not provided by `libc` on Windows
+#[cfg(target_vendor = "pc")]
+const AF_INET: i32 = 2;
+#[cfg(target_vendor = "pc")]
+const AF_INET6: i32 = 23;
+
+/// Returns the fully qualified host name services are registered under by default (e.g.
+/// `myhost.local`).
+///
+/// Bonjour does not expose the host name it advertises, so it is derived from the system's host
+/// name. On macOS, this may differ from the "Local Hostname" configured in the sharing settings.
+pub fn host_name() -> Result<String> {
+    local_host_name(&system_host_name()?)
+}
+
+/// Returns true if a connection to the Bonjour daemon can be established.
+pub fn is_available() -> bool {
+    match unsafe { ManagedDNSServiceRef::new().create_connection() } {
+        Ok(()) => true,
+        Err(error) => {
+            debug!("Bonjour is not available: {}", error);
+            false
+        }
+    }
+}
+
+/// Checks the socket of mDNSResponder on macOS, and connects to the Bonjour daemon.
+pub fn diagnose() -> Diagnosis {
+    #[cfg(target_vendor = "apple")]
+    return diagnose_socket(PathBuf::from(DAEMON_SOCKET));
+
+    #[cfg(target_vendor = "pc")]
+    return Diagnosis::new(None, Ok(()), connect());
+}
+
+/// Like [`diagnose()`], but checks the socket at `socket_path` rather than the one of
+/// mDNSResponder. The connection is still made to mDNSResponder.
+#[cfg(target_vendor = "apple")]
+pub(crate) fn diagnose_socket(socket_path: PathBuf) -> Diagnosis {
+    let socket_access = daemon::check_socket_access(&socket_path);
+    Diagnosis::new(Some(socket_path), socket_access, connect())
+}
+
+fn connect() -> Result<()> {
+    unsafe { ManagedDNSServiceRef::new().create_connection() }
+}
+
+/// Returns the [`BackendInfo`] of Bonjour: the version of the `dns_sd.h` API this crate was built
+/// against and the version of the running daemon.
+///
+/// [`BackendInfo`]: ../../struct.BackendInfo.html
+pub fn backend_info() -> Result<BackendInfo> {
+    let daemon_version = unsafe { service_ref::get_daemon_version() }?;
+
+    Ok(BackendInfo::builder()
+        .kind(BackendKind::Bonjour)
+        .version(format_version(bonjour_sys::_DNS_SD_H))
+        .daemon_version(Some(format_version(daemon_version)))
+        .build()?)
+}
+
+/// Formats a Bonjour version number, which is encoded as `AAAABBCC` for version `AAAA.BB.CC`
+/// (e.g. `13108001` for `1310.80.1`).
+pub fn format_version(version: u32) -> String {
+    format!(
+        "{}.{}.{}",
+        version / 10000,
+        version / 100 % 100,
+        version % 100
+    )
+}
+
+fn local_host_name(host_name: &str) -> Result<String> {
+    match host_name.split('.').next() {
+        Some(label) if !label.is_empty() => Ok(format!("{}.local", label)),
+        _ => Err(format!("invalid host name `{}`", host_name).into()),
+    }
+}
+
+#[cfg(target_vendor = "apple")]
+fn system_host_name() -> Result<String> {
+    let mut buf = [0 as libc::c_char; 256];
+
+    if unsafe { libc::gethostname(buf.as_mut_ptr(), buf.len()) } != 0 {
+        return Err(format!("gethostname() failed: {}", std::io::Error::last_os_error()).into());
+    }
+
+    // the result is not terminated if it was truncated
+    buf[buf.len() - 1] = 0;
+
+    Ok(unsafe { crate::ffi::c_str::try_raw_to_str(buf.as_ptr(), "host name") }?.to_string())
+}
+
+#[cfg(target_vendor = "pc")]
+fn system_host_name() -> Result<String> {
+    std::env::var("COMPUTERNAME")
+        .map_err(|_| "could not get host name: COMPUTERNAME is not set".into())
+}
 
 /// Normalizes the specified domain `&str` to conform to a standard enforced by this crate.
 ///
 /// Bonjour suffixes domains with a final `'.'` character in some contexts but is not required by
 /// the standard. This function removes the final dot if present.
 pub fn normalize_domain(domain: &str) -> String {
-    let end = domain
-        .chars()
-        .nth(domain.len() - 1)
-        .expect("could not index domain string");
-
-    if end == '.' {
-        String::from(&domain[..domain.len() - 1])
-    } else {
-        String::from(domain)
-    }
+    String::from(domain.strip_suffix('.').unwrap_or(domain))
 }
 
 /// Converts the specified [`NetworkInterface`] to the Bonjour expected value.
@@ -30,6 +136,7 @@ pub fn interface_index(interface: NetworkInterface) -> u32 {
     match interface {
         NetworkInterface::Unspec => constants::BONJOUR_IF_UNSPEC,
         NetworkInterface::AtIndex(i) => i,
+        NetworkInterface::LocalOnly => constants::BONJOUR_IF_LOCAL_ONLY,
     }
 }
 
@@ -37,28 +144,99 @@ pub fn interface_index(interface: NetworkInterface) -> u32 {
 pub fn interface_from_index(index: u32) -> NetworkInterface {
     match index {
         constants::BONJOUR_IF_UNSPEC => NetworkInterface::Unspec,
+        constants::BONJOUR_IF_LOCAL_ONLY => NetworkInterface::LocalOnly,
         _ => NetworkInterface::AtIndex(index),
     }
 }
 
+/// Converts the specified [`Protocol`] to the `DNSServiceProtocol` passed to
+/// `DNSServiceGetAddrInfo()`. `Protocol::Any` maps to `0`, which lets mDNSResponder look up the
+/// addresses of the protocols that are configured on the machine.
+///
+/// [`Protocol`]: ../../enum.Protocol.html
+pub fn address_protocol(protocol: Protocol) -> DNSServiceProtocol {
+    match protocol {
+        Protocol::Any => 0,
+        Protocol::V4 => bonjour_sys::kDNSServiceProtocol_IPv4,
+        Protocol::V6 => bonjour_sys::kDNSServiceProtocol_IPv6,
+    }
+}
+
 /// Executes the specified closure and returns a formatted `Result`
 pub fn sys_exec<F: FnOnce() -> DNSServiceErrorType>(func: F, message: &str) -> Result<()> {
     let err = func();
 
-    if err < 0 {
-        Err(format!("{} (code: {})", message, err).into())
-    } else {
+    if err >= 0 {
         Ok(())
+    } else if is_permission_denied(err) {
+        Err(Error::PermissionDenied {
+            detail: format!("{}: could not connect to {}", message, DAEMON_ENDPOINT),
+        })
+    } else if err == bonjour_sys::kDNSServiceErr_ServiceNotRunning {
+        Err(Error::DaemonNotRunning {
+            detail: format!("{}: could not connect to {}", message, DAEMON_ENDPOINT),
+        })
+    } else {
+        Err(format!("{} (code: {})", message, err).into())
+    }
+}
+
+/// Socket that the client library connects to mDNSResponder through
+#[cfg(target_vendor = "apple")]
+const DAEMON_SOCKET: &str = "/var/run/mDNSResponder";
+
+#[cfg(target_vendor = "apple")]
+const DAEMON_ENDPOINT: &str = "mDNSResponder at `/var/run/mDNSResponder`";
+#[cfg(target_vendor = "pc")]
+const DAEMON_ENDPOINT: &str = "the Bonjour service";
+
+/// Returns true if `err` indicates that the process is not allowed to talk to the daemon.
+///
+/// The client library reports a refused socket connection as `kDNSServiceErr_ServiceNotRunning`,
+/// so the permissions of the socket of mDNSResponder are checked as well.
+fn is_permission_denied(err: DNSServiceErrorType) -> bool {
+    if err == bonjour_sys::kDNSServiceErr_NoAuth {
+        return true;
     }
+
+    #[cfg(target_vendor = "apple")]
+    if err == bonjour_sys::kDNSServiceErr_ServiceNotRunning {
+        return matches!(
+            daemon::check_socket_access(Path::new(DAEMON_SOCKET)),
+            Err(Error::PermissionDenied { .. })
+        );
+    }
+
+    false
+}
+
+/// Converts the specified [`RegistrationFlags`] to the Bonjour expected value.
+///
+/// Returns `Error::Unsupported` if any of the flags has no Bonjour equivalent.
+///
+/// [`RegistrationFlags`]: ../../struct.RegistrationFlags.html
+pub fn register_flags(flags: RegistrationFlags) -> Result<DNSServiceFlags> {
+    let unsupported = flags.difference(RegistrationFlags::NO_PROBE);
+
+    if !unsupported.is_empty() {
+        return Err(Error::Unsupported(format!(
+            "registration flags not supported by Bonjour: {:?}",
+            unsupported
+        )));
+    }
+
+    let mut register_flags = constants::BONJOUR_RENAME_FLAGS;
+
+    if flags.contains(RegistrationFlags::NO_PROBE) {
+        register_flags |= bonjour_sys::kDNSServiceFlagsKnownUnique as DNSServiceFlags;
+    }
+
+    Ok(register_flags)
 }
 
 /// Formats the specified `ServiceType` as a `CString` for use with Bonjour
 pub fn format_regtype(service_type: &ServiceType) -> CString {
-    let mut regtype = vec![format!(
-        "_{}._{}",
-        service_type.name(),
-        service_type.protocol()
-    )];
+    let mut regtype = vec![service_type.to_string()];
 
     regtype.extend(
         service_type
@@ -70,23 +248,119 @@ pub fn format_regtype(service_type: &ServiceType) -> CString {
     c_string!(regtype.join(","))
 }
 
-/// Parses the specified `&str` into a `ServiceType`
+/// Parses the specified `&str` into a `ServiceType`. Sub-types are expected in the form produced
+/// by [`format_regtype()`] (e.g. `_http._tcp,_printer`).
+///
+/// [`format_regtype()`]: fn.format_regtype.html
 pub fn parse_regtype(regtype: &str) -> Result<ServiceType> {
-    let types = regtype.split(',').collect::<Vec<_>>();
-    let service_type = ServiceType::from_str(types[0])?;
+    ServiceType::from_str(regtype)
+}
 
-    let sub_types = types[1..]
-        .iter()
-        .map(|s| check_valid_characters(lstrip_underscore(s)))
-        .collect::<Result<Vec<_>>>()?;
+/// Returns the escaped full name of the service instance `name` of `service_type` in `domain`,
+/// constructed by `DNSServiceConstructFullName`. Sub-types are not included.
+pub fn construct_full_name(name: &str, service_type: &ServiceType, domain: &str) -> Result<String> {
+    let name = c_string!(name);
+    let regtype = c_string!(service_type.to_string());
+    let domain = c_string!(domain);
+    let mut buffer = vec![0 as libc::c_char; bonjour_sys::kDNSServiceMaxDomainName as usize];
 
-    ServiceType::with_sub_types(service_type.name(), service_type.protocol(), sub_types)
+    sys_exec(
+        || unsafe {
+            bonjour_sys::DNSServiceConstructFullName(
+                buffer.as_mut_ptr(),
+                name.as_ptr(),
+                regtype.as_ptr(),
+                domain.as_ptr(),
+            )
+        },
+        "could not construct full name",
+    )?;
+
+    Ok(unsafe { CStr::from_ptr(buffer.as_ptr()) }
+        .to_string_lossy()
+        .into_owned())
+}
+
+/// Converts the specified `*const sockaddr` reported by Bonjour to an `IpAddr`. Both IPv4 and IPv6
+/// addresses are supported.
+///
+/// # Safety
+/// This function is unsafe because of the raw pointer dereference.
+pub unsafe fn sockaddr_to_ip(address: *const sockaddr) -> Result<IpAddr> {
+    ensure_not_null!(address, "address");
+
+    match (*address).sa_family as i32 {
+        AF_INET => {
+            let address = &*(address as *const sockaddr_in);
+
+            #[cfg(target_vendor = "apple")]
+            let octets = address.sin_addr.s_addr.to_ne_bytes();
+
+            #[cfg(target_vendor = "pc")]
+            let octets = {
+                let s_un = address.sin_addr.S_un.S_un_b;
+                [s_un.s_b1, s_un.s_b2, s_un.s_b3, s_un.s_b4]
+            };
+
+            Ok(IpAddr::from(octets))
+        }
+        AF_INET6 => {
+            let address = &*(address as *const sockaddr_in6);
+
+            #[cfg(target_vendor = "apple")]
+            let octets = address.sin6_addr.s6_addr;
+
+            #[cfg(target_vendor = "pc")]
+            let octets = address.sin6_addr.u.Byte;
+
+            Ok(IpAddr::from(octets))
+        }
+        family => Err(format!("unsupported address family: {}", family).into()),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::ServiceType;
+    use proptest::prelude::*;
+
+    #[test]
+    fn format_version_splits_components() {
+        assert_eq!(format_version(13108001), "1310.80.1");
+        assert_eq!(format_version(16610000), "1661.0.0");
+    }
+
+    #[test]
+    fn sockaddr_to_ip_null_returns_error() {
+        assert_eq!(
+            unsafe { sockaddr_to_ip(std::ptr::null()) },
+            Err("missing address".into())
+        );
+    }
+
+    #[test]
+    fn register_flags_maps_no_probe() {
+        assert_eq!(
+            register_flags(RegistrationFlags::NONE),
+            Ok(constants::BONJOUR_RENAME_FLAGS)
+        );
+        assert_eq!(
+            register_flags(RegistrationFlags::NO_PROBE),
+            Ok(constants::BONJOUR_RENAME_FLAGS
+                | bonjour_sys::kDNSServiceFlagsKnownUnique as DNSServiceFlags)
+        );
+    }
+
+    #[test]
+    fn register_flags_rejects_unsupported() {
+        assert_eq!(
+            register_flags(RegistrationFlags::NO_PROBE | RegistrationFlags::UPDATE),
+            Err(Error::Unsupported(
+                "registration flags not supported by Bonjour: UPDATE".to_string()
+            ))
+        );
+    }
 
     #[test]
     fn parse_regtype_success() {
@@ -138,6 +412,25 @@ mod tests {
         );
     }
 
+    #[test]
+    fn sys_exec_returns_permission_denied_for_no_auth() {
+        assert!(matches!(
+            sys_exec(|| bonjour_sys::kDNSServiceErr_NoAuth, "could not browse"),
+            Err(Error::PermissionDenied { .. })
+        ));
+    }
+
+    #[test]
+    fn sys_exec_returns_daemon_not_running_for_service_not_running() {
+        assert!(matches!(
+            sys_exec(
+                || bonjour_sys::kDNSServiceErr_ServiceNotRunning,
+                "could not browse"
+            ),
+            Err(Error::DaemonNotRunning { .. })
+        ));
+    }
+
     #[test]
     fn sys_exec_returns_ok() {
         assert_eq!(sys_exec(|| 0, "success"), Ok(()));
@@ -153,6 +446,30 @@ mod tests {
         assert_eq!(interface_index(NetworkInterface::AtIndex(42)), 42);
     }
 
+    #[test]
+    fn network_interface_local_only_maps_to_bonjour_local_only() {
+        assert_eq!(interface_index(NetworkInterface::LocalOnly), u32::MAX);
+        assert_eq!(interface_from_index(u32::MAX), NetworkInterface::LocalOnly);
+    }
+
+    #[test]
+    fn local_host_name_replaces_domain_with_local() {
+        assert_eq!(local_host_name("myhost"), Ok("myhost.local".to_string()));
+        assert_eq!(
+            local_host_name("MyMac.local"),
+            Ok("MyMac.local".to_string())
+        );
+        assert_eq!(
+            local_host_name("myhost.example.com."),
+            Ok("myhost.local".to_string())
+        );
+    }
+
+    #[test]
+    fn local_host_name_rejects_empty_host_name() {
+        assert_eq!(local_host_name(""), Err("invalid host name ``".into()));
+    }
+
     #[test]
     fn normalize_domain_removes_trailing_dot() {
         assert_eq!(
@@ -165,4 +482,30 @@ mod tests {
     fn normalize_domain_does_not_remove_trailing_dot_if_not_present() {
         assert_eq!(normalize_domain("foo.bar.baz"), String::from("foo.bar.baz"));
     }
+
+    #[test]
+    fn normalize_domain_accepts_empty_domain() {
+        assert_eq!(normalize_domain(""), String::new());
+    }
+
+    proptest! {
+        #[test]
+        fn normalize_domain_removes_at_most_one_trailing_dot(domain in ".*") {
+            let normalized = normalize_domain(&domain);
+
+            prop_assert!(domain.starts_with(&normalized));
+            prop_assert!(domain.len() - normalized.len() <= 1);
+            prop_assert_eq!(normalized.len() < domain.len(), domain.ends_with('.'));
+        }
+    }
+
+    #[test]
+    fn sockaddr_to_ip_returns_error_for_unsupported_family() {
+        let address: sockaddr = unsafe { std::mem::zeroed() };
+
+        assert_eq!(
+            unsafe { sockaddr_to_ip(&address) },
+            Err("unsupported address family: 0".into())
+        );
+    }
 }
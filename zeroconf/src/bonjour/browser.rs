@@ -1,45 +1,64 @@
 //! Bonjour implementation for cross-platform browser
 
-use super::service_ref::{
-    BrowseServicesParams, GetAddressInfoParams, ManagedDNSServiceRef, ServiceResolveParams,
-};
-use super::txt_record_ref::ManagedTXTRecordRef;
+use super::resolver::{self, BonjourResolverContext, Resolution, ResolveTimeouts};
+use super::service_ref::{BrowseServicesParams, SharedConnection, SharedServiceRef};
 use super::{bonjour_util, constants};
-use crate::ffi::{c_str, AsRaw, FromRaw};
+use crate::cache::CachedService;
+#[cfg(test)]
+use crate::clock::Clock;
+use crate::dedup::Deduplicator;
+use crate::error::{Error, ResultExt};
+use crate::ffi::c_str::{self, AsCChars};
+use crate::ffi::{self, AsRaw, DropAfterDispatch, UnwrapOrNull};
+use crate::interface_watcher;
 use crate::prelude::*;
-use crate::{EventLoop, NetworkInterface, Result, ServiceType, TxtRecord};
-use crate::{ServiceDiscoveredCallback, ServiceDiscovery};
-#[cfg(target_vendor = "pc")]
-use bonjour_sys::sockaddr_in;
+use crate::settle::SettleTracker;
+use crate::{
+    BackendInfo, DaemonStateCallback, EventLoop, Id, LookupFlags, NetworkInterface, Protocol,
+    Result, ServiceKey, ServiceType,
+};
+use crate::{
+    BrowserEvent, BrowserEventCallback, BrowserStats, ServiceDiscoveredCallback, ServiceDiscovery,
+    SettledCallback,
+};
 use bonjour_sys::{DNSServiceErrorType, DNSServiceFlags, DNSServiceRef};
-#[cfg(target_vendor = "apple")]
-use libc::sockaddr_in;
-use libc::{c_char, c_uchar, c_void};
+use libc::{c_char, c_void};
 use std::any::Any;
 use std::ffi::CString;
-use std::fmt::{self, Formatter};
-use std::net::IpAddr;
-use std::ptr;
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Duration;
 
+/// The browse and the resolutions of the services it finds share a single connection, which is
+/// polled by the event loop.
 #[derive(Debug)]
 pub struct BonjourMdnsBrowser {
-    service: Arc<Mutex<ManagedDNSServiceRef>>,
     kind: CString,
     interface_index: u32,
-    context: Box<BonjourBrowserContext>,
+    domain: Option<CString>,
+    browse: Option<SharedServiceRef>,
+    context: DropAfterDispatch<Box<BonjourResolverContext>>,
 }
 
 impl TMdnsBrowser for BonjourMdnsBrowser {
     fn new(service_type: ServiceType) -> Self {
+        let mut context = Box::new(BonjourResolverContext::new());
+
+        context.meta_query = service_type.is_meta_query();
+        context.service_type = Some(service_type.clone());
+
         Self {
-            service: Arc::default(),
             kind: bonjour_util::format_regtype(&service_type),
             interface_index: constants::BONJOUR_IF_UNSPEC,
-            context: Box::default(),
+            domain: None,
+            browse: None,
+            context: DropAfterDispatch::new(context),
         }
     }
 
+    fn id(&self) -> Id {
+        self.context.id
+    }
+
     fn set_network_interface(&mut self, interface: NetworkInterface) {
         self.interface_index = bonjour_util::interface_index(interface);
     }
@@ -48,79 +67,273 @@ impl TMdnsBrowser for BonjourMdnsBrowser {
         bonjour_util::interface_from_index(self.interface_index)
     }
 
+    fn set_domain(&mut self, domain: &str) {
+        self.domain = Some(c_string!(domain));
+    }
+
+    fn domain(&self) -> Option<&str> {
+        self.domain.as_ref().map(c_str::to_str)
+    }
+
+    fn set_protocol(&mut self, protocol: Protocol) {
+        self.context.protocol = protocol;
+    }
+
+    fn protocol(&self) -> Protocol {
+        self.context.protocol
+    }
+
+    fn set_lookup_flags(&mut self, flags: LookupFlags) {
+        self.context.lookup_flags = flags;
+    }
+
+    fn lookup_flags(&self) -> LookupFlags {
+        self.context.lookup_flags
+    }
+
+    fn set_deduplicate(&mut self, deduplicate: bool) {
+        self.context.dedup = deduplicate.then(Deduplicator::default);
+    }
+
+    fn deduplicate(&self) -> bool {
+        self.context.dedup.is_some()
+    }
+
+    fn set_name_filter(&mut self, name_filter: impl Fn(&str) -> bool + Send + 'static) {
+        self.context.filter.set_name_filter(Box::new(name_filter));
+    }
+
+    fn set_discovery_filter(
+        &mut self,
+        discovery_filter: impl Fn(&ServiceDiscovery) -> bool + Send + 'static,
+    ) {
+        self.context
+            .filter
+            .set_discovery_filter(Box::new(discovery_filter));
+    }
+
+    fn set_max_concurrent_resolves(&mut self, max_concurrent_resolves: usize) {
+        self.context
+            .resolutions
+            .set_max_concurrent(max_concurrent_resolves);
+    }
+
+    fn max_concurrent_resolves(&self) -> usize {
+        self.context.resolutions.max_concurrent()
+    }
+
+    fn set_resolve_timeout(&mut self, timeout: Duration) {
+        self.context.resolutions.set_timeout(timeout);
+    }
+
+    fn resolve_timeout(&self) -> Duration {
+        self.context.resolutions.timeout()
+    }
+
+    fn set_rescan_deadline(&mut self, deadline: Duration) {
+        self.context.rescan_deadline = deadline;
+    }
+
+    fn rescan_deadline(&self) -> Duration {
+        self.context.rescan_deadline
+    }
+
+    fn set_grace_period(&mut self, grace_period: Duration) {
+        self.context
+            .update_cache(|c| c.set_grace_period(grace_period));
+    }
+
+    fn grace_period(&self) -> Duration {
+        self.context
+            .cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .grace_period()
+    }
+
     fn set_service_discovered_callback(
         &mut self,
         service_discovered_callback: Box<ServiceDiscoveredCallback>,
     ) {
-        self.context.service_discovered_callback = Some(service_discovered_callback);
+        self.context.service_discovered_callback = Some(Mutex::new(service_discovered_callback));
+    }
+
+    fn set_browser_event_callback(&mut self, browser_event_callback: Box<BrowserEventCallback>) {
+        self.context.browser_event_callback = Some(browser_event_callback);
     }
 
-    fn set_context(&mut self, context: Box<dyn Any>) {
+    fn set_context(&mut self, context: Box<dyn Any + Send + Sync>) {
         self.context.user_context = Some(Arc::from(context));
     }
 
-    fn context(&self) -> Option<&dyn Any> {
+    fn context(&self) -> Option<&(dyn Any + Send + Sync)> {
         self.context.user_context.as_ref().map(|c| c.as_ref())
     }
 
+    fn on_settled(&mut self, deadline: Duration, settled_callback: Box<SettledCallback>) {
+        self.context.settle = Some(Arc::new(Mutex::new(SettleTracker::new(
+            deadline,
+            settled_callback,
+            self.context.clock.clone(),
+        ))));
+    }
+
+    /// Bonjour connects to the daemon synchronously, so waiting for it is not supported.
+    fn set_wait_for_daemon(&mut self, wait_for_daemon: bool) -> Result<()> {
+        if wait_for_daemon {
+            return Err(Error::Unsupported(
+                "Bonjour does not support waiting for the daemon".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn wait_for_daemon(&self) -> bool {
+        false
+    }
+
+    /// Bonjour reports failures to connect to the daemon from `browse_services()`, so the callback is
+    /// never invoked.
+    fn set_daemon_state_callback(&mut self, _daemon_state_callback: Box<DaemonStateCallback>) {}
+
     fn browse_services(&mut self) -> Result<EventLoop> {
-        debug!("Browsing services: {:?}", self);
+        let span = span!(
+            "browse",
+            id = %self.context.id,
+            service_type = c_str::to_str(&self.kind)
+        );
+        let _entered = span.enter();
+        self.context.span = span.clone();
+
+        debug!("[{}] Browsing services: {:?}", self.context.id, self);
+
+        self.context.update_settle(|s| s.start());
+        self.context.update_cache(|c| c.reset());
+        self.context.interfaces = interface_watcher::watch();
+
+        let connection =
+            unsafe { SharedConnection::new() }.with_context(|| self.context.browse_context())?;
+
+        // the previous browse and its resolutions are terminated before their connection
+        self.browse = None;
+        self.context.resolutions.clear();
+        self.context.connection = Some(connection.clone());
+
+        self.browse()
+            .with_context(|| self.context.browse_context())?;
+
+        let event_loop = EventLoop::new(connection.service())
+            .with_resolve_timeouts(ResolveTimeouts::new(&mut self.context));
 
-        let mut service_lock = self
-            .service
+        debug!("[{}] Event loop: {}", self.context.id, event_loop.id());
+
+        Ok(match &self.context.settle {
+            Some(settle) => event_loop.with_settle_tracker(settle.clone()),
+            None => event_loop,
+        })
+    }
+
+    fn rescan(&mut self) -> Result<()> {
+        if self.browse.is_none() {
+            return Err("browser has not been started".into());
+        }
+
+        debug!("[{}] Rescanning services", self.context.id);
+
+        let now = self.context.clock.now();
+        let deadline = self.context.rescan_deadline;
+
+        self.context
+            .update_cache(|c| c.rescan_started(deadline, now));
+
+        self.browse()
+    }
+
+    fn stats(&self) -> BrowserStats {
+        let stats = *self
+            .context
+            .stats
             .lock()
-            .expect("should have been able to obtain lock on service ref");
+            .unwrap_or_else(PoisonError::into_inner);
+
+        stats.with_active_resolvers(self.context.resolutions.len())
+    }
+
+    fn known_services(&self) -> Vec<CachedService> {
+        self.context
+            .cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .snapshot()
+    }
+
+    fn backend_info(&self) -> Result<BackendInfo> {
+        if self.browse.is_none() {
+            return Err("browser has not been started".into());
+        }
+
+        bonjour_util::backend_info()
+    }
+}
+
+impl BonjourMdnsBrowser {
+    /// Sets the [`Clock`] the settle deadline and the resolve timeouts are measured with. Must be
+    /// called before `on_settled()`.
+    ///
+    /// [`Clock`]: ../../clock/trait.Clock.html
+    #[cfg(test)]
+    pub(crate) fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.context.clock = clock;
+    }
+
+    /// Starts browsing on the connection of the browser, replacing any previous browse.
+    fn browse(&mut self) -> Result<()> {
+        // dropping the previous browse terminates it
+        self.browse = None;
+        self.context.all_for_now = false;
+        self.context.pending_flush = None;
+
+        if let Some(dedup) = &mut self.context.dedup {
+            dedup.clear();
+        }
+
+        self.context.filter.clear();
+
+        let mut flags = bonjour_sys::kDNSServiceFlagsShareConnection;
+
+        if self
+            .context
+            .lookup_flags
+            .contains(LookupFlags::USE_MULTICAST)
+        {
+            flags |= bonjour_sys::kDNSServiceFlagsForceMulticast;
+        }
 
         let browse_params = BrowseServicesParams::builder()
-            .flags(0)
+            .flags(flags)
             .interface_index(self.interface_index)
             .regtype(self.kind.as_ptr())
-            .domain(ptr::null_mut())
+            .domain(self.domain.as_ref().as_c_chars().unwrap_or_null())
             .callback(Some(browse_callback))
             .context(self.context.as_raw())
             .build()?;
 
-        unsafe { service_lock.browse_services(browse_params)? };
+        let connection = self
+            .context
+            .connection
+            .as_ref()
+            .ok_or("browser has not been started")?;
 
-        Ok(EventLoop::new(self.service.clone()))
-    }
-}
+        self.browse = Some(unsafe { connection.start(|s| s.browse_services(browse_params)) }?);
 
-#[derive(Default, FromRaw, AsRaw)]
-struct BonjourBrowserContext {
-    service_discovered_callback: Option<Box<ServiceDiscoveredCallback>>,
-    resolved_name: Option<String>,
-    resolved_kind: Option<String>,
-    resolved_domain: Option<String>,
-    resolved_port: u16,
-    resolved_txt: Option<TxtRecord>,
-    user_context: Option<Arc<dyn Any>>,
-}
-
-impl BonjourBrowserContext {
-    fn invoke_callback(&self, result: Result<ServiceDiscovery>) {
-        if let Some(f) = &self.service_discovered_callback {
-            f(result, self.user_context.clone());
-        } else {
-            warn!("attempted to invoke callback but none was set");
-        }
-    }
-}
-
-impl fmt::Debug for BonjourBrowserContext {
-    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
-        f.debug_struct("BonjourResolverContext")
-            .field("resolved_name", &self.resolved_name)
-            .field("resolved_kind", &self.resolved_kind)
-            .field("resolved_domain", &self.resolved_domain)
-            .field("resolved_port", &self.resolved_port)
-            .finish()
+        Ok(())
     }
 }
 
 unsafe extern "system" fn browse_callback(
     _sd_ref: DNSServiceRef,
-    _flags: DNSServiceFlags,
+    flags: DNSServiceFlags,
     interface_index: u32,
     error: DNSServiceErrorType,
     name: *const c_char,
@@ -128,192 +341,173 @@ unsafe extern "system" fn browse_callback(
     domain: *const c_char,
     context: *mut c_void,
 ) {
-    let ctx = BonjourBrowserContext::from_raw(context);
-    if let Err(e) = handle_browse(ctx, error, name, regtype, domain, interface_index) {
-        ctx.invoke_callback(Err(e));
-    }
+    ffi::guard(context, |ctx: &mut BonjourResolverContext| {
+        ctx.update_stats(|s| s.event_received());
+
+        let added = error == 0 && flags & bonjour_sys::kDNSServiceFlagsAdd as DNSServiceFlags != 0;
+
+        if ctx.meta_query {
+            if let Err(e) = handle_type_browse(ctx, added, error, name, regtype) {
+                ctx.invoke_callback(Err(e.with_context(ctx.browse_context())));
+            }
+        } else {
+            handle_service_browse(ctx, added, error, name, regtype, domain, interface_index);
+        }
+
+        ctx.pending_flush = Some(error);
+        ctx.end_of_results(flags);
+    })
 }
 
-unsafe fn handle_browse(
-    ctx: &mut BonjourBrowserContext,
+unsafe fn handle_service_browse(
+    ctx: &mut BonjourResolverContext,
+    added: bool,
     error: DNSServiceErrorType,
     name: *const c_char,
     regtype: *const c_char,
     domain: *const c_char,
     interface_index: u32,
-) -> Result<()> {
+) {
     if error != 0 {
-        return Err(format!("browse_callback() reported error (code: {})", error).into());
+        let error = Error::from(format!(
+            "browse_callback() reported error (code: {})",
+            error
+        ));
+        ctx.invoke_callback(Err(error.with_context(ctx.browse_context())));
+
+        return;
     }
 
-    ctx.resolved_name = Some(c_str::copy_raw(name));
-    ctx.resolved_kind = Some(c_str::copy_raw(regtype));
-    ctx.resolved_domain = Some(c_str::copy_raw(domain));
-
-    ManagedDNSServiceRef::default().resolve_service(
-        ServiceResolveParams::builder()
-            .flags(bonjour_sys::kDNSServiceFlagsForceMulticast)
-            .interface_index(interface_index)
-            .name(name)
-            .regtype(regtype)
-            .domain(domain)
-            .callback(Some(resolve_callback))
-            .context(ctx.as_raw())
-            .build()?,
-    )
-}
+    // services whose name is filtered out are neither tracked nor resolved
+    if let Ok(name) = c_str::try_raw_to_str(name, "service name") {
+        if !ctx.filter.found(name) {
+            debug!("[{}] Ignoring filtered service: {}", ctx.id, name);
+            return;
+        }
+    }
 
-unsafe extern "system" fn resolve_callback(
-    _sd_ref: DNSServiceRef,
-    _flags: DNSServiceFlags,
-    interface_index: u32,
-    error: DNSServiceErrorType,
-    _fullname: *const c_char,
-    host_target: *const c_char,
-    port: u16,
-    txt_len: u16,
-    txt_record: *const c_uchar,
-    context: *mut c_void,
-) {
-    let ctx = BonjourBrowserContext::from_raw(context);
+    if let Err(e) = track_service(ctx, added, name, regtype, domain, interface_index) {
+        warn!("[{}] ignoring unidentifiable service: {}", ctx.id, e);
+    }
 
-    let result = handle_resolve(
-        ctx,
-        error,
-        port,
-        interface_index,
-        host_target,
-        txt_len,
-        txt_record,
-    );
+    if !added {
+        if let Err(e) = handle_browse_remove(ctx, name, regtype, domain, interface_index) {
+            warn!(
+                "[{}] ignoring removal of unidentifiable service: {}",
+                ctx.id, e
+            );
+        }
 
-    if let Err(e) = result {
-        ctx.invoke_callback(Err(e));
+        return;
+    }
+
+    match Resolution::new(interface_index, name, regtype, domain) {
+        Ok(resolution) => {
+            ctx.update_settle(|s| s.resolution_started());
+            ctx.resolutions.queue(resolution);
+            resolver::start_resolutions(ctx);
+        }
+        Err(e) => ctx.invoke_callback(Err(e.with_context(ctx.browse_context()))),
     }
 }
 
-unsafe fn handle_resolve(
-    ctx: &mut BonjourBrowserContext,
-    error: DNSServiceErrorType,
-    port: u16,
+/// Records a service found or removed by a browse result and reports its removal. If the browser
+/// deduplicates services, a removal is only reported once the service is gone from every
+/// interface it was found on.
+unsafe fn track_service(
+    ctx: &mut BonjourResolverContext,
+    added: bool,
+    name: *const c_char,
+    regtype: *const c_char,
+    domain: *const c_char,
     interface_index: u32,
-    host_target: *const c_char,
-    txt_len: u16,
-    txt_record: *const c_uchar,
 ) -> Result<()> {
-    if error != 0 {
-        return Err(format!("error reported by resolve_callback: (code: {})", error).into());
-    }
+    let name = c_str::try_raw_to_str(name, "service name")?;
+    let kind = bonjour_util::normalize_domain(c_str::try_raw_to_str(regtype, "service type")?);
+    let service_type = bonjour_util::parse_regtype(&kind)?;
+    let domain = bonjour_util::normalize_domain(c_str::try_raw_to_str(domain, "service domain")?);
+    let interface = bonjour_util::interface_from_index(interface_index);
 
-    ctx.resolved_port = port;
+    let key = ServiceKey::new(name, &service_type, &domain);
 
-    ctx.resolved_txt = if txt_len > 1 {
-        Some(TxtRecord::from(ManagedTXTRecordRef::clone_raw(
-            txt_record, txt_len,
-        )?))
-    } else {
-        None
+    if added {
+        if let Some(dedup) = &mut ctx.dedup {
+            dedup.found(key, interface);
+        }
+
+        return Ok(());
+    }
+
+    let report = match &mut ctx.dedup {
+        Some(dedup) => dedup.removed(&key, interface),
+        None => true,
     };
 
-    ManagedDNSServiceRef::default().get_address_info(
-        GetAddressInfoParams::builder()
-            .flags(bonjour_sys::kDNSServiceFlagsForceMulticast)
-            .interface_index(interface_index)
-            .protocol(0)
-            .hostname(host_target)
-            .callback(Some(get_address_info_callback))
-            .context(ctx.as_raw())
-            .build()?,
-    )
+    if report && ctx.filter.removed(&key) {
+        ctx.update_cache(|c| c.reported_removed(&key));
+        ctx.invoke_browser_event_callback(BrowserEvent::ServiceRemoved(key));
+    }
+
+    Ok(())
 }
 
-unsafe extern "system" fn get_address_info_callback(
-    _sd_ref: DNSServiceRef,
-    _flags: DNSServiceFlags,
-    _interface_index: u32,
+/// Reports a service type found by a meta-query, which is browsed as the name `_http` of the
+/// type `_tcp.local.`
+unsafe fn handle_type_browse(
+    ctx: &mut BonjourResolverContext,
+    added: bool,
     error: DNSServiceErrorType,
-    hostname: *const c_char,
-    address: *const bonjour_sys::sockaddr,
-    _ttl: u32,
-    context: *mut c_void,
-) {
-    let ctx = BonjourBrowserContext::from_raw(context);
-    if let Err(e) = handle_get_address_info(ctx, error, address, hostname) {
-        ctx.invoke_callback(Err(e));
+    name: *const c_char,
+    regtype: *const c_char,
+) -> Result<()> {
+    if error != 0 {
+        return Err(format!("browse_callback() reported error (code: {})", error).into());
     }
+
+    let name = c_str::try_raw_to_str(name, "service type name")?;
+    let regtype = c_str::try_raw_to_str(regtype, "service type protocol")?;
+    let service_type = crate::service_type::parse_advertised(&format!("{}.{}", name, regtype))?;
+
+    ctx.invoke_browser_event_callback(if added {
+        BrowserEvent::ServiceTypeFound(service_type)
+    } else {
+        BrowserEvent::ServiceTypeRemoved(service_type)
+    });
+
+    Ok(())
 }
 
-unsafe fn handle_get_address_info(
-    ctx: &mut BonjourBrowserContext,
-    error: DNSServiceErrorType,
-    address: *const bonjour_sys::sockaddr,
-    hostname: *const c_char,
+/// Terminates the resolutions of a removed service, as a service that is gone is never resolved,
+/// and records its removal.
+unsafe fn handle_browse_remove(
+    ctx: &mut BonjourResolverContext,
+    name: *const c_char,
+    regtype: *const c_char,
+    domain: *const c_char,
+    interface_index: u32,
 ) -> Result<()> {
-    // this callback runs multiple times for some reason
-    if ctx.resolved_name.is_none() {
-        return Ok(());
-    }
+    let name = c_str::try_raw_to_str(name, "service name")?;
+    let regtype = c_str::try_raw_to_str(regtype, "service type")?;
+    let domain = c_str::try_raw_to_str(domain, "service domain")?;
 
-    if error != 0 {
-        return Err(format!(
-            "get_address_info_callback() reported error (code: {})",
-            error
-        )
-        .into());
-    }
+    let cancelled = ctx
+        .resolutions
+        .cancel(interface_index, name, regtype, domain);
 
-    // on macOS the bytes are swapped for the port
-    let port: u16 = ctx.resolved_port.to_be();
+    let service_type = bonjour_util::parse_regtype(&bonjour_util::normalize_domain(regtype))?;
+    let domain = bonjour_util::normalize_domain(domain);
 
-    // on macOS the bytes are swapped for the ip
-    #[cfg(target_vendor = "apple")]
-    let ip = {
-        let address = address as *const sockaddr_in;
-        assert_not_null!(address);
-        let s_addr = (*address).sin_addr.s_addr.to_le_bytes();
-        IpAddr::from(s_addr).to_string()
-    };
+    ctx.update_settle(|s| {
+        for _ in 0..cancelled {
+            s.resolution_finished();
+        }
 
-    #[cfg(target_vendor = "pc")]
-    let ip = {
-        let address = address as *const sockaddr_in;
-        assert_not_null!(address);
-        let s_un = (*address).sin_addr.S_un.S_un_b;
-        let s_addr = [s_un.s_b1, s_un.s_b2, s_un.s_b3, s_un.s_b4];
-        IpAddr::from(s_addr).to_string()
-    };
+        s.removed(name, &service_type, &domain);
+    });
 
-    let hostname = c_str::copy_raw(hostname);
-
-    let domain = bonjour_util::normalize_domain(
-        &ctx.resolved_domain
-            .take()
-            .ok_or("could not get domain from BonjourBrowserContext")?,
-    );
-
-    let kind = bonjour_util::normalize_domain(
-        &ctx.resolved_kind
-            .take()
-            .ok_or("could not get kind from BonjourBrowserContext")?,
-    );
-
-    let name = ctx
-        .resolved_name
-        .take()
-        .ok_or("could not get name from BonjourBrowserContext")?;
-
-    let result = ServiceDiscovery::builder()
-        .name(name)
-        .service_type(bonjour_util::parse_regtype(&kind)?)
-        .domain(domain)
-        .host_name(hostname)
-        .address(ip)
-        .port(port)
-        .txt(ctx.resolved_txt.take())
-        .build()
-        .expect("could not build ServiceResolution");
-
-    ctx.invoke_callback(Ok(result));
+    if cancelled > 0 {
+        resolver::start_resolutions(ctx);
+    }
 
     Ok(())
 }
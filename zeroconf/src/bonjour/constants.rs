@@ -1,4 +1,5 @@
 use bonjour_sys::DNSServiceFlags;
 
 pub const BONJOUR_IF_UNSPEC: u32 = 0;
+pub const BONJOUR_IF_LOCAL_ONLY: u32 = u32::MAX;
 pub const BONJOUR_RENAME_FLAGS: DNSServiceFlags = 0;
@@ -0,0 +1,206 @@
+//! Bonjour implementation for cross-platform domain browser
+
+use super::service_ref::{EnumerateDomainsParams, ManagedDNSServiceRef};
+use super::{bonjour_util, constants};
+use crate::error::Error;
+use crate::ffi::{self, c_str, AsRaw, CallbackContext};
+use crate::prelude::*;
+use crate::{
+    DomainEvent, DomainEventCallback, DomainType, EventLoop, Id, NetworkInterface, Result,
+};
+use bonjour_sys::{DNSServiceErrorType, DNSServiceFlags, DNSServiceRef};
+use libc::{c_char, c_void};
+use std::any::Any;
+use std::fmt::{self, Formatter};
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug)]
+pub struct BonjourMdnsDomainBrowser {
+    service: Arc<Mutex<ManagedDNSServiceRef>>,
+    domain_type: DomainType,
+    interface_index: u32,
+    context: Box<BonjourDomainBrowserContext>,
+}
+
+impl TMdnsDomainBrowser for BonjourMdnsDomainBrowser {
+    fn new(domain_type: DomainType) -> Self {
+        Self {
+            service: Arc::default(),
+            domain_type,
+            interface_index: constants::BONJOUR_IF_UNSPEC,
+            context: Box::new(BonjourDomainBrowserContext::new()),
+        }
+    }
+
+    fn id(&self) -> Id {
+        self.context.id
+    }
+
+    fn domain_type(&self) -> DomainType {
+        self.domain_type
+    }
+
+    fn set_network_interface(&mut self, interface: NetworkInterface) {
+        self.interface_index = bonjour_util::interface_index(interface);
+    }
+
+    fn network_interface(&self) -> NetworkInterface {
+        bonjour_util::interface_from_index(self.interface_index)
+    }
+
+    fn set_domain_event_callback(&mut self, domain_event_callback: Box<DomainEventCallback>) {
+        self.context.domain_event_callback = Some(domain_event_callback);
+    }
+
+    fn set_context(&mut self, context: Box<dyn Any + Send + Sync>) {
+        self.context.user_context = Some(Arc::from(context));
+    }
+
+    fn context(&self) -> Option<&(dyn Any + Send + Sync)> {
+        self.context.user_context.as_ref().map(|c| c.as_ref())
+    }
+
+    fn browse_domains(&mut self) -> Result<EventLoop> {
+        debug!("[{}] Browsing domains: {:?}", self.context.id, self);
+
+        // replacing the service ref deallocates any previous enumeration
+        self.service = Arc::default();
+
+        let mut service_lock = self
+            .service
+            .lock()
+            .expect("should have been able to obtain lock on service ref");
+
+        unsafe {
+            service_lock.enumerate_domains(
+                EnumerateDomainsParams::builder()
+                    .flags(enumerate_flags(self.domain_type))
+                    .interface_index(self.interface_index)
+                    .callback(Some(enumerate_domains_callback))
+                    .context(self.context.as_raw())
+                    .build()?,
+            )?
+        };
+
+        let event_loop = EventLoop::new(self.service.clone());
+
+        debug!("[{}] Event loop: {}", self.context.id, event_loop.id());
+
+        Ok(event_loop)
+    }
+}
+
+#[derive(FromRaw, AsRaw)]
+struct BonjourDomainBrowserContext {
+    id: Id,
+    domain_event_callback: Option<Box<DomainEventCallback>>,
+    user_context: Option<Arc<dyn Any + Send + Sync>>,
+}
+
+impl BonjourDomainBrowserContext {
+    fn new() -> Self {
+        Self {
+            id: Id::next(),
+            domain_event_callback: None,
+            user_context: None,
+        }
+    }
+
+    fn invoke_callback(&self, result: Result<DomainEvent>) {
+        if let Some(f) = &self.domain_event_callback {
+            f(result, self.user_context.clone());
+        } else {
+            warn!(
+                "[{}] attempted to invoke callback but none was set",
+                self.id
+            );
+        }
+    }
+}
+
+impl CallbackContext for BonjourDomainBrowserContext {
+    fn id(&self) -> Id {
+        self.id
+    }
+
+    fn invoke_error(&self, error: Error) {
+        self.invoke_callback(Err(error))
+    }
+}
+
+impl fmt::Debug for BonjourDomainBrowserContext {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BonjourDomainBrowserContext")
+            .field("id", &self.id)
+            .finish()
+    }
+}
+
+fn enumerate_flags(domain_type: DomainType) -> DNSServiceFlags {
+    match domain_type {
+        DomainType::Browse => bonjour_sys::kDNSServiceFlagsBrowseDomains,
+        DomainType::Registration => bonjour_sys::kDNSServiceFlagsRegistrationDomains,
+    }
+}
+
+unsafe extern "system" fn enumerate_domains_callback(
+    _sd_ref: DNSServiceRef,
+    flags: DNSServiceFlags,
+    _interface_index: u32,
+    error: DNSServiceErrorType,
+    reply_domain: *const c_char,
+    context: *mut c_void,
+) {
+    ffi::guard(context, |ctx: &mut BonjourDomainBrowserContext| {
+        ctx.invoke_callback(handle_enumerate_domains(flags, error, reply_domain));
+    })
+}
+
+unsafe fn handle_enumerate_domains(
+    flags: DNSServiceFlags,
+    error: DNSServiceErrorType,
+    reply_domain: *const c_char,
+) -> Result<DomainEvent> {
+    if error != 0 {
+        return Err(format!(
+            "enumerate_domains_callback() reported error (code: {})",
+            error
+        )
+        .into());
+    }
+
+    let domain = bonjour_util::normalize_domain(c_str::try_raw_to_str(reply_domain, "domain")?);
+
+    // domains that are no longer recommended are reported without the `Add` flag
+    if flags & bonjour_sys::kDNSServiceFlagsAdd != 0 {
+        Ok(DomainEvent::Add(domain))
+    } else {
+        Ok(DomainEvent::Remove(domain))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ptr;
+
+    #[test]
+    fn handle_enumerate_domains_reports_added_and_removed_domains() {
+        let domain = c_string!("example.com.");
+
+        unsafe {
+            assert_eq!(
+                handle_enumerate_domains(bonjour_sys::kDNSServiceFlagsAdd, 0, domain.as_ptr()),
+                Ok(DomainEvent::Add("example.com".to_string()))
+            );
+            assert_eq!(
+                handle_enumerate_domains(0, 0, domain.as_ptr()),
+                Ok(DomainEvent::Remove("example.com".to_string()))
+            );
+            assert_eq!(
+                handle_enumerate_domains(0, 0, ptr::null()),
+                Err("missing domain".into())
+            );
+        }
+    }
+}
@@ -1,34 +1,265 @@
-//! Event loop for running a `MdnsService` or `MdnsBrowser`.
+//! Event loop for running a `MdnsService`, `MdnsServiceGroup` or `MdnsBrowser`.
 
+use super::resolver::ResolveTimeouts;
+use super::service::DeferredRunner;
+use super::service_group::InstanceChangeRunner;
 use super::service_ref::ManagedDNSServiceRef;
-use crate::event_loop::TEventLoop;
-use crate::{ffi, Result};
-use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use crate::error::{ErrorContext, Operation, ResultExt};
+use crate::event_loop::{ShutdownHandle, TEventLoop};
+use crate::settle::{self, SettleTracker};
+use crate::{ffi, Id, Result};
+use bonjour_sys::dnssd_sock_t;
+use std::io;
+use std::net::{Ipv4Addr, UdpSocket};
+#[cfg(target_vendor = "apple")]
+use std::os::unix::io::{AsRawFd, RawFd};
+#[cfg(target_vendor = "pc")]
+use std::os::windows::io::AsRawSocket;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
 
-#[derive(new)]
 pub struct BonjourEventLoop {
+    id: Id,
     service: Arc<Mutex<ManagedDNSServiceRef>>,
+    connection: Option<Arc<Mutex<ManagedDNSServiceRef>>>,
+    settle: Option<Arc<Mutex<SettleTracker>>>,
+    deferred: Option<DeferredRunner>,
+    resolve_timeouts: Option<ResolveTimeouts>,
+    instance_changes: Option<InstanceChangeRunner>,
+    shutdown: Arc<AtomicBool>,
+    wake_socket: OnceLock<Option<Arc<WakeSocket>>>,
+}
+
+impl BonjourEventLoop {
+    /// Constructs a new `BonjourEventLoop` for the specified service ref.
+    pub fn new(service: Arc<Mutex<ManagedDNSServiceRef>>) -> Self {
+        Self {
+            id: Id::next(),
+            service,
+            connection: None,
+            settle: None,
+            deferred: None,
+            resolve_timeouts: None,
+            instance_changes: None,
+            shutdown: Arc::default(),
+            wake_socket: OnceLock::new(),
+        }
+    }
+
+    /// Constructs a new `BonjourEventLoop` that also processes results for a connection created
+    /// with `ManagedDNSServiceRef::create_connection()`.
+    pub fn with_connection(
+        service: Arc<Mutex<ManagedDNSServiceRef>>,
+        connection: Arc<Mutex<ManagedDNSServiceRef>>,
+    ) -> Self {
+        Self {
+            id: Id::next(),
+            service,
+            connection: Some(connection),
+            settle: None,
+            deferred: None,
+            resolve_timeouts: None,
+            instance_changes: None,
+            shutdown: Arc::default(),
+            wake_socket: OnceLock::new(),
+        }
+    }
+
+    /// Invokes the settled callback of `settle` from `poll()` once the browser has settled.
+    pub(crate) fn with_settle_tracker(mut self, settle: Arc<Mutex<SettleTracker>>) -> Self {
+        self.settle = Some(settle);
+        self
+    }
+
+    /// Executes the closures queued for a service from `poll()`, once the callbacks have returned.
+    pub(crate) fn with_deferred(mut self, deferred: DeferredRunner) -> Self {
+        self.deferred = Some(deferred);
+        self
+    }
+
+    /// Terminates the resolutions of a browser that have timed out from `poll()`.
+    pub(crate) fn with_resolve_timeouts(mut self, resolve_timeouts: ResolveTimeouts) -> Self {
+        self.resolve_timeouts = Some(resolve_timeouts);
+        self
+    }
+
+    /// Adds and removes the instances of a service group from `poll()`.
+    pub(crate) fn with_instance_changes(mut self, instance_changes: InstanceChangeRunner) -> Self {
+        self.instance_changes = Some(instance_changes);
+        self
+    }
+
+    fn wake_fd(&self) -> Option<dnssd_sock_t> {
+        self.wake_socket.get()?.as_ref().map(|s| s.sock_fd())
+    }
+
+    fn process_deferred(&self) {
+        // objects dropped from within their callbacks are freed first, so that nothing is
+        // deferred on their behalf
+        ffi::drop_dispatched();
+
+        if let Some(deferred) = &self.deferred {
+            unsafe { deferred.run() };
+        }
+
+        if let Some(instance_changes) = &self.instance_changes {
+            unsafe { instance_changes.run() };
+        }
+
+        if let Some(resolve_timeouts) = &self.resolve_timeouts {
+            unsafe { resolve_timeouts.run() };
+        }
+
+        if let Some(settle) = &self.settle {
+            settle::poll(settle, self.id);
+        }
+    }
 }
 
 impl TEventLoop for BonjourEventLoop {
+    fn id(&self) -> Id {
+        self.id
+    }
+
     /// Polls for new events.
     ///
     /// Prior to calling `ManagedDNSServiceRef::process_result()`, this function performs a unix
     /// `select()` on the underlying socket with the specified timeout. If the socket contains no
     /// new data, the blocking call is not made.
+    ///
+    /// If the loop also holds a connection, it is polled afterwards without waiting. This is
+    /// repeated until a callback has been invoked or `timeout` has elapsed, so that an interrupted
+    /// `select()` does not return early.
+    ///
+    /// Returns without waiting once the loop has been shut down through a `ShutdownHandle`, whose
+    /// wake-up socket is part of the `select()` to interrupt a call that is waiting.
     fn poll(&self, timeout: Duration) -> Result<()> {
+        let span = span!("poll", event_loop = %self.id);
+        let _entered = span.enter();
+
+        let start = Instant::now();
+        let deadline = start + timeout;
+        let dispatched = ffi::dispatched();
+        let wake_fd = self.wake_fd();
+
+        while !self.shutdown.load(Ordering::SeqCst) {
+            poll_ref(
+                &self.service,
+                wake_fd,
+                deadline.saturating_duration_since(Instant::now()),
+            )?;
+
+            if let Some(connection) = &self.connection {
+                poll_ref(connection, None, Duration::from_secs(0))?;
+            }
+
+            if ffi::dispatched() != dispatched || Instant::now() >= deadline {
+                break;
+            }
+        }
+
+        trace!("[{}] Polled for {:?}", self.id, start.elapsed());
+
+        self.process_deferred();
+
+        Ok(())
+    }
+
+    fn process_events(&self) -> Result<()> {
+        poll_ref(&self.service, None, Duration::from_secs(0))?;
+
+        if let Some(connection) = &self.connection {
+            poll_ref(connection, None, Duration::from_secs(0))?;
+        }
+
+        self.process_deferred();
+
+        Ok(())
+    }
+
+    #[cfg(target_vendor = "apple")]
+    fn as_raw_fd(&self) -> Option<RawFd> {
+        if self.connection.is_some() {
+            return None;
+        }
+
         let service = self
             .service
             .lock()
             .expect("should have been able to obtain lock on service ref");
 
-        let select = unsafe { ffi::bonjour::read_select(service.sock_fd(), timeout)? };
+        Some(unsafe { service.sock_fd() })
+    }
+
+    fn shutdown_handle(&self) -> ShutdownHandle {
+        let wake_socket = self
+            .wake_socket
+            .get_or_init(|| match WakeSocket::new() {
+                Ok(wake_socket) => Some(Arc::new(wake_socket)),
+                Err(e) => {
+                    error!("[{}] Could not create wake-up socket: {}", self.id, e);
+                    None
+                }
+            })
+            .clone();
 
-        if select > 0 {
-            unsafe { service.process_result() }
-        } else {
-            Ok(())
+        ShutdownHandle::new(self.shutdown.clone(), move || {
+            if let Some(wake_socket) = &wake_socket {
+                wake_socket.wake();
+            }
+        })
+    }
+}
+
+/// Loopback socket that becomes readable once a `ShutdownHandle` has been used, so that a waiting
+/// `select()` is interrupted. A UDP socket connected to itself takes the place of a self-pipe, as
+/// Winsock can only `select()` on sockets.
+struct WakeSocket(UdpSocket);
+
+impl WakeSocket {
+    fn new() -> io::Result<Self> {
+        let socket = UdpSocket::bind((Ipv4Addr::LOCALHOST, 0))?;
+
+        socket.connect(socket.local_addr()?)?;
+        socket.set_nonblocking(true)?;
+
+        Ok(Self(socket))
+    }
+
+    /// Makes the socket readable. The datagram is never received, so that it stays readable for
+    /// every later `select()`.
+    fn wake(&self) {
+        if let Err(e) = self.0.send(&[0]) {
+            error!("Could not wake up event loop: {}", e);
         }
     }
+
+    #[cfg(target_vendor = "apple")]
+    fn sock_fd(&self) -> dnssd_sock_t {
+        self.0.as_raw_fd()
+    }
+
+    #[cfg(target_vendor = "pc")]
+    fn sock_fd(&self) -> dnssd_sock_t {
+        self.0.as_raw_socket() as dnssd_sock_t
+    }
+}
+
+fn poll_ref(
+    service: &Mutex<ManagedDNSServiceRef>,
+    wake_fd: Option<dnssd_sock_t>,
+    timeout: Duration,
+) -> Result<()> {
+    let service = service
+        .lock()
+        .expect("should have been able to obtain lock on service ref");
+
+    let result = match unsafe { ffi::bonjour::read_select(service.sock_fd(), wake_fd, timeout) } {
+        Ok(true) => unsafe { service.process_result() },
+        Ok(false) => Ok(()),
+        Err(e) => Err(e),
+    };
+
+    result.with_context(|| ErrorContext::new(Operation::Poll))
 }
@@ -0,0 +1,203 @@
+//! Bonjour implementation for cross-platform host name resolver
+
+use super::service_ref::{GetAddressInfoParams, ManagedDNSServiceRef};
+use super::{bonjour_util, constants};
+use crate::error::{Error, ErrorContext, Operation, ResultExt};
+use crate::ffi::{self, c_str, AsRaw, CallbackContext};
+use crate::prelude::*;
+use crate::{EventLoop, HostResolution, HostResolvedCallback, Id, NetworkInterface, Result};
+use bonjour_sys::{DNSServiceErrorType, DNSServiceFlags, DNSServiceRef};
+use libc::{c_char, c_void};
+use std::any::Any;
+use std::fmt::{self, Formatter};
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug)]
+pub struct BonjourMdnsHostResolver {
+    service: Arc<Mutex<ManagedDNSServiceRef>>,
+    interface_index: u32,
+    context: Box<BonjourHostResolverContext>,
+}
+
+impl TMdnsHostResolver for BonjourMdnsHostResolver {
+    fn new() -> Self {
+        Self {
+            service: Arc::default(),
+            interface_index: constants::BONJOUR_IF_UNSPEC,
+            context: Box::new(BonjourHostResolverContext::new()),
+        }
+    }
+
+    fn id(&self) -> Id {
+        self.context.id
+    }
+
+    fn set_network_interface(&mut self, interface: NetworkInterface) {
+        self.interface_index = bonjour_util::interface_index(interface);
+    }
+
+    fn network_interface(&self) -> NetworkInterface {
+        bonjour_util::interface_from_index(self.interface_index)
+    }
+
+    fn set_host_resolved_callback(&mut self, host_resolved_callback: Box<HostResolvedCallback>) {
+        self.context.host_resolved_callback = Some(host_resolved_callback);
+    }
+
+    fn set_context(&mut self, context: Box<dyn Any + Send + Sync>) {
+        self.context.user_context = Some(Arc::from(context));
+    }
+
+    fn context(&self) -> Option<&(dyn Any + Send + Sync)> {
+        self.context.user_context.as_ref().map(|c| c.as_ref())
+    }
+
+    fn resolve(&mut self, host_name: &str) -> Result<EventLoop> {
+        debug!(
+            "[{}] Resolving host `{}`: {:?}",
+            self.context.id, host_name, self
+        );
+
+        let c_host_name = c_string!(host_name);
+        self.context.host_name = Some(host_name.to_string());
+
+        // replacing the service ref deallocates any previous resolution
+        self.service = Arc::default();
+
+        let mut service_lock = self
+            .service
+            .lock()
+            .expect("should have been able to obtain lock on service ref");
+
+        unsafe {
+            service_lock.start_get_address_info(
+                GetAddressInfoParams::builder()
+                    .flags(bonjour_sys::kDNSServiceFlagsForceMulticast)
+                    .interface_index(self.interface_index)
+                    .protocol(
+                        bonjour_sys::kDNSServiceProtocol_IPv4
+                            | bonjour_sys::kDNSServiceProtocol_IPv6,
+                    )
+                    .hostname(c_host_name.as_ptr())
+                    .callback(Some(get_address_info_callback))
+                    .context(self.context.as_raw())
+                    .build()?,
+            )
+        }
+        .with_context(|| self.context.error_context())?;
+
+        let event_loop = EventLoop::new(self.service.clone());
+
+        debug!("[{}] Event loop: {}", self.context.id, event_loop.id());
+
+        Ok(event_loop)
+    }
+}
+
+#[derive(FromRaw, AsRaw)]
+struct BonjourHostResolverContext {
+    id: Id,
+    host_resolved_callback: Option<Box<HostResolvedCallback>>,
+    user_context: Option<Arc<dyn Any + Send + Sync>>,
+    host_name: Option<String>,
+}
+
+impl BonjourHostResolverContext {
+    fn new() -> Self {
+        Self {
+            id: Id::next(),
+            host_resolved_callback: None,
+            user_context: None,
+            host_name: None,
+        }
+    }
+
+    fn invoke_callback(&self, result: Result<HostResolution>) {
+        let result = result.with_context(|| self.error_context());
+
+        if let Some(f) = &self.host_resolved_callback {
+            f(result, self.user_context.clone());
+        } else {
+            warn!(
+                "[{}] attempted to invoke callback but none was set",
+                self.id
+            );
+        }
+    }
+
+    fn error_context(&self) -> ErrorContext {
+        ErrorContext::new(Operation::GetAddrInfo).with_name(self.host_name.as_deref())
+    }
+}
+
+impl CallbackContext for BonjourHostResolverContext {
+    fn id(&self) -> Id {
+        self.id
+    }
+
+    fn invoke_error(&self, error: Error) {
+        self.invoke_callback(Err(error))
+    }
+}
+
+impl fmt::Debug for BonjourHostResolverContext {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BonjourHostResolverContext")
+            .field("id", &self.id)
+            .finish()
+    }
+}
+
+unsafe extern "system" fn get_address_info_callback(
+    _sd_ref: DNSServiceRef,
+    flags: DNSServiceFlags,
+    interface_index: u32,
+    error: DNSServiceErrorType,
+    hostname: *const c_char,
+    address: *const bonjour_sys::sockaddr,
+    _ttl: u32,
+    context: *mut c_void,
+) {
+    ffi::guard(context, |ctx: &mut BonjourHostResolverContext| {
+        if error != 0 {
+            ctx.invoke_callback(Err(format!(
+                "get_address_info_callback() reported error (code: {})",
+                error
+            )
+            .into()));
+            return;
+        }
+
+        // addresses that are no longer valid are reported without the `Add` flag
+        if flags & bonjour_sys::kDNSServiceFlagsAdd == 0 {
+            return;
+        }
+
+        ctx.invoke_callback(handle_get_address_info(
+            ctx,
+            hostname,
+            address,
+            interface_index,
+        ));
+    })
+}
+
+unsafe fn handle_get_address_info(
+    ctx: &BonjourHostResolverContext,
+    hostname: *const c_char,
+    address: *const bonjour_sys::sockaddr,
+    interface_index: u32,
+) -> Result<HostResolution> {
+    let result = HostResolution::builder()
+        .host_name(bonjour_util::normalize_domain(c_str::try_raw_to_str(
+            hostname,
+            "host name",
+        )?))
+        .address(bonjour_util::sockaddr_to_ip(address)?)
+        .interface(bonjour_util::interface_from_index(interface_index))
+        .build()?;
+
+    debug!("[{}] Host resolved: {:?}", ctx.id, result);
+
+    Ok(result)
+}
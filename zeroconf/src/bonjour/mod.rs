@@ -8,8 +8,13 @@ pub(crate) mod constants;
 
 pub mod bonjour_util;
 pub mod browser;
+pub mod domain_browser;
 pub mod event_loop;
+pub mod host_resolver;
+pub mod record_browser;
+pub mod resolver;
 pub mod service;
+pub mod service_group;
 pub mod service_ref;
 pub mod txt_record;
 pub mod txt_record_ref;
@@ -0,0 +1,233 @@
+//! Bonjour implementation for cross-platform record browser
+
+use super::service_ref::{ManagedDNSServiceRef, QueryRecordParams};
+use super::{bonjour_util, constants};
+use crate::error::Error;
+use crate::ffi::{self, c_str, AsRaw, CallbackContext};
+use crate::prelude::*;
+use crate::{
+    EventLoop, Id, NetworkInterface, QueriedRecord, RecordEvent, RecordEventCallback, Result,
+};
+use bonjour_sys::{DNSServiceErrorType, DNSServiceFlags, DNSServiceRef};
+use libc::{c_char, c_void};
+use std::any::Any;
+use std::fmt::{self, Formatter};
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug)]
+pub struct BonjourMdnsRecordBrowser {
+    service: Arc<Mutex<ManagedDNSServiceRef>>,
+    interface_index: u32,
+    context: Box<BonjourRecordBrowserContext>,
+}
+
+impl TMdnsRecordBrowser for BonjourMdnsRecordBrowser {
+    fn new() -> Self {
+        Self {
+            service: Arc::default(),
+            interface_index: constants::BONJOUR_IF_UNSPEC,
+            context: Box::new(BonjourRecordBrowserContext::new()),
+        }
+    }
+
+    fn id(&self) -> Id {
+        self.context.id
+    }
+
+    fn set_network_interface(&mut self, interface: NetworkInterface) {
+        self.interface_index = bonjour_util::interface_index(interface);
+    }
+
+    fn network_interface(&self) -> NetworkInterface {
+        bonjour_util::interface_from_index(self.interface_index)
+    }
+
+    fn set_record_event_callback(&mut self, record_event_callback: Box<RecordEventCallback>) {
+        self.context.record_event_callback = Some(record_event_callback);
+    }
+
+    fn set_context(&mut self, context: Box<dyn Any + Send + Sync>) {
+        self.context.user_context = Some(Arc::from(context));
+    }
+
+    fn context(&self) -> Option<&(dyn Any + Send + Sync)> {
+        self.context.user_context.as_ref().map(|c| c.as_ref())
+    }
+
+    fn query_record(&mut self, name: &str, rr_type: u16, rr_class: u16) -> Result<EventLoop> {
+        debug!(
+            "[{}] Querying record `{}` (type: {}, class: {}): {:?}",
+            self.context.id, name, rr_type, rr_class, self
+        );
+
+        let fullname = c_string!(name);
+
+        // replacing the service ref deallocates any previous query
+        self.service = Arc::default();
+
+        let mut service_lock = self
+            .service
+            .lock()
+            .expect("should have been able to obtain lock on service ref");
+
+        unsafe {
+            service_lock.query_record(
+                QueryRecordParams::builder()
+                    .flags(0)
+                    .interface_index(self.interface_index)
+                    .fullname(fullname.as_ptr())
+                    .rr_type(rr_type)
+                    .rr_class(rr_class)
+                    .callback(Some(query_record_callback))
+                    .context(self.context.as_raw())
+                    .build()?,
+            )?
+        };
+
+        let event_loop = EventLoop::new(self.service.clone());
+
+        debug!("[{}] Event loop: {}", self.context.id, event_loop.id());
+
+        Ok(event_loop)
+    }
+}
+
+#[derive(FromRaw, AsRaw)]
+struct BonjourRecordBrowserContext {
+    id: Id,
+    record_event_callback: Option<Box<RecordEventCallback>>,
+    user_context: Option<Arc<dyn Any + Send + Sync>>,
+}
+
+impl BonjourRecordBrowserContext {
+    fn new() -> Self {
+        Self {
+            id: Id::next(),
+            record_event_callback: None,
+            user_context: None,
+        }
+    }
+
+    fn invoke_callback(&self, result: Result<RecordEvent>) {
+        if let Some(f) = &self.record_event_callback {
+            f(result, self.user_context.clone());
+        } else {
+            warn!(
+                "[{}] attempted to invoke callback but none was set",
+                self.id
+            );
+        }
+    }
+}
+
+impl CallbackContext for BonjourRecordBrowserContext {
+    fn id(&self) -> Id {
+        self.id
+    }
+
+    fn invoke_error(&self, error: Error) {
+        self.invoke_callback(Err(error))
+    }
+}
+
+impl fmt::Debug for BonjourRecordBrowserContext {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BonjourRecordBrowserContext")
+            .field("id", &self.id)
+            .finish()
+    }
+}
+
+unsafe extern "system" fn query_record_callback(
+    _sd_ref: DNSServiceRef,
+    flags: DNSServiceFlags,
+    interface_index: u32,
+    error: DNSServiceErrorType,
+    fullname: *const c_char,
+    rr_type: u16,
+    rr_class: u16,
+    rd_len: u16,
+    rdata: *const c_void,
+    ttl: u32,
+    context: *mut c_void,
+) {
+    ffi::guard(context, |ctx: &mut BonjourRecordBrowserContext| {
+        if error != 0 {
+            ctx.invoke_callback(Err(format!(
+                "query_record_callback() reported error (code: {})",
+                error
+            )
+            .into()));
+            return;
+        }
+
+        let record = handle_query_record(
+            fullname,
+            rr_type,
+            rr_class,
+            rd_len,
+            rdata,
+            ttl,
+            interface_index,
+        );
+
+        // records that are no longer valid are reported without the `Add` flag
+        ctx.invoke_callback(record.map(|r| {
+            if flags & bonjour_sys::kDNSServiceFlagsAdd != 0 {
+                RecordEvent::Add(r)
+            } else {
+                RecordEvent::Remove(r)
+            }
+        }));
+    })
+}
+
+unsafe fn handle_query_record(
+    fullname: *const c_char,
+    rr_type: u16,
+    rr_class: u16,
+    rd_len: u16,
+    rdata: *const c_void,
+    ttl: u32,
+    interface_index: u32,
+) -> Result<QueriedRecord> {
+    Ok(QueriedRecord::builder()
+        .name(bonjour_util::normalize_domain(c_str::try_raw_to_str(
+            fullname,
+            "record name",
+        )?))
+        .rr_type(rr_type)
+        .rr_class(rr_class)
+        .rdata(ffi::raw_to_bytes(rdata, rd_len as usize, "record data")?)
+        .ttl(Some(ttl))
+        .interface(bonjour_util::interface_from_index(interface_index))
+        .build()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn handle_query_record_copies_record() {
+        let fullname = c_string!("foo._http._tcp.local.");
+        let rdata = b"\x03a=1";
+
+        let record = unsafe {
+            handle_query_record(
+                fullname.as_ptr(),
+                16,
+                crate::RR_CLASS_IN,
+                rdata.len() as u16,
+                rdata.as_ptr() as *const c_void,
+                120,
+                0,
+            )
+        }
+        .unwrap();
+
+        assert_eq!(record.name(), "foo._http._tcp.local");
+        assert_eq!(record.rdata(), &rdata.to_vec());
+        assert_eq!(*record.ttl(), Some(120));
+    }
+}
@@ -0,0 +1,978 @@
+//! Bonjour implementation for cross-platform service resolver
+
+use super::service_ref::{
+    GetAddressInfoParams, ServiceResolveParams, SharedConnection, SharedServiceRef,
+};
+use super::{bonjour_util, constants};
+use crate::browser;
+use crate::cache::ServiceCache;
+use crate::clock::{Clock, SystemClock};
+use crate::dedup::Deduplicator;
+use crate::error::{Error, ErrorContext, Operation, ResultExt};
+use crate::ffi::{self, c_str, AsRaw, CallbackContext};
+use crate::filter::BrowserFilter;
+use crate::interface_watcher::InterfaceWatcher;
+use crate::prelude::*;
+use crate::resolve_queue::ResolveQueue;
+use crate::settle::SettleTracker;
+use crate::span::Span;
+use crate::{
+    BrowserEvent, BrowserEventCallback, BrowserStats, ResolveFailure, ServiceDiscoveredCallback,
+    ServiceDiscovery,
+};
+use crate::{
+    EventLoop, Id, LookupFlags, NetworkInterface, Protocol, Result, ServiceKey, ServiceType,
+    DEFAULT_RESCAN_DEADLINE,
+};
+use bonjour_sys::{DNSServiceErrorType, DNSServiceFlags, DNSServiceRef};
+use libc::{c_char, c_uchar, c_void};
+use std::any::Any;
+use std::collections::HashMap;
+use std::ffi::CString;
+use std::fmt::{self, Formatter};
+use std::slice;
+use std::sync::{Arc, Mutex, PoisonError, Weak};
+use std::time::{Duration, Instant};
+
+#[derive(Debug)]
+pub struct BonjourMdnsResolver {
+    interface_index: u32,
+    context: Box<BonjourResolverContext>,
+}
+
+impl TMdnsResolver for BonjourMdnsResolver {
+    fn new() -> Self {
+        Self {
+            interface_index: constants::BONJOUR_IF_UNSPEC,
+            context: Box::new(BonjourResolverContext::new()),
+        }
+    }
+
+    fn id(&self) -> Id {
+        self.context.id
+    }
+
+    fn set_network_interface(&mut self, interface: NetworkInterface) {
+        self.interface_index = bonjour_util::interface_index(interface);
+    }
+
+    fn network_interface(&self) -> NetworkInterface {
+        bonjour_util::interface_from_index(self.interface_index)
+    }
+
+    fn set_service_resolved_callback(
+        &mut self,
+        service_resolved_callback: Box<ServiceDiscoveredCallback>,
+    ) {
+        self.context.service_discovered_callback = Some(Mutex::new(service_resolved_callback));
+    }
+
+    fn set_context(&mut self, context: Box<dyn Any + Send + Sync>) {
+        self.context.user_context = Some(Arc::from(context));
+    }
+
+    fn context(&self) -> Option<&(dyn Any + Send + Sync)> {
+        self.context.user_context.as_ref().map(|c| c.as_ref())
+    }
+
+    fn resolve(
+        &mut self,
+        name: &str,
+        service_type: ServiceType,
+        domain: &str,
+    ) -> Result<EventLoop> {
+        let span = span!(
+            "resolve",
+            id = %self.context.id,
+            name,
+            service_type = service_type.to_string().as_str(),
+            interface = self.interface_index
+        );
+        let _entered = span.enter();
+        self.context.span = span.clone();
+
+        debug!(
+            "[{}] Resolving service `{}` of type `{:?}` in domain `{}`: {:?}",
+            self.context.id, name, service_type, domain, self
+        );
+
+        let regtype = bonjour_util::format_regtype(&ServiceType::new(
+            service_type.name(),
+            service_type.protocol(),
+        )?);
+
+        let mut resolution = Resolution {
+            interface_index: self.interface_index,
+            name: c_string!(name),
+            regtype,
+            domain: c_string!(domain),
+            port: 0,
+            txt: None,
+            service: None,
+            deadline: None,
+        };
+
+        let connection = unsafe { SharedConnection::new() }?;
+
+        // dropping the previous resolution terminates it
+        self.context.resolutions.clear();
+        self.context.connection = Some(connection.clone());
+
+        unsafe { start_resolve(&mut self.context, &mut resolution) }
+            .with_context(|| resolution.error_context(Operation::Resolve))?;
+        let now = self.context.clock.now();
+        self.context.resolutions.insert(resolution, now);
+
+        let event_loop = EventLoop::new(connection.service());
+
+        debug!("[{}] Event loop: {}", self.context.id, event_loop.id());
+
+        Ok(event_loop)
+    }
+}
+
+/// A service that is resolved on a [`SharedConnection`]: its SRV and TXT records first, then
+/// the address of its host.
+///
+/// [`SharedConnection`]: ../service_ref/struct.SharedConnection.html
+#[derive(Debug)]
+pub(crate) struct Resolution {
+    interface_index: u32,
+    name: CString,
+    regtype: CString,
+    domain: CString,
+    port: u16,
+    /// The TXT record in the DNS wire format
+    txt: Option<Vec<u8>>,
+    /// The operation that is running, `None` while the resolution is queued
+    service: Option<SharedServiceRef>,
+    /// When the resolution is given up on, `None` until it has been started
+    deadline: Option<Instant>,
+}
+
+impl Resolution {
+    /// Copies the values passed to a `DNSServiceBrowseReply` for a new service, as they only
+    /// live as long as the callback.
+    ///
+    /// # Safety
+    /// This function is unsafe because of the raw pointer dereferences.
+    pub(crate) unsafe fn new(
+        interface_index: u32,
+        name: *const c_char,
+        regtype: *const c_char,
+        domain: *const c_char,
+    ) -> Result<Self> {
+        Ok(Self {
+            interface_index,
+            name: c_string!(c_str::try_raw_to_str(name, "service name")?),
+            regtype: c_string!(c_str::try_raw_to_str(regtype, "service type")?),
+            domain: c_string!(c_str::try_raw_to_str(domain, "service domain")?),
+            port: 0,
+            txt: None,
+            service: None,
+            deadline: None,
+        })
+    }
+
+    /// Returns the context of the errors of `operation` on the service.
+    pub(crate) fn error_context(&self, operation: Operation) -> ErrorContext {
+        ErrorContext::new(operation)
+            .with_service_type(&bonjour_util::normalize_domain(c_str::to_str(
+                &self.regtype,
+            )))
+            .with_name(Some(c_str::to_str(&self.name)))
+    }
+
+    /// Returns the key of the service.
+    fn key(&self) -> Result<ServiceKey> {
+        let regtype = bonjour_util::normalize_domain(c_str::to_str(&self.regtype));
+
+        Ok(ServiceKey::new(
+            c_str::to_str(&self.name),
+            &bonjour_util::parse_regtype(&regtype)?,
+            &bonjour_util::normalize_domain(c_str::to_str(&self.domain)),
+        ))
+    }
+
+    fn is_of(&self, interface_index: u32, name: &str, regtype: &str, domain: &str) -> bool {
+        self.interface_index == interface_index
+            && c_str::to_str(&self.name) == name
+            && c_str::to_str(&self.regtype) == regtype
+            && c_str::to_str(&self.domain) == domain
+    }
+
+    fn span(&self) -> Span {
+        span!(
+            "resolve",
+            name = c_str::to_str(&self.name),
+            service_type = c_str::to_str(&self.regtype),
+            interface = self.interface_index
+        )
+    }
+}
+
+/// The resolutions that are running on the connection of a browser or resolver, along with the
+/// services that are queued until fewer than the maximum number of resolutions are running.
+///
+/// Running resolutions are identified by the ref of their operation, which is passed to its
+/// callbacks.
+#[derive(Debug, Default)]
+pub(crate) struct ServiceResolutionSet {
+    resolutions: HashMap<DNSServiceRef, Resolution>,
+    queue: ResolveQueue<Resolution>,
+}
+
+// SAFETY: the raw keys only identify the resolutions and are never dereferenced, and the shared
+// refs of the resolutions are `Send`.
+unsafe impl Send for ServiceResolutionSet {}
+
+impl ServiceResolutionSet {
+    /// Adds a resolution whose operation has been started at `now`. The resolution is given up on
+    /// by `expire()` once the resolve timeout has passed since it was first added.
+    fn insert(&mut self, mut resolution: Resolution, now: Instant) {
+        if resolution.deadline.is_none() {
+            resolution.deadline = Some(self.queue.deadline(now));
+        }
+
+        if let Some(service) = &resolution.service {
+            self.resolutions.insert(service.as_raw(), resolution);
+        }
+    }
+
+    /// Removes the resolution whose operation is `raw`. The operation keeps running until the
+    /// resolution is dropped.
+    fn remove_raw(&mut self, raw: DNSServiceRef) -> Option<Resolution> {
+        self.resolutions.remove(&raw)
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.resolutions.len()
+    }
+
+    /// Queues a service to be resolved by `start_resolutions()`.
+    pub(crate) fn queue(&mut self, resolution: Resolution) {
+        self.queue.push(resolution);
+    }
+
+    /// Returns the next queued service to resolve, if fewer than the maximum number of
+    /// resolutions are running.
+    fn next_pending(&mut self) -> Option<Resolution> {
+        self.queue.next(self.resolutions.len())
+    }
+
+    /// Terminates the resolutions of a service that is no longer found, whether they are running
+    /// or queued. Returns the number of resolutions that were terminated.
+    pub(crate) fn cancel(
+        &mut self,
+        interface_index: u32,
+        name: &str,
+        regtype: &str,
+        domain: &str,
+    ) -> usize {
+        let running = self.resolutions.len();
+
+        self.resolutions
+            .retain(|_, r| !r.is_of(interface_index, name, regtype, domain));
+
+        let cancelled = running - self.resolutions.len();
+
+        cancelled
+            + self
+                .queue
+                .remove_where(|r| r.is_of(interface_index, name, regtype, domain))
+    }
+
+    /// Terminates every resolution, running or queued.
+    pub(crate) fn clear(&mut self) {
+        self.resolutions.clear();
+        self.queue.remove_where(|_| true);
+    }
+
+    /// Removes the running resolutions whose deadline has passed at `now`. Their operations keep
+    /// running until they are dropped.
+    fn expire(&mut self, now: Instant) -> Vec<Resolution> {
+        let expired = self
+            .resolutions
+            .iter()
+            .filter(|(_, r)| r.deadline.is_some_and(|deadline| deadline <= now))
+            .map(|(raw, _)| *raw)
+            .collect::<Vec<_>>();
+
+        expired
+            .into_iter()
+            .filter_map(|raw| self.resolutions.remove(&raw))
+            .collect()
+    }
+
+    pub(crate) fn set_max_concurrent(&mut self, max_concurrent: usize) {
+        self.queue.set_max_concurrent(max_concurrent);
+    }
+
+    pub(crate) fn max_concurrent(&self) -> usize {
+        self.queue.max_concurrent()
+    }
+
+    pub(crate) fn set_timeout(&mut self, timeout: Duration) {
+        self.queue.set_timeout(timeout);
+    }
+
+    pub(crate) fn timeout(&self) -> Duration {
+        self.queue.timeout()
+    }
+}
+
+#[derive(FromRaw, AsRaw)]
+pub(crate) struct BonjourResolverContext {
+    pub(crate) id: Id,
+    pub(crate) service_discovered_callback: Option<Mutex<Box<ServiceDiscoveredCallback>>>,
+    pub(crate) browser_event_callback: Option<Box<BrowserEventCallback>>,
+    pub(crate) all_for_now: bool,
+    pub(crate) meta_query: bool,
+    pub(crate) lookup_flags: LookupFlags,
+    pub(crate) protocol: Protocol,
+    pub(crate) dedup: Option<Deduplicator>,
+    pub(crate) filter: BrowserFilter,
+    /// The service type that is browsed for, `None` when resolving a single service
+    pub(crate) service_type: Option<ServiceType>,
+    /// The connection the resolutions are started on
+    pub(crate) connection: Option<SharedConnection>,
+    pub(crate) resolutions: ServiceResolutionSet,
+    /// The error of the last browse result, if the end of its batch has not been reported yet
+    pub(crate) pending_flush: Option<DNSServiceErrorType>,
+    pub(crate) user_context: Option<Arc<dyn Any + Send + Sync>>,
+    pub(crate) settle: Option<Arc<Mutex<SettleTracker>>>,
+    /// Measures the settle deadline and the resolve timeouts
+    pub(crate) clock: Arc<dyn Clock>,
+    pub(crate) stats: Mutex<BrowserStats>,
+    /// The services reported to the callbacks, updated before they are invoked
+    pub(crate) cache: Mutex<ServiceCache>,
+    pub(crate) rescan_deadline: Duration,
+    /// Reports the interfaces that went down or came up while browsing
+    pub(crate) interfaces: Option<InterfaceWatcher>,
+    pub(crate) span: Span,
+    /// Dropped with the context, so that a `ResolveTimeouts` outliving it does nothing
+    alive: Arc<()>,
+}
+
+impl BonjourResolverContext {
+    pub(crate) fn new() -> Self {
+        Self {
+            id: Id::next(),
+            service_discovered_callback: None,
+            browser_event_callback: None,
+            all_for_now: false,
+            meta_query: false,
+            lookup_flags: LookupFlags::NONE,
+            protocol: Protocol::Any,
+            dedup: None,
+            filter: BrowserFilter::default(),
+            service_type: None,
+            connection: None,
+            resolutions: ServiceResolutionSet::default(),
+            pending_flush: None,
+            user_context: None,
+            settle: None,
+            clock: Arc::new(SystemClock),
+            stats: Mutex::default(),
+            cache: Mutex::default(),
+            rescan_deadline: DEFAULT_RESCAN_DEADLINE,
+            interfaces: None,
+            span: Span::none(),
+            alive: Arc::default(),
+        }
+    }
+
+    pub(crate) fn update_settle(&self, f: impl FnOnce(&mut SettleTracker)) {
+        if let Some(settle) = &self.settle {
+            f(&mut settle
+                .lock()
+                .expect("should have been able to obtain lock on settle tracker"));
+        }
+    }
+
+    pub(crate) fn update_cache(&self, f: impl FnOnce(&mut ServiceCache)) {
+        f(&mut self.cache.lock().unwrap_or_else(PoisonError::into_inner));
+    }
+
+    /// Removes the services that have not been reported again by the deadline of a rescan, or
+    /// that have been degraded for longer than the grace period, from the cache, and returns
+    /// their keys.
+    pub(crate) fn expired_services(&self) -> Vec<ServiceKey> {
+        let now = self.clock.now();
+
+        self.cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .expired(now)
+    }
+
+    /// Marks the services found on the interfaces that went down since the previous call as
+    /// degraded, or restores those on the interfaces that came back up, and returns the events to
+    /// report for them.
+    pub(crate) fn reachability_changes(&mut self) -> Vec<BrowserEvent> {
+        let changes = match &mut self.interfaces {
+            Some(interfaces) => interfaces.changes(),
+            None => return vec![],
+        };
+
+        let now = self.clock.now();
+
+        self.cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .interfaces_changed(changes, now)
+    }
+
+    pub(crate) fn update_stats(&self, f: impl FnOnce(&mut BrowserStats)) {
+        f(&mut self.stats.lock().unwrap_or_else(PoisonError::into_inner));
+    }
+
+    pub(crate) fn invoke_browser_event_callback(&self, event: BrowserEvent) {
+        debug!("[{}] Browser event: {:?}", self.id, event);
+
+        if let Some(f) = &self.browser_event_callback {
+            f(event, self.user_context.clone());
+        }
+    }
+
+    /// Reports the end of a batch of browse results once a callback is invoked without
+    /// `kDNSServiceFlagsMoreComing`. The flag applies to every operation on the connection, so
+    /// the batch may end in the callback of a resolution. The end of the first batch is taken as
+    /// the end of the initial enumeration.
+    pub(crate) fn end_of_results(&mut self, flags: DNSServiceFlags) {
+        if flags & bonjour_sys::kDNSServiceFlagsMoreComing as DNSServiceFlags != 0 {
+            return;
+        }
+
+        let error = match self.pending_flush.take() {
+            Some(error) => error,
+            None => return,
+        };
+
+        if error == 0 {
+            self.update_settle(|s| s.enumerated());
+        }
+
+        self.invoke_browser_event_callback(BrowserEvent::Flushed);
+
+        if error == 0 && !self.all_for_now {
+            self.all_for_now = true;
+            self.invoke_browser_event_callback(BrowserEvent::AllForNow);
+        }
+    }
+
+    /// Returns the context of the errors of the browse itself, rather than of resolving a service.
+    pub(crate) fn browse_context(&self) -> ErrorContext {
+        let context = ErrorContext::new(Operation::Browse);
+
+        match &self.service_type {
+            Some(service_type) => context.with_service_type(&service_type.to_string()),
+            None => context,
+        }
+    }
+
+    pub(crate) fn invoke_callback(&self, result: Result<ServiceDiscovery>) {
+        if result.is_err() {
+            self.update_stats(|s| s.failed());
+        }
+
+        if let Some(f) = &self.service_discovered_callback {
+            let mut f = f.lock().unwrap_or_else(PoisonError::into_inner);
+            f(result, self.user_context.clone());
+        } else {
+            warn!(
+                "[{}] attempted to invoke callback but none was set",
+                self.id
+            );
+        }
+    }
+}
+
+impl CallbackContext for BonjourResolverContext {
+    fn id(&self) -> Id {
+        self.id
+    }
+
+    fn invoke_error(&self, error: Error) {
+        self.invoke_callback(Err(error))
+    }
+
+    fn span(&self) -> Option<&Span> {
+        Some(&self.span)
+    }
+}
+
+impl fmt::Debug for BonjourResolverContext {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BonjourResolverContext")
+            .field("id", &self.id)
+            .field("resolutions", &self.resolutions)
+            .finish()
+    }
+}
+
+/// Starts resolving queued services until the maximum number of resolutions is running. A
+/// service that cannot be resolved is reported to the callback.
+///
+/// # Safety
+/// This function is unsafe because it calls C functions.
+pub(crate) unsafe fn start_resolutions(ctx: &mut BonjourResolverContext) {
+    while let Some(mut resolution) = ctx.resolutions.next_pending() {
+        match start_resolve(ctx, &mut resolution) {
+            Ok(()) => ctx.resolutions.insert(resolution, ctx.clock.now()),
+            Err(e) => {
+                let error_context = resolution.error_context(Operation::Resolve);
+                ctx.invoke_callback(Err(e.with_context(error_context)));
+                ctx.update_settle(|s| s.resolution_finished());
+            }
+        }
+    }
+}
+
+unsafe fn start_resolve(
+    ctx: &mut BonjourResolverContext,
+    resolution: &mut Resolution,
+) -> Result<()> {
+    let params = ServiceResolveParams::builder()
+        .flags(
+            bonjour_sys::kDNSServiceFlagsForceMulticast
+                | bonjour_sys::kDNSServiceFlagsShareConnection,
+        )
+        .interface_index(resolution.interface_index)
+        .name(resolution.name.as_ptr())
+        .regtype(resolution.regtype.as_ptr())
+        .domain(resolution.domain.as_ptr())
+        .callback(Some(resolve_callback))
+        .context(ctx.as_raw())
+        .build()?;
+
+    let connection = ctx
+        .connection
+        .as_ref()
+        .ok_or("expected initialized connection")?;
+
+    resolution.service = Some(connection.start(|s| s.start_resolve_service(params))?);
+
+    Ok(())
+}
+
+/// Terminates the resolutions that have not finished within the resolve timeout, and starts
+/// resolving the services queued in their place.
+unsafe fn expire_resolutions(ctx: &mut BonjourResolverContext) {
+    let now = ctx.clock.now();
+    let expired = ctx.resolutions.expire(now);
+
+    if expired.is_empty() {
+        return;
+    }
+
+    for resolution in expired {
+        ctx.update_settle(|s| s.resolution_finished());
+
+        match resolution.key() {
+            Ok(key) => {
+                debug!("[{}] Resolve timed out: {:?}", ctx.id, key);
+
+                ctx.invoke_browser_event_callback(BrowserEvent::ResolveFailed {
+                    key,
+                    reason: ResolveFailure::Timeout,
+                });
+            }
+            Err(e) => {
+                let error_context = resolution.error_context(Operation::Resolve);
+                ctx.invoke_callback(Err(e.with_context(error_context)));
+            }
+        }
+    }
+
+    start_resolutions(ctx);
+}
+
+/// Reports the services whose network interface went down or came back up.
+fn track_interfaces(ctx: &mut BonjourResolverContext) {
+    for event in ctx.reachability_changes() {
+        ctx.invoke_browser_event_callback(event);
+    }
+}
+
+/// Reports the removal of the services that have not been reported again by the deadline of a
+/// rescan, or that have been degraded for longer than the grace period.
+fn expire_services(ctx: &mut BonjourResolverContext) {
+    for key in ctx.expired_services() {
+        debug!("[{}] Service expired: {:?}", ctx.id, key);
+        ctx.invoke_browser_event_callback(BrowserEvent::ServiceRemoved(key));
+    }
+}
+
+/// Terminates the resolutions of a browser that have timed out on behalf of
+/// `BonjourEventLoop::poll()`.
+pub(crate) struct ResolveTimeouts {
+    context: *mut c_void,
+    alive: Weak<()>,
+}
+
+// SAFETY: `run()` only dereferences the context while the browser that owns it is alive, from
+// `BonjourEventLoop::poll()` while no result is being processed. The context is not locked, so
+// this relies on the browser not being used from another thread while its event loop is polled.
+unsafe impl Send for ResolveTimeouts {}
+
+impl ResolveTimeouts {
+    pub(crate) fn new(ctx: &mut BonjourResolverContext) -> Self {
+        Self {
+            alive: Arc::downgrade(&ctx.alive),
+            context: ctx.as_raw(),
+        }
+    }
+
+    /// Terminates the resolutions that have timed out, reports the services whose network
+    /// interface went down or came back up, and removes the services that have not been reported
+    /// again by the deadline of a rescan or that have been degraded for longer than the grace
+    /// period. Does nothing if the browser has been dropped.
+    ///
+    /// # Safety
+    /// This function is unsafe because of the raw pointer dereference. It must not be called
+    /// while the connection is processing a result.
+    pub(crate) unsafe fn run(&self) {
+        if self.alive.strong_count() == 0 {
+            return;
+        }
+
+        ffi::guard(self.context, |ctx: &mut BonjourResolverContext| {
+            expire_resolutions(ctx);
+            track_interfaces(ctx);
+            expire_services(ctx);
+        });
+
+        // releases the context if the browser was dropped by a callback
+        ffi::drop_dispatched();
+    }
+}
+
+/// Records that a resolution has ended and starts the next queued one.
+unsafe fn finish_resolution(ctx: &mut BonjourResolverContext) {
+    ctx.update_settle(|s| s.resolution_finished());
+    start_resolutions(ctx);
+}
+
+unsafe extern "system" fn resolve_callback(
+    sd_ref: DNSServiceRef,
+    flags: DNSServiceFlags,
+    interface_index: u32,
+    error: DNSServiceErrorType,
+    _fullname: *const c_char,
+    host_target: *const c_char,
+    port: u16,
+    txt_len: u16,
+    txt_record: *const c_uchar,
+    context: *mut c_void,
+) {
+    ffi::guard(context, |ctx: &mut BonjourResolverContext| {
+        ctx.update_stats(|s| s.event_received());
+
+        // the resolution is gone if it was cancelled while results were pending
+        if let Some(mut resolution) = ctx.resolutions.remove_raw(sd_ref) {
+            let span = resolution.span();
+            let _entered = span.enter();
+
+            let result = handle_resolve(
+                ctx,
+                &mut resolution,
+                error,
+                port,
+                interface_index,
+                host_target,
+                txt_len,
+                txt_record,
+            );
+
+            match result {
+                Ok(true) => ctx.resolutions.insert(resolution, ctx.clock.now()),
+                Ok(false) => finish_resolution(ctx),
+                Err(e) => {
+                    let error_context = resolution.error_context(Operation::Resolve);
+                    ctx.invoke_callback(Err(e.with_context(error_context)));
+                    finish_resolution(ctx);
+                }
+            }
+        }
+
+        ctx.end_of_results(flags);
+    })
+}
+
+/// Handles the SRV and TXT records of a resolution. Returns true if the address of the host is
+/// looked up next, in which case the resolution is still running.
+#[allow(clippy::too_many_arguments)]
+unsafe fn handle_resolve(
+    ctx: &mut BonjourResolverContext,
+    resolution: &mut Resolution,
+    error: DNSServiceErrorType,
+    port: u16,
+    interface_index: u32,
+    host_target: *const c_char,
+    txt_len: u16,
+    txt_record: *const c_uchar,
+) -> Result<bool> {
+    if error != 0 {
+        return Err(format!("error reported by resolve_callback: (code: {})", error).into());
+    }
+
+    ensure_not_null!(host_target, "host target");
+
+    resolution.port = port;
+
+    let look_up_txt = !ctx.lookup_flags.contains(LookupFlags::NO_TXT);
+
+    // the record is only decoded if it is accessed. A service without attributes has a record of
+    // a single empty string, which is reported as an empty record rather than none.
+    resolution.txt = if look_up_txt && txt_len > 0 && !txt_record.is_null() {
+        Some(slice::from_raw_parts(txt_record, txt_len as usize).to_vec())
+    } else {
+        None
+    };
+
+    if ctx.lookup_flags.contains(LookupFlags::NO_ADDRESS) {
+        let host_name = c_str::try_raw_to_str(host_target, "host target")?.to_string();
+        report_resolved(
+            ctx,
+            resolution,
+            host_name,
+            String::new(),
+            interface_index,
+            None,
+        )?;
+        return Ok(false);
+    }
+
+    let params = GetAddressInfoParams::builder()
+        .flags(
+            bonjour_sys::kDNSServiceFlagsForceMulticast
+                | bonjour_sys::kDNSServiceFlagsShareConnection,
+        )
+        .interface_index(interface_index)
+        .protocol(bonjour_util::address_protocol(ctx.protocol))
+        .hostname(host_target)
+        .callback(Some(get_address_info_callback))
+        .context(ctx.as_raw())
+        .build()?;
+
+    let connection = ctx
+        .connection
+        .as_ref()
+        .ok_or("expected initialized connection")?;
+
+    // replacing the operation terminates the resolve, which has delivered its result
+    resolution.service = Some(connection.start(|s| s.start_get_address_info(params))?);
+
+    Ok(true)
+}
+
+unsafe extern "system" fn get_address_info_callback(
+    sd_ref: DNSServiceRef,
+    flags: DNSServiceFlags,
+    interface_index: u32,
+    error: DNSServiceErrorType,
+    hostname: *const c_char,
+    address: *const bonjour_sys::sockaddr,
+    ttl: u32,
+    context: *mut c_void,
+) {
+    ffi::guard(context, |ctx: &mut BonjourResolverContext| {
+        ctx.update_stats(|s| s.event_received());
+
+        // only the first address is reported, after which the lookup is terminated
+        if let Some(mut resolution) = ctx.resolutions.remove_raw(sd_ref) {
+            let span = resolution.span();
+            let _entered = span.enter();
+
+            let result = handle_get_address_info(
+                ctx,
+                &mut resolution,
+                error,
+                interface_index,
+                address,
+                hostname,
+                ttl,
+            );
+
+            if let Err(e) = result {
+                let error_context = resolution.error_context(Operation::GetAddrInfo);
+                ctx.invoke_callback(Err(e.with_context(error_context)));
+            }
+
+            finish_resolution(ctx);
+        }
+
+        ctx.end_of_results(flags);
+    })
+}
+
+unsafe fn handle_get_address_info(
+    ctx: &mut BonjourResolverContext,
+    resolution: &mut Resolution,
+    error: DNSServiceErrorType,
+    interface_index: u32,
+    address: *const bonjour_sys::sockaddr,
+    hostname: *const c_char,
+    ttl: u32,
+) -> Result<()> {
+    if error != 0 {
+        return Err(format!(
+            "get_address_info_callback() reported error (code: {})",
+            error
+        )
+        .into());
+    }
+
+    let ip = browser::scoped_address(
+        bonjour_util::sockaddr_to_ip(address)?.to_string(),
+        bonjour_util::interface_from_index(interface_index),
+    );
+
+    let hostname = c_str::try_raw_to_str(hostname, "host name")?.to_string();
+
+    report_resolved(ctx, resolution, hostname, ip, interface_index, Some(ttl))
+}
+
+/// Reports the service that has been resolved by `resolution` to the user callback.
+fn report_resolved(
+    ctx: &mut BonjourResolverContext,
+    resolution: &mut Resolution,
+    host_name: String,
+    address: String,
+    interface_index: u32,
+    ttl: Option<u32>,
+) -> Result<()> {
+    // on macOS the bytes are swapped for the port
+    let port: u16 = resolution.port.to_be();
+
+    let domain = bonjour_util::normalize_domain(c_str::to_str(&resolution.domain));
+    let kind = bonjour_util::normalize_domain(c_str::to_str(&resolution.regtype));
+
+    let result = ServiceDiscovery::builder()
+        .name(c_str::to_str(&resolution.name).to_string())
+        .service_type(bonjour_util::parse_regtype(&kind)?)
+        .domain(domain)
+        .host_name(host_name)
+        .address(address)
+        .port(port)
+        .txt_bytes(resolution.txt.take())
+        .interface(bonjour_util::interface_from_index(interface_index))
+        .ttl(ttl)
+        .build_owned()
+        .expect("could not build ServiceResolution");
+
+    if !ctx.filter.resolved(&result) {
+        debug!("[{}] Ignoring filtered service: {:?}", ctx.id, result);
+        return Ok(());
+    }
+
+    if let Some(dedup) = &mut ctx.dedup {
+        if !dedup.resolved(&result) {
+            debug!("[{}] Ignoring duplicate: {:?}", ctx.id, result);
+            return Ok(());
+        }
+    }
+
+    let now = ctx.clock.now();
+
+    ctx.update_settle(|s| s.resolved(&result));
+    ctx.update_cache(|c| c.reported(&result, now));
+    ctx.invoke_callback(Ok(result));
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TxtRecord;
+
+    #[test]
+    fn resolve_skips_txt_and_address_if_not_looked_up() {
+        let results: Arc<Mutex<Vec<Result<ServiceDiscovery>>>> = Arc::default();
+        let sink = results.clone();
+        let host_target = c_string!("foo.local.");
+        let txt = b"\x05a=123";
+
+        let name = c_string!("foo");
+        let regtype = c_string!("_http._tcp.");
+        let domain = c_string!("local.");
+
+        let mut ctx = BonjourResolverContext::new();
+        let mut resolution =
+            unsafe { Resolution::new(0, name.as_ptr(), regtype.as_ptr(), domain.as_ptr()) }
+                .unwrap();
+
+        ctx.lookup_flags = LookupFlags::NO_TXT | LookupFlags::NO_ADDRESS;
+        ctx.service_discovered_callback = Some(Mutex::new(Box::new(move |result, _| {
+            sink.lock().unwrap().push(result)
+        })));
+
+        let address_looked_up = unsafe {
+            handle_resolve(
+                &mut ctx,
+                &mut resolution,
+                0,
+                8080_u16.to_be(),
+                0,
+                host_target.as_ptr(),
+                txt.len() as u16,
+                txt.as_ptr(),
+            )
+        }
+        .unwrap();
+
+        assert!(!address_looked_up);
+
+        let results = results.lock().unwrap();
+        let discovery = results[0].as_ref().unwrap();
+
+        assert_eq!(discovery.host_name(), "foo.local.");
+        assert_eq!(discovery.address(), "");
+        assert_eq!(*discovery.port(), 8080);
+        assert_eq!(discovery.txt(), None);
+    }
+
+    #[test]
+    fn resolve_reports_single_empty_string_as_empty_txt() {
+        let results: Arc<Mutex<Vec<Result<ServiceDiscovery>>>> = Arc::default();
+        let sink = results.clone();
+        let host_target = c_string!("foo.local.");
+        let txt = [0_u8];
+
+        let name = c_string!("foo");
+        let regtype = c_string!("_http._tcp.");
+        let domain = c_string!("local.");
+
+        let mut ctx = BonjourResolverContext::new();
+        let mut resolution =
+            unsafe { Resolution::new(0, name.as_ptr(), regtype.as_ptr(), domain.as_ptr()) }
+                .unwrap();
+
+        ctx.lookup_flags = LookupFlags::NO_ADDRESS;
+        ctx.service_discovered_callback = Some(Mutex::new(Box::new(move |result, _| {
+            sink.lock().unwrap().push(result)
+        })));
+
+        unsafe {
+            handle_resolve(
+                &mut ctx,
+                &mut resolution,
+                0,
+                8080_u16.to_be(),
+                0,
+                host_target.as_ptr(),
+                txt.len() as u16,
+                txt.as_ptr(),
+            )
+        }
+        .unwrap();
+
+        let results = results.lock().unwrap();
+        let discovery = results[0].as_ref().unwrap();
+
+        assert_eq!(discovery.txt_bytes(), Some(&txt[..]));
+        assert_eq!(discovery.txt(), Some(&TxtRecord::new()));
+    }
+}
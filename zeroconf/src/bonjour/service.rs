@@ -1,48 +1,82 @@
 //! Bonjour implementation for cross-platform service.
 
-use super::service_ref::{ManagedDNSServiceRef, RegisterServiceParams};
+use super::service_ref::{
+    AddRecordParams, ManagedDNSServiceRef, RegisterRecordParams, RegisterServiceParams,
+    UpdateRecordParams,
+};
 use super::{bonjour_util, constants};
+use crate::defer::{self, DeferredQueue};
+use crate::error::{Error, ErrorContext, Operation, ResultExt};
 use crate::ffi::c_str::{self, AsCChars};
-use crate::ffi::{AsRaw, FromRaw, UnwrapOrNull};
+use crate::ffi::{self, AsRaw, CallbackContext, DropAfterDispatch, UnwrapOrNull};
 use crate::prelude::*;
+use crate::span::Span;
+use crate::{service, service_name};
 use crate::{
-    EventLoop, NetworkInterface, Result, ServiceRegisteredCallback, ServiceRegistration,
-    ServiceType, TxtRecord,
+    BackendInfo, DaemonStateCallback, Deferrer, EventLoop, Id, NameChange, NameChangedCallback,
+    NetworkInterface, Protocol, RecordSharing, RegistrationFlags, RegistrationState,
+    RegistrationStateCallback, Result, ServiceRecord, ServiceRegisteredCallback,
+    ServiceRegistration, ServiceState, ServiceType, TxtRecord,
 };
-use bonjour_sys::{DNSServiceErrorType, DNSServiceFlags, DNSServiceRef};
+use bonjour_sys::{DNSRecordRef, DNSServiceErrorType, DNSServiceFlags, DNSServiceRef};
 use libc::{c_char, c_void};
 use std::any::Any;
 use std::ffi::CString;
-use std::sync::{Arc, Mutex};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, PoisonError, Weak};
 
 #[derive(Debug)]
 pub struct BonjourMdnsService {
     service: Arc<Mutex<ManagedDNSServiceRef>>,
-    kind: CString,
+    connection: Option<Arc<Mutex<ManagedDNSServiceRef>>>,
+    service_type: ServiceType,
     port: u16,
     name: Option<CString>,
+    name_fitting: bool,
+    allow_name_conflicts: bool,
     domain: Option<CString>,
     host: Option<CString>,
+    address: Option<IpAddr>,
     interface_index: u32,
     txt_record: Option<TxtRecord>,
-    context: Box<BonjourServiceContext>,
+    records: Vec<ServiceRecord>,
+    registration_flags: RegistrationFlags,
+    context: DropAfterDispatch<Box<BonjourServiceContext>>,
 }
 
 impl TMdnsService for BonjourMdnsService {
     fn new(service_type: ServiceType, port: u16) -> Self {
         Self {
             service: Arc::default(),
-            kind: bonjour_util::format_regtype(&service_type),
+            connection: None,
+            service_type,
             port,
             name: None,
+            name_fitting: false,
+            allow_name_conflicts: true,
             domain: None,
             host: None,
+            address: None,
             interface_index: constants::BONJOUR_IF_UNSPEC,
             txt_record: None,
-            context: Box::default(),
+            records: vec![],
+            registration_flags: RegistrationFlags::NONE,
+            context: DropAfterDispatch::new(Box::new(BonjourServiceContext::new())),
         }
     }
 
+    fn id(&self) -> Id {
+        self.context.id
+    }
+
+    fn set_port(&mut self, port: u16) {
+        self.port = port;
+    }
+
+    fn port(&self) -> u16 {
+        self.port
+    }
+
     /// Sets the name to register this service under. If no name is set, Bonjour will
     /// automatically assign one (usually to the name of the machine).
     fn set_name(&mut self, name: &str) {
@@ -53,6 +87,38 @@ impl TMdnsService for BonjourMdnsService {
         self.name.as_ref().map(c_str::to_str)
     }
 
+    fn set_name_fitting(&mut self, name_fitting: bool) {
+        self.name_fitting = name_fitting;
+    }
+
+    fn name_fitting(&self) -> bool {
+        self.name_fitting
+    }
+
+    fn set_allow_name_conflicts(&mut self, allow_name_conflicts: bool) {
+        self.allow_name_conflicts = allow_name_conflicts;
+    }
+
+    fn allow_name_conflicts(&self) -> bool {
+        self.allow_name_conflicts
+    }
+
+    /// Bonjour does not support re-registering services when the connection to mDNSResponder is
+    /// lost.
+    fn set_auto_reconnect(&mut self, auto_reconnect: bool) -> Result<()> {
+        if auto_reconnect {
+            return Err(Error::Unsupported(
+                "Bonjour does not support reconnecting services".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn auto_reconnect(&self) -> bool {
+        false
+    }
+
     fn set_network_interface(&mut self, interface: NetworkInterface) {
         self.interface_index = bonjour_util::interface_index(interface);
     }
@@ -61,6 +127,21 @@ impl TMdnsService for BonjourMdnsService {
         bonjour_util::interface_from_index(self.interface_index)
     }
 
+    /// Bonjour registers services on every protocol, so only `Protocol::Any` is supported.
+    fn set_protocol(&mut self, protocol: Protocol) -> Result<()> {
+        if protocol != Protocol::Any {
+            return Err(Error::Unsupported(
+                "Bonjour does not support registering services on a single protocol".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn protocol(&self) -> Protocol {
+        Protocol::Any
+    }
+
     fn set_domain(&mut self, domain: &str) {
         self.domain = Some(c_string!(domain));
     }
@@ -77,6 +158,14 @@ impl TMdnsService for BonjourMdnsService {
         self.host.as_ref().map(c_str::to_str)
     }
 
+    fn set_address(&mut self, address: IpAddr) {
+        self.address = Some(address);
+    }
+
+    fn address(&self) -> Option<IpAddr> {
+        self.address
+    }
+
     fn set_txt_record(&mut self, txt_record: TxtRecord) {
         self.txt_record = Some(txt_record);
     }
@@ -85,83 +174,655 @@ impl TMdnsService for BonjourMdnsService {
         self.txt_record.as_ref()
     }
 
+    /// Adds an additional record to publish with this service.
+    ///
+    /// Records without a name are added to the service itself and share its uniqueness, so they
+    /// cannot be `RecordSharing::Shared` on Bonjour.
+    fn add_record(&mut self, record: ServiceRecord) -> Result<()> {
+        validate_record(&record)?;
+        self.records.push(record);
+
+        Ok(())
+    }
+
+    fn records(&self) -> &[ServiceRecord] {
+        &self.records
+    }
+
+    fn add_sub_type(&mut self, sub_type: &str) -> Result<()> {
+        self.service_type.add_sub_type(sub_type).map(|_| ())
+    }
+
+    fn remove_sub_type(&mut self, sub_type: &str) -> Result<()> {
+        self.service_type.remove_sub_type(sub_type).map(|_| ())
+    }
+
+    fn set_registration_flags(&mut self, flags: RegistrationFlags) -> Result<()> {
+        bonjour_util::register_flags(flags)?;
+        self.registration_flags = flags;
+        Ok(())
+    }
+
+    fn registration_flags(&self) -> RegistrationFlags {
+        self.registration_flags
+    }
+
     fn set_registered_callback(&mut self, registered_callback: Box<ServiceRegisteredCallback>) {
-        self.context.registered_callback = Some(registered_callback);
+        self.context.registered_callback = Some(Mutex::new(registered_callback));
+    }
+
+    fn set_registration_state_callback(
+        &mut self,
+        registration_state_callback: Box<RegistrationStateCallback>,
+    ) {
+        self.context.registration_state_callback = Some(registration_state_callback);
     }
 
-    fn set_context(&mut self, context: Box<dyn Any>) {
+    fn set_name_changed_callback(&mut self, name_changed_callback: Box<NameChangedCallback>) {
+        self.context.name_changed_callback = Some(name_changed_callback);
+    }
+
+    fn set_context(&mut self, context: Box<dyn Any + Send + Sync>) {
         self.context.user_context = Some(Arc::from(context));
     }
 
-    fn context(&self) -> Option<&dyn Any> {
+    fn context(&self) -> Option<&(dyn Any + Send + Sync)> {
         self.context.user_context.as_ref().map(|c| c.as_ref())
     }
 
+    fn deferrer(&self) -> Deferrer {
+        Deferrer::new(&self.context.deferred)
+    }
+
+    /// Bonjour connects to the daemon synchronously, so waiting for it is not supported.
+    fn set_wait_for_daemon(&mut self, wait_for_daemon: bool) -> Result<()> {
+        if wait_for_daemon {
+            return Err(Error::Unsupported(
+                "Bonjour does not support waiting for the daemon".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn wait_for_daemon(&self) -> bool {
+        false
+    }
+
+    /// Bonjour reports failures to connect to the daemon from `register()`, so the callback is
+    /// never invoked.
+    fn set_daemon_state_callback(&mut self, _daemon_state_callback: Box<DaemonStateCallback>) {}
+
     fn register(&mut self) -> Result<EventLoop> {
-        debug!("Registering service: {:?}", self);
+        let span = span!(
+            "register",
+            id = %self.context.id,
+            name = self.name.as_ref().map(c_str::to_str),
+            service_type = self.service_type.to_string().as_str()
+        );
+        let _entered = span.enter();
+        self.context.span = span.clone();
+
+        debug!("[{}] Registering service: {:?}", self.context.id, self);
+
+        self.context.name_adjusted = false;
+        self.context.registered_name = None;
+
+        if let Some(name) = &self.name {
+            let (fitted, adjusted) = service_name::prepare(c_str::to_str(name), self.name_fitting)?;
+
+            if adjusted {
+                debug!(
+                    "[{}] Service name shortened to: {}",
+                    self.context.id, fitted
+                );
+                self.name = Some(c_string!(fitted.as_ref()));
+                self.context.name_adjusted = true;
+            }
+        }
+
+        let mut flags = bonjour_util::register_flags(self.registration_flags)?;
+
+        if !self.allow_name_conflicts {
+            flags |= bonjour_sys::kDNSServiceFlagsNoAutoRename as DNSServiceFlags;
+        }
+
+        self.context.registration = Some(BonjourRegistration {
+            service: self.service.clone(),
+            flags,
+            interface_index: self.interface_index,
+            name: self.name.clone(),
+            name_fitting: self.name_fitting,
+            service_type: self.service_type.clone(),
+            domain: self.domain.clone(),
+            host: self.host.clone(),
+            port: self.port,
+            txt_record: self.txt_record.clone(),
+            records: self
+                .records
+                .iter()
+                .filter(|r| r.name().is_none())
+                .cloned()
+                .collect(),
+        });
+
+        unsafe { register_service(&mut self.context) }
+            .with_context(|| self.context.error_context())?;
+
+        self.connection = self
+            .register_named_records()
+            .with_context(|| self.context.error_context())?;
+
+        let event_loop = match &self.connection {
+            Some(connection) => {
+                EventLoop::with_connection(self.service.clone(), connection.clone())
+            }
+            None => EventLoop::new(self.service.clone()),
+        };
+
+        debug!("[{}] Event loop: {}", self.context.id, event_loop.id());
+
+        Ok(event_loop.with_deferred(DeferredRunner::new(&mut self.context)))
+    }
+
+    fn state(&self) -> ServiceState {
+        *self
+            .context
+            .state
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn backend_info(&self) -> Result<BackendInfo> {
+        if self.context.registration.is_none() {
+            return Err("service has not been registered".into());
+        }
+
+        bonjour_util::backend_info()
+    }
+}
+
+impl BonjourMdnsService {
+    /// Registers the records that have a name of their own, including the address of the host,
+    /// on a separate connection, since `DNSServiceAddRecord` can only add records to the
+    /// service's own name.
+    fn register_named_records(&mut self) -> Result<Option<Arc<Mutex<ManagedDNSServiceRef>>>> {
+        let address_record = self.address_record()?;
+
+        let records = self
+            .records
+            .iter()
+            .chain(address_record.as_ref())
+            .filter_map(|r| r.name().as_ref().map(|name| (name, r)))
+            .collect::<Vec<_>>();
+
+        if records.is_empty() {
+            return Ok(None);
+        }
+
+        let mut connection = ManagedDNSServiceRef::default();
+
+        unsafe { connection.create_connection()? };
+
+        for (name, record) in records {
+            debug!("[{}] Registering record: {:?}", self.context.id, record);
+
+            let fullname = c_string!(name.as_str());
+
+            let flags = match record.sharing() {
+                RecordSharing::Unique => bonjour_sys::kDNSServiceFlagsUnique,
+                RecordSharing::Shared => bonjour_sys::kDNSServiceFlagsShared,
+            };
 
-        let txt_len = self
-            .txt_record
+            let params = RegisterRecordParams::builder()
+                .flags(flags as DNSServiceFlags)
+                .interface_index(self.interface_index)
+                .fullname(fullname.as_ptr())
+                .rr_type(*record.rr_type())
+                .rr_class(bonjour_sys::kDNSServiceClass_IN as u16)
+                .rdata(record.rdata())
+                .ttl(*record.ttl())
+                .callback(Some(register_record_callback))
+                .context(self.context.as_raw())
+                .build()?;
+
+            unsafe { connection.register_record(params)? };
+        }
+
+        Ok(Some(Arc::new(Mutex::new(connection))))
+    }
+
+    /// Returns the A or AAAA record for the address set with `set_address()`, if any.
+    fn address_record(&self) -> Result<Option<ServiceRecord>> {
+        let address = match self.address {
+            Some(address) => address,
+            None => return Ok(None),
+        };
+
+        let host = self.host().ok_or(service::ADDRESS_WITHOUT_HOST)?;
+
+        let (rr_type, rdata) = match address {
+            IpAddr::V4(v4) => (bonjour_sys::kDNSServiceType_A, v4.octets().to_vec()),
+            IpAddr::V6(v6) => (bonjour_sys::kDNSServiceType_AAAA, v6.octets().to_vec()),
+        };
+
+        Ok(Some(
+            ServiceRecord::builder()
+                .name(Some(host.to_string()))
+                .rr_type(rr_type as u16)
+                .rdata(rdata)
+                .build()?,
+        ))
+    }
+}
+
+/// Handle passed to closures queued with a [`Deferrer`]. See [`TDeferredService`].
+///
+/// [`Deferrer`]: ../../struct.Deferrer.html
+/// [`TDeferredService`]: ../../defer/trait.TDeferredService.html
+#[derive(Debug)]
+pub struct BonjourDeferredService<'a> {
+    context: &'a mut BonjourServiceContext,
+}
+
+impl BonjourDeferredService<'_> {
+    fn registration(&mut self) -> Result<&mut BonjourRegistration> {
+        Ok(self
+            .context
+            .registration
+            .as_mut()
+            .ok_or("service has not been registered")?)
+    }
+}
+
+impl TDeferredService for BonjourDeferredService<'_> {
+    fn name(&self) -> Option<&str> {
+        self.context
+            .registration
             .as_ref()
-            .map(|t| unsafe { t.inner().get_length() })
-            .unwrap_or(0);
+            .and_then(|r| r.name.as_ref())
+            .map(c_str::to_str)
+    }
 
-        let txt_record = self
-            .txt_record
+    fn txt_record(&self) -> Option<&TxtRecord> {
+        self.context
+            .registration
             .as_ref()
-            .map(|t| unsafe { t.inner().get_bytes_ptr() })
-            .unwrap_or_null();
+            .and_then(|r| r.txt_record.as_ref())
+    }
 
-        let mut service_lock = self
-            .service
-            .lock()
-            .expect("should be able to obtain lock on service");
-
-        let register_params = RegisterServiceParams::builder()
-            .flags(constants::BONJOUR_RENAME_FLAGS)
-            .interface_index(self.interface_index)
-            .name(self.name.as_ref().as_c_chars().unwrap_or_null())
-            .regtype(self.kind.as_ptr())
-            .domain(self.domain.as_ref().as_c_chars().unwrap_or_null())
-            .host(self.host.as_ref().as_c_chars().unwrap_or_null())
-            .port(self.port)
-            .txt_len(txt_len)
-            .txt_record(txt_record)
-            .callback(Some(register_callback))
-            .context(self.context.as_raw())
+    fn set_txt_record(&mut self, txt_record: TxtRecord) -> Result<()> {
+        debug!("[{}] Updating TXT record", self.context.id);
+
+        let registration = self.registration()?;
+        let rdata = txt_record.to_bytes();
+
+        registration.txt_record = Some(txt_record);
+
+        let params = UpdateRecordParams::builder()
+            .flags(0)
+            .rdata(&rdata)
+            .ttl(0)
+            .build()?;
+
+        unsafe {
+            registration
+                .service
+                .lock()
+                .expect("should be able to obtain lock on service")
+                .update_record(params)
+        }
+    }
+
+    fn set_name(&mut self, name: &str) -> Result<()> {
+        let registration = self.registration()?;
+        let (fitted, adjusted) = service_name::prepare(name, registration.name_fitting)?;
+
+        registration.name = Some(c_string!(fitted.as_ref()));
+        self.context.name_adjusted = adjusted;
+
+        debug!("[{}] Re-registering service", self.context.id);
+
+        unsafe { register_service(self.context) }
+    }
+
+    fn add_record(&mut self, record: ServiceRecord) -> Result<()> {
+        validate_record(&record)?;
+
+        // named records are registered on a connection that only exists if the service was
+        // registered with some
+        if record.name().is_some() {
+            return Err(Error::Unsupported(
+                "Bonjour cannot add records with a name of their own to a registered service"
+                    .to_string(),
+            ));
+        }
+
+        debug!("[{}] Adding record: {:?}", self.context.id, record);
+
+        let registration = self.registration()?;
+
+        let params = AddRecordParams::builder()
+            .flags(0)
+            .rr_type(*record.rr_type())
+            .rdata(record.rdata())
+            .ttl(*record.ttl())
             .build()?;
 
-        unsafe { service_lock.register_service(register_params)? };
+        unsafe {
+            registration
+                .service
+                .lock()
+                .expect("should be able to obtain lock on service")
+                .add_record(params)?
+        };
+
+        // kept so that the record is added again if the service is re-registered
+        registration.records.push(record);
+
+        Ok(())
+    }
+
+    fn add_sub_type(&mut self, sub_type: &str) -> Result<()> {
+        // the subtypes are part of the regtype the service is registered with
+        if self.registration()?.service_type.add_sub_type(sub_type)? {
+            debug!("[{}] Re-registering service", self.context.id);
+            unsafe { register_service(self.context) }?;
+        }
+
+        Ok(())
+    }
+
+    fn remove_sub_type(&mut self, sub_type: &str) -> Result<()> {
+        if self
+            .registration()?
+            .service_type
+            .remove_sub_type(sub_type)?
+        {
+            debug!("[{}] Re-registering service", self.context.id);
+            unsafe { register_service(self.context) }?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns an error if `record` cannot be published by Bonjour.
+fn validate_record(record: &ServiceRecord) -> Result<()> {
+    record.validate()?;
+
+    if record.name().is_none() && *record.sharing() == RecordSharing::Shared {
+        return Err(Error::Unsupported(
+            "Bonjour cannot share records at the service's own name".to_string(),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Executes the closures queued with a [`Deferrer`] on behalf of `BonjourEventLoop::poll()`.
+///
+/// [`Deferrer`]: ../../struct.Deferrer.html
+pub(crate) struct DeferredRunner {
+    context: *mut BonjourServiceContext,
+    queue: Weak<DeferredQueue>,
+}
+
+// SAFETY: the context is only dereferenced by `run()`, which `BonjourEventLoop::poll()` calls
+// while no result is being processed, and only while the queue shows the service is still alive.
+// The context is not locked, so the service must not be used from another thread while it is
+// polled; closures from other threads reach it through the `Mutex` of the queue instead.
+unsafe impl Send for DeferredRunner {}
+
+impl DeferredRunner {
+    fn new(context: &mut BonjourServiceContext) -> Self {
+        Self {
+            queue: Arc::downgrade(&context.deferred),
+            context,
+        }
+    }
+
+    /// Executes the queued closures. Does nothing if the service has been dropped.
+    ///
+    /// # Safety
+    /// This function is unsafe because of the raw pointer dereference. It must not be called
+    /// while the service ref is processing a result.
+    pub(crate) unsafe fn run(&self) {
+        let queue = match self.queue.upgrade() {
+            Some(queue) => queue,
+            None => return,
+        };
+
+        while let Some(f) = defer::pop(&queue) {
+            let context = &mut *self.context;
+
+            debug!("[{}] Running deferred closure", context.id);
+
+            if let Err(e) = f(&mut BonjourDeferredService {
+                context: &mut *context,
+            }) {
+                context.invoke_callback(Err(e));
+            }
+        }
+    }
+}
+
+/// Parameters of the current registration, kept to register the service again from a
+/// [`BonjourDeferredService`].
+///
+/// [`BonjourDeferredService`]: struct.BonjourDeferredService.html
+#[derive(Debug)]
+struct BonjourRegistration {
+    service: Arc<Mutex<ManagedDNSServiceRef>>,
+    flags: DNSServiceFlags,
+    interface_index: u32,
+    name: Option<CString>,
+    name_fitting: bool,
+    service_type: ServiceType,
+    domain: Option<CString>,
+    host: Option<CString>,
+    port: u16,
+    txt_record: Option<TxtRecord>,
+    records: Vec<ServiceRecord>,
+}
+
+/// Registers the service with the parameters of `context.registration`, replacing any previous
+/// registration, and adds the records without a name of their own.
+unsafe fn register_service(context: &mut BonjourServiceContext) -> Result<()> {
+    let raw_context = context.as_raw();
+
+    let registration = context
+        .registration
+        .as_ref()
+        .ok_or("service has not been registered")?;
+
+    let mut service_lock = registration
+        .service
+        .lock()
+        .expect("should be able to obtain lock on service");
+
+    // replacing the service ref deallocates any previous registration
+    *service_lock = ManagedDNSServiceRef::default();
+
+    let regtype = bonjour_util::format_regtype(&registration.service_type);
+
+    let register_params = RegisterServiceParams::builder()
+        .flags(registration.flags)
+        .interface_index(registration.interface_index)
+        .name(registration.name.as_ref().as_c_chars().unwrap_or_null())
+        .regtype(regtype.as_ptr())
+        .domain(registration.domain.as_ref().as_c_chars().unwrap_or_null())
+        .host(registration.host.as_ref().as_c_chars().unwrap_or_null())
+        .port(registration.port)
+        .txt_len(
+            registration
+                .txt_record
+                .as_ref()
+                .map(|t| t.inner().get_length())
+                .unwrap_or(0),
+        )
+        .txt_record(
+            registration
+                .txt_record
+                .as_ref()
+                .map(|t| t.inner().get_bytes_ptr())
+                .unwrap_or_null(),
+        )
+        .callback(Some(register_callback))
+        .context(raw_context)
+        .build()?;
+
+    service_lock.register_service(register_params)?;
+
+    for record in &registration.records {
+        debug!("[{}] Adding record: {:?}", context.id, record);
+
+        let params = AddRecordParams::builder()
+            .flags(0)
+            .rr_type(*record.rr_type())
+            .rdata(record.rdata())
+            .ttl(*record.ttl())
+            .build()?;
 
-        Ok(EventLoop::new(self.service.clone()))
+        service_lock.add_record(params)?;
     }
+
+    // mDNSResponder publishes registrations on `kDNSServiceInterfaceIndexAny` on every interface
+    // as it comes up, so the registration is never pending
+    context.invoke_state_callback(RegistrationState::Registering);
+
+    Ok(())
 }
 
-#[derive(Default, FromRaw, AsRaw)]
+#[derive(FromRaw, AsRaw)]
 struct BonjourServiceContext {
-    registered_callback: Option<Box<ServiceRegisteredCallback>>,
-    user_context: Option<Arc<dyn Any>>,
+    id: Id,
+    registered_callback: Option<Mutex<Box<ServiceRegisteredCallback>>>,
+    registration_state_callback: Option<Box<RegistrationStateCallback>>,
+    name_changed_callback: Option<Box<NameChangedCallback>>,
+    user_context: Option<Arc<dyn Any + Send + Sync>>,
+    name_adjusted: bool,
+    /// The name the service was last registered under, as Bonjour reports later renames to the
+    /// register callback with nothing but the new name
+    registered_name: Option<String>,
+    registration: Option<BonjourRegistration>,
+    state: Mutex<ServiceState>,
+    deferred: Arc<DeferredQueue>,
+    span: Span,
 }
 
 // Necessary for BonjourMdnsService, cant be `derive`d because of registered_callback
 impl std::fmt::Debug for BonjourServiceContext {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.debug_struct("BonjourServiceContext")
+            .field("id", &self.id)
             .field("user_context", &self.user_context)
             .finish()
     }
 }
 
 impl BonjourServiceContext {
+    fn new() -> Self {
+        Self {
+            id: Id::next(),
+            registered_callback: None,
+            registration_state_callback: None,
+            name_changed_callback: None,
+            user_context: None,
+            name_adjusted: false,
+            registered_name: None,
+            registration: None,
+            state: Mutex::new(ServiceState::Unregistered),
+            deferred: Arc::default(),
+            span: Span::none(),
+        }
+    }
+
     fn invoke_callback(&self, result: Result<ServiceRegistration>) {
         if let Some(f) = &self.registered_callback {
+            let mut f = f.lock().unwrap_or_else(PoisonError::into_inner);
             f(result, self.user_context.clone());
         } else {
-            warn!("attempted to invoke callback but none was set");
+            warn!(
+                "[{}] attempted to invoke callback but none was set",
+                self.id
+            );
+        }
+    }
+
+    /// Passes the outcome of the registration to the registered callback and updates the
+    /// `ServiceState` accordingly. A registration under a new name is also passed to the name
+    /// changed callback.
+    fn report_registration(&mut self, result: Result<ServiceRegistration>) {
+        let result = result.with_context(|| self.error_context());
+
+        self.set_state(ServiceState::after(&result));
+
+        let change = result
+            .as_ref()
+            .ok()
+            .and_then(|registration| NameChange::track(&mut self.registered_name, registration));
+
+        self.invoke_callback(result);
+
+        if let Some(change) = change {
+            self.invoke_name_changed_callback(change);
+        }
+    }
+
+    fn error_context(&self) -> ErrorContext {
+        let context = ErrorContext::new(Operation::Register);
+
+        match &self.registration {
+            Some(registration) => context
+                .with_service_type(&registration.service_type.to_string())
+                .with_name(registration.name.as_ref().map(c_str::to_str)),
+            None => context,
+        }
+    }
+
+    fn invoke_name_changed_callback(&self, change: NameChange) {
+        debug!(
+            "[{}] Service renamed from {} to {}",
+            self.id,
+            change.previous(),
+            change.name()
+        );
+
+        if let Some(f) = &self.name_changed_callback {
+            f(change, self.user_context.clone());
+        }
+    }
+
+    fn set_state(&self, state: ServiceState) {
+        *self.state.lock().unwrap_or_else(PoisonError::into_inner) = state;
+    }
+
+    fn invoke_state_callback(&self, state: RegistrationState) {
+        debug!("[{}] Registration state: {:?}", self.id, state);
+
+        self.set_state(ServiceState::Registering);
+
+        if let Some(f) = &self.registration_state_callback {
+            f(state, self.user_context.clone());
         }
     }
 }
 
+impl CallbackContext for BonjourServiceContext {
+    fn id(&self) -> Id {
+        self.id
+    }
+
+    fn invoke_error(&self, error: Error) {
+        self.invoke_callback(Err(error))
+    }
+
+    fn span(&self) -> Option<&Span> {
+        Some(&self.span)
+    }
+}
+
 unsafe extern "system" fn register_callback(
     _sd_ref: DNSServiceRef,
     _flags: DNSServiceFlags,
@@ -171,10 +832,28 @@ unsafe extern "system" fn register_callback(
     domain: *const c_char,
     context: *mut c_void,
 ) {
-    let context = BonjourServiceContext::from_raw(context);
-    if let Err(e) = handle_register(context, error, domain, name, regtype) {
-        context.invoke_callback(Err(e));
-    }
+    ffi::guard(context, |context: &mut BonjourServiceContext| {
+        context.report_registration(handle_register(context, error, domain, name, regtype));
+    })
+}
+
+unsafe extern "system" fn register_record_callback(
+    _sd_ref: DNSServiceRef,
+    _record_ref: DNSRecordRef,
+    _flags: DNSServiceFlags,
+    error: DNSServiceErrorType,
+    context: *mut c_void,
+) {
+    ffi::guard(context, |context: &mut BonjourServiceContext| {
+        if error != 0 {
+            let error = Error::from(format!(
+                "register_record_callback() reported error (code: {0})",
+                error
+            ));
+
+            context.invoke_callback(Err(error.with_context(context.error_context())));
+        }
+    })
 }
 
 unsafe fn handle_register(
@@ -183,22 +862,38 @@ unsafe fn handle_register(
     domain: *const c_char,
     name: *const c_char,
     regtype: *const c_char,
-) -> Result<()> {
+) -> Result<ServiceRegistration> {
+    if error == bonjour_sys::kDNSServiceErr_NameConflict {
+        return Err(Error::NameCollision {
+            name: c_str::try_raw_to_str(name, "service name")?.to_string(),
+        });
+    }
+
     if error != 0 {
         return Err(format!("register_callback() reported error (code: {0})", error).into());
     }
 
-    let domain = bonjour_util::normalize_domain(c_str::raw_to_str(domain));
-    let kind = bonjour_util::normalize_domain(c_str::raw_to_str(regtype));
+    let domain = bonjour_util::normalize_domain(c_str::try_raw_to_str(domain, "domain")?);
+    let kind = bonjour_util::normalize_domain(c_str::try_raw_to_str(regtype, "service type")?);
+
+    let registration = context
+        .registration
+        .as_ref()
+        .ok_or("service has not been registered")?;
 
-    let result = ServiceRegistration::builder()
-        .name(c_str::copy_raw(name))
+    Ok(ServiceRegistration::builder()
+        .name(c_str::try_raw_to_str(name, "service name")?.to_string())
         .service_type(bonjour_util::parse_regtype(&kind)?)
         .domain(domain)
+        .name_was_adjusted(context.name_adjusted)
+        .port(registration.port)
+        .host(
+            registration
+                .host
+                .as_ref()
+                .map(|h| c_str::to_str(h).to_string()),
+        )
+        .txt(registration.txt_record.clone())
         .build()
-        .expect("could not build ServiceRegistration");
-
-    context.invoke_callback(Ok(result));
-
-    Ok(())
+        .expect("could not build ServiceRegistration"))
 }
@@ -0,0 +1,405 @@
+//! Bonjour implementation for cross-platform service group.
+//!
+//! Every instance is registered with a `DNSServiceRef` of its own, all of them sharing a single
+//! connection to the daemon which is polled by the event loop of the group.
+
+use super::bonjour_util;
+use super::service_ref::{RegisterServiceParams, SharedConnection, SharedServiceRef};
+use crate::error::{Error, ErrorContext, Operation, ResultExt};
+use crate::ffi::c_str;
+use crate::ffi::{self, AsRaw, CallbackContext, DropAfterDispatch, UnwrapOrNull};
+use crate::prelude::*;
+use crate::service_group::{self, Instance, InstanceChange, InstanceChanges};
+use crate::span::Span;
+use crate::{
+    EventLoop, Id, InstanceHandle, InstanceRegisteredCallback, NetworkInterface, Result,
+    ServiceRegistration, ServiceType, TxtRecord,
+};
+use bonjour_sys::{DNSServiceErrorType, DNSServiceFlags, DNSServiceRef};
+use libc::{c_char, c_void};
+use std::any::Any;
+use std::ffi::CString;
+use std::ptr;
+use std::sync::{Arc, Mutex, PoisonError, Weak};
+
+#[derive(Debug)]
+pub struct BonjourMdnsServiceGroup {
+    context: DropAfterDispatch<Box<BonjourServiceGroupContext>>,
+}
+
+impl TMdnsServiceGroup for BonjourMdnsServiceGroup {
+    fn new(service_type: ServiceType) -> Self {
+        Self {
+            context: DropAfterDispatch::new(Box::new(BonjourServiceGroupContext::new(
+                service_type,
+            ))),
+        }
+    }
+
+    fn id(&self) -> Id {
+        self.context.id
+    }
+
+    fn service_type(&self) -> &ServiceType {
+        &self.context.service_type
+    }
+
+    fn set_network_interface(&mut self, interface: NetworkInterface) {
+        self.context.interface = interface;
+    }
+
+    fn network_interface(&self) -> NetworkInterface {
+        self.context.interface
+    }
+
+    fn add_instance(
+        &mut self,
+        name: &str,
+        port: u16,
+        txt_record: Option<TxtRecord>,
+    ) -> Result<InstanceHandle> {
+        let instance = Instance::new(name, port, txt_record)?;
+        let handle = instance.handle;
+
+        self.context.changes.push(InstanceChange::Add(instance));
+
+        Ok(handle)
+    }
+
+    fn remove_instance(&mut self, handle: InstanceHandle) -> Result<()> {
+        if !self.instances().contains(&handle) {
+            return Err(format!("instance {} is not in this group", handle).into());
+        }
+
+        self.context.changes.push(InstanceChange::Remove(handle));
+
+        Ok(())
+    }
+
+    fn instances(&self) -> Vec<InstanceHandle> {
+        service_group::instances_after(
+            self.context.instances.iter().map(|i| i.instance.handle),
+            &self.context.changes,
+        )
+    }
+
+    fn set_registered_callback(&mut self, registered_callback: Box<InstanceRegisteredCallback>) {
+        self.context.registered_callback = Some(Mutex::new(registered_callback))
+    }
+
+    fn set_context(&mut self, context: Box<dyn Any + Send + Sync>) {
+        self.context.user_context = Some(Arc::from(context))
+    }
+
+    fn context(&self) -> Option<&(dyn Any + Send + Sync)> {
+        self.context.user_context.as_ref().map(|c| c.as_ref())
+    }
+
+    fn register(&mut self) -> Result<EventLoop> {
+        let span = span!(
+            "register",
+            id = %self.context.id,
+            service_type = c_str::to_str(&self.context.kind)
+        );
+        let _entered = span.enter();
+        self.context.span = span.clone();
+
+        debug!(
+            "[{}] Registering service group: {:?}",
+            self.context.id, self
+        );
+
+        let connection = unsafe { SharedConnection::new() }?;
+
+        // the previous registrations are terminated before their connection
+        for instance in &mut self.context.instances {
+            instance.service = None;
+        }
+
+        self.context.connection = Some(connection.clone());
+
+        let handles = self
+            .context
+            .instances
+            .iter()
+            .map(|i| i.instance.handle)
+            .collect::<Vec<_>>();
+
+        for handle in handles {
+            unsafe { register_instance(&mut self.context, handle) }
+                .with_context(|| self.context.error_context(handle))?;
+        }
+
+        unsafe { apply_changes(&mut self.context) };
+
+        let event_loop = EventLoop::new(connection.service());
+
+        debug!("[{}] Event loop: {}", self.context.id, event_loop.id());
+
+        Ok(event_loop.with_instance_changes(InstanceChangeRunner::new(&mut self.context)))
+    }
+}
+
+/// Applies the instances added to and removed from a group on behalf of
+/// `BonjourEventLoop::poll()`.
+pub(crate) struct InstanceChangeRunner {
+    context: *mut BonjourServiceGroupContext,
+    changes: Weak<InstanceChanges>,
+}
+
+// SAFETY: the group's context is only dereferenced by `run()` from `BonjourEventLoop::poll()`
+// while no result is being processed, and only while the group still holds the changes. Other
+// threads only touch the `Mutex` guarding the changes, never the context itself.
+unsafe impl Send for InstanceChangeRunner {}
+
+impl InstanceChangeRunner {
+    fn new(context: &mut BonjourServiceGroupContext) -> Self {
+        Self {
+            changes: Arc::downgrade(&context.changes),
+            context,
+        }
+    }
+
+    /// Applies the changes made since the last run. Does nothing if the group has been dropped.
+    ///
+    /// # Safety
+    /// This function is unsafe because of the raw pointer dereference. It must not be called
+    /// while the connection is processing a result.
+    pub(crate) unsafe fn run(&self) {
+        if self.changes.upgrade().is_some() {
+            apply_changes(&mut *self.context);
+        }
+    }
+}
+
+/// An instance of the group, along with its registration once it has been started
+#[derive(Debug)]
+struct BonjourInstance {
+    instance: Instance,
+    name: CString,
+    service: Option<SharedServiceRef>,
+}
+
+#[derive(FromRaw, AsRaw)]
+struct BonjourServiceGroupContext {
+    id: Id,
+    service_type: ServiceType,
+    kind: CString,
+    interface: NetworkInterface,
+    connection: Option<SharedConnection>,
+    instances: Vec<BonjourInstance>,
+    changes: Arc<InstanceChanges>,
+    registered_callback: Option<Mutex<Box<InstanceRegisteredCallback>>>,
+    user_context: Option<Arc<dyn Any + Send + Sync>>,
+    span: Span,
+}
+
+// Necessary for BonjourMdnsServiceGroup, cant be `derive`d because of registered_callback
+impl std::fmt::Debug for BonjourServiceGroupContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BonjourServiceGroupContext")
+            .field("id", &self.id)
+            .field("kind", &self.kind)
+            .field("instances", &self.instances)
+            .finish()
+    }
+}
+
+impl BonjourServiceGroupContext {
+    fn new(service_type: ServiceType) -> Self {
+        Self {
+            id: Id::next(),
+            kind: bonjour_util::format_regtype(&service_type),
+            service_type,
+            interface: NetworkInterface::Unspec,
+            connection: None,
+            instances: vec![],
+            changes: Arc::default(),
+            registered_callback: None,
+            user_context: None,
+            span: Span::none(),
+        }
+    }
+
+    fn invoke_callback(&self, handle: InstanceHandle, result: Result<ServiceRegistration>) {
+        let result = result.with_context(|| self.error_context(handle));
+
+        if let Some(f) = &self.registered_callback {
+            let mut f = f.lock().unwrap_or_else(PoisonError::into_inner);
+            f(handle, result, self.user_context.clone());
+        } else {
+            warn!(
+                "[{}] attempted to invoke instance callback but none was set",
+                self.id
+            );
+        }
+    }
+
+    fn error_context(&self, handle: InstanceHandle) -> ErrorContext {
+        let instance = self.instances.iter().find(|i| i.instance.handle == handle);
+
+        ErrorContext::new(Operation::Register)
+            .with_service_type(&self.service_type.to_string())
+            .with_name(instance.map(|i| c_str::to_str(&i.name)))
+    }
+}
+
+impl CallbackContext for BonjourServiceGroupContext {
+    fn id(&self) -> Id {
+        self.id
+    }
+
+    fn invoke_error(&self, error: Error) {
+        for instance in &self.instances {
+            self.invoke_callback(instance.instance.handle, Err(error.clone()));
+        }
+    }
+
+    fn span(&self) -> Option<&Span> {
+        Some(&self.span)
+    }
+}
+
+/// Adds and removes the instances as requested with `add_instance()` and `remove_instance()`.
+/// Added instances are registered right away if the group has been registered.
+unsafe fn apply_changes(context: &mut BonjourServiceGroupContext) {
+    for change in context.changes.take() {
+        match change {
+            InstanceChange::Add(instance) => {
+                let handle = instance.handle;
+
+                context.instances.push(BonjourInstance {
+                    name: c_string!(instance.name.as_str()),
+                    instance,
+                    service: None,
+                });
+
+                if context.connection.is_some() {
+                    if let Err(e) = register_instance(context, handle) {
+                        context.invoke_callback(handle, Err(e));
+                    }
+                }
+            }
+            InstanceChange::Remove(handle) => {
+                debug!("[{}] Removing instance {}", context.id, handle);
+
+                // dropping the service ref deregisters the instance, and only that instance
+                context.instances.retain(|i| i.instance.handle != handle);
+            }
+        }
+    }
+}
+
+/// Registers the instance of `handle` on the connection of the group.
+unsafe fn register_instance(
+    context: &mut BonjourServiceGroupContext,
+    handle: InstanceHandle,
+) -> Result<()> {
+    let raw_context = context.as_raw();
+    let interface_index = bonjour_util::interface_index(context.interface);
+
+    let connection = context
+        .connection
+        .clone()
+        .ok_or("service group has not been registered")?;
+
+    let BonjourServiceGroupContext {
+        id,
+        kind,
+        instances,
+        ..
+    } = context;
+
+    let instance = instances
+        .iter_mut()
+        .find(|i| i.instance.handle == handle)
+        .ok_or_else(|| format!("instance {} is not in this group", handle))?;
+
+    debug!(
+        "[{}] Adding instance {}: {}",
+        id,
+        handle,
+        instance.name.to_string_lossy()
+    );
+
+    let txt_record = instance.instance.txt_record.as_ref();
+
+    let params = RegisterServiceParams::builder()
+        .flags(bonjour_sys::kDNSServiceFlagsShareConnection)
+        .interface_index(interface_index)
+        .name(instance.name.as_ptr())
+        .regtype(kind.as_ptr())
+        .domain(ptr::null())
+        .host(ptr::null())
+        .port(instance.instance.port)
+        .txt_len(txt_record.map(|t| t.inner().get_length()).unwrap_or(0))
+        .txt_record(
+            txt_record
+                .map(|t| t.inner().get_bytes_ptr())
+                .unwrap_or_null(),
+        )
+        .callback(Some(register_callback))
+        .context(raw_context)
+        .build()?;
+
+    // the previous registration, if any, is terminated first
+    instance.service = None;
+    instance.service = Some(connection.start(|service| service.register_service(params))?);
+
+    Ok(())
+}
+
+unsafe extern "system" fn register_callback(
+    sd_ref: DNSServiceRef,
+    _flags: DNSServiceFlags,
+    error: DNSServiceErrorType,
+    name: *const c_char,
+    regtype: *const c_char,
+    domain: *const c_char,
+    context: *mut c_void,
+) {
+    ffi::guard(context, |context: &mut BonjourServiceGroupContext| {
+        let instance = context.instances.iter().find(|i| {
+            i.service
+                .as_ref()
+                .is_some_and(|service| service.as_raw() == sd_ref)
+        });
+
+        // the instance has been removed in the meantime
+        if let Some(instance) = instance {
+            let handle = instance.instance.handle;
+            let result = handle_register(instance, error, domain, name, regtype);
+
+            context.invoke_callback(handle, result);
+        }
+    })
+}
+
+unsafe fn handle_register(
+    instance: &BonjourInstance,
+    error: DNSServiceErrorType,
+    domain: *const c_char,
+    name: *const c_char,
+    regtype: *const c_char,
+) -> Result<ServiceRegistration> {
+    if error == bonjour_sys::kDNSServiceErr_NameConflict {
+        return Err(Error::NameCollision {
+            name: c_str::try_raw_to_str(name, "service name")?.to_string(),
+        });
+    }
+
+    if error != 0 {
+        return Err(format!("register_callback() reported error (code: {0})", error).into());
+    }
+
+    let domain = bonjour_util::normalize_domain(c_str::try_raw_to_str(domain, "domain")?);
+    let kind = bonjour_util::normalize_domain(c_str::try_raw_to_str(regtype, "service type")?);
+
+    Ok(ServiceRegistration::builder()
+        .name(c_str::try_raw_to_str(name, "service name")?.to_string())
+        .service_type(bonjour_util::parse_regtype(&kind)?)
+        .domain(domain)
+        .port(instance.instance.port)
+        .txt(instance.instance.txt_record.clone())
+        .build()?)
+}
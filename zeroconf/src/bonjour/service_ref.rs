@@ -2,13 +2,18 @@
 
 use crate::{bonjour::bonjour_util, Result};
 use bonjour_sys::{
-    dnssd_sock_t, DNSServiceBrowse, DNSServiceBrowseReply, DNSServiceFlags, DNSServiceGetAddrInfo,
-    DNSServiceGetAddrInfoReply, DNSServiceProcessResult, DNSServiceProtocol, DNSServiceRef,
-    DNSServiceRefDeallocate, DNSServiceRefSockFD, DNSServiceRegister, DNSServiceRegisterReply,
-    DNSServiceResolve, DNSServiceResolveReply,
+    dnssd_sock_t, DNSRecordRef, DNSServiceAddRecord, DNSServiceBrowse, DNSServiceBrowseReply,
+    DNSServiceCreateConnection, DNSServiceDomainEnumReply, DNSServiceEnumerateDomains,
+    DNSServiceFlags, DNSServiceGetAddrInfo, DNSServiceGetAddrInfoReply, DNSServiceGetProperty,
+    DNSServiceProcessResult, DNSServiceProtocol, DNSServiceQueryRecord, DNSServiceQueryRecordReply,
+    DNSServiceRef, DNSServiceRefDeallocate, DNSServiceRefSockFD, DNSServiceRegister,
+    DNSServiceRegisterRecord, DNSServiceRegisterRecordReply, DNSServiceRegisterReply,
+    DNSServiceResolve, DNSServiceResolveReply, DNSServiceUpdateRecord,
 };
 use libc::{c_char, c_void};
+use std::convert::TryFrom;
 use std::ptr;
+use std::sync::{Arc, Mutex};
 
 /// Wraps the `DNSServiceRef` type from the raw Bonjour bindings.
 ///
@@ -71,6 +76,125 @@ impl ManagedDNSServiceRef {
         )
     }
 
+    /// Delegate function for `DNSServiceAddRecord`.
+    ///
+    /// Adds a record to a service previously registered with `register_service()`. The record is
+    /// removed when the service is deallocated.
+    ///
+    /// # Safety
+    /// This function is unsafe because it calls a C function.
+    pub unsafe fn add_record(
+        &mut self,
+        AddRecordParams {
+            flags,
+            rr_type,
+            rdata,
+            ttl,
+        }: AddRecordParams,
+    ) -> Result<()> {
+        let rdlen = u16::try_from(rdata.len()).map_err(|_| "record data is too long")?;
+        let mut record_ref: DNSRecordRef = ptr::null_mut();
+
+        bonjour_util::sys_exec(
+            || {
+                DNSServiceAddRecord(
+                    self.0,
+                    &mut record_ref as *mut DNSRecordRef,
+                    flags,
+                    rr_type,
+                    rdlen,
+                    rdata.as_ptr() as *const c_void,
+                    ttl,
+                )
+            },
+            "could not add record",
+        )
+    }
+
+    /// Delegate function for `DNSServiceUpdateRecord`.
+    ///
+    /// Updates the primary TXT record of a service previously registered with
+    /// `register_service()`.
+    ///
+    /// # Safety
+    /// This function is unsafe because it calls a C function.
+    pub unsafe fn update_record(
+        &mut self,
+        UpdateRecordParams { flags, rdata, ttl }: UpdateRecordParams,
+    ) -> Result<()> {
+        let rdlen = u16::try_from(rdata.len()).map_err(|_| "record data is too long")?;
+
+        bonjour_util::sys_exec(
+            || {
+                DNSServiceUpdateRecord(
+                    self.0,
+                    ptr::null_mut(),
+                    flags,
+                    rdlen,
+                    rdata.as_ptr() as *const c_void,
+                    ttl,
+                )
+            },
+            "could not update record",
+        )
+    }
+
+    /// Delegate function for `DNSServiceCreateConnection`.
+    ///
+    /// # Safety
+    /// This function is unsafe because it calls a C function.
+    pub unsafe fn create_connection(&mut self) -> Result<()> {
+        bonjour_util::sys_exec(
+            || DNSServiceCreateConnection(&mut self.0 as *mut DNSServiceRef),
+            "could not create connection",
+        )
+    }
+
+    /// Delegate function for `DNSServiceRegisterRecord`.
+    ///
+    /// Registers an individual record on a connection previously created with
+    /// `create_connection()`. The record is removed when the connection is deallocated.
+    ///
+    /// # Safety
+    /// This function is unsafe because it calls a C function.
+    pub unsafe fn register_record(
+        &mut self,
+        RegisterRecordParams {
+            flags,
+            interface_index,
+            fullname,
+            rr_type,
+            rr_class,
+            rdata,
+            ttl,
+            callback,
+            context,
+        }: RegisterRecordParams,
+    ) -> Result<()> {
+        let rdlen = u16::try_from(rdata.len()).map_err(|_| "record data is too long")?;
+        let mut record_ref: DNSRecordRef = ptr::null_mut();
+
+        bonjour_util::sys_exec(
+            || {
+                DNSServiceRegisterRecord(
+                    self.0,
+                    &mut record_ref as *mut DNSRecordRef,
+                    flags,
+                    interface_index,
+                    fullname,
+                    rr_type,
+                    rr_class,
+                    rdlen,
+                    rdata.as_ptr() as *const c_void,
+                    ttl,
+                    callback,
+                    context,
+                )
+            },
+            "could not register record",
+        )
+    }
+
     /// Delegate function for [`DNSServiceBrowse`].
     ///
     /// [`DNSServiceBrowse`]: https://developer.apple.com/documentation/dnssd/1804742-dnsservicebrowse?language=objc
@@ -104,13 +228,74 @@ impl ManagedDNSServiceRef {
         )
     }
 
-    /// Delegate function fro [`DNSServiceResolve`].
+    /// Delegate function for `DNSServiceEnumerateDomains`.
+    ///
+    /// # Safety
+    /// This function is unsafe because it calls a C function.
+    pub unsafe fn enumerate_domains(
+        &mut self,
+        EnumerateDomainsParams {
+            flags,
+            interface_index,
+            callback,
+            context,
+        }: EnumerateDomainsParams,
+    ) -> Result<()> {
+        bonjour_util::sys_exec(
+            || {
+                DNSServiceEnumerateDomains(
+                    &mut self.0 as *mut DNSServiceRef,
+                    flags,
+                    interface_index,
+                    callback,
+                    context,
+                )
+            },
+            "could not enumerate domains",
+        )
+    }
+
+    /// Delegate function for `DNSServiceQueryRecord`.
+    ///
+    /// # Safety
+    /// This function is unsafe because it calls a C function.
+    pub unsafe fn query_record(
+        &mut self,
+        QueryRecordParams {
+            flags,
+            interface_index,
+            fullname,
+            rr_type,
+            rr_class,
+            callback,
+            context,
+        }: QueryRecordParams,
+    ) -> Result<()> {
+        bonjour_util::sys_exec(
+            || {
+                DNSServiceQueryRecord(
+                    &mut self.0 as *mut DNSServiceRef,
+                    flags,
+                    interface_index,
+                    fullname,
+                    rr_type,
+                    rr_class,
+                    callback,
+                    context,
+                )
+            },
+            "could not query record",
+        )
+    }
+
+    /// Delegate function for [`DNSServiceResolve`]. Results are delivered through
+    /// `process_result()`.
     ///
     /// [`DNSServiceResolve`]: https://developer.apple.com/documentation/dnssd/1804744-dnsserviceresolve?language=objc
     ///
     /// # Safety
     /// This function is unsafe because it calls a C function.
-    pub unsafe fn resolve_service(
+    pub unsafe fn start_resolve_service(
         &mut self,
         ServiceResolveParams {
             flags,
@@ -136,18 +321,17 @@ impl ManagedDNSServiceRef {
                 )
             },
             "DNSServiceResolve() reported error",
-        )?;
-
-        self.process_result()
+        )
     }
 
-    /// Delegate function for [`DNSServiceGetAddrInfo`].
+    /// Delegate function for [`DNSServiceGetAddrInfo`]. Results are delivered through
+    /// `process_result()`.
     ///
     /// [`DNSServiceGetAddrInfo`]: https://developer.apple.com/documentation/dnssd/1804700-dnsservicegetaddrinfo?language=objc
     ///
     /// # Safety
     /// This function is unsafe because it calls a C function.
-    pub unsafe fn get_address_info(
+    pub unsafe fn start_get_address_info(
         &mut self,
         GetAddressInfoParams {
             flags,
@@ -171,9 +355,7 @@ impl ManagedDNSServiceRef {
                 )
             },
             "DNSServiceGetAddrInfo() reported error",
-        )?;
-
-        self.process_result()
+        )
     }
 
     /// Delegate function for [`DNSServiceProcessResult`].
@@ -216,8 +398,120 @@ impl Drop for ManagedDNSServiceRef {
     }
 }
 
+// SAFETY: a `DNSServiceRef` is not tied to the thread that created it, it just must not be used
+// from several threads at once. The delegate functions take `&mut self`, and the type is not
+// `Sync`, so a ref that is shared, like the one of a `SharedConnection`, sits behind a `Mutex`.
 unsafe impl Send for ManagedDNSServiceRef {}
 
+/// Delegate function for [`DNSServiceGetProperty`] with `kDNSServiceProperty_DaemonVersion`.
+/// Returns the version of the running daemon, encoded like `_DNS_SD_H`.
+///
+/// [`DNSServiceGetProperty`]: https://developer.apple.com/documentation/dnssd/1804720-dnsservicegetproperty?language=objc
+///
+/// # Safety
+/// This function is unsafe because it calls a C function.
+pub unsafe fn get_daemon_version() -> Result<u32> {
+    let mut version: u32 = 0;
+    let mut size = std::mem::size_of::<u32>() as u32;
+
+    bonjour_util::sys_exec(
+        || {
+            DNSServiceGetProperty(
+                bonjour_sys::kDNSServiceProperty_DaemonVersion.as_ptr() as *const c_char,
+                &mut version as *mut u32 as *mut c_void,
+                &mut size,
+            )
+        },
+        "could not get daemon version",
+    )?;
+
+    Ok(version)
+}
+
+/// A connection created with `DNSServiceCreateConnection` that several operations share, so that
+/// their results are all processed by polling the connection.
+///
+/// Deallocating the connection terminates every operation started on it, so the operations keep
+/// the connection alive until they have been dropped.
+#[derive(Debug, Clone)]
+pub struct SharedConnection {
+    connection: Arc<Mutex<ManagedDNSServiceRef>>,
+    raw: DNSServiceRef,
+}
+
+impl SharedConnection {
+    /// Creates a new connection to the daemon.
+    ///
+    /// # Safety
+    /// This function is unsafe because it calls a C function.
+    pub unsafe fn new() -> Result<Self> {
+        let mut connection = ManagedDNSServiceRef::default();
+        connection.create_connection()?;
+
+        Ok(Self {
+            raw: connection.0,
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+
+    /// Returns the service ref of the connection, which processes the results of every operation
+    /// started on it.
+    pub fn service(&self) -> Arc<Mutex<ManagedDNSServiceRef>> {
+        self.connection.clone()
+    }
+
+    /// Starts an operation on the connection. `start` is passed a copy of the connection's ref,
+    /// which it must pass to one of the delegate functions along with
+    /// `kDNSServiceFlagsShareConnection`. The operation is terminated when the returned ref is
+    /// dropped.
+    ///
+    /// The connection is not locked, so that operations can be started from within the callbacks
+    /// invoked while it is polled.
+    ///
+    /// # Safety
+    /// This function is unsafe because it calls C functions.
+    pub unsafe fn start(
+        &self,
+        start: impl FnOnce(&mut ManagedDNSServiceRef) -> Result<()>,
+    ) -> Result<SharedServiceRef> {
+        let mut service = ManagedDNSServiceRef(self.raw);
+
+        if let Err(e) = start(&mut service) {
+            // the copy may still refer to the connection, which must not be deallocated with it
+            service.0 = ptr::null_mut();
+            return Err(e);
+        }
+
+        Ok(SharedServiceRef {
+            service,
+            _connection: self.connection.clone(),
+        })
+    }
+}
+
+// SAFETY: the connection is polled through its `Mutex`, but `start()` uses the raw copy without
+// it, so that operations can be started from within callbacks. Operations are only started by
+// the owner of the connection and by the callbacks dispatched while polling it, so the owner must
+// not be used from another thread while its event loop is polled.
+unsafe impl Send for SharedConnection {}
+
+/// An operation started on a [`SharedConnection`], which is terminated on drop.
+///
+/// [`SharedConnection`]: struct.SharedConnection.html
+#[derive(Debug)]
+pub struct SharedServiceRef {
+    service: ManagedDNSServiceRef,
+    // dropped after the operation
+    _connection: Arc<Mutex<ManagedDNSServiceRef>>,
+}
+
+impl SharedServiceRef {
+    /// Returns the ref of the operation, as passed to its callbacks.
+    pub fn as_raw(&self) -> DNSServiceRef {
+        self.service.0
+    }
+}
+
 /// Holds parameters for `ManagedDNSServiceRef::register_service()`.
 #[derive(Builder, BuilderDelegate)]
 pub struct RegisterServiceParams {
@@ -234,6 +528,37 @@ pub struct RegisterServiceParams {
     context: *mut c_void,
 }
 
+/// Holds parameters for `ManagedDNSServiceRef::add_record()`.
+#[derive(Builder, BuilderDelegate)]
+pub struct AddRecordParams<'a> {
+    flags: DNSServiceFlags,
+    rr_type: u16,
+    rdata: &'a [u8],
+    ttl: u32,
+}
+
+/// Holds parameters for `ManagedDNSServiceRef::update_record()`.
+#[derive(Builder, BuilderDelegate)]
+pub struct UpdateRecordParams<'a> {
+    flags: DNSServiceFlags,
+    rdata: &'a [u8],
+    ttl: u32,
+}
+
+/// Holds parameters for `ManagedDNSServiceRef::register_record()`.
+#[derive(Builder, BuilderDelegate)]
+pub struct RegisterRecordParams<'a> {
+    flags: DNSServiceFlags,
+    interface_index: u32,
+    fullname: *const c_char,
+    rr_type: u16,
+    rr_class: u16,
+    rdata: &'a [u8],
+    ttl: u32,
+    callback: DNSServiceRegisterRecordReply,
+    context: *mut c_void,
+}
+
 /// Holds parameters for `ManagedDNSServiceRef::browse_services()`.
 #[derive(Builder, BuilderDelegate)]
 pub struct BrowseServicesParams {
@@ -245,7 +570,28 @@ pub struct BrowseServicesParams {
     context: *mut c_void,
 }
 
-/// Holds parameters for `ManagedDNSServiceRef::resolve_service()`.
+/// Holds parameters for `ManagedDNSServiceRef::enumerate_domains()`.
+#[derive(Builder, BuilderDelegate)]
+pub struct EnumerateDomainsParams {
+    flags: DNSServiceFlags,
+    interface_index: u32,
+    callback: DNSServiceDomainEnumReply,
+    context: *mut c_void,
+}
+
+/// Holds parameters for `ManagedDNSServiceRef::query_record()`.
+#[derive(Builder, BuilderDelegate)]
+pub struct QueryRecordParams {
+    flags: DNSServiceFlags,
+    interface_index: u32,
+    fullname: *const c_char,
+    rr_type: u16,
+    rr_class: u16,
+    callback: DNSServiceQueryRecordReply,
+    context: *mut c_void,
+}
+
+/// Holds parameters for `ManagedDNSServiceRef::start_resolve_service()`.
 #[derive(Builder, BuilderDelegate)]
 pub struct ServiceResolveParams {
     flags: DNSServiceFlags,
@@ -257,7 +603,7 @@ pub struct ServiceResolveParams {
     context: *mut c_void,
 }
 
-/// Holds parameters for `ManagedDNSServiceRef::get_address_info()`.
+/// Holds parameters for `ManagedDNSServiceRef::start_get_address_info()`.
 #[derive(Builder, BuilderDelegate)]
 pub struct GetAddressInfoParams {
     flags: DNSServiceFlags,
@@ -1,11 +1,12 @@
 //! Bonjour implementation for cross-platform TXT record.
 
 use super::txt_record_ref::ManagedTXTRecordRef;
-use crate::ffi::c_str;
-use crate::txt_record::TTxtRecord;
+use crate::error::Error;
+use crate::txt_record::{self, TTxtRecord};
 use crate::Result;
 use libc::{c_char, c_void};
-use std::ffi::CString;
+use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::{ptr, slice};
 
 /// Interface for interfacing with Bonjour's TXT record capabilities.
@@ -16,24 +17,41 @@ impl TTxtRecord for BonjourTxtRecord {
         Self(unsafe { ManagedTXTRecordRef::new() })
     }
 
-    fn insert(&mut self, key: &str, value: &str) -> Result<()> {
+    fn insert_bytes(&mut self, key: &str, value: &[u8]) -> Result<()> {
+        txt_record::validate_entry(key, value)?;
+
+        // `TXTRecordSetValue()` only replaces the first occurrence of the key
+        self.remove(key);
+
         let key = c_string!(key);
-        let value = c_string!(value);
-        let value_size = value.as_bytes().len();
 
+        // `validate_entry()` guarantees that the value fits in a `u8`
         unsafe {
             self.0.set_value(
                 key.as_ptr() as *const c_char,
-                value_size as u8,
+                value.len() as u8,
                 value.as_ptr() as *const c_void,
             )
         }
     }
 
-    fn get(&self, key: &str) -> Option<String> {
+    fn insert_key_only(&mut self, key: &str) -> Result<()> {
+        txt_record::validate_entry(key, &[])?;
+
+        self.remove(key);
+
+        let key = c_string!(key);
+
+        unsafe {
+            self.0
+                .set_value(key.as_ptr() as *const c_char, 0, ptr::null())
+        }
+    }
+
+    fn get_bytes(&self, key: &str) -> Option<Vec<u8>> {
         let mut value_len: u8 = 0;
 
-        let c_str = c_string!(key);
+        let c_str = txt_record::lookup_key(key)?;
 
         let value_raw = unsafe {
             self.0
@@ -48,20 +66,30 @@ impl TTxtRecord for BonjourTxtRecord {
     }
 
     fn remove(&mut self, key: &str) -> Option<String> {
-        let c_str = c_string!(key);
-        let prev = self.get(key)?;
+        if !self.contains_key(key) {
+            return None;
+        }
 
-        unsafe {
-            self.0
-                .remove_value(c_str.as_ptr() as *const c_char)
-                .expect("could not remove value")
-        };
+        let c_str = txt_record::lookup_key(key)?;
+        let prev = self.get(key).unwrap_or_default();
+
+        while self.contains_key(key) {
+            unsafe {
+                self.0
+                    .remove_value(c_str.as_ptr() as *const c_char)
+                    .expect("could not remove value")
+            };
+        }
 
         prev.into()
     }
 
     fn contains_key(&self, key: &str) -> bool {
-        let c_str = c_string!(key);
+        let c_str = match txt_record::lookup_key(key) {
+            Some(c_str) => c_str,
+            None => return false,
+        };
+
         unsafe { self.0.contains_key(c_str.as_ptr() as *const c_char) }
     }
 
@@ -70,7 +98,9 @@ impl TTxtRecord for BonjourTxtRecord {
     }
 
     fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (String, String)> + 'a> {
-        Box::new(Iter::new(self))
+        Box::new(
+            Iter::new(self).map(|(key, value)| (key, String::from_utf8_lossy(&value).into_owned())),
+        )
     }
 
     fn keys<'a>(&'a self) -> Box<dyn Iterator<Item = String> + 'a> {
@@ -80,6 +110,39 @@ impl TTxtRecord for BonjourTxtRecord {
     fn values<'a>(&'a self) -> Box<dyn Iterator<Item = String> + 'a> {
         Box::new(Values(Iter::new(self)))
     }
+
+    fn iter_bytes<'a>(&'a self) -> Box<dyn Iterator<Item = (String, Vec<u8>)> + 'a> {
+        Box::new(Iter::new(self))
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let len = unsafe { self.0.get_length() };
+
+        // Bonjour substitutes a single empty string when registering an empty record
+        if len == 0 {
+            return vec![0];
+        }
+
+        unsafe { slice::from_raw_parts(self.0.get_bytes_ptr() as *const u8, len as usize) }.to_vec()
+    }
+
+    fn from_bytes(data: &[u8]) -> Result<Self> {
+        let data = txt_record::sanitize_wire_format(data)?;
+
+        let len = u16::try_from(data.len()).map_err(|_| {
+            Error::InvalidTxtRecord(format!(
+                "record is {} bytes long, exceeding the maximum of {}",
+                data.len(),
+                u16::MAX
+            ))
+        })?;
+
+        if len == 0 {
+            return Ok(Self::new());
+        }
+
+        Ok(unsafe { ManagedTXTRecordRef::clone_raw(data.as_ptr(), len) }?.into())
+    }
 }
 
 impl Clone for BonjourTxtRecord {
@@ -89,6 +152,30 @@ impl Clone for BonjourTxtRecord {
 }
 
 impl BonjourTxtRecord {
+    /// Returns the value at the specified key without copying it, or `None` if no such key exists.
+    /// Keys without a value have an empty value.
+    pub(crate) fn value_ref(&self, key: &str) -> Option<&[u8]> {
+        let c_str = txt_record::lookup_key(key)?;
+
+        if !self.contains_key(key) {
+            return None;
+        }
+
+        let mut value_len: u8 = 0;
+
+        let value_raw = unsafe {
+            self.0
+                .get_value_ptr(c_str.as_ptr() as *const c_char, &mut value_len)
+        };
+
+        // the value points into the bytes of the record, which live as long as `self`
+        if value_raw.is_null() {
+            Some(&[])
+        } else {
+            Some(unsafe { slice::from_raw_parts(value_raw as *const u8, value_len as usize) })
+        }
+    }
+
     pub(super) fn inner(&self) -> &ManagedTXTRecordRef {
         &self.0
     }
@@ -102,7 +189,8 @@ impl From<ManagedTXTRecordRef> for BonjourTxtRecord {
 
 impl PartialEq for BonjourTxtRecord {
     fn eq(&self, other: &Self) -> bool {
-        self.to_map() == other.to_map()
+        self.iter_bytes().collect::<HashMap<_, _>>()
+            == other.iter_bytes().collect::<HashMap<_, _>>()
     }
 }
 
@@ -114,41 +202,19 @@ pub struct Iter<'a> {
     index: usize,
 }
 
-impl Iter<'_> {
-    const KEY_LEN: u16 = 256;
-}
-
 impl Iterator for Iter<'_> {
-    type Item = (String, String);
+    type Item = (String, Vec<u8>);
 
     fn next(&mut self) -> Option<Self::Item> {
         if self.index == self.record.len() {
             return None;
         }
 
-        let raw_key: CString = unsafe { c_string!(alloc(Iter::KEY_LEN as usize)) };
-        let mut value_len: u8 = 0;
-        let mut value: *const c_void = ptr::null_mut();
-
-        unsafe {
-            self.record
-                .0
-                .get_item_at_index(
-                    self.index as u16,
-                    Iter::KEY_LEN,
-                    raw_key.as_ptr() as *mut c_char,
-                    &mut value_len,
-                    &mut value,
-                )
-                .expect("could not get item at index");
-        }
-
-        assert_not_null!(value);
-
-        let key = String::from(c_str::to_str(&raw_key))
-            .trim_matches(char::from(0))
-            .to_string();
+        // the record is validated when it is created, so this only fails if it is corrupt
+        let (key, value, value_len) =
+            unsafe { self.record.0.read_item_at_index(self.index as u16) }.ok()?;
 
+        let key = key.to_string_lossy().into_owned();
         let value = unsafe { read_value(value, value_len) };
 
         self.index += 1;
@@ -175,12 +241,18 @@ impl<'a> Iterator for Values<'a> {
     type Item = String;
 
     fn next(&mut self) -> Option<Self::Item> {
-        self.0.next().map(|e| e.1)
+        self.0
+            .next()
+            .map(|e| String::from_utf8_lossy(&e.1).into_owned())
     }
 }
 
-unsafe fn read_value(value: *const c_void, value_len: u8) -> String {
-    let value_len = value_len as usize;
-    let value_raw = slice::from_raw_parts(value as *const u8, value_len);
-    String::from_utf8(value_raw.to_vec()).expect("could not read value")
+/// Copies the value at the specified pointer. Keys without a value have a null pointer, which is
+/// read as an empty value.
+unsafe fn read_value(value: *const c_void, value_len: u8) -> Vec<u8> {
+    if value.is_null() {
+        return vec![];
+    }
+
+    slice::from_raw_parts(value as *const u8, value_len as usize).to_vec()
 }
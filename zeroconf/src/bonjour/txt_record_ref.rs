@@ -8,7 +8,7 @@ use bonjour_sys::{
 };
 use libc::{c_char, c_uchar, c_void};
 use std::ffi::CString;
-use std::{fmt, mem, ptr};
+use std::{fmt, mem, ptr, slice};
 
 use super::bonjour_util;
 
@@ -148,33 +148,96 @@ impl ManagedTXTRecordRef {
             .expect("could not clone TXT record")
     }
 
+    /// Returns the key, value pointer and value length of the item at `item_index`, retrying with
+    /// a larger key buffer for as long as Bonjour reports that the key does not fit.
+    ///
+    /// # Safety
+    /// This function is unsafe because it makes no guarantees about it's raw pointer arguments
+    pub unsafe fn read_item_at_index(
+        &self,
+        item_index: u16,
+    ) -> Result<(CString, *const c_void, u8)> {
+        _read_item_at_index(self.get_length(), self.get_bytes_ptr(), item_index)
+    }
+
+    /// Parses a TXT record from the `size` bytes at `raw`, e.g. as received from the network.
+    /// Keys that occur more than once are kept every time. Malformed records result in an error.
     pub(crate) unsafe fn clone_raw(raw: *const c_uchar, size: u16) -> Result<Self> {
-        let chars = c_string!(alloc(size as usize)).into_raw() as *mut c_uchar;
-        ptr::copy(raw, chars, size as usize);
-        let chars = CString::from_raw(chars as *mut c_char);
+        // copy the record first, so that it is never read past the `size` bytes given to us
+        let data = if size == 0 {
+            vec![]
+        } else {
+            slice::from_raw_parts(raw, size as usize).to_vec()
+        };
 
+        let data_ptr = data.as_ptr() as *const c_void;
         let mut record = Self::new();
 
-        for i in 0.._get_count(size, chars.as_ptr() as *const c_void) {
-            let key = c_string!(alloc(256));
-            let mut value_len: u8 = 0;
-            let mut value: *const c_void = ptr::null_mut();
-
-            _get_item_at_index(
-                size,
-                chars.as_ptr() as *const c_void,
-                i,
-                256,
-                key.as_ptr() as *mut c_char,
-                &mut value_len,
-                &mut value,
-            )?;
+        for i in 0.._get_count(size, data_ptr) {
+            let (key, value, value_len) = _read_item_at_index(size, data_ptr, i)?;
+
+            // empty strings, like the one substituted for an empty record, have no key
+            if key.as_bytes().is_empty() {
+                continue;
+            }
 
-            record.set_value(key.as_ptr() as *mut c_char, value_len, value)?;
+            // `TXTRecordSetValue()` would replace the first occurrence of the key
+            if record.contains_key(key.as_ptr()) {
+                record.push_duplicate(&key, value, value_len)?;
+            } else {
+                record.set_value(key.as_ptr(), value_len, value)?;
+            }
         }
 
         Ok(record)
     }
+
+    /// Appends another occurrence of `key`, which is already in the record. Bonjour provides no
+    /// function for this, so an entry of the same length is appended under a key that is not in
+    /// the record yet and then overwritten in place.
+    unsafe fn push_duplicate(
+        &mut self,
+        key: &CString,
+        value: *const c_void,
+        value_len: u8,
+    ) -> Result<()> {
+        let mut entry = key.as_bytes().to_vec();
+
+        if !value.is_null() {
+            entry.push(b'=');
+            entry.extend_from_slice(slice::from_raw_parts(
+                value as *const u8,
+                value_len as usize,
+            ));
+        }
+
+        let placeholder = (0u32..)
+            .map(|i| c_string!(i.to_string()))
+            .take_while(|k| k.as_bytes().len() <= entry.len())
+            .find(|k| !self.contains_key(k.as_ptr()))
+            .ok_or("could not append duplicate TXT record key")?;
+
+        let offset = self.get_length() as usize;
+
+        if placeholder.as_bytes().len() == entry.len() {
+            self.set_value(placeholder.as_ptr(), 0, ptr::null())?;
+        } else {
+            // placeholder + '=' + padding
+            let padding = vec![0u8; entry.len() - placeholder.as_bytes().len() - 1];
+
+            self.set_value(
+                placeholder.as_ptr(),
+                padding.len() as u8,
+                padding.as_ptr() as *const c_void,
+            )?;
+        }
+
+        // the entry is appended after its length byte, in a buffer owned by this record
+        let data = self.get_bytes_ptr() as *mut u8;
+        ptr::copy_nonoverlapping(entry.as_ptr(), data.add(offset + 1), entry.len());
+
+        Ok(())
+    }
 }
 
 impl Drop for ManagedTXTRecordRef {
@@ -189,12 +252,56 @@ impl fmt::Debug for ManagedTXTRecordRef {
     }
 }
 
+// SAFETY: the record is a buffer allocated by `TXTRecordCreate()` that only this value refers to,
+// and the `TXTRecord` functions never talk to the daemon, so it can be moved like any other heap
+// allocation.
 unsafe impl Send for ManagedTXTRecordRef {}
 
 unsafe fn _get_count(length: u16, data: *const c_void) -> u16 {
     TXTRecordGetCount(length, data)
 }
 
+/// Initial size of the key buffer passed to `TXTRecordGetItemAtIndex()`, enough for any key in a
+/// well-formed record
+const KEY_BUF_LEN: u16 = 256;
+
+unsafe fn _read_item_at_index(
+    length: u16,
+    data: *const c_void,
+    item_index: u16,
+) -> Result<(CString, *const c_void, u8)> {
+    let mut key_buf_len = KEY_BUF_LEN;
+
+    loop {
+        let mut key = vec![0u8; key_buf_len as usize];
+        let mut value_len: u8 = 0;
+        let mut value: *const c_void = ptr::null();
+
+        let err = TXTRecordGetItemAtIndex(
+            length,
+            data,
+            item_index,
+            key_buf_len,
+            key.as_mut_ptr() as *mut c_char,
+            &mut value_len,
+            &mut value,
+        );
+
+        if err == bonjour_sys::kDNSServiceErr_NoMemory && key_buf_len < u16::MAX {
+            key_buf_len = key_buf_len.saturating_mul(2);
+            continue;
+        }
+
+        bonjour_util::sys_exec(|| err, "could not get item at index for TXT record")?;
+
+        // the key is NUL-terminated within the buffer once the call succeeds
+        let key_len = key.iter().position(|b| *b == 0).unwrap_or(key.len());
+        key.truncate(key_len);
+
+        return Ok((CString::from_vec_unchecked(key), value, value_len));
+    }
+}
+
 unsafe fn _get_item_at_index(
     length: u16,
     data: *const c_void,
@@ -380,4 +487,86 @@ mod tests {
             assert_eq!(value, "bar");
         }
     }
+
+    #[test]
+    fn clone_raw_reads_item_with_longest_key() {
+        let mut data = vec![255];
+        data.extend_from_slice(&[b'k'; 255]);
+
+        let record =
+            unsafe { ManagedTXTRecordRef::clone_raw(data.as_ptr(), data.len() as u16) }.unwrap();
+        let (key, _, value_len) = unsafe { record.read_item_at_index(0) }.unwrap();
+
+        assert_eq!(key.as_bytes(), &[b'k'; 255][..]);
+        assert_eq!(value_len, 0);
+    }
+
+    #[test]
+    fn clone_raw_skips_empty_strings() {
+        let data = b"\x00\x03a=b\x04=foo";
+
+        let record =
+            unsafe { ManagedTXTRecordRef::clone_raw(data.as_ptr(), data.len() as u16) }.unwrap();
+
+        assert_eq!(unsafe { record.get_count() }, 1);
+        assert_eq!(
+            unsafe { record.read_item_at_index(0) }
+                .unwrap()
+                .0
+                .as_bytes(),
+            b"a"
+        );
+    }
+
+    #[test]
+    fn clone_raw_keeps_duplicate_keys() {
+        let data = b"\x05a=one\x01b\x05A=two\x01a";
+
+        let record =
+            unsafe { ManagedTXTRecordRef::clone_raw(data.as_ptr(), data.len() as u16) }.unwrap();
+
+        assert_eq!(unsafe { record.get_count() }, 4);
+        assert_eq!(
+            unsafe {
+                slice::from_raw_parts(
+                    record.get_bytes_ptr() as *const u8,
+                    record.get_length() as usize,
+                )
+            },
+            &data[..]
+        );
+    }
+
+    #[test]
+    fn clone_raw_rejects_truncated_record() {
+        let data = b"\x07foo=bar\x09a=b";
+
+        assert!(
+            unsafe { ManagedTXTRecordRef::clone_raw(data.as_ptr(), data.len() as u16) }.is_err()
+        );
+    }
+
+    #[test]
+    fn clone_raw_never_panics_on_arbitrary_data() {
+        let mut state: u32 = 0x2545_f491;
+
+        for _ in 0..2000 {
+            let data = (0..state % 300)
+                .map(|_| {
+                    state ^= state << 13;
+                    state ^= state >> 17;
+                    state ^= state << 5;
+                    state as u8
+                })
+                .collect::<Vec<_>>();
+
+            if let Ok(record) =
+                unsafe { ManagedTXTRecordRef::clone_raw(data.as_ptr(), data.len() as u16) }
+            {
+                for i in 0..unsafe { record.get_count() } {
+                    unsafe { record.read_item_at_index(i) }.unwrap();
+                }
+            }
+        }
+    }
 }
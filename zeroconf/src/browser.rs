@@ -1,23 +1,192 @@
 //! Trait definition for cross-platform browser
 
-use crate::{EventLoop, NetworkInterface, Result, ServiceType, TxtRecord};
+use crate::context;
+use crate::prelude::*;
+use crate::{
+    BackendInfo, CachedService, DaemonStateCallback, DetachedHandle, EventLoop, Id, MdnsBrowser,
+    NetworkInterface, Protocol, Reachability, Result, ServiceType, TxtRecord,
+};
 use std::any::Any;
-use std::sync::Arc;
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6, ToSocketAddrs};
+use std::ops::{BitOr, BitOrAssign};
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, SystemTime};
+
+/// The number of services a browser resolves at the same time unless set otherwise with
+/// [`TMdnsBrowser::set_max_concurrent_resolves()`].
+///
+/// [`TMdnsBrowser::set_max_concurrent_resolves()`]: trait.TMdnsBrowser.html#tymethod.set_max_concurrent_resolves
+pub const DEFAULT_MAX_CONCURRENT_RESOLVES: usize = 16;
+
+/// How long a browser waits for a service to resolve unless set otherwise with
+/// [`TMdnsBrowser::set_resolve_timeout()`].
+///
+/// [`TMdnsBrowser::set_resolve_timeout()`]: trait.TMdnsBrowser.html#tymethod.set_resolve_timeout
+pub const DEFAULT_RESOLVE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long the services known to a browser have to be reported again after a
+/// [`TMdnsBrowser::rescan()`] to be kept, unless set otherwise with
+/// [`TMdnsBrowser::set_rescan_deadline()`].
+///
+/// [`TMdnsBrowser::rescan()`]: trait.TMdnsBrowser.html#tymethod.rescan
+/// [`TMdnsBrowser::set_rescan_deadline()`]: trait.TMdnsBrowser.html#tymethod.set_rescan_deadline
+pub const DEFAULT_RESCAN_DEADLINE: Duration = Duration::from_secs(15);
 
 /// Interface for interacting with underlying mDNS implementation service browsing capabilities.
+///
+/// # Dropping from a callback
+///
+/// A browser may be dropped from within its own callbacks, for example once the service it was
+/// looking for has been found. Its resources are then freed by the [`EventLoop`] once the
+/// callbacks being dispatched have returned.
+///
+/// [`EventLoop`]: ../type.EventLoop.html
 pub trait TMdnsBrowser {
     /// Creates a new `MdnsBrowser` that browses for the specified `kind` (e.g. `_http._tcp`)
+    ///
+    /// Pass [`ServiceType::meta_query()`] to browse for the service types advertised on the
+    /// network instead.
+    ///
+    /// [`ServiceType::meta_query()`]: ../struct.ServiceType.html#method.meta_query
     fn new(service_type: ServiceType) -> Self;
 
+    /// Returns the [`Id`] that prefixes the log output of this browser.
+    ///
+    /// [`Id`]: ../struct.Id.html
+    fn id(&self) -> Id;
+
     /// Sets the network interface on which to browse for services on.
     ///
     /// Most applications will want to use the default value `NetworkInterface::Unspec` to browse
-    /// on all available interfaces.
+    /// on all available interfaces. To browse on a single interface, look it up by name with
+    /// [`NetworkInterface::by_name()`] rather than hardcoding its index.
+    ///
+    /// [`NetworkInterface::by_name()`]: ../enum.NetworkInterface.html#method.by_name
     fn set_network_interface(&mut self, interface: NetworkInterface);
 
     /// Returns the network interface on which to browse for services on.
     fn network_interface(&self) -> NetworkInterface;
 
+    /// Sets the domain in which to browse for services. Discovered services are resolved in this
+    /// domain too, which is then reported by [`ServiceDiscovery::domain()`].
+    ///
+    /// Most applications will want to leave this unset to browse the default domains, which is
+    /// usually `local`. Takes effect the next time the browser is started.
+    ///
+    /// [`ServiceDiscovery::domain()`]: ../struct.ServiceDiscovery.html#method.domain
+    fn set_domain(&mut self, domain: &str);
+
+    /// Returns the domain in which to browse for services, `None` for the default domains.
+    fn domain(&self) -> Option<&str>;
+
+    /// Sets the [`Protocol`] on which to browse for services and look up their addresses.
+    /// Defaults to `Protocol::Any`.
+    ///
+    /// Takes effect the next time the browser is started.
+    ///
+    /// # Note
+    /// Bonjour cannot restrict browsing to a protocol, so on macOS and Windows only the address
+    /// lookup of discovered services is restricted.
+    ///
+    /// [`Protocol`]: ../enum.Protocol.html
+    fn set_protocol(&mut self, protocol: Protocol);
+
+    /// Returns the [`Protocol`] on which to browse for services and look up their addresses.
+    ///
+    /// [`Protocol`]: ../enum.Protocol.html
+    fn protocol(&self) -> Protocol;
+
+    /// Sets the [`LookupFlags`] that control how discovered services are looked up. Defaults to
+    /// `LookupFlags::NONE`. Flags that the underlying implementation does not support are
+    /// ignored.
+    ///
+    /// Takes effect the next time the browser is started.
+    ///
+    /// [`LookupFlags`]: ../struct.LookupFlags.html
+    fn set_lookup_flags(&mut self, flags: LookupFlags);
+
+    /// Returns the [`LookupFlags`] that control how discovered services are looked up.
+    ///
+    /// [`LookupFlags`]: ../struct.LookupFlags.html
+    fn lookup_flags(&self) -> LookupFlags;
+
+    /// Sets whether a service found on several network interfaces or protocols is reported only
+    /// once. Defaults to `false`.
+    ///
+    /// When enabled, the [`ServiceDiscoveredCallback`] is invoked for the first resolution of a
+    /// service only, and [`BrowserEvent::ServiceRemoved`] is reported once the service is gone
+    /// from every interface and protocol it was found on. The reported [`ServiceDiscovery`]
+    /// describes the interface the service was first resolved on.
+    ///
+    /// Takes effect the next time the browser is started.
+    ///
+    /// [`ServiceDiscoveredCallback`]: ../type.ServiceDiscoveredCallback.html
+    /// [`BrowserEvent::ServiceRemoved`]: ../enum.BrowserEvent.html#variant.ServiceRemoved
+    /// [`ServiceDiscovery`]: ../struct.ServiceDiscovery.html
+    fn set_deduplicate(&mut self, deduplicate: bool);
+
+    /// Returns whether a service found on several network interfaces or protocols is reported
+    /// only once.
+    fn deduplicate(&self) -> bool;
+
+    /// Sets the maximum number of services that are resolved at the same time. Defaults to
+    /// [`DEFAULT_MAX_CONCURRENT_RESOLVES`]; a maximum of `0` is treated as `1`.
+    ///
+    /// Services found while the maximum is reached are queued, and resolved in the order they
+    /// were found as earlier resolutions finish. This keeps a browser started on a network with
+    /// hundreds of services from flooding the mDNS daemon.
+    ///
+    /// [`DEFAULT_MAX_CONCURRENT_RESOLVES`]: constant.DEFAULT_MAX_CONCURRENT_RESOLVES.html
+    fn set_max_concurrent_resolves(&mut self, max_concurrent_resolves: usize);
+
+    /// Returns the maximum number of services that are resolved at the same time.
+    fn max_concurrent_resolves(&self) -> usize;
+
+    /// Sets how long the browser waits for a service to resolve. Defaults to
+    /// [`DEFAULT_RESOLVE_TIMEOUT`].
+    ///
+    /// A service that has not resolved in time is given up on and reported as
+    /// [`BrowserEvent::ResolveFailed`], which makes room for the next queued service. Timeouts
+    /// are checked whenever the [`EventLoop`] is polled.
+    ///
+    /// [`DEFAULT_RESOLVE_TIMEOUT`]: constant.DEFAULT_RESOLVE_TIMEOUT.html
+    /// [`BrowserEvent::ResolveFailed`]: ../enum.BrowserEvent.html#variant.ResolveFailed
+    /// [`EventLoop`]: ../type.EventLoop.html
+    fn set_resolve_timeout(&mut self, timeout: Duration);
+
+    /// Returns how long the browser waits for a service to resolve.
+    fn resolve_timeout(&self) -> Duration;
+
+    /// Sets how long the services known to the browser have to be reported again after a
+    /// [`rescan()`] to be kept. Defaults to [`DEFAULT_RESCAN_DEADLINE`], which leaves room for
+    /// every service to be resolved within the [`DEFAULT_RESOLVE_TIMEOUT`].
+    ///
+    /// [`rescan()`]: #tymethod.rescan
+    /// [`DEFAULT_RESCAN_DEADLINE`]: constant.DEFAULT_RESCAN_DEADLINE.html
+    /// [`DEFAULT_RESOLVE_TIMEOUT`]: constant.DEFAULT_RESOLVE_TIMEOUT.html
+    fn set_rescan_deadline(&mut self, deadline: Duration);
+
+    /// Returns how long the services known to the browser have to be reported again after a
+    /// rescan to be kept.
+    fn rescan_deadline(&self) -> Duration;
+
+    /// Sets how long a service whose network interface went down is kept before it is removed
+    /// and reported as [`BrowserEvent::ServiceRemoved`]. Defaults to [`DEFAULT_GRACE_PERIOD`].
+    ///
+    /// Within the grace period, the service is reported as [`Reachability::Degraded`] and
+    /// restored if the interface comes back up or the service is reported again.
+    ///
+    /// [`BrowserEvent::ServiceRemoved`]: ../enum.BrowserEvent.html#variant.ServiceRemoved
+    /// [`DEFAULT_GRACE_PERIOD`]: ../constant.DEFAULT_GRACE_PERIOD.html
+    /// [`Reachability::Degraded`]: ../enum.Reachability.html#variant.Degraded
+    fn set_grace_period(&mut self, grace_period: Duration);
+
+    /// Returns how long a service whose network interface went down is kept.
+    fn grace_period(&self) -> Duration;
+
     /// Sets the [`ServiceDiscoveredCallback`] that is invoked when the browser has discovered and
     /// resolved a service.
     ///
@@ -27,17 +196,261 @@ pub trait TMdnsBrowser {
         service_discovered_callback: Box<ServiceDiscoveredCallback>,
     );
 
+    /// Sets a closure that is invoked when the browser has discovered and resolved a service.
+    ///
+    /// This is a shorthand for [`set_service_discovered_callback()`] for closures that keep
+    /// their state to themselves rather than in the user context.
+    ///
+    /// [`set_service_discovered_callback()`]: #tymethod.set_service_discovered_callback
+    fn set_on_discovered(
+        &mut self,
+        mut on_discovered: impl FnMut(Result<ServiceDiscovery>) + Send + 'static,
+    ) {
+        self.set_service_discovered_callback(Box::new(move |result, _| on_discovered(result)));
+    }
+
+    /// Sets a callback that is invoked when the browser has discovered and resolved a service,
+    /// with the context set with [`set_context_typed()`] already downcast to a `T`.
+    ///
+    /// # Panics
+    /// The callback panics if no context of type `T` has been set, which is logged as an error.
+    ///
+    /// [`set_context_typed()`]: #method.set_context_typed
+    fn set_service_discovered_callback_with<T: Any + Send + Sync>(
+        &mut self,
+        mut service_discovered_callback: impl FnMut(Result<ServiceDiscovery>, &T) + Send + 'static,
+    ) {
+        self.set_service_discovered_callback(Box::new(move |result, ctx| {
+            service_discovered_callback(result, context::downcast(ctx.as_deref()))
+        }));
+    }
+
+    /// Sets a filter on the names of the services found by the browser. Services whose name is
+    /// rejected are not resolved, which saves the traffic of resolving them, and are neither
+    /// reported to the [`ServiceDiscoveredCallback`] nor as [`BrowserEvent::ServiceRemoved`].
+    ///
+    /// Takes effect for the services found from then on.
+    ///
+    /// [`ServiceDiscoveredCallback`]: ../type.ServiceDiscoveredCallback.html
+    /// [`BrowserEvent::ServiceRemoved`]: ../enum.BrowserEvent.html#variant.ServiceRemoved
+    fn set_name_filter(&mut self, name_filter: impl Fn(&str) -> bool + Send + 'static);
+
+    /// Sets a filter on the resolved services, e.g. to only report the services with a certain
+    /// TXT record entry. Services that are rejected are not reported to the
+    /// [`ServiceDiscoveredCallback`], and neither is their [`BrowserEvent::ServiceRemoved`]
+    /// unless a later discovery of them has been accepted.
+    ///
+    /// Takes effect for the services resolved from then on.
+    ///
+    /// [`ServiceDiscoveredCallback`]: ../type.ServiceDiscoveredCallback.html
+    /// [`BrowserEvent::ServiceRemoved`]: ../enum.BrowserEvent.html#variant.ServiceRemoved
+    fn set_discovery_filter(
+        &mut self,
+        discovery_filter: impl Fn(&ServiceDiscovery) -> bool + Send + 'static,
+    );
+
+    /// Sets the optional [`BrowserEventCallback`] that is invoked with the [`BrowserEvent`]s
+    /// reported by the implementation, e.g. when the initial burst of results has been delivered.
+    ///
+    /// [`BrowserEventCallback`]: ../type.BrowserEventCallback.html
+    /// [`BrowserEvent`]: ../enum.BrowserEvent.html
+    fn set_browser_event_callback(&mut self, browser_event_callback: Box<BrowserEventCallback>);
+
     /// Sets the optional user context to pass through to the callback. This is useful if you need
-    /// to share state between pre and post-callback. The context type must implement `Any`.
-    fn set_context(&mut self, context: Box<dyn Any>);
+    /// to share state between pre and post-callback. The context type must implement `Any`, `Send`
+    /// and `Sync`.
+    fn set_context(&mut self, context: Box<dyn Any + Send + Sync>);
+
+    /// Sets the user context to pass through to callbacks registered with
+    /// [`set_service_discovered_callback_with()`], which receive it as a `&T` rather than having
+    /// to downcast it.
+    ///
+    /// [`set_service_discovered_callback_with()`]: #method.set_service_discovered_callback_with
+    fn set_context_typed<T: Any + Send + Sync>(&mut self, context: Arc<T>) {
+        self.set_context(Box::new(context));
+    }
 
     /// Returns the optional user context to pass through to the callback.
-    fn context(&self) -> Option<&dyn Any>;
+    fn context(&self) -> Option<&(dyn Any + Send + Sync)>;
+
+    /// Sets the [`SettledCallback`] that is invoked exactly once with a snapshot of the services
+    /// discovered so far when the initial enumeration has settled. Discovered services continue
+    /// to be delivered to the [`ServiceDiscoveredCallback`] afterwards.
+    ///
+    /// The browser has settled once the implementation has signalled that it does not expect
+    /// more services in the near future, no resolution is in flight any longer and a short
+    /// period has passed without any new services. If that has not happened within `deadline`
+    /// after `browse_services()`, the browser settles with whatever has been resolved by then.
+    ///
+    /// The callback is invoked from `EventLoop::poll()`, so it may be late by up to the timeout
+    /// passed to `poll()`. Must be called before `browse_services()`.
+    ///
+    /// # Note
+    /// Bonjour only signals the end of an enumeration along with a discovered service, so on
+    /// macOS and Windows a browser that finds nothing settles at the deadline.
+    ///
+    /// [`SettledCallback`]: ../type.SettledCallback.html
+    /// [`ServiceDiscoveredCallback`]: ../type.ServiceDiscoveredCallback.html
+    fn on_settled(&mut self, deadline: Duration, settled_callback: Box<SettledCallback>);
+
+    /// Sets whether `browse_services()` should wait for the mDNS daemon if it is not running, instead of
+    /// failing. Defaults to `false`.
+    ///
+    /// If `true`, browsing starts once the daemon is available, and the [`DaemonStateCallback`]
+    /// is informed while waiting. Must be called before `browse_services()`.
+    ///
+    /// # Note
+    /// Only supported on Linux; Bonjour returns `Error::Unsupported` when enabling it.
+    ///
+    /// [`DaemonStateCallback`]: ../daemon/type.DaemonStateCallback.html
+    fn set_wait_for_daemon(&mut self, wait_for_daemon: bool) -> Result<()>;
+
+    /// Returns whether `browse_services()` waits for the mDNS daemon if it is not running.
+    fn wait_for_daemon(&self) -> bool;
+
+    /// Sets the optional [`DaemonStateCallback`] that is invoked when the state of the connection
+    /// to the mDNS daemon changes.
+    ///
+    /// # Note
+    /// Bonjour connects to the daemon synchronously and reports failures from `browse_services()`, so the
+    /// callback is never invoked on macOS and Windows.
+    ///
+    /// [`DaemonStateCallback`]: ../daemon/type.DaemonStateCallback.html
+    fn set_daemon_state_callback(&mut self, daemon_state_callback: Box<DaemonStateCallback>);
 
     /// Starts the browser. Returns an `EventLoop` which can be called to keep the browser alive.
+    ///
+    /// Calling `browse_services()` again restarts the browser: the previous browse and its
+    /// pending resolves are terminated and the services on the network are reported anew. The
+    /// `EventLoop` returned by the previous call should be dropped. Use [`rescan()`] to report the
+    /// services anew on the same event loop instead.
+    ///
+    /// [`rescan()`]: #tymethod.rescan
     fn browse_services(&mut self) -> Result<EventLoop>;
+
+    /// Starts the browser and polls its `EventLoop` on a background thread named
+    /// `zeroconf-poll`, which takes ownership of the browser. The browser keeps running until the
+    /// returned [`DetachedHandle`] is stopped or dropped.
+    ///
+    /// # Note
+    /// Callbacks are invoked on the background thread.
+    ///
+    /// [`DetachedHandle`]: ../detached/struct.DetachedHandle.html
+    fn browse_detached(mut self) -> Result<DetachedHandle>
+    where
+        Self: Sized + Send + 'static,
+    {
+        let event_loop = self.browse_services()?;
+        DetachedHandle::spawn(self, event_loop)
+    }
+
+    /// Restarts the queries of a running browser, so that the services that are still alive are
+    /// discovered and resolved again and passed to the [`ServiceDiscoveredCallback`].
+    ///
+    /// Every service in [`known_services()`] that has not been reported again within the
+    /// [`rescan_deadline()`] is then removed and reported as [`BrowserEvent::ServiceRemoved`],
+    /// which gets rid of services that went away without sending a goodbye. The deadline is
+    /// checked whenever the [`EventLoop`] is polled.
+    ///
+    /// Must be called after `browse_services()`.
+    ///
+    /// # Note
+    /// Only Bonjour bypasses the cache of the daemon. On macOS and Windows, the browse is
+    /// restarted and services are resolved with `kDNSServiceFlagsForceMulticast`, which sends
+    /// the queries on the network even if the answers are cached. On Linux, the Avahi service
+    /// browser is recreated, but Avahi has no way of flushing its cache: the daemon answers the
+    /// new browser from it, so a service that died silently is only removed by the deadline once
+    /// its records have expired from the daemon's cache.
+    ///
+    /// [`ServiceDiscoveredCallback`]: ../type.ServiceDiscoveredCallback.html
+    /// [`known_services()`]: #tymethod.known_services
+    /// [`rescan_deadline()`]: #tymethod.rescan_deadline
+    /// [`BrowserEvent::ServiceRemoved`]: ../enum.BrowserEvent.html#variant.ServiceRemoved
+    /// [`EventLoop`]: ../type.EventLoop.html
+    fn rescan(&mut self) -> Result<()>;
+
+    /// Returns the services this browser has reported to the [`ServiceDiscoveredCallback`] and
+    /// whose removal it has not reported since, ordered by their [`ServiceKey`].
+    ///
+    /// The services are kept in a [`ServiceCache`], which is updated before the callbacks are
+    /// invoked for the same event. From within the `ServiceDiscoveredCallback`, the service being
+    /// reported is therefore included with the contents it is reported with, and from within the
+    /// [`BrowserEventCallback`] for [`BrowserEvent::ServiceRemoved`], the removed service is no
+    /// longer included. The services are forgotten when `browse_services()` is called again.
+    ///
+    /// [`ServiceDiscoveredCallback`]: ../type.ServiceDiscoveredCallback.html
+    /// [`ServiceKey`]: ../struct.ServiceKey.html
+    /// [`ServiceCache`]: ../cache/struct.ServiceCache.html
+    /// [`BrowserEventCallback`]: ../type.BrowserEventCallback.html
+    /// [`BrowserEvent::ServiceRemoved`]: ../enum.BrowserEvent.html#variant.ServiceRemoved
+    fn known_services(&self) -> Vec<CachedService>;
+
+    /// Returns a snapshot of the [`BrowserStats`] of this browser, e.g. for health checks.
+    ///
+    /// [`BrowserStats`]: ../struct.BrowserStats.html
+    fn stats(&self) -> BrowserStats;
+
+    /// Returns the [`BackendInfo`] of the mDNS stack this browser is browsing with, e.g. for bug
+    /// reports. Returns `Err` if the browser has not been started, as there is no connection to
+    /// the daemon to ask yet.
+    ///
+    /// [`BackendInfo`]: ../struct.BackendInfo.html
+    fn backend_info(&self) -> Result<BackendInfo>;
+}
+
+/// Properties of a [`MdnsBrowser`] created with `MdnsBrowser::builder()`.
+///
+/// [`MdnsBrowser`]: ../type.MdnsBrowser.html
+#[derive(Builder)]
+#[builder(
+    name = "BrowserBuilder",
+    public,
+    pattern = "owned",
+    build_fn(private, name = "build_config")
+)]
+struct BrowserConfig {
+    service_type: ServiceType,
+    #[builder(default = "NetworkInterface::Unspec")]
+    network_interface: NetworkInterface,
+    #[builder(setter(strip_option), default)]
+    service_discovered_callback: Option<Box<ServiceDiscoveredCallback>>,
+    #[builder(setter(strip_option), default)]
+    browser_event_callback: Option<Box<BrowserEventCallback>>,
+    #[builder(setter(strip_option), default)]
+    context: Option<Box<dyn Any + Send + Sync>>,
 }
 
+impl BrowserBuilder {
+    /// Builds the `MdnsBrowser`, returning an error if a required property is missing.
+    ///
+    /// Every property is passed on to the corresponding setter of [`TMdnsBrowser`], only
+    /// `service_type` is required.
+    ///
+    /// [`TMdnsBrowser`]: trait.TMdnsBrowser.html
+    pub fn build(self) -> Result<MdnsBrowser> {
+        let config = self.build_config()?;
+        let mut browser = MdnsBrowser::new(config.service_type);
+
+        browser.set_network_interface(config.network_interface);
+
+        if let Some(service_discovered_callback) = config.service_discovered_callback {
+            browser.set_service_discovered_callback(service_discovered_callback);
+        }
+
+        if let Some(browser_event_callback) = config.browser_event_callback {
+            browser.set_browser_event_callback(browser_event_callback);
+        }
+
+        if let Some(context) = config.context {
+            browser.set_context(context);
+        }
+
+        Ok(browser)
+    }
+}
+
+impl BuilderDelegate<BrowserBuilder> for MdnsBrowser {}
+
 /// Callback invoked from [`MdnsBrowser`] once a service has been discovered and resolved.
 ///
 /// # Arguments
@@ -45,19 +458,577 @@ pub trait TMdnsBrowser {
 /// * `context` - The optional user context passed through
 ///
 /// [`MdnsBrowser`]: type.MdnsBrowser.html
-pub type ServiceDiscoveredCallback = dyn Fn(Result<ServiceDiscovery>, Option<Arc<dyn Any>>);
+pub type ServiceDiscoveredCallback =
+    dyn FnMut(Result<ServiceDiscovery>, Option<Arc<dyn Any + Send + Sync>>) + Send;
+
+/// Filter set with [`TMdnsBrowser::set_name_filter()`], which returns true for the names of the
+/// services to resolve.
+///
+/// [`TMdnsBrowser::set_name_filter()`]: browser/trait.TMdnsBrowser.html#tymethod.set_name_filter
+pub type NameFilter = dyn Fn(&str) -> bool + Send;
+
+/// Filter set with [`TMdnsBrowser::set_discovery_filter()`], which returns true for the resolved
+/// services to report.
+///
+/// [`TMdnsBrowser::set_discovery_filter()`]: browser/trait.TMdnsBrowser.html#tymethod.set_discovery_filter
+pub type DiscoveryFilter = dyn Fn(&ServiceDiscovery) -> bool + Send;
+
+/// Callback invoked from [`MdnsBrowser`] for every [`BrowserEvent`].
+///
+/// # Arguments
+/// * `event` - The event reported by the implementation
+/// * `context` - The optional user context passed through
+///
+/// [`MdnsBrowser`]: type.MdnsBrowser.html
+/// [`BrowserEvent`]: enum.BrowserEvent.html
+pub type BrowserEventCallback = dyn Fn(BrowserEvent, Option<Arc<dyn Any + Send + Sync>>) + Send;
+
+/// Progress of a browser, reported to the [`BrowserEventCallback`].
+///
+/// [`BrowserEventCallback`]: type.BrowserEventCallback.html
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum BrowserEvent {
+    /// No more results are expected in the near future. Useful for "scan, then show the results"
+    /// flows. Services appearing later are still delivered.
+    ///
+    /// # Note
+    /// Bonjour has no equivalent event, so on macOS and Windows it is approximated by the end of
+    /// the first batch of browse results, and it is never reported if no service is found.
+    AllForNow,
+    /// All results from the cache of the mDNS daemon have been delivered; further results come
+    /// from the network.
+    ///
+    /// # Note
+    /// Only reported on Linux; Bonjour does not distinguish cached results.
+    CacheExhausted,
+    /// The results delivered since the previous `Flushed` form a batch, and no more results are
+    /// immediately pending. Applications can defer updating their UI until this event to avoid
+    /// redrawing for every single service.
+    ///
+    /// # Note
+    /// On macOS and Windows, this is reported once a browse or resolve result arrives without
+    /// `kDNSServiceFlagsMoreComing`. Avahi does not batch results, so on Linux it is reported
+    /// once the browser has reported `AllForNow` and no service is left to be resolved.
+    Flushed,
+    /// A service type is advertised on the network. Only reported by browsers created with
+    /// [`ServiceType::meta_query()`], which do not discover any services.
+    ///
+    /// # Note
+    /// Avahi reports a service type once for every network interface and protocol it is found on.
+    ///
+    /// [`ServiceType::meta_query()`]: struct.ServiceType.html#method.meta_query
+    ServiceTypeFound(ServiceType),
+    /// A service type reported by [`ServiceTypeFound`] is no longer advertised.
+    ///
+    /// [`ServiceTypeFound`]: #variant.ServiceTypeFound
+    ServiceTypeRemoved(ServiceType),
+    /// A service is no longer advertised. The removed service matches the discoveries whose
+    /// [`ServiceDiscovery::key()`] equals the reported key.
+    ///
+    /// # Note
+    /// The mDNS implementation reports a removal for every network interface and protocol the
+    /// service was found on, unless the browser deduplicates services.
+    ///
+    /// [`ServiceDiscovery::key()`]: struct.ServiceDiscovery.html#method.key
+    ServiceRemoved(ServiceKey),
+    /// The network interface a known service was resolved on went down or came back up. A
+    /// service that stays [`Reachability::Degraded`] for the browser's grace period is then
+    /// removed.
+    ///
+    /// # Note
+    /// Interface changes are only tracked on Linux and macOS.
+    ///
+    /// [`Reachability::Degraded`]: enum.Reachability.html#variant.Degraded
+    ServiceReachabilityChanged {
+        /// The service whose reachability changed
+        key: ServiceKey,
+        /// Whether the service can still be reached
+        reachability: Reachability,
+    },
+    /// A service was found, but could not be resolved. It is not retried until it is found
+    /// again.
+    ResolveFailed {
+        /// The service that could not be resolved
+        key: ServiceKey,
+        /// Why the service could not be resolved
+        reason: ResolveFailure,
+    },
+}
+
+/// Reason reported with [`BrowserEvent::ResolveFailed`].
+///
+/// [`BrowserEvent::ResolveFailed`]: enum.BrowserEvent.html#variant.ResolveFailed
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ResolveFailure {
+    /// The service did not resolve within the [`TMdnsBrowser::resolve_timeout()`].
+    ///
+    /// [`TMdnsBrowser::resolve_timeout()`]: browser/trait.TMdnsBrowser.html#tymethod.resolve_timeout
+    Timeout,
+}
+
+/// Callback invoked from [`MdnsBrowser`] once its initial enumeration has settled.
+///
+/// # Arguments
+/// * `services` - The services that were discovered and resolved before the browser settled
+///
+/// [`MdnsBrowser`]: type.MdnsBrowser.html
+pub type SettledCallback = dyn FnOnce(Vec<ServiceDiscovery>) + Send;
+
+/// Statistics of a [`MdnsBrowser`], returned by [`TMdnsBrowser::stats()`].
+///
+/// [`MdnsBrowser`]: type.MdnsBrowser.html
+/// [`TMdnsBrowser::stats()`]: browser/trait.TMdnsBrowser.html#tymethod.stats
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Default, Getters, Clone, Copy, PartialEq, Eq)]
+pub struct BrowserStats {
+    /// The number of services that are being resolved
+    active_resolvers: usize,
+    /// The number of events received from the mDNS implementation since browsing started
+    events_received: u64,
+    /// When the last event was received, `None` if no event has been received yet
+    last_event: Option<SystemTime>,
+    /// The number of errors passed to the [`ServiceDiscoveredCallback`]
+    ///
+    /// [`ServiceDiscoveredCallback`]: type.ServiceDiscoveredCallback.html
+    failures: u64,
+}
+
+impl BrowserStats {
+    /// Records an event received from the mDNS implementation.
+    pub(crate) fn event_received(&mut self) {
+        self.events_received += 1;
+        self.last_event = Some(SystemTime::now());
+    }
+
+    /// Records an error passed to the `ServiceDiscoveredCallback`.
+    pub(crate) fn failed(&mut self) {
+        self.failures += 1;
+    }
+
+    #[cfg(not(feature = "test-mock"))]
+    pub(crate) fn with_active_resolvers(mut self, active_resolvers: usize) -> Self {
+        self.active_resolvers = active_resolvers;
+        self
+    }
+}
+
+/// Flags that modify how a [`MdnsBrowser`] looks up the services it discovers. Flags can be
+/// combined with `|`.
+///
+/// Skipping the TXT record or address lookup saves a round trip per service, which is worthwhile
+/// when browsing large networks for names only.
+///
+/// | Flag            | Avahi                        | Bonjour                                   |
+/// |-----------------|------------------------------|-------------------------------------------|
+/// | `USE_MULTICAST` | `AVAHI_LOOKUP_USE_MULTICAST` | `kDNSServiceFlagsForceMulticast`          |
+/// | `NO_TXT`        | `AVAHI_LOOKUP_NO_TXT`        | the TXT record is discarded once resolved |
+/// | `NO_ADDRESS`    | `AVAHI_LOOKUP_NO_ADDRESS`    | `DNSServiceGetAddrInfo()` is skipped      |
+///
+/// [`MdnsBrowser`]: type.MdnsBrowser.html
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct LookupFlags(u32);
+
+impl LookupFlags {
+    /// No flags
+    pub const NONE: Self = Self(0);
+    /// Only look up services with multicast DNS, even if wide-area DNS is available
+    pub const USE_MULTICAST: Self = Self(1);
+    /// Do not look up the TXT record of discovered services
+    pub const NO_TXT: Self = Self(1 << 1);
+    /// Do not look up the address of the host of discovered services
+    pub const NO_ADDRESS: Self = Self(1 << 2);
+
+    const NAMED: [(Self, &'static str); 3] = [
+        (Self::USE_MULTICAST, "USE_MULTICAST"),
+        (Self::NO_TXT, "NO_TXT"),
+        (Self::NO_ADDRESS, "NO_ADDRESS"),
+    ];
+
+    /// Returns true if no flags are set.
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns true if all flags in `other` are set.
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl BitOr for LookupFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for LookupFlags {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl fmt::Debug for LookupFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "NONE");
+        }
+
+        let names = Self::NAMED
+            .iter()
+            .filter(|(flag, _)| self.contains(*flag))
+            .map(|(_, name)| *name)
+            .collect::<Vec<_>>();
+
+        write!(f, "{}", names.join(" | "))
+    }
+}
 
 /// Represents a service that has been discovered by a [`MdnsBrowser`].
 ///
 /// [`MdnsBrowser`]: type.MdnsBrowser.html
+///
+/// Discoveries are compared by the service they describe; `ttl` and `discovered_at` describe when
+/// the service was observed and are not compared.
+///
+/// A discovery can be constructed with [`ServiceDiscoveryBuilder`], e.g. to test the callback
+/// passed to [`TMdnsBrowser::set_service_discovered_callback()`]. The `name`, `service_type`,
+/// `domain`, `host_name`, `address` and `port` are required, every other property has a default,
+/// as will any property added in the future.
+///
+/// ```
+/// use zeroconf::prelude::*;
+/// use zeroconf::{ServiceDiscovery, ServiceType, TxtRecord};
+///
+/// let mut txt = TxtRecord::new();
+/// txt.insert("path", "/").unwrap();
+///
+/// let discovery = ServiceDiscovery::builder()
+///     .name("foo")
+///     .service_type(ServiceType::new("http", "tcp").unwrap())
+///     .domain("local")
+///     .host_name("foo.local")
+///     .address("192.168.1.2")
+///     .port(8080)
+///     .txt(Some(txt))
+///     .build()
+///     .unwrap();
+///
+/// assert_eq!(discovery.name(), "foo");
+/// ```
+///
+/// [`ServiceDiscoveryBuilder`]: struct.ServiceDiscoveryBuilder.html
+/// [`TMdnsBrowser::set_service_discovered_callback()`]: browser/trait.TMdnsBrowser.html#tymethod.set_service_discovered_callback
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-#[derive(Debug, Getters, Builder, BuilderDelegate, Clone, PartialEq, Eq)]
+#[derive(Getters, Builder, BuilderDelegate, Clone)]
 pub struct ServiceDiscovery {
+    #[builder(setter(into))]
     name: String,
     service_type: ServiceType,
+    #[builder(setter(into))]
     domain: String,
+    #[builder(setter(into))]
     host_name: String,
+    /// The address of the host, empty if the service was looked up with
+    /// `LookupFlags::NO_ADDRESS`. Link-local IPv6 addresses carry the index of the interface
+    /// they were resolved on as their zone, e.g. `fe80::1%2`
+    #[builder(setter(into))]
     address: String,
     port: u16,
-    txt: Option<TxtRecord>,
+    #[builder(default)]
+    #[getter(skip)]
+    #[cfg_attr(feature = "serde", serde(rename = "txt", with = "txt_wire_format"))]
+    txt_bytes: Option<Vec<u8>>,
+    #[builder(setter(skip))]
+    #[getter(skip)]
+    #[cfg_attr(feature = "serde", serde(skip))]
+    txt: OnceLock<Option<TxtRecord>>,
+    /// The network interface the service was resolved on
+    #[builder(default = "NetworkInterface::Unspec")]
+    interface: NetworkInterface,
+    /// The remaining time to live in seconds of the address record, after which the service
+    /// should be considered gone unless it is resolved again. Only reported by Bonjour.
+    #[builder(default)]
+    #[cfg_attr(feature = "serde", serde(default))]
+    ttl: Option<u32>,
+    /// The time at which the service was resolved
+    #[builder(default = "SystemTime::now()")]
+    #[cfg_attr(feature = "serde", serde(default = "SystemTime::now"))]
+    discovered_at: SystemTime,
+}
+
+impl PartialEq for ServiceDiscovery {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+            && self.service_type == other.service_type
+            && self.domain == other.domain
+            && self.host_name == other.host_name
+            && self.address == other.address
+            && self.port == other.port
+            && self.txt() == other.txt()
+            && self.interface == other.interface
+    }
+}
+
+impl fmt::Debug for ServiceDiscovery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServiceDiscovery")
+            .field("name", &self.name)
+            .field("service_type", &self.service_type)
+            .field("domain", &self.domain)
+            .field("host_name", &self.host_name)
+            .field("address", &self.address)
+            .field("port", &self.port)
+            .field("txt", &self.txt())
+            .field("interface", &self.interface)
+            .field("ttl", &self.ttl)
+            .field("discovered_at", &self.discovered_at)
+            .finish()
+    }
+}
+
+impl Eq for ServiceDiscovery {}
+
+impl ServiceDiscovery {
+    /// Returns the [`ServiceKey`] identifying this service regardless of the network interface
+    /// and protocol it was discovered on. Use the key to index collections of discoveries; the
+    /// TXT record of a discovery is backed by the mDNS implementation and can not be hashed.
+    ///
+    /// [`ServiceKey`]: struct.ServiceKey.html
+    pub fn key(&self) -> ServiceKey {
+        ServiceKey::new(&self.name, &self.service_type, &self.domain)
+    }
+
+    /// Returns the TXT record of the service, `None` if the service was looked up with
+    /// `LookupFlags::NO_TXT` or its record could not be decoded.
+    ///
+    /// Every service is advertised with a TXT record, so a service without attributes yields
+    /// `Some` empty record rather than `None`.
+    ///
+    /// The record is decoded from [`txt_bytes()`] the first time it is accessed, so discoveries
+    /// whose TXT record is never read do not pay for decoding it.
+    ///
+    /// [`txt_bytes()`]: #method.txt_bytes
+    pub fn txt(&self) -> Option<&TxtRecord> {
+        self.txt
+            .get_or_init(|| match TxtRecord::from_bytes(self.txt_bytes.as_deref()?) {
+                Ok(txt) => Some(txt),
+                Err(e) => {
+                    warn!("could not decode TXT record of `{}`: {}", self.name, e);
+                    None
+                }
+            })
+            .as_ref()
+    }
+
+    /// Returns the TXT record of the service as received, in the DNS wire format (RFC 6763,
+    /// section 6), `None` if the service was looked up with `LookupFlags::NO_TXT`.
+    ///
+    /// Unlike [`txt()`], this does not decode the record, which makes it the cheaper choice for
+    /// e.g. logging the size or a hash of the record.
+    ///
+    /// [`txt()`]: #method.txt
+    pub fn txt_bytes(&self) -> Option<&[u8]> {
+        self.txt_bytes.as_deref()
+    }
+
+    /// Returns the socket addresses to connect to the service, pairing the resolved `address`
+    /// with the `port`.
+    ///
+    /// The index of the network interface the service was resolved on is attached as the scope
+    /// id of link-local IPv6 addresses, which can not be connected to without one. Returns `Err`
+    /// if the address is empty, as it is if the service was looked up with
+    /// `LookupFlags::NO_ADDRESS`, or can not be parsed.
+    ///
+    /// `ServiceDiscovery` also implements `ToSocketAddrs`, so a discovery can be passed to e.g.
+    /// `TcpStream::connect()` directly.
+    ///
+    /// ```
+    /// use std::net::SocketAddr;
+    /// use zeroconf::prelude::*;
+    /// use zeroconf::{ServiceDiscovery, ServiceType};
+    ///
+    /// let discovery = ServiceDiscovery::builder()
+    ///     .name("foo")
+    ///     .service_type(ServiceType::new("http", "tcp").unwrap())
+    ///     .domain("local")
+    ///     .host_name("foo.local")
+    ///     .address("192.168.1.2")
+    ///     .port(8080)
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// assert_eq!(
+    ///     discovery.socket_addrs().unwrap(),
+    ///     vec!["192.168.1.2:8080".parse::<SocketAddr>().unwrap()]
+    /// );
+    /// ```
+    pub fn socket_addrs(&self) -> Result<Vec<SocketAddr>> {
+        if self.address.is_empty() {
+            return Err(format!(
+                "service `{}` has not been resolved to an address",
+                self.name
+            )
+            .into());
+        }
+
+        // link-local IPv6 addresses may carry their scope, e.g. `fe80::1%2`
+        let (address, scope) = match self.address.split_once('%') {
+            Some((address, scope)) => (address, Some(scope)),
+            None => (self.address.as_str(), None),
+        };
+
+        let invalid = || format!("invalid address `{}`", self.address);
+        let ip = IpAddr::from_str(address).map_err(|_| invalid())?;
+
+        Ok(vec![match ip {
+            IpAddr::V4(ip) => SocketAddr::V4(SocketAddrV4::new(ip, self.port)),
+            IpAddr::V6(ip) => {
+                let scope_id = match scope {
+                    Some(scope) => scope.parse().map_err(|_| invalid())?,
+                    None if is_link_local(&ip) => self.interface_index(),
+                    None => 0,
+                };
+
+                SocketAddr::V6(SocketAddrV6::new(ip, self.port, 0, scope_id))
+            }
+        }])
+    }
+
+    /// Returns the index of the network interface the service was resolved on, `0` if unknown.
+    fn interface_index(&self) -> u32 {
+        match self.interface {
+            NetworkInterface::AtIndex(index) => index,
+            NetworkInterface::Unspec | NetworkInterface::LocalOnly => 0,
+        }
+    }
+}
+
+impl ToSocketAddrs for ServiceDiscovery {
+    type Iter = std::vec::IntoIter<SocketAddr>;
+
+    fn to_socket_addrs(&self) -> io::Result<Self::Iter> {
+        self.socket_addrs()
+            .map(Vec::into_iter)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.to_string()))
+    }
+}
+
+/// Appends the index of `interface` as the zone to `address` if it is a link-local IPv6 address
+/// (e.g. `fe80::1%2`), which is ambiguous without one. Other addresses are returned as is.
+///
+/// The zone is numeric rather than the name of the interface, so that it can be parsed by
+/// [`ServiceDiscovery::socket_addrs()`] on every platform.
+pub(crate) fn scoped_address(address: String, interface: NetworkInterface) -> String {
+    let index = match interface {
+        NetworkInterface::AtIndex(index) if index > 0 => index,
+        _ => return address,
+    };
+
+    match Ipv6Addr::from_str(&address) {
+        Ok(ip) if is_link_local(&ip) => format!("{}%{}", address, index),
+        _ => address,
+    }
+}
+
+/// Returns true if `ip` is a unicast link-local address (`fe80::/10`).
+fn is_link_local(ip: &Ipv6Addr) -> bool {
+    ip.segments()[0] & 0xffc0 == 0xfe80
+}
+
+impl ServiceDiscoveryBuilder {
+    /// Sets the TXT record of the service, which is stored in the DNS wire format like the
+    /// records received by the browser. See [`ServiceDiscovery::txt_bytes()`].
+    ///
+    /// [`ServiceDiscovery::txt_bytes()`]: struct.ServiceDiscovery.html#method.txt_bytes
+    pub fn txt(&mut self, txt: Option<TxtRecord>) -> &mut Self {
+        self.txt_bytes = Some(txt.map(|txt| txt.to_bytes()));
+        self
+    }
+
+    /// Builds the `ServiceDiscovery` by taking the values out of this builder, rather than copying
+    /// every field like `build()` does. Backends build a discovery for every resolved service, so
+    /// this spares them a copy of each string and of the TXT record.
+    pub(crate) fn build_owned(&mut self) -> std::result::Result<ServiceDiscovery, String> {
+        fn required<T>(value: Option<T>, field: &str) -> std::result::Result<T, String> {
+            value.ok_or_else(|| format!("`{}` must be initialized", field))
+        }
+
+        Ok(ServiceDiscovery {
+            name: required(self.name.take(), "name")?,
+            service_type: required(self.service_type.take(), "service_type")?,
+            domain: required(self.domain.take(), "domain")?,
+            host_name: required(self.host_name.take(), "host_name")?,
+            address: required(self.address.take(), "address")?,
+            port: required(self.port.take(), "port")?,
+            txt_bytes: self.txt_bytes.take().unwrap_or_default(),
+            txt: OnceLock::new(),
+            interface: self.interface.take().unwrap_or(NetworkInterface::Unspec),
+            ttl: self.ttl.take().unwrap_or_default(),
+            discovered_at: self.discovered_at.take().unwrap_or_else(SystemTime::now),
+        })
+    }
+}
+
+/// Serializes the TXT record of a [`ServiceDiscovery`] decoded, rather than in the wire format it
+/// is stored in.
+///
+/// [`ServiceDiscovery`]: ../struct.ServiceDiscovery.html
+#[cfg(feature = "serde")]
+mod txt_wire_format {
+    use crate::prelude::*;
+    use crate::TxtRecord;
+    use serde::ser::Error;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub(super) fn serialize<S: Serializer>(
+        txt_bytes: &Option<Vec<u8>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        txt_bytes
+            .as_deref()
+            .map(TxtRecord::from_bytes)
+            .transpose()
+            .map_err(S::Error::custom)?
+            .serialize(serializer)
+    }
+
+    pub(super) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Vec<u8>>, D::Error> {
+        Ok(Option::<TxtRecord>::deserialize(deserializer)?.map(|txt| txt.to_bytes()))
+    }
+}
+
+/// Identifies a discovered service by its name, type and domain, so that a [`ServiceDiscovery`]
+/// can be correlated with a later [`BrowserEvent::ServiceRemoved`] or used as the key of a
+/// collection.
+///
+/// Keys are ordered by name, then service type, then domain.
+///
+/// [`ServiceDiscovery`]: struct.ServiceDiscovery.html
+/// [`BrowserEvent::ServiceRemoved`]: enum.BrowserEvent.html#variant.ServiceRemoved
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Getters, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ServiceKey {
+    name: String,
+    service_type: ServiceType,
+    domain: String,
+}
+
+impl ServiceKey {
+    /// Creates a new `ServiceKey` for the service with the specified name, type and domain.
+    pub fn new(name: &str, service_type: &ServiceType, domain: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            service_type: service_type.clone(),
+            domain: domain.to_string(),
+        }
+    }
 }
@@ -0,0 +1,1294 @@
+//! Platform-independent cache of the services known to a browser
+
+use crate::prelude::*;
+use crate::{BrowserEvent, NetworkInterface, ServiceDiscovery, ServiceKey, ServiceType};
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+/// Portion of a record's TTL after which a well-behaved cache starts sending refresh queries
+/// (RFC 6762, section 5.2). A TTL that is reset before this point was not caused by our own
+/// cache maintenance.
+const REFRESH_FRACTION: f64 = 0.8;
+
+/// TTLs are reported in whole seconds, so a remaining TTL can appear to grow by up to a second
+/// without the record having been refreshed.
+const TTL_TOLERANCE: Duration = Duration::from_secs(1);
+
+/// Default time a service may stay [`Degraded`] before it is expired from the cache.
+///
+/// [`Degraded`]: enum.Reachability.html#variant.Degraded
+pub const DEFAULT_GRACE_PERIOD: Duration = Duration::from_secs(30);
+
+/// Controls which signals a [`ServiceCache`] accepts as evidence that a service has restarted.
+///
+/// The defaults are conservative: only an observed goodbye is considered evidence. Other signals
+/// must be opted into explicitly.
+///
+/// [`ServiceCache`]: struct.ServiceCache.html
+#[derive(Debug, Getters, Builder, BuilderDelegate, Clone, PartialEq, Eq)]
+pub struct IncarnationPolicy {
+    /// Whether a service re-appearing after a goodbye (removal) is a new incarnation
+    #[builder(default = "true")]
+    goodbye: bool,
+    /// Whether a TTL reset observed through re-resolution is a new incarnation.
+    ///
+    /// Responses to queries made by other hosts on the network also refresh TTLs, so this signal
+    /// can produce false positives on busy networks.
+    #[builder(default)]
+    ttl_reset: bool,
+    /// TXT keys (e.g. `boot` or `seq`) whose value changes when the service restarts
+    #[builder(default)]
+    txt_keys: Vec<String>,
+}
+
+impl IncarnationPolicy {
+    fn txt_key_changed(&self, old: &ServiceDiscovery, new: &ServiceDiscovery) -> bool {
+        let (old_txt, new_txt) = match (old.txt(), new.txt()) {
+            (Some(old_txt), Some(new_txt)) => (old_txt, new_txt),
+            _ => return false,
+        };
+
+        self.txt_keys
+            .iter()
+            .any(|key| match (old_txt.get(key), new_txt.get(key)) {
+                (Some(old_value), Some(new_value)) => old_value != new_value,
+                _ => false,
+            })
+    }
+
+    fn ttl_was_reset(&self, old: Option<Expiry>, new: Option<Expiry>) -> bool {
+        if !self.ttl_reset {
+            return false;
+        }
+
+        match (old, new) {
+            (Some(old), Some(new)) => {
+                new.observed_at < old.refresh_at()
+                    && new.expires_at() > old.expires_at() + TTL_TOLERANCE
+            }
+            _ => false,
+        }
+    }
+}
+
+impl Default for IncarnationPolicy {
+    fn default() -> Self {
+        Self {
+            goodbye: true,
+            ttl_reset: false,
+            txt_keys: vec![],
+        }
+    }
+}
+
+/// A service held by a [`ServiceCache`].
+///
+/// [`ServiceCache`]: struct.ServiceCache.html
+#[derive(Debug, Getters, Clone, PartialEq, Eq)]
+pub struct CachedService {
+    discovery: ServiceDiscovery,
+    /// Monotonic counter that is bumped every time a restart of the service is detected
+    incarnation: u64,
+    /// Whether the interface the service was last seen on is still available
+    reachability: Reachability,
+    #[getter(skip)]
+    expiry: Option<Expiry>,
+    #[getter(skip)]
+    degraded_at: Option<Instant>,
+    #[getter(skip)]
+    confirm_by: Option<Instant>,
+}
+
+/// Describes whether a [`CachedService`] can still be reached on the interface it was discovered
+/// on.
+///
+/// [`CachedService`]: struct.CachedService.html
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Reachability {
+    /// The service's source interface is up
+    Reachable,
+    /// The service's source interface went down. The service is kept until it is seen again or
+    /// the cache's grace period elapses, whichever comes first.
+    Degraded,
+}
+
+/// A change in the state of a local network interface, as reported by the platform's interface
+/// watcher.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InterfaceChange {
+    /// The interface has gone down or been removed
+    Down(NetworkInterface),
+    /// The interface has come (back) up
+    Up(NetworkInterface),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Expiry {
+    observed_at: Instant,
+    ttl: Duration,
+}
+
+impl Expiry {
+    fn expires_at(&self) -> Instant {
+        self.observed_at + self.ttl
+    }
+
+    fn refresh_at(&self) -> Instant {
+        self.observed_at + self.ttl.mul_f64(REFRESH_FRACTION)
+    }
+}
+
+/// An update emitted by a [`ServiceCache`] after it has processed an observation.
+///
+/// By the time an event is returned or delivered to a [`CacheEventCallback`], the cache already
+/// reflects it.
+///
+/// [`ServiceCache`]: struct.ServiceCache.html
+/// [`CacheEventCallback`]: type.CacheEventCallback.html
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CacheEvent {
+    /// A service that was not previously known (or was removed) is now present
+    Added(CachedService),
+    /// A known service has changed without a detected restart
+    Updated(CachedService),
+    /// A known service has been removed
+    Removed(CachedService),
+    /// A known service has restarted and is now on a new incarnation. This is emitted in place of
+    /// `Added` when a removed service returns as a new incarnation.
+    IncarnationChanged {
+        /// The service as it appears on its new incarnation
+        service: CachedService,
+        /// The incarnation prior to the restart
+        previous: u64,
+    },
+}
+
+/// Callback invoked by a [`ServiceCache`] for every [`CacheEvent`] it emits.
+///
+/// # Arguments
+/// * `event` - The event being delivered
+/// * `cache` - The cache that emitted the event, already updated to reflect it
+///
+/// [`ServiceCache`]: struct.ServiceCache.html
+/// [`CacheEvent`]: enum.CacheEvent.html
+pub type CacheEventCallback = dyn Fn(&CacheEvent, &ServiceCache) + Send;
+
+#[derive(Debug)]
+struct Entry {
+    service: CachedService,
+    goodbye_seen: bool,
+    expired: bool,
+}
+
+impl Entry {
+    fn is_present(&self) -> bool {
+        !self.goodbye_seen && !self.expired
+    }
+
+    fn is_on(&self, interface: NetworkInterface) -> bool {
+        interface != NetworkInterface::Unspec && self.service.discovery.interface() == &interface
+    }
+}
+
+/// Keeps track of the services known to a browser, including a best-effort incarnation number
+/// for each one that can be used to detect when the same service has restarted.
+///
+/// The cache does not talk to the mDNS implementation itself. Instead, it is fed the results of
+/// browsing and resolution and returns the [`CacheEvent`]s that follow from them. Services that
+/// have been removed are remembered so that their incarnation survives a restart. Every browser
+/// feeds one with the services it reports and with the changes to the network interfaces of the
+/// local machine, which is returned by [`TMdnsBrowser::known_services()`].
+///
+/// Services whose source interface goes down are marked [`Degraded`] (see
+/// [`interface_changed()`]) and expired by [`expire()`] if they are not seen again within the
+/// grace period. Likewise, services that are not resolved again within the deadline passed to
+/// [`rescan_started()`] are expired, which finds services that went away without a goodbye.
+///
+/// # Ordering
+/// Every method that emits events applies all of its changes to the cache before any of them is
+/// returned or passed to the [`CacheEventCallback`]. A callback may therefore query the cache
+/// while handling an event and will always find it in a state that includes that event:
+///
+/// * For `Added`, `Updated` and `IncarnationChanged`, the service is present with the contents
+///   carried by the event.
+/// * For `Removed`, including services expired by [`expire()`], the service is no longer
+///   present.
+///
+/// When a single call emits several events (e.g. [`interface_changed()`]), the cache reflects all
+/// of them by the time the first one is delivered.
+///
+/// [`CacheEvent`]: enum.CacheEvent.html
+/// [`CacheEventCallback`]: type.CacheEventCallback.html
+/// [`Degraded`]: enum.Reachability.html#variant.Degraded
+/// [`interface_changed()`]: #method.interface_changed
+/// [`expire()`]: #method.expire
+/// [`rescan_started()`]: #method.rescan_started
+/// [`TMdnsBrowser::known_services()`]: ../browser/trait.TMdnsBrowser.html#tymethod.known_services
+pub struct ServiceCache {
+    policy: IncarnationPolicy,
+    grace_period: Duration,
+    entries: HashMap<ServiceKey, Entry>,
+    event_callback: Option<Box<CacheEventCallback>>,
+}
+
+// Can't be `derive`d because of event_callback
+impl fmt::Debug for ServiceCache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ServiceCache")
+            .field("policy", &self.policy)
+            .field("grace_period", &self.grace_period)
+            .field("entries", &self.entries)
+            .finish()
+    }
+}
+
+impl Default for ServiceCache {
+    fn default() -> Self {
+        Self::new(IncarnationPolicy::default())
+    }
+}
+
+impl ServiceCache {
+    /// Creates a new empty `ServiceCache` with the specified [`IncarnationPolicy`]
+    ///
+    /// [`IncarnationPolicy`]: struct.IncarnationPolicy.html
+    pub fn new(policy: IncarnationPolicy) -> Self {
+        Self {
+            policy,
+            grace_period: DEFAULT_GRACE_PERIOD,
+            entries: HashMap::new(),
+            event_callback: None,
+        }
+    }
+
+    /// Sets the [`CacheEventCallback`] that is invoked for every event emitted by this cache, in
+    /// addition to the event being returned.
+    ///
+    /// [`CacheEventCallback`]: type.CacheEventCallback.html
+    pub fn set_event_callback(&mut self, event_callback: Box<CacheEventCallback>) {
+        self.event_callback = Some(event_callback);
+    }
+
+    /// Sets how long a [`Degraded`] service is kept before [`expire()`] removes it. Defaults to
+    /// [`DEFAULT_GRACE_PERIOD`].
+    ///
+    /// [`Degraded`]: enum.Reachability.html#variant.Degraded
+    /// [`expire()`]: #method.expire
+    /// [`DEFAULT_GRACE_PERIOD`]: constant.DEFAULT_GRACE_PERIOD.html
+    pub fn set_grace_period(&mut self, grace_period: Duration) {
+        self.grace_period = grace_period;
+    }
+
+    /// Returns how long a degraded service is kept before it is expired.
+    pub fn grace_period(&self) -> Duration {
+        self.grace_period
+    }
+
+    /// Returns the [`IncarnationPolicy`] used by this cache.
+    ///
+    /// [`IncarnationPolicy`]: struct.IncarnationPolicy.html
+    pub fn policy(&self) -> &IncarnationPolicy {
+        &self.policy
+    }
+
+    /// Records that `discovery` has been resolved at `now`. The `ttl` is the remaining
+    /// time-to-live reported for the service's records, if known.
+    pub fn resolved(
+        &mut self,
+        discovery: ServiceDiscovery,
+        ttl: Option<Duration>,
+        now: Instant,
+    ) -> Option<CacheEvent> {
+        let event = self.apply_resolved(discovery, ttl, now);
+        self.notify(event.as_slice());
+        event
+    }
+
+    fn apply_resolved(
+        &mut self,
+        discovery: ServiceDiscovery,
+        ttl: Option<Duration>,
+        now: Instant,
+    ) -> Option<CacheEvent> {
+        let key = discovery.key();
+
+        let expiry = ttl.map(|ttl| Expiry {
+            observed_at: now,
+            ttl,
+        });
+
+        let entry = match self.entries.get_mut(&key) {
+            Some(entry) => entry,
+            None => {
+                let service = CachedService {
+                    discovery,
+                    incarnation: 0,
+                    reachability: Reachability::Reachable,
+                    expiry,
+                    degraded_at: None,
+                    confirm_by: None,
+                };
+
+                self.entries.insert(
+                    key,
+                    Entry {
+                        service: service.clone(),
+                        goodbye_seen: false,
+                        expired: false,
+                    },
+                );
+
+                return Some(CacheEvent::Added(service));
+            }
+        };
+
+        let policy = &self.policy;
+        let previous = entry.service.incarnation;
+        let restarted = (entry.goodbye_seen && policy.goodbye)
+            || policy.txt_key_changed(&entry.service.discovery, &discovery)
+            || (!entry.goodbye_seen && policy.ttl_was_reset(entry.service.expiry, expiry));
+
+        let changed = entry.service.discovery != discovery
+            || entry.service.reachability == Reachability::Degraded;
+        let returned = !entry.is_present();
+
+        if restarted {
+            entry.service.incarnation += 1;
+        }
+
+        entry.service.discovery = discovery;
+        entry.service.expiry = expiry.or(entry.service.expiry);
+        entry.service.reachability = Reachability::Reachable;
+        entry.service.degraded_at = None;
+        entry.service.confirm_by = None;
+        entry.goodbye_seen = false;
+        entry.expired = false;
+
+        let service = entry.service.clone();
+
+        if restarted {
+            debug!(
+                "service {:?} restarted, incarnation {} -> {}",
+                service.discovery.name(),
+                previous,
+                service.incarnation
+            );
+            Some(CacheEvent::IncarnationChanged { service, previous })
+        } else if returned {
+            Some(CacheEvent::Added(service))
+        } else if changed {
+            Some(CacheEvent::Updated(service))
+        } else {
+            None
+        }
+    }
+
+    /// Records that the service has been removed (i.e. a goodbye has been received). Returns
+    /// `None` if the service was not known.
+    pub fn removed(
+        &mut self,
+        name: &str,
+        service_type: &ServiceType,
+        domain: &str,
+    ) -> Option<CacheEvent> {
+        let event = self.apply_removed(name, service_type, domain);
+        self.notify(event.as_slice());
+        event
+    }
+
+    fn apply_removed(
+        &mut self,
+        name: &str,
+        service_type: &ServiceType,
+        domain: &str,
+    ) -> Option<CacheEvent> {
+        let entry = self
+            .entries
+            .get_mut(&ServiceKey::new(name, service_type, domain))?;
+
+        if !entry.is_present() {
+            return None;
+        }
+
+        entry.goodbye_seen = true;
+        entry.service.expiry = None;
+
+        Some(CacheEvent::Removed(entry.service.clone()))
+    }
+
+    /// Records a change in the state of a local network interface at `now`.
+    ///
+    /// When an interface goes down, every present service that was resolved on it is marked
+    /// [`Degraded`]. When it comes back up, those services are restored to [`Reachable`]. An
+    /// `Updated` event is returned for each service whose reachability changed.
+    ///
+    /// Services resolved on `NetworkInterface::Unspec` have no known source interface and are
+    /// never affected.
+    ///
+    /// [`Degraded`]: enum.Reachability.html#variant.Degraded
+    /// [`Reachable`]: enum.Reachability.html#variant.Reachable
+    pub fn interface_changed(&mut self, change: InterfaceChange, now: Instant) -> Vec<CacheEvent> {
+        let (interface, from, to) = match change {
+            InterfaceChange::Down(i) => (i, Reachability::Reachable, Reachability::Degraded),
+            InterfaceChange::Up(i) => (i, Reachability::Degraded, Reachability::Reachable),
+        };
+
+        let events = self
+            .entries
+            .values_mut()
+            .filter(|e| e.is_present() && e.is_on(interface))
+            .filter(|e| e.service.reachability == from)
+            .map(|e| {
+                e.service.reachability = to;
+                e.service.degraded_at = match to {
+                    Reachability::Degraded => Some(now),
+                    Reachability::Reachable => None,
+                };
+
+                CacheEvent::Updated(e.service.clone())
+            })
+            .collect::<Vec<_>>();
+
+        self.notify(&events);
+
+        events
+    }
+
+    /// Records that the browser has been asked to rescan at `now` (see
+    /// [`TMdnsBrowser::rescan()`]). Every present service that is not resolved again within
+    /// `deadline` is removed by the first call to [`expire()`] after the deadline.
+    ///
+    /// Does not emit any events.
+    ///
+    /// [`TMdnsBrowser::rescan()`]: ../browser/trait.TMdnsBrowser.html#tymethod.rescan
+    /// [`expire()`]: #method.expire
+    pub fn rescan_started(&mut self, deadline: Duration, now: Instant) {
+        for entry in self.entries.values_mut().filter(|e| e.is_present()) {
+            entry.service.confirm_by = Some(now + deadline);
+        }
+    }
+
+    /// Removes every service that has been [`Degraded`] for longer than the grace period or that
+    /// has not been confirmed by the deadline of a rescan, and returns a `Removed` event for each
+    /// one.
+    ///
+    /// Unlike a goodbye, expiry is not evidence of a restart, so a service that is seen again
+    /// after expiring keeps its incarnation.
+    ///
+    /// [`Degraded`]: enum.Reachability.html#variant.Degraded
+    pub fn expire(&mut self, now: Instant) -> Vec<CacheEvent> {
+        let grace_period = self.grace_period;
+
+        let events = self
+            .entries
+            .values_mut()
+            .filter(|e| e.is_present())
+            .filter(|e| {
+                let degraded = match e.service.degraded_at {
+                    Some(degraded_at) => now >= degraded_at + grace_period,
+                    None => false,
+                };
+
+                let unconfirmed = match e.service.confirm_by {
+                    Some(confirm_by) => now >= confirm_by,
+                    None => false,
+                };
+
+                degraded || unconfirmed
+            })
+            .map(|e| {
+                debug!("service {:?} expired", e.service.discovery.name());
+                e.expired = true;
+                e.service.expiry = None;
+                e.service.degraded_at = None;
+                e.service.confirm_by = None;
+                CacheEvent::Removed(e.service.clone())
+            })
+            .collect::<Vec<_>>();
+
+        self.notify(&events);
+
+        events
+    }
+
+    /// Returns the service with the specified name, type and domain if it is currently present.
+    pub fn get(
+        &self,
+        name: &str,
+        service_type: &ServiceType,
+        domain: &str,
+    ) -> Option<&CachedService> {
+        self.entries
+            .get(&ServiceKey::new(name, service_type, domain))
+            .filter(|e| e.is_present())
+            .map(|e| &e.service)
+    }
+
+    /// Returns an iterator over the services that are currently present.
+    pub fn iter(&self) -> impl Iterator<Item = &CachedService> {
+        self.entries
+            .values()
+            .filter(|e| e.is_present())
+            .map(|e| &e.service)
+    }
+
+    /// Returns the number of services that are currently present.
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// Returns true if there are no services currently present.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Forgets every removed or expired service, including its incarnation.
+    pub fn purge_removed(&mut self) {
+        self.entries.retain(|_, e| e.is_present());
+    }
+
+    /// Records that a browser has reported `discovery` to its callback at `now`, with the TTL the
+    /// discovery carries.
+    pub(crate) fn reported(&mut self, discovery: &ServiceDiscovery, now: Instant) {
+        let ttl = discovery.ttl().map(|ttl| Duration::from_secs(ttl.into()));
+        self.resolved(discovery.clone(), ttl, now);
+    }
+
+    /// Records that a browser has reported the removal of the service identified by `key`.
+    pub(crate) fn reported_removed(&mut self, key: &ServiceKey) {
+        self.removed(key.name(), key.service_type(), key.domain());
+    }
+
+    /// Expires the services like [`expire()`] and returns the keys of the services a browser has
+    /// to report as removed.
+    ///
+    /// [`expire()`]: #method.expire
+    pub(crate) fn expired(&mut self, now: Instant) -> Vec<ServiceKey> {
+        self.expire(now)
+            .iter()
+            .filter_map(|event| match event {
+                CacheEvent::Removed(service) => Some(service.discovery.key()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Records `changes` like [`interface_changed()`] and returns the
+    /// `BrowserEvent::ServiceReachabilityChanged` events a browser has to report for them.
+    ///
+    /// [`interface_changed()`]: #method.interface_changed
+    pub(crate) fn interfaces_changed(
+        &mut self,
+        changes: Vec<InterfaceChange>,
+        now: Instant,
+    ) -> Vec<BrowserEvent> {
+        changes
+            .into_iter()
+            .flat_map(|change| self.interface_changed(change, now))
+            .filter_map(|event| match event {
+                CacheEvent::Updated(service) => Some(BrowserEvent::ServiceReachabilityChanged {
+                    key: service.discovery.key(),
+                    reachability: service.reachability,
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Forgets every service, keeping the policy, grace period and event callback of the cache.
+    pub(crate) fn reset(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Returns the services that are currently present, ordered by their [`ServiceKey`].
+    ///
+    /// [`ServiceKey`]: ../struct.ServiceKey.html
+    pub(crate) fn snapshot(&self) -> Vec<CachedService> {
+        let mut services = self.iter().cloned().collect::<Vec<_>>();
+        services.sort_by_key(|s| s.discovery.key());
+        services
+    }
+
+    fn notify(&self, events: &[CacheEvent]) {
+        if let Some(f) = &self.event_callback {
+            for event in events {
+                f(event, self);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::{Clock, MockClock};
+    use crate::tests::{discovery_builder, service_type};
+    use crate::TxtRecord;
+    use std::mem;
+    use std::sync::{Arc, Mutex};
+
+    const ETH0: NetworkInterface = NetworkInterface::AtIndex(2);
+    const WLAN0: NetworkInterface = NetworkInterface::AtIndex(3);
+
+    fn discovery(txt: Option<TxtRecord>) -> ServiceDiscovery {
+        discovery_on(ETH0, txt)
+    }
+
+    fn discovery_on(interface: NetworkInterface, txt: Option<TxtRecord>) -> ServiceDiscovery {
+        discovery_named("foo", interface, txt)
+    }
+
+    fn discovery_named(
+        name: &str,
+        interface: NetworkInterface,
+        txt: Option<TxtRecord>,
+    ) -> ServiceDiscovery {
+        discovery_builder(name)
+            .txt(txt)
+            .interface(interface)
+            .build()
+            .unwrap()
+    }
+
+    fn reachability(cache: &ServiceCache) -> Option<Reachability> {
+        cache
+            .get("foo", &service_type(), "local")
+            .map(|s| *s.reachability())
+    }
+
+    fn txt(key: &str, value: &str) -> Option<TxtRecord> {
+        Some(hashmap! { key => value }.into())
+    }
+
+    fn incarnation(event: Option<CacheEvent>) -> u64 {
+        *event_service(&event.expect("expected event")).incarnation()
+    }
+
+    fn event_service(event: &CacheEvent) -> &CachedService {
+        match event {
+            CacheEvent::Added(s)
+            | CacheEvent::Updated(s)
+            | CacheEvent::Removed(s)
+            | CacheEvent::IncarnationChanged { service: s, .. } => s,
+        }
+    }
+
+    /// Each delivered event along with a snapshot of every present service, taken from inside
+    /// the callback
+    type Observations = Arc<Mutex<Vec<(CacheEvent, Vec<CachedService>)>>>;
+
+    fn observe(cache: &mut ServiceCache) -> Observations {
+        let observations = Observations::default();
+        let sink = observations.clone();
+
+        cache.set_event_callback(Box::new(move |event, cache| {
+            let mut present = cache.iter().cloned().collect::<Vec<_>>();
+            present.sort_by(|a, b| a.discovery().name().cmp(b.discovery().name()));
+            sink.lock().unwrap().push((event.clone(), present));
+        }));
+
+        observations
+    }
+
+    fn observed_single(observations: &Observations) -> (CacheEvent, Vec<CachedService>) {
+        let mut observations = observations.lock().unwrap();
+        assert_eq!(observations.len(), 1);
+        observations.pop().unwrap()
+    }
+
+    #[test]
+    fn resolved_new_service_is_added_at_incarnation_zero() {
+        let mut cache = ServiceCache::default();
+        let event = cache.resolved(discovery(None), None, Instant::now());
+
+        assert!(matches!(event, Some(CacheEvent::Added(_))));
+        assert_eq!(incarnation(event), 0);
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn resolved_unchanged_service_emits_nothing() {
+        let mut cache = ServiceCache::default();
+        let now = Instant::now();
+
+        cache.resolved(discovery(None), None, now);
+
+        assert_eq!(cache.resolved(discovery(None), None, now), None);
+    }
+
+    #[test]
+    fn clean_restart_with_goodbye_bumps_incarnation() {
+        let mut cache = ServiceCache::default();
+        let now = Instant::now();
+
+        cache.resolved(discovery(None), None, now);
+
+        let removed = cache.removed("foo", &service_type(), "local");
+        assert!(matches!(removed, Some(CacheEvent::Removed(_))));
+        assert!(cache.is_empty());
+
+        let event = cache.resolved(discovery(None), None, now);
+        assert!(matches!(
+            event,
+            Some(CacheEvent::IncarnationChanged { previous: 0, .. })
+        ));
+        assert_eq!(incarnation(event), 1);
+    }
+
+    #[test]
+    fn goodbye_ignored_if_disabled() {
+        let policy = IncarnationPolicy::builder().goodbye(false).build().unwrap();
+        let mut cache = ServiceCache::new(policy);
+        let now = Instant::now();
+
+        cache.resolved(discovery(None), None, now);
+        cache.removed("foo", &service_type(), "local");
+
+        let event = cache.resolved(discovery(None), None, now);
+        assert!(matches!(event, Some(CacheEvent::Added(_))));
+        assert_eq!(incarnation(event), 0);
+    }
+
+    #[test]
+    fn removed_unknown_service_returns_none() {
+        let mut cache = ServiceCache::default();
+        assert_eq!(cache.removed("foo", &service_type(), "local"), None);
+    }
+
+    #[test]
+    fn silent_restart_detected_by_ttl_reset() {
+        let policy = IncarnationPolicy::builder()
+            .ttl_reset(true)
+            .build()
+            .unwrap();
+        let mut cache = ServiceCache::new(policy);
+        let clock = MockClock::new();
+        let ttl = Some(Duration::from_secs(120));
+
+        cache.resolved(discovery(None), ttl, clock.now());
+        clock.advance(Duration::from_secs(30));
+
+        let event = cache.resolved(discovery(None), ttl, clock.now());
+
+        assert_eq!(
+            event,
+            Some(CacheEvent::IncarnationChanged {
+                service: cache.get("foo", &service_type(), "local").unwrap().clone(),
+                previous: 0,
+            })
+        );
+    }
+
+    #[test]
+    fn ttl_reset_ignored_if_disabled() {
+        let mut cache = ServiceCache::default();
+        let clock = MockClock::new();
+        let ttl = Some(Duration::from_secs(120));
+
+        cache.resolved(discovery(None), ttl, clock.now());
+        clock.advance(Duration::from_secs(30));
+
+        assert_eq!(cache.resolved(discovery(None), ttl, clock.now()), None);
+    }
+
+    #[test]
+    fn ttl_refresh_after_refresh_point_is_not_a_restart() {
+        let policy = IncarnationPolicy::builder()
+            .ttl_reset(true)
+            .build()
+            .unwrap();
+        let mut cache = ServiceCache::new(policy);
+        let clock = MockClock::new();
+        let ttl = Some(Duration::from_secs(120));
+
+        cache.resolved(discovery(None), ttl, clock.now());
+        clock.advance(Duration::from_secs(100));
+
+        assert_eq!(cache.resolved(discovery(None), ttl, clock.now()), None);
+    }
+
+    #[test]
+    fn decaying_ttl_is_not_a_restart() {
+        let policy = IncarnationPolicy::builder()
+            .ttl_reset(true)
+            .build()
+            .unwrap();
+        let mut cache = ServiceCache::new(policy);
+        let clock = MockClock::new();
+
+        cache.resolved(discovery(None), Some(Duration::from_secs(120)), clock.now());
+        clock.advance(Duration::from_secs(30));
+
+        let event = cache.resolved(discovery(None), Some(Duration::from_secs(90)), clock.now());
+
+        assert_eq!(event, None);
+    }
+
+    #[test]
+    fn ttl_rounding_is_not_a_restart() {
+        let policy = IncarnationPolicy::builder()
+            .ttl_reset(true)
+            .build()
+            .unwrap();
+        let mut cache = ServiceCache::new(policy);
+        let clock = MockClock::new();
+
+        cache.resolved(discovery(None), Some(Duration::from_secs(120)), clock.now());
+        clock.advance(Duration::from_millis(29_500));
+
+        let event = cache.resolved(discovery(None), Some(Duration::from_secs(91)), clock.now());
+
+        assert_eq!(event, None);
+    }
+
+    #[test]
+    fn txt_seq_bump_detected() {
+        let policy = IncarnationPolicy::builder()
+            .txt_keys(vec!["seq".to_string()])
+            .build()
+            .unwrap();
+
+        let mut cache = ServiceCache::new(policy);
+        let now = Instant::now();
+
+        cache.resolved(discovery(txt("seq", "1")), None, now);
+
+        let event = cache.resolved(discovery(txt("seq", "2")), None, now);
+        assert!(matches!(
+            event,
+            Some(CacheEvent::IncarnationChanged { previous: 0, .. })
+        ));
+        assert_eq!(incarnation(event), 1);
+    }
+
+    #[test]
+    fn txt_change_without_hint_is_update() {
+        let mut cache = ServiceCache::default();
+        let now = Instant::now();
+
+        cache.resolved(discovery(txt("seq", "1")), None, now);
+
+        let event = cache.resolved(discovery(txt("seq", "2")), None, now);
+        assert!(matches!(event, Some(CacheEvent::Updated(_))));
+        assert_eq!(incarnation(event), 0);
+    }
+
+    #[test]
+    fn txt_hint_missing_key_is_not_a_restart() {
+        let policy = IncarnationPolicy::builder()
+            .txt_keys(vec!["boot".to_string()])
+            .build()
+            .unwrap();
+
+        let mut cache = ServiceCache::new(policy);
+        let now = Instant::now();
+
+        cache.resolved(discovery(txt("boot", "abc")), None, now);
+
+        let event = cache.resolved(discovery(txt("other", "x")), None, now);
+        assert_eq!(incarnation(event), 0);
+    }
+
+    #[test]
+    fn incarnation_is_monotonic_across_restarts() {
+        let mut cache = ServiceCache::default();
+        let now = Instant::now();
+
+        cache.resolved(discovery(None), None, now);
+
+        for expected in 1..=3 {
+            cache.removed("foo", &service_type(), "local");
+            assert_eq!(
+                incarnation(cache.resolved(discovery(None), None, now)),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn purge_removed_forgets_incarnation() {
+        let mut cache = ServiceCache::default();
+        let now = Instant::now();
+
+        cache.resolved(discovery(None), None, now);
+        cache.removed("foo", &service_type(), "local");
+        cache.purge_removed();
+
+        assert_eq!(incarnation(cache.resolved(discovery(None), None, now)), 0);
+    }
+
+    #[test]
+    fn interface_down_degrades_services_on_interface() {
+        let mut cache = ServiceCache::default();
+        let now = Instant::now();
+
+        cache.resolved(discovery(None), None, now);
+
+        let events = cache.interface_changed(InterfaceChange::Down(ETH0), now);
+
+        assert_eq!(events.len(), 1);
+        assert!(matches!(events[0], CacheEvent::Updated(_)));
+        assert_eq!(reachability(&cache), Some(Reachability::Degraded));
+    }
+
+    #[test]
+    fn interface_down_ignores_other_interfaces() {
+        let mut cache = ServiceCache::default();
+        let now = Instant::now();
+
+        cache.resolved(discovery(None), None, now);
+
+        assert!(cache
+            .interface_changed(InterfaceChange::Down(WLAN0), now)
+            .is_empty());
+        assert_eq!(reachability(&cache), Some(Reachability::Reachable));
+    }
+
+    #[test]
+    fn interface_down_ignores_unspec_services() {
+        let mut cache = ServiceCache::default();
+        let now = Instant::now();
+
+        cache.resolved(discovery_on(NetworkInterface::Unspec, None), None, now);
+
+        assert!(cache
+            .interface_changed(InterfaceChange::Down(ETH0), now)
+            .is_empty());
+    }
+
+    #[test]
+    fn interface_up_restores_degraded_services() {
+        let mut cache = ServiceCache::default();
+        let now = Instant::now();
+
+        cache.resolved(discovery(None), None, now);
+        cache.interface_changed(InterfaceChange::Down(ETH0), now);
+
+        let events = cache.interface_changed(InterfaceChange::Up(ETH0), now);
+
+        assert_eq!(events.len(), 1);
+        assert!(matches!(events[0], CacheEvent::Updated(_)));
+        assert_eq!(reachability(&cache), Some(Reachability::Reachable));
+        assert!(cache.expire(now + DEFAULT_GRACE_PERIOD).is_empty());
+    }
+
+    #[test]
+    fn repeated_interface_down_emits_once() {
+        let mut cache = ServiceCache::default();
+        let now = Instant::now();
+
+        cache.resolved(discovery(None), None, now);
+        cache.interface_changed(InterfaceChange::Down(ETH0), now);
+
+        assert!(cache
+            .interface_changed(InterfaceChange::Down(ETH0), now)
+            .is_empty());
+    }
+
+    #[test]
+    fn degraded_service_seen_elsewhere_is_restored() {
+        let mut cache = ServiceCache::default();
+        let now = Instant::now();
+
+        cache.resolved(discovery(None), None, now);
+        cache.interface_changed(InterfaceChange::Down(ETH0), now);
+
+        let event = cache.resolved(discovery_on(WLAN0, None), None, now);
+
+        assert!(matches!(event, Some(CacheEvent::Updated(_))));
+        assert_eq!(incarnation(event), 0);
+        assert_eq!(reachability(&cache), Some(Reachability::Reachable));
+        assert!(cache.expire(now + DEFAULT_GRACE_PERIOD).is_empty());
+    }
+
+    #[test]
+    fn degraded_service_re_seen_on_same_interface_is_restored() {
+        let mut cache = ServiceCache::default();
+        let now = Instant::now();
+
+        cache.resolved(discovery(None), None, now);
+        cache.interface_changed(InterfaceChange::Down(ETH0), now);
+
+        let event = cache.resolved(discovery(None), None, now);
+
+        assert!(matches!(event, Some(CacheEvent::Updated(_))));
+        assert_eq!(reachability(&cache), Some(Reachability::Reachable));
+    }
+
+    #[test]
+    fn degraded_service_expires_after_grace_period() {
+        let mut cache = ServiceCache::default();
+        cache.set_grace_period(Duration::from_secs(10));
+        let clock = MockClock::new();
+
+        cache.resolved(discovery(None), None, clock.now());
+        cache.interface_changed(InterfaceChange::Down(ETH0), clock.now());
+
+        clock.advance(Duration::from_secs(9));
+        assert!(cache.expire(clock.now()).is_empty());
+        assert_eq!(cache.len(), 1);
+
+        clock.advance(Duration::from_secs(1));
+        let events = cache.expire(clock.now());
+
+        assert_eq!(events.len(), 1);
+        assert!(matches!(events[0], CacheEvent::Removed(_)));
+        assert!(cache.is_empty());
+
+        clock.advance(Duration::from_secs(10));
+        assert!(cache.expire(clock.now()).is_empty());
+    }
+
+    #[test]
+    fn expired_service_returns_on_same_incarnation() {
+        let mut cache = ServiceCache::default();
+        let clock = MockClock::new();
+
+        cache.resolved(discovery(None), None, clock.now());
+        cache.interface_changed(InterfaceChange::Down(ETH0), clock.now());
+
+        clock.advance(DEFAULT_GRACE_PERIOD);
+        cache.expire(clock.now());
+
+        let event = cache.resolved(discovery(None), None, clock.now());
+
+        assert!(matches!(event, Some(CacheEvent::Added(_))));
+        assert_eq!(incarnation(event), 0);
+        assert_eq!(reachability(&cache), Some(Reachability::Reachable));
+    }
+
+    #[test]
+    fn silently_dead_service_expires_after_rescan_deadline() {
+        let mut cache = ServiceCache::default();
+        let clock = MockClock::new();
+
+        cache.resolved(discovery_named("alive", ETH0, None), None, clock.now());
+        cache.resolved(discovery_named("dead", ETH0, None), None, clock.now());
+        cache.rescan_started(Duration::from_secs(5), clock.now());
+
+        clock.advance(Duration::from_secs(2));
+        let event = cache.resolved(discovery_named("alive", ETH0, None), None, clock.now());
+
+        assert_eq!(event, None);
+
+        clock.advance(Duration::from_secs(2));
+        assert!(cache.expire(clock.now()).is_empty());
+        assert_eq!(cache.len(), 2);
+
+        clock.advance(Duration::from_secs(1));
+        let events = cache.expire(clock.now());
+
+        assert_eq!(events.len(), 1);
+        assert!(matches!(&events[0], CacheEvent::Removed(s) if s.discovery().name() == "dead"));
+        assert!(cache.get("alive", &service_type(), "local").is_some());
+
+        clock.advance(Duration::from_secs(10));
+        assert!(cache.expire(clock.now()).is_empty());
+    }
+
+    #[test]
+    fn service_added_after_rescan_is_not_expired() {
+        let mut cache = ServiceCache::default();
+        let clock = MockClock::new();
+
+        cache.rescan_started(Duration::from_secs(5), clock.now());
+        cache.resolved(discovery(None), None, clock.now());
+
+        clock.advance(Duration::from_secs(5));
+
+        assert!(cache.expire(clock.now()).is_empty());
+        assert_eq!(cache.len(), 1);
+    }
+
+    #[test]
+    fn service_expired_by_rescan_returns_on_same_incarnation() {
+        let mut cache = ServiceCache::default();
+        let clock = MockClock::new();
+
+        cache.resolved(discovery(None), None, clock.now());
+        cache.rescan_started(Duration::from_secs(5), clock.now());
+
+        clock.advance(Duration::from_secs(5));
+        cache.expire(clock.now());
+
+        let event = cache.resolved(discovery(None), None, clock.now());
+
+        assert!(matches!(event, Some(CacheEvent::Added(_))));
+        assert_eq!(incarnation(event), 0);
+    }
+
+    #[test]
+    fn removed_service_is_not_degraded() {
+        let mut cache = ServiceCache::default();
+        let now = Instant::now();
+
+        cache.resolved(discovery(None), None, now);
+        cache.removed("foo", &service_type(), "local");
+
+        assert!(cache
+            .interface_changed(InterfaceChange::Down(ETH0), now)
+            .is_empty());
+    }
+
+    #[test]
+    fn callback_sees_added_service() {
+        let mut cache = ServiceCache::default();
+        let observations = observe(&mut cache);
+
+        let event = cache.resolved(discovery(None), None, Instant::now());
+        let (observed, present) = observed_single(&observations);
+
+        assert_eq!(Some(&observed), event.as_ref());
+        assert_eq!(present, vec![event_service(&observed).clone()]);
+    }
+
+    #[test]
+    fn callback_sees_updated_service() {
+        let mut cache = ServiceCache::default();
+        let now = Instant::now();
+
+        cache.resolved(discovery(txt("foo", "bar")), None, now);
+
+        let observations = observe(&mut cache);
+        cache.resolved(discovery(txt("foo", "baz")), None, now);
+
+        let (observed, present) = observed_single(&observations);
+
+        assert!(matches!(observed, CacheEvent::Updated(_)));
+        assert_eq!(present, vec![event_service(&observed).clone()]);
+        assert_eq!(present[0].discovery().txt(), txt("foo", "baz").as_ref());
+    }
+
+    #[test]
+    fn callback_sees_new_incarnation() {
+        let mut cache = ServiceCache::default();
+        let now = Instant::now();
+
+        cache.resolved(discovery(None), None, now);
+        cache.removed("foo", &service_type(), "local");
+
+        let observations = observe(&mut cache);
+        cache.resolved(discovery(None), None, now);
+
+        let (observed, present) = observed_single(&observations);
+
+        assert!(matches!(observed, CacheEvent::IncarnationChanged { .. }));
+        assert_eq!(present.len(), 1);
+        assert_eq!(*present[0].incarnation(), 1);
+    }
+
+    #[test]
+    fn callback_does_not_see_removed_service() {
+        let mut cache = ServiceCache::default();
+        let now = Instant::now();
+
+        cache.resolved(discovery_named("foo", ETH0, None), None, now);
+        cache.resolved(discovery_named("bar", ETH0, None), None, now);
+
+        let observations = observe(&mut cache);
+        cache.removed("foo", &service_type(), "local");
+
+        let (observed, present) = observed_single(&observations);
+
+        assert!(matches!(observed, CacheEvent::Removed(_)));
+        assert_eq!(present.len(), 1);
+        assert_eq!(present[0].discovery().name(), "bar");
+    }
+
+    #[test]
+    fn callback_does_not_see_expired_service() {
+        let mut cache = ServiceCache::default();
+        let clock = MockClock::new();
+
+        cache.resolved(discovery_named("foo", ETH0, None), None, clock.now());
+        cache.resolved(discovery_named("bar", WLAN0, None), None, clock.now());
+        cache.interface_changed(InterfaceChange::Down(ETH0), clock.now());
+
+        let observations = observe(&mut cache);
+
+        clock.advance(DEFAULT_GRACE_PERIOD);
+        let events = cache.expire(clock.now());
+
+        let (observed, present) = observed_single(&observations);
+
+        assert_eq!(events, vec![observed.clone()]);
+        assert!(matches!(observed, CacheEvent::Removed(_)));
+        assert_eq!(present.len(), 1);
+        assert_eq!(present[0].discovery().name(), "bar");
+    }
+
+    #[test]
+    fn callback_sees_whole_expiry() {
+        let mut cache = ServiceCache::default();
+        let clock = MockClock::new();
+
+        cache.resolved(discovery_named("foo", ETH0, None), None, clock.now());
+        cache.resolved(discovery_named("bar", ETH0, None), None, clock.now());
+        cache.rescan_started(Duration::from_secs(5), clock.now());
+
+        let observations = observe(&mut cache);
+
+        clock.advance(Duration::from_secs(5));
+        let events = cache.expire(clock.now());
+        let observed = mem::take(&mut *observations.lock().unwrap());
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            observed.iter().map(|(e, _)| e.clone()).collect::<Vec<_>>(),
+            events
+        );
+
+        for (_, present) in observed {
+            assert!(present.is_empty());
+        }
+    }
+
+    #[test]
+    fn callback_sees_whole_interface_change() {
+        let mut cache = ServiceCache::default();
+        let now = Instant::now();
+
+        cache.resolved(discovery_named("foo", ETH0, None), None, now);
+        cache.resolved(discovery_named("bar", ETH0, None), None, now);
+
+        let observations = observe(&mut cache);
+
+        for change in [InterfaceChange::Down(ETH0), InterfaceChange::Up(ETH0)].iter() {
+            let expected = match change {
+                InterfaceChange::Down(_) => Reachability::Degraded,
+                InterfaceChange::Up(_) => Reachability::Reachable,
+            };
+
+            let events = cache.interface_changed(*change, now);
+            let observed = mem::take(&mut *observations.lock().unwrap());
+
+            assert_eq!(events.len(), 2);
+            assert_eq!(
+                observed.iter().map(|(e, _)| e.clone()).collect::<Vec<_>>(),
+                events
+            );
+
+            for (_, present) in observed {
+                assert_eq!(present.len(), 2);
+                assert!(present.iter().all(|s| *s.reachability() == expected));
+            }
+        }
+    }
+
+    #[test]
+    fn callback_not_invoked_without_event() {
+        let mut cache = ServiceCache::default();
+        let now = Instant::now();
+
+        cache.resolved(discovery(None), None, now);
+
+        let observations = observe(&mut cache);
+
+        cache.resolved(discovery(None), None, now);
+        cache.removed("bar", &service_type(), "local");
+
+        assert!(observations.lock().unwrap().is_empty());
+    }
+}
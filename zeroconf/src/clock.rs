@@ -0,0 +1,86 @@
+//! Source of time for time-dependent internals
+
+use std::fmt;
+use std::time::Instant;
+
+#[cfg(test)]
+use std::sync::Mutex;
+#[cfg(test)]
+use std::time::Duration;
+
+/// Source of the current time.
+///
+/// Internals that depend on time take a `Clock` instead of calling `Instant::now()` directly, so
+/// that tests can substitute a [`MockClock`] and control the passage of time.
+///
+/// [`MockClock`]: struct.MockClock.html
+pub(crate) trait Clock: fmt::Debug + Send + Sync {
+    /// Returns the current time.
+    fn now(&self) -> Instant;
+}
+
+/// [`Clock`] backed by the system's monotonic clock
+///
+/// [`Clock`]: trait.Clock.html
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// [`Clock`] that only advances when told to
+///
+/// [`Clock`]: trait.Clock.html
+#[cfg(test)]
+#[derive(Debug)]
+pub(crate) struct MockClock {
+    now: Mutex<Instant>,
+}
+
+#[cfg(test)]
+impl MockClock {
+    pub(crate) fn new() -> Self {
+        Self {
+            now: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Advances the clock by `duration`.
+    pub(crate) fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap() += duration;
+    }
+}
+
+#[cfg(test)]
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mock_clock_only_advances_when_told() {
+        let clock = MockClock::new();
+        let start = clock.now();
+
+        assert_eq!(clock.now(), start);
+
+        clock.advance(Duration::from_secs(5));
+        assert_eq!(clock.now(), start + Duration::from_secs(5));
+    }
+
+    #[test]
+    fn system_clock_is_monotonic() {
+        let clock = SystemClock;
+        let start = clock.now();
+
+        assert!(clock.now() >= start);
+    }
+}
@@ -0,0 +1,59 @@
+//! Typed access to the user context passed through to callbacks
+
+use std::any::{self, Any};
+use std::sync::Arc;
+
+/// Returns the user context passed through to a callback as a `T`, whether it was set as an
+/// `Arc<T>` with `set_context_typed()` or as a plain `T` with `set_context()`.
+///
+/// # Panics
+/// Panics if no context was set or if it is not a `T`.
+pub(crate) fn downcast<T: Any + Send + Sync>(context: Option<&(dyn Any + Send + Sync)>) -> &T {
+    let context = context.unwrap_or_else(|| {
+        panic!(
+            "expected a context of type `{}` but none was set",
+            any::type_name::<T>()
+        )
+    });
+
+    context
+        .downcast_ref::<Arc<T>>()
+        .map(Arc::as_ref)
+        .or_else(|| context.downcast_ref::<T>())
+        .unwrap_or_else(|| {
+            panic!(
+                "expected a context of type `{}` but a context of another type was set",
+                any::type_name::<T>()
+            )
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn downcast_returns_context_set_as_arc() {
+        let context: Box<dyn Any + Send + Sync> = Box::new(Arc::new(42_u32));
+        assert_eq!(downcast::<u32>(Some(context.as_ref())), &42);
+    }
+
+    #[test]
+    fn downcast_returns_context_set_as_value() {
+        let context: Box<dyn Any + Send + Sync> = Box::new(42_u32);
+        assert_eq!(downcast::<u32>(Some(context.as_ref())), &42);
+    }
+
+    #[test]
+    #[should_panic(expected = "expected a context of type `u32` but a context of another type")]
+    fn downcast_panics_on_mismatch() {
+        let context: Box<dyn Any + Send + Sync> = Box::new("foo");
+        downcast::<u32>(Some(context.as_ref()));
+    }
+
+    #[test]
+    #[should_panic(expected = "expected a context of type `u32` but none was set")]
+    fn downcast_panics_without_context() {
+        downcast::<u32>(None);
+    }
+}
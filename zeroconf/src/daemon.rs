@@ -0,0 +1,304 @@
+//! State of the connection to the mDNS daemon
+
+#[cfg(all(unix, not(feature = "test-mock")))]
+use crate::error::Error;
+use crate::Result;
+use std::any::Any;
+use std::fmt;
+#[cfg(all(unix, not(feature = "test-mock")))]
+use std::io;
+#[cfg(all(unix, not(feature = "test-mock")))]
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Returns true if the mDNS daemon is reachable, so that applications can degrade gracefully
+/// rather than fail when services are registered or browsed.
+///
+/// On Avahi, this checks that `avahi-daemon` is running, which it commonly is not in containers.
+/// On Bonjour, this connects to mDNSResponder or the Bonjour service on Windows. With the
+/// `test-mock` feature, this is always `true`.
+///
+/// Operations that fail because the daemon is not reachable return
+/// `Error::DaemonNotRunning`.
+pub fn is_available() -> bool {
+    #[cfg(all(target_os = "linux", not(feature = "test-mock")))]
+    return crate::avahi::client::is_available();
+    #[cfg(all(
+        any(target_vendor = "apple", target_vendor = "pc"),
+        not(feature = "test-mock")
+    ))]
+    return crate::bonjour::bonjour_util::is_available();
+    #[cfg(feature = "test-mock")]
+    return true;
+}
+
+/// Checks whether, and if not why, this process can talk to the mDNS daemon, e.g. to explain an
+/// `Error::DaemonNotRunning` or `Error::PermissionDenied` to the user.
+///
+/// On Avahi, the socket of the D-Bus system bus is checked. On macOS, the socket of mDNSResponder
+/// is checked. The Bonjour service on Windows and the `test-mock` feature have no socket to check.
+pub fn diagnose() -> Diagnosis {
+    #[cfg(all(target_os = "linux", not(feature = "test-mock")))]
+    return crate::avahi::client::diagnose();
+    #[cfg(all(
+        any(target_vendor = "apple", target_vendor = "pc"),
+        not(feature = "test-mock")
+    ))]
+    return crate::bonjour::bonjour_util::diagnose();
+    #[cfg(feature = "test-mock")]
+    return Diagnosis::new(None, Ok(()), Ok(()));
+}
+
+/// Report on the connection to the mDNS daemon, as returned by [`diagnose()`].
+///
+/// [`diagnose()`]: fn.diagnose.html
+#[derive(Debug, Clone, PartialEq, Eq, Getters, new)]
+pub struct Diagnosis {
+    /// The path of the socket that the daemon is talked to through, if it has one
+    socket_path: Option<PathBuf>,
+    /// Whether the socket exists and this process may read from and write to it
+    socket_access: Result<()>,
+    /// Whether a connection to the daemon could be established
+    connection: Result<()>,
+}
+
+impl Diagnosis {
+    /// Returns true if every check has passed.
+    pub fn is_ok(&self) -> bool {
+        self.socket_access.is_ok() && self.connection.is_ok()
+    }
+}
+
+impl fmt::Display for Diagnosis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn check(result: &Result<()>) -> String {
+            match result {
+                Ok(()) => "ok".to_string(),
+                Err(e) => e.to_string(),
+            }
+        }
+
+        match &self.socket_path {
+            Some(path) => writeln!(f, "socket: {}", path.display())?,
+            None => writeln!(f, "socket: none")?,
+        }
+
+        writeln!(f, "socket access: {}", check(&self.socket_access))?;
+        write!(f, "connection: {}", check(&self.connection))
+    }
+}
+
+/// Checks that the daemon socket at `path` exists and that this process may read from and write to
+/// it. The permissions are checked with `access()`, which takes the real user and group of the
+/// process into account.
+#[cfg(all(unix, not(feature = "test-mock")))]
+pub(crate) fn check_socket_access(path: &Path) -> Result<()> {
+    let c_path = c_string!(path.as_os_str().to_string_lossy().as_ref());
+
+    if unsafe { libc::access(c_path.as_ptr(), libc::R_OK | libc::W_OK) } == 0 {
+        return Ok(());
+    }
+
+    let error = io::Error::last_os_error();
+
+    Err(match error.kind() {
+        io::ErrorKind::NotFound => Error::DaemonNotRunning {
+            detail: format!("socket `{}` does not exist", path.display()),
+        },
+        io::ErrorKind::PermissionDenied => Error::PermissionDenied {
+            detail: format!("no read and write access to socket `{}`", path.display()),
+        },
+        _ => format!("could not access socket `{}`: {}", path.display(), error).into(),
+    })
+}
+
+/// State of the connection to the mDNS daemon, reported to the [`DaemonStateCallback`].
+///
+/// [`DaemonStateCallback`]: type.DaemonStateCallback.html
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DaemonState {
+    /// The daemon is not available (yet) and the client is waiting for it to appear
+    Connecting,
+    /// The daemon is available and ready
+    Running,
+    /// The connection to the daemon has failed or has been lost
+    Failed,
+}
+
+/// Callback invoked from [`MdnsBrowser`] and [`MdnsService`] when the state of their connection
+/// to the mDNS daemon changes.
+///
+/// # Arguments
+/// * `state` - The new state of the connection
+/// * `context` - The optional user context passed through
+///
+/// [`MdnsBrowser`]: ../type.MdnsBrowser.html
+/// [`MdnsService`]: ../type.MdnsService.html
+pub type DaemonStateCallback = dyn Fn(DaemonState, Option<Arc<dyn Any + Send + Sync>>) + Send;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+
+    #[test]
+    fn diagnosis_is_ok_only_if_every_check_passed() {
+        assert!(Diagnosis::new(None, Ok(()), Ok(())).is_ok());
+        assert!(!Diagnosis::new(None, Ok(()), Err("no connection".into())).is_ok());
+        assert!(!Diagnosis::new(
+            Some(PathBuf::from("/run/daemon.sock")),
+            Err("no access".into()),
+            Ok(())
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn diagnosis_displays_every_check() {
+        let diagnosis = Diagnosis::new(
+            Some(PathBuf::from("/run/daemon.sock")),
+            Err(Error::PermissionDenied {
+                detail: "no read and write access to socket `/run/daemon.sock`".to_string(),
+            }),
+            Ok(()),
+        );
+
+        assert_eq!(
+            diagnosis.to_string(),
+            "socket: /run/daemon.sock\n\
+             socket access: permission denied: no read and write access to socket \
+             `/run/daemon.sock`\n\
+             connection: ok"
+        );
+    }
+
+    #[cfg(all(unix, not(feature = "test-mock")))]
+    mod socket_access {
+        use super::super::*;
+        use std::fs;
+        use std::os::unix::fs::PermissionsExt;
+        use std::os::unix::net::UnixListener;
+
+        /// Directory holding a bound socket, removed on drop
+        struct SocketDir {
+            path: PathBuf,
+            _listener: UnixListener,
+        }
+
+        impl SocketDir {
+            fn bind(name: &str) -> Self {
+                let path =
+                    std::env::temp_dir().join(format!("zeroconf-{}-{}", name, std::process::id()));
+
+                let _ = fs::remove_dir_all(&path);
+                fs::create_dir(&path).unwrap();
+
+                Self {
+                    _listener: UnixListener::bind(path.join("daemon.sock")).unwrap(),
+                    path,
+                }
+            }
+
+            fn socket(&self) -> PathBuf {
+                self.path.join("daemon.sock")
+            }
+        }
+
+        impl Drop for SocketDir {
+            fn drop(&mut self) {
+                let _ = fs::remove_dir_all(&self.path);
+            }
+        }
+
+        /// Diagnoses the connection to the daemon of the backend, checking the socket at `path`
+        fn diagnose_socket(path: &Path) -> Diagnosis {
+            #[cfg(target_os = "linux")]
+            return crate::avahi::client::diagnose_socket(Some(path.to_path_buf()));
+            #[cfg(target_vendor = "apple")]
+            return crate::bonjour::bonjour_util::diagnose_socket(path.to_path_buf());
+        }
+
+        #[test]
+        fn accessible_socket_is_accepted() {
+            let dir = SocketDir::bind("accessible");
+
+            assert_eq!(check_socket_access(&dir.socket()), Ok(()));
+        }
+
+        #[test]
+        fn missing_socket_is_reported_as_daemon_not_running() {
+            let dir = SocketDir::bind("missing");
+            let path = dir.path.join("missing.sock");
+
+            assert_eq!(
+                check_socket_access(&path),
+                Err(Error::DaemonNotRunning {
+                    detail: format!("socket `{}` does not exist", path.display())
+                })
+            );
+        }
+
+        #[test]
+        fn inaccessible_socket_is_reported_as_permission_denied() {
+            // root may access the socket regardless of its permissions
+            if unsafe { libc::geteuid() } == 0 {
+                return;
+            }
+
+            let dir = SocketDir::bind("inaccessible");
+            let path = dir.socket();
+
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o000)).unwrap();
+
+            assert_eq!(
+                check_socket_access(&path),
+                Err(Error::PermissionDenied {
+                    detail: format!("no read and write access to socket `{}`", path.display())
+                })
+            );
+        }
+
+        #[test]
+        fn diagnosis_reports_inaccessible_socket_as_permission_denied() {
+            // root may access the socket regardless of its permissions
+            if unsafe { libc::geteuid() } == 0 {
+                return;
+            }
+
+            let dir = SocketDir::bind("diagnose-inaccessible");
+            let path = dir.socket();
+
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o000)).unwrap();
+
+            let diagnosis = diagnose_socket(&path);
+
+            assert!(!diagnosis.is_ok());
+            assert_eq!(diagnosis.socket_path(), &Some(path.clone()));
+            assert_eq!(
+                diagnosis.socket_access(),
+                &Err(Error::PermissionDenied {
+                    detail: format!("no read and write access to socket `{}`", path.display())
+                })
+            );
+        }
+
+        #[test]
+        fn diagnosis_reports_missing_socket_as_daemon_not_running() {
+            let dir = SocketDir::bind("diagnose-missing");
+            let path = dir.path.join("missing.sock");
+
+            let diagnosis = diagnose_socket(&path);
+
+            assert!(!diagnosis.is_ok());
+            assert_eq!(diagnosis.socket_path(), &Some(path.clone()));
+            assert_eq!(
+                diagnosis.socket_access(),
+                &Err(Error::DaemonNotRunning {
+                    detail: format!("socket `{}` does not exist", path.display())
+                })
+            );
+        }
+    }
+}
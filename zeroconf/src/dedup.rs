@@ -0,0 +1,187 @@
+//! Deduplication of the services a browser finds on several interfaces and protocols
+
+use crate::{NetworkInterface, ServiceDiscovery, ServiceKey};
+use std::collections::HashMap;
+
+#[derive(Debug, Default)]
+struct Sightings {
+    interfaces: HashMap<NetworkInterface, usize>,
+    reported: bool,
+}
+
+/// Reduces the sightings of a service reported by the mDNS implementation, one for every
+/// interface and protocol the service is found on, to a single discovery and a single removal.
+///
+/// A service is reported when it is first resolved, and its removal once the last of its
+/// sightings has been removed.
+#[derive(Debug, Default)]
+pub(crate) struct Deduplicator {
+    services: HashMap<ServiceKey, Sightings>,
+}
+
+impl Deduplicator {
+    /// Records that the service has been found on `interface`.
+    pub(crate) fn found(&mut self, key: ServiceKey, interface: NetworkInterface) {
+        *self
+            .services
+            .entry(key)
+            .or_default()
+            .interfaces
+            .entry(interface)
+            .or_default() += 1;
+    }
+
+    /// Returns true if `discovery` should be reported, which is the case for the first
+    /// resolution of a service that is still found on any interface.
+    pub(crate) fn resolved(&mut self, discovery: &ServiceDiscovery) -> bool {
+        match self.services.get_mut(&discovery.key()) {
+            Some(sightings) if !sightings.reported => {
+                sightings.reported = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Records that the service is no longer found on `interface`. Returns true if the removal
+    /// should be reported, which is the case once the last sighting of a reported service has
+    /// been removed.
+    pub(crate) fn removed(&mut self, key: &ServiceKey, interface: NetworkInterface) -> bool {
+        let sightings = match self.services.get_mut(key) {
+            Some(sightings) => sightings,
+            None => return false,
+        };
+
+        if let Some(count) = sightings.interfaces.get_mut(&interface) {
+            *count -= 1;
+
+            if *count == 0 {
+                sightings.interfaces.remove(&interface);
+            }
+        }
+
+        if !sightings.interfaces.is_empty() {
+            return false;
+        }
+
+        self.services
+            .remove(key)
+            .map(|s| s.reported)
+            .unwrap_or_default()
+    }
+
+    /// Forgets every service, e.g. because browsing is restarted.
+    pub(crate) fn clear(&mut self) {
+        self.services.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{discovery_builder, service_key};
+
+    const ETH0: NetworkInterface = NetworkInterface::AtIndex(2);
+    const WLAN0: NetworkInterface = NetworkInterface::AtIndex(3);
+
+    fn discovery(name: &str, interface: NetworkInterface) -> ServiceDiscovery {
+        discovery_builder(name)
+            .interface(interface)
+            .build()
+            .unwrap()
+    }
+
+    /// Finds `name` once per protocol on both interfaces
+    fn found_everywhere(dedup: &mut Deduplicator, name: &str) {
+        for interface in [ETH0, ETH0, WLAN0, WLAN0] {
+            dedup.found(service_key(name), interface);
+        }
+    }
+
+    #[test]
+    fn only_first_resolution_is_reported() {
+        let mut dedup = Deduplicator::default();
+
+        found_everywhere(&mut dedup, "foo");
+
+        assert!(dedup.resolved(&discovery("foo", ETH0)));
+        assert!(!dedup.resolved(&discovery("foo", ETH0)));
+        assert!(!dedup.resolved(&discovery("foo", WLAN0)));
+    }
+
+    #[test]
+    fn distinct_services_are_reported_separately() {
+        let mut dedup = Deduplicator::default();
+
+        found_everywhere(&mut dedup, "foo");
+        found_everywhere(&mut dedup, "bar");
+
+        assert!(dedup.resolved(&discovery("foo", ETH0)));
+        assert!(dedup.resolved(&discovery("bar", ETH0)));
+    }
+
+    #[test]
+    fn removal_is_reported_once_last_sighting_is_removed() {
+        let mut dedup = Deduplicator::default();
+
+        found_everywhere(&mut dedup, "foo");
+        dedup.resolved(&discovery("foo", WLAN0));
+
+        let removals = [ETH0, WLAN0, ETH0, WLAN0]
+            .iter()
+            .map(|i| dedup.removed(&service_key("foo"), *i))
+            .collect::<Vec<_>>();
+
+        assert_eq!(removals, vec![false, false, false, true]);
+    }
+
+    #[test]
+    fn removal_of_unreported_service_is_not_reported() {
+        let mut dedup = Deduplicator::default();
+
+        dedup.found(service_key("foo"), ETH0);
+
+        assert!(!dedup.removed(&service_key("foo"), ETH0));
+    }
+
+    #[test]
+    fn resolution_after_removal_is_not_reported() {
+        let mut dedup = Deduplicator::default();
+
+        dedup.found(service_key("foo"), ETH0);
+        dedup.removed(&service_key("foo"), ETH0);
+
+        assert!(!dedup.resolved(&discovery("foo", ETH0)));
+    }
+
+    #[test]
+    fn service_found_again_after_removal_is_reported_again() {
+        let mut dedup = Deduplicator::default();
+
+        dedup.found(service_key("foo"), ETH0);
+        dedup.resolved(&discovery("foo", ETH0));
+        dedup.removed(&service_key("foo"), ETH0);
+        dedup.found(service_key("foo"), WLAN0);
+
+        assert!(dedup.resolved(&discovery("foo", WLAN0)));
+    }
+
+    #[test]
+    fn unknown_removal_is_ignored() {
+        let mut dedup = Deduplicator::default();
+
+        assert!(!dedup.removed(&service_key("foo"), ETH0));
+    }
+
+    #[test]
+    fn clear_forgets_services() {
+        let mut dedup = Deduplicator::default();
+
+        found_everywhere(&mut dedup, "foo");
+        dedup.resolved(&discovery("foo", ETH0));
+        dedup.clear();
+        dedup.found(service_key("foo"), ETH0);
+
+        assert!(dedup.resolved(&discovery("foo", ETH0)));
+    }
+}
@@ -0,0 +1,159 @@
+//! Deferred changes to registered services
+
+use crate::{DeferredService, Result, ServiceRecord, TxtRecord};
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex, Weak};
+
+/// Change to a registered service, queued with [`Deferrer::defer()`]
+///
+/// [`Deferrer::defer()`]: struct.Deferrer.html#method.defer
+pub type DeferredFn = dyn FnOnce(&mut DeferredService<'_>) -> Result<()> + Send;
+
+pub(crate) type DeferredQueue = Mutex<VecDeque<Box<DeferredFn>>>;
+
+/// Interface for changing a registered service from a closure queued with
+/// [`Deferrer::defer()`].
+///
+/// Changes are applied to the live registration immediately.
+///
+/// [`Deferrer::defer()`]: struct.Deferrer.html#method.defer
+pub trait TDeferredService {
+    /// Returns the name the service is registered under, if known.
+    fn name(&self) -> Option<&str>;
+
+    /// Returns the TXT record of the service.
+    fn txt_record(&self) -> Option<&TxtRecord>;
+
+    /// Replaces the TXT record of the registered service.
+    fn set_txt_record(&mut self, txt_record: TxtRecord) -> Result<()>;
+
+    /// Re-registers the service under the specified `name`. The registered callback is invoked
+    /// again once the new registration completes.
+    fn set_name(&mut self, name: &str) -> Result<()>;
+
+    /// Publishes an additional record with the registered service. Like the records added with
+    /// [`TMdnsService::add_record()`], it is removed along with the service.
+    ///
+    /// # Note
+    /// Avahi cannot add records to a published entry group, so the service is registered again and
+    /// the registered callback is invoked again. Bonjour can only add records without a name of
+    /// their own to a registered service; returning `Error::Unsupported` otherwise.
+    ///
+    /// [`TMdnsService::add_record()`]: ../service/trait.TMdnsService.html#tymethod.add_record
+    fn add_record(&mut self, record: ServiceRecord) -> Result<()>;
+
+    /// Adds a sub-type to the registered service, so that browsers for the sub-type find it.
+    /// Adding a sub-type the service already has does nothing.
+    ///
+    /// # Note
+    /// Neither Avahi nor Bonjour can change the sub-types of a published service, so the service
+    /// is registered again and the registered callback is invoked again.
+    fn add_sub_type(&mut self, sub_type: &str) -> Result<()>;
+
+    /// Removes a sub-type from the registered service, which browsers for the sub-type then
+    /// report as removed. Removing a sub-type the service does not have does nothing.
+    ///
+    /// # Note
+    /// Like [`add_sub_type()`], this registers the service again.
+    ///
+    /// [`add_sub_type()`]: #tymethod.add_sub_type
+    fn remove_sub_type(&mut self, sub_type: &str) -> Result<()>;
+}
+
+/// Handle for queueing changes to a registered [`MdnsService`], obtained with
+/// [`TMdnsService::deferrer()`].
+///
+/// A service must not be used from within its own callbacks: they are invoked while the backend
+/// is dispatching results, where calling back into it may deadlock (Bonjour) or modify the entry
+/// group while it is in use (Avahi). A `Deferrer` can be moved into the callback instead, and the
+/// queued closures are executed by the service's [`EventLoop`] once the callback has returned.
+///
+/// Deferred closures must not poll the event loop themselves. Closures queued after the service
+/// has been dropped are discarded. Errors returned by a closure are passed to the
+/// [`ServiceRegisteredCallback`].
+///
+/// [`MdnsService`]: ../type.MdnsService.html
+/// [`TMdnsService::deferrer()`]: ../service/trait.TMdnsService.html#tymethod.deferrer
+/// [`EventLoop`]: ../type.EventLoop.html
+/// [`ServiceRegisteredCallback`]: ../type.ServiceRegisteredCallback.html
+#[derive(Clone)]
+pub struct Deferrer {
+    queue: Weak<DeferredQueue>,
+}
+
+impl Deferrer {
+    pub(crate) fn new(queue: &Arc<DeferredQueue>) -> Self {
+        Self {
+            queue: Arc::downgrade(queue),
+        }
+    }
+
+    /// Queues `f` to be executed by the event loop of the service.
+    pub fn defer<F>(&self, f: F)
+    where
+        F: FnOnce(&mut DeferredService<'_>) -> Result<()> + Send + 'static,
+    {
+        if let Some(queue) = self.queue.upgrade() {
+            lock(&queue).push_back(Box::new(f));
+        }
+    }
+}
+
+impl fmt::Debug for Deferrer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Deferrer")
+            .field("pending", &self.queue.upgrade().map(|q| lock(&q).len()))
+            .finish()
+    }
+}
+
+/// Removes the next closure from `queue`. The lock is released before the closure is returned,
+/// so that it may queue further closures.
+pub(crate) fn pop(queue: &DeferredQueue) -> Option<Box<DeferredFn>> {
+    lock(queue).pop_front()
+}
+
+fn lock(queue: &DeferredQueue) -> std::sync::MutexGuard<'_, VecDeque<Box<DeferredFn>>> {
+    queue
+        .lock()
+        .expect("should have been able to obtain lock on deferred queue")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    #[test]
+    fn defer_queues_in_order() {
+        let queue: Arc<DeferredQueue> = Arc::default();
+        let deferrer = Deferrer::new(&queue);
+
+        deferrer.defer(|_| Ok(()));
+        deferrer.defer(|_| Err("second".into()));
+
+        assert_eq!(lock(&queue).len(), 2);
+        assert!(pop(&queue).is_some());
+        assert!(pop(&queue).is_some());
+        assert!(pop(&queue).is_none());
+    }
+
+    #[test]
+    fn defer_after_drop_is_discarded() {
+        let queue: Arc<DeferredQueue> = Arc::default();
+        let deferrer = Deferrer::new(&queue);
+        let called = Arc::new(AtomicBool::new(false));
+        let flag = called.clone();
+
+        drop(queue);
+
+        deferrer.defer(move |_| {
+            flag.store(true, Ordering::SeqCst);
+            Ok(())
+        });
+
+        assert!(!called.load(Ordering::SeqCst));
+        assert_eq!(Arc::strong_count(&called), 1);
+    }
+}
@@ -0,0 +1,132 @@
+//! Polling of services and browsers on a background thread managed by the crate
+
+use crate::error::Error;
+use crate::event_loop::ShutdownHandle;
+use crate::prelude::*;
+use crate::{ffi, EventLoop, Result};
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// Name of the threads that poll detached services and browsers
+pub const POLL_THREAD_NAME: &str = "zeroconf-poll";
+
+/// Timeout passed to each `poll()` of a detached event loop. Stopping does not wait for it to
+/// elapse, as it interrupts the poll through a [`ShutdownHandle`].
+///
+/// [`ShutdownHandle`]: ../struct.ShutdownHandle.html
+const POLL_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Handle on a service or browser that is polled on a background thread, as returned by
+/// [`TMdnsService::register_detached()`] and [`TMdnsBrowser::browse_detached()`].
+///
+/// The thread owns the service or browser along with its event loop and invokes the callbacks
+/// from there. It keeps polling until the handle is stopped or dropped, or until polling fails,
+/// and then drops the service or browser.
+///
+/// A panic that escapes from polling, e.g. in a closure queued with a [`Deferrer`], stops only the
+/// thread of this handle. Panics in the callbacks themselves are reported to them as
+/// `Error::CallbackPanicked` like they are when polling on your own.
+///
+/// [`TMdnsService::register_detached()`]: service/trait.TMdnsService.html#method.register_detached
+/// [`TMdnsBrowser::browse_detached()`]: browser/trait.TMdnsBrowser.html#method.browse_detached
+/// [`Deferrer`]: struct.Deferrer.html
+pub struct DetachedHandle {
+    shutdown: ShutdownHandle,
+    thread: Option<JoinHandle<Result<()>>>,
+}
+
+impl DetachedHandle {
+    /// Spawns the thread that polls `event_loop` on behalf of `owner`, which is dropped on the
+    /// thread once polling has stopped.
+    pub(crate) fn spawn<T: Send + 'static>(owner: T, event_loop: EventLoop) -> Result<Self> {
+        let shutdown = event_loop.shutdown_handle();
+        let stopped = shutdown.clone();
+
+        let thread = thread::Builder::new()
+            .name(POLL_THREAD_NAME.to_string())
+            .spawn(move || {
+                let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                    while !stopped.is_shutdown() {
+                        event_loop.poll(POLL_TIMEOUT)?;
+                    }
+
+                    Ok(())
+                }))
+                .unwrap_or_else(|payload| {
+                    Err(Error::CallbackPanicked {
+                        message: ffi::panic_message(payload.as_ref()),
+                    })
+                });
+
+                if let Err(e) = &result {
+                    error!("[{}] Detached polling stopped: {}", event_loop.id(), e);
+                }
+
+                // the service or browser is dropped before the event loop it is polled by
+                drop(owner);
+                drop(event_loop);
+
+                result
+            })
+            .map_err(|e| format!("could not spawn {} thread: {}", POLL_THREAD_NAME, e))?;
+
+        Ok(Self {
+            shutdown,
+            thread: Some(thread),
+        })
+    }
+
+    /// Returns `true` until the thread has stopped polling and dropped the service or browser.
+    pub fn is_running(&self) -> bool {
+        self.thread
+            .as_ref()
+            .is_some_and(|thread| !thread.is_finished())
+    }
+
+    /// Stops polling and waits for the thread to drop the service or browser. Returns the error
+    /// that stopped polling early, if any.
+    ///
+    /// # Note
+    /// Called from one of its own callbacks, this does not wait, as the thread is still busy
+    /// invoking the callback. It stops right after the callback has returned.
+    pub fn stop(mut self) -> Result<()> {
+        self.stop_thread()
+    }
+
+    fn stop_thread(&mut self) -> Result<()> {
+        self.shutdown.shutdown();
+
+        let thread = match self.thread.take() {
+            Some(thread) => thread,
+            None => return Ok(()),
+        };
+
+        if thread.thread().id() == thread::current().id() {
+            return Ok(());
+        }
+
+        thread.join().unwrap_or_else(|payload| {
+            Err(Error::CallbackPanicked {
+                message: ffi::panic_message(payload.as_ref()),
+            })
+        })
+    }
+}
+
+impl Drop for DetachedHandle {
+    fn drop(&mut self) {
+        if let Err(e) = self.stop_thread() {
+            warn!("Detached polling had stopped with an error: {}", e);
+        }
+    }
+}
+
+impl fmt::Debug for DetachedHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DetachedHandle")
+            .field("running", &self.is_running())
+            .finish()
+    }
+}
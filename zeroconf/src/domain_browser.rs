@@ -0,0 +1,87 @@
+//! Trait definition for cross-platform domain browser
+
+use crate::{EventLoop, Id, NetworkInterface, Result};
+use std::any::Any;
+use std::sync::Arc;
+
+/// Interface for interacting with underlying mDNS implementation domain enumeration capabilities.
+///
+/// This is useful with wide-area DNS-SD, where the domains to browse in or register services in
+/// are announced by the network (`b._dns-sd._udp` and `r._dns-sd._udp`) instead of always being
+/// `local`.
+pub trait TMdnsDomainBrowser {
+    /// Creates a new `MdnsDomainBrowser` that enumerates the domains of the specified
+    /// `domain_type`.
+    fn new(domain_type: DomainType) -> Self;
+
+    /// Returns the [`Id`] that prefixes the log output of this domain browser.
+    ///
+    /// [`Id`]: ../struct.Id.html
+    fn id(&self) -> Id;
+
+    /// Returns the type of domains this browser enumerates.
+    fn domain_type(&self) -> DomainType;
+
+    /// Sets the network interface on which to enumerate domains on.
+    ///
+    /// Most applications will want to use the default value `NetworkInterface::Unspec` to
+    /// enumerate on all available interfaces.
+    fn set_network_interface(&mut self, interface: NetworkInterface);
+
+    /// Returns the network interface on which to enumerate domains on.
+    fn network_interface(&self) -> NetworkInterface;
+
+    /// Sets the [`DomainEventCallback`] that is invoked when a domain is added or removed.
+    ///
+    /// [`DomainEventCallback`]: ../type.DomainEventCallback.html
+    fn set_domain_event_callback(&mut self, domain_event_callback: Box<DomainEventCallback>);
+
+    /// Sets the optional user context to pass through to the callback. This is useful if you need
+    /// to share state between pre and post-callback. The context type must implement `Any`, `Send`
+    /// and `Sync`.
+    fn set_context(&mut self, context: Box<dyn Any + Send + Sync>);
+
+    /// Returns the optional user context to pass through to the callback.
+    fn context(&self) -> Option<&(dyn Any + Send + Sync)>;
+
+    /// Starts enumerating domains. Returns an `EventLoop` which can be called to keep the browser
+    /// alive.
+    fn browse_domains(&mut self) -> Result<EventLoop>;
+}
+
+/// The type of domains enumerated by a [`MdnsDomainBrowser`].
+///
+/// [`MdnsDomainBrowser`]: ../type.MdnsDomainBrowser.html
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DomainType {
+    /// Domains recommended for browsing for services
+    Browse,
+    /// Domains recommended for registering services
+    Registration,
+}
+
+/// Callback invoked from [`MdnsDomainBrowser`] once a domain has been added or removed.
+///
+/// # Arguments
+/// * `event` - The domain that was added or removed
+/// * `context` - The optional user context passed through
+///
+/// [`MdnsDomainBrowser`]: type.MdnsDomainBrowser.html
+pub type DomainEventCallback =
+    dyn Fn(Result<DomainEvent>, Option<Arc<dyn Any + Send + Sync>>) + Send;
+
+/// Represents a change to the domains enumerated by a [`MdnsDomainBrowser`].
+///
+/// # Note
+/// Avahi reports a domain once for every network interface and protocol it is found on.
+///
+/// [`MdnsDomainBrowser`]: type.MdnsDomainBrowser.html
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DomainEvent {
+    /// A domain has been added (e.g. `example.com`)
+    Add(String),
+    /// A previously added domain has been removed
+    Remove(String),
+}
@@ -1,18 +1,147 @@
 //! Utilities regarding error handling
 
 use std::fmt;
+use std::time::Duration;
 
 /// For when something goes wrong when interfacing with mDNS implementations
-#[derive(new, Debug, Clone, PartialEq, Eq)]
-pub struct Error {
-    description: String,
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Error {
+    /// A general error reported by the mDNS implementation or by this crate
+    Other(String),
+    /// A TXT record entry violates the DNS-SD constraints on keys and values
+    InvalidTxtRecord(String),
+    /// A service instance name cannot be registered, e.g. because it is empty
+    InvalidName(String),
+    /// The requested operation cannot be expressed by the underlying mDNS implementation
+    Unsupported(String),
+    /// The process is not allowed to communicate with the mDNS daemon (e.g. because of sandboxing
+    /// or a mandatory access control policy)
+    PermissionDenied {
+        /// Describes what was attempted, including the socket path or bus address
+        detail: String,
+    },
+    /// The mDNS daemon is not running or not installed (e.g. the Bonjour service on Windows)
+    DaemonNotRunning {
+        /// Describes what was attempted
+        detail: String,
+    },
+    /// A service name does not fit into a single DNS label
+    NameTooLong {
+        /// Length of the name in bytes
+        bytes: usize,
+        /// Maximum permitted length in bytes
+        max: usize,
+    },
+    /// The service name is already in use on the network and renaming on conflict is disabled
+    NameCollision {
+        /// The name that could not be registered
+        name: String,
+    },
+    /// An operation did not complete in time
+    TimedOut {
+        /// The time that was waited for
+        timeout: Duration,
+    },
+    /// A panic occurred while handling a result reported by the mDNS implementation, e.g. in a
+    /// user callback. It was caught to keep it from unwinding into the C library.
+    CallbackPanicked {
+        /// The panic message
+        message: String,
+    },
+    /// An error reported by the mDNS implementation, along with the operation and service it
+    /// occurred in. The error itself is exposed by [`source()`] and [`without_context()`].
+    ///
+    /// [`source()`]: #method.source
+    /// [`without_context()`]: #method.without_context
+    WithContext {
+        /// The operation and service the error occurred in
+        context: ErrorContext,
+        /// The error reported by the mDNS implementation
+        source: Box<Error>,
+    },
 }
 
-impl std::error::Error for Error {}
+impl Error {
+    /// Constructs a new `Error::Other` with the specified description.
+    #[deprecated(
+        since = "0.16.0",
+        note = "`Error` is an enum now, construct `Error::Other` or use `Error::from()` instead"
+    )]
+    pub fn new(description: String) -> Self {
+        Error::Other(description)
+    }
+
+    /// Returns the [`ErrorContext`] of an error reported by the mDNS implementation, if known.
+    ///
+    /// [`ErrorContext`]: struct.ErrorContext.html
+    pub fn context(&self) -> Option<&ErrorContext> {
+        match self {
+            Error::WithContext { context, .. } => Some(context),
+            _ => None,
+        }
+    }
+
+    /// Returns the error without its [`ErrorContext`], for matching on the error itself.
+    ///
+    /// [`ErrorContext`]: struct.ErrorContext.html
+    pub fn without_context(&self) -> &Error {
+        match self {
+            Error::WithContext { source, .. } => source.without_context(),
+            _ => self,
+        }
+    }
+
+    /// Attaches `context` to an error reported by the mDNS implementation.
+    ///
+    /// Only `Error::Other` is wrapped in `Error::WithContext`. The other variants are returned
+    /// unchanged, as they already describe what went wrong and are matched on by callers, as is
+    /// an error that already has a context.
+    pub fn with_context(self, context: ErrorContext) -> Error {
+        match self {
+            Error::Other(_) => Error::WithContext {
+                context,
+                source: Box::new(self),
+            },
+            _ => self,
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::WithContext { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.description)
+        match self {
+            Error::Other(description) => write!(f, "{}", description),
+            Error::InvalidTxtRecord(description) => {
+                write!(f, "invalid TXT record: {}", description)
+            }
+            Error::InvalidName(description) => write!(f, "invalid service name: {}", description),
+            Error::Unsupported(description) => write!(f, "unsupported: {}", description),
+            Error::PermissionDenied { detail } => write!(f, "permission denied: {}", detail),
+            Error::DaemonNotRunning { detail } => {
+                write!(f, "mDNS daemon not available: {}", detail)
+            }
+            Error::NameTooLong { bytes, max } => write!(
+                f,
+                "service name is {} bytes long, but at most {} bytes are allowed",
+                bytes, max
+            ),
+            Error::NameCollision { name } => {
+                write!(f, "service name `{}` is already in use", name)
+            }
+            Error::TimedOut { timeout } => write!(f, "timed out after {:?}", timeout),
+            Error::CallbackPanicked { message } => write!(f, "callback panicked: {}", message),
+            Error::WithContext { context, source } => write!(f, "{} failed: {}", context, source),
+        }
     }
 }
 
@@ -24,6 +153,185 @@ impl From<&str> for Error {
 
 impl From<String> for Error {
     fn from(s: String) -> Self {
-        Error::new(s)
+        Error::Other(s)
+    }
+}
+
+/// Attaches an [`ErrorContext`] to the error of a `Result`.
+///
+/// [`ErrorContext`]: struct.ErrorContext.html
+pub(crate) trait ResultExt<T> {
+    /// Attaches the context returned by `f` to the error, see [`Error::with_context()`].
+    ///
+    /// [`Error::with_context()`]: enum.Error.html#method.with_context
+    fn with_context<F: FnOnce() -> ErrorContext>(self, f: F) -> crate::Result<T>;
+}
+
+impl<T> ResultExt<T> for crate::Result<T> {
+    fn with_context<F: FnOnce() -> ErrorContext>(self, f: F) -> crate::Result<T> {
+        self.map_err(|e| e.with_context(f()))
+    }
+}
+
+/// The operation of the mDNS implementation an [`ErrorContext`] describes
+///
+/// [`ErrorContext`]: struct.ErrorContext.html
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Operation {
+    /// Registering a service
+    Register,
+    /// Browsing for services
+    Browse,
+    /// Resolving a discovered service
+    Resolve,
+    /// Looking up the addresses of a host
+    GetAddrInfo,
+    /// Polling an event loop
+    Poll,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Operation::Register => "register",
+            Operation::Browse => "browse",
+            Operation::Resolve => "resolve",
+            Operation::GetAddrInfo => "address lookup",
+            Operation::Poll => "poll",
+        })
+    }
+}
+
+/// Describes which operation of which service an [`Error`] reported by the mDNS implementation
+/// occurred in, so that the instance at fault can be told apart when several services and
+/// browsers run in one process.
+///
+/// [`Error`]: enum.Error.html
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Getters, Clone, PartialEq, Eq, Hash)]
+pub struct ErrorContext {
+    operation: Operation,
+    /// The service type, e.g. `_http._tcp`, if known
+    service_type: Option<String>,
+    /// The name of the service instance, or the host name of an address lookup, if known
+    name: Option<String>,
+}
+
+impl ErrorContext {
+    /// Creates a new `ErrorContext` for `operation`, with neither service type nor name.
+    pub fn new(operation: Operation) -> Self {
+        Self {
+            operation,
+            service_type: None,
+            name: None,
+        }
+    }
+
+    /// Sets the service type the operation was performed on.
+    pub fn with_service_type(mut self, service_type: &str) -> Self {
+        self.service_type = Some(service_type.to_string());
+        self
+    }
+
+    /// Sets the name of the service instance, or the host name, the operation was performed on.
+    pub fn with_name(mut self, name: Option<&str>) -> Self {
+        self.name = name.map(str::to_string);
+        self
+    }
+}
+
+impl fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.operation)?;
+
+        if let Some(name) = &self.name {
+            write!(f, " of `{}`", name)?;
+        }
+
+        if let Some(service_type) = &self.service_type {
+            write!(f, " ({})", service_type)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::error::Error as _;
+
+    fn register_context() -> ErrorContext {
+        ErrorContext::new(Operation::Register)
+            .with_service_type("_http._tcp")
+            .with_name(Some("My Printer"))
+    }
+
+    #[test]
+    fn display_includes_operation_name_and_service_type() {
+        let error = Error::from("could not register service: `Local name collision`")
+            .with_context(register_context());
+
+        assert_eq!(
+            error.to_string(),
+            "register of `My Printer` (_http._tcp) failed: could not register service: \
+             `Local name collision`"
+        );
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn new_constructs_other() {
+        assert_eq!(
+            Error::new("something went wrong".to_string()),
+            Error::Other("something went wrong".to_string())
+        );
+    }
+
+    #[test]
+    fn display_omits_unknown_service() {
+        let error = Error::from("poll(): failed").with_context(ErrorContext::new(Operation::Poll));
+
+        assert_eq!(error.to_string(), "poll failed: poll(): failed");
+    }
+
+    #[test]
+    fn source_exposes_underlying_error() {
+        let error = Error::from("could not process service result (code: -65563)")
+            .with_context(register_context());
+
+        assert_eq!(
+            error.source().map(ToString::to_string),
+            Some("could not process service result (code: -65563)".to_string())
+        );
+        assert_eq!(
+            error.without_context(),
+            &Error::from("could not process service result (code: -65563)")
+        );
+        assert_eq!(error.context(), Some(&register_context()));
+    }
+
+    #[test]
+    fn with_context_keeps_typed_errors_and_existing_context() {
+        let collision = Error::NameCollision {
+            name: "My Printer".to_string(),
+        };
+
+        assert_eq!(
+            collision.clone().with_context(register_context()),
+            collision
+        );
+
+        let error = Error::from("uh oh spaghetti-o").with_context(register_context());
+
+        assert_eq!(
+            error
+                .clone()
+                .with_context(ErrorContext::new(Operation::Poll))
+                .context(),
+            Some(&register_context())
+        );
     }
 }
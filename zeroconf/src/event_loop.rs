@@ -1,11 +1,147 @@
 //! Trait definition for cross-platform event loop
 
-use crate::Result;
-use std::time::Duration;
+use crate::{ffi, Id, Result};
+use std::fmt;
+#[cfg(any(target_os = "linux", target_vendor = "apple"))]
+use std::os::unix::io::RawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 /// A handle on the underlying implementation to poll the event loop. Typically, `poll()`
 /// is called in a loop to keep a `MdnsService` or `MdnsBrowser` running.
 pub trait TEventLoop {
+    /// Returns the [`Id`] that prefixes the log output of this event loop.
+    ///
+    /// [`Id`]: ../struct.Id.html
+    fn id(&self) -> Id;
+
     /// Polls for new events.
     fn poll(&self, timeout: Duration) -> Result<()>;
+
+    /// Processes the events that are ready without blocking. Returns `true` if any callback was
+    /// invoked.
+    ///
+    /// Useful for driving the event loop from an application loop that must not block, such as a
+    /// game or UI loop.
+    fn try_poll(&self) -> Result<bool> {
+        let dispatched = ffi::dispatched();
+
+        self.process_events()?;
+
+        Ok(ffi::dispatched() != dispatched)
+    }
+
+    /// Processes events until `deadline`, rather than returning after the first callback like
+    /// `poll()` does. Interrupted system calls do not end polling early.
+    ///
+    /// If `deadline` has already passed, the events that are ready are processed once.
+    fn poll_until(&self, deadline: Instant) -> Result<()> {
+        loop {
+            self.poll(deadline.saturating_duration_since(Instant::now()))?;
+
+            if Instant::now() >= deadline {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Processes the events that are ready without waiting for new ones.
+    ///
+    /// Meant to be called whenever the descriptor returned by [`as_raw_fd()`] is readable, in place
+    /// of `poll()`.
+    ///
+    /// [`as_raw_fd()`]: #tymethod.as_raw_fd
+    fn process_events(&self) -> Result<()>;
+
+    /// Returns a file descriptor that becomes readable whenever there are events to process, for
+    /// integrating the event loop into an external reactor (e.g. epoll or mio). Once it is
+    /// readable, `process_events()` should be called.
+    ///
+    /// Returns `None` if the events cannot be waited for on a single descriptor: on Bonjour, if
+    /// the service has records registered on a separate connection, and always with the
+    /// `test-mock` feature.
+    ///
+    /// # Note
+    /// On Avahi, the first call switches the event loop over to keeping the descriptor up to date,
+    /// and must not be made while the loop is being polled.
+    #[cfg(any(target_os = "linux", target_vendor = "apple"))]
+    fn as_raw_fd(&self) -> Option<RawFd>;
+
+    /// Returns a [`ShutdownHandle`] for ending the polling of this event loop from another thread,
+    /// e.g. the one that owns the objects sharing it.
+    ///
+    /// [`ShutdownHandle`]: struct.ShutdownHandle.html
+    fn shutdown_handle(&self) -> ShutdownHandle;
+}
+
+/// Ends the polling of an event loop from any thread, as returned by
+/// [`TEventLoop::shutdown_handle()`].
+///
+/// Once [`shutdown()`] has been called, a `poll()` that is waiting for events returns `Ok(())`
+/// promptly, and so does every later call without waiting. Worker threads driving the loop can
+/// check [`is_shutdown()`] to know when to stop.
+///
+/// [`TEventLoop::shutdown_handle()`]: trait.TEventLoop.html#tymethod.shutdown_handle
+/// [`shutdown()`]: #method.shutdown
+/// [`is_shutdown()`]: #method.is_shutdown
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    requested: Arc<AtomicBool>,
+    wake: Arc<dyn Fn() + Send + Sync>,
+}
+
+impl ShutdownHandle {
+    /// Constructs a new `ShutdownHandle` that sets `requested` and then calls `wake` to interrupt
+    /// the event loop if it is waiting.
+    pub(crate) fn new(requested: Arc<AtomicBool>, wake: impl Fn() + Send + Sync + 'static) -> Self {
+        Self {
+            requested,
+            wake: Arc::new(wake),
+        }
+    }
+
+    /// Shuts the event loop down. Calling this more than once has no further effect.
+    pub fn shutdown(&self) {
+        if !self.requested.swap(true, Ordering::SeqCst) {
+            (self.wake)();
+        }
+    }
+
+    /// Returns `true` if the event loop has been shut down.
+    pub fn is_shutdown(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+}
+
+impl fmt::Debug for ShutdownHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ShutdownHandle")
+            .field("requested", &self.is_shutdown())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+
+    #[test]
+    fn shutdown_wakes_event_loop_once() {
+        let wakes = Arc::new(AtomicUsize::new(0));
+        let counter = wakes.clone();
+
+        let handle = ShutdownHandle::new(Arc::default(), move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+
+        assert!(!handle.is_shutdown());
+
+        handle.shutdown();
+        handle.clone().shutdown();
+
+        assert!(handle.is_shutdown());
+        assert_eq!(wakes.load(Ordering::SeqCst), 1);
+    }
 }
@@ -1,5 +1,6 @@
 //! Utilities related to c-string handling
 
+use crate::Result;
 use libc::c_char;
 use std::ffi::{CStr, CString};
 
@@ -23,6 +24,7 @@ impl AsCChars for Option<&CString> {
 /// This function is unsafe due to a call to the unsafe function [`CStr::from_ptr()`].
 ///
 /// [`CStr::from_ptr()`]: https://doc.rust-lang.org/std/ffi/struct.CStr.html#method.from_ptr
+#[cfg(any(target_os = "linux", test))]
 pub unsafe fn raw_to_str<'a>(s: *const c_char) -> &'a str {
     assert_not_null!(s);
     CStr::from_ptr(s)
@@ -30,12 +32,47 @@ pub unsafe fn raw_to_str<'a>(s: *const c_char) -> &'a str {
         .expect("could not convert raw to str")
 }
 
+/// Returns the specified `*const c_char` as a `&'a str`, or an error naming `what` if it is null
+/// or not valid UTF-8. Ownership is not taken.
+///
+/// Unlike [`raw_to_str()`], this does not panic and should be used for strings reported by the
+/// mDNS implementation.
+///
+/// # Safety
+/// This function is unsafe due to a call to the unsafe function [`CStr::from_ptr()`].
+///
+/// [`raw_to_str()`]: fn.raw_to_str.html
+/// [`CStr::from_ptr()`]: https://doc.rust-lang.org/std/ffi/struct.CStr.html#method.from_ptr
+pub unsafe fn try_raw_to_str<'a>(s: *const c_char, what: &str) -> Result<&'a str> {
+    ensure_not_null!(s, what);
+    CStr::from_ptr(s)
+        .to_str()
+        .map_err(|_| format!("{} is not valid UTF-8", what).into())
+}
+
+/// Returns the specified `*const c_char` as a string suitable for diagnostics. Null pointers are
+/// rendered as `<null>` and invalid UTF-8 is replaced. Ownership is not taken.
+///
+/// # Safety
+/// This function is unsafe due to a call to the unsafe function [`CStr::from_ptr()`].
+///
+/// [`CStr::from_ptr()`]: https://doc.rust-lang.org/std/ffi/struct.CStr.html#method.from_ptr
+#[cfg(target_os = "linux")]
+pub unsafe fn raw_to_str_lossy<'a>(s: *const c_char) -> std::borrow::Cow<'a, str> {
+    if s.is_null() {
+        std::borrow::Cow::Borrowed("<null>")
+    } else {
+        CStr::from_ptr(s).to_string_lossy()
+    }
+}
+
 /// Copies the specified `*const c_char` into a `String`.
 ///
 /// # Safety
 /// This function is unsafe due to a call to the unsafe function [`raw_to_str()`].
 ///
 /// [`raw_to_str()`]: fn.raw_to_str.html
+#[cfg(target_os = "linux")]
 pub unsafe fn copy_raw(s: *const c_char) -> String {
     assert_not_null!(s);
     String::from(raw_to_str(s))
@@ -65,6 +102,44 @@ mod tests {
     }
 
     #[test]
+    fn try_raw_to_str_success() {
+        let c_string = c_string!("foo");
+        unsafe { assert_eq!(try_raw_to_str(c_string.as_ptr(), "name"), Ok("foo")) };
+    }
+
+    #[test]
+    fn try_raw_to_str_null_returns_error() {
+        unsafe {
+            assert_eq!(
+                try_raw_to_str(ptr::null(), "name"),
+                Err("missing name".into())
+            )
+        };
+    }
+
+    #[test]
+    fn try_raw_to_str_invalid_utf8_returns_error() {
+        let c_string = CString::new(vec![0xff, 0xfe]).unwrap();
+        unsafe {
+            assert_eq!(
+                try_raw_to_str(c_string.as_ptr(), "name"),
+                Err("name is not valid UTF-8".into())
+            )
+        };
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
+    fn raw_to_str_lossy_renders_null() {
+        let c_string = c_string!("foo");
+        unsafe {
+            assert_eq!(raw_to_str_lossy(c_string.as_ptr()), "foo");
+            assert_eq!(raw_to_str_lossy(ptr::null()), "<null>");
+        }
+    }
+
+    #[test]
+    #[cfg(target_os = "linux")]
     fn copy_raw_success() {
         let c_string = c_string!("foo");
         let c_str = c_string.as_ptr() as *const c_char;
@@ -72,6 +147,7 @@ mod tests {
     }
 
     #[test]
+    #[cfg(target_os = "linux")]
     #[should_panic]
     fn copy_raw_expects_non_null() {
         unsafe { copy_raw(ptr::null() as *const c_char) };
@@ -1,9 +1,26 @@
 //! Utilities related to FFI bindings
 
+use crate::error::Error;
+use crate::span::Span;
+use crate::Id;
+#[cfg(not(feature = "test-mock"))]
+use crate::Result;
 use libc::c_void;
-use std::ptr;
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::mem;
+#[cfg(not(feature = "test-mock"))]
+use std::ops::{Deref, DerefMut};
+use std::panic::{self, AssertUnwindSafe};
+#[cfg(any(target_os = "linux", feature = "test-mock"))]
+use std::sync::{Mutex, PoisonError};
+#[cfg(not(feature = "test-mock"))]
+use std::{fmt, ptr, slice};
 
+#[cfg(not(feature = "test-mock"))]
 pub(crate) mod c_str;
+#[cfg(all(target_os = "linux", not(feature = "test-mock")))]
+pub(crate) mod weak;
 
 /// Helper trait to convert a raw `*mut c_void` to it's rust type
 pub trait FromRaw<T> {
@@ -25,12 +42,305 @@ pub trait AsRaw {
     }
 }
 
+thread_local! {
+    static DISPATCHED: Cell<u64> = const { Cell::new(0) };
+    static DISPATCHING: Cell<usize> = const { Cell::new(0) };
+    static DROPPED: RefCell<Vec<Box<dyn Any>>> = const { RefCell::new(Vec::new()) };
+    #[cfg(any(target_os = "linux", feature = "test-mock"))]
+    static UNLOCKED: RefCell<Option<Vec<Box<UnlockedCall>>>> = const { RefCell::new(None) };
+}
+
+/// Call of a user callback passed to [`invoke_unlocked()`]
+///
+/// [`invoke_unlocked()`]: fn.invoke_unlocked.html
+#[cfg(any(target_os = "linux", feature = "test-mock"))]
+pub(crate) type UnlockedCall = dyn FnOnce();
+
+/// Returns the number of callbacks that have been dispatched on the current thread. Event loops
+/// compare it before and after waiting for the mDNS implementation, to tell whether anything
+/// happened while polling.
+pub(crate) fn dispatched() -> u64 {
+    DISPATCHED.with(Cell::get)
+}
+
+/// Marks the current thread as dispatching a callback until dropped. Values passed to
+/// [`drop_after_dispatch()`] in the meantime are kept alive until [`drop_dispatched()`] is called
+/// outside of any callback.
+///
+/// [`drop_after_dispatch()`]: fn.drop_after_dispatch.html
+/// [`drop_dispatched()`]: fn.drop_dispatched.html
+pub(crate) struct Dispatching(());
+
+impl Dispatching {
+    pub(crate) fn enter() -> Self {
+        DISPATCHING.with(|depth| depth.set(depth.get() + 1));
+        Self(())
+    }
+}
+
+impl Drop for Dispatching {
+    fn drop(&mut self) {
+        DISPATCHING.with(|depth| depth.set(depth.get() - 1));
+    }
+}
+
+/// Drops `value`, unless a callback is being dispatched on the current thread. The value is then
+/// kept alive until the event loop has finished dispatching, so that the objects invoking the
+/// callback are not freed from within it.
+#[cfg(not(feature = "test-mock"))]
+pub(crate) fn drop_after_dispatch<T: 'static>(value: T) {
+    if DISPATCHING.with(Cell::get) > 0 {
+        DROPPED.with(|dropped| dropped.borrow_mut().push(Box::new(value)));
+    } else {
+        drop(value);
+    }
+}
+
+/// Drops the values passed to [`drop_after_dispatch()`] while dispatching callbacks on the current
+/// thread. Does nothing if called from within a callback.
+///
+/// [`drop_after_dispatch()`]: fn.drop_after_dispatch.html
+pub(crate) fn drop_dispatched() {
+    if DISPATCHING.with(Cell::get) > 0 {
+        return;
+    }
+
+    // dropping a value may pass further values to `drop_after_dispatch()`, which are then dropped
+    // right away
+    let dropped = DROPPED.with(|dropped| mem::take(&mut *dropped.borrow_mut()));
+
+    drop(dropped);
+}
+
+/// Owned value that is dropped with [`drop_after_dispatch()`], for the context of an object that
+/// may be dropped from within its own callbacks.
+///
+/// [`drop_after_dispatch()`]: fn.drop_after_dispatch.html
+#[cfg(not(feature = "test-mock"))]
+pub(crate) struct DropAfterDispatch<T: 'static>(Option<T>);
+
+#[cfg(not(feature = "test-mock"))]
+impl<T: 'static> DropAfterDispatch<T> {
+    pub(crate) fn new(value: T) -> Self {
+        Self(Some(value))
+    }
+}
+
+#[cfg(not(feature = "test-mock"))]
+impl<T: 'static> Deref for DropAfterDispatch<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        self.0.as_ref().expect("value should not have been dropped")
+    }
+}
+
+#[cfg(not(feature = "test-mock"))]
+impl<T: 'static> DerefMut for DropAfterDispatch<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        self.0.as_mut().expect("value should not have been dropped")
+    }
+}
+
+#[cfg(not(feature = "test-mock"))]
+impl<T: 'static> Drop for DropAfterDispatch<T> {
+    fn drop(&mut self) {
+        if let Some(value) = self.0.take() {
+            drop_after_dispatch(value);
+        }
+    }
+}
+
+#[cfg(not(feature = "test-mock"))]
+impl<T: fmt::Debug + 'static> fmt::Debug for DropAfterDispatch<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Context passed as user data to the callbacks invoked by the mDNS implementation.
+pub(crate) trait CallbackContext: FromRaw<Self> + Sized {
+    /// Returns the id of the object the callback was registered for.
+    fn id(&self) -> Id;
+
+    /// Passes `error` on to the user callback.
+    fn invoke_error(&self, error: Error);
+
+    /// Returns the span that callbacks for this context are executed in, if any.
+    fn span(&self) -> Option<&Span> {
+        None
+    }
+}
+
+/// Executes `f`, the body of a callback invoked by the mDNS implementation, on the context behind
+/// `userdata` and within the context's span.
+///
+/// Unwinding out of an `extern` function into C is undefined behavior, so a panic in `f`
+/// (including one in a user callback) is caught, logged and passed on to the user callback as
+/// [`Error::CallbackPanicked`]. A panic while passing it on is only logged.
+///
+/// [`Error::CallbackPanicked`]: ../error/enum.Error.html#variant.CallbackPanicked
+///
+/// # Safety
+/// This function is unsafe because `userdata` is dereferenced as a `C`.
+pub(crate) unsafe fn guard<C, F>(userdata: *mut c_void, f: F)
+where
+    C: CallbackContext,
+    F: FnOnce(&mut C),
+{
+    DISPATCHED.with(|count| count.set(count.get().wrapping_add(1)));
+
+    let _dispatching = Dispatching::enter();
+
+    let span = C::from_raw(userdata).span().cloned();
+    let _entered = span.as_ref().map(Span::enter);
+
+    let payload = match panic::catch_unwind(AssertUnwindSafe(|| f(C::from_raw(userdata)))) {
+        Ok(()) => return,
+        Err(payload) => payload,
+    };
+
+    let message = panic_message(payload.as_ref());
+
+    let reported = panic::catch_unwind(AssertUnwindSafe(|| {
+        let context = C::from_raw(userdata);
+        error!("[{}] Callback panicked: {}", context.id(), message);
+        context.invoke_error(Error::CallbackPanicked {
+            message: message.clone(),
+        });
+    }));
+
+    if reported.is_err() {
+        error!("Callback panicked and could not be reported: {}", message);
+    }
+}
+
+/// Calls `f`, which invokes a user callback, once the context of the callback being dispatched on
+/// the current thread has been unlocked by [`guard_locked()`]. Outside of it, `f` is called right
+/// away.
+///
+/// User callbacks are passed to it by contexts shared in a `Mutex`, so that they may call methods
+/// of the object the context belongs to without deadlocking.
+///
+/// [`guard_locked()`]: fn.guard_locked.html
+#[cfg(any(target_os = "linux", feature = "test-mock"))]
+pub(crate) fn invoke_unlocked(f: impl FnOnce() + 'static) {
+    let f: Box<UnlockedCall> = Box::new(f);
+
+    let f = UNLOCKED.with(|calls| match &mut *calls.borrow_mut() {
+        Some(calls) => {
+            calls.push(f);
+            None
+        }
+        None => Some(f),
+    });
+
+    if let Some(f) = f {
+        f();
+    }
+}
+
+/// Executes `f`, returning the calls passed to [`invoke_unlocked()`] in the meantime instead of
+/// making them.
+///
+/// [`invoke_unlocked()`]: fn.invoke_unlocked.html
+#[cfg(any(target_os = "linux", feature = "test-mock"))]
+fn collect_unlocked(f: impl FnOnce()) -> Vec<Box<UnlockedCall>> {
+    let outer = UNLOCKED.with(|calls| calls.replace(Some(vec![])));
+
+    f();
+
+    UNLOCKED
+        .with(|calls| calls.replace(outer))
+        .unwrap_or_default()
+}
+
+/// Executes `f` like [`guard()`] on the context shared in `shared`, which is locked for the
+/// duration of `f` only. The user callbacks that `f` passes to [`invoke_unlocked()`] are called
+/// once the context has been unlocked, within its span. A panic in one of them is reported like
+/// a panic in `f`, and the remaining calls are still made.
+///
+/// [`guard()`]: fn.guard.html
+/// [`invoke_unlocked()`]: fn.invoke_unlocked.html
+#[cfg(any(target_os = "linux", feature = "test-mock"))]
+pub(crate) fn guard_locked<C, F>(shared: &Mutex<C>, f: F)
+where
+    C: CallbackContext + AsRaw,
+    F: FnOnce(&mut C),
+{
+    let _dispatching = Dispatching::enter();
+
+    let mut span = None;
+
+    let calls = collect_unlocked(|| {
+        let mut context = shared.lock().unwrap_or_else(PoisonError::into_inner);
+        span = context.span().cloned();
+
+        // the pointer is derived from the guard, which outlives the call
+        unsafe { guard(context.as_raw(), f) }
+    });
+
+    let _entered = span.as_ref().map(Span::enter);
+
+    for call in calls {
+        let payload = match panic::catch_unwind(AssertUnwindSafe(call)) {
+            Ok(()) => continue,
+            Err(payload) => payload,
+        };
+
+        let message = panic_message(payload.as_ref());
+
+        let reports = collect_unlocked(|| {
+            let context = shared.lock().unwrap_or_else(PoisonError::into_inner);
+            error!("[{}] Callback panicked: {}", context.id(), message);
+            context.invoke_error(Error::CallbackPanicked {
+                message: message.clone(),
+            });
+        });
+
+        let reported =
+            panic::catch_unwind(AssertUnwindSafe(|| reports.into_iter().for_each(|f| f())));
+
+        if reported.is_err() {
+            error!("Callback panicked and could not be reported: {}", message);
+        }
+    }
+}
+
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+/// Copies `len` bytes of raw data received from the mDNS implementation; returning `Err` naming
+/// `what` if the data is missing.
+///
+/// # Safety
+/// This function is unsafe because `data` is dereferenced.
+#[cfg(not(feature = "test-mock"))]
+pub(crate) unsafe fn raw_to_bytes(data: *const c_void, len: usize, what: &str) -> Result<Vec<u8>> {
+    if len == 0 {
+        return Ok(vec![]);
+    }
+
+    ensure_not_null!(data, what);
+
+    Ok(slice::from_raw_parts(data as *const u8, len).to_vec())
+}
+
 /// Helper trait to unwrap a type to a `*const T` or a null-pointer if not present.
+#[cfg(not(feature = "test-mock"))]
 pub trait UnwrapOrNull<T> {
     /// Unwraps this type to `*const T` or `ptr::null()` if not present.
     fn unwrap_or_null(&self) -> *const T;
 }
 
+#[cfg(not(feature = "test-mock"))]
 impl<T> UnwrapOrNull<T> for Option<*const T> {
     fn unwrap_or_null(&self) -> *const T {
         self.unwrap_or_else(ptr::null)
@@ -38,72 +348,107 @@ impl<T> UnwrapOrNull<T> for Option<*const T> {
 }
 
 /// Helper trait to unwrap a type to a `*mut T` or a null-pointer if not present.
-#[cfg(target_os = "linux")]
+#[cfg(all(target_os = "linux", not(feature = "test-mock")))]
 pub trait UnwrapMutOrNull<T> {
     /// Unwraps this type to `*mut T` or `ptr::null_mut()` if not present.
     fn unwrap_mut_or_null(&mut self) -> *mut T;
 }
 
-#[cfg(target_os = "linux")]
+#[cfg(all(target_os = "linux", not(feature = "test-mock")))]
 impl<T> UnwrapMutOrNull<T> for Option<*mut T> {
     fn unwrap_mut_or_null(&mut self) -> *mut T {
         self.unwrap_or_else(ptr::null_mut)
     }
 }
 
-#[cfg(target_vendor = "apple")]
+#[cfg(all(target_vendor = "apple", not(feature = "test-mock")))]
 pub(crate) mod bonjour {
+    use crate::error::Error;
     use crate::Result;
     use libc::{fd_set, suseconds_t, time_t, timeval};
     use std::time::Duration;
-    use std::{mem, ptr};
+    use std::{io, mem, ptr};
 
-    /// Performs a unix `select()` on the specified `sock_fd` and `timeout`. Returns the select result
-    /// or `Err` if the result is negative.
+    /// Performs a unix `select()` on the specified `sock_fd` and `timeout`, which also returns once
+    /// `wake_fd` is readable. Returns whether `sock_fd` is readable, or `Err` if the socket is
+    /// invalid or the result is negative. An interrupted `select()` returns `false`.
     ///
     /// # Safety
     /// This function is unsafe because it directly interfaces with C-library system calls.
-    pub unsafe fn read_select(sock_fd: i32, timeout: Duration) -> Result<u32> {
+    pub unsafe fn read_select(
+        sock_fd: i32,
+        wake_fd: Option<i32>,
+        timeout: Duration,
+    ) -> Result<bool> {
+        if sock_fd < 0 {
+            return Err(Error::DaemonNotRunning {
+                detail: "the service ref is not connected to mDNSResponder".to_string(),
+            });
+        }
+
         let mut read_flags: fd_set = mem::zeroed();
 
         libc::FD_ZERO(&mut read_flags);
         libc::FD_SET(sock_fd, &mut read_flags);
 
+        if let Some(wake_fd) = wake_fd {
+            libc::FD_SET(wake_fd, &mut read_flags);
+        }
+
         let tv_sec = timeout.as_secs() as time_t;
         let tv_usec = timeout.subsec_micros() as suseconds_t;
         let mut timeout = timeval { tv_sec, tv_usec };
 
         let result = libc::select(
-            sock_fd + 1,
+            sock_fd.max(wake_fd.unwrap_or(-1)) + 1,
             &mut read_flags,
             ptr::null_mut(),
             ptr::null_mut(),
             &mut timeout,
         );
 
-        if result < 0 {
+        if result < 0 && io::Error::last_os_error().kind() == io::ErrorKind::Interrupted {
+            Ok(false)
+        } else if result < 0 {
             Err("select(): returned error status".into())
         } else {
-            Ok(result as u32)
+            Ok(result > 0 && libc::FD_ISSET(sock_fd, &read_flags))
         }
     }
 }
 
-#[cfg(target_vendor = "pc")]
+#[cfg(all(target_vendor = "pc", not(feature = "test-mock")))]
 pub(crate) mod bonjour {
+    use crate::error::Error;
     use crate::Result;
-    use bonjour_sys::{dnssd_sock_t, fd_set, select, timeval};
-    #[cfg(target_vendor = "apple")]
-    use std::mem;
-    use std::ptr;
+    use bonjour_sys::{dnssd_sock_t, fd_set, select, timeval, WSAGetLastError};
+    use libc::c_int;
     use std::time::Duration;
+    use std::{mem, ptr};
 
-    /// Performs a unix `select()` on the specified `sock_fd` and `timeout`. Returns the select result
-    /// or `Err` if the result is negative.
+    /// Value of `DNSServiceRefSockFD()` for a ref that is not connected to the Bonjour service
+    const INVALID_SOCKET: dnssd_sock_t = !0;
+    const SOCKET_ERROR: c_int = -1;
+
+    const WSAEINTR: c_int = 10004;
+    const WSAENOTSOCK: c_int = 10038;
+    const WSANOTINITIALISED: c_int = 10093;
+
+    /// Performs a Winsock `select()` on the specified `sock_fd` and `timeout`, which also returns
+    /// once `wake_fd` is readable. Returns whether `sock_fd` is readable, or `Err` if the socket is
+    /// invalid or the call fails. An interrupted `select()` returns `false`.
     ///
     /// # Safety
     /// This function is unsafe because it directly interfaces with C-library system calls.
-    pub unsafe fn read_select(sock_fd: dnssd_sock_t, timeout: Duration) -> Result<u32> {
+    pub unsafe fn read_select(
+        sock_fd: dnssd_sock_t,
+        wake_fd: Option<dnssd_sock_t>,
+        timeout: Duration,
+    ) -> Result<bool> {
+        if sock_fd == INVALID_SOCKET {
+            return Err(not_running());
+        }
+
         if timeout.as_secs() > i32::MAX as u64 {
             return Err(
                 "Invalid timeout duration, as_secs() value exceeds ::libc::c_long. ".into(),
@@ -115,18 +460,113 @@ pub(crate) mod bonjour {
             tv_usec: timeout.subsec_micros() as ::libc::c_long,
         };
 
-        let mut set: fd_set = fd_set {
-            fd_count: 1,
-            fd_array: [0; 64],
-        };
-        set.fd_array[0] = sock_fd;
+        let mut read_set: fd_set = mem::zeroed();
+        fd_set_insert(&mut read_set, sock_fd);
+
+        if let Some(wake_fd) = wake_fd {
+            fd_set_insert(&mut read_set, wake_fd);
+        }
 
-        let result = select(0, &mut set, ptr::null_mut(), &mut set, &timeout);
+        let result = select(0, &mut read_set, ptr::null_mut(), ptr::null_mut(), &timeout);
 
-        if result < 0 {
-            Err("select(): returned error status".into())
+        if result == SOCKET_ERROR {
+            select_error(WSAGetLastError())
         } else {
-            Ok(result as u32)
+            // `select()` leaves only the sockets that are readable in the set
+            Ok(read_set.fd_array[..read_set.fd_count as usize].contains(&sock_fd))
         }
     }
+
+    /// Equivalent of the `FD_SET` macro of Winsock, where an `fd_set` is an array of sockets
+    /// rather than a bit set.
+    fn fd_set_insert(set: &mut fd_set, sock_fd: dnssd_sock_t) {
+        let count = set.fd_count as usize;
+
+        if !set.fd_array[..count].contains(&sock_fd) && count < set.fd_array.len() {
+            set.fd_array[count] = sock_fd;
+            set.fd_count += 1;
+        }
+    }
+
+    fn select_error(code: c_int) -> Result<bool> {
+        match code {
+            WSAEINTR => Ok(false),
+            WSAENOTSOCK => Err(not_running()),
+            WSANOTINITIALISED => Err("select(): Winsock has not been initialized".into()),
+            _ => Err(format!("select(): returned error status (WSA error: {})", code).into()),
+        }
+    }
+
+    fn not_running() -> Error {
+        Error::DaemonNotRunning {
+            detail: "the service ref is not connected to the Bonjour service, check that \
+                     Bonjour is installed and running"
+                .to_string(),
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn fd_set_insert_adds_socket_once() {
+            let mut set: fd_set = unsafe { mem::zeroed() };
+
+            fd_set_insert(&mut set, 42);
+            fd_set_insert(&mut set, 42);
+
+            assert_eq!(set.fd_count, 1);
+            assert_eq!(set.fd_array[0], 42);
+        }
+
+        #[test]
+        fn read_select_rejects_invalid_socket() {
+            assert!(matches!(
+                unsafe { read_select(INVALID_SOCKET, None, Duration::from_secs(0)) },
+                Err(Error::DaemonNotRunning { .. })
+            ));
+        }
+
+        #[test]
+        fn select_error_maps_wsa_errors() {
+            assert_eq!(select_error(WSAEINTR), Ok(false));
+            assert!(matches!(
+                select_error(WSAENOTSOCK),
+                Err(Error::DaemonNotRunning { .. })
+            ));
+            assert_eq!(
+                select_error(10050),
+                Err("select(): returned error status (WSA error: 10050)".into())
+            );
+        }
+    }
+}
+
+#[cfg(all(test, not(feature = "test-mock")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raw_to_bytes_copies_data() {
+        let data = [1u8, 2, 3];
+
+        assert_eq!(
+            unsafe { raw_to_bytes(data.as_ptr() as *const c_void, 2, "data") },
+            Ok(vec![1, 2])
+        );
+    }
+
+    #[test]
+    fn raw_to_bytes_allows_null_empty_data() {
+        assert_eq!(unsafe { raw_to_bytes(ptr::null(), 0, "data") }, Ok(vec![]));
+    }
+
+    #[test]
+    fn raw_to_bytes_rejects_null_data() {
+        assert_eq!(
+            unsafe { raw_to_bytes(ptr::null(), 4, "record data") },
+            Err("missing record data".into())
+        );
+    }
 }
@@ -0,0 +1,87 @@
+//! User data for callbacks that must not keep their context alive
+
+use super::{AsRaw, CallbackContext};
+use libc::c_void;
+use std::fmt;
+use std::mem::ManuallyDrop;
+use std::sync::{Mutex, Weak};
+
+/// User data that refers to a context shared in an `Arc<Mutex<C>>` without keeping it alive.
+///
+/// Callbacks receiving it through [`guard_weak()`] do nothing once the context has been dropped.
+/// The user data must outlive every object it has been passed to, which is the case if it is
+/// stored in the context after the objects invoking the callbacks.
+///
+/// [`guard_weak()`]: fn.guard_weak.html
+pub(crate) struct WeakUserdata<C> {
+    raw: *const Mutex<C>,
+}
+
+impl<C> WeakUserdata<C> {
+    /// Creates user data from a weak reference to the context.
+    pub(crate) fn new(context: Weak<Mutex<C>>) -> Self {
+        Self {
+            raw: Weak::into_raw(context),
+        }
+    }
+
+    /// Returns the pointer to pass to the mDNS implementation.
+    pub(crate) fn as_raw(&self) -> *mut c_void {
+        self.raw as *mut c_void
+    }
+}
+
+impl<C> Drop for WeakUserdata<C> {
+    fn drop(&mut self) {
+        unsafe { drop(Weak::from_raw(self.raw)) };
+    }
+}
+
+impl<C> fmt::Debug for WeakUserdata<C> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_tuple("WeakUserdata").field(&self.raw).finish()
+    }
+}
+
+// the pointer is only used to upgrade the weak reference, which is as thread-safe as the `Weak`
+unsafe impl<C: Send> Send for WeakUserdata<C> {}
+
+/// Executes `f` like [`guard_locked()`] on the context that `userdata`, a pointer obtained from a
+/// [`WeakUserdata`], refers to. `f` is not executed if the context has already been dropped. If
+/// the owner of the context is dropped by `f` or by a user callback, the context is only dropped
+/// once the event loop has finished dispatching.
+///
+/// The context must not be locked while calling into the mDNS implementation with functions that
+/// may invoke the callback before returning, such as `avahi_client_new()`.
+///
+/// [`guard_locked()`]: ../fn.guard_locked.html
+/// [`WeakUserdata`]: struct.WeakUserdata.html
+///
+/// # Safety
+/// This function is unsafe because `userdata` is dereferenced as a `Weak<Mutex<C>>`.
+pub(crate) unsafe fn guard_weak<C, F>(userdata: *mut c_void, f: F)
+where
+    C: CallbackContext + AsRaw + 'static,
+    F: FnOnce(&mut C),
+{
+    assert_not_null!(userdata);
+
+    let _dispatching = super::Dispatching::enter();
+
+    // the reference is owned by the `WeakUserdata` and must not be released here
+    let weak = ManuallyDrop::new(Weak::from_raw(userdata as *const Mutex<C>));
+
+    let shared = match weak.upgrade() {
+        Some(shared) => shared,
+        None => {
+            debug!("Ignoring callback for dropped context");
+            return;
+        }
+    };
+
+    super::guard_locked(&shared, f);
+
+    // the owner may have been dropped by `f` or by a user callback, which must not free the context from within the
+    // callback
+    super::drop_after_dispatch(shared);
+}
@@ -0,0 +1,151 @@
+//! Filtering of the services a browser reports
+
+use crate::{DiscoveryFilter, NameFilter, ServiceDiscovery, ServiceKey};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Applies the filters set with `set_name_filter()` and `set_discovery_filter()` to the services
+/// found by a browser.
+///
+/// A service whose name is filtered out is neither resolved nor reported. A service whose every
+/// discovery so far has been filtered out is not reported as removed either, so that the
+/// callbacks only ever see both or neither.
+#[derive(Default)]
+pub(crate) struct BrowserFilter {
+    name_filter: Option<Box<NameFilter>>,
+    discovery_filter: Option<Box<DiscoveryFilter>>,
+    /// Whether a discovery of the service has passed the discovery filter
+    reported: HashMap<ServiceKey, bool>,
+}
+
+impl BrowserFilter {
+    pub(crate) fn set_name_filter(&mut self, name_filter: Box<NameFilter>) {
+        self.name_filter = Some(name_filter);
+    }
+
+    pub(crate) fn set_discovery_filter(&mut self, discovery_filter: Box<DiscoveryFilter>) {
+        self.discovery_filter = Some(discovery_filter);
+    }
+
+    /// Returns true if the service found under `name` should be resolved.
+    pub(crate) fn found(&self, name: &str) -> bool {
+        self.name_filter.as_ref().is_none_or(|f| f(name))
+    }
+
+    /// Returns true if `discovery` should be reported.
+    pub(crate) fn resolved(&mut self, discovery: &ServiceDiscovery) -> bool {
+        let f = match &self.discovery_filter {
+            Some(f) => f,
+            None => return true,
+        };
+
+        let passed = f(discovery);
+        let reported = self.reported.entry(discovery.key()).or_default();
+
+        *reported |= passed;
+
+        passed
+    }
+
+    /// Returns true if the removal of the service should be reported, which is the case unless
+    /// its name or every discovery of it has been filtered out.
+    pub(crate) fn removed(&self, key: &ServiceKey) -> bool {
+        self.found(key.name()) && self.reported.get(key).copied().unwrap_or(true)
+    }
+
+    /// Forgets which services have been reported, e.g. because browsing is restarted.
+    pub(crate) fn clear(&mut self) {
+        self.reported.clear();
+    }
+}
+
+impl fmt::Debug for BrowserFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("BrowserFilter")
+            .field("name_filter", &self.name_filter.is_some())
+            .field("discovery_filter", &self.discovery_filter.is_some())
+            .field("reported", &self.reported)
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::*;
+    use crate::tests::{discovery_builder, service_key};
+    use crate::TxtRecord;
+
+    fn discovery(name: &str, role: &str) -> ServiceDiscovery {
+        let mut txt = TxtRecord::new();
+        txt.insert("role", role).unwrap();
+
+        discovery_builder(name).txt(Some(txt)).build().unwrap()
+    }
+
+    fn gateways_only() -> BrowserFilter {
+        let mut filter = BrowserFilter::default();
+
+        filter.set_discovery_filter(Box::new(|discovery| {
+            discovery.txt().and_then(|txt| txt.get("role")).as_deref() == Some("gateway")
+        }));
+
+        filter
+    }
+
+    #[test]
+    fn everything_passes_without_filters() {
+        let mut filter = BrowserFilter::default();
+
+        assert!(filter.found("foo"));
+        assert!(filter.resolved(&discovery("foo", "sensor")));
+        assert!(filter.removed(&service_key("foo")));
+    }
+
+    #[test]
+    fn name_filter_applies_to_found_and_removed_services() {
+        let mut filter = BrowserFilter::default();
+
+        filter.set_name_filter(Box::new(|name| name.starts_with("gw-")));
+
+        assert!(filter.found("gw-1"));
+        assert!(!filter.found("printer"));
+        assert!(filter.removed(&service_key("gw-1")));
+        assert!(!filter.removed(&service_key("printer")));
+    }
+
+    #[test]
+    fn removal_of_filtered_discovery_is_suppressed() {
+        let mut filter = gateways_only();
+
+        assert!(!filter.resolved(&discovery("foo", "sensor")));
+        assert!(!filter.removed(&service_key("foo")));
+    }
+
+    #[test]
+    fn removal_of_reported_discovery_is_reported() {
+        let mut filter = gateways_only();
+
+        assert!(filter.resolved(&discovery("foo", "gateway")));
+        assert!(filter.removed(&service_key("foo")));
+    }
+
+    #[test]
+    fn removal_is_reported_once_any_discovery_has_been_reported() {
+        let mut filter = gateways_only();
+
+        assert!(filter.resolved(&discovery("foo", "gateway")));
+        assert!(!filter.resolved(&discovery("foo", "sensor")));
+        assert!(filter.removed(&service_key("foo")));
+    }
+
+    #[test]
+    fn clear_forgets_reported_services() {
+        let mut filter = gateways_only();
+
+        filter.resolved(&discovery("foo", "sensor"));
+        filter.clear();
+
+        assert!(filter.removed(&service_key("foo")));
+    }
+}
@@ -0,0 +1,270 @@
+//! Construction and parsing of the full names of service instances
+//!
+//! A full name joins the name of a service instance with its type and domain, e.g.
+//! `Brother\032HL-2270DW._ipp._tcp.local.`. Dots, backslashes and control characters in the
+//! instance name are escaped as described in RFC 6763, section 4.3, so that the name remains a
+//! single label.
+
+use crate::error::Error;
+use crate::service_name::MAX_NAME_LEN;
+use crate::{service_type, Result, ServiceType};
+
+/// Returns the escaped full name of the service instance `name` of `service_type` in `domain`
+/// (e.g. `local`). The name ends with a dot, and sub-types of `service_type` are not included.
+///
+/// Returns `Error::NameTooLong` if `name` is longer than [`MAX_NAME_LEN`] bytes.
+///
+/// # Note
+/// Bonjour constructs the name with `DNSServiceConstructFullName`; Avahi has no equivalent, so
+/// the name is escaped the same way in Rust.
+///
+/// [`MAX_NAME_LEN`]: ../service_name/constant.MAX_NAME_LEN.html
+pub fn construct(name: &str, service_type: &ServiceType, domain: &str) -> Result<String> {
+    if name.is_empty() {
+        return Err("service name must not be empty".into());
+    }
+
+    if name.len() > MAX_NAME_LEN {
+        return Err(Error::NameTooLong {
+            bytes: name.len(),
+            max: MAX_NAME_LEN,
+        });
+    }
+
+    let domain = domain.strip_suffix('.').unwrap_or(domain);
+
+    if domain.is_empty() {
+        return Err("domain must not be empty".into());
+    }
+
+    #[cfg(any(target_os = "linux", feature = "test-mock"))]
+    return Ok(join(name, service_type, domain));
+    #[cfg(all(
+        any(target_vendor = "apple", target_vendor = "pc"),
+        not(feature = "test-mock")
+    ))]
+    return crate::bonjour::bonjour_util::construct_full_name(name, service_type, domain);
+}
+
+/// Splits the full name of a service instance, as returned by [`construct()`], into the
+/// unescaped instance name, the service type and the domain. The domain is returned without a
+/// trailing dot, as in a [`ServiceDiscovery`].
+///
+/// [`construct()`]: fn.construct.html
+/// [`ServiceDiscovery`]: ../struct.ServiceDiscovery.html
+pub fn parse(fullname: &str) -> Result<(String, ServiceType, String)> {
+    let fullname = fullname.strip_suffix('.').unwrap_or(fullname);
+    let (name, rest) = split_label(fullname)?;
+    let (service_name, rest) = split_label(rest)?;
+    let (protocol, domain) = split_label(rest)?;
+
+    if domain.is_empty() {
+        return Err(format!("missing domain in full name `{}`", fullname).into());
+    }
+
+    if !service_name.starts_with(b"_") || !protocol.starts_with(b"_") {
+        return Err(format!("missing service type in full name `{}`", fullname).into());
+    }
+
+    let service_type = service_type::parse_advertised(&format!(
+        "{}.{}",
+        String::from_utf8_lossy(&service_name),
+        String::from_utf8_lossy(&protocol)
+    ))?;
+
+    let name = String::from_utf8(name)
+        .map_err(|_| format!("service name in `{}` is not valid UTF-8", fullname))?;
+
+    Ok((name, service_type, domain.to_string()))
+}
+
+/// Escapes `name` and joins it with `service_type` and `domain` like
+/// `DNSServiceConstructFullName` does.
+#[cfg(any(target_os = "linux", feature = "test-mock", test))]
+fn join(name: &str, service_type: &ServiceType, domain: &str) -> String {
+    let mut escaped = String::new();
+
+    for c in name.chars() {
+        match c {
+            '.' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '\0'..=' ' => escaped.push_str(&format!("\\{:03}", c as u32)),
+            _ => escaped.push(c),
+        }
+    }
+
+    format!("{}.{}.{}.", escaped, service_type, domain)
+}
+
+/// Splits off the first label of `name`, returning it unescaped along with the rest of the name.
+fn split_label(name: &str) -> Result<(Vec<u8>, &str)> {
+    let bytes = name.as_bytes();
+    let mut label = vec![];
+    let mut i = 0;
+
+    while i < bytes.len() {
+        match bytes[i] {
+            b'.' => return Ok((label, &name[i + 1..])),
+            b'\\' => {
+                let digits = bytes
+                    .get(i + 1..i + 4)
+                    .filter(|d| d.iter().all(u8::is_ascii_digit));
+
+                match (digits, bytes.get(i + 1)) {
+                    (Some(digits), _) => {
+                        let value = digits
+                            .iter()
+                            .fold(0u32, |value, d| value * 10 + u32::from(d - b'0'));
+
+                        if value > u32::from(u8::MAX) {
+                            return Err(format!("invalid escape sequence in `{}`", name).into());
+                        }
+
+                        label.push(value as u8);
+                        i += 4;
+                    }
+                    (None, Some(c)) => {
+                        label.push(*c);
+                        i += 2;
+                    }
+                    (None, None) => {
+                        return Err(format!("incomplete escape sequence in `{}`", name).into())
+                    }
+                }
+            }
+            byte => {
+                label.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    Err(format!("expected more labels in `{}`", name).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn ipp() -> ServiceType {
+        ServiceType::new("ipp", "tcp").unwrap()
+    }
+
+    const NAMES: &[(&str, &str)] = &[
+        ("printer", "printer._ipp._tcp.local."),
+        (
+            "Brother HL-2270DW",
+            "Brother\\032HL-2270DW._ipp._tcp.local.",
+        ),
+        ("foo.bar", "foo\\.bar._ipp._tcp.local."),
+        ("back\\slash", "back\\\\slash._ipp._tcp.local."),
+        ("tab\tstop", "tab\\009stop._ipp._tcp.local."),
+        ("Drucker Büro", "Drucker\\032Büro._ipp._tcp.local."),
+        ("Büro-Drucker 🖨", "Büro-Drucker\\032🖨._ipp._tcp.local."),
+    ];
+
+    #[test]
+    fn construct_escapes_name() {
+        for (name, fullname) in NAMES {
+            assert_eq!(construct(name, &ipp(), "local").unwrap(), *fullname);
+        }
+    }
+
+    #[test]
+    fn construct_matches_rust_escaping() {
+        for (name, _) in NAMES {
+            assert_eq!(
+                construct(name, &ipp(), "local").unwrap(),
+                join(name, &ipp(), "local")
+            );
+        }
+    }
+
+    #[test]
+    fn construct_accepts_domain_with_trailing_dot() {
+        assert_eq!(
+            construct("printer", &ipp(), "local.").unwrap(),
+            "printer._ipp._tcp.local."
+        );
+    }
+
+    #[test]
+    fn construct_ignores_sub_types() {
+        let service_type = ServiceType::with_sub_types("ipp", "tcp", vec!["color"]).unwrap();
+
+        assert_eq!(
+            construct("printer", &service_type, "local").unwrap(),
+            "printer._ipp._tcp.local."
+        );
+    }
+
+    #[test]
+    fn construct_rejects_invalid_names() {
+        assert!(construct("", &ipp(), "local").is_err());
+        assert!(construct("printer", &ipp(), "").is_err());
+        assert_eq!(
+            construct(&"a".repeat(MAX_NAME_LEN + 1), &ipp(), "local"),
+            Err(Error::NameTooLong {
+                bytes: MAX_NAME_LEN + 1,
+                max: MAX_NAME_LEN
+            })
+        );
+    }
+
+    #[test]
+    fn parse_reverses_construct() {
+        for (name, fullname) in NAMES {
+            assert_eq!(
+                parse(fullname).unwrap(),
+                (name.to_string(), ipp(), "local".to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn parse_accepts_name_without_trailing_dot() {
+        assert_eq!(
+            parse("foo\\.bar._ipp._tcp.example.com").unwrap(),
+            ("foo.bar".to_string(), ipp(), "example.com".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_unescapes_multi_byte_character() {
+        // an escaped character is taken literally, even if it spans more than one byte
+        assert_eq!(
+            parse("\\🖨\\.b\\üro._ipp._tcp.local.").unwrap(),
+            ("🖨.büro".to_string(), ipp(), "local".to_string())
+        );
+    }
+
+    #[test]
+    fn parse_rejects_malformed_names() {
+        assert!(parse("printer._ipp._tcp").is_err());
+        assert!(parse("printer._ipp").is_err());
+        assert!(parse("printer\\").is_err());
+        assert!(parse("printer\\256._ipp._tcp.local.").is_err());
+        assert!(parse("printer.ipp.tcp.local.").is_err());
+    }
+
+    proptest! {
+        #[test]
+        fn parse_does_not_panic_on_arbitrary_names(fullname in ".*") {
+            let _ = parse(&fullname);
+        }
+
+        #[test]
+        fn parse_reverses_join_for_arbitrary_names(
+            name in ".{1,63}",
+            domain in "[a-z]{1,10}(\\.[a-z]{1,10})?",
+        ) {
+            prop_assert_eq!(
+                parse(&join(&name, &ipp(), &domain)).unwrap(),
+                (name, ipp(), domain)
+            );
+        }
+    }
+}
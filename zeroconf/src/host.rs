@@ -0,0 +1,27 @@
+//! Information about the local host
+
+use crate::Result;
+
+/// Returns the fully qualified host name services registered by this host are advertised under
+/// by default (e.g. `myhost.local`). This is the host name reported by
+/// [`ServiceDiscovery::host_name()`] when a service registered without
+/// [`TMdnsService::set_host()`] is discovered.
+///
+/// # Note
+/// Avahi is asked for the host name, so the daemon must be running. Bonjour does not expose it,
+/// so it is derived from the system's host name instead. With the `test-mock` feature, this is
+/// always `zeroconf-mock.local`.
+///
+/// [`ServiceDiscovery::host_name()`]: struct.ServiceDiscovery.html#method.host_name
+/// [`TMdnsService::set_host()`]: service/trait.TMdnsService.html#tymethod.set_host
+pub fn host_name() -> Result<String> {
+    #[cfg(all(target_os = "linux", not(feature = "test-mock")))]
+    return crate::avahi::client::host_name();
+    #[cfg(all(
+        any(target_vendor = "apple", target_vendor = "pc"),
+        not(feature = "test-mock")
+    ))]
+    return crate::bonjour::bonjour_util::host_name();
+    #[cfg(feature = "test-mock")]
+    return Ok(crate::mock::HOST_NAME.to_string());
+}
@@ -0,0 +1,70 @@
+//! Trait definition for cross-platform host name resolver
+
+use crate::{EventLoop, Id, NetworkInterface, Result};
+use std::any::Any;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+/// Interface for interacting with underlying mDNS implementation host name resolution
+/// capabilities.
+///
+/// This is useful when an application already knows a host name (e.g. `myprinter.local`) and only
+/// needs its addresses, without browsing for services.
+pub trait TMdnsHostResolver {
+    /// Creates a new `MdnsHostResolver`.
+    fn new() -> Self;
+
+    /// Returns the [`Id`] that prefixes the log output of this host resolver.
+    ///
+    /// [`Id`]: ../struct.Id.html
+    fn id(&self) -> Id;
+
+    /// Sets the network interface on which to resolve host names on.
+    ///
+    /// Most applications will want to use the default value `NetworkInterface::Unspec` to resolve
+    /// on all available interfaces.
+    fn set_network_interface(&mut self, interface: NetworkInterface);
+
+    /// Returns the network interface on which to resolve host names on.
+    fn network_interface(&self) -> NetworkInterface;
+
+    /// Sets the [`HostResolvedCallback`] that is invoked for each address the host name resolves
+    /// to.
+    ///
+    /// [`HostResolvedCallback`]: ../type.HostResolvedCallback.html
+    fn set_host_resolved_callback(&mut self, host_resolved_callback: Box<HostResolvedCallback>);
+
+    /// Sets the optional user context to pass through to the callback. This is useful if you need
+    /// to share state between pre and post-callback. The context type must implement `Any`, `Send`
+    /// and `Sync`.
+    fn set_context(&mut self, context: Box<dyn Any + Send + Sync>);
+
+    /// Returns the optional user context to pass through to the callback.
+    fn context(&self) -> Option<&(dyn Any + Send + Sync)>;
+
+    /// Starts resolving the IPv4 and IPv6 addresses of the specified `host_name`. Returns an
+    /// `EventLoop` which can be called to keep the resolver alive.
+    fn resolve(&mut self, host_name: &str) -> Result<EventLoop>;
+}
+
+/// Callback invoked from [`MdnsHostResolver`] once an address for a host name has been resolved.
+/// The callback is invoked once per address.
+///
+/// # Arguments
+/// * `resolution` - The address that was resolved
+/// * `context` - The optional user context passed through
+///
+/// [`MdnsHostResolver`]: type.MdnsHostResolver.html
+pub type HostResolvedCallback =
+    dyn Fn(Result<HostResolution>, Option<Arc<dyn Any + Send + Sync>>) + Send;
+
+/// Represents an address that has been resolved for a host name by a [`MdnsHostResolver`].
+///
+/// [`MdnsHostResolver`]: type.MdnsHostResolver.html
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Getters, Builder, BuilderDelegate, Clone, PartialEq, Eq)]
+pub struct HostResolution {
+    host_name: String,
+    address: IpAddr,
+    interface: NetworkInterface,
+}
@@ -0,0 +1,51 @@
+//! Identifiers for correlating log output
+
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+
+/// Process-wide unique identifier of a service, browser, resolver or event loop.
+///
+/// Every log message emitted on behalf of an object is prefixed with its id (e.g. `[#3]`), so that
+/// the output of several concurrent services, browsers and event loops can be told apart.
+/// Applications can include the id in their own log output to correlate it with this crate's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Id(u64);
+
+impl Id {
+    /// Returns a new id that is different from every id returned before.
+    pub(crate) fn next() -> Self {
+        Self(NEXT_ID.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// Returns the numeric value of this id.
+    pub fn get(self) -> u64 {
+        self.0
+    }
+}
+
+impl fmt::Display for Id {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn next_returns_unique_ids() {
+        let a = Id::next();
+        let b = Id::next();
+
+        assert_ne!(a, b);
+        assert!(b > a);
+    }
+
+    #[test]
+    fn display_prefixes_hash() {
+        assert_eq!(Id(3).to_string(), "#3");
+    }
+}
@@ -1,8 +1,156 @@
+//! Network interfaces to register and browse services on
+
+#[cfg(target_vendor = "pc")]
+use crate::error::Error;
+use crate::Result;
+#[cfg(any(target_os = "linux", target_vendor = "apple"))]
+use libc::if_nametoindex;
+use std::ffi::CString;
+
 /// Represents a network interface for mDNS services
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+///
+/// Interface indexes are assigned by the OS and may change across reboots, so they are best looked
+/// up with [`NetworkInterface::by_name()`] or [`interfaces()`] rather than hardcoded.
+///
+/// [`NetworkInterface::by_name()`]: enum.NetworkInterface.html#method.by_name
+/// [`interfaces()`]: fn.interfaces.html
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum NetworkInterface {
     /// No interface specified, bind to all available interfaces
     Unspec,
     /// An interface at a specified index
     AtIndex(u32),
+    /// Only the local machine, so that services are not advertised to the network
+    ///
+    /// On Bonjour, services registered locally are found only by browsers on the same machine, and
+    /// browsing locally finds every service registered on the machine. On Avahi, this binds to the
+    /// loopback interface.
+    LocalOnly,
+}
+
+impl NetworkInterface {
+    /// Returns the interface with the specified OS `name` (e.g. `eth0` or `en0`), or `Err` if
+    /// there is no such interface.
+    ///
+    /// ```no_run
+    /// use zeroconf::prelude::*;
+    /// use zeroconf::{MdnsBrowser, NetworkInterface, ServiceType};
+    ///
+    /// let mut browser = MdnsBrowser::new(ServiceType::new("http", "tcp").unwrap());
+    ///
+    /// browser.set_network_interface(NetworkInterface::by_name("eth0").unwrap());
+    /// ```
+    pub fn by_name(name: &str) -> Result<Self> {
+        let c_name = CString::new(name)
+            .map_err(|_| format!("invalid network interface name `{}`", name.escape_debug()))?;
+
+        match unsafe { if_nametoindex(c_name.as_ptr()) } {
+            0 => Err(format!(
+                "no network interface named `{}`: {}",
+                name,
+                std::io::Error::last_os_error()
+            )
+            .into()),
+            index => Ok(Self::AtIndex(index)),
+        }
+    }
+}
+
+/// Describes a network interface of the local machine, as listed by [`interfaces()`].
+///
+/// [`interfaces()`]: fn.interfaces.html
+#[derive(Debug, Getters, Clone, PartialEq, Eq)]
+pub struct InterfaceInfo {
+    /// The OS interface index
+    index: u32,
+    /// The OS interface name (e.g. `eth0` or `en0`)
+    name: String,
+    /// Whether this is a loopback interface
+    loopback: bool,
+    /// Whether the interface supports multicast, which mDNS requires
+    multicast: bool,
+    /// Whether the interface is up and has a link
+    up: bool,
+}
+
+impl InterfaceInfo {
+    /// Returns the [`NetworkInterface`] to bind a service or browser to this interface.
+    ///
+    /// [`NetworkInterface`]: enum.NetworkInterface.html
+    pub fn interface(&self) -> NetworkInterface {
+        NetworkInterface::AtIndex(self.index)
+    }
+}
+
+/// Lists the network interfaces of the local machine, ordered by index.
+///
+/// # Note
+/// Listing interfaces is not supported on Windows, where `Error::Unsupported` is returned.
+/// [`NetworkInterface::by_name()`] is supported on every platform.
+///
+/// [`NetworkInterface::by_name()`]: enum.NetworkInterface.html#method.by_name
+#[cfg(any(target_os = "linux", target_vendor = "apple"))]
+pub fn interfaces() -> Result<Vec<InterfaceInfo>> {
+    let mut addrs = std::ptr::null_mut();
+
+    if unsafe { libc::getifaddrs(&mut addrs) } != 0 {
+        return Err(format!("getifaddrs() failed: {}", std::io::Error::last_os_error()).into());
+    }
+
+    let mut interfaces: Vec<InterfaceInfo> = vec![];
+    let mut next = addrs;
+
+    // the list contains an entry for every address of an interface
+    while let Some(addr) = unsafe { next.as_ref() } {
+        next = addr.ifa_next;
+
+        if addr.ifa_name.is_null() {
+            continue;
+        }
+
+        let name = unsafe { std::ffi::CStr::from_ptr(addr.ifa_name) };
+        let index = unsafe { if_nametoindex(name.as_ptr()) };
+        let name = name.to_string_lossy();
+
+        if index == 0 || interfaces.iter().any(|i| i.name == name) {
+            continue;
+        }
+
+        let up = (libc::IFF_UP | libc::IFF_RUNNING) as libc::c_uint;
+
+        interfaces.push(InterfaceInfo {
+            index,
+            name: name.into_owned(),
+            loopback: addr.ifa_flags & libc::IFF_LOOPBACK as libc::c_uint != 0,
+            multicast: addr.ifa_flags & libc::IFF_MULTICAST as libc::c_uint != 0,
+            up: addr.ifa_flags & up == up,
+        });
+    }
+
+    unsafe { libc::freeifaddrs(addrs) };
+
+    interfaces.sort_by_key(|i| i.index);
+
+    Ok(interfaces)
+}
+
+/// Lists the network interfaces of the local machine, ordered by index.
+///
+/// # Note
+/// Listing interfaces is not supported on Windows, where `Error::Unsupported` is returned.
+/// [`NetworkInterface::by_name()`] is supported on every platform.
+///
+/// [`NetworkInterface::by_name()`]: enum.NetworkInterface.html#method.by_name
+#[cfg(target_vendor = "pc")]
+pub fn interfaces() -> Result<Vec<InterfaceInfo>> {
+    Err(Error::Unsupported(
+        "listing network interfaces is not supported on Windows".to_string(),
+    ))
+}
+
+#[cfg(target_vendor = "pc")]
+#[link(name = "iphlpapi")]
+extern "system" {
+    fn if_nametoindex(name: *const libc::c_char) -> u32;
 }
@@ -0,0 +1,335 @@
+//! Watches the network interfaces of the local machine on behalf of the browsers
+//!
+//! On Linux and macOS, a routing socket is opened that receives a message whenever a link or an
+//! address changes. The interfaces are then listed again with [`interfaces()`] and compared with
+//! the interfaces that were up before. Interface changes are not tracked on Windows. With the
+//! mock backend, the changes are the ones simulated with [`mock::interface_changed()`].
+//!
+//! [`interfaces()`]: ../fn.interfaces.html
+//! [`mock::interface_changed()`]: ../mock/fn.interface_changed.html
+
+#[cfg(all(
+    any(target_os = "linux", target_vendor = "apple"),
+    not(feature = "test-mock")
+))]
+use crate::NetworkInterface;
+use crate::{InterfaceChange, Result};
+#[cfg(all(
+    any(target_os = "linux", target_vendor = "apple"),
+    not(feature = "test-mock")
+))]
+use std::collections::BTreeSet;
+
+/// Reports the network interfaces that have gone down or come up since it was last asked, so that
+/// a browser can mark the services found on them as degraded or reachable again.
+#[cfg(all(
+    any(target_os = "linux", target_vendor = "apple"),
+    not(feature = "test-mock")
+))]
+#[derive(Debug)]
+pub(crate) struct InterfaceWatcher {
+    socket: std::os::unix::io::RawFd,
+    /// Indexes of the interfaces that were up when they were last listed
+    up: BTreeSet<u32>,
+}
+
+#[cfg(all(
+    any(target_os = "linux", target_vendor = "apple"),
+    not(feature = "test-mock")
+))]
+impl InterfaceWatcher {
+    /// Opens the routing socket and lists the interfaces that are currently up.
+    pub(crate) fn new() -> Result<Self> {
+        let mut watcher = Self {
+            socket: open_socket()?,
+            up: BTreeSet::new(),
+        };
+
+        watcher.up = up_interfaces(&crate::interfaces()?);
+
+        Ok(watcher)
+    }
+
+    /// Returns the interfaces that have gone down or come up since the previous call. Does not
+    /// block.
+    pub(crate) fn changes(&mut self) -> Vec<InterfaceChange> {
+        if !self.drain() {
+            return vec![];
+        }
+
+        match crate::interfaces() {
+            Ok(interfaces) => {
+                let up = up_interfaces(&interfaces);
+                let changes = diff(&self.up, &up);
+                self.up = up;
+                changes
+            }
+            Err(e) => {
+                warn!("Could not list network interfaces: {}", e);
+                vec![]
+            }
+        }
+    }
+
+    /// Reads every pending message from the routing socket and returns true if there was any.
+    /// The messages are not parsed, the interfaces are listed again instead.
+    fn drain(&self) -> bool {
+        let mut buffer = [0u8; 8192];
+        let mut received = false;
+
+        loop {
+            let len = unsafe {
+                libc::recv(
+                    self.socket,
+                    buffer.as_mut_ptr() as *mut libc::c_void,
+                    buffer.len(),
+                    libc::MSG_DONTWAIT,
+                )
+            };
+
+            if len > 0 {
+                received = true;
+                continue;
+            }
+
+            if len == 0 {
+                return received;
+            }
+
+            let error = std::io::Error::last_os_error();
+
+            match error.raw_os_error() {
+                // messages have been dropped because the socket was not read in time
+                Some(libc::ENOBUFS) => received = true,
+                Some(libc::EINTR) => {}
+                _ => {
+                    if error.kind() != std::io::ErrorKind::WouldBlock {
+                        warn!("Could not read from routing socket: {}", error);
+                    }
+
+                    return received;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(all(
+    any(target_os = "linux", target_vendor = "apple"),
+    not(feature = "test-mock")
+))]
+impl Drop for InterfaceWatcher {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.socket) };
+    }
+}
+
+/// Stands in for the interface watcher on Windows, where interface changes are not tracked.
+#[cfg(all(target_vendor = "pc", not(feature = "test-mock")))]
+#[derive(Debug)]
+pub(crate) struct InterfaceWatcher;
+
+#[cfg(all(target_vendor = "pc", not(feature = "test-mock")))]
+impl InterfaceWatcher {
+    /// Returns `Error::Unsupported`.
+    pub(crate) fn new() -> Result<Self> {
+        Err(crate::error::Error::Unsupported(
+            "watching network interfaces is not supported on Windows".to_string(),
+        ))
+    }
+
+    /// Returns no changes.
+    pub(crate) fn changes(&mut self) -> Vec<InterfaceChange> {
+        vec![]
+    }
+}
+
+/// Reports the interface changes simulated with `mock::interface_changed()`.
+#[cfg(feature = "test-mock")]
+#[derive(Debug)]
+pub(crate) struct InterfaceWatcher {
+    /// The changes that have been simulated since they were last asked for
+    inbox: std::sync::Arc<std::sync::Mutex<Vec<InterfaceChange>>>,
+}
+
+#[cfg(feature = "test-mock")]
+impl InterfaceWatcher {
+    /// Subscribes to the simulated interface changes.
+    pub(crate) fn new() -> Result<Self> {
+        let inbox = std::sync::Arc::default();
+
+        crate::mock::registry::watch_interfaces(&inbox);
+
+        Ok(Self { inbox })
+    }
+
+    /// Returns the changes that have been simulated since the previous call.
+    pub(crate) fn changes(&mut self) -> Vec<InterfaceChange> {
+        let mut inbox = self
+            .inbox
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner);
+
+        std::mem::take(&mut *inbox)
+    }
+}
+
+/// Returns a new [`InterfaceWatcher`], or `None` if interface changes cannot be tracked. The
+/// services found by a browser without a watcher are never degraded.
+///
+/// [`InterfaceWatcher`]: struct.InterfaceWatcher.html
+pub(crate) fn watch() -> Option<InterfaceWatcher> {
+    match InterfaceWatcher::new() {
+        Ok(watcher) => Some(watcher),
+        Err(crate::error::Error::Unsupported(reason)) => {
+            debug!("Not tracking network interfaces: {}", reason);
+            None
+        }
+        Err(e) => {
+            warn!("Could not watch network interfaces: {}", e);
+            None
+        }
+    }
+}
+
+/// Opens a netlink socket that receives the changes to links and addresses.
+#[cfg(all(target_os = "linux", not(feature = "test-mock")))]
+fn open_socket() -> Result<std::os::unix::io::RawFd> {
+    let socket = unsafe {
+        libc::socket(
+            libc::AF_NETLINK,
+            libc::SOCK_RAW | libc::SOCK_CLOEXEC,
+            libc::NETLINK_ROUTE,
+        )
+    };
+
+    if socket < 0 {
+        return Err(os_error("socket()"));
+    }
+
+    let mut addr: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+    addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+    addr.nl_groups =
+        (libc::RTMGRP_LINK | libc::RTMGRP_IPV4_IFADDR | libc::RTMGRP_IPV6_IFADDR) as u32;
+
+    let bound = unsafe {
+        libc::bind(
+            socket,
+            &addr as *const libc::sockaddr_nl as *const libc::sockaddr,
+            std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+        )
+    };
+
+    if bound < 0 {
+        let error = os_error("bind()");
+        unsafe { libc::close(socket) };
+        return Err(error);
+    }
+
+    Ok(socket)
+}
+
+/// Opens a routing socket, which receives every change to the routing tables, including those to
+/// links and addresses.
+#[cfg(all(target_vendor = "apple", not(feature = "test-mock")))]
+fn open_socket() -> Result<std::os::unix::io::RawFd> {
+    let socket = unsafe { libc::socket(libc::PF_ROUTE, libc::SOCK_RAW, libc::AF_UNSPEC) };
+
+    if socket < 0 {
+        return Err(os_error("socket()"));
+    }
+
+    if unsafe { libc::fcntl(socket, libc::F_SETFD, libc::FD_CLOEXEC) } < 0 {
+        let error = os_error("fcntl()");
+        unsafe { libc::close(socket) };
+        return Err(error);
+    }
+
+    Ok(socket)
+}
+
+#[cfg(all(
+    any(target_os = "linux", target_vendor = "apple"),
+    not(feature = "test-mock")
+))]
+fn os_error(function: &str) -> crate::error::Error {
+    format!("{} failed: {}", function, std::io::Error::last_os_error()).into()
+}
+
+/// Returns the indexes of the interfaces that are up.
+#[cfg(all(
+    any(target_os = "linux", target_vendor = "apple"),
+    not(feature = "test-mock")
+))]
+fn up_interfaces(interfaces: &[crate::InterfaceInfo]) -> BTreeSet<u32> {
+    interfaces
+        .iter()
+        .filter(|i| *i.up())
+        .map(|i| *i.index())
+        .collect()
+}
+
+/// Returns the changes that turn the interfaces that are up in `before` into those in `after`.
+/// An interface that has been removed is reported as down.
+#[cfg(all(
+    any(target_os = "linux", target_vendor = "apple"),
+    not(feature = "test-mock")
+))]
+fn diff(before: &BTreeSet<u32>, after: &BTreeSet<u32>) -> Vec<InterfaceChange> {
+    let down = before
+        .difference(after)
+        .map(|i| InterfaceChange::Down(NetworkInterface::AtIndex(*i)));
+
+    let up = after
+        .difference(before)
+        .map(|i| InterfaceChange::Up(NetworkInterface::AtIndex(*i)));
+
+    down.chain(up).collect()
+}
+
+#[cfg(all(
+    test,
+    any(target_os = "linux", target_vendor = "apple"),
+    not(feature = "test-mock")
+))]
+mod tests {
+    use super::*;
+
+    fn indexes(indexes: &[u32]) -> BTreeSet<u32> {
+        indexes.iter().copied().collect()
+    }
+
+    #[test]
+    fn diff_reports_nothing_without_changes() {
+        assert!(diff(&indexes(&[1, 2]), &indexes(&[1, 2])).is_empty());
+    }
+
+    #[test]
+    fn diff_reports_interfaces_gone_down_before_those_come_up() {
+        assert_eq!(
+            diff(&indexes(&[1, 2]), &indexes(&[1, 3])),
+            vec![
+                InterfaceChange::Down(NetworkInterface::AtIndex(2)),
+                InterfaceChange::Up(NetworkInterface::AtIndex(3)),
+            ]
+        );
+    }
+
+    #[test]
+    fn diff_reports_removed_interfaces_as_down() {
+        assert_eq!(
+            diff(&indexes(&[1, 2]), &indexes(&[])),
+            vec![
+                InterfaceChange::Down(NetworkInterface::AtIndex(1)),
+                InterfaceChange::Down(NetworkInterface::AtIndex(2)),
+            ]
+        );
+    }
+
+    #[test]
+    fn watcher_starts_with_interfaces_that_are_up() {
+        let watcher = InterfaceWatcher::new().unwrap();
+
+        assert_eq!(watcher.up, up_interfaces(&crate::interfaces().unwrap()));
+    }
+}
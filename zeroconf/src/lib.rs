@@ -12,9 +12,11 @@
 //!
 //! ## Register a service
 //!
-//! When registering a service, you may optionally pass a "context" to pass state through the
-//! callback. The only requirement is that this context implements the [`Any`] trait, which most
-//! types will automatically. See [`MdnsService`] for more information about contexts.
+//! Callbacks are closures, which may capture and modify state of their own. State that is shared
+//! with the rest of the application can be captured as well (e.g. in an `Arc<Mutex<_>>`), or passed
+//! through the callback as a "context" instead. The only requirement is that this context implements
+//! the [`Any`] trait, which most types will automatically. See [`MdnsService`] for more information about
+//! contexts.
 //!
 //! ```no_run
 //! #[macro_use]
@@ -22,11 +24,9 @@
 //!
 //! use clap::Parser;
 //!
-//! use std::any::Any;
-//! use std::sync::{Arc, Mutex};
 //! use std::time::Duration;
 //! use zeroconf::prelude::*;
-//! use zeroconf::{MdnsService, ServiceRegistration, ServiceType, TxtRecord};
+//! use zeroconf::{MdnsService, ServiceType, TxtRecord};
 //!
 //! #[derive(Parser, Debug)]
 //! #[command(author, version, about)]
@@ -44,11 +44,6 @@
 //!     sub_types: Vec<String>,
 //! }
 //!
-//! #[derive(Default, Debug)]
-//! pub struct Context {
-//!     service_name: String,
-//! }
-//!
 //! fn main() -> zeroconf::Result<()> {
 //!     env_logger::init();
 //!
@@ -62,46 +57,30 @@
 //!     let service_type = ServiceType::with_sub_types(&name, &protocol, sub_types)?;
 //!     let mut service = MdnsService::new(service_type, 8080);
 //!     let mut txt_record = TxtRecord::new();
-//!     let context: Arc<Mutex<Context>> = Arc::default();
+//!     let mut registrations = 0;
 //!
 //!     txt_record.insert("foo", "bar")?;
 //!
 //!     service.set_name("zeroconf_example_service");
-//!     service.set_registered_callback(Box::new(on_service_registered));
-//!     service.set_context(Box::new(context));
 //!     service.set_txt_record(txt_record);
 //!
-//!     let event_loop = service.register()?;
-//!
-//!     loop {
-//!         // calling `poll()` will keep this service alive
-//!         event_loop.poll(Duration::from_secs(0))?;
-//!     }
-//! }
+//!     service.set_on_registered(move |result| {
+//!         let service = result.expect("failed to register service");
 //!
-//! fn on_service_registered(
-//!     result: zeroconf::Result<ServiceRegistration>,
-//!     context: Option<Arc<dyn Any>>,
-//! ) {
-//!     let service = result.expect("failed to register service");
+//!         // closures may keep state of their own
+//!         registrations += 1;
 //!
-//!     info!("Service registered: {:?}", service);
+//!         info!("Service registered ({}): {:?}", registrations, service);
 //!
-//!     let context = context
-//!         .as_ref()
-//!         .expect("could not get context")
-//!         .downcast_ref::<Arc<Mutex<Context>>>()
-//!         .expect("error down-casting context")
-//!         .clone();
+//!         // ...
+//!     });
 //!
-//!     context
-//!         .lock()
-//!         .expect("failed to obtain context lock")
-//!         .service_name = service.name().clone();
-//!
-//!     info!("Context: {:?}", context);
+//!     let event_loop = service.register()?;
 //!
-//!     // ...
+//!     loop {
+//!         // calling `poll()` will keep this service alive
+//!         event_loop.poll(Duration::from_secs(1))?;
+//!     }
 //! }
 //! ```
 //!
@@ -112,11 +91,9 @@
 //!
 //! use clap::Parser;
 //!
-//! use std::any::Any;
-//! use std::sync::Arc;
 //! use std::time::Duration;
 //! use zeroconf::prelude::*;
-//! use zeroconf::{MdnsBrowser, ServiceDiscovery, ServiceType};
+//! use zeroconf::{MdnsBrowser, ServiceType};
 //!
 //! /// Example of a simple mDNS browser
 //! #[derive(Parser, Debug)]
@@ -154,27 +131,22 @@
 //!
 //!     let mut browser = MdnsBrowser::new(service_type);
 //!
-//!     browser.set_service_discovered_callback(Box::new(on_service_discovered));
+//!     browser.set_on_discovered(|result| {
+//!         info!(
+//!             "Service discovered: {:?}",
+//!             result.expect("service discovery failed")
+//!         );
+//!
+//!         // ...
+//!     });
 //!
 //!     let event_loop = browser.browse_services()?;
 //!
 //!     loop {
 //!         // calling `poll()` will keep this browser alive
-//!         event_loop.poll(Duration::from_secs(0))?;
+//!         event_loop.poll(Duration::from_secs(1))?;
 //!     }
 //! }
-//!
-//! fn on_service_discovered(
-//!     result: zeroconf::Result<ServiceDiscovery>,
-//!     _context: Option<Arc<dyn Any>>,
-//! ) {
-//!     info!(
-//!         "Service discovered: {:?}",
-//!         result.expect("service discovery failed")
-//!     );
-//!
-//!     // ...
-//! }
 //! ```
 //!
 //! [ZeroConf/mDNS]: https://en.wikipedia.org/wiki/Zero-configuration_networking
@@ -192,14 +164,20 @@ extern crate serde;
 extern crate derive_builder;
 #[macro_use]
 extern crate zeroconf_macros;
-#[cfg(target_os = "linux")]
+#[cfg(all(target_os = "linux", not(feature = "test-mock")))]
 extern crate avahi_sys;
-#[cfg(any(target_vendor = "apple", target_vendor = "pc"))]
+#[cfg(all(
+    any(target_vendor = "apple", target_vendor = "pc"),
+    not(feature = "test-mock")
+))]
 extern crate bonjour_sys;
 #[macro_use]
 extern crate derive_getters;
-#[macro_use]
+#[cfg_attr(not(feature = "tracing"), macro_use)]
 extern crate log;
+#[cfg(feature = "tracing")]
+#[macro_use(debug, error, trace, warn)]
+extern crate tracing;
 #[macro_use]
 extern crate derive_new;
 
@@ -210,58 +188,222 @@ extern crate maplit;
 
 #[macro_use]
 mod macros;
+mod clock;
+mod context;
+mod dedup;
 mod ffi;
+mod filter;
+mod host;
+mod id;
 mod interface;
+mod interface_watcher;
+mod protocol;
+#[cfg(not(feature = "test-mock"))]
+mod resolve_queue;
 mod service_type;
+mod settle;
+mod span;
 #[cfg(test)]
 mod tests;
 
+pub mod backend;
 pub mod browser;
+pub mod cache;
+pub mod daemon;
+pub mod defer;
+pub mod detached;
+pub mod domain_browser;
 pub mod error;
 pub mod event_loop;
+pub mod fullname;
+pub mod host_resolver;
 pub mod prelude;
+pub mod record_browser;
+pub mod resolver;
 pub mod service;
+pub mod service_group;
+pub mod service_name;
 pub mod txt_record;
 
-#[cfg(target_os = "linux")]
+#[cfg(all(target_os = "linux", not(feature = "test-mock")))]
 pub mod avahi;
-#[cfg(any(target_vendor = "apple", target_vendor = "pc"))]
+#[cfg(all(
+    any(target_vendor = "apple", target_vendor = "pc"),
+    not(feature = "test-mock")
+))]
 pub mod bonjour;
+#[cfg(feature = "test-mock")]
+pub mod mock;
 
-pub use browser::{ServiceDiscoveredCallback, ServiceDiscovery};
+pub use backend::{backend_info, BackendInfo, BackendKind};
+pub use browser::{
+    BrowserBuilder, BrowserEvent, BrowserEventCallback, BrowserStats, DiscoveryFilter, LookupFlags,
+    NameFilter, ResolveFailure, ServiceDiscoveredCallback, ServiceDiscovery,
+    ServiceDiscoveryBuilder, ServiceKey, SettledCallback, DEFAULT_MAX_CONCURRENT_RESOLVES,
+    DEFAULT_RESCAN_DEADLINE, DEFAULT_RESOLVE_TIMEOUT,
+};
+pub use cache::{
+    CacheEvent, CacheEventCallback, CachedService, IncarnationPolicy, InterfaceChange,
+    Reachability, ServiceCache, DEFAULT_GRACE_PERIOD,
+};
+pub use daemon::{diagnose, is_available, DaemonState, DaemonStateCallback, Diagnosis};
+pub use defer::{DeferredFn, Deferrer};
+pub use detached::DetachedHandle;
+pub use domain_browser::{DomainEvent, DomainEventCallback, DomainType};
+pub use event_loop::ShutdownHandle;
+pub use host::host_name;
+pub use host_resolver::{HostResolution, HostResolvedCallback};
+pub use id::Id;
 pub use interface::*;
-pub use service::{ServiceRegisteredCallback, ServiceRegistration};
+pub use protocol::*;
+pub use record_browser::{QueriedRecord, RecordEvent, RecordEventCallback, RR_CLASS_IN};
+pub use service::{
+    NameChange, NameChangedCallback, RecordSharing, RegistrationFlags, RegistrationState,
+    RegistrationStateCallback, ServiceBuilder, ServiceRecord, ServiceRegisteredCallback,
+    ServiceRegistration, ServiceRegistrationBuilder, ServiceState,
+};
+pub use service_group::{InstanceHandle, InstanceRegisteredCallback};
+pub use service_name::alternative_service_name;
 pub use service_type::*;
 
 /// Type alias for the platform-specific mDNS browser implementation
-#[cfg(target_os = "linux")]
+#[cfg(all(target_os = "linux", not(feature = "test-mock")))]
 pub type MdnsBrowser = avahi::browser::AvahiMdnsBrowser;
 /// Type alias for the platform-specific mDNS browser implementation
-#[cfg(any(target_vendor = "apple", target_vendor = "pc"))]
+#[cfg(all(
+    any(target_vendor = "apple", target_vendor = "pc"),
+    not(feature = "test-mock")
+))]
 pub type MdnsBrowser = bonjour::browser::BonjourMdnsBrowser;
+/// Type alias for the platform-specific mDNS browser implementation
+#[cfg(feature = "test-mock")]
+pub type MdnsBrowser = mock::browser::MockMdnsBrowser;
+
+/// Type alias for the platform-specific mDNS resolver implementation
+#[cfg(all(target_os = "linux", not(feature = "test-mock")))]
+pub type MdnsResolver = avahi::service_resolver::AvahiMdnsResolver;
+/// Type alias for the platform-specific mDNS resolver implementation
+#[cfg(all(
+    any(target_vendor = "apple", target_vendor = "pc"),
+    not(feature = "test-mock")
+))]
+pub type MdnsResolver = bonjour::resolver::BonjourMdnsResolver;
+/// Type alias for the platform-specific mDNS resolver implementation
+#[cfg(feature = "test-mock")]
+pub type MdnsResolver = mock::resolver::MockMdnsResolver;
+
+/// Type alias for the platform-specific mDNS host name resolver implementation
+#[cfg(all(target_os = "linux", not(feature = "test-mock")))]
+pub type MdnsHostResolver = avahi::host_resolver::AvahiMdnsHostResolver;
+/// Type alias for the platform-specific mDNS host name resolver implementation
+#[cfg(all(
+    any(target_vendor = "apple", target_vendor = "pc"),
+    not(feature = "test-mock")
+))]
+pub type MdnsHostResolver = bonjour::host_resolver::BonjourMdnsHostResolver;
+/// Type alias for the platform-specific mDNS host name resolver implementation
+#[cfg(feature = "test-mock")]
+pub type MdnsHostResolver = mock::host_resolver::MockMdnsHostResolver;
+
+/// Type alias for the platform-specific mDNS domain browser implementation
+#[cfg(all(target_os = "linux", not(feature = "test-mock")))]
+pub type MdnsDomainBrowser = avahi::domain_browser::AvahiMdnsDomainBrowser;
+/// Type alias for the platform-specific mDNS domain browser implementation
+#[cfg(all(
+    any(target_vendor = "apple", target_vendor = "pc"),
+    not(feature = "test-mock")
+))]
+pub type MdnsDomainBrowser = bonjour::domain_browser::BonjourMdnsDomainBrowser;
+/// Type alias for the platform-specific mDNS domain browser implementation
+#[cfg(feature = "test-mock")]
+pub type MdnsDomainBrowser = mock::domain_browser::MockMdnsDomainBrowser;
+
+/// Type alias for the platform-specific mDNS record browser implementation
+#[cfg(all(target_os = "linux", not(feature = "test-mock")))]
+pub type MdnsRecordBrowser = avahi::record_browser::AvahiMdnsRecordBrowser;
+/// Type alias for the platform-specific mDNS record browser implementation
+#[cfg(all(
+    any(target_vendor = "apple", target_vendor = "pc"),
+    not(feature = "test-mock")
+))]
+pub type MdnsRecordBrowser = bonjour::record_browser::BonjourMdnsRecordBrowser;
+/// Type alias for the platform-specific mDNS record browser implementation
+#[cfg(feature = "test-mock")]
+pub type MdnsRecordBrowser = mock::record_browser::MockMdnsRecordBrowser;
 
 /// Type alias for the platform-specific mDNS service implementation
-#[cfg(target_os = "linux")]
+#[cfg(all(target_os = "linux", not(feature = "test-mock")))]
 pub type MdnsService = avahi::service::AvahiMdnsService;
 /// Type alias for the platform-specific mDNS service implementation
-#[cfg(any(target_vendor = "apple", target_vendor = "pc"))]
+#[cfg(all(
+    any(target_vendor = "apple", target_vendor = "pc"),
+    not(feature = "test-mock")
+))]
 pub type MdnsService = bonjour::service::BonjourMdnsService;
+/// Type alias for the platform-specific mDNS service implementation
+#[cfg(feature = "test-mock")]
+pub type MdnsService = mock::service::MockMdnsService;
+
+/// Type alias for the platform-specific mDNS service group implementation
+#[cfg(all(target_os = "linux", not(feature = "test-mock")))]
+pub type MdnsServiceGroup = avahi::service_group::AvahiMdnsServiceGroup;
+/// Type alias for the platform-specific mDNS service group implementation
+#[cfg(all(
+    any(target_vendor = "apple", target_vendor = "pc"),
+    not(feature = "test-mock")
+))]
+pub type MdnsServiceGroup = bonjour::service_group::BonjourMdnsServiceGroup;
+/// Type alias for the platform-specific mDNS service group implementation
+#[cfg(feature = "test-mock")]
+pub type MdnsServiceGroup = mock::service_group::MockMdnsServiceGroup;
+
+/// Type alias for the platform-specific handle passed to closures queued with [`Deferrer`]
+///
+/// [`Deferrer`]: struct.Deferrer.html
+#[cfg(all(target_os = "linux", not(feature = "test-mock")))]
+pub type DeferredService<'a> = avahi::service::AvahiDeferredService<'a>;
+/// Type alias for the platform-specific handle passed to closures queued with [`Deferrer`]
+///
+/// [`Deferrer`]: struct.Deferrer.html
+#[cfg(all(
+    any(target_vendor = "apple", target_vendor = "pc"),
+    not(feature = "test-mock")
+))]
+pub type DeferredService<'a> = bonjour::service::BonjourDeferredService<'a>;
+/// Type alias for the platform-specific handle passed to closures queued with [`Deferrer`]
+///
+/// [`Deferrer`]: struct.Deferrer.html
+#[cfg(feature = "test-mock")]
+pub type DeferredService<'a> = mock::service::MockDeferredService<'a>;
 
 /// Type alias for the platform-specific structure responsible for polling the mDNS event loop
-#[cfg(target_os = "linux")]
+#[cfg(all(target_os = "linux", not(feature = "test-mock")))]
 pub type EventLoop = avahi::event_loop::AvahiEventLoop;
 /// Type alias for the platform-specific structure responsible for polling the mDNS event loop
-#[cfg(any(target_vendor = "apple", target_vendor = "pc"))]
+#[cfg(all(
+    any(target_vendor = "apple", target_vendor = "pc"),
+    not(feature = "test-mock")
+))]
 pub type EventLoop = bonjour::event_loop::BonjourEventLoop;
+/// Type alias for the platform-specific structure responsible for polling the mDNS event loop
+#[cfg(feature = "test-mock")]
+pub type EventLoop = mock::event_loop::MockEventLoop;
 
 /// Type alias for the platform-specific structure responsible for storing and accessing TXT
 /// record data
-#[cfg(target_os = "linux")]
+#[cfg(all(target_os = "linux", not(feature = "test-mock")))]
 pub type TxtRecord = avahi::txt_record::AvahiTxtRecord;
 /// Type alias for the platform-specific structure responsible for storing and accessing TXT
 /// record data
-#[cfg(any(target_vendor = "apple", target_vendor = "pc"))]
+#[cfg(all(
+    any(target_vendor = "apple", target_vendor = "pc"),
+    not(feature = "test-mock")
+))]
 pub type TxtRecord = bonjour::txt_record::BonjourTxtRecord;
+/// Type alias for the platform-specific structure responsible for storing and accessing TXT
+/// record data
+#[cfg(feature = "test-mock")]
+pub type TxtRecord = mock::txt_record::MockTxtRecord;
 
 /// Result type for this library
 pub type Result<T> = std::result::Result<T, error::Error>;
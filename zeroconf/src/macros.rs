@@ -1,11 +1,31 @@
 #![allow(useless_ptr_null_checks)]
 
+/// Panics if the specified pointer is null.
+///
+/// Only use this for pointers whose validity is guaranteed by our own code (e.g. the `userdata`
+/// passed back to a callback). Pointers that the mDNS implementation may legitimately report as
+/// null should be checked with `ensure_not_null!` instead.
 macro_rules! assert_not_null {
     ($ptr:expr) => {
         assert!(!$ptr.is_null(), "expected non-null value");
     };
 }
 
+/// Returns early with an `Err` naming `$what` if the specified pointer is null.
+///
+/// Fallible counterpart to `assert_not_null!` for pointers received from the mDNS implementation
+/// that may be null depending on lookup flags, event type or data received from the network.
+#[cfg(not(feature = "test-mock"))]
+macro_rules! ensure_not_null {
+    ($ptr:expr, $what:expr) => {
+        if $ptr.is_null() {
+            warn!("expected non-null {}", $what);
+            return Err($crate::error::Error::from(format!("missing {}", $what)));
+        }
+    };
+}
+
+#[cfg(not(feature = "test-mock"))]
 macro_rules! c_string {
     (alloc($len:expr)) => {
         ::std::ffi::CString::from_vec_unchecked(vec![0; $len])
@@ -15,12 +35,27 @@ macro_rules! c_string {
     };
 }
 
+/// Creates a debug level span named `$name` with the specified fields, using the same syntax as
+/// `tracing::debug_span!`. Evaluates to a no-op span if the `tracing` feature is disabled, in which
+/// case the fields are not evaluated.
+macro_rules! span {
+    ($name:expr $(, $($fields:tt)+)?) => {{
+        #[cfg(feature = "tracing")]
+        let span = ::tracing::debug_span!($name $(, $($fields)+)?);
+        #[cfg(not(feature = "tracing"))]
+        let span = $crate::span::Span::none();
+        span
+    }};
+}
+
 #[cfg(test)]
 mod tests {
     use libc::c_char;
+    #[cfg(not(feature = "test-mock"))]
     use std::ffi::CString;
     use std::ptr;
 
+    #[cfg(not(feature = "test-mock"))]
     #[test]
     fn assert_not_null_non_null_success() {
         let c_str = c_string!("foo");
@@ -33,6 +68,29 @@ mod tests {
         assert_not_null!(ptr::null() as *const c_char);
     }
 
+    #[cfg(not(feature = "test-mock"))]
+    fn ensure_not_null_helper(ptr: *const c_char) -> crate::Result<()> {
+        ensure_not_null!(ptr, "value");
+        Ok(())
+    }
+
+    #[cfg(not(feature = "test-mock"))]
+    #[test]
+    fn ensure_not_null_non_null_success() {
+        let c_str = c_string!("foo");
+        assert!(ensure_not_null_helper(c_str.as_ptr()).is_ok());
+    }
+
+    #[cfg(not(feature = "test-mock"))]
+    #[test]
+    fn ensure_not_null_null_returns_error() {
+        assert_eq!(
+            ensure_not_null_helper(ptr::null()),
+            Err("missing value".into())
+        );
+    }
+
+    #[cfg(not(feature = "test-mock"))]
     #[test]
     fn c_string_success() {
         assert_eq!(
@@ -0,0 +1,751 @@
+//! Mock implementation for cross-platform browser
+
+use super::event_loop::EventQueue;
+use super::registry::{self, Change, Listener, Registration};
+use crate::cache::{CachedService, ServiceCache};
+use crate::clock::{Clock, SystemClock};
+use crate::dedup::Deduplicator;
+use crate::error::{Error, Operation};
+use crate::ffi::{self, CallbackContext};
+use crate::filter::BrowserFilter;
+use crate::interface_watcher::{self, InterfaceWatcher};
+use crate::prelude::*;
+use crate::settle::SettleTracker;
+use crate::span::Span;
+use crate::{
+    BackendInfo, BrowserEvent, BrowserEventCallback, BrowserStats, DaemonState,
+    DaemonStateCallback, EventLoop, Id, LookupFlags, NetworkInterface, Protocol, Result,
+    ServiceDiscoveredCallback, ServiceDiscovery, ServiceKey, ServiceType, SettledCallback,
+    DEFAULT_MAX_CONCURRENT_RESOLVES, DEFAULT_RESCAN_DEADLINE, DEFAULT_RESOLVE_TIMEOUT,
+};
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, Weak};
+use std::time::Duration;
+
+/// Browser that finds the services registered in the registry of the mock backend.
+pub struct MockMdnsBrowser {
+    id: Id,
+    user_context: Option<Arc<dyn Any + Send + Sync>>,
+    domain: Option<String>,
+    context: Arc<Mutex<MockBrowserContext>>,
+    queue: Option<Arc<EventQueue>>,
+    listener: Option<Arc<Listener>>,
+    scope: Option<Scope>,
+}
+
+impl TMdnsBrowser for MockMdnsBrowser {
+    fn new(service_type: ServiceType) -> Self {
+        let context = MockBrowserContext::new(service_type);
+
+        Self {
+            id: context.id,
+            user_context: None,
+            domain: None,
+            context: Arc::new(Mutex::new(context)),
+            queue: None,
+            listener: None,
+            scope: None,
+        }
+    }
+
+    fn id(&self) -> Id {
+        self.id
+    }
+
+    fn set_network_interface(&mut self, interface: NetworkInterface) {
+        self.lock().interface = interface;
+    }
+
+    fn network_interface(&self) -> NetworkInterface {
+        self.lock().interface
+    }
+
+    fn set_domain(&mut self, domain: &str) {
+        self.domain = Some(domain.to_string());
+    }
+
+    fn domain(&self) -> Option<&str> {
+        self.domain.as_deref()
+    }
+
+    /// The mock backend has a single address per host, so the protocol is only recorded.
+    fn set_protocol(&mut self, protocol: Protocol) {
+        self.lock().protocol = protocol;
+    }
+
+    fn protocol(&self) -> Protocol {
+        self.lock().protocol
+    }
+
+    fn set_lookup_flags(&mut self, flags: LookupFlags) {
+        self.lock().lookup_flags = flags;
+    }
+
+    fn lookup_flags(&self) -> LookupFlags {
+        self.lock().lookup_flags
+    }
+
+    fn set_deduplicate(&mut self, deduplicate: bool) {
+        self.lock().dedup = deduplicate.then(Deduplicator::default);
+    }
+
+    fn deduplicate(&self) -> bool {
+        self.lock().dedup.is_some()
+    }
+
+    /// The mock backend resolves services immediately, so the maximum is only recorded.
+    fn set_name_filter(&mut self, name_filter: impl Fn(&str) -> bool + Send + 'static) {
+        self.lock().filter.set_name_filter(Box::new(name_filter));
+    }
+
+    fn set_discovery_filter(
+        &mut self,
+        discovery_filter: impl Fn(&ServiceDiscovery) -> bool + Send + 'static,
+    ) {
+        self.lock()
+            .filter
+            .set_discovery_filter(Box::new(discovery_filter));
+    }
+
+    fn set_max_concurrent_resolves(&mut self, max_concurrent_resolves: usize) {
+        self.lock().max_concurrent_resolves = max_concurrent_resolves.max(1);
+    }
+
+    fn max_concurrent_resolves(&self) -> usize {
+        self.lock().max_concurrent_resolves
+    }
+
+    /// The mock backend resolves services immediately, so the timeout is only recorded.
+    fn set_resolve_timeout(&mut self, timeout: Duration) {
+        self.lock().resolve_timeout = timeout;
+    }
+
+    fn resolve_timeout(&self) -> Duration {
+        self.lock().resolve_timeout
+    }
+
+    fn set_rescan_deadline(&mut self, deadline: Duration) {
+        self.lock().rescan_deadline = deadline;
+    }
+
+    fn rescan_deadline(&self) -> Duration {
+        self.lock().rescan_deadline
+    }
+
+    fn set_grace_period(&mut self, grace_period: Duration) {
+        self.lock()
+            .update_cache(|c| c.set_grace_period(grace_period));
+    }
+
+    fn grace_period(&self) -> Duration {
+        let context = self.lock();
+        let cache = context.cache.lock().unwrap_or_else(PoisonError::into_inner);
+
+        cache.grace_period()
+    }
+
+    fn set_service_discovered_callback(
+        &mut self,
+        service_discovered_callback: Box<ServiceDiscoveredCallback>,
+    ) {
+        self.lock().service_discovered_callback =
+            Some(Arc::new(Mutex::new(service_discovered_callback)));
+    }
+
+    fn set_browser_event_callback(&mut self, browser_event_callback: Box<BrowserEventCallback>) {
+        self.lock().browser_event_callback = Some(Arc::new(Mutex::new(browser_event_callback)));
+    }
+
+    fn set_context(&mut self, context: Box<dyn Any + Send + Sync>) {
+        let context = Arc::from(context);
+        self.user_context = Some(Arc::clone(&context));
+        self.lock().user_context = Some(context);
+    }
+
+    fn context(&self) -> Option<&(dyn Any + Send + Sync)> {
+        self.user_context.as_ref().map(|c| c.as_ref())
+    }
+
+    fn on_settled(&mut self, deadline: Duration, settled_callback: Box<SettledCallback>) {
+        let mut context = self.lock();
+
+        context.settle = Some(Arc::new(Mutex::new(SettleTracker::new(
+            deadline,
+            settled_callback,
+            context.clock.clone(),
+        ))));
+    }
+
+    fn set_wait_for_daemon(&mut self, wait_for_daemon: bool) -> Result<()> {
+        self.lock().wait_for_daemon = wait_for_daemon;
+        Ok(())
+    }
+
+    fn wait_for_daemon(&self) -> bool {
+        self.lock().wait_for_daemon
+    }
+
+    fn set_daemon_state_callback(&mut self, daemon_state_callback: Box<DaemonStateCallback>) {
+        self.lock().daemon_state_callback = Some(Arc::new(Mutex::new(daemon_state_callback)));
+    }
+
+    /// Starts browsing the registry of the mock backend. The services that are already registered
+    /// are reported once the event loop is polled, along with the services registered or removed
+    /// later on.
+    fn browse_services(&mut self) -> Result<EventLoop> {
+        let queue = Arc::new(EventQueue::default());
+
+        let (scope, settle) = {
+            let mut context = self.lock();
+
+            let span = span!(
+                "browse",
+                id = %context.id,
+                service_type = context.service_type.to_string().as_str()
+            );
+            let _entered = span.enter();
+            context.span = span.clone();
+
+            debug!("[{}] Browsing services: {:?}", context.id, context);
+
+            context.update_settle(|s| s.start());
+            context.update_cache(|c| c.reset());
+            context.interfaces = interface_watcher::watch();
+
+            let scope = Scope {
+                service_type: context.service_type.clone(),
+                interface: context.interface,
+                domain: self.domain.clone(),
+            };
+
+            (scope, context.settle.clone())
+        };
+
+        let listener = listener(&self.context, &queue, scope.clone());
+
+        registry::subscribe(&listener, |services| {
+            enumerate(&self.context, &queue, services, &scope)
+        });
+
+        self.queue = Some(queue.clone());
+        self.listener = Some(listener);
+        self.scope = Some(scope);
+
+        let event_loop =
+            EventLoop::new(queue).with_service_expiry(ServiceExpiry::new(&self.context));
+
+        debug!("[{}] Event loop: {}", self.id, event_loop.id());
+
+        Ok(match settle {
+            Some(settle) => event_loop.with_settle_tracker(settle),
+            None => event_loop,
+        })
+    }
+
+    fn rescan(&mut self) -> Result<()> {
+        let (queue, scope) = match (&self.queue, &self.scope) {
+            (Some(queue), Some(scope)) => (queue, scope),
+            _ => return Err("browser has not been started".into()),
+        };
+
+        debug!("[{}] Rescanning services", self.id);
+
+        {
+            let context = self.lock();
+            let now = context.clock.now();
+            let deadline = context.rescan_deadline;
+
+            context.update_cache(|c| c.rescan_started(deadline, now));
+        }
+
+        enumerate(&self.context, queue, &registry::services(), scope);
+
+        Ok(())
+    }
+
+    fn stats(&self) -> BrowserStats {
+        *self
+            .lock()
+            .stats
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn known_services(&self) -> Vec<CachedService> {
+        let context = self.lock();
+        let cache = context.cache.lock().unwrap_or_else(PoisonError::into_inner);
+
+        cache.snapshot()
+    }
+
+    fn backend_info(&self) -> Result<BackendInfo> {
+        self.queue.as_ref().ok_or("browser has not been started")?;
+
+        Ok(super::backend_info())
+    }
+}
+
+impl fmt::Debug for MockMdnsBrowser {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MockMdnsBrowser")
+            .field("id", &self.id)
+            .field("domain", &self.domain)
+            .field("context", &self.context)
+            .finish()
+    }
+}
+
+impl MockMdnsBrowser {
+    /// Sets the [`Clock`] the settle deadline is measured with. Must be called before
+    /// `on_settled()`.
+    ///
+    /// [`Clock`]: ../../clock/trait.Clock.html
+    #[cfg(test)]
+    pub(crate) fn set_clock(&mut self, clock: Arc<dyn Clock>) {
+        self.lock().clock = clock;
+    }
+
+    fn lock(&self) -> MutexGuard<'_, MockBrowserContext> {
+        self.context.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[derive(FromRaw, AsRaw)]
+struct MockBrowserContext {
+    id: Id,
+    service_type: ServiceType,
+    service_discovered_callback: Option<Arc<Mutex<Box<ServiceDiscoveredCallback>>>>,
+    browser_event_callback: Option<Arc<Mutex<Box<BrowserEventCallback>>>>,
+    daemon_state_callback: Option<Arc<Mutex<Box<DaemonStateCallback>>>>,
+    user_context: Option<Arc<dyn Any + Send + Sync>>,
+    interface: NetworkInterface,
+    protocol: Protocol,
+    wait_for_daemon: bool,
+    lookup_flags: LookupFlags,
+    dedup: Option<Deduplicator>,
+    filter: BrowserFilter,
+    max_concurrent_resolves: usize,
+    resolve_timeout: Duration,
+    service_types: HashMap<ServiceType, usize>,
+    settle: Option<Arc<Mutex<SettleTracker>>>,
+    clock: Arc<dyn Clock>,
+    stats: Mutex<BrowserStats>,
+    /// The services reported to the callbacks, updated before they are invoked
+    cache: Mutex<ServiceCache>,
+    rescan_deadline: Duration,
+    /// Reports the interfaces that went down or came up while browsing
+    interfaces: Option<InterfaceWatcher>,
+    span: Span,
+}
+
+impl MockBrowserContext {
+    fn new(service_type: ServiceType) -> Self {
+        Self {
+            id: Id::next(),
+            service_type,
+            service_discovered_callback: None,
+            browser_event_callback: None,
+            daemon_state_callback: None,
+            user_context: None,
+            interface: NetworkInterface::Unspec,
+            protocol: Protocol::Any,
+            wait_for_daemon: false,
+            lookup_flags: LookupFlags::NONE,
+            dedup: None,
+            filter: BrowserFilter::default(),
+            max_concurrent_resolves: DEFAULT_MAX_CONCURRENT_RESOLVES,
+            resolve_timeout: DEFAULT_RESOLVE_TIMEOUT,
+            service_types: HashMap::new(),
+            settle: None,
+            clock: Arc::new(SystemClock),
+            stats: Mutex::default(),
+            cache: Mutex::default(),
+            rescan_deadline: DEFAULT_RESCAN_DEADLINE,
+            interfaces: None,
+            span: Span::none(),
+        }
+    }
+
+    fn update_settle(&self, f: impl FnOnce(&mut SettleTracker)) {
+        if let Some(settle) = &self.settle {
+            f(&mut settle
+                .lock()
+                .expect("should have been able to obtain lock on settle tracker"));
+        }
+    }
+
+    fn update_stats(&self, f: impl FnOnce(&mut BrowserStats)) {
+        f(&mut self.stats.lock().unwrap_or_else(PoisonError::into_inner));
+    }
+
+    fn update_cache(&self, f: impl FnOnce(&mut ServiceCache)) {
+        f(&mut self.cache.lock().unwrap_or_else(PoisonError::into_inner));
+    }
+
+    /// Removes the services that have not been reported again by the deadline of a rescan, or
+    /// that have been degraded for longer than the grace period, from the cache, and returns
+    /// their keys.
+    fn expired_services(&self) -> Vec<ServiceKey> {
+        let now = self.clock.now();
+
+        self.cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .expired(now)
+    }
+
+    /// Marks the services found on the interfaces that went down since the previous call as
+    /// degraded, or restores those on the interfaces that came back up, and returns the events to
+    /// report for them.
+    fn reachability_changes(&mut self) -> Vec<BrowserEvent> {
+        let changes = match &mut self.interfaces {
+            Some(interfaces) => interfaces.changes(),
+            None => return vec![],
+        };
+
+        let now = self.clock.now();
+
+        self.cache
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .interfaces_changed(changes, now)
+    }
+
+    /// Reports a service that has been registered, or a service type if this is a meta-query.
+    fn found(&mut self, registration: &Registration) {
+        if self.service_type.is_meta_query() {
+            let kind = registration.kind();
+            let count = self.service_types.entry(kind.clone()).or_default();
+
+            *count += 1;
+
+            if *count == 1 {
+                self.invoke_browser_event_callback(BrowserEvent::ServiceTypeFound(kind));
+            }
+
+            return;
+        }
+
+        // services whose name is filtered out are not resolved
+        if !self.filter.found(&registration.name) {
+            debug!(
+                "[{}] Ignoring filtered service: {}",
+                self.id, registration.name
+            );
+            return;
+        }
+
+        self.update_settle(|s| s.resolution_started());
+
+        if let Some(dedup) = &mut self.dedup {
+            dedup.found(registration.key(), registration.interface);
+        }
+
+        self.resolved(registration);
+        self.update_settle(|s| s.resolution_finished());
+    }
+
+    /// Reports the current state of a service, as if it had been resolved.
+    fn resolved(&mut self, registration: &Registration) {
+        if self.service_type.is_meta_query() || !self.filter.found(&registration.name) {
+            return;
+        }
+
+        let result = match registration.discovery(self.interface, self.lookup_flags) {
+            Ok(result) => result,
+            Err(e) => {
+                let error_context = registration.error_context(Operation::Resolve);
+                return self.invoke_callback(Err(e.with_context(error_context)));
+            }
+        };
+
+        debug!("[{}] Service resolved: {:?}", self.id, result);
+
+        if !self.filter.resolved(&result) {
+            debug!("[{}] Ignoring filtered service: {:?}", self.id, result);
+            return;
+        }
+
+        if let Some(dedup) = &mut self.dedup {
+            if !dedup.resolved(&result) {
+                debug!("[{}] Ignoring duplicate: {:?}", self.id, result);
+                return;
+            }
+        }
+
+        let now = self.clock.now();
+
+        self.update_settle(|s| s.resolved(&result));
+        self.update_cache(|c| c.reported(&result, now));
+        self.invoke_callback(Ok(result));
+    }
+
+    /// Reports a service that has been removed, or a service type that is no longer registered if
+    /// this is a meta-query.
+    fn removed(&mut self, registration: &Registration) {
+        if self.service_type.is_meta_query() {
+            let kind = registration.kind();
+
+            if let Some(count) = self.service_types.get_mut(&kind) {
+                *count -= 1;
+
+                if *count == 0 {
+                    self.service_types.remove(&kind);
+                    self.invoke_browser_event_callback(BrowserEvent::ServiceTypeRemoved(kind));
+                }
+            }
+
+            return;
+        }
+
+        let key = registration.key();
+
+        self.update_settle(|s| s.removed(key.name(), key.service_type(), key.domain()));
+
+        let report = match &mut self.dedup {
+            Some(dedup) => dedup.removed(&key, registration.interface),
+            None => true,
+        };
+
+        if report && self.filter.removed(&key) {
+            self.update_cache(|c| c.reported_removed(&key));
+            self.invoke_browser_event_callback(BrowserEvent::ServiceRemoved(key));
+        }
+    }
+
+    fn invoke_callback(&self, result: Result<crate::ServiceDiscovery>) {
+        if result.is_err() {
+            self.update_stats(|s| s.failed());
+        }
+
+        if let Some(f) = self.service_discovered_callback.clone() {
+            let user_context = self.user_context.clone();
+
+            ffi::invoke_unlocked(move || {
+                let mut f = f.lock().unwrap_or_else(PoisonError::into_inner);
+                f(result, user_context);
+            });
+        } else {
+            warn!(
+                "[{}] attempted to invoke browser callback but none was set",
+                self.id
+            );
+        }
+    }
+
+    fn invoke_browser_event_callback(&self, event: BrowserEvent) {
+        debug!("[{}] Browser event: {:?}", self.id, event);
+
+        if let Some(f) = self.browser_event_callback.clone() {
+            let user_context = self.user_context.clone();
+
+            ffi::invoke_unlocked(move || {
+                let f = f.lock().unwrap_or_else(PoisonError::into_inner);
+                f(event, user_context);
+            });
+        }
+    }
+
+    fn invoke_daemon_state_callback(&self, state: DaemonState) {
+        debug!("[{}] Daemon state: {:?}", self.id, state);
+
+        if let Some(f) = self.daemon_state_callback.clone() {
+            let user_context = self.user_context.clone();
+
+            ffi::invoke_unlocked(move || {
+                let f = f.lock().unwrap_or_else(PoisonError::into_inner);
+                f(state, user_context);
+            });
+        }
+    }
+}
+
+impl CallbackContext for MockBrowserContext {
+    fn id(&self) -> Id {
+        self.id
+    }
+
+    fn invoke_error(&self, error: Error) {
+        self.invoke_callback(Err(error))
+    }
+
+    fn span(&self) -> Option<&Span> {
+        Some(&self.span)
+    }
+}
+
+impl fmt::Debug for MockBrowserContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MockBrowserContext")
+            .field("id", &self.id)
+            .field("service_type", &self.service_type)
+            .field("interface", &self.interface)
+            .finish()
+    }
+}
+
+/// The services a started browser looks for.
+#[derive(Clone)]
+struct Scope {
+    service_type: ServiceType,
+    interface: NetworkInterface,
+    /// Any domain if `None`, as the mock backend has no default domains
+    domain: Option<String>,
+}
+
+impl Scope {
+    /// Returns true if a browser with this scope finds `registration`. Placeholders registered
+    /// on port `0` are never found.
+    fn includes(&self, registration: &Registration) -> bool {
+        if registration.port == 0 || !registration.is_on(self.interface) {
+            return false;
+        }
+
+        if let Some(domain) = &self.domain {
+            let domain = domain.strip_suffix('.').unwrap_or(domain);
+
+            if !domain.eq_ignore_ascii_case(&registration.domain) {
+                return false;
+            }
+        }
+
+        if self.service_type.is_meta_query() {
+            return true;
+        }
+
+        let sub_types = registration.service_type.sub_types();
+
+        registration.service_type.name() == self.service_type.name()
+            && registration.service_type.protocol() == self.service_type.protocol()
+            && (self.service_type.sub_types().is_empty()
+                || self
+                    .service_type
+                    .sub_types()
+                    .iter()
+                    .any(|s| sub_types.contains(s)))
+    }
+}
+
+/// Reports the services of a browser whose network interface went down or came back up, and the
+/// removal of those that have not been reported again by the deadline of a rescan or that have
+/// been degraded for longer than the grace period, on behalf of `MockEventLoop::poll()`.
+pub(crate) struct ServiceExpiry {
+    context: Weak<Mutex<MockBrowserContext>>,
+}
+
+impl ServiceExpiry {
+    fn new(context: &Arc<Mutex<MockBrowserContext>>) -> Self {
+        Self {
+            context: Arc::downgrade(context),
+        }
+    }
+
+    /// Reports the services whose reachability has changed or that have expired. Nothing is
+    /// dispatched if there are none, or if the browser has been dropped.
+    pub(crate) fn run(&self) {
+        let (changed, expired) = match self.context.upgrade() {
+            Some(context) => {
+                let mut context = context.lock().unwrap_or_else(PoisonError::into_inner);
+                (context.reachability_changes(), context.expired_services())
+            }
+            None => return,
+        };
+
+        if changed.is_empty() && expired.is_empty() {
+            return;
+        }
+
+        super::dispatch(&self.context, |context: &mut MockBrowserContext| {
+            for event in changed {
+                context.invoke_browser_event_callback(event);
+            }
+
+            for key in expired {
+                debug!("[{}] Service expired: {:?}", context.id, key);
+                context.invoke_browser_event_callback(BrowserEvent::ServiceRemoved(key));
+            }
+        });
+    }
+}
+
+/// Returns the listener that queues the changes to the registry that concern the browser.
+fn listener(
+    context: &Arc<Mutex<MockBrowserContext>>,
+    queue: &Arc<EventQueue>,
+    scope: Scope,
+) -> Arc<Listener> {
+    let context = Arc::downgrade(context);
+    let queue = Arc::downgrade(queue);
+
+    Arc::new(move |change: &Change| {
+        let queue = match queue.upgrade() {
+            Some(queue) => queue,
+            None => return,
+        };
+
+        if !scope.includes(change.registration()) {
+            return;
+        }
+
+        let context = Weak::clone(&context);
+        let change = change.clone();
+
+        queue.push(Box::new(move || {
+            super::dispatch(&context, |context: &mut MockBrowserContext| {
+                context.update_stats(|s| s.event_received());
+
+                match &change {
+                    Change::Added(registration) => context.found(registration),
+                    Change::Updated(registration) => context.resolved(registration),
+                    Change::Removed(registration) => return context.removed(registration),
+                }
+
+                context.invoke_browser_event_callback(BrowserEvent::Flushed);
+            })
+        }));
+    })
+}
+
+/// Queues the report of the registered `services`, followed by `BrowserEvent::AllForNow`.
+fn enumerate(
+    context: &Arc<Mutex<MockBrowserContext>>,
+    queue: &EventQueue,
+    services: &[Registration],
+    scope: &Scope,
+) {
+    let context = Arc::downgrade(context);
+
+    let services = services
+        .iter()
+        .filter(|r| scope.includes(r))
+        .cloned()
+        .collect::<Vec<_>>();
+
+    queue.push(Box::new(move || {
+        super::dispatch(&context, |context: &mut MockBrowserContext| {
+            context.invoke_daemon_state_callback(DaemonState::Running);
+
+            if let Some(dedup) = &mut context.dedup {
+                dedup.clear();
+            }
+
+            context.filter.clear();
+
+            context.service_types.clear();
+
+            for registration in &services {
+                context.update_stats(|s| s.event_received());
+                context.found(registration);
+            }
+
+            if !services.is_empty() {
+                context.invoke_browser_event_callback(BrowserEvent::Flushed);
+            }
+
+            context.update_settle(|s| s.enumerated());
+            context.invoke_browser_event_callback(BrowserEvent::AllForNow);
+        })
+    }));
+}
@@ -0,0 +1,141 @@
+//! Mock implementation for cross-platform domain browser
+
+use super::event_loop::EventQueue;
+use crate::error::Error;
+use crate::ffi::CallbackContext;
+use crate::prelude::*;
+use crate::{
+    DomainEvent, DomainEventCallback, DomainType, EventLoop, Id, NetworkInterface, Result,
+};
+use std::any::Any;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// Domain browser that reports the domain of the mock backend, `local`, for both domain types.
+#[derive(Debug)]
+pub struct MockMdnsDomainBrowser {
+    id: Id,
+    domain_type: DomainType,
+    user_context: Option<Arc<dyn Any + Send + Sync>>,
+    context: Arc<Mutex<MockDomainBrowserContext>>,
+}
+
+impl TMdnsDomainBrowser for MockMdnsDomainBrowser {
+    fn new(domain_type: DomainType) -> Self {
+        let context = MockDomainBrowserContext::new();
+
+        Self {
+            id: context.id,
+            domain_type,
+            user_context: None,
+            context: Arc::new(Mutex::new(context)),
+        }
+    }
+
+    fn id(&self) -> Id {
+        self.id
+    }
+
+    fn domain_type(&self) -> DomainType {
+        self.domain_type
+    }
+
+    fn set_network_interface(&mut self, interface: NetworkInterface) {
+        self.lock().interface = interface;
+    }
+
+    fn network_interface(&self) -> NetworkInterface {
+        self.lock().interface
+    }
+
+    fn set_domain_event_callback(&mut self, domain_event_callback: Box<DomainEventCallback>) {
+        self.lock().domain_event_callback = Some(domain_event_callback);
+    }
+
+    fn set_context(&mut self, context: Box<dyn Any + Send + Sync>) {
+        let context = Arc::from(context);
+        self.user_context = Some(Arc::clone(&context));
+        self.lock().user_context = Some(context);
+    }
+
+    fn context(&self) -> Option<&(dyn Any + Send + Sync)> {
+        self.user_context.as_ref().map(|c| c.as_ref())
+    }
+
+    fn browse_domains(&mut self) -> Result<EventLoop> {
+        debug!(
+            "[{}] Browsing domains of type {:?}",
+            self.id, self.domain_type
+        );
+
+        let queue = Arc::new(EventQueue::default());
+        let context = Arc::downgrade(&self.context);
+
+        queue.push(Box::new(move || {
+            super::dispatch(&context, |context: &mut MockDomainBrowserContext| {
+                context.invoke_callback(Ok(DomainEvent::Add(super::DOMAIN.to_string())))
+            })
+        }));
+
+        let event_loop = EventLoop::new(queue);
+
+        debug!("[{}] Event loop: {}", self.id, event_loop.id());
+
+        Ok(event_loop)
+    }
+}
+
+impl MockMdnsDomainBrowser {
+    fn lock(&self) -> MutexGuard<'_, MockDomainBrowserContext> {
+        self.context.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[derive(FromRaw, AsRaw)]
+struct MockDomainBrowserContext {
+    id: Id,
+    domain_event_callback: Option<Box<DomainEventCallback>>,
+    user_context: Option<Arc<dyn Any + Send + Sync>>,
+    interface: NetworkInterface,
+}
+
+impl MockDomainBrowserContext {
+    fn new() -> Self {
+        Self {
+            id: Id::next(),
+            domain_event_callback: None,
+            user_context: None,
+            interface: NetworkInterface::Unspec,
+        }
+    }
+
+    fn invoke_callback(&self, result: Result<DomainEvent>) {
+        if let Some(f) = &self.domain_event_callback {
+            f(result, self.user_context.clone());
+        } else {
+            warn!(
+                "[{}] attempted to invoke domain browser callback but none was set",
+                self.id
+            );
+        }
+    }
+}
+
+impl CallbackContext for MockDomainBrowserContext {
+    fn id(&self) -> Id {
+        self.id
+    }
+
+    fn invoke_error(&self, error: Error) {
+        self.invoke_callback(Err(error))
+    }
+}
+
+impl fmt::Debug for MockDomainBrowserContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MockDomainBrowserContext")
+            .field("id", &self.id)
+            .field("interface", &self.interface)
+            .finish()
+    }
+}
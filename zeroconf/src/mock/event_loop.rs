@@ -0,0 +1,237 @@
+//! Event loop for running a `MockMdnsService`, `MockMdnsServiceGroup` or `MockMdnsBrowser`.
+
+use super::browser::ServiceExpiry;
+use super::service::DeferredRunner;
+use super::service_group::InstanceChangeRunner;
+use crate::event_loop::{ShutdownHandle, TEventLoop};
+use crate::settle::{self, SettleTracker};
+use crate::{ffi, Id, Result};
+use std::collections::VecDeque;
+use std::mem;
+#[cfg(any(target_os = "linux", target_vendor = "apple"))]
+use std::os::unix::io::RawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+/// Invocation of callbacks that is due, queued for the event loop of the object they belong to
+pub(crate) type Task = dyn FnOnce() + Send;
+
+/// Queue of the [`Task`]s of an object, executed when its event loop is polled.
+#[derive(Default)]
+pub(crate) struct EventQueue {
+    tasks: Mutex<VecDeque<Box<Task>>>,
+    ready: Condvar,
+}
+
+impl EventQueue {
+    /// Queues `task` and wakes up the event loop if it is waiting.
+    pub(crate) fn push(&self, task: Box<Task>) {
+        self.lock().push_back(task);
+        self.ready.notify_all();
+    }
+
+    /// Wakes up the event loop without queuing a task, e.g. once it has been shut down.
+    fn wake(&self) {
+        // taking the lock ensures that the event loop is either waiting or has yet to check
+        // whether it should
+        drop(self.lock());
+        self.ready.notify_all();
+    }
+
+    /// Waits until a task has been queued, `shutdown` has been set or `deadline` has passed.
+    fn wait_until(&self, deadline: Instant, shutdown: &AtomicBool) {
+        let mut tasks = self.lock();
+
+        while tasks.is_empty() && !shutdown.load(Ordering::SeqCst) {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+
+            if timeout.is_zero() {
+                return;
+            }
+
+            tasks = self
+                .ready
+                .wait_timeout(tasks, timeout)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+    }
+
+    /// Removes the queued tasks. The lock is released before they are returned, so that they may
+    /// queue further tasks.
+    fn take(&self) -> VecDeque<Box<Task>> {
+        mem::take(&mut *self.lock())
+    }
+
+    fn lock(&self) -> MutexGuard<'_, VecDeque<Box<Task>>> {
+        self.tasks.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[derive(new)]
+pub struct MockEventLoop {
+    queue: Arc<EventQueue>,
+    #[new(value = "Id::next()")]
+    id: Id,
+    #[new(default)]
+    settle: Option<Arc<Mutex<SettleTracker>>>,
+    #[new(default)]
+    deferred: Option<DeferredRunner>,
+    #[new(default)]
+    instance_changes: Option<InstanceChangeRunner>,
+    #[new(default)]
+    service_expiry: Option<ServiceExpiry>,
+    #[new(default)]
+    shutdown: Arc<AtomicBool>,
+}
+
+impl MockEventLoop {
+    /// Invokes the settled callback of `settle` from `poll()` once the browser has settled.
+    pub(crate) fn with_settle_tracker(mut self, settle: Arc<Mutex<SettleTracker>>) -> Self {
+        self.settle = Some(settle);
+        self
+    }
+
+    /// Executes the closures queued for a service from `poll()`, once the callbacks have returned.
+    pub(crate) fn with_deferred(mut self, deferred: DeferredRunner) -> Self {
+        self.deferred = Some(deferred);
+        self
+    }
+
+    /// Adds and removes the instances of a service group from `poll()`.
+    pub(crate) fn with_instance_changes(mut self, instance_changes: InstanceChangeRunner) -> Self {
+        self.instance_changes = Some(instance_changes);
+        self
+    }
+
+    /// Reports the services of a browser whose network interface went down or came back up, and
+    /// removes those that have expired, from `poll()`.
+    pub(crate) fn with_service_expiry(mut self, service_expiry: ServiceExpiry) -> Self {
+        self.service_expiry = Some(service_expiry);
+        self
+    }
+
+    fn process_deferred(&self) {
+        ffi::drop_dispatched();
+
+        if let Some(deferred) = &self.deferred {
+            deferred.run();
+        }
+
+        if let Some(instance_changes) = &self.instance_changes {
+            instance_changes.run();
+        }
+
+        if let Some(service_expiry) = &self.service_expiry {
+            service_expiry.run();
+        }
+
+        if let Some(settle) = &self.settle {
+            settle::poll(settle, self.id);
+        }
+    }
+}
+
+impl TEventLoop for MockEventLoop {
+    fn id(&self) -> Id {
+        self.id
+    }
+
+    /// Polls for new events.
+    ///
+    /// Waits until a callback is due or `timeout` has elapsed, and then invokes the callbacks that
+    /// are due. Does not wait once the loop has been shut down through a `ShutdownHandle`.
+    fn poll(&self, timeout: Duration) -> Result<()> {
+        let span = span!("poll", event_loop = %self.id);
+        let _entered = span.enter();
+
+        let start = Instant::now();
+
+        self.queue.wait_until(start + timeout, &self.shutdown);
+        self.process_events()?;
+
+        trace!("[{}] Polled for {:?}", self.id, start.elapsed());
+
+        Ok(())
+    }
+
+    fn process_events(&self) -> Result<()> {
+        for task in self.queue.take() {
+            task();
+        }
+
+        self.process_deferred();
+
+        Ok(())
+    }
+
+    /// Returns `None`, the mock backend has no file descriptor to wait on.
+    #[cfg(any(target_os = "linux", target_vendor = "apple"))]
+    fn as_raw_fd(&self) -> Option<RawFd> {
+        None
+    }
+
+    fn shutdown_handle(&self) -> ShutdownHandle {
+        let queue = self.queue.clone();
+        ShutdownHandle::new(self.shutdown.clone(), move || queue.wake())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn poll_returns_once_task_is_queued() {
+        let queue = Arc::new(EventQueue::default());
+        let event_loop = MockEventLoop::new(queue.clone());
+        let done = Arc::new(AtomicBool::new(false));
+        let sink = done.clone();
+
+        let pusher = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            queue.push(Box::new(move || sink.store(true, Ordering::SeqCst)));
+        });
+
+        let start = Instant::now();
+        event_loop.poll(Duration::from_secs(10)).unwrap();
+        pusher.join().unwrap();
+
+        assert!(done.load(Ordering::SeqCst));
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn poll_waits_for_timeout_when_idle() {
+        let event_loop = MockEventLoop::new(Arc::default());
+        let start = Instant::now();
+
+        event_loop.poll(Duration::from_millis(100)).unwrap();
+
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[test]
+    fn shutdown_interrupts_poll() {
+        let event_loop = MockEventLoop::new(Arc::default());
+        let handle = event_loop.shutdown_handle();
+
+        let shutdown = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            handle.shutdown();
+            Instant::now()
+        });
+
+        event_loop.poll(Duration::from_secs(10)).unwrap();
+        let requested = shutdown.join().unwrap();
+
+        assert!(requested.elapsed() < Duration::from_millis(100));
+
+        let start = Instant::now();
+        event_loop.poll(Duration::from_secs(10)).unwrap();
+
+        assert!(start.elapsed() < Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,164 @@
+//! Mock implementation for cross-platform host name resolver
+
+use super::event_loop::EventQueue;
+use super::registry;
+use crate::error::Error;
+use crate::ffi::CallbackContext;
+use crate::prelude::*;
+use crate::{EventLoop, HostResolution, HostResolvedCallback, Id, NetworkInterface, Result};
+use std::any::Any;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// Host resolver that resolves the mock host and the hosts of the services registered with the
+/// mock backend.
+#[derive(Debug)]
+pub struct MockMdnsHostResolver {
+    id: Id,
+    user_context: Option<Arc<dyn Any + Send + Sync>>,
+    context: Arc<Mutex<MockHostResolverContext>>,
+}
+
+impl TMdnsHostResolver for MockMdnsHostResolver {
+    fn new() -> Self {
+        let context = MockHostResolverContext::new();
+
+        Self {
+            id: context.id,
+            user_context: None,
+            context: Arc::new(Mutex::new(context)),
+        }
+    }
+
+    fn id(&self) -> Id {
+        self.id
+    }
+
+    fn set_network_interface(&mut self, interface: NetworkInterface) {
+        self.lock().interface = interface;
+    }
+
+    fn network_interface(&self) -> NetworkInterface {
+        self.lock().interface
+    }
+
+    fn set_host_resolved_callback(&mut self, host_resolved_callback: Box<HostResolvedCallback>) {
+        self.lock().host_resolved_callback = Some(host_resolved_callback);
+    }
+
+    fn set_context(&mut self, context: Box<dyn Any + Send + Sync>) {
+        let context = Arc::from(context);
+        self.user_context = Some(Arc::clone(&context));
+        self.lock().user_context = Some(context);
+    }
+
+    fn context(&self) -> Option<&(dyn Any + Send + Sync)> {
+        self.user_context.as_ref().map(|c| c.as_ref())
+    }
+
+    fn resolve(&mut self, host_name: &str) -> Result<EventLoop> {
+        let queue = Arc::new(EventQueue::default());
+
+        debug!("[{}] Resolving host `{}`", self.id, host_name);
+
+        let context = Arc::downgrade(&self.context);
+        let host_name = host_name.to_string();
+
+        queue.push(Box::new(move || {
+            super::dispatch(&context, |context: &mut MockHostResolverContext| {
+                let result = context.resolve(&host_name);
+                context.invoke_callback(result);
+            })
+        }));
+
+        let event_loop = EventLoop::new(queue);
+
+        debug!("[{}] Event loop: {}", self.id, event_loop.id());
+
+        Ok(event_loop)
+    }
+}
+
+impl MockMdnsHostResolver {
+    fn lock(&self) -> MutexGuard<'_, MockHostResolverContext> {
+        self.context.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[derive(FromRaw, AsRaw)]
+struct MockHostResolverContext {
+    id: Id,
+    host_resolved_callback: Option<Box<HostResolvedCallback>>,
+    user_context: Option<Arc<dyn Any + Send + Sync>>,
+    interface: NetworkInterface,
+}
+
+impl MockHostResolverContext {
+    fn new() -> Self {
+        Self {
+            id: Id::next(),
+            host_resolved_callback: None,
+            user_context: None,
+            interface: NetworkInterface::Unspec,
+        }
+    }
+
+    /// Returns the address of the host. Host names are compared case-insensitively and may end
+    /// with a dot.
+    fn resolve(&self, host_name: &str) -> Result<HostResolution> {
+        let name = host_name.strip_suffix('.').unwrap_or(host_name);
+
+        let (address, interface) = if name.eq_ignore_ascii_case(super::HOST_NAME) {
+            (super::HOST_ADDRESS, self.interface)
+        } else {
+            registry::services()
+                .iter()
+                .find(|r| r.is_on(self.interface) && r.host_name.eq_ignore_ascii_case(name))
+                .map(|r| (r.address, r.interface))
+                .ok_or_else(|| Error::from(format!("failed to resolve host `{}`", host_name)))?
+        };
+
+        let interface = match self.interface {
+            NetworkInterface::Unspec => interface,
+            requested => requested,
+        };
+
+        Ok(HostResolution::builder()
+            .host_name(name.to_string())
+            .address(address)
+            .interface(interface)
+            .build()?)
+    }
+
+    fn invoke_callback(&self, result: Result<HostResolution>) {
+        debug!("[{}] Host resolved: {:?}", self.id, result);
+
+        if let Some(f) = &self.host_resolved_callback {
+            f(result, self.user_context.clone());
+        } else {
+            warn!(
+                "[{}] attempted to invoke host resolver callback but none was set",
+                self.id
+            );
+        }
+    }
+}
+
+impl CallbackContext for MockHostResolverContext {
+    fn id(&self) -> Id {
+        self.id
+    }
+
+    fn invoke_error(&self, error: Error) {
+        self.invoke_callback(Err(error))
+    }
+}
+
+impl fmt::Debug for MockHostResolverContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MockHostResolverContext")
+            .field("id", &self.id)
+            .field("interface", &self.interface)
+            .finish()
+    }
+}
@@ -0,0 +1,82 @@
+//! In-memory ZeroConf implementation for testing
+//!
+//! This module replaces the platform implementation when the `test-mock` feature is enabled, so
+//! that applications can be tested without a running mDNS daemon. No network traffic is involved:
+//! services registered with a [`MockMdnsService`] are kept in a registry shared by the whole
+//! process, and are reported to every [`MockMdnsBrowser`] in the same process that browses for
+//! their type. Dropping a service removes it from the registry again.
+//!
+//! Callbacks are invoked when the [`MockEventLoop`] of the object they were set on is polled,
+//! like with the platform implementations.
+//!
+//! # Note
+//! The registry is global, so tests that run in parallel see each other's services. Use distinct
+//! service types or names in every test.
+//!
+//! [`MockMdnsService`]: service/struct.MockMdnsService.html
+//! [`MockMdnsBrowser`]: browser/struct.MockMdnsBrowser.html
+//! [`MockEventLoop`]: event_loop/struct.MockEventLoop.html
+
+use crate::ffi::{self, AsRaw, CallbackContext};
+use crate::prelude::*;
+use crate::{BackendInfo, BackendKind, InterfaceChange};
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::{Mutex, Weak};
+
+pub(crate) mod registry;
+
+pub mod browser;
+pub mod domain_browser;
+pub mod event_loop;
+pub mod host_resolver;
+pub mod record_browser;
+pub mod resolver;
+pub mod service;
+pub mod service_group;
+pub mod txt_record;
+
+/// Host name of the mock host, which services are advertised under unless a host is set
+pub const HOST_NAME: &str = "zeroconf-mock.local";
+
+/// Address of the mock host, which services are resolved to unless an address is set
+pub const HOST_ADDRESS: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+/// Domain services are registered in unless a domain is set
+pub const DOMAIN: &str = "local";
+
+/// Returns the [`BackendInfo`] of the mock backend, whose version is that of this crate.
+///
+/// [`BackendInfo`]: ../struct.BackendInfo.html
+pub(crate) fn backend_info() -> BackendInfo {
+    BackendInfo::builder()
+        .kind(BackendKind::Mock)
+        .version(env!("CARGO_PKG_VERSION").to_string())
+        .build()
+        .expect("could not build BackendInfo")
+}
+
+/// Simulates a change in the state of a network interface of the local machine.
+///
+/// Every started [`MockMdnsBrowser`] marks the services it has found on the interface as degraded
+/// or reachable again the next time its event loop is polled. Register a service on a
+/// `NetworkInterface::AtIndex` for it to be found on that interface.
+///
+/// [`MockMdnsBrowser`]: browser/struct.MockMdnsBrowser.html
+pub fn interface_changed(change: InterfaceChange) {
+    registry::interface_changed(change);
+}
+
+/// Executes `f` on the context behind `context` like a callback invoked by an mDNS
+/// implementation, see [`ffi::guard_locked()`]. Does nothing if the object the context belongs to
+/// has been dropped.
+///
+/// [`ffi::guard_locked()`]: ../ffi/fn.guard_locked.html
+fn dispatch<C, F>(context: &Weak<Mutex<C>>, f: F)
+where
+    C: CallbackContext + AsRaw,
+    F: FnOnce(&mut C),
+{
+    if let Some(context) = context.upgrade() {
+        ffi::guard_locked(&context, f);
+    }
+}
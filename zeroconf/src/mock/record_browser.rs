@@ -0,0 +1,296 @@
+//! Mock implementation for cross-platform record browser
+
+use super::event_loop::EventQueue;
+use super::registry::{self, Change, Listener, Registration};
+use crate::error::Error;
+use crate::ffi::CallbackContext;
+use crate::prelude::*;
+use crate::service::DEFAULT_RECORD_TTL;
+use crate::{
+    EventLoop, Id, NetworkInterface, QueriedRecord, RecordEvent, RecordEventCallback, Result,
+    RR_CLASS_IN,
+};
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, Weak};
+
+const RR_TYPE_TXT: u16 = 16;
+
+/// Record browser that finds the TXT records and additional records of the services registered
+/// with the mock backend.
+pub struct MockMdnsRecordBrowser {
+    id: Id,
+    user_context: Option<Arc<dyn Any + Send + Sync>>,
+    context: Arc<Mutex<MockRecordBrowserContext>>,
+    listener: Option<Arc<Listener>>,
+}
+
+impl TMdnsRecordBrowser for MockMdnsRecordBrowser {
+    fn new() -> Self {
+        let context = MockRecordBrowserContext::new();
+
+        Self {
+            id: context.id,
+            user_context: None,
+            context: Arc::new(Mutex::new(context)),
+            listener: None,
+        }
+    }
+
+    fn id(&self) -> Id {
+        self.id
+    }
+
+    fn set_network_interface(&mut self, interface: NetworkInterface) {
+        self.lock().interface = interface;
+    }
+
+    fn network_interface(&self) -> NetworkInterface {
+        self.lock().interface
+    }
+
+    fn set_record_event_callback(&mut self, record_event_callback: Box<RecordEventCallback>) {
+        self.lock().record_event_callback = Some(record_event_callback);
+    }
+
+    fn set_context(&mut self, context: Box<dyn Any + Send + Sync>) {
+        let context = Arc::from(context);
+        self.user_context = Some(Arc::clone(&context));
+        self.lock().user_context = Some(context);
+    }
+
+    fn context(&self) -> Option<&(dyn Any + Send + Sync)> {
+        self.user_context.as_ref().map(|c| c.as_ref())
+    }
+
+    /// Starts querying the records of the services registered with the mock backend. The records
+    /// that are already published are reported once the event loop is polled, along with the
+    /// records added or removed later on.
+    fn query_record(&mut self, name: &str, rr_type: u16, rr_class: u16) -> Result<EventLoop> {
+        debug!(
+            "[{}] Querying record `{}` (type: {}, class: {})",
+            self.id, name, rr_type, rr_class
+        );
+
+        {
+            let mut context = self.lock();
+            context.name = name.strip_suffix('.').unwrap_or(name).to_string();
+            context.rr_type = rr_type;
+            context.rr_class = rr_class;
+            context.found.clear();
+        }
+
+        let queue = Arc::new(EventQueue::default());
+        let listener = listener(&self.context, &queue);
+
+        registry::subscribe(&listener, |services| {
+            let context = Arc::downgrade(&self.context);
+            let services = services.to_vec();
+
+            queue.push(Box::new(move || {
+                super::dispatch(&context, |context: &mut MockRecordBrowserContext| {
+                    for registration in &services {
+                        context.update(registration, false);
+                    }
+                })
+            }));
+        });
+
+        self.listener = Some(listener);
+
+        let event_loop = EventLoop::new(queue);
+
+        debug!("[{}] Event loop: {}", self.id, event_loop.id());
+
+        Ok(event_loop)
+    }
+}
+
+impl fmt::Debug for MockMdnsRecordBrowser {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MockMdnsRecordBrowser")
+            .field("id", &self.id)
+            .field("context", &self.context)
+            .finish()
+    }
+}
+
+impl MockMdnsRecordBrowser {
+    fn lock(&self) -> MutexGuard<'_, MockRecordBrowserContext> {
+        self.context.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[derive(FromRaw, AsRaw)]
+struct MockRecordBrowserContext {
+    id: Id,
+    record_event_callback: Option<Box<RecordEventCallback>>,
+    user_context: Option<Arc<dyn Any + Send + Sync>>,
+    interface: NetworkInterface,
+    name: String,
+    rr_type: u16,
+    rr_class: u16,
+    /// Records reported so far, by the id of the service that published them
+    found: HashMap<Id, Vec<QueriedRecord>>,
+}
+
+impl MockRecordBrowserContext {
+    fn new() -> Self {
+        Self {
+            id: Id::next(),
+            record_event_callback: None,
+            user_context: None,
+            interface: NetworkInterface::Unspec,
+            name: String::new(),
+            rr_type: 0,
+            rr_class: 0,
+            found: HashMap::new(),
+        }
+    }
+
+    /// Returns the records published by `registration` that match the query. Placeholders
+    /// registered on port `0` publish no records.
+    fn records(&self, registration: &Registration) -> Vec<QueriedRecord> {
+        if self.rr_class != RR_CLASS_IN
+            || registration.port == 0
+            || !registration.is_on(self.interface)
+        {
+            return vec![];
+        }
+
+        let full_name = match registration.full_name() {
+            Ok(full_name) => full_name,
+            Err(_) => return vec![],
+        };
+
+        let interface = match self.interface {
+            NetworkInterface::Unspec => registration.interface,
+            requested => requested,
+        };
+
+        let txt = (self.rr_type == RR_TYPE_TXT).then(|| {
+            (
+                full_name.as_str(),
+                registration.txt_bytes(),
+                DEFAULT_RECORD_TTL,
+            )
+        });
+
+        let records = registration
+            .records
+            .iter()
+            .filter(|r| *r.rr_type() == self.rr_type)
+            .map(|r| {
+                (
+                    r.name().as_deref().unwrap_or(&full_name),
+                    r.rdata().clone(),
+                    *r.ttl(),
+                )
+            });
+
+        txt.into_iter()
+            .chain(records)
+            .filter(|(name, _, _)| {
+                name.strip_suffix('.')
+                    .unwrap_or(name)
+                    .eq_ignore_ascii_case(&self.name)
+            })
+            .filter_map(|(name, rdata, ttl)| {
+                QueriedRecord::builder()
+                    .name(name.to_string())
+                    .rr_type(self.rr_type)
+                    .rr_class(RR_CLASS_IN)
+                    .rdata(rdata)
+                    .ttl(Some(ttl))
+                    .interface(interface)
+                    .build()
+                    .ok()
+            })
+            .collect()
+    }
+
+    /// Reports the records of `registration` that have been added or removed since the last
+    /// update.
+    fn update(&mut self, registration: &Registration, removed: bool) {
+        let current = if removed {
+            vec![]
+        } else {
+            self.records(registration)
+        };
+
+        let previous = self.found.remove(&registration.id).unwrap_or_default();
+
+        for record in previous.iter().filter(|r| !current.contains(r)) {
+            self.invoke_callback(Ok(RecordEvent::Remove(record.clone())));
+        }
+
+        for record in current.iter().filter(|r| !previous.contains(r)) {
+            self.invoke_callback(Ok(RecordEvent::Add(record.clone())));
+        }
+
+        if !current.is_empty() {
+            self.found.insert(registration.id, current);
+        }
+    }
+
+    fn invoke_callback(&self, result: Result<RecordEvent>) {
+        debug!("[{}] Record event: {:?}", self.id, result);
+
+        if let Some(f) = &self.record_event_callback {
+            f(result, self.user_context.clone());
+        } else {
+            warn!(
+                "[{}] attempted to invoke record browser callback but none was set",
+                self.id
+            );
+        }
+    }
+}
+
+impl CallbackContext for MockRecordBrowserContext {
+    fn id(&self) -> Id {
+        self.id
+    }
+
+    fn invoke_error(&self, error: Error) {
+        self.invoke_callback(Err(error))
+    }
+}
+
+impl fmt::Debug for MockRecordBrowserContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MockRecordBrowserContext")
+            .field("id", &self.id)
+            .field("name", &self.name)
+            .field("rr_type", &self.rr_type)
+            .field("rr_class", &self.rr_class)
+            .finish()
+    }
+}
+
+/// Returns the listener that queues the changes to the registry for the browser.
+fn listener(
+    context: &Arc<Mutex<MockRecordBrowserContext>>,
+    queue: &Arc<EventQueue>,
+) -> Arc<Listener> {
+    let context = Arc::downgrade(context);
+    let queue = Arc::downgrade(queue);
+
+    Arc::new(move |change: &Change| {
+        let queue = match queue.upgrade() {
+            Some(queue) => queue,
+            None => return,
+        };
+
+        let context = Weak::clone(&context);
+        let change = change.clone();
+
+        queue.push(Box::new(move || {
+            super::dispatch(&context, |context: &mut MockRecordBrowserContext| {
+                let removed = matches!(change, Change::Removed(_));
+                context.update(change.registration(), removed);
+            })
+        }));
+    })
+}
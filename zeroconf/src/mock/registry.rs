@@ -0,0 +1,285 @@
+//! Registry of the services registered with the mock backend, shared by the whole process
+
+use crate::browser;
+use crate::error::{Error, ErrorContext, Operation};
+use crate::prelude::*;
+use crate::service_name;
+use crate::{
+    fullname, Id, InterfaceChange, LookupFlags, NetworkInterface, Result, ServiceDiscovery,
+    ServiceKey, ServiceRecord, ServiceType, TxtRecord,
+};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, Weak};
+
+static REGISTRY: Mutex<Registry> = Mutex::new(Registry {
+    services: vec![],
+    listeners: vec![],
+    interface_watchers: vec![],
+});
+
+/// A service registered by a `MockMdnsService`
+#[derive(Debug, Clone)]
+pub(crate) struct Registration {
+    /// Id of the service that registered it
+    pub(crate) id: Id,
+    pub(crate) name: String,
+    pub(crate) service_type: ServiceType,
+    pub(crate) domain: String,
+    pub(crate) host_name: String,
+    pub(crate) address: IpAddr,
+    pub(crate) port: u16,
+    pub(crate) txt: Option<TxtRecord>,
+    pub(crate) interface: NetworkInterface,
+    pub(crate) records: Vec<ServiceRecord>,
+}
+
+impl Registration {
+    /// Returns the context of the errors of `operation` on this service.
+    pub(crate) fn error_context(&self, operation: Operation) -> ErrorContext {
+        ErrorContext::new(operation)
+            .with_service_type(&self.service_type.to_string())
+            .with_name(Some(&self.name))
+    }
+
+    pub(crate) fn key(&self) -> ServiceKey {
+        ServiceKey::new(&self.name, &self.kind(), &self.domain)
+    }
+
+    /// Returns the type of the service without its sub-types, as it is reported to browsers.
+    pub(crate) fn kind(&self) -> ServiceType {
+        ServiceType::new_unchecked(self.service_type.name(), self.service_type.protocol())
+    }
+
+    /// Returns the full name of the service, e.g. `foo._http._tcp.local`.
+    pub(crate) fn full_name(&self) -> Result<String> {
+        fullname::construct(&self.name, &self.service_type, &self.domain)
+    }
+
+    /// Returns true if this is the service of the specified name, type and domain. Names and
+    /// domains are compared case-insensitively, like DNS names.
+    pub(crate) fn is(&self, name: &str, service_type: &ServiceType, domain: &str) -> bool {
+        let domain = domain.strip_suffix('.').unwrap_or(domain);
+
+        self.name.eq_ignore_ascii_case(name)
+            && self.service_type.name() == service_type.name()
+            && self.service_type.protocol() == service_type.protocol()
+            && self.domain.eq_ignore_ascii_case(domain)
+    }
+
+    /// Returns true if the service is published on `interface`. Every mock service is registered on
+    /// the local machine, so browsing with `NetworkInterface::LocalOnly` finds all of them.
+    pub(crate) fn is_on(&self, interface: NetworkInterface) -> bool {
+        self.interface == NetworkInterface::Unspec
+            || interface == NetworkInterface::Unspec
+            || interface == NetworkInterface::LocalOnly
+            || self.interface == interface
+    }
+
+    /// Returns the discovery of this service by a browser or resolver on `interface`.
+    pub(crate) fn discovery(
+        &self,
+        interface: NetworkInterface,
+        flags: LookupFlags,
+    ) -> Result<ServiceDiscovery> {
+        let interface = match interface {
+            NetworkInterface::Unspec => self.interface,
+            interface => interface,
+        };
+
+        let address = if flags.contains(LookupFlags::NO_ADDRESS) {
+            String::new()
+        } else {
+            browser::scoped_address(self.address.to_string(), interface)
+        };
+
+        // like by the platform implementations, a service without a TXT record is advertised
+        // with an empty one
+        let txt = if flags.contains(LookupFlags::NO_TXT) {
+            None
+        } else {
+            Some(self.txt.clone().unwrap_or_default())
+        };
+
+        Ok(ServiceDiscovery::builder()
+            .name(self.name.clone())
+            .service_type(self.kind())
+            .domain(self.domain.clone())
+            .host_name(self.host_name.clone())
+            .address(address)
+            .port(self.port)
+            .txt(txt)
+            .interface(interface)
+            .build_owned()?)
+    }
+
+    /// Returns the wire format of the TXT record of this service.
+    pub(crate) fn txt_bytes(&self) -> Vec<u8> {
+        match &self.txt {
+            Some(txt) => txt.to_bytes(),
+            None => TxtRecord::new().to_bytes(),
+        }
+    }
+}
+
+/// Change to the registry, reported to the listeners
+#[derive(Debug, Clone)]
+pub(crate) enum Change {
+    /// A service has been registered
+    Added(Registration),
+    /// The TXT record or records of a registered service have changed
+    Updated(Registration),
+    /// A service has been removed
+    Removed(Registration),
+}
+
+impl Change {
+    /// Returns the service that has changed.
+    pub(crate) fn registration(&self) -> &Registration {
+        match self {
+            Change::Added(r) | Change::Updated(r) | Change::Removed(r) => r,
+        }
+    }
+}
+
+/// Function called for every change to the registry. It is called while the registry is locked
+/// and must not call back into it.
+pub(crate) type Listener = dyn Fn(&Change) + Send + Sync;
+
+struct Registry {
+    services: Vec<Registration>,
+    listeners: Vec<Weak<Listener>>,
+    /// Inboxes of the interface watchers of the browsers
+    interface_watchers: Vec<Weak<Mutex<Vec<InterfaceChange>>>>,
+}
+
+fn lock() -> MutexGuard<'static, Registry> {
+    REGISTRY.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn notify(listeners: &mut Vec<Weak<Listener>>, change: Change) {
+    listeners.retain(|listener| match listener.upgrade() {
+        Some(listener) => {
+            listener(&change);
+            true
+        }
+        None => false,
+    });
+}
+
+/// Adds `registration` to the registry and returns it as registered.
+///
+/// If another service of the same type is already registered under the name, the name is
+/// replaced with an alternative like Avahi does, or `Error::NameCollision` is returned if
+/// `allow_name_conflicts` is false.
+pub(crate) fn register(
+    mut registration: Registration,
+    allow_name_conflicts: bool,
+) -> Result<Registration> {
+    let mut registry = lock();
+
+    while registry.services.iter().any(|s| {
+        s.id != registration.id
+            && s.is(
+                &registration.name,
+                &registration.service_type,
+                &registration.domain,
+            )
+    }) {
+        if !allow_name_conflicts {
+            return Err(Error::NameCollision {
+                name: registration.name,
+            });
+        }
+
+        registration.name = service_name::alternative_service_name(&registration.name);
+    }
+
+    let Registry {
+        services,
+        listeners,
+        ..
+    } = &mut *registry;
+
+    services.push(registration.clone());
+    notify(listeners, Change::Added(registration.clone()));
+
+    Ok(registration)
+}
+
+/// Applies `f` to the services registered by the service with the specified id.
+pub(crate) fn update(id: Id, f: impl Fn(&mut Registration)) {
+    let mut registry = lock();
+
+    let Registry {
+        services,
+        listeners,
+        ..
+    } = &mut *registry;
+
+    for registration in services.iter_mut().filter(|s| s.id == id) {
+        f(registration);
+        notify(listeners, Change::Updated(registration.clone()));
+    }
+}
+
+/// Removes the services registered by the service with the specified id.
+pub(crate) fn unregister(id: Id) {
+    let mut registry = lock();
+
+    let Registry {
+        services,
+        listeners,
+        ..
+    } = &mut *registry;
+
+    let (removed, kept) = services.drain(..).partition(|s| s.id == id);
+    *services = kept;
+
+    for registration in removed {
+        notify(listeners, Change::Removed(registration));
+    }
+}
+
+/// Removes the services registered by the service with the specified id without notifying the
+/// browsers, like a host that drops off the network without sending a goodbye.
+#[cfg(test)]
+pub(crate) fn vanish(id: Id) {
+    lock().services.retain(|s| s.id != id);
+}
+
+/// Returns the registered services.
+pub(crate) fn services() -> Vec<Registration> {
+    lock().services.clone()
+}
+
+/// Adds `listener` to be called for every change until it is dropped. No change is reported for
+/// the services that are already registered, they are passed to `registered` instead before any
+/// later change is reported.
+pub(crate) fn subscribe(listener: &Arc<Listener>, registered: impl FnOnce(&[Registration])) {
+    let mut registry = lock();
+    registry.listeners.push(Arc::downgrade(listener));
+    registered(&registry.services);
+}
+
+/// Adds `inbox` to receive every change passed to [`interface_changed()`] until it is dropped.
+///
+/// [`interface_changed()`]: fn.interface_changed.html
+pub(crate) fn watch_interfaces(inbox: &Arc<Mutex<Vec<InterfaceChange>>>) {
+    lock().interface_watchers.push(Arc::downgrade(inbox));
+}
+
+/// Passes `change` to every interface watcher.
+pub(crate) fn interface_changed(change: InterfaceChange) {
+    lock()
+        .interface_watchers
+        .retain(|inbox| match inbox.upgrade() {
+            Some(inbox) => {
+                inbox
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .push(change);
+                true
+            }
+            None => false,
+        });
+}
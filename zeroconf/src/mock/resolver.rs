@@ -0,0 +1,194 @@
+//! Mock implementation for cross-platform service resolver
+
+use super::event_loop::EventQueue;
+use super::registry;
+use crate::error::Error;
+use crate::ffi::CallbackContext;
+use crate::prelude::*;
+use crate::span::Span;
+use crate::{
+    EventLoop, Id, LookupFlags, NetworkInterface, Result, ServiceDiscoveredCallback,
+    ServiceDiscovery, ServiceType,
+};
+use std::any::Any;
+use std::fmt;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// Resolver that looks up the services registered in the registry of the mock backend.
+#[derive(Debug)]
+pub struct MockMdnsResolver {
+    id: Id,
+    user_context: Option<Arc<dyn Any + Send + Sync>>,
+    context: Arc<Mutex<MockResolverContext>>,
+}
+
+impl TMdnsResolver for MockMdnsResolver {
+    fn new() -> Self {
+        let context = MockResolverContext::new();
+
+        Self {
+            id: context.id,
+            user_context: None,
+            context: Arc::new(Mutex::new(context)),
+        }
+    }
+
+    fn id(&self) -> Id {
+        self.id
+    }
+
+    fn set_network_interface(&mut self, interface: NetworkInterface) {
+        self.lock().interface = interface;
+    }
+
+    fn network_interface(&self) -> NetworkInterface {
+        self.lock().interface
+    }
+
+    fn set_service_resolved_callback(
+        &mut self,
+        service_resolved_callback: Box<ServiceDiscoveredCallback>,
+    ) {
+        self.lock().service_resolved_callback = Some(Mutex::new(service_resolved_callback));
+    }
+
+    fn set_context(&mut self, context: Box<dyn Any + Send + Sync>) {
+        let context = Arc::from(context);
+        self.user_context = Some(Arc::clone(&context));
+        self.lock().user_context = Some(context);
+    }
+
+    fn context(&self) -> Option<&(dyn Any + Send + Sync)> {
+        self.user_context.as_ref().map(|c| c.as_ref())
+    }
+
+    /// Looks up the service in the registry of the mock backend. The service is reported once the
+    /// event loop is polled, or an error if no such service is registered at that point.
+    fn resolve(
+        &mut self,
+        name: &str,
+        service_type: ServiceType,
+        domain: &str,
+    ) -> Result<EventLoop> {
+        let queue = Arc::new(EventQueue::default());
+
+        {
+            let mut context = self.lock();
+
+            let span = span!(
+                "resolve",
+                id = %context.id,
+                name,
+                service_type = service_type.to_string().as_str()
+            );
+            let _entered = span.enter();
+            context.span = span.clone();
+
+            debug!(
+                "[{}] Resolving service `{}` of type `{}` in `{}`",
+                context.id, name, service_type, domain
+            );
+        }
+
+        let context = Arc::downgrade(&self.context);
+        let name = name.to_string();
+        let domain = domain.to_string();
+
+        queue.push(Box::new(move || {
+            super::dispatch(&context, |context: &mut MockResolverContext| {
+                let result = context.resolve(&name, &service_type, &domain);
+                context.invoke_callback(result);
+            })
+        }));
+
+        let event_loop = EventLoop::new(queue);
+
+        debug!("[{}] Event loop: {}", self.id, event_loop.id());
+
+        Ok(event_loop)
+    }
+}
+
+impl MockMdnsResolver {
+    fn lock(&self) -> MutexGuard<'_, MockResolverContext> {
+        self.context.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[derive(FromRaw, AsRaw)]
+struct MockResolverContext {
+    id: Id,
+    service_resolved_callback: Option<Mutex<Box<ServiceDiscoveredCallback>>>,
+    user_context: Option<Arc<dyn Any + Send + Sync>>,
+    interface: NetworkInterface,
+    span: Span,
+}
+
+impl MockResolverContext {
+    fn new() -> Self {
+        Self {
+            id: Id::next(),
+            service_resolved_callback: None,
+            user_context: None,
+            interface: NetworkInterface::Unspec,
+            span: Span::none(),
+        }
+    }
+
+    /// Returns the registered service of the specified name, type and domain. Placeholders
+    /// registered on port `0` cannot be resolved.
+    fn resolve(
+        &self,
+        name: &str,
+        service_type: &ServiceType,
+        domain: &str,
+    ) -> Result<ServiceDiscovery> {
+        registry::services()
+            .iter()
+            .find(|r| r.port != 0 && r.is_on(self.interface) && r.is(name, service_type, domain))
+            .ok_or_else(|| {
+                Error::from(format!(
+                    "failed to resolve service `{}` of type `{}` in `{}`",
+                    name, service_type, domain
+                ))
+            })?
+            .discovery(self.interface, LookupFlags::NONE)
+    }
+
+    fn invoke_callback(&self, result: Result<ServiceDiscovery>) {
+        debug!("[{}] Service resolved: {:?}", self.id, result);
+
+        if let Some(f) = &self.service_resolved_callback {
+            let mut f = f.lock().unwrap_or_else(PoisonError::into_inner);
+            f(result, self.user_context.clone());
+        } else {
+            warn!(
+                "[{}] attempted to invoke resolver callback but none was set",
+                self.id
+            );
+        }
+    }
+}
+
+impl CallbackContext for MockResolverContext {
+    fn id(&self) -> Id {
+        self.id
+    }
+
+    fn invoke_error(&self, error: Error) {
+        self.invoke_callback(Err(error))
+    }
+
+    fn span(&self) -> Option<&Span> {
+        Some(&self.span)
+    }
+}
+
+impl fmt::Debug for MockResolverContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MockResolverContext")
+            .field("id", &self.id)
+            .field("interface", &self.interface)
+            .finish()
+    }
+}
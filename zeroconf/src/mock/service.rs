@@ -0,0 +1,640 @@
+//! Mock implementation for cross-platform service.
+
+use super::event_loop::EventQueue;
+use super::registry::{self, Registration};
+use crate::defer::{self, DeferredQueue};
+use crate::error::{Error, Operation, ResultExt};
+use crate::ffi::CallbackContext;
+use crate::prelude::*;
+use crate::span::Span;
+use crate::{service, service_name};
+use crate::{
+    BackendInfo, DaemonState, DaemonStateCallback, Deferrer, EventLoop, Id, NameChange,
+    NameChangedCallback, NetworkInterface, Protocol, RegistrationFlags, RegistrationState,
+    RegistrationStateCallback, Result, ServiceRecord, ServiceRegisteredCallback,
+    ServiceRegistration, ServiceState, ServiceType, TxtRecord,
+};
+use std::any::Any;
+use std::fmt::{self, Formatter};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, Weak};
+
+/// Service that is registered in the registry of the mock backend, and removed from it when
+/// dropped.
+#[derive(Debug)]
+pub struct MockMdnsService {
+    id: Id,
+    service_type: ServiceType,
+    name: Option<String>,
+    name_fitting: bool,
+    allow_name_conflicts: bool,
+    auto_reconnect: bool,
+    wait_for_daemon: bool,
+    port: u16,
+    interface: NetworkInterface,
+    protocol: Protocol,
+    domain: Option<String>,
+    host: Option<String>,
+    address: Option<IpAddr>,
+    txt_record: Option<TxtRecord>,
+    records: Vec<ServiceRecord>,
+    registration_flags: RegistrationFlags,
+    user_context: Option<Arc<dyn Any + Send + Sync>>,
+    context: Arc<Mutex<MockServiceContext>>,
+}
+
+impl TMdnsService for MockMdnsService {
+    fn new(service_type: ServiceType, port: u16) -> Self {
+        let context = MockServiceContext::new();
+
+        Self {
+            id: context.id,
+            service_type,
+            name: None,
+            name_fitting: false,
+            allow_name_conflicts: true,
+            auto_reconnect: false,
+            wait_for_daemon: false,
+            port,
+            interface: NetworkInterface::Unspec,
+            protocol: Protocol::Any,
+            domain: None,
+            host: None,
+            address: None,
+            txt_record: None,
+            records: vec![],
+            registration_flags: RegistrationFlags::NONE,
+            user_context: None,
+            context: Arc::new(Mutex::new(context)),
+        }
+    }
+
+    fn id(&self) -> Id {
+        self.id
+    }
+
+    /// Sets the name to register this service under. If no name is set, the name of the mock
+    /// host is used instead.
+    fn set_name(&mut self, name: &str) {
+        self.name = Some(name.to_string());
+    }
+
+    fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    fn set_name_fitting(&mut self, name_fitting: bool) {
+        self.name_fitting = name_fitting;
+    }
+
+    fn name_fitting(&self) -> bool {
+        self.name_fitting
+    }
+
+    fn set_allow_name_conflicts(&mut self, allow_name_conflicts: bool) {
+        self.allow_name_conflicts = allow_name_conflicts;
+    }
+
+    fn allow_name_conflicts(&self) -> bool {
+        self.allow_name_conflicts
+    }
+
+    fn set_auto_reconnect(&mut self, auto_reconnect: bool) -> Result<()> {
+        self.auto_reconnect = auto_reconnect;
+        Ok(())
+    }
+
+    fn auto_reconnect(&self) -> bool {
+        self.auto_reconnect
+    }
+
+    fn set_port(&mut self, port: u16) {
+        self.port = port;
+    }
+
+    fn port(&self) -> u16 {
+        self.port
+    }
+
+    fn set_network_interface(&mut self, interface: NetworkInterface) {
+        self.interface = interface;
+    }
+
+    fn network_interface(&self) -> NetworkInterface {
+        self.interface
+    }
+
+    /// The mock backend has a single address per host, so the protocol is only recorded.
+    fn set_protocol(&mut self, protocol: Protocol) -> Result<()> {
+        self.protocol = protocol;
+        Ok(())
+    }
+
+    fn protocol(&self) -> Protocol {
+        self.protocol
+    }
+
+    fn set_domain(&mut self, domain: &str) {
+        self.domain = Some(domain.to_string());
+    }
+
+    fn domain(&self) -> Option<&str> {
+        self.domain.as_deref()
+    }
+
+    fn set_host(&mut self, host: &str) {
+        self.host = Some(host.to_string());
+    }
+
+    fn host(&self) -> Option<&str> {
+        self.host.as_deref()
+    }
+
+    fn set_address(&mut self, address: IpAddr) {
+        self.address = Some(address);
+    }
+
+    fn address(&self) -> Option<IpAddr> {
+        self.address
+    }
+
+    fn set_txt_record(&mut self, txt_record: TxtRecord) {
+        self.txt_record = Some(txt_record);
+    }
+
+    fn txt_record(&self) -> Option<&TxtRecord> {
+        self.txt_record.as_ref()
+    }
+
+    fn add_record(&mut self, record: ServiceRecord) -> Result<()> {
+        record.validate()?;
+        self.records.push(record);
+        Ok(())
+    }
+
+    fn records(&self) -> &[ServiceRecord] {
+        &self.records
+    }
+
+    fn add_sub_type(&mut self, sub_type: &str) -> Result<()> {
+        self.service_type.add_sub_type(sub_type).map(|_| ())
+    }
+
+    fn remove_sub_type(&mut self, sub_type: &str) -> Result<()> {
+        self.service_type.remove_sub_type(sub_type).map(|_| ())
+    }
+
+    fn set_registration_flags(&mut self, flags: RegistrationFlags) -> Result<()> {
+        self.registration_flags = flags;
+        Ok(())
+    }
+
+    fn registration_flags(&self) -> RegistrationFlags {
+        self.registration_flags
+    }
+
+    fn set_registered_callback(&mut self, registered_callback: Box<ServiceRegisteredCallback>) {
+        self.lock().registered_callback = Some(Mutex::new(registered_callback));
+    }
+
+    fn set_registration_state_callback(
+        &mut self,
+        registration_state_callback: Box<RegistrationStateCallback>,
+    ) {
+        self.lock().registration_state_callback = Some(registration_state_callback);
+    }
+
+    fn set_name_changed_callback(&mut self, name_changed_callback: Box<NameChangedCallback>) {
+        self.lock().name_changed_callback = Some(name_changed_callback);
+    }
+
+    fn set_context(&mut self, context: Box<dyn Any + Send + Sync>) {
+        let context = Arc::from(context);
+        self.user_context = Some(Arc::clone(&context));
+        self.lock().user_context = Some(context);
+    }
+
+    fn context(&self) -> Option<&(dyn Any + Send + Sync)> {
+        self.user_context.as_ref().map(|c| c.as_ref())
+    }
+
+    fn deferrer(&self) -> Deferrer {
+        Deferrer::new(&self.lock().deferred)
+    }
+
+    fn set_wait_for_daemon(&mut self, wait_for_daemon: bool) -> Result<()> {
+        self.wait_for_daemon = wait_for_daemon;
+        Ok(())
+    }
+
+    fn wait_for_daemon(&self) -> bool {
+        self.wait_for_daemon
+    }
+
+    fn set_daemon_state_callback(&mut self, daemon_state_callback: Box<DaemonStateCallback>) {
+        self.lock().daemon_state_callback = Some(daemon_state_callback);
+    }
+
+    /// Adds the service to the registry of the mock backend, where it is found by browsers right
+    /// away. The result is passed to the registered callback once the event loop is polled.
+    fn register(&mut self) -> Result<EventLoop> {
+        let span = span!(
+            "register",
+            id = %self.id,
+            name = self.name.as_deref(),
+            service_type = self.service_type.to_string().as_str()
+        );
+        let _entered = span.enter();
+
+        debug!("[{}] Registering service: {:?}", self.id, self);
+
+        if self.address.is_some() && self.host.is_none() {
+            return Err(service::ADDRESS_WITHOUT_HOST.into());
+        }
+
+        let default_name = super::HOST_NAME.strip_suffix(".local");
+        let name = self.name.as_deref().or(default_name).unwrap_or_default();
+        let (name, name_adjusted) = service_name::prepare(name, self.name_fitting)?;
+
+        if name_adjusted {
+            debug!("[{}] Service name shortened to: {}", self.id, name);
+        }
+
+        let domain = self.domain.as_deref().unwrap_or(super::DOMAIN);
+
+        let registration = Registration {
+            id: self.id,
+            name: name.into_owned(),
+            service_type: self.service_type.clone(),
+            domain: domain.strip_suffix('.').unwrap_or(domain).to_string(),
+            host_name: self.host.as_deref().unwrap_or(super::HOST_NAME).to_string(),
+            address: self.address.unwrap_or(super::HOST_ADDRESS),
+            port: self.port,
+            txt: self.txt_record.clone(),
+            interface: self.interface,
+            records: self.records.clone(),
+        };
+
+        registry::unregister(self.id);
+
+        let error_context = registration.error_context(Operation::Register);
+        let result = registry::register(registration, self.allow_name_conflicts)
+            .with_context(|| error_context);
+
+        if let Ok(registration) = &result {
+            self.name = Some(registration.name.clone());
+        }
+
+        let queue = Arc::new(EventQueue::default());
+
+        {
+            let mut context = self.lock();
+            context.span = span.clone();
+            context.name_fitting = self.name_fitting;
+            context.allow_name_conflicts = self.allow_name_conflicts;
+            context.name_adjusted = name_adjusted;
+            context.registered_name = None;
+            context.host.clone_from(&self.host);
+            context.registration = result.as_ref().ok().cloned();
+        }
+
+        let context = Arc::downgrade(&self.context);
+
+        queue.push(Box::new(move || {
+            super::dispatch(&context, |context: &mut MockServiceContext| {
+                context.invoke_daemon_state_callback(DaemonState::Running);
+
+                match result {
+                    Ok(_) => {
+                        context.invoke_state_callback(RegistrationState::Registering);
+                        let result = context.registered();
+                        context.report_registration(result);
+                    }
+                    Err(e) => context.report_registration(Err(e)),
+                }
+            })
+        }));
+
+        let event_loop = EventLoop::new(queue);
+
+        debug!("[{}] Event loop: {}", self.id, event_loop.id());
+
+        Ok(event_loop.with_deferred(DeferredRunner::new(&self.context)))
+    }
+
+    fn state(&self) -> ServiceState {
+        self.lock().state
+    }
+
+    fn backend_info(&self) -> Result<BackendInfo> {
+        if self.lock().registration.is_none() {
+            return Err("service has not been registered".into());
+        }
+
+        Ok(super::backend_info())
+    }
+}
+
+impl MockMdnsService {
+    fn lock(&self) -> MutexGuard<'_, MockServiceContext> {
+        self.context.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Drop for MockMdnsService {
+    fn drop(&mut self) {
+        registry::unregister(self.id);
+    }
+}
+
+/// Handle passed to closures queued with a [`Deferrer`]. See [`TDeferredService`].
+///
+/// [`Deferrer`]: ../../struct.Deferrer.html
+/// [`TDeferredService`]: ../../defer/trait.TDeferredService.html
+#[derive(Debug)]
+pub struct MockDeferredService<'a> {
+    context: &'a mut MockServiceContext,
+}
+
+impl TDeferredService for MockDeferredService<'_> {
+    fn name(&self) -> Option<&str> {
+        self.context.registration.as_ref().map(|r| r.name.as_str())
+    }
+
+    fn txt_record(&self) -> Option<&TxtRecord> {
+        self.context.registration.as_ref()?.txt.as_ref()
+    }
+
+    fn set_txt_record(&mut self, txt_record: TxtRecord) -> Result<()> {
+        let registration = self
+            .context
+            .registration
+            .as_mut()
+            .ok_or("service is not registered")?;
+
+        debug!("[{}] Updating TXT record", self.context.id);
+
+        registration.txt = Some(txt_record.clone());
+        registry::update(self.context.id, |r| r.txt = Some(txt_record.clone()));
+
+        Ok(())
+    }
+
+    fn set_name(&mut self, name: &str) -> Result<()> {
+        let (fitted, adjusted) = service_name::prepare(name, self.context.name_fitting)?;
+
+        let mut registration = self
+            .context
+            .registration
+            .take()
+            .ok_or("service is not registered")?;
+
+        registration.name = fitted.into_owned();
+        self.context.name_adjusted = adjusted;
+        self.reregister(registration);
+
+        Ok(())
+    }
+
+    fn add_record(&mut self, record: ServiceRecord) -> Result<()> {
+        record.validate()?;
+
+        let registration = self
+            .context
+            .registration
+            .as_mut()
+            .ok_or("service is not registered")?;
+
+        registration.records.push(record.clone());
+        registry::update(self.context.id, |r| r.records.push(record.clone()));
+
+        Ok(())
+    }
+
+    fn add_sub_type(&mut self, sub_type: &str) -> Result<()> {
+        let mut registration = self
+            .context
+            .registration
+            .clone()
+            .ok_or("service is not registered")?;
+
+        // re-registered like by the platform implementations, so that browsers for the sub-type
+        // see the service appear
+        if registration.service_type.add_sub_type(sub_type)? {
+            self.reregister(registration);
+        }
+
+        Ok(())
+    }
+
+    fn remove_sub_type(&mut self, sub_type: &str) -> Result<()> {
+        let mut registration = self
+            .context
+            .registration
+            .clone()
+            .ok_or("service is not registered")?;
+
+        if registration.service_type.remove_sub_type(sub_type)? {
+            self.reregister(registration);
+        }
+
+        Ok(())
+    }
+}
+
+impl MockDeferredService<'_> {
+    /// Replaces the registration of the service with `registration` and reports the result to
+    /// the registered callback.
+    fn reregister(&mut self, registration: Registration) {
+        debug!("[{}] Re-registering service", self.context.id);
+
+        registry::unregister(self.context.id);
+
+        let result = registry::register(registration, self.context.allow_name_conflicts);
+
+        self.context.registration = result.as_ref().ok().cloned();
+
+        let result = result.and_then(|_| self.context.registered());
+        self.context.report_registration(result);
+    }
+}
+
+/// Executes the closures queued with a [`Deferrer`] on behalf of `MockEventLoop::poll()`.
+///
+/// [`Deferrer`]: ../../struct.Deferrer.html
+pub(crate) struct DeferredRunner {
+    context: Weak<Mutex<MockServiceContext>>,
+}
+
+impl DeferredRunner {
+    fn new(context: &Arc<Mutex<MockServiceContext>>) -> Self {
+        Self {
+            context: Arc::downgrade(context),
+        }
+    }
+
+    /// Executes the queued closures. Does nothing if the service has been dropped.
+    pub(crate) fn run(&self) {
+        let context = match self.context.upgrade() {
+            Some(context) => context,
+            None => return,
+        };
+
+        let mut context = context.lock().unwrap_or_else(PoisonError::into_inner);
+        let queue = Arc::clone(&context.deferred);
+
+        while let Some(f) = defer::pop(&queue) {
+            debug!("[{}] Running deferred closure", context.id);
+
+            if let Err(e) = f(&mut MockDeferredService {
+                context: &mut context,
+            }) {
+                context.invoke_callback(Err(e));
+            }
+        }
+    }
+}
+
+#[derive(FromRaw, AsRaw)]
+struct MockServiceContext {
+    id: Id,
+    registration: Option<Registration>,
+    name_fitting: bool,
+    name_adjusted: bool,
+    allow_name_conflicts: bool,
+    host: Option<String>,
+    state: ServiceState,
+    /// The name the service was last registered under, to report the renames that follow
+    registered_name: Option<String>,
+    registered_callback: Option<Mutex<Box<ServiceRegisteredCallback>>>,
+    registration_state_callback: Option<Box<RegistrationStateCallback>>,
+    name_changed_callback: Option<Box<NameChangedCallback>>,
+    daemon_state_callback: Option<Box<DaemonStateCallback>>,
+    user_context: Option<Arc<dyn Any + Send + Sync>>,
+    deferred: Arc<DeferredQueue>,
+    span: Span,
+}
+
+impl MockServiceContext {
+    fn new() -> Self {
+        Self {
+            id: Id::next(),
+            registration: None,
+            name_fitting: false,
+            name_adjusted: false,
+            allow_name_conflicts: true,
+            host: None,
+            state: ServiceState::Unregistered,
+            registered_name: None,
+            registered_callback: None,
+            registration_state_callback: None,
+            name_changed_callback: None,
+            daemon_state_callback: None,
+            user_context: None,
+            deferred: Arc::default(),
+            span: Span::none(),
+        }
+    }
+
+    /// Returns the `ServiceRegistration` of the current registration.
+    fn registered(&self) -> Result<ServiceRegistration> {
+        let registration = self
+            .registration
+            .as_ref()
+            .ok_or("service is not registered")?;
+
+        Ok(ServiceRegistration::builder()
+            .name(registration.name.clone())
+            .service_type(registration.service_type.clone())
+            .domain(registration.domain.clone())
+            .name_was_adjusted(self.name_adjusted)
+            .port(registration.port)
+            .host(self.host.clone())
+            .txt(registration.txt.clone())
+            .build()?)
+    }
+
+    fn invoke_callback(&self, result: Result<ServiceRegistration>) {
+        if let Some(f) = &self.registered_callback {
+            let mut f = f.lock().unwrap_or_else(PoisonError::into_inner);
+            f(result, self.user_context.clone());
+        } else {
+            warn!(
+                "[{}] attempted to invoke service callback but none was set",
+                self.id
+            );
+        }
+    }
+
+    /// Passes the outcome of the registration to the registered callback and updates the
+    /// `ServiceState` accordingly. A registration under a new name is also passed to the name
+    /// changed callback.
+    fn report_registration(&mut self, result: Result<ServiceRegistration>) {
+        self.state = ServiceState::after(&result);
+
+        let change = result
+            .as_ref()
+            .ok()
+            .and_then(|registration| NameChange::track(&mut self.registered_name, registration));
+
+        self.invoke_callback(result);
+
+        if let Some(change) = change {
+            self.invoke_name_changed_callback(change);
+        }
+    }
+
+    fn invoke_name_changed_callback(&self, change: NameChange) {
+        debug!(
+            "[{}] Service renamed from {} to {}",
+            self.id,
+            change.previous(),
+            change.name()
+        );
+
+        if let Some(f) = &self.name_changed_callback {
+            f(change, self.user_context.clone());
+        }
+    }
+
+    fn invoke_state_callback(&mut self, state: RegistrationState) {
+        debug!("[{}] Registration state: {:?}", self.id, state);
+
+        self.state = ServiceState::Registering;
+
+        if let Some(f) = &self.registration_state_callback {
+            f(state, self.user_context.clone());
+        }
+    }
+
+    fn invoke_daemon_state_callback(&self, state: DaemonState) {
+        debug!("[{}] Daemon state: {:?}", self.id, state);
+
+        if let Some(f) = &self.daemon_state_callback {
+            f(state, self.user_context.clone());
+        }
+    }
+}
+
+impl CallbackContext for MockServiceContext {
+    fn id(&self) -> Id {
+        self.id
+    }
+
+    fn invoke_error(&self, error: Error) {
+        self.invoke_callback(Err(error))
+    }
+
+    fn span(&self) -> Option<&Span> {
+        Some(&self.span)
+    }
+}
+
+impl fmt::Debug for MockServiceContext {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MockServiceContext")
+            .field("id", &self.id)
+            .field("registration", &self.registration)
+            .finish()
+    }
+}
@@ -0,0 +1,314 @@
+//! Mock implementation for cross-platform service group.
+
+use super::event_loop::EventQueue;
+use super::registry::{self, Registration};
+use crate::error::Error;
+use crate::ffi::CallbackContext;
+use crate::prelude::*;
+use crate::service_group::{self, Instance, InstanceChange, InstanceChanges};
+use crate::span::Span;
+use crate::{
+    EventLoop, Id, InstanceHandle, InstanceRegisteredCallback, NetworkInterface, Result,
+    ServiceRegistration, ServiceType, TxtRecord,
+};
+use std::any::Any;
+use std::fmt::{self, Formatter};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, Weak};
+
+/// Group of instances that are registered in the registry of the mock backend, and removed from
+/// it when dropped. Every instance is registered under the [`Id`] of its handle, so that it can be
+/// removed on its own.
+///
+/// [`Id`]: ../../struct.Id.html
+#[derive(Debug)]
+pub struct MockMdnsServiceGroup {
+    id: Id,
+    service_type: ServiceType,
+    interface: NetworkInterface,
+    user_context: Option<Arc<dyn Any + Send + Sync>>,
+    changes: Arc<InstanceChanges>,
+    context: Arc<Mutex<MockServiceGroupContext>>,
+}
+
+impl TMdnsServiceGroup for MockMdnsServiceGroup {
+    fn new(service_type: ServiceType) -> Self {
+        let context = MockServiceGroupContext::new(service_type.clone());
+
+        Self {
+            id: context.id,
+            service_type,
+            interface: NetworkInterface::Unspec,
+            user_context: None,
+            changes: Arc::default(),
+            context: Arc::new(Mutex::new(context)),
+        }
+    }
+
+    fn id(&self) -> Id {
+        self.id
+    }
+
+    fn service_type(&self) -> &ServiceType {
+        &self.service_type
+    }
+
+    fn set_network_interface(&mut self, interface: NetworkInterface) {
+        self.interface = interface;
+    }
+
+    fn network_interface(&self) -> NetworkInterface {
+        self.interface
+    }
+
+    fn add_instance(
+        &mut self,
+        name: &str,
+        port: u16,
+        txt_record: Option<TxtRecord>,
+    ) -> Result<InstanceHandle> {
+        let instance = Instance::new(name, port, txt_record)?;
+        let handle = instance.handle;
+
+        self.changes.push(InstanceChange::Add(instance));
+
+        Ok(handle)
+    }
+
+    fn remove_instance(&mut self, handle: InstanceHandle) -> Result<()> {
+        if !self.instances().contains(&handle) {
+            return Err(format!("instance {} is not in this group", handle).into());
+        }
+
+        self.changes.push(InstanceChange::Remove(handle));
+
+        Ok(())
+    }
+
+    fn instances(&self) -> Vec<InstanceHandle> {
+        let context = self.lock();
+
+        service_group::instances_after(context.instances.iter().map(|i| i.handle), &self.changes)
+    }
+
+    fn set_registered_callback(&mut self, registered_callback: Box<InstanceRegisteredCallback>) {
+        self.lock().registered_callback = Some(Mutex::new(registered_callback));
+    }
+
+    fn set_context(&mut self, context: Box<dyn Any + Send + Sync>) {
+        let context = Arc::from(context);
+        self.user_context = Some(Arc::clone(&context));
+        self.lock().user_context = Some(context);
+    }
+
+    fn context(&self) -> Option<&(dyn Any + Send + Sync)> {
+        self.user_context.as_ref().map(|c| c.as_ref())
+    }
+
+    /// Adds the instances to the registry of the mock backend, where they are found by browsers
+    /// right away. The results are passed to the registered callback once the event loop is
+    /// polled.
+    fn register(&mut self) -> Result<EventLoop> {
+        let span = span!(
+            "register",
+            id = %self.id,
+            service_type = self.service_type.to_string().as_str()
+        );
+        let _entered = span.enter();
+
+        debug!("[{}] Registering service group: {:?}", self.id, self);
+
+        let results = {
+            let mut context = self.lock();
+            context.span = span.clone();
+            context.interface = self.interface;
+            context.apply(&self.changes)
+        };
+
+        let queue = Arc::new(EventQueue::default());
+        let context = Arc::downgrade(&self.context);
+
+        queue.push(Box::new(move || {
+            super::dispatch(&context, |context: &mut MockServiceGroupContext| {
+                for (handle, result) in results {
+                    context.invoke_callback(handle, result);
+                }
+            })
+        }));
+
+        let event_loop = EventLoop::new(queue);
+
+        debug!("[{}] Event loop: {}", self.id, event_loop.id());
+
+        Ok(event_loop
+            .with_instance_changes(InstanceChangeRunner::new(&self.context, &self.changes)))
+    }
+}
+
+impl MockMdnsServiceGroup {
+    fn lock(&self) -> MutexGuard<'_, MockServiceGroupContext> {
+        self.context.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Drop for MockMdnsServiceGroup {
+    fn drop(&mut self) {
+        for instance in &self.lock().instances {
+            registry::unregister(instance.handle.id());
+        }
+    }
+}
+
+/// Applies the instances added to and removed from a group on behalf of
+/// `MockEventLoop::poll()`.
+pub(crate) struct InstanceChangeRunner {
+    context: Weak<Mutex<MockServiceGroupContext>>,
+    changes: Weak<InstanceChanges>,
+}
+
+impl InstanceChangeRunner {
+    fn new(context: &Arc<Mutex<MockServiceGroupContext>>, changes: &Arc<InstanceChanges>) -> Self {
+        Self {
+            context: Arc::downgrade(context),
+            changes: Arc::downgrade(changes),
+        }
+    }
+
+    /// Applies the changes made since the last run. Does nothing if the group has been dropped.
+    pub(crate) fn run(&self) {
+        let (context, changes) = match (self.context.upgrade(), self.changes.upgrade()) {
+            (Some(context), Some(changes)) => (context, changes),
+            _ => return,
+        };
+
+        let mut context = context.lock().unwrap_or_else(PoisonError::into_inner);
+
+        for (handle, result) in context.apply(&changes) {
+            context.invoke_callback(handle, result);
+        }
+    }
+}
+
+#[derive(FromRaw, AsRaw)]
+struct MockServiceGroupContext {
+    id: Id,
+    service_type: ServiceType,
+    interface: NetworkInterface,
+    instances: Vec<Instance>,
+    registered_callback: Option<Mutex<Box<InstanceRegisteredCallback>>>,
+    user_context: Option<Arc<dyn Any + Send + Sync>>,
+    span: Span,
+}
+
+impl MockServiceGroupContext {
+    fn new(service_type: ServiceType) -> Self {
+        Self {
+            id: Id::next(),
+            service_type,
+            interface: NetworkInterface::Unspec,
+            instances: vec![],
+            registered_callback: None,
+            user_context: None,
+            span: Span::none(),
+        }
+    }
+
+    /// Adds the instances to and removes them from the registry as requested with
+    /// `add_instance()` and `remove_instance()`, returning the results of the added instances.
+    fn apply(
+        &mut self,
+        changes: &InstanceChanges,
+    ) -> Vec<(InstanceHandle, Result<ServiceRegistration>)> {
+        let mut results = vec![];
+
+        for change in changes.take() {
+            match change {
+                InstanceChange::Add(instance) => {
+                    let handle = instance.handle;
+                    let result = self.register(instance);
+
+                    results.push((handle, result));
+                }
+                InstanceChange::Remove(handle) => {
+                    debug!("[{}] Removing instance {}", self.id, handle);
+
+                    registry::unregister(handle.id());
+                    self.instances.retain(|i| i.handle != handle);
+                }
+            }
+        }
+
+        results
+    }
+
+    fn register(&mut self, instance: Instance) -> Result<ServiceRegistration> {
+        debug!(
+            "[{}] Adding instance {}: {}",
+            self.id, instance.handle, instance.name
+        );
+
+        let registration = Registration {
+            id: instance.handle.id(),
+            name: instance.name.clone(),
+            service_type: self.service_type.clone(),
+            domain: super::DOMAIN.to_string(),
+            host_name: super::HOST_NAME.to_string(),
+            address: super::HOST_ADDRESS,
+            port: instance.port,
+            txt: instance.txt_record.clone(),
+            interface: self.interface,
+            records: vec![],
+        };
+
+        // the instance stays in the group even if it could not be registered, like with the
+        // platform implementations
+        self.instances.push(instance);
+
+        let registration = registry::register(registration, true)?;
+
+        Ok(ServiceRegistration::builder()
+            .name(registration.name.clone())
+            .service_type(registration.service_type.clone())
+            .domain(registration.domain.clone())
+            .port(registration.port)
+            .txt(registration.txt.clone())
+            .build()?)
+    }
+
+    fn invoke_callback(&self, handle: InstanceHandle, result: Result<ServiceRegistration>) {
+        if let Some(f) = &self.registered_callback {
+            let mut f = f.lock().unwrap_or_else(PoisonError::into_inner);
+            f(handle, result, self.user_context.clone());
+        } else {
+            warn!(
+                "[{}] attempted to invoke instance callback but none was set",
+                self.id
+            );
+        }
+    }
+}
+
+impl CallbackContext for MockServiceGroupContext {
+    fn id(&self) -> Id {
+        self.id
+    }
+
+    fn invoke_error(&self, error: Error) {
+        for instance in &self.instances {
+            self.invoke_callback(instance.handle, Err(error.clone()));
+        }
+    }
+
+    fn span(&self) -> Option<&Span> {
+        Some(&self.span)
+    }
+}
+
+impl fmt::Debug for MockServiceGroupContext {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MockServiceGroupContext")
+            .field("id", &self.id)
+            .field("service_type", &self.service_type)
+            .field("instances", &self.instances)
+            .finish()
+    }
+}
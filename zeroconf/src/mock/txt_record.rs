@@ -0,0 +1,157 @@
+//! Mock implementation for cross-platform TXT record.
+
+use crate::txt_record::{self, TTxtRecord};
+use crate::Result;
+use std::collections::HashMap;
+
+/// TXT record held in memory. Entries are kept in the order they were inserted in.
+#[derive(Clone)]
+pub struct MockTxtRecord {
+    entries: Vec<(String, Option<Vec<u8>>)>,
+}
+
+impl TTxtRecord for MockTxtRecord {
+    fn new() -> Self {
+        Self { entries: vec![] }
+    }
+
+    fn insert_bytes(&mut self, key: &str, value: &[u8]) -> Result<()> {
+        txt_record::validate_entry(key, value)?;
+
+        self.remove(key);
+        self.entries.push((key.to_string(), Some(value.to_vec())));
+
+        Ok(())
+    }
+
+    fn insert_key_only(&mut self, key: &str) -> Result<()> {
+        txt_record::validate_entry(key, &[])?;
+
+        self.remove(key);
+        self.entries.push((key.to_string(), None));
+
+        Ok(())
+    }
+
+    fn get_bytes(&self, key: &str) -> Option<Vec<u8>> {
+        self.position(key)
+            .and_then(|i| self.entries[i].1.as_ref())
+            .cloned()
+    }
+
+    fn remove(&mut self, key: &str) -> Option<String> {
+        let (_, value) = self.entries.remove(self.position(key)?);
+
+        while let Some(i) = self.position(key) {
+            self.entries.remove(i);
+        }
+
+        Some(
+            value
+                .map(|v| String::from_utf8_lossy(&v).into_owned())
+                .unwrap_or_default(),
+        )
+    }
+
+    fn contains_key(&self, key: &str) -> bool {
+        self.position(key).is_some()
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (String, String)> + 'a> {
+        Box::new(
+            self.iter_bytes()
+                .map(|(key, value)| (key, String::from_utf8_lossy(&value).into_owned())),
+        )
+    }
+
+    fn keys<'a>(&'a self) -> Box<dyn Iterator<Item = String> + 'a> {
+        Box::new(self.entries.iter().map(|(key, _)| key.clone()))
+    }
+
+    fn values<'a>(&'a self) -> Box<dyn Iterator<Item = String> + 'a> {
+        Box::new(self.iter().map(|(_, value)| value))
+    }
+
+    fn iter_bytes<'a>(&'a self) -> Box<dyn Iterator<Item = (String, Vec<u8>)> + 'a> {
+        Box::new(
+            self.entries
+                .iter()
+                .map(|(key, value)| (key.clone(), value.clone().unwrap_or_default())),
+        )
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        if self.entries.is_empty() {
+            return vec![0];
+        }
+
+        let mut data = vec![];
+
+        for (key, value) in &self.entries {
+            let mut entry = key.as_bytes().to_vec();
+
+            if let Some(value) = value {
+                entry.push(b'=');
+                entry.extend_from_slice(value);
+            }
+
+            data.push(entry.len() as u8);
+            data.extend_from_slice(&entry);
+        }
+
+        data
+    }
+
+    fn from_bytes(data: &[u8]) -> Result<Self> {
+        let data = txt_record::sanitize_wire_format(data)?;
+        let mut record = Self::new();
+        let mut offset = 0;
+
+        while offset < data.len() {
+            let end = offset + 1 + data[offset] as usize;
+            let entry = &data[offset + 1..end];
+
+            // the keys are checked to be ASCII by `sanitize_wire_format()`
+            let (key, value) = match entry.iter().position(|b| *b == b'=') {
+                Some(i) => (&entry[..i], Some(entry[i + 1..].to_vec())),
+                None => (entry, None),
+            };
+
+            record
+                .entries
+                .push((String::from_utf8_lossy(key).into_owned(), value));
+
+            offset = end;
+        }
+
+        Ok(record)
+    }
+}
+
+impl MockTxtRecord {
+    /// Returns the value at the specified key without copying it, or `None` if no such key exists.
+    /// Keys without a value have an empty value.
+    pub(crate) fn value_ref(&self, key: &str) -> Option<&[u8]> {
+        self.position(key)
+            .map(|i| self.entries[i].1.as_deref().unwrap_or_default())
+    }
+
+    fn position(&self, key: &str) -> Option<usize> {
+        txt_record::lookup_key(key)?;
+
+        self.entries
+            .iter()
+            .position(|(k, _)| k.eq_ignore_ascii_case(key))
+    }
+}
+
+impl PartialEq for MockTxtRecord {
+    fn eq(&self, other: &Self) -> bool {
+        self.iter_bytes().collect::<HashMap<_, _>>()
+            == other.iter_bytes().collect::<HashMap<_, _>>()
+    }
+}
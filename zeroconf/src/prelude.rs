@@ -1,8 +1,14 @@
 //! Crate prelude
 
 pub use crate::browser::TMdnsBrowser;
+pub use crate::defer::TDeferredService;
+pub use crate::domain_browser::TMdnsDomainBrowser;
 pub use crate::event_loop::TEventLoop;
+pub use crate::host_resolver::TMdnsHostResolver;
+pub use crate::record_browser::TMdnsRecordBrowser;
+pub use crate::resolver::TMdnsResolver;
 pub use crate::service::TMdnsService;
+pub use crate::service_group::TMdnsServiceGroup;
 pub use crate::txt_record::TTxtRecord;
 
 /// Implements a `builder()` function for the specified type
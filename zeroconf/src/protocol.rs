@@ -0,0 +1,16 @@
+//! IP protocols to register and browse services on
+
+/// The IP protocol that services are registered, browsed and resolved on
+///
+/// Restricting a browser to one protocol avoids waiting on addresses that are never answered,
+/// e.g. on dual-stack networks where IPv6 is broken.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Protocol {
+    /// Both IPv4 and IPv6
+    Any,
+    /// Only IPv4
+    V4,
+    /// Only IPv6
+    V6,
+}
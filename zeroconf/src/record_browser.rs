@@ -0,0 +1,101 @@
+//! Trait definition for cross-platform record browser
+
+use crate::{EventLoop, Id, NetworkInterface, Result};
+use std::any::Any;
+use std::sync::Arc;
+
+/// DNS class number of the Internet class, which is the class of every mDNS record
+pub const RR_CLASS_IN: u16 = 1;
+
+/// Interface for querying arbitrary DNS records from the underlying mDNS implementation, similar
+/// to `dns-sd -Q`.
+///
+/// This is a low-level API meant for diagnostics and for records the crate has no dedicated API
+/// for. Record data is passed on in wire format, parsing it is left to the caller.
+pub trait TMdnsRecordBrowser {
+    /// Creates a new `MdnsRecordBrowser`.
+    fn new() -> Self;
+
+    /// Returns the [`Id`] that prefixes the log output of this record browser.
+    ///
+    /// [`Id`]: ../struct.Id.html
+    fn id(&self) -> Id;
+
+    /// Sets the network interface on which to query records on.
+    ///
+    /// Most applications will want to use the default value `NetworkInterface::Unspec` to query
+    /// on all available interfaces.
+    fn set_network_interface(&mut self, interface: NetworkInterface);
+
+    /// Returns the network interface on which to query records on.
+    fn network_interface(&self) -> NetworkInterface;
+
+    /// Sets the [`RecordEventCallback`] that is invoked when a record is added or removed.
+    ///
+    /// [`RecordEventCallback`]: ../type.RecordEventCallback.html
+    fn set_record_event_callback(&mut self, record_event_callback: Box<RecordEventCallback>);
+
+    /// Sets the optional user context to pass through to the callback. This is useful if you need
+    /// to share state between pre and post-callback. The context type must implement `Any`, `Send`
+    /// and `Sync`.
+    fn set_context(&mut self, context: Box<dyn Any + Send + Sync>);
+
+    /// Returns the optional user context to pass through to the callback.
+    fn context(&self) -> Option<&(dyn Any + Send + Sync)>;
+
+    /// Starts querying the records of type `rr_type` (e.g. `16` for TXT) and class `rr_class`
+    /// (usually [`RR_CLASS_IN`]) at the fully qualified `name` (e.g.
+    /// `My Printer._ipp._tcp.local`). Returns an `EventLoop` which can be called to keep the
+    /// browser alive.
+    ///
+    /// The name of a service instance can be built with [`fullname::construct()`].
+    ///
+    /// [`RR_CLASS_IN`]: constant.RR_CLASS_IN.html
+    /// [`fullname::construct()`]: ../fullname/fn.construct.html
+    fn query_record(&mut self, name: &str, rr_type: u16, rr_class: u16) -> Result<EventLoop>;
+}
+
+/// Callback invoked from [`MdnsRecordBrowser`] once a record has been added or removed.
+///
+/// # Arguments
+/// * `event` - The record that was added or removed
+/// * `context` - The optional user context passed through
+///
+/// [`MdnsRecordBrowser`]: type.MdnsRecordBrowser.html
+pub type RecordEventCallback =
+    dyn Fn(Result<RecordEvent>, Option<Arc<dyn Any + Send + Sync>>) + Send;
+
+/// Represents a change to the records queried by a [`MdnsRecordBrowser`].
+///
+/// # Note
+/// Avahi reports a record once for every network interface and protocol it is found on.
+///
+/// [`MdnsRecordBrowser`]: type.MdnsRecordBrowser.html
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RecordEvent {
+    /// A record has been found
+    Add(QueriedRecord),
+    /// A previously found record has been removed
+    Remove(QueriedRecord),
+}
+
+/// Represents a DNS record that has been found by a [`MdnsRecordBrowser`].
+///
+/// [`MdnsRecordBrowser`]: type.MdnsRecordBrowser.html
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Getters, Builder, BuilderDelegate, Clone, PartialEq, Eq)]
+pub struct QueriedRecord {
+    /// Fully qualified name of the record
+    name: String,
+    /// DNS resource record type number
+    rr_type: u16,
+    /// DNS class number
+    rr_class: u16,
+    /// Raw record data in wire format
+    rdata: Vec<u8>,
+    /// Time-to-live in seconds. Avahi does not report TTLs, so this is `None` on Linux.
+    ttl: Option<u32>,
+    /// Interface the record was found on
+    interface: NetworkInterface,
+}
@@ -0,0 +1,142 @@
+//! Limits the number of services a browser resolves at the same time, and how long each may take
+
+use crate::browser::{DEFAULT_MAX_CONCURRENT_RESOLVES, DEFAULT_RESOLVE_TIMEOUT};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Queues the services found by a browser until they can be resolved, so that at most
+/// `max_concurrent` resolutions are active at the same time.
+///
+/// The resolutions themselves are owned by the backend, which passes the number of active ones
+/// to [`next()`](#method.next) and gives up on each one that is still active at its
+/// [`deadline()`](#method.deadline).
+#[derive(Debug)]
+pub(crate) struct ResolveQueue<T> {
+    pending: VecDeque<T>,
+    max_concurrent: usize,
+    timeout: Duration,
+}
+
+impl<T> ResolveQueue<T> {
+    /// Queues a service to be resolved.
+    pub(crate) fn push(&mut self, resolve: T) {
+        self.pending.push_back(resolve);
+    }
+
+    /// Returns the next queued service to resolve, if fewer than the maximum number of
+    /// resolutions are `active`.
+    pub(crate) fn next(&mut self, active: usize) -> Option<T> {
+        if active < self.max_concurrent {
+            self.pending.pop_front()
+        } else {
+            None
+        }
+    }
+
+    /// Returns whether no service is queued.
+    #[cfg(target_os = "linux")]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    /// Removes the queued services `f` returns true for, returning how many were removed.
+    pub(crate) fn remove_where(&mut self, mut f: impl FnMut(&T) -> bool) -> usize {
+        let len = self.pending.len();
+        self.pending.retain(|resolve| !f(resolve));
+        len - self.pending.len()
+    }
+
+    /// Sets the maximum number of active resolutions. A maximum of `0` is treated as `1`, as
+    /// nothing would ever be resolved otherwise.
+    pub(crate) fn set_max_concurrent(&mut self, max_concurrent: usize) {
+        self.max_concurrent = max_concurrent.max(1);
+    }
+
+    pub(crate) fn max_concurrent(&self) -> usize {
+        self.max_concurrent
+    }
+
+    /// Returns the deadline of a resolution that is started `now`.
+    pub(crate) fn deadline(&self, now: Instant) -> Instant {
+        now + self.timeout
+    }
+
+    pub(crate) fn set_timeout(&mut self, timeout: Duration) {
+        self.timeout = timeout;
+    }
+
+    pub(crate) fn timeout(&self) -> Duration {
+        self.timeout
+    }
+}
+
+impl<T> Default for ResolveQueue<T> {
+    fn default() -> Self {
+        Self {
+            pending: VecDeque::new(),
+            max_concurrent: DEFAULT_MAX_CONCURRENT_RESOLVES,
+            timeout: DEFAULT_RESOLVE_TIMEOUT,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn services_are_resolved_in_order_up_to_max() {
+        let mut queue = ResolveQueue::default();
+
+        queue.set_max_concurrent(2);
+
+        for name in ["foo", "bar", "baz"] {
+            queue.push(name);
+        }
+
+        assert_eq!(queue.next(0), Some("foo"));
+        assert_eq!(queue.next(1), Some("bar"));
+        assert_eq!(queue.next(2), None);
+        assert_eq!(queue.next(1), Some("baz"));
+        assert_eq!(queue.next(0), None);
+    }
+
+    #[test]
+    fn max_of_zero_is_treated_as_one() {
+        let mut queue = ResolveQueue::default();
+
+        assert_eq!(queue.max_concurrent(), DEFAULT_MAX_CONCURRENT_RESOLVES);
+
+        queue.set_max_concurrent(0);
+        queue.push("foo");
+
+        assert_eq!(queue.max_concurrent(), 1);
+        assert_eq!(queue.next(0), Some("foo"));
+    }
+
+    #[test]
+    fn removed_services_are_not_resolved() {
+        let mut queue = ResolveQueue::default();
+
+        for name in ["foo", "bar", "foo"] {
+            queue.push(name);
+        }
+
+        assert_eq!(queue.remove_where(|name| *name == "foo"), 2);
+        assert_eq!(queue.next(0), Some("bar"));
+        assert_eq!(queue.next(0), None);
+    }
+
+    #[test]
+    fn deadline_is_timeout_after_start() {
+        let mut queue = ResolveQueue::<()>::default();
+        let now = Instant::now();
+
+        assert_eq!(queue.deadline(now), now + DEFAULT_RESOLVE_TIMEOUT);
+
+        queue.set_timeout(Duration::from_secs(2));
+
+        assert_eq!(queue.timeout(), Duration::from_secs(2));
+        assert_eq!(queue.deadline(now), now + Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,56 @@
+//! Trait definition for cross-platform service resolver
+
+use crate::{EventLoop, Id, NetworkInterface, Result, ServiceDiscoveredCallback, ServiceType};
+use std::any::Any;
+
+/// Interface for interacting with underlying mDNS implementation service resolution
+/// capabilities.
+///
+/// Unlike [`MdnsBrowser`], which resolves every service it discovers, a resolver looks up a single
+/// service whose name is already known. This is useful for refreshing the address of a previously
+/// discovered service right before connecting to it.
+///
+/// [`MdnsBrowser`]: ../type.MdnsBrowser.html
+pub trait TMdnsResolver {
+    /// Creates a new `MdnsResolver`.
+    fn new() -> Self;
+
+    /// Returns the [`Id`] that prefixes the log output of this resolver.
+    ///
+    /// [`Id`]: ../struct.Id.html
+    fn id(&self) -> Id;
+
+    /// Sets the network interface on which to resolve services on.
+    ///
+    /// Most applications will want to use the default value `NetworkInterface::Unspec` to resolve
+    /// on all available interfaces.
+    fn set_network_interface(&mut self, interface: NetworkInterface);
+
+    /// Returns the network interface on which to resolve services on.
+    fn network_interface(&self) -> NetworkInterface;
+
+    /// Sets the [`ServiceDiscoveredCallback`] that is invoked when the service has been resolved.
+    ///
+    /// This is the same callback type used by [`MdnsBrowser`] so that handlers can be shared
+    /// between the two.
+    ///
+    /// [`ServiceDiscoveredCallback`]: ../type.ServiceDiscoveredCallback.html
+    /// [`MdnsBrowser`]: ../type.MdnsBrowser.html
+    fn set_service_resolved_callback(
+        &mut self,
+        service_resolved_callback: Box<ServiceDiscoveredCallback>,
+    );
+
+    /// Sets the optional user context to pass through to the callback. This is useful if you need
+    /// to share state between pre and post-callback. The context type must implement `Any`, `Send`
+    /// and `Sync`.
+    fn set_context(&mut self, context: Box<dyn Any + Send + Sync>);
+
+    /// Returns the optional user context to pass through to the callback.
+    fn context(&self) -> Option<&(dyn Any + Send + Sync)>;
+
+    /// Starts resolving the service with the specified `name`, `service_type` and `domain` (e.g.
+    /// `local`). Returns an `EventLoop` which can be called to keep the resolver alive.
+    fn resolve(&mut self, name: &str, service_type: ServiceType, domain: &str)
+        -> Result<EventLoop>;
+}
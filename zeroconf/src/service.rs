@@ -1,35 +1,196 @@
 //! Trait definition for cross-platform service.
 
-use crate::{EventLoop, NetworkInterface, Result, ServiceType, TxtRecord};
+use crate::context;
+use crate::error::Error;
+use crate::prelude::*;
+use crate::service_name;
+use crate::{
+    BackendInfo, DaemonStateCallback, DeferredService, Deferrer, DetachedHandle, EventLoop, Id,
+    MdnsService, NetworkInterface, Protocol, Result, ServiceType, TxtRecord,
+};
 use std::any::Any;
-use std::sync::Arc;
+use std::fmt;
+use std::net::IpAddr;
+use std::ops::{BitOr, BitOrAssign};
+use std::sync::{mpsc, Arc};
+use std::time::{Duration, Instant};
 
 /// Interface for interacting with underlying mDNS service implementation registration
 /// capabilities.
+///
+/// # Proxy registration
+///
+/// A service can be registered on behalf of another device (e.g. by a bridge advertising
+/// devices that do not speak mDNS themselves) by setting both the device's host name and its
+/// address. The address record for the host is then published together with the service, so
+/// browsers resolve the service to the device rather than to the local machine.
+///
+/// ```no_run
+/// use std::net::{IpAddr, Ipv4Addr};
+/// use zeroconf::prelude::*;
+/// use zeroconf::{MdnsService, ServiceType};
+///
+/// let mut service = MdnsService::new(ServiceType::new("http", "tcp").unwrap(), 80);
+///
+/// service.set_name("Living Room Lamp");
+/// service.set_host("lamp.local");
+/// service.set_address(IpAddr::V4(Ipv4Addr::new(192, 168, 1, 42)));
+///
+/// let event_loop = service.register().unwrap();
+/// ```
+///
+/// # Dropping from a callback
+///
+/// A service may be dropped from within its own callbacks. Its resources are then freed by the
+/// [`EventLoop`] once the callbacks being dispatched have returned, which unregisters the service.
+/// Closures still queued with a [`Deferrer`] are discarded.
+///
+/// [`EventLoop`]: ../type.EventLoop.html
+/// [`Deferrer`]: ../struct.Deferrer.html
 pub trait TMdnsService {
     /// Creates a new `MdnsService` with the specified `ServiceType` (e.g. `_http._tcp`) and `port`.
+    ///
+    /// A `port` of `0` registers a placeholder service, see [`set_port()`]. [`ServiceBuilder`]
+    /// rejects it unless a placeholder is requested explicitly.
+    ///
+    /// [`set_port()`]: #tymethod.set_port
+    /// [`ServiceBuilder`]: struct.ServiceBuilder.html
     fn new(service_type: ServiceType, port: u16) -> Self;
 
+    /// Returns the [`Id`] that prefixes the log output of this service.
+    ///
+    /// [`Id`]: ../struct.Id.html
+    fn id(&self) -> Id;
+
     /// Sets the name to register this service under.
+    ///
+    /// A service without a name is registered under the host name of the machine, and is renamed
+    /// when the host name changes. The new name is passed to the [`ServiceRegisteredCallback`].
+    ///
+    /// Names longer than [`MAX_NAME_LEN`] bytes are rejected by `register()` with
+    /// `Error::NameTooLong` unless name fitting is enabled. Use [`try_set_name()`] to validate the
+    /// name right away.
+    ///
+    /// [`MAX_NAME_LEN`]: service_name/constant.MAX_NAME_LEN.html
+    /// [`try_set_name()`]: #method.try_set_name
+    /// [`ServiceRegisteredCallback`]: ../type.ServiceRegisteredCallback.html
     fn set_name(&mut self, name: &str);
 
+    /// Sets the name to register this service under, like [`set_name()`], if it is valid.
+    ///
+    /// Returns `Error::InvalidName` if `name` is empty or contains a NUL character, or
+    /// `Error::NameTooLong` if it is longer than [`MAX_NAME_LEN`] bytes and name fitting is
+    /// disabled. Any other UTF-8 is allowed. The name is left unchanged on error.
+    ///
+    /// [`set_name()`]: #tymethod.set_name
+    /// [`MAX_NAME_LEN`]: service_name/constant.MAX_NAME_LEN.html
+    fn try_set_name(&mut self, name: &str) -> Result<()> {
+        service_name::validate(name, self.name_fitting())?;
+        self.set_name(name);
+        Ok(())
+    }
+
     /// Returns the name to register this service under. In some cases, the name of the service
     /// may be auto-assigned, in which case in may not be available until after registration.
     fn name(&self) -> Option<&str>;
 
+    /// Sets whether a name that is too long should be shortened with [`service_name::fit()`]
+    /// when the service is registered, instead of being rejected. Defaults to `false`.
+    ///
+    /// [`ServiceRegistration::name_was_adjusted()`] reports whether the name was shortened.
+    ///
+    /// [`service_name::fit()`]: ../service_name/fn.fit.html
+    /// [`ServiceRegistration::name_was_adjusted()`]: ../struct.ServiceRegistration.html
+    fn set_name_fitting(&mut self, name_fitting: bool);
+
+    /// Returns whether a name that is too long is shortened when the service is registered.
+    fn name_fitting(&self) -> bool;
+
+    /// Sets whether the service should be renamed (e.g. to `foo (2)`) if its name is already in
+    /// use on the network. Defaults to `true`.
+    ///
+    /// If `false`, a conflict is reported to the [`ServiceRegisteredCallback`] as
+    /// `Error::NameCollision` instead. If `true`, the [`ServiceRegistration`] reports the name
+    /// that was eventually registered.
+    ///
+    /// [`ServiceRegisteredCallback`]: ../type.ServiceRegisteredCallback.html
+    /// [`ServiceRegistration`]: ../struct.ServiceRegistration.html
+    fn set_allow_name_conflicts(&mut self, allow_name_conflicts: bool);
+
+    /// Returns whether the service is renamed if its name is already in use on the network.
+    fn allow_name_conflicts(&self) -> bool;
+
+    /// Sets whether the service should be registered again when the connection to the mDNS
+    /// daemon is lost, e.g. because the daemon has been restarted. Defaults to `false`.
+    ///
+    /// If `true`, a lost connection is reported to the [`RegistrationStateCallback`] as
+    /// `RegistrationState::ConnectionLost` instead of an error, the service is re-registered once
+    /// the daemon is available again, and the [`ServiceRegisteredCallback`] is invoked with the
+    /// new registration. Must be called before `register()`.
+    ///
+    /// # Note
+    /// Only supported on Linux; Bonjour returns `Error::Unsupported` when enabling it.
+    ///
+    /// [`RegistrationStateCallback`]: ../type.RegistrationStateCallback.html
+    /// [`ServiceRegisteredCallback`]: ../type.ServiceRegisteredCallback.html
+    fn set_auto_reconnect(&mut self, auto_reconnect: bool) -> Result<()>;
+
+    /// Returns whether the service is registered again when the connection to the mDNS daemon is
+    /// lost.
+    fn auto_reconnect(&self) -> bool;
+
+    /// Sets the port to register this service on, replacing the port passed to `new()`. Takes
+    /// effect the next time the service is registered.
+    ///
+    /// # Placeholder services
+    /// Port `0` registers a placeholder that reserves the service name without offering the
+    /// service: it is not discovered by browsers, but registering the same name elsewhere
+    /// conflicts with it.
+    ///
+    /// # Note
+    /// Placeholders are only supported by Bonjour and the `test-mock` backend. Avahi returns
+    /// `Error::Unsupported` from `register()` if the port is `0`.
+    fn set_port(&mut self, port: u16);
+
+    /// Returns the port to register this service on.
+    fn port(&self) -> u16;
+
     /// Sets the network interface to bind this service to.
     ///
     /// Most applications will want to use the default value `NetworkInterface::Unspec` to bind to
-    /// all available interfaces.
+    /// all available interfaces. To bind to a single interface, look it up by name with
+    /// [`NetworkInterface::by_name()`] rather than hardcoding its index.
+    ///
+    /// [`NetworkInterface::by_name()`]: ../enum.NetworkInterface.html#method.by_name
     fn set_network_interface(&mut self, interface: NetworkInterface);
 
     /// Returns the network interface to bind this service to.
     fn network_interface(&self) -> NetworkInterface;
 
+    /// Sets the [`Protocol`] to register this service on. Defaults to `Protocol::Any`.
+    ///
+    /// # Note
+    /// Bonjour cannot restrict a registration to a protocol, so it returns `Error::Unsupported`
+    /// for anything but `Protocol::Any`.
+    ///
+    /// [`Protocol`]: ../enum.Protocol.html
+    fn set_protocol(&mut self, protocol: Protocol) -> Result<()>;
+
+    /// Returns the [`Protocol`] to register this service on.
+    ///
+    /// [`Protocol`]: ../enum.Protocol.html
+    fn protocol(&self) -> Protocol;
+
     /// Sets the domain on which to advertise the service.
     ///
-    /// Most applications will want to use the default value of `ptr::null()` to register to the
-    /// default domain.
+    /// Most applications will want to leave this unset to register in the default domain, which is
+    /// usually `local`. Registering in a wide-area domain such as `dns-sd.example.com` requires a
+    /// DNS server that accepts dynamic updates for it and, on Avahi, a daemon configured for
+    /// wide-area publishing.
+    ///
+    /// The domain the service was registered in is reported by [`ServiceRegistration::domain()`].
+    ///
+    /// [`ServiceRegistration::domain()`]: ../struct.ServiceRegistration.html#method.domain
     fn set_domain(&mut self, domain: &str);
 
     /// Returns the domain on which to advertise the service.
@@ -38,34 +199,270 @@ pub trait TMdnsService {
     /// Sets the SRV target host name.
     ///
     /// Most applications will want to use the default value of `ptr::null()` to use the machine's
-    /// default host name.
+    /// default host name. When registering on behalf of another device, the host's address must
+    /// also be set with `set_address()` for the service to be resolvable.
     fn set_host(&mut self, _host: &str);
 
     /// Returns the SRV target host name.
     fn host(&self) -> Option<&str>;
 
+    /// Sets an address to publish for the host set with `set_host()`. This allows services to be
+    /// advertised on behalf of devices that cannot run mDNS themselves, e.g. by a gateway.
+    ///
+    /// Registering fails if no host has been set.
+    fn set_address(&mut self, address: IpAddr);
+
+    /// Returns the address to publish for the host, if any.
+    fn address(&self) -> Option<IpAddr>;
+
     /// Sets the optional `TxtRecord` to register this service with.
     fn set_txt_record(&mut self, txt_record: TxtRecord);
 
     /// Returns the optional `TxtRecord` to register this service with.
     fn txt_record(&self) -> Option<&TxtRecord>;
 
+    /// Adds an additional DNS record to publish when this service is registered. Records are
+    /// removed when the service is dropped. Use [`TDeferredService::add_record()`] to add a record
+    /// to a service that has already been registered.
+    ///
+    /// Returns `Error::Unsupported` if the record's [`RecordSharing`] cannot be expressed by the
+    /// underlying mDNS implementation.
+    ///
+    /// [`RecordSharing`]: ../enum.RecordSharing.html
+    /// [`TDeferredService::add_record()`]: ../defer/trait.TDeferredService.html#tymethod.add_record
+    fn add_record(&mut self, record: ServiceRecord) -> Result<()>;
+
+    /// Returns the additional records to publish with this service.
+    fn records(&self) -> &[ServiceRecord];
+
+    /// Adds a sub-type to register this service under, in addition to the sub-types of the
+    /// `ServiceType` passed to `new()`. Takes effect the next time the service is registered; use
+    /// [`TDeferredService::add_sub_type()`] to add a sub-type to a service that has already been
+    /// registered.
+    ///
+    /// Returns `Err` if `sub_type` is not a valid sub-type. Adding a sub-type the service already
+    /// has does nothing.
+    ///
+    /// [`TDeferredService::add_sub_type()`]: ../defer/trait.TDeferredService.html#tymethod.add_sub_type
+    fn add_sub_type(&mut self, sub_type: &str) -> Result<()>;
+
+    /// Removes a sub-type to register this service under, including the sub-types of the
+    /// `ServiceType` passed to `new()`. Takes effect the next time the service is registered; use
+    /// [`TDeferredService::remove_sub_type()`] to remove a sub-type from a service that has
+    /// already been registered.
+    ///
+    /// Returns `Err` if `sub_type` is not a valid sub-type. Removing a sub-type the service does
+    /// not have does nothing.
+    ///
+    /// [`TDeferredService::remove_sub_type()`]: ../defer/trait.TDeferredService.html#tymethod.remove_sub_type
+    fn remove_sub_type(&mut self, sub_type: &str) -> Result<()>;
+
+    /// Sets the [`RegistrationFlags`] to register this service with. Defaults to
+    /// `RegistrationFlags::NONE`.
+    ///
+    /// Returns `Error::Unsupported` if any of the flags cannot be expressed by the underlying mDNS
+    /// implementation, in which case the previously set flags are kept.
+    ///
+    /// [`RegistrationFlags`]: ../struct.RegistrationFlags.html
+    fn set_registration_flags(&mut self, flags: RegistrationFlags) -> Result<()>;
+
+    /// Returns the [`RegistrationFlags`] to register this service with.
+    ///
+    /// [`RegistrationFlags`]: ../struct.RegistrationFlags.html
+    fn registration_flags(&self) -> RegistrationFlags;
+
     /// Sets the [`ServiceRegisteredCallback`] that is invoked when the service has been
     /// registered.
     ///
     /// [`ServiceRegisteredCallback`]: ../type.ServiceRegisteredCallback.html
     fn set_registered_callback(&mut self, registered_callback: Box<ServiceRegisteredCallback>);
 
+    /// Sets a closure that is invoked when the service has been registered.
+    ///
+    /// This is a shorthand for [`set_registered_callback()`] for closures that keep their state
+    /// to themselves rather than in the user context.
+    ///
+    /// [`set_registered_callback()`]: #tymethod.set_registered_callback
+    fn set_on_registered(
+        &mut self,
+        mut on_registered: impl FnMut(Result<ServiceRegistration>) + Send + 'static,
+    ) {
+        self.set_registered_callback(Box::new(move |result, _| on_registered(result)));
+    }
+
+    /// Sets a callback that is invoked when the service has been registered, with the context
+    /// set with [`set_context_typed()`] already downcast to a `T`.
+    ///
+    /// # Panics
+    /// The callback panics if no context of type `T` has been set, which is logged as an error.
+    ///
+    /// [`set_context_typed()`]: #method.set_context_typed
+    fn set_registered_callback_with<T: Any + Send + Sync>(
+        &mut self,
+        mut registered_callback: impl FnMut(Result<ServiceRegistration>, &T) + Send + 'static,
+    ) {
+        self.set_registered_callback(Box::new(move |result, ctx| {
+            registered_callback(result, context::downcast(ctx.as_deref()))
+        }));
+    }
+
+    /// Sets the optional [`RegistrationStateCallback`] that is invoked as the registration
+    /// progresses, e.g. while it is pending because no network interface is up yet.
+    ///
+    /// [`RegistrationStateCallback`]: ../type.RegistrationStateCallback.html
+    fn set_registration_state_callback(
+        &mut self,
+        registration_state_callback: Box<RegistrationStateCallback>,
+    );
+
+    /// Sets the optional [`NameChangedCallback`] that is invoked whenever the service is
+    /// registered under a different name than it was last registered under since `register()`.
+    ///
+    /// This includes the renames made by the mDNS daemon after a name collision, e.g. from
+    /// `Printer` to `Printer (2)`, which Bonjour may also make long after the service has first
+    /// been registered, as well as names set with [`TDeferredService::set_name()`]. The
+    /// [`ServiceRegisteredCallback`] is invoked with the new registration beforehand.
+    ///
+    /// [`NameChangedCallback`]: ../type.NameChangedCallback.html
+    /// [`TDeferredService::set_name()`]: ../defer/trait.TDeferredService.html#tymethod.set_name
+    /// [`ServiceRegisteredCallback`]: ../type.ServiceRegisteredCallback.html
+    fn set_name_changed_callback(&mut self, name_changed_callback: Box<NameChangedCallback>);
+
     /// Sets the optional user context to pass through to the callback. This is useful if you need
-    /// to share state between pre and post-callback. The context type must implement `Any`.
-    fn set_context(&mut self, context: Box<dyn Any>);
+    /// to share state between pre and post-callback. The context type must implement `Any`, `Send`
+    /// and `Sync`.
+    fn set_context(&mut self, context: Box<dyn Any + Send + Sync>);
+
+    /// Sets the user context to pass through to callbacks registered with
+    /// [`set_registered_callback_with()`], which receive it as a `&T` rather than having to
+    /// downcast it.
+    ///
+    /// [`set_registered_callback_with()`]: #method.set_registered_callback_with
+    fn set_context_typed<T: Any + Send + Sync>(&mut self, context: Arc<T>) {
+        self.set_context(Box::new(context));
+    }
 
     /// Returns the optional user context.
-    fn context(&self) -> Option<&dyn Any>;
+    fn context(&self) -> Option<&(dyn Any + Send + Sync)>;
+
+    /// Returns a [`Deferrer`] for changing the service once it has been registered, e.g. from
+    /// within the [`ServiceRegisteredCallback`], where the service itself must not be used.
+    ///
+    /// [`Deferrer`]: ../struct.Deferrer.html
+    /// [`ServiceRegisteredCallback`]: ../type.ServiceRegisteredCallback.html
+    fn deferrer(&self) -> Deferrer;
+
+    /// Queues `f` to be executed by the event loop of the service. Shorthand for
+    /// `self.deferrer().defer(f)`.
+    fn defer<F>(&self, f: F)
+    where
+        F: FnOnce(&mut DeferredService<'_>) -> Result<()> + Send + 'static,
+    {
+        self.deferrer().defer(f)
+    }
+
+    /// Sets whether `register()` should wait for the mDNS daemon if it is not running, instead of
+    /// failing. Defaults to `false`.
+    ///
+    /// If `true`, the registration starts once the daemon is available, and the [`DaemonStateCallback`]
+    /// is informed while waiting. Must be called before `register()`.
+    ///
+    /// # Note
+    /// Only supported on Linux; Bonjour returns `Error::Unsupported` when enabling it.
+    ///
+    /// [`DaemonStateCallback`]: ../daemon/type.DaemonStateCallback.html
+    fn set_wait_for_daemon(&mut self, wait_for_daemon: bool) -> Result<()>;
+
+    /// Returns whether `register()` waits for the mDNS daemon if it is not running.
+    fn wait_for_daemon(&self) -> bool;
+
+    /// Sets the optional [`DaemonStateCallback`] that is invoked when the state of the connection
+    /// to the mDNS daemon changes.
+    ///
+    /// # Note
+    /// Bonjour connects to the daemon synchronously and reports failures from `register()`, so the
+    /// callback is never invoked on macOS and Windows.
+    ///
+    /// [`DaemonStateCallback`]: ../daemon/type.DaemonStateCallback.html
+    fn set_daemon_state_callback(&mut self, daemon_state_callback: Box<DaemonStateCallback>);
 
     /// Registers and start's the service. Returns an `EventLoop` which can be called to keep
     /// the service alive.
+    ///
+    /// Calling `register()` again restarts the service: the previous registration is withdrawn
+    /// and the service is registered anew with its current settings. The `EventLoop` returned by
+    /// the previous call should be dropped.
     fn register(&mut self) -> Result<EventLoop>;
+
+    /// Registers the service and polls its `EventLoop` on a background thread named
+    /// `zeroconf-poll`, which takes ownership of the service. The service stays registered until
+    /// the returned [`DetachedHandle`] is stopped or dropped.
+    ///
+    /// # Note
+    /// Callbacks are invoked on the background thread. Obtain a [`deferrer()`] beforehand to
+    /// update the service once it is detached.
+    ///
+    /// [`DetachedHandle`]: ../detached/struct.DetachedHandle.html
+    /// [`deferrer()`]: #tymethod.deferrer
+    fn register_detached(mut self) -> Result<DetachedHandle>
+    where
+        Self: Sized + Send + 'static,
+    {
+        let event_loop = self.register()?;
+        DetachedHandle::spawn(self, event_loop)
+    }
+
+    /// Registers the service and polls the `EventLoop` until the service has been registered or
+    /// `timeout` has elapsed. Returns the [`ServiceRegistration`] along with the `EventLoop`,
+    /// which must be polled to keep the service alive.
+    ///
+    /// Failures reported to the registered callback, such as `Error::NameCollision`, are
+    /// returned as `Err`, as is `Error::TimedOut` if the service was not registered in time.
+    ///
+    /// # Note
+    /// This replaces the [`ServiceRegisteredCallback`]. Use a [`RegistrationStateCallback`] to
+    /// follow the registration afterwards.
+    ///
+    /// [`ServiceRegistration`]: ../struct.ServiceRegistration.html
+    /// [`ServiceRegisteredCallback`]: ../type.ServiceRegisteredCallback.html
+    /// [`RegistrationStateCallback`]: ../type.RegistrationStateCallback.html
+    fn register_blocking(&mut self, timeout: Duration) -> Result<(ServiceRegistration, EventLoop)> {
+        let (tx, rx) = mpsc::channel();
+
+        self.set_on_registered(move |result| {
+            // the receiver is gone once the first result has been returned
+            let _ = tx.send(result);
+        });
+
+        let event_loop = self.register()?;
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            if let Ok(result) = rx.try_recv() {
+                return result.map(|registration| (registration, event_loop));
+            }
+
+            let now = Instant::now();
+
+            if now >= deadline {
+                return Err(Error::TimedOut { timeout });
+            }
+
+            event_loop.poll(deadline - now)?;
+        }
+    }
+
+    /// Returns the [`ServiceState`] of the registration, e.g. for health checks.
+    ///
+    /// [`ServiceState`]: ../enum.ServiceState.html
+    fn state(&self) -> ServiceState;
+
+    /// Returns the [`BackendInfo`] of the mDNS stack this service is registered with, e.g. for
+    /// bug reports. Returns `Err` if the service has not been registered, as there is no
+    /// connection to the daemon to ask yet.
+    ///
+    /// [`BackendInfo`]: ../struct.BackendInfo.html
+    fn backend_info(&self) -> Result<BackendInfo>;
 }
 
 /// Callback invoked from [`MdnsService`] once it has successfully registered.
@@ -75,14 +472,434 @@ pub trait TMdnsService {
 /// * `context` - The optional user context passed through
 ///
 /// [`MdnsService`]: type.MdnsService.html
-pub type ServiceRegisteredCallback = dyn Fn(Result<ServiceRegistration>, Option<Arc<dyn Any>>);
+pub type ServiceRegisteredCallback =
+    dyn FnMut(Result<ServiceRegistration>, Option<Arc<dyn Any + Send + Sync>>) + Send;
+
+/// Callback invoked from [`MdnsService`] as its registration progresses.
+///
+/// # Arguments
+/// * `state` - The state the registration has entered
+/// * `context` - The optional user context passed through
+///
+/// [`MdnsService`]: type.MdnsService.html
+pub type RegistrationStateCallback =
+    dyn Fn(RegistrationState, Option<Arc<dyn Any + Send + Sync>>) + Send;
+
+/// Callback invoked from [`MdnsService`] when the name it is registered under changes.
+///
+/// # Arguments
+/// * `change` - The previous and the new name of the service
+/// * `context` - The optional user context passed through
+///
+/// [`MdnsService`]: type.MdnsService.html
+pub type NameChangedCallback = dyn Fn(NameChange, Option<Arc<dyn Any + Send + Sync>>) + Send;
+
+/// Change of the name of a registered service, reported to the [`NameChangedCallback`].
+///
+/// [`NameChangedCallback`]: type.NameChangedCallback.html
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Getters, Clone, PartialEq, Eq)]
+pub struct NameChange {
+    /// The name the service was previously registered under
+    previous: String,
+    /// The name the service is now registered under
+    name: String,
+}
+
+impl NameChange {
+    /// Remembers the name of `registration` in `registered_name`, returning the `NameChange` if
+    /// the service was previously registered under a different name.
+    pub(crate) fn track(
+        registered_name: &mut Option<String>,
+        registration: &ServiceRegistration,
+    ) -> Option<Self> {
+        let previous = registered_name.replace(registration.name.clone())?;
+
+        if previous == registration.name {
+            return None;
+        }
+
+        Some(Self {
+            previous,
+            name: registration.name.clone(),
+        })
+    }
+}
+
+/// Progress of a service registration, reported to the [`RegistrationStateCallback`].
+///
+/// A service registered on [`NetworkInterface::Unspec`] before any network interface is up
+/// (e.g. early during boot) does not fail to register: it stays `Pending` until the mDNS daemon
+/// is ready and is then published without any action from the application. Avahi reports
+/// `Pending` while the daemon is registering or re-registering its host name, which it does when
+/// interfaces appear. Bonjour publishes on interfaces as they appear without ever reporting
+/// `Pending`.
+///
+/// [`RegistrationStateCallback`]: type.RegistrationStateCallback.html
+/// [`NetworkInterface::Unspec`]: enum.NetworkInterface.html#variant.Unspec
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegistrationState {
+    /// The mDNS daemon is not ready to publish the service yet; it is published once it is
+    Pending,
+    /// The service has been passed to the mDNS daemon, which is probing for and announcing it.
+    /// The [`ServiceRegisteredCallback`] is invoked once it has been established.
+    ///
+    /// [`ServiceRegisteredCallback`]: type.ServiceRegisteredCallback.html
+    Registering,
+    /// The connection to the mDNS daemon has been lost and the service is no longer published.
+    /// Only reported if auto-reconnect is enabled; the service is registered again once the
+    /// daemon is available.
+    ConnectionLost,
+    /// The connection to the mDNS daemon has been reestablished after it was lost and the service
+    /// is being registered again
+    Reestablished,
+}
+
+/// State of the registration of a [`MdnsService`], returned by [`TMdnsService::state()`].
+///
+/// The state follows the [`RegistrationState`] reported while registering and the result passed
+/// to the [`ServiceRegisteredCallback`]. Errors reported for a closure queued with a [`Deferrer`]
+/// do not affect it.
+///
+/// [`MdnsService`]: type.MdnsService.html
+/// [`TMdnsService::state()`]: service/trait.TMdnsService.html#tymethod.state
+/// [`RegistrationState`]: enum.RegistrationState.html
+/// [`ServiceRegisteredCallback`]: type.ServiceRegisteredCallback.html
+/// [`Deferrer`]: struct.Deferrer.html
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ServiceState {
+    /// The service has not been registered yet
+    Unregistered,
+    /// The service is being registered, or is waiting for the mDNS daemon to register it again
+    /// after the daemon or the connection to it has been lost
+    Registering,
+    /// The service has been registered
+    Registered,
+    /// The service could not be registered because its name is already taken and name conflicts
+    /// are not allowed
+    Collided,
+    /// The registration has failed
+    Failed,
+}
+
+impl ServiceState {
+    /// Returns the state after `result` has been passed to the `ServiceRegisteredCallback`.
+    pub(crate) fn after(result: &Result<ServiceRegistration>) -> Self {
+        match result {
+            Ok(_) => Self::Registered,
+            Err(Error::NameCollision { .. }) => Self::Collided,
+            Err(_) => Self::Failed,
+        }
+    }
+}
 
 /// Represents a registration event for a [`MdnsService`].
 ///
+/// A registration can be constructed with [`ServiceRegistrationBuilder`], e.g. to test the
+/// [`ServiceRegisteredCallback`]. The `name`, `service_type` and `domain` are required, every other
+/// property has a default, as will any property added in the future.
+///
 /// [`MdnsService`]: type.MdnsService.html
+/// [`ServiceRegistrationBuilder`]: struct.ServiceRegistrationBuilder.html
+/// [`ServiceRegisteredCallback`]: type.ServiceRegisteredCallback.html
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[derive(Builder, BuilderDelegate, Debug, Getters, Clone, Default, PartialEq, Eq)]
 pub struct ServiceRegistration {
+    #[builder(setter(into))]
     name: String,
     service_type: ServiceType,
+    /// The domain the service was registered in, without a trailing dot
+    #[builder(setter(into))]
     domain: String,
+    /// Whether the name was shortened by name fitting before it was registered
+    #[builder(default)]
+    #[cfg_attr(feature = "serde", serde(default))]
+    name_was_adjusted: bool,
+    /// The port the service was registered on
+    #[builder(default)]
+    #[cfg_attr(feature = "serde", serde(default))]
+    port: u16,
+    /// The SRV target host name, if one was set. Otherwise the machine's host name is used.
+    #[builder(default)]
+    #[cfg_attr(feature = "serde", serde(default))]
+    host: Option<String>,
+    /// The TXT record the service was registered with
+    #[builder(default)]
+    #[cfg_attr(feature = "serde", serde(default))]
+    txt: Option<TxtRecord>,
+}
+
+/// Properties of a [`MdnsService`] created with `MdnsService::builder()`.
+///
+/// [`MdnsService`]: ../type.MdnsService.html
+#[derive(Builder)]
+#[builder(
+    name = "ServiceBuilder",
+    public,
+    pattern = "owned",
+    build_fn(private, name = "build_config")
+)]
+struct ServiceConfig {
+    service_type: ServiceType,
+    port: u16,
+    /// Whether a `port` of `0` registers a placeholder service rather than being rejected.
+    /// Defaults to `false`.
+    #[builder(default)]
+    placeholder: bool,
+    #[builder(setter(into, strip_option), default)]
+    name: Option<String>,
+    #[builder(default = "NetworkInterface::Unspec")]
+    network_interface: NetworkInterface,
+    #[builder(setter(into, strip_option), default)]
+    domain: Option<String>,
+    #[builder(setter(into, strip_option), default)]
+    host: Option<String>,
+    #[builder(setter(strip_option), default)]
+    address: Option<IpAddr>,
+    #[builder(setter(strip_option), default)]
+    txt: Option<TxtRecord>,
+    #[builder(setter(strip_option), default)]
+    registration_flags: Option<RegistrationFlags>,
+    #[builder(setter(strip_option), default)]
+    registered_callback: Option<Box<ServiceRegisteredCallback>>,
+    #[builder(setter(strip_option), default)]
+    registration_state_callback: Option<Box<RegistrationStateCallback>>,
+    #[builder(setter(strip_option), default)]
+    name_changed_callback: Option<Box<NameChangedCallback>>,
+    #[builder(setter(strip_option), default)]
+    context: Option<Box<dyn Any + Send + Sync>>,
+}
+
+impl ServiceBuilder {
+    /// Builds the `MdnsService`, returning an error if a required property is missing or a
+    /// property is rejected by the implementation.
+    ///
+    /// Every property is passed on to the corresponding setter of [`TMdnsService`], only
+    /// `service_type` and `port` are required. The name is set with
+    /// [`TMdnsService::try_set_name()`], and a `port` of `0` is rejected unless `placeholder` is
+    /// set.
+    ///
+    /// ```no_run
+    /// use zeroconf::prelude::*;
+    /// use zeroconf::{MdnsService, ServiceType};
+    ///
+    /// let mut service = MdnsService::builder()
+    ///     .service_type(ServiceType::new("http", "tcp").unwrap())
+    ///     .port(8080)
+    ///     .name("foo")
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// let event_loop = service.register().unwrap();
+    /// ```
+    ///
+    /// [`TMdnsService`]: trait.TMdnsService.html
+    /// [`TMdnsService::try_set_name()`]: trait.TMdnsService.html#method.try_set_name
+    pub fn build(self) -> Result<MdnsService> {
+        let config = self.build_config()?;
+
+        if config.port == 0 && !config.placeholder {
+            return Err(
+                "port 0 registers a placeholder service, which must be requested with `placeholder(true)`"
+                    .into(),
+            );
+        }
+
+        let mut service = MdnsService::new(config.service_type, config.port);
+
+        service.set_network_interface(config.network_interface);
+
+        if let Some(flags) = config.registration_flags {
+            service.set_registration_flags(flags)?;
+        }
+
+        if let Some(name) = config.name {
+            service.try_set_name(&name)?;
+        }
+
+        if let Some(domain) = config.domain {
+            service.set_domain(&domain);
+        }
+
+        if let Some(host) = config.host {
+            service.set_host(&host);
+        }
+
+        if let Some(address) = config.address {
+            service.set_address(address);
+        }
+
+        if let Some(txt) = config.txt {
+            service.set_txt_record(txt);
+        }
+
+        if let Some(registered_callback) = config.registered_callback {
+            service.set_registered_callback(registered_callback);
+        }
+
+        if let Some(registration_state_callback) = config.registration_state_callback {
+            service.set_registration_state_callback(registration_state_callback);
+        }
+
+        if let Some(name_changed_callback) = config.name_changed_callback {
+            service.set_name_changed_callback(name_changed_callback);
+        }
+
+        if let Some(context) = config.context {
+            service.set_context(context);
+        }
+
+        Ok(service)
+    }
+}
+
+impl BuilderDelegate<ServiceBuilder> for MdnsService {}
+
+/// DNS resource record type number of SRV records
+const RR_TYPE_SRV: u16 = 33;
+
+/// Error message for services with an address but no host to publish it for
+pub(crate) const ADDRESS_WITHOUT_HOST: &str =
+    "an address can only be published for a host set with `set_host()`";
+
+/// Default TTL of additional records, in seconds
+pub const DEFAULT_RECORD_TTL: u32 = 4500;
+
+/// Determines whether a [`ServiceRecord`] may be published by more than one host.
+///
+/// [`ServiceRecord`]: struct.ServiceRecord.html
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordSharing {
+    /// The record is owned by this host. Conflicting records on the network are resolved by
+    /// probing, which may cause the service to be renamed.
+    Unique,
+    /// The record may be published by any number of hosts at the same time (e.g. a PTR record
+    /// that many hosts use to announce their presence). Shared records are not probed.
+    Shared,
+}
+
+/// Flags that modify how a [`MdnsService`] is registered. Flags can be combined with `|`.
+///
+/// | Flag            | Avahi                         | Bonjour                       |
+/// |-----------------|-------------------------------|-------------------------------|
+/// | `NO_PROBE`      | `AVAHI_PUBLISH_NO_PROBE`      | `kDNSServiceFlagsKnownUnique` |
+/// | `NO_ANNOUNCE`   | `AVAHI_PUBLISH_NO_ANNOUNCE`   | unsupported                   |
+/// | `UPDATE`        | `AVAHI_PUBLISH_UPDATE`        | unsupported                   |
+/// | `USE_MULTICAST` | `AVAHI_PUBLISH_USE_MULTICAST` | unsupported                   |
+///
+/// [`MdnsService`]: type.MdnsService.html
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct RegistrationFlags(u32);
+
+impl RegistrationFlags {
+    /// No flags
+    pub const NONE: Self = Self(0);
+    /// Skip probing for conflicting names before announcing the service. Only use this for names
+    /// that are known to be unique on the network.
+    pub const NO_PROBE: Self = Self(1);
+    /// Do not announce the service after it has been registered
+    pub const NO_ANNOUNCE: Self = Self(1 << 1);
+    /// Update the records of a service that has already been registered rather than adding new
+    /// ones
+    pub const UPDATE: Self = Self(1 << 2);
+    /// Only publish the service with multicast DNS, even if wide-area DNS is available
+    pub const USE_MULTICAST: Self = Self(1 << 3);
+
+    const NAMED: [(Self, &'static str); 4] = [
+        (Self::NO_PROBE, "NO_PROBE"),
+        (Self::NO_ANNOUNCE, "NO_ANNOUNCE"),
+        (Self::UPDATE, "UPDATE"),
+        (Self::USE_MULTICAST, "USE_MULTICAST"),
+    ];
+
+    /// Returns true if no flags are set.
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns true if all flags in `other` are set.
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns the flags that are set in `self` but not in `other`.
+    pub fn difference(self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+}
+
+impl BitOr for RegistrationFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for RegistrationFlags {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+impl fmt::Debug for RegistrationFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return write!(f, "NONE");
+        }
+
+        let names = Self::NAMED
+            .iter()
+            .filter(|(flag, _)| self.contains(*flag))
+            .map(|(_, name)| *name)
+            .collect::<Vec<_>>();
+
+        write!(f, "{}", names.join(" | "))
+    }
+}
+
+/// An additional DNS resource record that is published alongside a [`MdnsService`].
+///
+/// The primary SRV record of a service must remain unique; adding a shared SRV record at the
+/// service's own name is rejected with `Error::Unsupported`.
+///
+/// [`MdnsService`]: type.MdnsService.html
+#[derive(Builder, BuilderDelegate, Debug, Getters, Clone, PartialEq, Eq)]
+pub struct ServiceRecord {
+    /// Fully qualified name to publish the record at (e.g. `presence.local`). If `None`, the
+    /// record is published at the service instance's own name.
+    #[builder(default)]
+    name: Option<String>,
+    /// DNS resource record type number (e.g. `16` for TXT)
+    rr_type: u16,
+    /// Raw record data in wire format
+    rdata: Vec<u8>,
+    /// Time-to-live in seconds
+    #[builder(default = "DEFAULT_RECORD_TTL")]
+    ttl: u32,
+    #[builder(default = "RecordSharing::Unique")]
+    sharing: RecordSharing,
+}
+
+impl ServiceRecord {
+    /// Returns an error if this record may not be published with its [`RecordSharing`] on any
+    /// platform.
+    ///
+    /// [`RecordSharing`]: enum.RecordSharing.html
+    pub(crate) fn validate(&self) -> Result<()> {
+        if self.name.is_none()
+            && self.rr_type == RR_TYPE_SRV
+            && self.sharing == RecordSharing::Shared
+        {
+            return Err(Error::Unsupported(
+                "the SRV record of a service must be unique".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
 }
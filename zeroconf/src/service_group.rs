@@ -0,0 +1,246 @@
+//! Trait definition for cross-platform groups of service instances
+
+use crate::service_name;
+use crate::{EventLoop, Id, NetworkInterface, Result, ServiceRegistration, ServiceType, TxtRecord};
+use std::any::Any;
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// Interface for registering many instances of the same service type at once, e.g. one for every
+/// sensor attached to a machine.
+///
+/// Unlike registering an [`MdnsService`] for every instance, a group connects to the mDNS daemon
+/// only once and is polled with a single [`EventLoop`]. Instances can be added and removed while
+/// the group is registered; the changes are applied the next time the event loop is polled.
+/// Removing an instance only withdraws that instance, the others stay published.
+///
+/// [`MdnsService`]: ../type.MdnsService.html
+/// [`EventLoop`]: ../type.EventLoop.html
+pub trait TMdnsServiceGroup {
+    /// Creates a new, empty group of instances of the specified `service_type`.
+    fn new(service_type: ServiceType) -> Self;
+
+    /// Returns the [`Id`] that prefixes the log output of this group.
+    ///
+    /// [`Id`]: ../struct.Id.html
+    fn id(&self) -> Id;
+
+    /// Returns the type of the instances in this group.
+    fn service_type(&self) -> &ServiceType;
+
+    /// Sets the network interface to bind the instances to. Must be called before `register()`.
+    fn set_network_interface(&mut self, interface: NetworkInterface);
+
+    /// Returns the network interface to bind the instances to.
+    fn network_interface(&self) -> NetworkInterface;
+
+    /// Adds an instance to register under `name` on `port`, returning the handle to remove it
+    /// with.
+    ///
+    /// The name is validated right away, like with [`TMdnsService::try_set_name()`]. If the group
+    /// has been registered, the instance is registered the next time the event loop is polled.
+    /// Every instance is renamed when its name collides with another service.
+    ///
+    /// [`TMdnsService::try_set_name()`]: ../service/trait.TMdnsService.html#method.try_set_name
+    fn add_instance(
+        &mut self,
+        name: &str,
+        port: u16,
+        txt_record: Option<TxtRecord>,
+    ) -> Result<InstanceHandle>;
+
+    /// Removes the instance of the specified `handle` from the group. If the group has been
+    /// registered, the instance is withdrawn the next time the event loop is polled, without
+    /// affecting the other instances.
+    ///
+    /// Returns an error if the instance is not in this group.
+    fn remove_instance(&mut self, handle: InstanceHandle) -> Result<()>;
+
+    /// Returns the handles of the instances in this group, in the order they were added.
+    fn instances(&self) -> Vec<InstanceHandle>;
+
+    /// Sets the [`InstanceRegisteredCallback`] that is invoked when an instance has been
+    /// registered, or could not be registered.
+    ///
+    /// [`InstanceRegisteredCallback`]: ../type.InstanceRegisteredCallback.html
+    fn set_registered_callback(&mut self, registered_callback: Box<InstanceRegisteredCallback>);
+
+    /// Sets a closure that is invoked when an instance has been registered, or could not be
+    /// registered.
+    ///
+    /// This is a shorthand for [`set_registered_callback()`] for closures that keep their state
+    /// to themselves rather than in the user context.
+    ///
+    /// [`set_registered_callback()`]: #tymethod.set_registered_callback
+    fn set_on_registered(
+        &mut self,
+        mut on_registered: impl FnMut(InstanceHandle, Result<ServiceRegistration>) + Send + 'static,
+    ) {
+        self.set_registered_callback(Box::new(move |handle, result, _| {
+            on_registered(handle, result)
+        }));
+    }
+
+    /// Sets the optional user context to pass through to the callback. This is useful if you need
+    /// to share state between pre and post-callback. The context type must implement `Any`, `Send`
+    /// and `Sync`.
+    fn set_context(&mut self, context: Box<dyn Any + Send + Sync>);
+
+    /// Returns the optional user context to pass through to the callback.
+    fn context(&self) -> Option<&(dyn Any + Send + Sync)>;
+
+    /// Registers the instances of the group. Returns an `EventLoop` which can be called to keep
+    /// the instances alive, and which applies the changes made with `add_instance()` and
+    /// `remove_instance()` afterwards.
+    ///
+    /// Calling `register()` again restarts the group like [`TMdnsService::register()`].
+    ///
+    /// [`TMdnsService::register()`]: ../service/trait.TMdnsService.html#tymethod.register
+    fn register(&mut self) -> Result<EventLoop>;
+}
+
+/// Identifies an instance of a [`MdnsServiceGroup`], returned by
+/// [`TMdnsServiceGroup::add_instance()`].
+///
+/// [`MdnsServiceGroup`]: type.MdnsServiceGroup.html
+/// [`TMdnsServiceGroup::add_instance()`]: service_group/trait.TMdnsServiceGroup.html#tymethod.add_instance
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct InstanceHandle(Id);
+
+impl InstanceHandle {
+    /// Returns the [`Id`] that prefixes the log output of this instance.
+    ///
+    /// [`Id`]: struct.Id.html
+    pub fn id(self) -> Id {
+        self.0
+    }
+}
+
+impl fmt::Display for InstanceHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Callback invoked from [`MdnsServiceGroup`] once an instance has been registered, or could not
+/// be registered.
+///
+/// # Arguments
+/// * `handle` - The instance the result is for
+/// * `service` - The service information that was registered
+/// * `context` - The optional user context passed through
+///
+/// [`MdnsServiceGroup`]: type.MdnsServiceGroup.html
+pub type InstanceRegisteredCallback = dyn FnMut(InstanceHandle, Result<ServiceRegistration>, Option<Arc<dyn Any + Send + Sync>>)
+    + Send;
+
+/// An instance added to a group with `add_instance()`
+#[derive(Debug, Clone)]
+pub(crate) struct Instance {
+    pub(crate) handle: InstanceHandle,
+    pub(crate) name: String,
+    pub(crate) port: u16,
+    pub(crate) txt_record: Option<TxtRecord>,
+}
+
+impl Instance {
+    /// Returns a new instance with a handle of its own, if `name` is a valid service name.
+    pub(crate) fn new(name: &str, port: u16, txt_record: Option<TxtRecord>) -> Result<Self> {
+        service_name::validate(name, false)?;
+
+        Ok(Self {
+            handle: InstanceHandle(Id::next()),
+            name: name.to_string(),
+            port,
+            txt_record,
+        })
+    }
+}
+
+/// Change to the instances of a group, applied by the event loop of the group
+#[derive(Debug)]
+pub(crate) enum InstanceChange {
+    Add(Instance),
+    Remove(InstanceHandle),
+}
+
+/// Changes to the instances of a group, in the order they were made
+#[derive(Debug, Default)]
+pub(crate) struct InstanceChanges {
+    changes: Mutex<VecDeque<InstanceChange>>,
+}
+
+impl InstanceChanges {
+    pub(crate) fn push(&self, change: InstanceChange) {
+        self.lock().push_back(change);
+    }
+
+    /// Removes the changes made so far. The lock is released before they are returned, so that
+    /// further changes can be made while they are applied.
+    pub(crate) fn take(&self) -> VecDeque<InstanceChange> {
+        std::mem::take(&mut *self.lock())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, VecDeque<InstanceChange>> {
+        self.changes.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Returns the handles of `instances` after the pending `changes` have been applied.
+pub(crate) fn instances_after(
+    instances: impl Iterator<Item = InstanceHandle>,
+    changes: &InstanceChanges,
+) -> Vec<InstanceHandle> {
+    let mut handles = instances.collect::<Vec<_>>();
+
+    for change in changes.lock().iter() {
+        match change {
+            InstanceChange::Add(instance) => handles.push(instance.handle),
+            InstanceChange::Remove(handle) => handles.retain(|h| h != handle),
+        }
+    }
+
+    handles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use crate::service_name::MAX_NAME_LEN;
+
+    #[test]
+    fn instances_have_distinct_handles() {
+        let a = Instance::new("a", 8080, None).unwrap();
+        let b = Instance::new("a", 8080, None).unwrap();
+
+        assert_ne!(a.handle, b.handle);
+    }
+
+    #[test]
+    fn instance_with_invalid_name_is_rejected() {
+        assert!(matches!(
+            Instance::new(&"a".repeat(MAX_NAME_LEN + 1), 8080, None),
+            Err(Error::NameTooLong { .. })
+        ));
+    }
+
+    #[test]
+    fn instances_after_applies_pending_changes_in_order() {
+        let a = Instance::new("a", 8080, None).unwrap();
+        let b = Instance::new("b", 8080, None).unwrap();
+        let (a_handle, b_handle) = (a.handle, b.handle);
+        let changes = InstanceChanges::default();
+
+        changes.push(InstanceChange::Add(b));
+        changes.push(InstanceChange::Remove(a_handle));
+
+        assert_eq!(
+            instances_after(std::iter::once(a_handle), &changes),
+            vec![b_handle]
+        );
+        assert_eq!(changes.take().len(), 2);
+        assert!(changes.take().is_empty());
+    }
+}
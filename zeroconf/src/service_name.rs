@@ -0,0 +1,370 @@
+//! Utilities for service instance names
+
+use crate::error::Error;
+use crate::Result;
+use std::borrow::Cow;
+
+/// Maximum length in bytes of a service instance name, which is a single DNS label
+/// (RFC 6763, section 4.1.1)
+pub const MAX_NAME_LEN: usize = 63;
+
+const ZERO_WIDTH_JOINER: char = '\u{200D}';
+
+/// Fits the specified service instance name into [`MAX_NAME_LEN`] bytes.
+///
+/// Names that already fit are returned unchanged. Longer names are truncated at the last
+/// character boundary within the limit, so the result is always valid UTF-8. If that boundary
+/// falls inside a combining sequence (e.g. an emoji joined with zero width joiners, a base
+/// character followed by combining marks, variation selectors or skin tone modifiers) the whole
+/// partial sequence is dropped, so that a truncated name never ends in a dangling or altered
+/// symbol.
+///
+/// This is an approximation of extended grapheme cluster boundaries that covers the sequences
+/// commonly found in names.
+///
+/// [`MAX_NAME_LEN`]: constant.MAX_NAME_LEN.html
+pub fn fit(name: &str) -> Cow<'_, str> {
+    if name.len() <= MAX_NAME_LEN {
+        return Cow::Borrowed(name);
+    }
+
+    let mut end = MAX_NAME_LEN;
+
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    while end > 0 && splits_sequence(name, end) {
+        end = name[..end]
+            .char_indices()
+            .next_back()
+            .map(|(i, _)| i)
+            .unwrap_or(0);
+    }
+
+    Cow::Borrowed(&name[..end])
+}
+
+/// Checks that `name` can be registered as a service instance name. Instance names may contain
+/// any UTF-8 (RFC 6763, section 4.1.1), but must not be empty or contain NUL characters, which the
+/// mDNS implementations cannot pass on.
+///
+/// If `fitting` is disabled, names longer than [`MAX_NAME_LEN`] are rejected with
+/// `Error::NameTooLong`.
+///
+/// [`MAX_NAME_LEN`]: constant.MAX_NAME_LEN.html
+pub fn validate(name: &str, fitting: bool) -> Result<()> {
+    if name.is_empty() {
+        return Err(Error::InvalidName("the name is empty".to_string()));
+    }
+
+    if name.contains('\0') {
+        return Err(Error::InvalidName(format!(
+            "`{}` contains a NUL character",
+            name.escape_debug()
+        )));
+    }
+
+    prepare(name, fitting).map(|_| ())
+}
+
+/// Returns the name to register and whether it differs from the requested `name`.
+///
+/// If `fitting` is disabled, names longer than [`MAX_NAME_LEN`] are rejected with
+/// `Error::NameTooLong`.
+///
+/// [`MAX_NAME_LEN`]: constant.MAX_NAME_LEN.html
+pub(crate) fn prepare(name: &str, fitting: bool) -> Result<(Cow<'_, str>, bool)> {
+    if name.len() <= MAX_NAME_LEN {
+        Ok((Cow::Borrowed(name), false))
+    } else if fitting {
+        Ok((fit(name), true))
+    } else {
+        Err(Error::NameTooLong {
+            bytes: name.len(),
+            max: MAX_NAME_LEN,
+        })
+    }
+}
+
+/// Returns the name to register a service under after its `name` collided with another service,
+/// the way Avahi renames services: `My Service` becomes `My Service #2`, which becomes
+/// `My Service #3` and so on.
+///
+/// The name is shortened at a character boundary if needed, so that it fits into
+/// [`MAX_NAME_LEN`] bytes along with the counter. This allows applications that disable
+/// [`TMdnsService::set_allow_name_conflicts()`] to choose the next candidate themselves.
+///
+/// # Note
+/// On Linux the name is generated by Avahi. Bonjour renames services in a different format
+/// (`My Service (2)`) when it resolves collisions itself.
+///
+/// [`MAX_NAME_LEN`]: constant.MAX_NAME_LEN.html
+/// [`TMdnsService::set_allow_name_conflicts()`]: ../service/trait.TMdnsService.html#tymethod.set_allow_name_conflicts
+pub fn alternative_service_name(name: &str) -> String {
+    #[cfg(all(target_os = "linux", not(feature = "test-mock")))]
+    if let Ok(c_name) = std::ffi::CString::new(name) {
+        // Avahi rejects names that are not valid service names
+        if let Some(alternative) =
+            unsafe { crate::avahi::avahi_util::alternative_service_name(&c_name) }
+        {
+            return alternative;
+        }
+    }
+
+    alternative(name)
+}
+
+/// Returns the alternative of `name` like `avahi_alternative_service_name()`.
+fn alternative(name: &str) -> String {
+    let (base, counter) = match name.rsplit_once(" #") {
+        Some((base, counter)) if is_counter(counter) => match counter.parse::<u64>() {
+            Ok(counter) if counter < u64::MAX => (base, counter + 1),
+            _ => (name, 2),
+        },
+        _ => (name, 2),
+    };
+
+    let suffix = format!(" #{}", counter);
+    let mut end = base.len().min(MAX_NAME_LEN.saturating_sub(suffix.len()));
+
+    while !base.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    format!("{}{}", &base[..end], suffix)
+}
+
+/// Returns true if `s` is a counter appended by [`alternative()`]: a decimal number without
+/// leading zeros.
+///
+/// [`alternative()`]: fn.alternative.html
+fn is_counter(s: &str) -> bool {
+    !s.is_empty() && !s.starts_with('0') && s.bytes().all(|b| b.is_ascii_digit())
+}
+
+fn splits_sequence(name: &str, index: usize) -> bool {
+    name[..index].ends_with(ZERO_WIDTH_JOINER)
+        || name[index..].chars().next().is_some_and(is_extender)
+}
+
+/// Returns true if `c` extends the preceding character rather than starting a new one.
+fn is_extender(c: char) -> bool {
+    matches!(c,
+        '\u{0300}'..='\u{036F}'     // combining diacritical marks
+        | '\u{1AB0}'..='\u{1AFF}'   // combining diacritical marks extended
+        | '\u{20D0}'..='\u{20FF}'   // combining marks for symbols
+        | '\u{FE00}'..='\u{FE0F}'   // variation selectors
+        | '\u{FE20}'..='\u{FE2F}'   // combining half marks
+        | '\u{1F3FB}'..='\u{1F3FF}' // emoji skin tone modifiers
+        | '\u{E0020}'..='\u{E007F}' // tags
+        | ZERO_WIDTH_JOINER
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_accepts_multi_byte_name_of_max_len() {
+        // 3 bytes per char, 21 chars = 63 bytes
+        assert_eq!(validate(&"漢".repeat(21), false), Ok(()));
+    }
+
+    #[test]
+    fn validate_rejects_multi_byte_name_over_max_len() {
+        let name = format!("{}a", "漢".repeat(21));
+
+        assert_eq!(
+            validate(&name, false),
+            Err(Error::NameTooLong {
+                bytes: 64,
+                max: MAX_NAME_LEN
+            })
+        );
+        assert_eq!(validate(&name, true), Ok(()));
+    }
+
+    #[test]
+    fn validate_rejects_empty_name() {
+        assert!(matches!(validate("", true), Err(Error::InvalidName(_))));
+    }
+
+    #[test]
+    fn validate_rejects_nul() {
+        assert!(matches!(
+            validate("foo\0bar", true),
+            Err(Error::InvalidName(_))
+        ));
+    }
+
+    #[test]
+    fn validate_accepts_any_other_utf8() {
+        assert_eq!(validate("Küche.Lamp (2) 😀 \\ \"x\"", false), Ok(()));
+    }
+
+    const ALTERNATIVES: &[(&str, &str)] = &[
+        ("foo", "foo #2"),
+        ("foo #2", "foo #3"),
+        ("foo #9", "foo #10"),
+        ("foo #2 #3", "foo #2 #4"),
+        ("foo #bar", "foo #bar #2"),
+        ("foo#2", "foo#2 #2"),
+        ("foo #", "foo # #2"),
+        ("foo #0", "foo #0 #2"),
+        ("foo #02", "foo #02 #2"),
+        ("Küche", "Küche #2"),
+    ];
+
+    #[test]
+    fn alternative_increments_counter() {
+        for (name, expected) in ALTERNATIVES {
+            assert_eq!(alternative(name), *expected);
+        }
+    }
+
+    #[test]
+    fn alternative_fits_max_len() {
+        let name = "a".repeat(MAX_NAME_LEN);
+        let expected = format!("{} #2", "a".repeat(MAX_NAME_LEN - 3));
+
+        assert_eq!(alternative(&name), expected);
+
+        let name = format!("{} #9", "a".repeat(MAX_NAME_LEN - 3));
+        let expected = format!("{} #10", "a".repeat(MAX_NAME_LEN - 4));
+
+        assert_eq!(alternative(&name), expected);
+    }
+
+    #[test]
+    fn alternative_fits_max_len_on_char_boundary() {
+        // 3 bytes per char, 21 chars = 63 bytes
+        let name = "漢".repeat(21);
+        assert_eq!(alternative(&name), format!("{} #2", "漢".repeat(20)));
+
+        let name = format!("{} #9", "漢".repeat(20));
+        assert_eq!(alternative(&name), format!("{} #10", "漢".repeat(19)));
+    }
+
+    #[test]
+    fn alternative_shortens_names_longer_than_max_len() {
+        let name = "a".repeat(MAX_NAME_LEN + 10);
+        assert_eq!(alternative(&name).len(), MAX_NAME_LEN);
+    }
+
+    #[test]
+    fn alternative_does_not_overflow_counter() {
+        let name = format!("foo #{}", u64::MAX);
+        assert_eq!(alternative(&name), format!("{} #2", name));
+    }
+
+    #[cfg(all(target_os = "linux", not(feature = "test-mock")))]
+    #[test]
+    fn alternative_matches_avahi() {
+        let long = [
+            "a".repeat(MAX_NAME_LEN),
+            format!("{} #9", "a".repeat(MAX_NAME_LEN - 3)),
+            format!("{} #99", "a".repeat(MAX_NAME_LEN - 4)),
+            "漢".repeat(21),
+            format!("{} #9", "漢".repeat(20)),
+            format!("{}é", "a".repeat(MAX_NAME_LEN - 2)),
+        ];
+
+        let names = ALTERNATIVES
+            .iter()
+            .map(|(name, _)| *name)
+            .chain(long.iter().map(String::as_str));
+
+        for name in names {
+            let avahi =
+                unsafe { crate::avahi::avahi_util::alternative_service_name(&c_string!(name)) };
+
+            assert_eq!(Some(alternative(name)), avahi, "alternative of `{}`", name);
+        }
+    }
+
+    #[test]
+    fn fit_returns_short_name_unchanged() {
+        assert_eq!(fit("foo"), "foo");
+        assert!(matches!(fit("foo"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn fit_returns_name_of_max_len_unchanged() {
+        let name = "a".repeat(MAX_NAME_LEN);
+        assert_eq!(fit(&name), name);
+    }
+
+    #[test]
+    fn fit_truncates_ascii_to_max_len() {
+        let name = "a".repeat(MAX_NAME_LEN + 10);
+        assert_eq!(fit(&name).len(), MAX_NAME_LEN);
+    }
+
+    #[test]
+    fn fit_truncates_on_char_boundary() {
+        // 3 bytes per char, 22 chars = 66 bytes
+        let name = "漢".repeat(22);
+        let fitted = fit(&name);
+
+        assert_eq!(fitted, "漢".repeat(21));
+        assert_eq!(fitted.len(), 63);
+    }
+
+    #[test]
+    fn fit_truncates_four_byte_chars_on_char_boundary() {
+        let name = format!("ab{}", "😀".repeat(20));
+        let fitted = fit(&name);
+
+        assert_eq!(fitted, format!("ab{}", "😀".repeat(15)));
+        assert!(fitted.len() <= MAX_NAME_LEN);
+    }
+
+    #[test]
+    fn fit_drops_partial_zwj_sequence() {
+        // family emoji: 👨 ZWJ 👩 ZWJ 👧 (18 bytes)
+        let family = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}";
+        let name = format!("{}{}", "a".repeat(50), family);
+
+        assert_eq!(fit(&name), "a".repeat(50));
+    }
+
+    #[test]
+    fn fit_drops_partial_combining_sequence() {
+        // "e" followed by a combining acute accent
+        let name = format!("{}e\u{0301}", "a".repeat(62));
+
+        assert_eq!(fit(&name), "a".repeat(62));
+    }
+
+    #[test]
+    fn fit_drops_emoji_with_cut_skin_tone() {
+        let name = format!("{}\u{1F44D}\u{1F3FD}", "a".repeat(58));
+
+        assert_eq!(fit(&name), "a".repeat(58));
+    }
+
+    #[test]
+    fn prepare_rejects_long_name_without_fitting() {
+        let name = "a".repeat(MAX_NAME_LEN + 1);
+
+        assert_eq!(
+            prepare(&name, false),
+            Err(Error::NameTooLong {
+                bytes: MAX_NAME_LEN + 1,
+                max: MAX_NAME_LEN
+            })
+        );
+    }
+
+    #[test]
+    fn prepare_reports_adjusted_name() {
+        let name = "a".repeat(MAX_NAME_LEN + 1);
+        let (fitted, adjusted) = prepare(&name, true).unwrap();
+
+        assert_eq!(fitted.len(), MAX_NAME_LEN);
+        assert!(adjusted);
+        assert_eq!(prepare("foo", true).unwrap(), (Cow::Borrowed("foo"), false));
+    }
+}
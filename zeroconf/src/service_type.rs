@@ -1,12 +1,22 @@
 //! Data type for constructing a service type
 
+use std::fmt;
 use std::str::FromStr;
 
 use crate::{error::Error, Result};
 
+/// Maximum length of a service name, excluding the leading underscore (RFC 6763, section 7.2)
+pub const MAX_SERVICE_NAME_LEN: usize = 15;
+
+/// Maximum length of a sub-type label (RFC 6763, section 7.1)
+const MAX_SUB_TYPE_LEN: usize = 63;
+
+/// Name of the DNS-SD meta-query service type `_services._dns-sd._udp` (RFC 6763, section 9)
+const META_QUERY_NAME: &str = "services._dns-sd";
+
 /// Data type for constructing a service type to register as an mDNS service.
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-#[derive(Default, Debug, Getters, Clone, PartialEq, Eq)]
+#[derive(Default, Debug, Getters, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct ServiceType {
     name: String,
     protocol: String,
@@ -15,33 +25,268 @@ pub struct ServiceType {
 
 impl ServiceType {
     /// Creates a new `ServiceType` with the specified name (e.g. `http`) and protocol (e.g. `tcp`)
+    ///
+    /// The name must be 1-15 characters long, consist only of letters, digits and hyphens,
+    /// contain at least one letter, begin and end with a letter or digit and must not contain
+    /// consecutive hyphens. The protocol must be `tcp` or `udp` (RFC 6763, section 7).
     pub fn new(name: &str, protocol: &str) -> Result<Self> {
         Ok(Self {
-            name: check_valid_characters(name)?.to_string(),
-            protocol: check_valid_characters(protocol)?.to_string(),
+            name: check_name(name)?.to_string(),
+            protocol: check_protocol(protocol)?.to_string(),
             sub_types: vec![],
         })
     }
 
     /// Creates a new `ServiceType` with the specified name (e.g. `http`) and protocol (e.g. `tcp`)
     /// and sub-types.
+    ///
+    /// The name and protocol are subject to the same rules as in [`new()`]. Sub-types may be up to
+    /// 63 characters long.
+    ///
+    /// [`new()`]: #method.new
     pub fn with_sub_types(name: &str, protocol: &str, sub_types: Vec<&str>) -> Result<Self> {
         Ok(Self {
-            name: check_valid_characters(name)?.to_string(),
-            protocol: check_valid_characters(protocol)?.to_string(),
             sub_types: sub_types
                 .into_iter()
-                .map(|s| check_valid_characters(s).map(|valid| valid.to_string()))
+                .map(|s| check_sub_type(s).map(|valid| valid.to_string()))
                 .collect::<Result<Vec<_>>>()?,
+            ..Self::new(name, protocol)?
         })
     }
+
+    /// Creates a new `ServiceType` with the specified name and protocol without validating
+    /// either of them.
+    ///
+    /// This is an escape hatch for service types that do not follow RFC 6763 but are accepted by
+    /// the mDNS implementation. The caller is responsible for ensuring that neither contains `.`
+    /// or `,`, as they could not be formatted unambiguously.
+    pub fn new_unchecked(name: &str, protocol: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            protocol: protocol.to_string(),
+            sub_types: vec![],
+        }
+    }
+
+    /// Returns the service type of the DNS-SD meta-query, `_services._dns-sd._udp`, which
+    /// enumerates the service types advertised on the network (RFC 6763, section 9).
+    ///
+    /// An `MdnsBrowser` created with it does not discover any services. Instead, every service
+    /// type it finds is reported to the [`BrowserEventCallback`] as
+    /// [`BrowserEvent::ServiceTypeFound`].
+    ///
+    /// [`BrowserEventCallback`]: type.BrowserEventCallback.html
+    /// [`BrowserEvent::ServiceTypeFound`]: enum.BrowserEvent.html#variant.ServiceTypeFound
+    pub fn meta_query() -> Self {
+        Self::new_unchecked(META_QUERY_NAME, "udp")
+    }
+
+    /// Returns true if this is the service type of the DNS-SD meta-query.
+    pub fn is_meta_query(&self) -> bool {
+        self.name == META_QUERY_NAME && self.protocol == "udp"
+    }
+
+    /// Adds `sub_type` if this service type does not have it yet. Returns whether it was added.
+    pub(crate) fn add_sub_type(&mut self, sub_type: &str) -> Result<bool> {
+        check_sub_type(sub_type)?;
+
+        if self.sub_types.iter().any(|s| s == sub_type) {
+            return Ok(false);
+        }
+
+        self.sub_types.push(sub_type.to_string());
+
+        Ok(true)
+    }
+
+    /// Removes `sub_type` from this service type. Returns whether it was removed.
+    pub(crate) fn remove_sub_type(&mut self, sub_type: &str) -> Result<bool> {
+        check_sub_type(sub_type)?;
+
+        let len = self.sub_types.len();
+        self.sub_types.retain(|s| s != sub_type);
+
+        Ok(self.sub_types.len() != len)
+    }
+
+    /// Returns a [`ServiceTypeBuilder`] for a service type with the specified name and protocol,
+    /// which are subject to the same rules as in [`new()`].
+    ///
+    /// [`ServiceTypeBuilder`]: struct.ServiceTypeBuilder.html
+    /// [`new()`]: #method.new
+    pub fn builder(name: &str, protocol: &str) -> ServiceTypeBuilder {
+        ServiceTypeBuilder {
+            result: Self::new(name, protocol),
+        }
+    }
+}
+
+macro_rules! well_known_service_types {
+    ($($(#[$doc:meta])* $constructor:ident => ($name:literal, $protocol:literal),)*) => {
+        /// Name and protocol of the well-known service types that have an associated constructor
+        /// on [`ServiceType`], e.g. `ServiceType::http()`.
+        ///
+        /// [`ServiceType`]: struct.ServiceType.html
+        pub const WELL_KNOWN_SERVICE_TYPES: &[(&str, &str)] = &[$(($name, $protocol)),*];
+
+        impl ServiceType {
+            $(
+                $(#[$doc])*
+                pub fn $constructor() -> Self {
+                    Self::new_unchecked($name, $protocol)
+                }
+            )*
+        }
+    };
+}
+
+well_known_service_types! {
+    /// `_http._tcp`: web server
+    http => ("http", "tcp"),
+    /// `_https._tcp`: web server over TLS
+    https => ("https", "tcp"),
+    /// `_ipp._tcp`: Internet Printing Protocol
+    ipp => ("ipp", "tcp"),
+    /// `_ipps._tcp`: Internet Printing Protocol over TLS
+    ipps => ("ipps", "tcp"),
+    /// `_printer._tcp`: Line Printer Daemon
+    printer => ("printer", "tcp"),
+    /// `_pdl-datastream._tcp`: raw printing (e.g. HP JetDirect)
+    pdl_datastream => ("pdl-datastream", "tcp"),
+    /// `_uscan._tcp`: eSCL (AirScan) scanner
+    uscan => ("uscan", "tcp"),
+    /// `_airplay._tcp`: Apple AirPlay
+    airplay => ("airplay", "tcp"),
+    /// `_raop._tcp`: Apple AirPlay audio (Remote Audio Output Protocol)
+    raop => ("raop", "tcp"),
+    /// `_googlecast._tcp`: Google Cast
+    googlecast => ("googlecast", "tcp"),
+    /// `_spotify-connect._tcp`: Spotify Connect
+    spotify_connect => ("spotify-connect", "tcp"),
+    /// `_hap._tcp`: HomeKit Accessory Protocol
+    hap => ("hap", "tcp"),
+    /// `_matter._tcp`: Matter operational node
+    matter => ("matter", "tcp"),
+    /// `_ssh._tcp`: Secure Shell
+    ssh => ("ssh", "tcp"),
+    /// `_sftp-ssh._tcp`: SFTP over SSH
+    sftp_ssh => ("sftp-ssh", "tcp"),
+    /// `_smb._tcp`: SMB file sharing
+    smb => ("smb", "tcp"),
+    /// `_afpovertcp._tcp`: Apple Filing Protocol
+    afp => ("afpovertcp", "tcp"),
+    /// `_nfs._tcp`: Network File System
+    nfs => ("nfs", "tcp"),
+    /// `_ftp._tcp`: File Transfer Protocol
+    ftp => ("ftp", "tcp"),
+    /// `_rfb._tcp`: VNC remote framebuffer
+    rfb => ("rfb", "tcp"),
+    /// `_mqtt._tcp`: MQTT broker
+    mqtt => ("mqtt", "tcp"),
+    /// `_workstation._tcp`: workstation
+    workstation => ("workstation", "tcp"),
+    /// `_device-info._tcp`: device information (e.g. the model of a host)
+    device_info => ("device-info", "tcp"),
+}
+
+/// Builder for a [`ServiceType`] that validates each part as it is added. The first error is
+/// returned by `build()`.
+///
+/// ```
+/// use zeroconf::ServiceType;
+///
+/// let service_type = ServiceType::builder("http", "tcp")
+///     .sub_type("printer")
+///     .build()
+///     .unwrap();
+///
+/// assert_eq!(service_type.sub_types(), &vec!["printer".to_string()]);
+/// ```
+///
+/// A well-known service type can be converted into a builder to add sub-types:
+///
+/// ```
+/// use zeroconf::{ServiceType, ServiceTypeBuilder};
+///
+/// let service_type = ServiceTypeBuilder::from(ServiceType::ipp())
+///     .sub_type("universal")
+///     .build()
+///     .unwrap();
+/// ```
+///
+/// [`ServiceType`]: struct.ServiceType.html
+#[derive(Debug, Clone)]
+pub struct ServiceTypeBuilder {
+    result: Result<ServiceType>,
+}
+
+impl ServiceTypeBuilder {
+    /// Adds a sub-type. Sub-types may be up to 63 characters long.
+    pub fn sub_type(self, sub_type: &str) -> Self {
+        Self {
+            result: self.result.and_then(|mut service_type| {
+                service_type
+                    .sub_types
+                    .push(check_sub_type(sub_type)?.to_string());
+
+                Ok(service_type)
+            }),
+        }
+    }
+
+    /// Returns the `ServiceType`, or the first error encountered while building it.
+    pub fn build(self) -> Result<ServiceType> {
+        self.result
+    }
+}
+
+impl From<ServiceType> for ServiceTypeBuilder {
+    fn from(service_type: ServiceType) -> Self {
+        Self {
+            result: Ok(service_type),
+        }
+    }
+}
+
+/// Formats the service type as `_name._protocol` (e.g. `_http._tcp`). Sub-types are not included.
+impl fmt::Display for ServiceType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "_{}._{}", self.name, self.protocol)
+    }
 }
 
 impl FromStr for ServiceType {
     type Err = Error;
 
+    /// Parses a service type of the form `_name._protocol`, optionally followed by a `.local.`
+    /// domain, with or without the trailing dot.
+    ///
+    /// Sub-types are accepted in both of the forms used by the mDNS implementations:
+    /// * `_printer._sub._http._tcp` as used by Avahi (and DNS-SD in general)
+    /// * `_http._tcp,_printer,_scanner` as used by Bonjour
     fn from_str(s: &str) -> Result<Self> {
-        let parts = s.split('.').collect::<Vec<_>>();
+        let mut types = s.split(',');
+        let mut sub_types = vec![];
+
+        let kind = types.next().unwrap_or_default();
+        let kind = kind.strip_suffix('.').unwrap_or(kind);
+        let kind = kind.strip_suffix(".local").unwrap_or(kind);
+
+        if kind == Self::meta_query().to_string() {
+            return Ok(Self::meta_query());
+        }
+
+        let kind = match kind.split_once("._sub.") {
+            Some((sub_type, kind)) => {
+                sub_types.push(lstrip_underscore(sub_type));
+                kind
+            }
+            None => kind,
+        };
+
+        sub_types.extend(types.map(lstrip_underscore));
+
+        let parts = kind.split('.').collect::<Vec<_>>();
 
         if parts.len() != 2 {
             return Err("invalid name and protocol".into());
@@ -50,7 +295,31 @@ impl FromStr for ServiceType {
         let name = lstrip_underscore(check_valid_characters(parts[0])?);
         let protocol = lstrip_underscore(check_valid_characters(parts[1])?);
 
-        Self::new(name, protocol)
+        Self::with_sub_types(name, protocol, sub_types)
+    }
+}
+
+/// Parses a service type reported by a meta-query. Service types that are advertised on the
+/// network but do not follow RFC 6763 are accepted as with `ServiceType::new_unchecked()`, as long
+/// as they are of the form `_name._protocol`.
+pub(crate) fn parse_advertised(s: &str) -> Result<ServiceType> {
+    if let Ok(service_type) = ServiceType::from_str(s) {
+        return Ok(service_type);
+    }
+
+    let kind = s.strip_suffix('.').unwrap_or(s);
+    let kind = kind.strip_suffix(".local").unwrap_or(kind);
+
+    match kind.split_once('.') {
+        Some((name, protocol))
+            if name.starts_with('_') && protocol.starts_with('_') && !protocol.contains('.') =>
+        {
+            Ok(ServiceType::new_unchecked(
+                lstrip_underscore(check_valid_characters(name)?),
+                lstrip_underscore(check_valid_characters(protocol)?),
+            ))
+        }
+        _ => Err(format!("invalid advertised service type `{}`", s).into()),
     }
 }
 
@@ -66,6 +335,72 @@ pub fn check_valid_characters(part: &str) -> Result<&str> {
     }
 }
 
+fn check_name(name: &str) -> Result<&str> {
+    check_valid_characters(name)?;
+
+    let len = name.chars().count();
+
+    if len > MAX_SERVICE_NAME_LEN {
+        return Err(format!(
+            "service name `{}` is {} characters long, exceeding the maximum of {}",
+            name, len, MAX_SERVICE_NAME_LEN
+        )
+        .into());
+    }
+
+    if let Some(c) = name
+        .chars()
+        .find(|c| !c.is_ascii_alphanumeric() && *c != '-')
+    {
+        return Err(format!("invalid character in service name `{}`: {:?}", name, c).into());
+    }
+
+    if name.starts_with('-') || name.ends_with('-') {
+        return Err(format!(
+            "service name `{}` must begin and end with a letter or digit",
+            name
+        )
+        .into());
+    }
+
+    if name.contains("--") {
+        return Err(format!(
+            "service name `{}` must not contain consecutive hyphens",
+            name
+        )
+        .into());
+    }
+
+    if !name.chars().any(|c| c.is_ascii_alphabetic()) {
+        return Err(format!("service name `{}` must contain at least one letter", name).into());
+    }
+
+    Ok(name)
+}
+
+fn check_protocol(protocol: &str) -> Result<&str> {
+    match protocol {
+        "tcp" | "udp" => Ok(protocol),
+        _ => Err(format!("protocol must be `tcp` or `udp`, got `{}`", protocol).into()),
+    }
+}
+
+fn check_sub_type(sub_type: &str) -> Result<&str> {
+    check_valid_characters(sub_type)?;
+
+    if sub_type.len() > MAX_SUB_TYPE_LEN {
+        return Err(format!(
+            "sub-type `{}` is {} bytes long, exceeding the maximum of {}",
+            sub_type,
+            sub_type.len(),
+            MAX_SUB_TYPE_LEN
+        )
+        .into());
+    }
+
+    Ok(sub_type)
+}
+
 pub fn lstrip_underscore(s: &str) -> &str {
     if let Some(stripped) = s.strip_prefix('_') {
         stripped
@@ -88,6 +423,141 @@ mod tests {
         ServiceType::new("http", "").expect_err("cannot be empty");
     }
 
+    #[test]
+    fn new_accepts_valid_names() {
+        ServiceType::new("http", "tcp").unwrap();
+        ServiceType::new("airplay", "udp").unwrap();
+        ServiceType::new("a", "tcp").unwrap();
+        ServiceType::new("x-plane-2", "udp").unwrap();
+        ServiceType::new("abcdefghijklmno", "tcp").unwrap();
+    }
+
+    #[test]
+    fn new_rejects_long_name() {
+        ServiceType::new("abcdefghijklmnop", "tcp").expect_err("exceeding the maximum");
+    }
+
+    #[test]
+    fn new_rejects_invalid_name_characters() {
+        ServiceType::new("my service", "tcp").expect_err("invalid character");
+        ServiceType::new("my_service", "tcp").expect_err("invalid character");
+        ServiceType::new("sérvice", "tcp").expect_err("invalid character");
+    }
+
+    #[test]
+    fn new_rejects_misplaced_hyphens() {
+        ServiceType::new("-http", "tcp").expect_err("must begin and end");
+        ServiceType::new("http-", "tcp").expect_err("must begin and end");
+        ServiceType::new("ht--tp", "tcp").expect_err("consecutive hyphens");
+    }
+
+    #[test]
+    fn new_requires_letter() {
+        ServiceType::new("1234", "tcp").expect_err("at least one letter");
+    }
+
+    #[test]
+    fn new_rejects_unknown_protocol() {
+        ServiceType::new("http", "sctp").expect_err("protocol must be");
+        ServiceType::new("http", "TCP").expect_err("protocol must be");
+    }
+
+    #[test]
+    fn with_sub_types_validates_name_and_sub_types() {
+        ServiceType::with_sub_types("my service", "tcp", vec!["printer"])
+            .expect_err("invalid character");
+        ServiceType::with_sub_types("http", "tcp", vec![&"a".repeat(64)])
+            .expect_err("exceeding the maximum");
+        ServiceType::with_sub_types("http", "tcp", vec![&"a".repeat(63)]).unwrap();
+    }
+
+    #[test]
+    fn new_unchecked_skips_validation() {
+        let service_type = ServiceType::new_unchecked("a-very-long-service-name", "sctp");
+
+        assert_eq!(service_type.name(), "a-very-long-service-name");
+        assert_eq!(service_type.protocol(), "sctp");
+    }
+
+    #[test]
+    fn display_formats_name_and_protocol() {
+        assert_eq!(
+            ServiceType::with_sub_types("http", "tcp", vec!["printer"])
+                .unwrap()
+                .to_string(),
+            "_http._tcp"
+        );
+    }
+
+    #[test]
+    fn from_str_accepts_local_domain() {
+        let expected = ServiceType::new("http", "tcp").unwrap();
+
+        assert_eq!(
+            ServiceType::from_str("_http._tcp.local.").unwrap(),
+            expected
+        );
+        assert_eq!(ServiceType::from_str("_http._tcp.local").unwrap(), expected);
+        assert_eq!(ServiceType::from_str("_http._tcp.").unwrap(), expected);
+    }
+
+    #[test]
+    fn from_str_round_trips_display() {
+        let service_type = ServiceType::new("ipp", "tcp").unwrap();
+        assert_eq!(
+            ServiceType::from_str(&service_type.to_string()).unwrap(),
+            service_type
+        );
+    }
+
+    #[test]
+    fn from_str_parses_avahi_sub_type() {
+        assert_eq!(
+            ServiceType::from_str("_printer._sub._http._tcp").unwrap(),
+            ServiceType::with_sub_types("http", "tcp", vec!["printer"]).unwrap()
+        );
+        assert_eq!(
+            ServiceType::from_str("_printer._sub._http._tcp.local.").unwrap(),
+            ServiceType::with_sub_types("http", "tcp", vec!["printer"]).unwrap()
+        );
+    }
+
+    #[test]
+    fn from_str_parses_bonjour_sub_types() {
+        assert_eq!(
+            ServiceType::from_str("_http._tcp,_printer1,_printer2").unwrap(),
+            ServiceType::with_sub_types("http", "tcp", vec!["printer1", "printer2"]).unwrap()
+        );
+    }
+
+    #[test]
+    fn from_str_rejects_invalid_sub_types() {
+        ServiceType::from_str("_http._tcp,").expect_err("cannot be empty");
+        ServiceType::from_str("_sub._http._tcp").expect_err("invalid name and protocol");
+        ServiceType::from_str("_a.b._sub._http._tcp").expect_err("invalid character: .");
+    }
+
+    #[test]
+    fn from_str_round_trips_avahi_sub_type() {
+        let service_type = ServiceType::with_sub_types("http", "tcp", vec!["printer"]).unwrap();
+        let formatted = format!("_{}._sub.{}", service_type.sub_types()[0], service_type);
+
+        assert_eq!(ServiceType::from_str(&formatted).unwrap(), service_type);
+    }
+
+    #[test]
+    fn from_str_round_trips_bonjour_sub_types() {
+        let service_type =
+            ServiceType::with_sub_types("http", "tcp", vec!["printer1", "printer2"]).unwrap();
+
+        let formatted = std::iter::once(service_type.to_string())
+            .chain(service_type.sub_types().iter().map(|s| format!("_{}", s)))
+            .collect::<Vec<_>>()
+            .join(",");
+
+        assert_eq!(ServiceType::from_str(&formatted).unwrap(), service_type);
+    }
+
     #[test]
     fn from_str_requires_two_parts() {
         ServiceType::from_str("_http").expect_err("invalid name and protocol");
@@ -102,6 +572,143 @@ mod tests {
         );
     }
 
+    #[test]
+    fn well_known_service_types_are_valid() {
+        for (name, protocol) in WELL_KNOWN_SERVICE_TYPES {
+            ServiceType::new(name, protocol).unwrap();
+        }
+    }
+
+    #[test]
+    fn well_known_service_types_round_trip() {
+        for (name, protocol) in WELL_KNOWN_SERVICE_TYPES {
+            let service_type = ServiceType::new_unchecked(name, protocol);
+            let formatted = service_type.to_string();
+
+            assert_eq!(formatted, format!("_{}._{}", name, protocol));
+            assert_eq!(ServiceType::from_str(&formatted).unwrap(), service_type);
+        }
+    }
+
+    #[test]
+    fn well_known_constructors_format_expected_types() {
+        assert_eq!(ServiceType::http().to_string(), "_http._tcp");
+        assert_eq!(ServiceType::ipp().to_string(), "_ipp._tcp");
+        assert_eq!(ServiceType::airplay().to_string(), "_airplay._tcp");
+        assert_eq!(ServiceType::googlecast().to_string(), "_googlecast._tcp");
+        assert_eq!(
+            ServiceType::pdl_datastream().to_string(),
+            "_pdl-datastream._tcp"
+        );
+        assert_eq!(ServiceType::afp().to_string(), "_afpovertcp._tcp");
+    }
+
+    #[test]
+    fn add_sub_type_ignores_existing_sub_type() {
+        let mut service_type = ServiceType::new("http", "tcp").unwrap();
+
+        assert!(service_type.add_sub_type("printer").unwrap());
+        assert!(!service_type.add_sub_type("printer").unwrap());
+        assert_eq!(service_type.sub_types(), &vec!["printer".to_string()]);
+    }
+
+    #[test]
+    fn add_sub_type_rejects_invalid_sub_type() {
+        let mut service_type = ServiceType::new("http", "tcp").unwrap();
+
+        service_type.add_sub_type(&"a".repeat(64)).unwrap_err();
+        service_type.add_sub_type("foo.bar").unwrap_err();
+
+        assert!(service_type.sub_types().is_empty());
+    }
+
+    #[test]
+    fn remove_sub_type_reports_whether_removed() {
+        let mut service_type =
+            ServiceType::with_sub_types("http", "tcp", vec!["printer", "scan"]).unwrap();
+
+        assert!(service_type.remove_sub_type("printer").unwrap());
+        assert!(!service_type.remove_sub_type("printer").unwrap());
+        assert_eq!(service_type.sub_types(), &vec!["scan".to_string()]);
+    }
+
+    #[test]
+    fn builder_adds_sub_types() {
+        assert_eq!(
+            ServiceType::builder("http", "tcp")
+                .sub_type("printer1")
+                .sub_type("printer2")
+                .build()
+                .unwrap(),
+            ServiceType::with_sub_types("http", "tcp", vec!["printer1", "printer2"]).unwrap()
+        );
+    }
+
+    #[test]
+    fn builder_returns_first_error() {
+        let result = ServiceType::builder("my service", "tcp")
+            .sub_type(&"a".repeat(64))
+            .build();
+
+        result.expect_err("invalid character");
+
+        ServiceType::builder("http", "tcp")
+            .sub_type(&"a".repeat(64))
+            .sub_type("printer")
+            .build()
+            .expect_err("exceeding the maximum");
+    }
+
+    #[test]
+    fn builder_from_well_known_type() {
+        assert_eq!(
+            ServiceTypeBuilder::from(ServiceType::ipp())
+                .sub_type("universal")
+                .build()
+                .unwrap(),
+            ServiceType::with_sub_types("ipp", "tcp", vec!["universal"]).unwrap()
+        );
+    }
+
+    #[test]
+    fn meta_query_round_trips() {
+        let meta_query = ServiceType::meta_query();
+
+        assert_eq!(meta_query.to_string(), "_services._dns-sd._udp");
+        assert_eq!(
+            ServiceType::from_str("_services._dns-sd._udp").unwrap(),
+            meta_query
+        );
+        assert_eq!(
+            ServiceType::from_str("_services._dns-sd._udp.local.").unwrap(),
+            meta_query
+        );
+        assert!(meta_query.is_meta_query());
+        assert!(!ServiceType::http().is_meta_query());
+    }
+
+    #[test]
+    fn parse_advertised_accepts_valid_types() {
+        assert_eq!(
+            parse_advertised("_http._tcp.local.").unwrap(),
+            ServiceType::http()
+        );
+    }
+
+    #[test]
+    fn parse_advertised_accepts_non_conforming_types() {
+        let service_type = parse_advertised("_microsoft_mcc._tcp").unwrap();
+
+        assert_eq!(service_type.name(), "microsoft_mcc");
+        assert_eq!(service_type.protocol(), "tcp");
+    }
+
+    #[test]
+    fn parse_advertised_rejects_malformed_types() {
+        parse_advertised("http").expect_err("invalid advertised service type");
+        parse_advertised("_a._b._c").expect_err("invalid advertised service type");
+    }
+
     #[test]
     fn check_valid_characters_returns_error_if_dot() {
         check_valid_characters("foo.bar").expect_err("invalid character: .");
@@ -0,0 +1,332 @@
+//! Detection of when a browser's initial enumeration has settled
+
+use crate::clock::Clock;
+use crate::{Id, ServiceDiscovery, ServiceType, SettledCallback};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Time without new activity after the initial enumeration is complete before a browser is
+/// considered settled
+pub(crate) const QUIESCENCE: Duration = Duration::from_millis(500);
+
+/// Tracks the progress of a browser's initial enumeration and hands out the [`SettledCallback`]
+/// once it has settled.
+///
+/// A browser has settled at the earlier of:
+/// * the deadline, measured from the start of browsing
+/// * the backend having signalled that its initial enumeration is complete, no resolution still
+///   being in flight and [`QUIESCENCE`] having passed since the last activity
+///
+/// The callback is handed out at most once.
+///
+/// [`SettledCallback`]: ../type.SettledCallback.html
+/// [`QUIESCENCE`]: constant.QUIESCENCE.html
+pub(crate) struct SettleTracker {
+    clock: Arc<dyn Clock>,
+    deadline: Duration,
+    callback: Option<Box<SettledCallback>>,
+    started_at: Option<Instant>,
+    last_activity: Option<Instant>,
+    enumerated: bool,
+    pending: usize,
+    services: Vec<ServiceDiscovery>,
+}
+
+impl SettleTracker {
+    /// Creates a new `SettleTracker` that measures time with the specified [`Clock`], which is the
+    /// clock of the browser it tracks.
+    ///
+    /// [`Clock`]: ../clock/trait.Clock.html
+    pub(crate) fn new(
+        deadline: Duration,
+        callback: Box<SettledCallback>,
+        clock: Arc<dyn Clock>,
+    ) -> Self {
+        Self {
+            clock,
+            deadline,
+            callback: Some(callback),
+            started_at: None,
+            last_activity: None,
+            enumerated: false,
+            pending: 0,
+            services: vec![],
+        }
+    }
+
+    /// Records that browsing has started. This resets any previous progress.
+    pub(crate) fn start(&mut self) {
+        let now = self.clock.now();
+        self.started_at = Some(now);
+        self.last_activity = Some(now);
+        self.enumerated = false;
+        self.pending = 0;
+        self.services.clear();
+    }
+
+    /// Records that a service has been found and its resolution has started.
+    pub(crate) fn resolution_started(&mut self) {
+        self.pending += 1;
+        self.last_activity = Some(self.clock.now());
+    }
+
+    /// Records that a resolution previously started with `resolution_started()` has finished,
+    /// successfully or not.
+    pub(crate) fn resolution_finished(&mut self) {
+        self.pending = self.pending.saturating_sub(1);
+        self.last_activity = Some(self.clock.now());
+    }
+
+    /// Adds `discovery` to the snapshot, replacing an earlier discovery of the same service.
+    pub(crate) fn resolved(&mut self, discovery: &ServiceDiscovery) {
+        self.services.retain(|s| !is_same_service(s, discovery));
+        self.services.push(discovery.clone());
+        self.last_activity = Some(self.clock.now());
+    }
+
+    /// Removes the specified service from the snapshot.
+    pub(crate) fn removed(&mut self, name: &str, service_type: &ServiceType, domain: &str) {
+        self.services.retain(|s| {
+            s.name() != name || s.service_type() != service_type || s.domain() != domain
+        });
+    }
+
+    /// Records that the backend has signalled that its initial enumeration is complete.
+    pub(crate) fn enumerated(&mut self) {
+        if !self.enumerated {
+            self.enumerated = true;
+            self.last_activity = Some(self.clock.now());
+        }
+    }
+
+    /// Returns true if the browser has settled.
+    pub(crate) fn is_settled(&self) -> bool {
+        self.is_settled_at(self.clock.now())
+    }
+
+    fn is_settled_at(&self, now: Instant) -> bool {
+        let started_at = match self.started_at {
+            Some(started_at) => started_at,
+            None => return false,
+        };
+
+        if now >= started_at + self.deadline {
+            return true;
+        }
+
+        let quiet = self
+            .last_activity
+            .is_some_and(|last| now >= last + QUIESCENCE);
+
+        self.enumerated && self.pending == 0 && quiet
+    }
+
+    /// Takes the callback along with the snapshot of services if the browser has settled.
+    /// Returns `None` if it has not settled or the callback has already been taken.
+    pub(crate) fn take_if_settled(
+        &mut self,
+    ) -> Option<(Box<SettledCallback>, Vec<ServiceDiscovery>)> {
+        if self.callback.is_none() || !self.is_settled() {
+            return None;
+        }
+
+        let services = self.services.clone();
+        self.callback.take().map(|callback| (callback, services))
+    }
+}
+
+/// Invokes the settled callback of `tracker` if the browser has settled.
+///
+/// The lock is released before the callback is invoked. `id` is the id of the polling event loop.
+pub(crate) fn poll(tracker: &Mutex<SettleTracker>, id: Id) {
+    let settled = tracker
+        .lock()
+        .expect("should have been able to obtain lock on settle tracker")
+        .take_if_settled();
+
+    if let Some((callback, services)) = settled {
+        debug!("[{}] Browser settled with {} services", id, services.len());
+        callback(services);
+    }
+}
+
+fn is_same_service(a: &ServiceDiscovery, b: &ServiceDiscovery) -> bool {
+    a.name() == b.name() && a.service_type() == b.service_type() && a.domain() == b.domain()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use crate::tests::discovery;
+
+    const DEADLINE: Duration = Duration::from_secs(5);
+
+    fn tracker() -> (SettleTracker, Arc<MockClock>) {
+        let clock = Arc::new(MockClock::new());
+        let mut tracker = SettleTracker::new(DEADLINE, Box::new(|_| {}), clock.clone());
+        tracker.start();
+        (tracker, clock)
+    }
+
+    fn names(services: &[ServiceDiscovery]) -> Vec<&str> {
+        services.iter().map(|s| s.name().as_str()).collect()
+    }
+
+    #[test]
+    fn not_settled_before_start() {
+        let clock = Arc::new(MockClock::new());
+        let tracker = SettleTracker::new(DEADLINE, Box::new(|_| {}), clock.clone());
+
+        clock.advance(DEADLINE);
+
+        assert!(!tracker.is_settled());
+    }
+
+    #[test]
+    fn fast_network_settles_after_signal_and_quiescence() {
+        let (mut tracker, clock) = tracker();
+
+        tracker.resolution_started();
+        tracker.resolved(&discovery("foo"));
+        tracker.resolution_finished();
+
+        clock.advance(Duration::from_millis(100));
+        tracker.enumerated();
+
+        clock.advance(QUIESCENCE / 2);
+        assert!(!tracker.is_settled());
+
+        clock.advance(QUIESCENCE / 2);
+        let (_, services) = tracker.take_if_settled().unwrap();
+        assert_eq!(names(&services), vec!["foo"]);
+    }
+
+    #[test]
+    fn activity_restarts_quiescence() {
+        let (mut tracker, clock) = tracker();
+
+        tracker.enumerated();
+
+        clock.advance(QUIESCENCE / 2);
+        tracker.resolution_started();
+        tracker.resolved(&discovery("foo"));
+        tracker.resolution_finished();
+
+        clock.advance(QUIESCENCE / 2);
+        assert!(!tracker.is_settled());
+
+        clock.advance(QUIESCENCE / 2);
+        assert!(tracker.is_settled());
+    }
+
+    #[test]
+    fn slow_resolution_delays_settling() {
+        let (mut tracker, clock) = tracker();
+
+        tracker.resolution_started();
+        tracker.enumerated();
+
+        clock.advance(QUIESCENCE * 4);
+        assert!(!tracker.is_settled());
+
+        clock.advance(Duration::from_secs(2) - QUIESCENCE * 4);
+        tracker.resolved(&discovery("foo"));
+        tracker.resolution_finished();
+
+        assert!(!tracker.is_settled());
+
+        clock.advance(QUIESCENCE);
+        let (_, services) = tracker.take_if_settled().unwrap();
+        assert_eq!(names(&services), vec!["foo"]);
+    }
+
+    #[test]
+    fn stalled_resolution_settles_at_deadline() {
+        let (mut tracker, clock) = tracker();
+
+        tracker.resolution_started();
+        tracker.resolution_started();
+        tracker.resolved(&discovery("foo"));
+        tracker.resolution_finished();
+        tracker.enumerated();
+
+        clock.advance(DEADLINE - Duration::from_millis(1));
+        assert!(!tracker.is_settled());
+
+        clock.advance(Duration::from_millis(1));
+        let (_, services) = tracker.take_if_settled().unwrap();
+        assert_eq!(names(&services), vec!["foo"]);
+    }
+
+    #[test]
+    fn empty_network_settles_at_quiescence_after_signal() {
+        let (mut tracker, clock) = tracker();
+
+        tracker.enumerated();
+        clock.advance(QUIESCENCE);
+
+        let (_, services) = tracker.take_if_settled().unwrap();
+        assert!(services.is_empty());
+    }
+
+    #[test]
+    fn empty_network_without_signal_settles_at_deadline() {
+        let (tracker, clock) = tracker();
+
+        clock.advance(QUIESCENCE * 2);
+        assert!(!tracker.is_settled());
+
+        clock.advance(DEADLINE - QUIESCENCE * 2);
+        assert!(tracker.is_settled());
+    }
+
+    #[test]
+    fn callback_is_taken_once() {
+        let (mut tracker, clock) = tracker();
+
+        clock.advance(DEADLINE);
+
+        assert!(tracker.take_if_settled().is_some());
+        assert!(tracker.take_if_settled().is_none());
+    }
+
+    #[test]
+    fn snapshot_replaces_and_removes_services() {
+        let (mut tracker, clock) = tracker();
+
+        tracker.resolved(&discovery("foo"));
+        tracker.resolved(&discovery("bar"));
+        tracker.resolved(&discovery("foo"));
+        tracker.removed("bar", &ServiceType::new("http", "tcp").unwrap(), "local");
+
+        clock.advance(DEADLINE);
+
+        let (_, services) = tracker.take_if_settled().unwrap();
+        assert_eq!(names(&services), vec!["foo"]);
+    }
+
+    #[test]
+    fn poll_invokes_callback_once() {
+        let calls: Arc<Mutex<Vec<usize>>> = Arc::default();
+        let sink = calls.clone();
+        let clock = Arc::new(MockClock::new());
+
+        let tracker = Mutex::new(SettleTracker::new(
+            DEADLINE,
+            Box::new(move |services| sink.lock().unwrap().push(services.len())),
+            clock.clone(),
+        ));
+
+        poll(&tracker, Id::next());
+        assert!(calls.lock().unwrap().is_empty());
+
+        tracker.lock().unwrap().start();
+        clock.advance(DEADLINE);
+
+        poll(&tracker, Id::next());
+        poll(&tracker, Id::next());
+
+        assert_eq!(*calls.lock().unwrap(), vec![0]);
+    }
+}
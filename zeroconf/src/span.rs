@@ -0,0 +1,31 @@
+//! Spans for structured diagnostics with the `tracing` feature
+//!
+//! With the feature enabled, spans created with `span!` are `tracing` spans and log messages are
+//! emitted as `tracing` events inside of them, so that they can be filtered by span fields such as
+//! the service type. Without it, spans are no-ops and messages are logged with `log`.
+
+#[cfg(feature = "tracing")]
+pub(crate) use tracing::Span;
+
+/// Stand-in for `tracing::Span` when the `tracing` feature is disabled
+#[cfg(not(feature = "tracing"))]
+#[derive(Debug, Clone)]
+pub(crate) struct Span;
+
+/// Guard returned by [`Span::enter()`], for parity with `tracing::span::Entered`
+#[cfg(not(feature = "tracing"))]
+#[derive(Debug)]
+pub(crate) struct Entered;
+
+#[cfg(not(feature = "tracing"))]
+impl Span {
+    /// Returns a span that is never entered.
+    pub(crate) fn none() -> Self {
+        Self
+    }
+
+    /// Enters the span until the returned guard is dropped.
+    pub(crate) fn enter(&self) -> Entered {
+        Entered
+    }
+}
@@ -0,0 +1,879 @@
+use crate::browser::scoped_address;
+use crate::clock::MockClock;
+use crate::prelude::*;
+use crate::{
+    MdnsBrowser, MdnsService, NetworkInterface, Protocol, ServiceDiscovery,
+    ServiceDiscoveryBuilder, ServiceKey, ServiceType, TxtRecord, DEFAULT_MAX_CONCURRENT_RESOLVES,
+    DEFAULT_RESCAN_DEADLINE, DEFAULT_RESOLVE_TIMEOUT,
+};
+use std::collections::{BTreeSet, HashMap};
+use std::io;
+use std::net::{SocketAddr, SocketAddrV6, ToSocketAddrs};
+#[cfg(any(target_os = "linux", feature = "test-mock"))]
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+fn discovery_builder(name: &str, interface: NetworkInterface) -> ServiceDiscoveryBuilder {
+    let mut builder = super::discovery_builder(name);
+    builder.interface(interface);
+    builder
+}
+
+fn discovery(name: &str, interface: NetworkInterface) -> ServiceDiscovery {
+    discovery_builder(name, interface).build().unwrap()
+}
+
+#[test]
+fn service_discovery_builder_defaults_optional_properties() {
+    let discovery = ServiceDiscoveryBuilder::default()
+        .name("foo")
+        .service_type(ServiceType::new("http", "tcp").unwrap())
+        .domain("local")
+        .host_name("foo.local")
+        .address("192.168.1.2")
+        .port(8080)
+        .build()
+        .unwrap();
+
+    assert_eq!(
+        discovery,
+        discovery_builder("foo", NetworkInterface::Unspec)
+            .build()
+            .unwrap()
+    );
+    assert_eq!(*discovery.ttl(), None);
+}
+
+#[test]
+fn service_discovery_builder_requires_address() {
+    assert!(ServiceDiscovery::builder()
+        .name("foo")
+        .service_type(ServiceType::new("http", "tcp").unwrap())
+        .domain("local")
+        .host_name("foo.local")
+        .port(8080)
+        .build()
+        .is_err());
+}
+
+#[test]
+fn service_discovery_builder_build_owned_matches_build() {
+    let mut builder = discovery_builder("foo", NetworkInterface::AtIndex(1));
+
+    assert_eq!(builder.build().unwrap(), builder.build_owned().unwrap());
+}
+
+#[test]
+fn service_discovery_builder_build_owned_requires_address() {
+    let mut builder = ServiceDiscovery::builder();
+
+    builder
+        .name("foo")
+        .service_type(ServiceType::new("http", "tcp").unwrap())
+        .domain("local")
+        .host_name("foo.local")
+        .port(8080);
+
+    assert_eq!(
+        builder.build_owned(),
+        Err("`address` must be initialized".to_string())
+    );
+}
+
+#[test]
+fn browser_rescan_before_browse_services_fails() {
+    super::setup();
+
+    let mut browser = MdnsBrowser::new(ServiceType::new("http", "tcp").unwrap());
+
+    assert_eq!(browser.rescan(), Err("browser has not been started".into()));
+}
+
+#[test]
+fn browser_wait_for_daemon_defaults_to_false() {
+    let mut browser = MdnsBrowser::new(ServiceType::new("http", "tcp").unwrap());
+
+    assert!(!browser.wait_for_daemon());
+
+    #[cfg(any(target_os = "linux", feature = "test-mock"))]
+    {
+        browser.set_wait_for_daemon(true).unwrap();
+        assert!(browser.wait_for_daemon());
+    }
+
+    #[cfg(not(any(target_os = "linux", feature = "test-mock")))]
+    assert!(matches!(
+        browser.set_wait_for_daemon(true),
+        Err(crate::error::Error::Unsupported(_))
+    ));
+}
+
+#[cfg(any(target_os = "linux", feature = "test-mock"))]
+#[test]
+fn browser_dropped_after_browse_services_ignores_pending_events() {
+    super::setup();
+
+    let discovered = Arc::new(AtomicBool::new(false));
+    let sink = discovered.clone();
+
+    let mut browser = MdnsBrowser::new(ServiceType::new("http", "tcp").unwrap());
+    browser.set_wait_for_daemon(true).unwrap();
+    browser
+        .set_service_discovered_callback(Box::new(move |_, _| sink.store(true, Ordering::SeqCst)));
+
+    let event_loop = match browser.browse_services() {
+        Ok(event_loop) => event_loop,
+        // no daemon to browse
+        Err(_) => return,
+    };
+
+    drop(browser);
+
+    for _ in 0..10 {
+        event_loop.poll(Duration::from_millis(10)).unwrap();
+    }
+
+    assert!(!discovered.load(Ordering::SeqCst));
+}
+
+#[test]
+fn browser_dropped_from_discovered_callback_keeps_polling() {
+    super::setup();
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+    static SERVICE_NAME: &str = "browser_dropped_from_discovered_callback_keeps_polling";
+
+    let service_type = ServiceType::new("http", "tcp").unwrap();
+
+    let mut service = MdnsService::new(service_type.clone(), 8090);
+    service.set_name(SERVICE_NAME);
+    service.set_network_interface(NetworkInterface::LocalOnly);
+
+    let _registered = service.register_blocking(TIMEOUT).unwrap();
+
+    let slot: Arc<Mutex<Option<MdnsBrowser>>> = Arc::default();
+    let handle = slot.clone();
+    let (tx, rx) = mpsc::channel();
+
+    let mut browser = MdnsBrowser::new(service_type);
+
+    browser.set_on_discovered(move |result| {
+        if matches!(&result, Ok(discovery) if discovery.name() == SERVICE_NAME) {
+            drop(handle.lock().unwrap().take());
+            tx.send(()).unwrap();
+        }
+    });
+
+    let event_loop = browser.browse_services().unwrap();
+    *slot.lock().unwrap() = Some(browser);
+
+    let start = Instant::now();
+
+    while rx.try_recv().is_err() {
+        event_loop.poll(Duration::from_millis(100)).unwrap();
+
+        assert!(
+            start.elapsed() < TIMEOUT,
+            "service was not discovered within {:?}",
+            TIMEOUT
+        );
+    }
+
+    assert!(slot.lock().unwrap().is_none());
+
+    for _ in 0..5 {
+        event_loop.poll(Duration::from_millis(10)).unwrap();
+    }
+}
+
+#[test]
+fn browser_methods_can_be_called_from_its_callbacks() {
+    super::setup();
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+    static SERVICE_NAME: &str = "browser_methods_can_be_called_from_its_callbacks";
+
+    let service_type = ServiceType::new("http", "tcp").unwrap();
+
+    let mut service = MdnsService::new(service_type.clone(), 8096);
+    service.set_name(SERVICE_NAME);
+    service.set_network_interface(NetworkInterface::LocalOnly);
+
+    let _registered = service.register_blocking(TIMEOUT).unwrap();
+
+    let slot: Arc<Mutex<Option<MdnsBrowser>>> = Arc::default();
+    let handle = slot.clone();
+    let (tx, rx) = mpsc::channel();
+
+    let mut browser = MdnsBrowser::new(service_type);
+
+    browser.set_on_discovered(move |result| {
+        if !matches!(&result, Ok(discovery) if discovery.name() == SERVICE_NAME) {
+            return;
+        }
+
+        let mut browser = handle.lock().unwrap();
+        let browser = browser.as_mut().unwrap();
+
+        let events = *browser.stats().events_received();
+        browser.rescan().unwrap();
+
+        tx.send(events).unwrap();
+    });
+
+    let event_loop = browser.browse_services().unwrap();
+    *slot.lock().unwrap() = Some(browser);
+
+    let start = Instant::now();
+
+    // the rescan reports the service once more
+    for _ in 0..2 {
+        loop {
+            event_loop.poll(Duration::from_millis(100)).unwrap();
+
+            if let Ok(events) = rx.try_recv() {
+                assert!(events > 0);
+                break;
+            }
+
+            assert!(
+                start.elapsed() < TIMEOUT,
+                "service was not discovered within {:?}",
+                TIMEOUT
+            );
+        }
+    }
+}
+
+#[test]
+fn browser_browse_services_twice_restarts_browse() {
+    super::setup();
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+    static SERVICE_NAME: &str = "browser_browse_services_twice_restarts_browse";
+
+    let service_type = ServiceType::new("http", "tcp").unwrap();
+
+    let mut service = MdnsService::new(service_type.clone(), 8091);
+    service.set_name(SERVICE_NAME);
+    service.set_network_interface(NetworkInterface::LocalOnly);
+
+    let _registered = service.register_blocking(TIMEOUT).unwrap();
+
+    let (tx, rx) = mpsc::channel();
+
+    let mut browser = MdnsBrowser::new(service_type);
+
+    browser.set_on_discovered(move |result| {
+        if matches!(&result, Ok(discovery) if discovery.name() == SERVICE_NAME) {
+            tx.send(()).unwrap();
+        }
+    });
+
+    let first = browser.browse_services().unwrap();
+    drop(first);
+
+    // the service is reported through the event loop of the second browse
+    let event_loop = browser.browse_services().unwrap();
+    let start = Instant::now();
+
+    while rx.try_recv().is_err() {
+        event_loop.poll(Duration::from_millis(100)).unwrap();
+
+        assert!(
+            start.elapsed() < TIMEOUT,
+            "service was not discovered within {:?}",
+            TIMEOUT
+        );
+    }
+}
+
+#[test]
+fn browser_reports_empty_txt_record_as_empty() {
+    super::setup();
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+    static SERVICE_NAME: &str = "browser_reports_empty_txt_record_as_empty";
+
+    let service_type = ServiceType::new("http", "tcp").unwrap();
+
+    let mut service = MdnsService::new(service_type.clone(), 8094);
+    service.set_name(SERVICE_NAME);
+    service.set_network_interface(NetworkInterface::LocalOnly);
+    service.set_txt_record(TxtRecord::new());
+
+    let _registered = service.register_blocking(TIMEOUT).unwrap();
+
+    let (tx, rx) = mpsc::channel();
+
+    let mut browser = MdnsBrowser::new(service_type);
+
+    browser.set_on_discovered(move |result| match result {
+        Ok(discovery) if discovery.name() == SERVICE_NAME => {
+            tx.send(discovery.txt().cloned()).unwrap()
+        }
+        _ => {}
+    });
+
+    let event_loop = browser.browse_services().unwrap();
+    let start = Instant::now();
+
+    let txt = loop {
+        event_loop.poll(Duration::from_millis(100)).unwrap();
+
+        if let Ok(txt) = rx.try_recv() {
+            break txt;
+        }
+
+        assert!(
+            start.elapsed() < TIMEOUT,
+            "service was not discovered within {:?}",
+            TIMEOUT
+        );
+    };
+
+    assert_eq!(txt, Some(TxtRecord::new()));
+}
+
+#[test]
+fn browser_reports_configured_domain() {
+    super::setup();
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+    static SERVICE_NAME: &str = "browser_reports_configured_domain";
+
+    let service_type = ServiceType::new("http", "tcp").unwrap();
+
+    let mut service = MdnsService::new(service_type.clone(), 8095);
+    service.set_name(SERVICE_NAME);
+    service.set_network_interface(NetworkInterface::LocalOnly);
+
+    let _registered = service.register_blocking(TIMEOUT).unwrap();
+
+    let (tx, rx) = mpsc::channel();
+
+    let mut browser = MdnsBrowser::new(service_type);
+    browser.set_domain("local");
+
+    browser.set_on_discovered(move |result| match result {
+        Ok(discovery) if discovery.name() == SERVICE_NAME => {
+            tx.send(discovery.domain().clone()).unwrap()
+        }
+        _ => {}
+    });
+
+    let event_loop = browser.browse_services().unwrap();
+    let start = Instant::now();
+
+    let domain = loop {
+        event_loop.poll(Duration::from_millis(100)).unwrap();
+
+        if let Ok(domain) = rx.try_recv() {
+            break domain;
+        }
+
+        assert!(
+            start.elapsed() < TIMEOUT,
+            "service was not discovered within {:?}",
+            TIMEOUT
+        );
+    };
+
+    assert_eq!(domain, "local");
+}
+
+#[test]
+fn browser_resolves_services_while_another_never_resolves() {
+    super::setup();
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+    static SERVICE_NAME: &str = "browser_resolves_services_while_another_never_resolves";
+
+    let service_type = ServiceType::new("http", "tcp").unwrap();
+
+    // the host of this service has no address, so it cannot be resolved
+    let mut unresolvable = MdnsService::new(service_type.clone(), 8091);
+    unresolvable.set_name("browser_never_resolving_service");
+    unresolvable.set_host("browser-test-host-without-address.local");
+    unresolvable.set_network_interface(NetworkInterface::LocalOnly);
+
+    let mut service = MdnsService::new(service_type.clone(), 8092);
+    service.set_name(SERVICE_NAME);
+    service.set_network_interface(NetworkInterface::LocalOnly);
+
+    let _unresolvable = unresolvable.register_blocking(TIMEOUT).unwrap();
+    let _registered = service.register_blocking(TIMEOUT).unwrap();
+
+    let (tx, rx) = mpsc::channel();
+    let mut browser = MdnsBrowser::new(service_type);
+
+    browser.set_on_discovered(move |result| {
+        if matches!(&result, Ok(discovery) if discovery.name() == SERVICE_NAME) {
+            let _ = tx.send(());
+        }
+    });
+
+    let event_loop = browser.browse_services().unwrap();
+    let start = Instant::now();
+
+    while rx.try_recv().is_err() {
+        event_loop.poll(Duration::from_millis(100)).unwrap();
+
+        assert!(
+            start.elapsed() < TIMEOUT,
+            "service was not discovered within {:?}",
+            TIMEOUT
+        );
+    }
+}
+
+// the mock backend resolves every service immediately
+#[cfg(not(feature = "test-mock"))]
+#[test]
+fn browser_reports_service_that_does_not_resolve_in_time() {
+    use crate::{BrowserEvent, ResolveFailure};
+
+    super::setup();
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+    static SERVICE_NAME: &str = "browser_resolve_timeout_service";
+
+    let service_type = ServiceType::new("http", "tcp").unwrap();
+
+    // the host of this service has no address, so it cannot be resolved
+    let mut service = MdnsService::new(service_type.clone(), 8093);
+    service.set_name(SERVICE_NAME);
+    service.set_host("browser-timeout-host-without-address.local");
+    service.set_network_interface(NetworkInterface::LocalOnly);
+
+    let _registered = service.register_blocking(TIMEOUT).unwrap();
+
+    let (tx, rx) = mpsc::channel();
+    let mut browser = MdnsBrowser::new(service_type);
+
+    browser.set_resolve_timeout(Duration::from_millis(500));
+    browser.set_browser_event_callback(Box::new(move |event, _| {
+        if let BrowserEvent::ResolveFailed { key, reason } = event {
+            if key.name() == SERVICE_NAME {
+                let _ = tx.send(reason);
+            }
+        }
+    }));
+
+    let event_loop = browser.browse_services().unwrap();
+    let start = Instant::now();
+
+    let reason = loop {
+        if let Ok(reason) = rx.try_recv() {
+            break reason;
+        }
+
+        event_loop.poll(Duration::from_millis(100)).unwrap();
+
+        assert!(
+            start.elapsed() < TIMEOUT,
+            "resolve did not time out within {:?}",
+            TIMEOUT
+        );
+    };
+
+    assert_eq!(reason, ResolveFailure::Timeout);
+    assert_eq!(browser.stats().active_resolvers(), &0);
+}
+
+#[test]
+fn browser_domain_defaults_to_none() {
+    let mut browser = MdnsBrowser::new(ServiceType::new("http", "tcp").unwrap());
+
+    assert_eq!(browser.domain(), None);
+
+    browser.set_domain("local");
+
+    assert_eq!(browser.domain(), Some("local"));
+}
+
+#[test]
+fn browser_protocol_defaults_to_any() {
+    let mut browser = MdnsBrowser::new(ServiceType::new("http", "tcp").unwrap());
+
+    assert_eq!(browser.protocol(), Protocol::Any);
+
+    browser.set_protocol(Protocol::V6);
+
+    assert_eq!(browser.protocol(), Protocol::V6);
+}
+
+#[test]
+fn browser_max_concurrent_resolves_defaults_to_default() {
+    let mut browser = MdnsBrowser::new(ServiceType::new("http", "tcp").unwrap());
+
+    assert_eq!(
+        browser.max_concurrent_resolves(),
+        DEFAULT_MAX_CONCURRENT_RESOLVES
+    );
+
+    browser.set_max_concurrent_resolves(4);
+
+    assert_eq!(browser.max_concurrent_resolves(), 4);
+
+    browser.set_max_concurrent_resolves(0);
+
+    assert_eq!(browser.max_concurrent_resolves(), 1);
+}
+
+#[test]
+fn browser_resolve_timeout_defaults_to_default() {
+    let mut browser = MdnsBrowser::new(ServiceType::new("http", "tcp").unwrap());
+
+    assert_eq!(browser.resolve_timeout(), DEFAULT_RESOLVE_TIMEOUT);
+
+    browser.set_resolve_timeout(Duration::from_secs(3));
+
+    assert_eq!(browser.resolve_timeout(), Duration::from_secs(3));
+}
+
+#[test]
+fn browser_rescan_deadline_defaults_to_default() {
+    let mut browser = MdnsBrowser::new(ServiceType::new("http", "tcp").unwrap());
+
+    assert_eq!(browser.rescan_deadline(), DEFAULT_RESCAN_DEADLINE);
+
+    browser.set_rescan_deadline(Duration::from_secs(3));
+
+    assert_eq!(browser.rescan_deadline(), Duration::from_secs(3));
+}
+
+#[test]
+fn browser_builder_sets_properties() {
+    let browser = MdnsBrowser::builder()
+        .service_type(ServiceType::new("http", "tcp").unwrap())
+        .network_interface(NetworkInterface::AtIndex(1))
+        .service_discovered_callback(Box::new(|_, _| {}))
+        .build()
+        .unwrap();
+
+    assert_eq!(browser.network_interface(), NetworkInterface::AtIndex(1));
+}
+
+#[test]
+fn browser_builder_requires_service_type() {
+    assert!(MdnsBrowser::builder().build().is_err());
+}
+
+#[test]
+fn service_discovery_key_ignores_interface() {
+    let http = ServiceType::new("http", "tcp").unwrap();
+
+    assert_eq!(
+        discovery("foo", NetworkInterface::AtIndex(1)).key(),
+        ServiceKey::new("foo", &http, "local")
+    );
+    assert_eq!(
+        discovery("foo", NetworkInterface::AtIndex(1)).key(),
+        discovery("foo", NetworkInterface::AtIndex(2)).key()
+    );
+}
+
+#[test]
+fn service_keys_are_ordered_by_name_then_type_then_domain() {
+    let http = ServiceType::new("http", "tcp").unwrap();
+    let ipp = ServiceType::new("ipp", "tcp").unwrap();
+
+    let keys = vec![
+        ServiceKey::new("foo", &http, "local"),
+        ServiceKey::new("bar", &ipp, "local"),
+        ServiceKey::new("foo", &ipp, "example.com"),
+        ServiceKey::new("foo", &http, "example.com"),
+    ]
+    .into_iter()
+    .collect::<BTreeSet<_>>()
+    .into_iter()
+    .collect::<Vec<_>>();
+
+    assert_eq!(
+        keys,
+        vec![
+            ServiceKey::new("bar", &ipp, "local"),
+            ServiceKey::new("foo", &http, "example.com"),
+            ServiceKey::new("foo", &http, "local"),
+            ServiceKey::new("foo", &ipp, "example.com"),
+        ]
+    );
+}
+
+#[test]
+fn service_discoveries_can_be_indexed_by_key() {
+    let discoveries = vec![
+        discovery("foo", NetworkInterface::AtIndex(1)),
+        discovery("foo", NetworkInterface::AtIndex(2)),
+        discovery("bar", NetworkInterface::AtIndex(1)),
+    ]
+    .into_iter()
+    .map(|d| (d.key(), d))
+    .collect::<HashMap<_, _>>();
+
+    assert_eq!(discoveries.len(), 2);
+}
+
+fn discovery_at(address: &str, interface: NetworkInterface) -> ServiceDiscovery {
+    discovery_builder("foo", interface)
+        .address(address.to_string())
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn service_discovery_socket_addrs_pairs_ipv4_address_with_port() {
+    let discovery = discovery_at("192.168.1.2", NetworkInterface::AtIndex(2));
+
+    assert_eq!(
+        discovery.socket_addrs().unwrap(),
+        vec![SocketAddr::from(([192, 168, 1, 2], 8080))]
+    );
+}
+
+#[test]
+fn service_discovery_socket_addrs_leaves_global_ipv6_unscoped() {
+    let discovery = discovery_at("2001:db8::1", NetworkInterface::AtIndex(2));
+
+    assert_eq!(
+        discovery.socket_addrs().unwrap(),
+        vec![SocketAddr::V6(SocketAddrV6::new(
+            "2001:db8::1".parse().unwrap(),
+            8080,
+            0,
+            0
+        ))]
+    );
+}
+
+#[test]
+fn service_discovery_socket_addrs_scopes_link_local_ipv6_to_interface() {
+    let discovery = discovery_at("fe80::1", NetworkInterface::AtIndex(2));
+
+    assert_eq!(
+        discovery.socket_addrs().unwrap(),
+        vec![SocketAddr::V6(SocketAddrV6::new(
+            "fe80::1".parse().unwrap(),
+            8080,
+            0,
+            2
+        ))]
+    );
+}
+
+#[test]
+fn service_discovery_socket_addrs_keeps_scope_of_address() {
+    let discovery = discovery_at("fe80::1%3", NetworkInterface::AtIndex(2));
+
+    assert_eq!(
+        discovery.socket_addrs().unwrap(),
+        vec![SocketAddr::V6(SocketAddrV6::new(
+            "fe80::1".parse().unwrap(),
+            8080,
+            0,
+            3
+        ))]
+    );
+}
+
+#[test]
+fn service_discovery_socket_addrs_rejects_missing_or_invalid_address() {
+    assert!(discovery_at("", NetworkInterface::Unspec)
+        .socket_addrs()
+        .is_err());
+    assert!(discovery_at("foo.local", NetworkInterface::Unspec)
+        .socket_addrs()
+        .is_err());
+    assert!(discovery_at("fe80::1%eth0", NetworkInterface::Unspec)
+        .socket_addrs()
+        .is_err());
+}
+
+#[test]
+fn scoped_address_appends_interface_to_link_local_ipv6() {
+    assert_eq!(
+        scoped_address("fe80::1".to_string(), NetworkInterface::AtIndex(2)),
+        "fe80::1%2"
+    );
+}
+
+#[test]
+fn scoped_address_leaves_other_addresses_as_is() {
+    for address in ["2001:db8::1", "192.168.1.2", "fe80::1%3"].iter() {
+        assert_eq!(
+            scoped_address(address.to_string(), NetworkInterface::AtIndex(2)),
+            *address
+        );
+    }
+}
+
+#[test]
+fn scoped_address_leaves_link_local_ipv6_without_interface_as_is() {
+    for interface in [NetworkInterface::Unspec, NetworkInterface::LocalOnly].iter() {
+        assert_eq!(scoped_address("fe80::1".to_string(), *interface), "fe80::1");
+    }
+}
+
+#[test]
+fn scoped_address_composes_with_socket_addrs() {
+    let address = scoped_address("fe80::1".to_string(), NetworkInterface::AtIndex(2));
+
+    assert_eq!(
+        discovery_at(&address, NetworkInterface::AtIndex(2))
+            .socket_addrs()
+            .unwrap(),
+        vec![SocketAddr::V6(SocketAddrV6::new(
+            "fe80::1".parse().unwrap(),
+            8080,
+            0,
+            2
+        ))]
+    );
+}
+
+#[test]
+fn service_discovery_to_socket_addrs_matches_socket_addrs() {
+    let discovery = discovery_at("fe80::1", NetworkInterface::AtIndex(2));
+
+    assert_eq!(
+        discovery.to_socket_addrs().unwrap().collect::<Vec<_>>(),
+        discovery.socket_addrs().unwrap()
+    );
+    assert_eq!(
+        discovery_at("", NetworkInterface::Unspec)
+            .to_socket_addrs()
+            .unwrap_err()
+            .kind(),
+        io::ErrorKind::InvalidInput
+    );
+}
+
+#[test]
+fn service_discovery_defaults_to_now_without_ttl() {
+    let before = SystemTime::now();
+    let discovery = discovery("foo", NetworkInterface::Unspec);
+
+    assert_eq!(*discovery.ttl(), None);
+    assert!(*discovery.discovered_at() >= before);
+    assert!(*discovery.discovered_at() <= SystemTime::now());
+}
+
+#[test]
+fn service_discovery_equality_ignores_ttl_and_discovery_time() {
+    let other = discovery_builder("foo", NetworkInterface::Unspec)
+        .ttl(Some(120))
+        .discovered_at(SystemTime::now() + Duration::from_secs(60))
+        .build()
+        .unwrap();
+
+    assert_eq!(discovery("foo", NetworkInterface::Unspec), other);
+}
+
+#[test]
+fn service_discovery_decodes_txt_bytes_on_access() {
+    let discovery = discovery_builder("foo", NetworkInterface::Unspec)
+        .txt_bytes(Some(b"\x05a=123\x01b".to_vec()))
+        .build()
+        .unwrap();
+
+    assert_eq!(discovery.txt_bytes(), Some(&b"\x05a=123\x01b"[..]));
+
+    let txt = discovery.txt().unwrap();
+
+    assert_eq!(txt.get("a"), Some("123".to_string()));
+    assert_eq!(txt.get_opt("b"), Some(None));
+}
+
+#[test]
+fn service_discovery_with_malformed_txt_bytes_has_no_txt() {
+    let discovery = discovery_builder("foo", NetworkInterface::Unspec)
+        .txt_bytes(Some(b"\x05a=1".to_vec()))
+        .build()
+        .unwrap();
+
+    assert_eq!(discovery.txt(), None);
+    assert_eq!(discovery.txt_bytes(), Some(&b"\x05a=1"[..]));
+}
+
+#[test]
+fn service_discovery_txt_is_stored_in_wire_format() {
+    let mut txt = TxtRecord::new();
+    txt.insert("a", "123").unwrap();
+
+    let discovery = discovery_builder("foo", NetworkInterface::Unspec)
+        .txt(Some(txt.clone()))
+        .build()
+        .unwrap();
+
+    assert_eq!(discovery.txt_bytes(), Some(&txt.to_bytes()[..]));
+    assert_eq!(discovery.txt(), Some(&txt));
+}
+
+#[test]
+#[cfg(feature = "serde")]
+fn service_discovery_serializes_ttl_and_discovery_time() {
+    let discovery = discovery_builder("foo", NetworkInterface::Unspec)
+        .ttl(Some(120))
+        .build()
+        .unwrap();
+
+    let json = serde_json::to_string(&discovery).unwrap();
+    let discovery_de = serde_json::from_str::<ServiceDiscovery>(&json).unwrap();
+
+    assert_eq!(discovery_de.ttl(), discovery.ttl());
+    assert_eq!(discovery_de.discovered_at(), discovery.discovered_at());
+}
+
+#[test]
+#[cfg(feature = "serde")]
+fn service_discovery_serializes_decoded_txt() {
+    let mut txt = TxtRecord::new();
+    txt.insert("a", "123").unwrap();
+
+    let discovery = discovery_builder("foo", NetworkInterface::Unspec)
+        .txt(Some(txt))
+        .build()
+        .unwrap();
+
+    let json = serde_json::to_string(&discovery).unwrap();
+
+    assert!(json.contains(r#""txt":{"a":"123"}"#));
+    assert_eq!(
+        serde_json::from_str::<ServiceDiscovery>(&json).unwrap(),
+        discovery
+    );
+}
+
+#[test]
+fn browser_settles_at_deadline_of_its_clock() {
+    super::setup();
+
+    let clock = Arc::new(MockClock::new());
+    let mut browser = MdnsBrowser::new(ServiceType::new("settle-clock", "tcp").unwrap());
+    let (tx, rx) = mpsc::channel();
+
+    browser.set_clock(clock.clone());
+    browser.on_settled(
+        Duration::from_secs(30),
+        Box::new(move |services| {
+            let _ = tx.send(services.len());
+        }),
+    );
+
+    let event_loop = browser.browse_services().unwrap();
+    let start = Instant::now();
+
+    // the quiescence period passes in real time, but not on the clock of the browser
+    while start.elapsed() < Duration::from_secs(1) {
+        event_loop.poll(Duration::from_millis(100)).unwrap();
+    }
+
+    assert!(rx.try_recv().is_err());
+
+    clock.advance(Duration::from_secs(30));
+    event_loop.poll(Duration::from_millis(100)).unwrap();
+
+    assert_eq!(rx.try_recv(), Ok(0));
+}
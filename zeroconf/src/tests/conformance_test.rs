@@ -0,0 +1,497 @@
+//! Scenarios every backend must pass. They only use the backends through [`TMdnsService`],
+//! [`TMdnsServiceGroup`] and [`TMdnsBrowser`], and are instantiated for each backend with
+//! [`conformance_suite!`].
+//!
+//! The scenarios that register or browse services live in a `network` module, so that
+//! environments without a working mDNS daemon can skip them with `--skip ::network::`.
+
+use crate::error::Error;
+use crate::prelude::*;
+use crate::service_name::MAX_NAME_LEN;
+use crate::txt_record::MAX_ENTRY_LEN;
+use crate::{
+    BrowserEvent, EventLoop, NetworkInterface, ServiceDiscovery, ServiceKey, ServiceType, TxtRecord,
+};
+use std::collections::HashMap;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Polls `event_loops` until `rx` receives a value.
+fn recv<T>(rx: &Receiver<T>, event_loops: &[&EventLoop]) -> T {
+    let start = Instant::now();
+
+    loop {
+        for event_loop in event_loops {
+            event_loop.poll(Duration::from_millis(10)).unwrap();
+        }
+
+        if let Ok(value) = rx.try_recv() {
+            return value;
+        }
+
+        assert!(
+            start.elapsed() < TIMEOUT,
+            "nothing received within {:?}",
+            TIMEOUT
+        );
+    }
+}
+
+/// Returns a service registered only on the local machine, so the tests do not advertise to the
+/// network.
+fn local_service<S: TMdnsService>(service_type: ServiceType, port: u16) -> S {
+    let mut service = S::new(service_type, port);
+    service.set_network_interface(NetworkInterface::LocalOnly);
+    service
+}
+
+/// Browses for `service_type` until the service registered under `name` is discovered.
+///
+/// The browser is returned along with its event loop, and the receiver of the services it
+/// reports as removed.
+fn discover<B: TMdnsBrowser>(
+    service_type: ServiceType,
+    name: &str,
+    event_loops: &[&EventLoop],
+) -> (ServiceDiscovery, B, EventLoop, Receiver<ServiceKey>) {
+    let (tx, rx) = mpsc::channel();
+    let (removed_tx, removed_rx) = mpsc::channel();
+    let expected = name.to_string();
+
+    let mut browser = B::new(service_type);
+
+    browser.set_on_discovered(move |result| {
+        let discovery = result.unwrap();
+
+        if *discovery.name() == expected {
+            let _ = tx.send(discovery);
+        }
+    });
+
+    browser.set_browser_event_callback(Box::new(move |event, _| {
+        if let BrowserEvent::ServiceRemoved(key) = event {
+            let _ = removed_tx.send(key);
+        }
+    }));
+
+    let browser_loop = browser.browse_services().unwrap();
+
+    let mut all_loops = event_loops.to_vec();
+    all_loops.push(&browser_loop);
+
+    let discovery = recv(&rx, &all_loops);
+
+    (discovery, browser, browser_loop, removed_rx)
+}
+
+fn service_name_round_trips<S: TMdnsService>() {
+    let mut service: S = local_service(ServiceType::new("http", "tcp").unwrap(), 8100);
+
+    assert_eq!(service.name(), None);
+
+    service.set_name("service_name_round_trips");
+
+    assert_eq!(service.name(), Some("service_name_round_trips"));
+}
+
+fn service_network_interface_round_trips<S: TMdnsService>() {
+    let mut service = S::new(ServiceType::new("http", "tcp").unwrap(), 8100);
+
+    assert_eq!(service.network_interface(), NetworkInterface::Unspec);
+
+    for interface in [
+        NetworkInterface::AtIndex(1),
+        NetworkInterface::LocalOnly,
+        NetworkInterface::Unspec,
+    ] {
+        service.set_network_interface(interface);
+        assert_eq!(service.network_interface(), interface);
+    }
+}
+
+fn service_txt_record_round_trips<S: TMdnsService>() {
+    let mut service: S = local_service(ServiceType::new("http", "tcp").unwrap(), 8100);
+    let txt = txt_record();
+
+    assert!(service.txt_record().is_none());
+
+    service.set_txt_record(txt.clone());
+
+    assert_eq!(service.txt_record().unwrap().to_map(), txt.to_map());
+}
+
+fn service_context_round_trips<S: TMdnsService>() {
+    let mut service: S = local_service(ServiceType::new("http", "tcp").unwrap(), 8100);
+
+    assert!(service.context().is_none());
+
+    service.set_context(Box::new(42u32));
+
+    assert_eq!(service.context().unwrap().downcast_ref::<u32>(), Some(&42));
+
+    service.set_context_typed(Arc::new("typed"));
+
+    assert_eq!(
+        service
+            .context()
+            .unwrap()
+            .downcast_ref::<Arc<&str>>()
+            .map(|context| **context),
+        Some("typed")
+    );
+}
+
+fn browser_network_interface_round_trips<B: TMdnsBrowser>() {
+    let mut browser = B::new(ServiceType::new("http", "tcp").unwrap());
+
+    assert_eq!(browser.network_interface(), NetworkInterface::Unspec);
+
+    for interface in [
+        NetworkInterface::AtIndex(1),
+        NetworkInterface::LocalOnly,
+        NetworkInterface::Unspec,
+    ] {
+        browser.set_network_interface(interface);
+        assert_eq!(browser.network_interface(), interface);
+    }
+}
+
+fn browser_context_round_trips<B: TMdnsBrowser>() {
+    let mut browser = B::new(ServiceType::new("http", "tcp").unwrap());
+
+    assert!(browser.context().is_none());
+
+    browser.set_context(Box::new(42u32));
+
+    assert_eq!(browser.context().unwrap().downcast_ref::<u32>(), Some(&42));
+}
+
+/// Returns a TXT record with an empty value, a key without a value, and an entry of the maximum
+/// length.
+fn service_group_rejects_removing_unknown_instance<G: TMdnsServiceGroup>() {
+    let mut group = G::new(ServiceType::new("http", "tcp").unwrap());
+    let mut other = G::new(ServiceType::new("http", "tcp").unwrap());
+
+    let a = group.add_instance("a", 8100, None).unwrap();
+    let b = group.add_instance("b", 8100, None).unwrap();
+    let foreign = other.add_instance("a", 8100, None).unwrap();
+
+    assert_eq!(group.instances(), vec![a, b]);
+    assert!(group.remove_instance(foreign).is_err());
+
+    group.remove_instance(a).unwrap();
+
+    assert_eq!(group.instances(), vec![b]);
+    assert!(group.remove_instance(a).is_err());
+}
+
+fn txt_record() -> TxtRecord {
+    let mut txt = TxtRecord::new();
+
+    txt.insert("empty", "").unwrap();
+    txt.insert_key_only("flag").unwrap();
+    txt.insert("long", &"a".repeat(MAX_ENTRY_LEN - "long=".len()))
+        .unwrap();
+
+    txt
+}
+
+fn registered_service_is_discovered_by_name<S: TMdnsService, B: TMdnsBrowser>() {
+    static SERVICE_NAME: &str = "registered_service_is_discovered_by_name";
+
+    let service_type = ServiceType::new("zcc-named", "tcp").unwrap();
+    let mut service: S = local_service(service_type.clone(), 8101);
+
+    service.set_name(SERVICE_NAME);
+
+    let (registration, service_loop) = service.register_blocking(TIMEOUT).unwrap();
+
+    assert_eq!(registration.name(), SERVICE_NAME);
+    assert_eq!(*registration.port(), 8101);
+
+    let (discovery, ..) = discover::<B>(service_type, SERVICE_NAME, &[&service_loop]);
+
+    assert_eq!(*discovery.port(), 8101);
+}
+
+fn unnamed_service_is_registered_under_default_name<S: TMdnsService, B: TMdnsBrowser>() {
+    let service_type = ServiceType::new("zcc-unnamed", "tcp").unwrap();
+    let mut service: S = local_service(service_type.clone(), 8102);
+
+    let (registration, service_loop) = service.register_blocking(TIMEOUT).unwrap();
+
+    assert!(!registration.name().is_empty());
+
+    discover::<B>(service_type, registration.name(), &[&service_loop]);
+}
+
+fn service_with_sub_type_is_discovered_by_sub_type<S: TMdnsService, B: TMdnsBrowser>() {
+    static SERVICE_NAME: &str = "service_with_sub_type_is_discovered_by_sub_type";
+
+    let service_type = ServiceType::with_sub_types("zcc-sub", "tcp", vec!["printer"]).unwrap();
+    let mut service: S = local_service(service_type.clone(), 8103);
+
+    service.set_name(SERVICE_NAME);
+
+    let (_, service_loop) = service.register_blocking(TIMEOUT).unwrap();
+
+    discover::<B>(service_type, SERVICE_NAME, &[&service_loop]);
+}
+
+fn txt_record_round_trips<S: TMdnsService, B: TMdnsBrowser>() {
+    static SERVICE_NAME: &str = "txt_record_round_trips";
+
+    let service_type = ServiceType::new("zcc-txt", "tcp").unwrap();
+    let mut service: S = local_service(service_type.clone(), 8104);
+    let txt = txt_record();
+
+    service.set_name(SERVICE_NAME);
+    service.set_txt_record(txt.clone());
+
+    let (_, service_loop) = service.register_blocking(TIMEOUT).unwrap();
+    let (discovery, ..) = discover::<B>(service_type, SERVICE_NAME, &[&service_loop]);
+    let discovered_txt = discovery.txt().unwrap();
+
+    assert_eq!(discovered_txt.to_map(), txt.to_map());
+    assert_eq!(discovered_txt.get_opt("empty"), Some(Some(String::new())));
+    assert_eq!(discovered_txt.get_opt("flag"), Some(None));
+    assert_eq!(
+        discovered_txt.get("long").map(|value| value.len()),
+        Some(MAX_ENTRY_LEN - "long=".len())
+    );
+}
+
+fn dropped_service_is_reported_removed<S: TMdnsService, B: TMdnsBrowser>() {
+    static SERVICE_NAME: &str = "dropped_service_is_reported_removed";
+
+    let service_type = ServiceType::new("zcc-removed", "tcp").unwrap();
+    let mut service: S = local_service(service_type.clone(), 8105);
+
+    service.set_name(SERVICE_NAME);
+
+    let (_, service_loop) = service.register_blocking(TIMEOUT).unwrap();
+    let (discovery, _browser, browser_loop, removed_rx) =
+        discover::<B>(service_type, SERVICE_NAME, &[&service_loop]);
+
+    drop(service_loop);
+    drop(service);
+
+    loop {
+        if recv(&removed_rx, &[&browser_loop]) == discovery.key() {
+            break;
+        }
+    }
+}
+
+fn invalid_deferred_change_is_reported_to_callback<S: TMdnsService>() {
+    static SERVICE_NAME: &str = "invalid_deferred_change_is_reported_to_callback";
+
+    let mut service: S = local_service(ServiceType::new("zcc-invalid", "tcp").unwrap(), 8106);
+    let (tx, rx) = mpsc::channel();
+
+    service.set_name(SERVICE_NAME);
+    service.set_on_registered(move |result| tx.send(result).unwrap());
+
+    let event_loop = service.register().unwrap();
+
+    assert_eq!(recv(&rx, &[&event_loop]).unwrap().name(), SERVICE_NAME);
+
+    service.defer(|service| service.set_name(&"a".repeat(MAX_NAME_LEN + 1)));
+
+    assert!(matches!(
+        recv(&rx, &[&event_loop]),
+        Err(Error::NameTooLong { bytes, max }) if bytes == MAX_NAME_LEN + 1 && max == MAX_NAME_LEN
+    ));
+    assert_eq!(service.name(), Some(SERVICE_NAME));
+}
+
+fn removing_instance_keeps_other_instances<G: TMdnsServiceGroup, B: TMdnsBrowser>() {
+    static NAMES: [&str; 3] = ["group_instance_a", "group_instance_b", "group_instance_c"];
+
+    let service_type = ServiceType::new("zcc-group", "tcp").unwrap();
+    let mut group = G::new(service_type.clone());
+    let (registered_tx, registered_rx) = mpsc::channel();
+
+    group.set_network_interface(NetworkInterface::LocalOnly);
+    group.set_on_registered(move |_, result| registered_tx.send(result.unwrap()).unwrap());
+
+    let handles = NAMES
+        .iter()
+        .map(|name| group.add_instance(name, 8107, None).unwrap())
+        .collect::<Vec<_>>();
+
+    let group_loop = group.register().unwrap();
+
+    for _ in NAMES {
+        recv(&registered_rx, &[&group_loop]);
+    }
+
+    let (discovered_tx, discovered_rx) = mpsc::channel();
+    let (removed_tx, removed_rx) = mpsc::channel();
+    let mut browser = B::new(service_type);
+
+    browser.set_on_discovered(move |result| {
+        let discovery = result.unwrap();
+
+        if NAMES.contains(&discovery.name().as_str()) {
+            let _ = discovered_tx.send(discovery);
+        }
+    });
+
+    browser.set_browser_event_callback(Box::new(move |event, _| {
+        if let BrowserEvent::ServiceRemoved(key) = event {
+            let _ = removed_tx.send(key);
+        }
+    }));
+
+    let browser_loop = browser.browse_services().unwrap();
+    let mut keys = HashMap::new();
+
+    while keys.len() < NAMES.len() {
+        let discovery = recv(&discovered_rx, &[&group_loop, &browser_loop]);
+        keys.insert(discovery.name().clone(), discovery.key());
+    }
+
+    group.remove_instance(handles[1]).unwrap();
+
+    assert_eq!(
+        recv(&removed_rx, &[&group_loop, &browser_loop]),
+        keys[NAMES[1]]
+    );
+
+    // the other instances must neither be withdrawn nor announced again
+    let start = Instant::now();
+
+    while start.elapsed() < Duration::from_secs(2) {
+        group_loop.poll(Duration::from_millis(10)).unwrap();
+        browser_loop.poll(Duration::from_millis(10)).unwrap();
+
+        assert!(removed_rx.try_recv().is_err());
+        assert!(discovered_rx.try_recv().is_err());
+    }
+
+    assert_eq!(group.instances(), vec![handles[0], handles[2]]);
+}
+
+/// Instantiates the conformance scenarios for the backend implemented by `$service`, `$group` and
+/// `$browser`, in a module named `$backend`.
+macro_rules! conformance_suite {
+    ($backend:ident, $service:ty, $group:ty, $browser:ty) => {
+        mod $backend {
+            #[test]
+            fn service_name_round_trips() {
+                super::service_name_round_trips::<$service>();
+            }
+
+            #[test]
+            fn service_network_interface_round_trips() {
+                super::service_network_interface_round_trips::<$service>();
+            }
+
+            #[test]
+            fn service_txt_record_round_trips() {
+                super::service_txt_record_round_trips::<$service>();
+            }
+
+            #[test]
+            fn service_context_round_trips() {
+                super::service_context_round_trips::<$service>();
+            }
+
+            #[test]
+            fn service_group_rejects_removing_unknown_instance() {
+                super::service_group_rejects_removing_unknown_instance::<$group>();
+            }
+
+            #[test]
+            fn browser_network_interface_round_trips() {
+                super::browser_network_interface_round_trips::<$browser>();
+            }
+
+            #[test]
+            fn browser_context_round_trips() {
+                super::browser_context_round_trips::<$browser>();
+            }
+
+            mod network {
+                #[test]
+                fn registered_service_is_discovered_by_name() {
+                    crate::tests::setup();
+                    super::super::registered_service_is_discovered_by_name::<$service, $browser>();
+                }
+
+                #[test]
+                fn unnamed_service_is_registered_under_default_name() {
+                    crate::tests::setup();
+                    super::super::unnamed_service_is_registered_under_default_name::<
+                        $service,
+                        $browser,
+                    >();
+                }
+
+                #[test]
+                fn service_with_sub_type_is_discovered_by_sub_type() {
+                    crate::tests::setup();
+                    super::super::service_with_sub_type_is_discovered_by_sub_type::<
+                        $service,
+                        $browser,
+                    >();
+                }
+
+                #[test]
+                fn txt_record_round_trips() {
+                    crate::tests::setup();
+                    super::super::txt_record_round_trips::<$service, $browser>();
+                }
+
+                #[test]
+                fn dropped_service_is_reported_removed() {
+                    crate::tests::setup();
+                    super::super::dropped_service_is_reported_removed::<$service, $browser>();
+                }
+
+                #[test]
+                fn invalid_deferred_change_is_reported_to_callback() {
+                    crate::tests::setup();
+                    super::super::invalid_deferred_change_is_reported_to_callback::<$service>();
+                }
+
+                #[test]
+                fn removing_instance_keeps_other_instances() {
+                    crate::tests::setup();
+                    super::super::removing_instance_keeps_other_instances::<$group, $browser>();
+                }
+            }
+        }
+    };
+}
+
+#[cfg(all(target_os = "linux", not(feature = "test-mock")))]
+conformance_suite!(
+    avahi,
+    crate::avahi::service::AvahiMdnsService,
+    crate::avahi::service_group::AvahiMdnsServiceGroup,
+    crate::avahi::browser::AvahiMdnsBrowser
+);
+
+#[cfg(all(
+    any(target_vendor = "apple", target_vendor = "pc"),
+    not(feature = "test-mock")
+))]
+conformance_suite!(
+    bonjour,
+    crate::bonjour::service::BonjourMdnsService,
+    crate::bonjour::service_group::BonjourMdnsServiceGroup,
+    crate::bonjour::browser::BonjourMdnsBrowser
+);
+
+#[cfg(feature = "test-mock")]
+conformance_suite!(
+    mock,
+    crate::mock::service::MockMdnsService,
+    crate::mock::service_group::MockMdnsServiceGroup,
+    crate::mock::browser::MockMdnsBrowser
+);
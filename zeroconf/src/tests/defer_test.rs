@@ -0,0 +1,89 @@
+use crate::prelude::*;
+use crate::{
+    MdnsBrowser, MdnsService, ServiceDiscovery, ServiceRegistration, ServiceType, TxtRecord,
+};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[test]
+fn service_register_is_browsable_after_deferred_update() {
+    super::setup();
+
+    const TOTAL_TEST_TIME: Duration = Duration::from_secs(20);
+    static DRAFT_NAME: &str = "service_deferred_draft";
+    static FINAL_NAME: &str = "service_deferred_final";
+
+    let mut service = MdnsService::new(ServiceType::new("http", "tcp").unwrap(), 8085);
+    let registrations: Arc<Mutex<Vec<ServiceRegistration>>> = Arc::default();
+    let sink = registrations.clone();
+    let deferrer = service.deferrer();
+
+    let mut txt = TxtRecord::new();
+    txt.insert("version", "1").unwrap();
+
+    service.set_name(DRAFT_NAME);
+    service.set_txt_record(txt);
+
+    service.set_registered_callback(Box::new(move |result, _| {
+        let registration = result.unwrap();
+
+        // inspect the registered name, then correct the name and TXT record once the callback
+        // has returned
+        if registration.name() == DRAFT_NAME {
+            deferrer.defer(|service| {
+                assert_eq!(service.name(), Some(DRAFT_NAME));
+
+                let mut txt = service.txt_record().unwrap().clone();
+                txt.insert("version", "2")?;
+
+                service.set_txt_record(txt)?;
+                service.set_name(FINAL_NAME)
+            });
+        }
+
+        sink.lock().unwrap().push(registration);
+    }));
+
+    let event_loop = service.register().unwrap();
+    let start = Instant::now();
+
+    while !registrations
+        .lock()
+        .unwrap()
+        .iter()
+        .any(|r| r.name() == FINAL_NAME)
+    {
+        assert!(start.elapsed() < TOTAL_TEST_TIME, "service was not renamed");
+        event_loop.poll(Duration::from_millis(100)).unwrap();
+    }
+
+    let discovered: Arc<Mutex<Option<ServiceDiscovery>>> = Arc::default();
+    let sink = discovered.clone();
+    let mut browser = MdnsBrowser::new(ServiceType::new("http", "tcp").unwrap());
+
+    browser.set_service_discovered_callback(Box::new(move |result, _| {
+        if let Ok(discovery) = result {
+            if discovery.name() == FINAL_NAME {
+                *sink.lock().unwrap() = Some(discovery);
+            }
+        }
+    }));
+
+    let browser_loop = browser.browse_services().unwrap();
+
+    while discovered.lock().unwrap().is_none() {
+        assert!(
+            start.elapsed() < TOTAL_TEST_TIME,
+            "service was not discovered"
+        );
+        event_loop.poll(Duration::from_secs(0)).unwrap();
+        browser_loop.poll(Duration::from_millis(100)).unwrap();
+    }
+
+    let discovery = discovered.lock().unwrap().take().unwrap();
+
+    assert_eq!(
+        discovery.txt().unwrap().get("version"),
+        Some("2".to_string())
+    );
+}
@@ -0,0 +1,118 @@
+use crate::error::Error;
+use crate::prelude::*;
+use crate::{MdnsBrowser, MdnsService, NetworkInterface, ServiceType};
+use std::sync::mpsc::{self, TryRecvError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+fn local_service(service_type: ServiceType, name: &str) -> MdnsService {
+    let mut service = MdnsService::new(service_type, 8080);
+    service.set_name(name);
+    service.set_network_interface(NetworkInterface::LocalOnly);
+    service
+}
+
+#[test]
+fn detached_service_stop_joins_poll_thread() {
+    super::setup();
+
+    let mut service = local_service(
+        ServiceType::new("detached-stop", "tcp").unwrap(),
+        "detached_service_stop",
+    );
+
+    let (tx, rx) = mpsc::channel();
+    service.set_on_registered(move |result| {
+        let _ = tx.send(result.map(|r| r.name().clone()));
+    });
+
+    let handle = service.register_detached().unwrap();
+
+    assert_eq!(
+        rx.recv_timeout(TIMEOUT).unwrap().unwrap(),
+        "detached_service_stop"
+    );
+    assert!(handle.is_running());
+
+    handle.stop().unwrap();
+
+    // the callback, and with it the sender, is dropped along with the service by the thread
+    assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+}
+
+#[test]
+fn detached_browser_stop_joins_poll_thread() {
+    super::setup();
+
+    let mut browser = MdnsBrowser::new(ServiceType::new("detached-stop", "tcp").unwrap());
+    let (tx, rx) = mpsc::channel();
+
+    browser.set_on_discovered(move |result| {
+        let _ = tx.send(result.is_ok());
+    });
+
+    let handle = browser.browse_detached().unwrap();
+
+    assert!(handle.is_running());
+
+    handle.stop().unwrap();
+
+    assert_eq!(rx.try_recv(), Err(TryRecvError::Disconnected));
+}
+
+#[test]
+fn detached_service_panic_in_deferred_closure_stops_only_its_handle() {
+    super::setup();
+
+    let service_type = ServiceType::new("detached-panic", "tcp").unwrap();
+    let mut panicking = local_service(service_type.clone(), "detached_panicking");
+    let mut other = local_service(service_type, "detached_other");
+
+    let deferrer = panicking.deferrer();
+    let (tx, rx) = mpsc::channel();
+    let other_tx = tx.clone();
+
+    panicking.set_on_registered(move |result| {
+        let _ = tx.send(result.map(|r| r.name().clone()));
+    });
+
+    other.set_on_registered(move |result| {
+        let _ = other_tx.send(result.map(|r| r.name().clone()));
+    });
+
+    let panicking_handle = panicking.register_detached().unwrap();
+    let other_handle = other.register_detached().unwrap();
+
+    for _ in 0..2 {
+        rx.recv_timeout(TIMEOUT).unwrap().unwrap();
+    }
+
+    deferrer.defer(|_| panic!("deferred closure panicked"));
+
+    let start = Instant::now();
+
+    while panicking_handle.is_running() {
+        assert!(
+            start.elapsed() < TIMEOUT,
+            "panicking handle did not stop within {:?}",
+            TIMEOUT
+        );
+
+        thread::sleep(Duration::from_millis(10));
+    }
+
+    assert!(other_handle.is_running());
+
+    assert_eq!(
+        panicking_handle.stop(),
+        Err(Error::CallbackPanicked {
+            message: "deferred closure panicked".to_string()
+        })
+    );
+
+    assert!(other_handle.is_running());
+
+    other_handle.stop().unwrap();
+}
@@ -1,5 +1,6 @@
 use crate::prelude::*;
-use crate::{MdnsService, ServiceType, TxtRecord};
+use crate::{MdnsBrowser, MdnsService, ServiceType, TxtRecord};
+use std::thread;
 use std::time::{Duration, Instant};
 
 const TEST_DURATION: Duration = Duration::from_secs(1);
@@ -10,6 +11,11 @@ const FAST_SPIN_MIN_ITERS: u32 = 10_000;
 const LONG_POLL_TIMEOUT: Duration = Duration::from_secs(1);
 const LONG_POLL_MAX_ITERS: u32 = 100;
 
+const IDLE_POLL_TIMEOUT: Duration = Duration::from_millis(200);
+const IDLE_POLL_MIN_DURATION: Duration = Duration::from_millis(150);
+
+const SHUTDOWN_MAX_LATENCY: Duration = Duration::from_millis(100);
+
 #[test]
 fn event_loop_spins_fast() {
     super::setup();
@@ -83,3 +89,48 @@ fn event_loop_long_polls() {
 
     assert!(LONG_POLL_MAX_ITERS > iterations);
 }
+
+#[test]
+fn event_loop_sleeps_when_idle() {
+    super::setup();
+
+    // nothing is registered under this type, so the browser has nothing to report once it has
+    // settled
+    let mut browser = MdnsBrowser::new(ServiceType::new("zeroconf-idle", "tcp").unwrap());
+    let event_loop = browser.browse_services().unwrap();
+
+    for _ in 0..5 {
+        event_loop.poll(Duration::from_millis(100)).unwrap();
+    }
+
+    let start = Instant::now();
+    event_loop.poll(IDLE_POLL_TIMEOUT).unwrap();
+
+    assert!(start.elapsed() >= IDLE_POLL_MIN_DURATION);
+}
+
+#[test]
+fn event_loop_shutdown_interrupts_poll() {
+    super::setup();
+
+    let mut browser = MdnsBrowser::new(ServiceType::new("zeroconf-idle", "tcp").unwrap());
+    let event_loop = browser.browse_services().unwrap();
+
+    for _ in 0..5 {
+        event_loop.poll(Duration::from_millis(100)).unwrap();
+    }
+
+    let handle = event_loop.shutdown_handle();
+
+    let shutdown = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        handle.shutdown();
+        Instant::now()
+    });
+
+    event_loop.poll(Duration::from_secs(10)).unwrap();
+    let requested = shutdown.join().unwrap();
+
+    assert!(requested.elapsed() < SHUTDOWN_MAX_LATENCY);
+    assert!(event_loop.shutdown_handle().is_shutdown());
+}
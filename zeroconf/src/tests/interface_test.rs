@@ -0,0 +1,41 @@
+use crate::NetworkInterface;
+
+#[test]
+fn by_name_rejects_unknown_interface() {
+    assert!(NetworkInterface::by_name("zeroconf-missing0").is_err());
+    assert!(NetworkInterface::by_name("lo\0").is_err());
+}
+
+#[cfg(any(target_os = "linux", target_vendor = "apple"))]
+#[test]
+fn loopback_resolves_to_valid_index() {
+    let interfaces = crate::interfaces().unwrap();
+
+    let loopback = interfaces
+        .iter()
+        .find(|i| *i.loopback())
+        .expect("should have found a loopback interface");
+
+    assert_ne!(*loopback.index(), 0);
+    assert_eq!(
+        NetworkInterface::by_name(loopback.name()).unwrap(),
+        loopback.interface()
+    );
+}
+
+#[cfg(target_vendor = "pc")]
+#[test]
+fn loopback_resolves_to_valid_index() {
+    assert!(matches!(
+        NetworkInterface::by_name("loopback_0"),
+        Ok(NetworkInterface::AtIndex(index)) if index != 0
+    ));
+}
+
+#[cfg(any(target_os = "linux", target_vendor = "apple"))]
+#[test]
+fn interfaces_are_ordered_by_index_without_duplicates() {
+    let interfaces = crate::interfaces().unwrap();
+
+    assert!(interfaces.windows(2).all(|w| w[0].index() < w[1].index()));
+}
@@ -0,0 +1,54 @@
+use crate::prelude::*;
+use crate::{MdnsBrowser, MdnsService, ServiceType};
+
+#[test]
+fn service_register_logs_id() {
+    super::setup();
+
+    let mut service = MdnsService::new(ServiceType::new("http", "tcp").unwrap(), 8080);
+    let other = MdnsService::new(ServiceType::new("http", "tcp").unwrap(), 8080);
+
+    assert_ne!(service.id(), other.id());
+
+    // registration fails without a running daemon, but is logged regardless
+    let event_loop = service.register();
+
+    assert!(super::logged(&format!(
+        "[{}] Registering service",
+        service.id()
+    )));
+
+    if let Ok(event_loop) = event_loop {
+        assert!(super::logged(&format!(
+            "[{}] Event loop: {}",
+            service.id(),
+            event_loop.id()
+        )));
+    }
+}
+
+#[test]
+fn browser_browse_services_logs_id() {
+    super::setup();
+
+    let mut browser = MdnsBrowser::new(ServiceType::new("http", "tcp").unwrap());
+    let other = MdnsBrowser::new(ServiceType::new("http", "tcp").unwrap());
+
+    assert_ne!(browser.id(), other.id());
+
+    // browsing fails without a running daemon, but is logged regardless
+    let event_loop = browser.browse_services();
+
+    assert!(super::logged(&format!(
+        "[{}] Browsing services",
+        browser.id()
+    )));
+
+    if let Ok(event_loop) = event_loop {
+        assert!(super::logged(&format!(
+            "[{}] Event loop: {}",
+            browser.id(),
+            event_loop.id()
+        )));
+    }
+}
@@ -0,0 +1,824 @@
+use crate::clock::MockClock;
+use crate::detached::POLL_THREAD_NAME;
+use crate::error::Error;
+use crate::mock::{self, registry};
+use crate::prelude::*;
+use crate::{
+    BackendKind, BrowserEvent, BrowserStats, CachedService, EventLoop, InterfaceChange,
+    MdnsBrowser, MdnsRecordBrowser, MdnsResolver, MdnsService, NetworkInterface, Reachability,
+    RecordEvent, ServiceState, ServiceType, TxtRecord, DEFAULT_GRACE_PERIOD,
+    DEFAULT_RESCAN_DEADLINE, RR_CLASS_IN,
+};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Polls `event_loops` until `rx` receives a value.
+fn recv<T>(rx: &Receiver<T>, event_loops: &[&EventLoop]) -> T {
+    let start = Instant::now();
+
+    loop {
+        for event_loop in event_loops {
+            event_loop.poll(Duration::from_millis(10)).unwrap();
+        }
+
+        if let Ok(value) = rx.try_recv() {
+            return value;
+        }
+
+        assert!(
+            start.elapsed() < TIMEOUT,
+            "nothing received within {:?}",
+            TIMEOUT
+        );
+    }
+}
+
+#[test]
+fn mock_browser_finds_registered_service_until_dropped() {
+    super::setup();
+
+    let service_type = ServiceType::new("mock-found", "tcp").unwrap();
+
+    let mut txt = TxtRecord::new();
+    txt.insert("foo", "bar").unwrap();
+
+    let mut service = MdnsService::new(service_type.clone(), 8080);
+    service.set_name("found");
+    service.set_txt_record(txt);
+
+    let (_, service_loop) = service.register_blocking(TIMEOUT).unwrap();
+
+    let (tx, rx) = mpsc::channel();
+    let (removed_tx, removed_rx) = mpsc::channel();
+
+    let mut browser = MdnsBrowser::new(service_type);
+    browser.set_on_discovered(move |result| tx.send(result).unwrap());
+    browser.set_browser_event_callback(Box::new(move |event, _| {
+        if let BrowserEvent::ServiceRemoved(key) = event {
+            removed_tx.send(key).unwrap();
+        }
+    }));
+
+    let browser_loop = browser.browse_services().unwrap();
+
+    let discovery = recv(&rx, &[&browser_loop]).unwrap();
+
+    assert_eq!(discovery.name(), "found");
+    assert_eq!(*discovery.port(), 8080);
+    assert_eq!(discovery.txt().unwrap().get("foo"), Some("bar".to_string()));
+
+    drop(service_loop);
+    drop(service);
+
+    assert_eq!(recv(&removed_rx, &[&browser_loop]).name(), "found");
+}
+
+#[test]
+fn mock_detached_browser_finds_detached_service() {
+    super::setup();
+
+    let service_type = ServiceType::new("mock-detached", "tcp").unwrap();
+
+    let mut service = MdnsService::new(service_type.clone(), 8100);
+    service.set_name("detached");
+
+    let service_handle = service.register_detached().unwrap();
+
+    let (tx, rx) = mpsc::channel();
+
+    let mut browser = MdnsBrowser::new(service_type);
+    browser.set_on_discovered(move |result| {
+        let thread = thread::current().name().map(str::to_string);
+        let _ = tx.send((result, thread));
+    });
+
+    let browser_handle = browser.browse_detached().unwrap();
+
+    let (discovery, thread) = rx.recv_timeout(TIMEOUT).unwrap();
+
+    assert_eq!(discovery.unwrap().name(), "detached");
+    assert_eq!(thread.as_deref(), Some(POLL_THREAD_NAME));
+    assert!(service_handle.is_running());
+    assert!(browser_handle.is_running());
+
+    let (stopped_tx, stopped_rx) = mpsc::channel();
+
+    thread::spawn(move || {
+        drop(browser_handle);
+        drop(service_handle);
+        stopped_tx.send(()).unwrap();
+    });
+
+    stopped_rx
+        .recv_timeout(TIMEOUT)
+        .expect("poll threads should have exited");
+}
+
+#[test]
+fn mock_local_only_browser_finds_services_on_every_interface() {
+    super::setup();
+
+    let service_type = ServiceType::new("mock-local", "tcp").unwrap();
+
+    let mut service = MdnsService::new(service_type.clone(), 8080);
+    service.set_name("local");
+    service.set_network_interface(NetworkInterface::AtIndex(7));
+
+    let (_, _service_loop) = service.register_blocking(TIMEOUT).unwrap();
+
+    let (tx, rx) = mpsc::channel();
+
+    let mut browser = MdnsBrowser::new(service_type);
+    browser.set_network_interface(NetworkInterface::LocalOnly);
+    browser.set_on_discovered(move |result| tx.send(result).unwrap());
+
+    let browser_loop = browser.browse_services().unwrap();
+
+    let discovery = recv(&rx, &[&browser_loop]).unwrap();
+
+    assert_eq!(discovery.name(), "local");
+    assert_eq!(*discovery.interface(), NetworkInterface::LocalOnly);
+}
+
+#[test]
+fn mock_browser_reports_updated_txt_record() {
+    super::setup();
+
+    let service_type = ServiceType::new("mock-txt", "tcp").unwrap();
+
+    let mut service = MdnsService::new(service_type.clone(), 8080);
+    service.set_name("updated");
+
+    let (_, service_loop) = service.register_blocking(TIMEOUT).unwrap();
+
+    let (tx, rx) = mpsc::channel();
+
+    let mut browser = MdnsBrowser::new(service_type);
+    browser.set_on_discovered(move |result| tx.send(result).unwrap());
+
+    let browser_loop = browser.browse_services().unwrap();
+
+    assert!(recv(&rx, &[&browser_loop])
+        .unwrap()
+        .txt()
+        .unwrap()
+        .is_empty());
+
+    service.defer(|service| {
+        let mut txt = TxtRecord::new();
+        txt.insert("version", "2")?;
+        service.set_txt_record(txt)
+    });
+
+    let discovery = recv(&rx, &[&service_loop, &browser_loop]).unwrap();
+
+    assert_eq!(
+        discovery.txt().unwrap().get("version"),
+        Some("2".to_string())
+    );
+}
+
+#[test]
+fn mock_browser_reports_empty_txt_record_as_empty() {
+    super::setup();
+
+    let service_type = ServiceType::new("mock-empty-txt", "tcp").unwrap();
+
+    let mut service = MdnsService::new(service_type.clone(), 8080);
+    service.set_name("empty");
+    service.set_txt_record(TxtRecord::new());
+
+    let (_, _service_loop) = service.register_blocking(TIMEOUT).unwrap();
+
+    let (tx, rx) = mpsc::channel();
+
+    let mut browser = MdnsBrowser::new(service_type);
+    browser.set_on_discovered(move |result| tx.send(result).unwrap());
+
+    let browser_loop = browser.browse_services().unwrap();
+
+    let discovery = recv(&rx, &[&browser_loop]).unwrap();
+
+    assert_eq!(discovery.txt(), Some(&TxtRecord::new()));
+    assert_eq!(discovery.txt_bytes(), Some(&[0][..]));
+}
+
+#[test]
+fn mock_browser_finds_services_in_configured_domain_only() {
+    super::setup();
+
+    let service_type = ServiceType::new("mock-domain", "tcp").unwrap();
+
+    let mut local = MdnsService::new(service_type.clone(), 8080);
+    local.set_name("local");
+
+    let mut wide_area = MdnsService::new(service_type.clone(), 8080);
+    wide_area.set_name("wide-area");
+    wide_area.set_domain("dns-sd.example.com");
+
+    let _local = local.register_blocking(TIMEOUT).unwrap();
+    let _wide_area = wide_area.register_blocking(TIMEOUT).unwrap();
+
+    let (tx, rx) = mpsc::channel();
+
+    let mut browser = MdnsBrowser::new(service_type);
+    browser.set_domain("dns-sd.example.com.");
+    browser.set_on_discovered(move |result| tx.send(result).unwrap());
+
+    let browser_loop = browser.browse_services().unwrap();
+
+    let discovery = recv(&rx, &[&browser_loop]).unwrap();
+
+    assert_eq!(discovery.name(), "wide-area");
+    assert_eq!(discovery.domain(), "dns-sd.example.com");
+
+    browser.rescan().unwrap();
+
+    assert_eq!(recv(&rx, &[&browser_loop]).unwrap().name(), "wide-area");
+    assert!(rx.try_recv().is_err());
+}
+
+#[test]
+fn mock_browser_reports_filtered_services_only() {
+    super::setup();
+
+    let service_type = ServiceType::new("mock-filter", "tcp").unwrap();
+
+    let services = [
+        ("gw-1", "gateway"),
+        ("gw-2", "sensor"),
+        ("printer", "gateway"),
+    ]
+    .iter()
+    .map(|(name, role)| {
+        let mut txt = TxtRecord::new();
+        txt.insert("role", role).unwrap();
+
+        let mut service = MdnsService::new(service_type.clone(), 8080);
+        service.set_name(name);
+        service.set_txt_record(txt);
+
+        let (_, service_loop) = service.register_blocking(TIMEOUT).unwrap();
+
+        (service, service_loop)
+    })
+    .collect::<Vec<_>>();
+
+    let (tx, rx) = mpsc::channel();
+    let (removed_tx, removed_rx) = mpsc::channel();
+
+    let mut browser = MdnsBrowser::new(service_type);
+    browser.set_name_filter(|name| name.starts_with("gw-"));
+    browser.set_discovery_filter(|discovery| {
+        discovery.txt().and_then(|txt| txt.get("role")).as_deref() == Some("gateway")
+    });
+    browser.set_on_discovered(move |result| tx.send(result).unwrap());
+    browser.set_browser_event_callback(Box::new(move |event, _| {
+        if let BrowserEvent::ServiceRemoved(key) = event {
+            removed_tx.send(key).unwrap();
+        }
+    }));
+
+    let browser_loop = browser.browse_services().unwrap();
+
+    assert_eq!(recv(&rx, &[&browser_loop]).unwrap().name(), "gw-1");
+
+    // the reported service is removed last, so that any other removal would be received first
+    for service in services.into_iter().rev() {
+        drop(service);
+    }
+
+    assert_eq!(recv(&removed_rx, &[&browser_loop]).name(), "gw-1");
+    assert!(rx.try_recv().is_err());
+    assert!(removed_rx.try_recv().is_err());
+}
+
+#[test]
+fn mock_sub_type_browser_follows_deferred_sub_types() {
+    super::setup();
+
+    let service_type = ServiceType::new("mock-sub", "tcp").unwrap();
+
+    let mut service = MdnsService::new(service_type, 8080);
+    service.set_name("scanner");
+
+    let (_, service_loop) = service.register_blocking(TIMEOUT).unwrap();
+
+    let (tx, rx) = mpsc::channel();
+    let (removed_tx, removed_rx) = mpsc::channel();
+
+    let mut browser =
+        MdnsBrowser::new(ServiceType::with_sub_types("mock-sub", "tcp", vec!["scan"]).unwrap());
+    browser.set_on_discovered(move |result| tx.send(result).unwrap());
+    browser.set_browser_event_callback(Box::new(move |event, _| {
+        if let BrowserEvent::ServiceRemoved(key) = event {
+            removed_tx.send(key).unwrap();
+        }
+    }));
+
+    let browser_loop = browser.browse_services().unwrap();
+
+    service.defer(|service| service.add_sub_type("scan"));
+
+    let discovery = recv(&rx, &[&service_loop, &browser_loop]).unwrap();
+
+    assert_eq!(discovery.name(), "scanner");
+
+    service.defer(|service| service.remove_sub_type("scan"));
+
+    assert_eq!(
+        recv(&removed_rx, &[&service_loop, &browser_loop]).name(),
+        "scanner"
+    );
+    assert!(rx.try_recv().is_err());
+}
+
+#[test]
+fn mock_service_registers_sub_types_added_before_registration() {
+    super::setup();
+
+    let mut service = MdnsService::new(ServiceType::new("mock-sub-new", "tcp").unwrap(), 8080);
+    service.set_name("printer");
+    service.add_sub_type("printer").unwrap();
+    service.add_sub_type("scan").unwrap();
+    service.remove_sub_type("scan").unwrap();
+
+    assert!(service.add_sub_type("foo.bar").is_err());
+
+    let (registration, _service_loop) = service.register_blocking(TIMEOUT).unwrap();
+
+    assert_eq!(
+        registration.service_type().sub_types(),
+        &vec!["printer".to_string()]
+    );
+}
+
+#[test]
+fn mock_service_renames_on_name_conflict() {
+    super::setup();
+
+    let service_type = ServiceType::new("mock-conflict", "tcp").unwrap();
+
+    let mut service = MdnsService::new(service_type.clone(), 8080);
+    let mut conflicting = MdnsService::new(service_type.clone(), 8081);
+    let mut rejected = MdnsService::new(service_type, 8082);
+
+    service.set_name("conflict");
+    conflicting.set_name("conflict");
+    rejected.set_name("conflict");
+    rejected.set_allow_name_conflicts(false);
+
+    let _registered = service.register_blocking(TIMEOUT).unwrap();
+    let (registration, _event_loop) = conflicting.register_blocking(TIMEOUT).unwrap();
+
+    assert_eq!(registration.name(), "conflict #2");
+    assert!(matches!(
+        rejected.register_blocking(TIMEOUT),
+        Err(Error::NameCollision { name }) if name == "conflict"
+    ));
+}
+
+#[test]
+fn mock_service_reports_rename_after_registration() {
+    super::setup();
+
+    let service_type = ServiceType::new("mock-renamed", "tcp").unwrap();
+
+    let mut service = MdnsService::new(service_type.clone(), 8080);
+    let mut renamed = MdnsService::new(service_type, 8081);
+
+    service.set_name("printer");
+    renamed.set_name("scanner");
+
+    let (tx, rx) = mpsc::channel();
+
+    renamed.set_name_changed_callback(Box::new(move |change, _| {
+        tx.send(change).unwrap();
+    }));
+
+    let _registered = service.register_blocking(TIMEOUT).unwrap();
+    let (_, renamed_loop) = renamed.register_blocking(TIMEOUT).unwrap();
+
+    assert!(rx.try_recv().is_err());
+
+    // taking the name of the other service makes the mock daemon rename it
+    renamed.defer(|service| service.set_name("printer"));
+
+    let change = recv(&rx, &[&renamed_loop]);
+
+    assert_eq!(change.previous(), "scanner");
+    assert_eq!(change.name(), "printer #2");
+    assert!(rx.try_recv().is_err());
+}
+
+#[test]
+fn mock_service_state_follows_registration() {
+    super::setup();
+
+    let service_type = ServiceType::new("mock-state", "tcp").unwrap();
+
+    let mut service = MdnsService::new(service_type.clone(), 8080);
+    let mut rejected = MdnsService::new(service_type, 8081);
+
+    service.set_name("state");
+    rejected.set_name("state");
+    rejected.set_allow_name_conflicts(false);
+
+    assert_eq!(service.state(), ServiceState::Unregistered);
+
+    let _registered = service.register_blocking(TIMEOUT).unwrap();
+
+    assert_eq!(service.state(), ServiceState::Registered);
+    assert!(rejected.register_blocking(TIMEOUT).is_err());
+    assert_eq!(rejected.state(), ServiceState::Collided);
+}
+
+#[test]
+fn mock_browser_stats_count_events() {
+    super::setup();
+
+    let service_type = ServiceType::new("mock-stats", "tcp").unwrap();
+
+    let mut service = MdnsService::new(service_type.clone(), 8080);
+    service.set_name("stats");
+
+    let (_, _service_loop) = service.register_blocking(TIMEOUT).unwrap();
+
+    let (tx, rx) = mpsc::channel();
+
+    let mut browser = MdnsBrowser::new(service_type);
+    browser.set_on_discovered(move |result| tx.send(result).unwrap());
+
+    assert_eq!(browser.stats(), BrowserStats::default());
+
+    let browser_loop = browser.browse_services().unwrap();
+
+    recv(&rx, &[&browser_loop]).unwrap();
+
+    let stats = browser.stats();
+
+    assert_eq!(*stats.events_received(), 1);
+    assert_eq!(*stats.active_resolvers(), 0);
+    assert_eq!(*stats.failures(), 0);
+    assert!(stats.last_event().is_some());
+}
+
+#[test]
+fn mock_browser_known_services_reflect_event_being_delivered() {
+    super::setup();
+
+    let service_type = ServiceType::new("mock-known", "tcp").unwrap();
+
+    let mut kept = MdnsService::new(service_type.clone(), 8080);
+    kept.set_name("kept");
+
+    let mut dropped = MdnsService::new(service_type.clone(), 8081);
+    dropped.set_name("dropped");
+
+    let (_, kept_loop) = kept.register_blocking(TIMEOUT).unwrap();
+    let (_, dropped_loop) = dropped.register_blocking(TIMEOUT).unwrap();
+
+    let slot: Arc<Mutex<Option<MdnsBrowser>>> = Arc::default();
+    let (tx, rx) = mpsc::channel();
+    let (removed_tx, removed_rx) = mpsc::channel();
+
+    let mut browser = MdnsBrowser::new(service_type);
+    let handle = slot.clone();
+
+    browser.set_on_discovered(move |result| {
+        let known = handle.lock().unwrap().as_ref().unwrap().known_services();
+        tx.send((result.unwrap(), known)).unwrap();
+    });
+
+    let handle = slot.clone();
+
+    browser.set_browser_event_callback(Box::new(move |event, _| {
+        if let BrowserEvent::ServiceRemoved(key) = event {
+            let known = handle.lock().unwrap().as_ref().unwrap().known_services();
+            removed_tx.send((key, known)).unwrap();
+        }
+    }));
+
+    let browser_loop = browser.browse_services().unwrap();
+    *slot.lock().unwrap() = Some(browser);
+
+    let known_names = |known: &[CachedService]| {
+        known
+            .iter()
+            .map(|s| s.discovery().name().clone())
+            .collect::<Vec<_>>()
+    };
+
+    // every added service is known by the time it is reported
+    for _ in 0..2 {
+        let (discovery, known) = recv(&rx, &[&browser_loop]);
+
+        assert!(known.iter().any(|s| s.discovery() == &discovery));
+    }
+
+    kept.defer(|service| {
+        let mut txt = TxtRecord::new();
+        txt.insert("version", "2")?;
+        service.set_txt_record(txt)
+    });
+
+    let (discovery, known) = recv(&rx, &[&kept_loop, &browser_loop]);
+    let cached = known.iter().find(|s| s.discovery().name() == "kept");
+
+    assert_eq!(
+        discovery.txt().unwrap().get("version"),
+        Some("2".to_string())
+    );
+    assert_eq!(cached.map(|s| s.discovery()), Some(&discovery));
+    assert_eq!(known_names(&known), ["dropped", "kept"]);
+
+    drop(dropped_loop);
+    drop(dropped);
+
+    let (key, known) = recv(&removed_rx, &[&browser_loop]);
+
+    assert_eq!(key.name(), "dropped");
+    assert_eq!(known_names(&known), ["kept"]);
+}
+
+#[test]
+fn mock_browser_rescan_removes_services_that_died_silently() {
+    super::setup();
+
+    let service_type = ServiceType::new("mock-rescan", "tcp").unwrap();
+
+    let mut alive = MdnsService::new(service_type.clone(), 8080);
+    alive.set_name("alive");
+
+    let mut vanished = MdnsService::new(service_type.clone(), 8081);
+    vanished.set_name("vanished");
+
+    let (_, _alive_loop) = alive.register_blocking(TIMEOUT).unwrap();
+    let (_, _vanished_loop) = vanished.register_blocking(TIMEOUT).unwrap();
+
+    let clock = Arc::new(MockClock::new());
+    let (tx, rx) = mpsc::channel();
+    let (removed_tx, removed_rx) = mpsc::channel();
+
+    let mut browser = MdnsBrowser::new(service_type);
+    browser.set_clock(clock.clone());
+    browser.set_on_discovered(move |result| tx.send(result.unwrap()).unwrap());
+    browser.set_browser_event_callback(Box::new(move |event, _| {
+        if let BrowserEvent::ServiceRemoved(key) = event {
+            removed_tx.send(key).unwrap();
+        }
+    }));
+
+    let browser_loop = browser.browse_services().unwrap();
+
+    recv(&rx, &[&browser_loop]);
+    recv(&rx, &[&browser_loop]);
+
+    registry::vanish(vanished.id());
+    browser.rescan().unwrap();
+
+    assert_eq!(recv(&rx, &[&browser_loop]).name(), "alive");
+
+    // the service is kept until the deadline, measured with the clock of the browser
+    browser_loop.poll(Duration::from_millis(10)).unwrap();
+    assert!(removed_rx.try_recv().is_err());
+
+    clock.advance(DEFAULT_RESCAN_DEADLINE);
+
+    assert_eq!(recv(&removed_rx, &[&browser_loop]).name(), "vanished");
+    assert_eq!(
+        browser
+            .known_services()
+            .iter()
+            .map(|s| s.discovery().name().as_str())
+            .collect::<Vec<_>>(),
+        ["alive"]
+    );
+    assert!(removed_rx.try_recv().is_err());
+}
+
+/// Returns the reachability of the services known to `browser`.
+fn reachabilities(browser: &MdnsBrowser) -> Vec<Reachability> {
+    browser
+        .known_services()
+        .iter()
+        .map(|s| *s.reachability())
+        .collect()
+}
+
+#[test]
+fn mock_browser_degrades_services_while_their_interface_is_down() {
+    super::setup();
+
+    // interface changes reach every browser, so no other test registers services on these
+    let interface = NetworkInterface::AtIndex(4242);
+    let other_interface = NetworkInterface::AtIndex(4243);
+
+    let service_type = ServiceType::new("mock-degraded", "tcp").unwrap();
+
+    let mut service = MdnsService::new(service_type.clone(), 8080);
+    service.set_name("degraded");
+    service.set_network_interface(interface);
+
+    let (_, _service_loop) = service.register_blocking(TIMEOUT).unwrap();
+
+    let (tx, rx) = mpsc::channel();
+    let (changed_tx, changed_rx) = mpsc::channel();
+
+    let mut browser = MdnsBrowser::new(service_type);
+    browser.set_on_discovered(move |result| tx.send(result.unwrap()).unwrap());
+    browser.set_browser_event_callback(Box::new(move |event, _| {
+        if let BrowserEvent::ServiceReachabilityChanged { key, reachability } = event {
+            changed_tx.send((key, reachability)).unwrap();
+        }
+    }));
+
+    let browser_loop = browser.browse_services().unwrap();
+
+    assert_eq!(*recv(&rx, &[&browser_loop]).interface(), interface);
+    assert_eq!(reachabilities(&browser), [Reachability::Reachable]);
+
+    mock::interface_changed(InterfaceChange::Down(other_interface));
+    browser_loop.poll(Duration::from_millis(10)).unwrap();
+
+    assert!(changed_rx.try_recv().is_err());
+
+    mock::interface_changed(InterfaceChange::Down(interface));
+
+    let (key, reachability) = recv(&changed_rx, &[&browser_loop]);
+
+    assert_eq!(key.name(), "degraded");
+    assert_eq!(reachability, Reachability::Degraded);
+    assert_eq!(reachabilities(&browser), [Reachability::Degraded]);
+
+    mock::interface_changed(InterfaceChange::Up(interface));
+
+    let (key, reachability) = recv(&changed_rx, &[&browser_loop]);
+
+    assert_eq!(key.name(), "degraded");
+    assert_eq!(reachability, Reachability::Reachable);
+    assert_eq!(reachabilities(&browser), [Reachability::Reachable]);
+}
+
+#[test]
+fn mock_browser_removes_services_degraded_for_grace_period() {
+    super::setup();
+
+    let interface = NetworkInterface::AtIndex(4244);
+    let service_type = ServiceType::new("mock-grace", "tcp").unwrap();
+
+    let mut service = MdnsService::new(service_type.clone(), 8080);
+    service.set_name("lost");
+    service.set_network_interface(interface);
+
+    let (_, _service_loop) = service.register_blocking(TIMEOUT).unwrap();
+
+    let clock = Arc::new(MockClock::new());
+    let (tx, rx) = mpsc::channel();
+    let (event_tx, event_rx) = mpsc::channel();
+
+    let mut browser = MdnsBrowser::new(service_type);
+
+    assert_eq!(browser.grace_period(), DEFAULT_GRACE_PERIOD);
+
+    browser.set_clock(clock.clone());
+    browser.set_grace_period(Duration::from_secs(10));
+    browser.set_on_discovered(move |result| tx.send(result.unwrap()).unwrap());
+    browser.set_browser_event_callback(Box::new(move |event, _| match event {
+        BrowserEvent::ServiceReachabilityChanged { .. } | BrowserEvent::ServiceRemoved(_) => {
+            event_tx.send(event).unwrap()
+        }
+        _ => {}
+    }));
+
+    assert_eq!(browser.grace_period(), Duration::from_secs(10));
+
+    let browser_loop = browser.browse_services().unwrap();
+
+    let key = recv(&rx, &[&browser_loop]).key();
+
+    mock::interface_changed(InterfaceChange::Down(interface));
+
+    assert_eq!(
+        recv(&event_rx, &[&browser_loop]),
+        BrowserEvent::ServiceReachabilityChanged {
+            key: key.clone(),
+            reachability: Reachability::Degraded,
+        }
+    );
+
+    // the service is kept until the grace period has elapsed on the clock of the browser
+    clock.advance(Duration::from_secs(9));
+    browser_loop.poll(Duration::from_millis(10)).unwrap();
+
+    assert!(event_rx.try_recv().is_err());
+    assert_eq!(browser.known_services().len(), 1);
+
+    clock.advance(Duration::from_secs(1));
+
+    assert_eq!(
+        recv(&event_rx, &[&browser_loop]),
+        BrowserEvent::ServiceRemoved(key)
+    );
+    assert!(browser.known_services().is_empty());
+}
+
+#[test]
+fn mock_resolver_resolves_registered_service() {
+    super::setup();
+
+    let service_type = ServiceType::new("mock-resolve", "tcp").unwrap();
+
+    let mut service = MdnsService::new(service_type.clone(), 8080);
+    service.set_name("resolved");
+
+    let _registered = service.register_blocking(TIMEOUT).unwrap();
+
+    let (tx, rx) = mpsc::channel();
+
+    let mut resolver = MdnsResolver::new();
+    resolver.set_service_resolved_callback(Box::new(move |result, _| tx.send(result).unwrap()));
+
+    let event_loop = resolver
+        .resolve("resolved", service_type.clone(), "local")
+        .unwrap();
+
+    let discovery = recv(&rx, &[&event_loop]).unwrap();
+
+    assert_eq!(discovery.host_name(), crate::mock::HOST_NAME);
+    assert_eq!(*discovery.port(), 8080);
+
+    let event_loop = resolver.resolve("missing", service_type, "local").unwrap();
+
+    assert!(recv(&rx, &[&event_loop]).is_err());
+}
+
+#[test]
+fn mock_record_browser_finds_txt_record() {
+    super::setup();
+
+    let service_type = ServiceType::new("mock-record", "tcp").unwrap();
+
+    let mut txt = TxtRecord::new();
+    txt.insert("foo", "bar").unwrap();
+
+    let mut service = MdnsService::new(service_type, 8080);
+    service.set_name("record");
+    service.set_txt_record(txt.clone());
+
+    let _registered = service.register_blocking(TIMEOUT).unwrap();
+
+    let (tx, rx) = mpsc::channel();
+
+    let mut browser = MdnsRecordBrowser::new();
+    browser.set_record_event_callback(Box::new(move |result, _| tx.send(result).unwrap()));
+
+    let event_loop = browser
+        .query_record("record._mock-record._tcp.local", 16, RR_CLASS_IN)
+        .unwrap();
+
+    match recv(&rx, &[&event_loop]).unwrap() {
+        RecordEvent::Add(record) => assert_eq!(*record.rdata(), txt.to_bytes()),
+        event => panic!("unexpected event: {:?}", event),
+    }
+}
+
+#[test]
+fn mock_daemon_is_available() {
+    assert!(crate::is_available());
+}
+
+#[test]
+fn mock_diagnosis_is_ok() {
+    let diagnosis = crate::diagnose();
+
+    assert!(diagnosis.is_ok());
+    assert_eq!(*diagnosis.socket_path(), None);
+}
+
+#[test]
+fn mock_backend_info_is_available_once_started() {
+    let service_type = ServiceType::new("mock-backend", "tcp").unwrap();
+    let mut service = MdnsService::new(service_type.clone(), 8080);
+    let mut browser = MdnsBrowser::new(service_type);
+
+    assert!(service.backend_info().is_err());
+    assert!(browser.backend_info().is_err());
+
+    service.set_registered_callback(Box::new(|_, _| {}));
+    browser.set_service_discovered_callback(Box::new(|_, _| {}));
+
+    let _service_loop = service.register().unwrap();
+    let _browser_loop = browser.browse_services().unwrap();
+
+    let info = crate::backend_info().unwrap();
+
+    assert_eq!(*info.kind(), BackendKind::Mock);
+    assert_eq!(info.version(), env!("CARGO_PKG_VERSION"));
+    assert_eq!(service.backend_info().unwrap(), info);
+    assert_eq!(browser.backend_info().unwrap(), info);
+}
@@ -1,10 +1,135 @@
-use std::sync::Once;
+use crate::prelude::*;
+use crate::{ServiceDiscovery, ServiceDiscoveryBuilder, ServiceKey, ServiceType};
+use log::{LevelFilter, Log, Metadata, Record};
+use std::sync::{Mutex, Once};
 
 static INIT: Once = Once::new();
+static CAPTURED: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Logger that records every message, so that tests can assert on the log output, before passing
+/// it on to `env_logger`.
+struct CapturingLogger {
+    inner: env_logger::Logger,
+}
+
+impl Log for CapturingLogger {
+    fn enabled(&self, _metadata: &Metadata) -> bool {
+        true
+    }
+
+    fn log(&self, record: &Record) {
+        CAPTURED
+            .lock()
+            .expect("should have been able to obtain lock on captured logs")
+            .push(record.args().to_string());
+
+        if self.inner.enabled(record.metadata()) {
+            self.inner.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Writer that records the events formatted by the `tracing` subscriber, including the fields of
+/// the spans they were emitted in.
+#[cfg(feature = "tracing")]
+struct CapturingWriter;
+
+#[cfg(feature = "tracing")]
+impl std::io::Write for CapturingWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        CAPTURED
+            .lock()
+            .expect("should have been able to obtain lock on captured logs")
+            .push(String::from_utf8_lossy(buf).into_owned());
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
 
 pub(crate) fn setup() {
-    INIT.call_once(env_logger::init);
+    INIT.call_once(|| {
+        let inner = env_logger::Builder::from_default_env().build();
+
+        log::set_boxed_logger(Box::new(CapturingLogger { inner }))
+            .expect("should have been able to set logger");
+
+        log::set_max_level(LevelFilter::Debug);
+
+        // once a subscriber is set, `tracing` no longer emits `log` records
+        #[cfg(feature = "tracing")]
+        tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::TRACE)
+            .with_ansi(false)
+            .without_time()
+            .with_writer(|| CapturingWriter)
+            .init();
+    });
+}
+
+/// Returns true if any message logged since `setup()` contains `pattern`.
+pub(crate) fn logged(pattern: &str) -> bool {
+    CAPTURED
+        .lock()
+        .expect("should have been able to obtain lock on captured logs")
+        .iter()
+        .any(|message| message.contains(pattern))
+}
+
+/// Returns the `_http._tcp` service type used by the discovery fixtures.
+pub(crate) fn service_type() -> ServiceType {
+    ServiceType::new("http", "tcp").unwrap()
+}
+
+/// Returns the key of the `_http._tcp` service named `name` in the `local` domain.
+pub(crate) fn service_key(name: &str) -> ServiceKey {
+    ServiceKey::new(name, &service_type(), "local")
+}
+
+/// Returns a builder for a discovery of the `_http._tcp` service named `name`, resolved to
+/// `foo.local` at `192.168.1.2:8080` without a TXT record. The remaining properties can be set
+/// before building it.
+pub(crate) fn discovery_builder(name: &str) -> ServiceDiscoveryBuilder {
+    let mut builder = ServiceDiscovery::builder();
+
+    builder
+        .name(name.to_string())
+        .service_type(service_type())
+        .domain("local".to_string())
+        .host_name("foo.local".to_string())
+        .address("192.168.1.2".to_string())
+        .port(8080)
+        .txt(None);
+
+    builder
+}
+
+/// Returns a discovery of the `_http._tcp` service named `name`, see [`discovery_builder()`].
+///
+/// [`discovery_builder()`]: fn.discovery_builder.html
+pub(crate) fn discovery(name: &str) -> ServiceDiscovery {
+    discovery_builder(name).build().unwrap()
 }
 
+mod browser_test;
+mod conformance_test;
+mod defer_test;
+mod detached_test;
 mod event_loop_test;
+mod interface_test;
+mod log_test;
+#[cfg(feature = "test-mock")]
+mod mock_test;
+mod record_test;
+mod resolver_test;
+mod send_test;
 mod service_test;
+#[cfg(feature = "tracing")]
+mod tracing_test;
@@ -0,0 +1,114 @@
+use crate::error::Error;
+use crate::prelude::*;
+use crate::{MdnsService, RecordSharing, ServiceRecord, ServiceRegistration, ServiceType};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const TOTAL_TEST_TIME: Duration = Duration::from_secs(10);
+const SETTLE_TIME: Duration = Duration::from_secs(2);
+
+static SHARED_RECORD_NAME: &str = "zeroconf-shared-record-test.local";
+
+type Registrations = Arc<Mutex<Vec<crate::Result<ServiceRegistration>>>>;
+
+fn shared_txt_record() -> ServiceRecord {
+    let txt = b"presence=1";
+    let mut rdata = vec![txt.len() as u8];
+    rdata.extend_from_slice(txt);
+
+    ServiceRecord::builder()
+        .name(Some(SHARED_RECORD_NAME.to_string()))
+        .rr_type(16)
+        .rdata(rdata)
+        .sharing(RecordSharing::Shared)
+        .build()
+        .unwrap()
+}
+
+fn service_with_shared_record(name: &str, port: u16, registrations: &Registrations) -> MdnsService {
+    let mut service = MdnsService::new(ServiceType::new("http", "tcp").unwrap(), port);
+
+    service.set_name(name);
+    service.add_record(shared_txt_record()).unwrap();
+    service.set_context(Box::new(registrations.clone()));
+
+    service.set_registered_callback(Box::new(|result, context| {
+        context
+            .as_ref()
+            .unwrap()
+            .downcast_ref::<Registrations>()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .push(result);
+    }));
+
+    service
+}
+
+#[test]
+fn shared_record_registered_by_two_services() {
+    super::setup();
+
+    static FIRST_NAME: &str = "shared_record_registered_by_two_services_1";
+    static SECOND_NAME: &str = "shared_record_registered_by_two_services_2";
+
+    let registrations: Registrations = Arc::default();
+
+    let mut first = service_with_shared_record(FIRST_NAME, 8082, &registrations);
+    let mut second = service_with_shared_record(SECOND_NAME, 8083, &registrations);
+
+    let first_loop = first.register().unwrap();
+    let second_loop = second.register().unwrap();
+
+    let start = Instant::now();
+    let mut registered_at = None;
+
+    loop {
+        first_loop.poll(Duration::from_millis(50)).unwrap();
+        second_loop.poll(Duration::from_millis(50)).unwrap();
+
+        let now = Instant::now();
+
+        if registered_at.is_none() && registrations.lock().unwrap().len() >= 2 {
+            registered_at = Some(now);
+        }
+
+        match registered_at {
+            // keep polling for a while to catch late conflicts
+            Some(at) if now - at >= SETTLE_TIME => break,
+            None if now - start >= TOTAL_TEST_TIME => panic!("test timed out"),
+            _ => {}
+        }
+    }
+
+    let registrations = registrations.lock().unwrap();
+    let mut names = registrations
+        .iter()
+        .map(|r| r.as_ref().unwrap().name().clone())
+        .collect::<Vec<_>>();
+
+    names.sort();
+
+    assert_eq!(names, vec![FIRST_NAME, SECOND_NAME]);
+}
+
+#[test]
+fn shared_srv_record_at_service_name_is_unsupported() {
+    super::setup();
+
+    let mut service = MdnsService::new(ServiceType::new("http", "tcp").unwrap(), 8080);
+
+    let record = ServiceRecord::builder()
+        .rr_type(33)
+        .rdata(vec![0; 7])
+        .sharing(RecordSharing::Shared)
+        .build()
+        .unwrap();
+
+    assert!(matches!(
+        service.add_record(record),
+        Err(Error::Unsupported(_))
+    ));
+    assert!(service.records().is_empty());
+}
@@ -0,0 +1,125 @@
+use crate::prelude::*;
+use crate::{MdnsResolver, MdnsService, ServiceDiscovery, ServiceType};
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Default, Debug)]
+struct Context {
+    resolved: Option<ServiceDiscovery>,
+    timed_out: bool,
+}
+
+const TOTAL_TEST_TIME_S: u64 = 30;
+
+#[test]
+fn service_register_is_resolvable() {
+    super::setup();
+
+    static SERVICE_NAME: &str = "service_register_is_resolvable";
+
+    let mut service = MdnsService::new(ServiceType::new("http", "tcp").unwrap(), 8081);
+
+    service.set_name(SERVICE_NAME);
+
+    let resolved = register_and_resolve(service).expect("service was not resolved");
+
+    assert_eq!(resolved.name(), SERVICE_NAME);
+    assert_eq!(*resolved.port(), 8081);
+}
+
+#[test]
+fn proxied_service_resolves_to_proxied_address() {
+    super::setup();
+
+    static SERVICE_NAME: &str = "proxied_service_resolves_to_proxied_address";
+    static HOST_NAME: &str = "zeroconf-proxy-test.local";
+
+    // documentation address range (RFC 5737), so it can never be the local machine's
+    let address = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 42));
+    let mut service = MdnsService::new(ServiceType::new("http", "tcp").unwrap(), 8082);
+
+    service.set_name(SERVICE_NAME);
+    service.set_host(HOST_NAME);
+    service.set_address(address);
+
+    let resolved = register_and_resolve(service).expect("service was not resolved");
+
+    assert_eq!(resolved.name(), SERVICE_NAME);
+    assert_eq!(resolved.host_name(), HOST_NAME);
+    assert_eq!(resolved.address(), &address.to_string());
+}
+
+fn register_and_resolve(mut service: MdnsService) -> Option<ServiceDiscovery> {
+    let context: Arc<Mutex<Context>> = Arc::default();
+
+    service.set_context(Box::new(context.clone()));
+
+    service.set_registered_callback(Box::new(|result, context| {
+        let registration = result.unwrap();
+        let mut resolver = MdnsResolver::new();
+
+        let context = context
+            .as_ref()
+            .unwrap()
+            .downcast_ref::<Arc<Mutex<Context>>>()
+            .unwrap()
+            .clone();
+
+        resolver.set_context(Box::new(context.clone()));
+
+        resolver.set_service_resolved_callback(Box::new(|service, context| {
+            context
+                .as_ref()
+                .unwrap()
+                .downcast_ref::<Arc<Mutex<Context>>>()
+                .unwrap()
+                .lock()
+                .unwrap()
+                .resolved = Some(service.unwrap());
+        }));
+
+        let event_loop = resolver
+            .resolve(
+                registration.name(),
+                registration.service_type().clone(),
+                registration.domain(),
+            )
+            .unwrap();
+
+        let resolve_start = std::time::Instant::now();
+
+        loop {
+            event_loop.poll(Duration::from_secs(0)).unwrap();
+
+            if context.lock().unwrap().resolved.is_some() {
+                break;
+            }
+
+            if resolve_start.elapsed().as_secs() > TOTAL_TEST_TIME_S {
+                context.lock().unwrap().timed_out = true;
+                break;
+            }
+        }
+    }));
+
+    let event_loop = service.register().unwrap();
+    let publish_start = std::time::Instant::now();
+
+    loop {
+        event_loop.poll(Duration::from_secs(0)).unwrap();
+
+        let mtx = context.lock().unwrap();
+
+        if mtx.resolved.is_some() || mtx.timed_out {
+            break;
+        }
+
+        if publish_start.elapsed().as_secs() > TOTAL_TEST_TIME_S * 2 {
+            break;
+        }
+    }
+
+    let resolved = context.lock().unwrap().resolved.take();
+    resolved
+}
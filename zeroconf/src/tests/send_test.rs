@@ -0,0 +1,39 @@
+use crate::prelude::*;
+use crate::{
+    EventLoop, MdnsBrowser, MdnsDomainBrowser, MdnsHostResolver, MdnsRecordBrowser, MdnsResolver,
+    MdnsService, ServiceType, TxtRecord,
+};
+use std::thread;
+
+fn assert_send<T: Send>() {}
+
+#[test]
+fn public_types_are_send() {
+    assert_send::<MdnsService>();
+    assert_send::<MdnsBrowser>();
+    assert_send::<MdnsResolver>();
+    assert_send::<MdnsDomainBrowser>();
+    assert_send::<MdnsHostResolver>();
+    assert_send::<MdnsRecordBrowser>();
+    assert_send::<EventLoop>();
+    assert_send::<TxtRecord>();
+}
+
+#[test]
+fn service_is_configurable_from_worker_thread() {
+    let mut service = MdnsService::new(ServiceType::new("http", "tcp").unwrap(), 8080);
+
+    service.set_context(Box::new(1u32));
+    service.set_registered_callback(Box::new(|_, context| {
+        assert_eq!(context.unwrap().downcast_ref::<u32>(), Some(&1));
+    }));
+
+    let service = thread::spawn(move || {
+        service.set_name("service_is_configurable_from_worker_thread");
+        service
+    })
+    .join()
+    .unwrap();
+
+    assert_eq!(service.context().unwrap().downcast_ref::<u32>(), Some(&1));
+}
@@ -1,7 +1,12 @@
+use crate::error::Error;
 use crate::prelude::*;
-use crate::{MdnsBrowser, MdnsService, ServiceType, TxtRecord};
-use std::sync::{Arc, Mutex};
-use std::time::Duration;
+use crate::service_name::{self, MAX_NAME_LEN};
+use crate::{
+    MdnsBrowser, MdnsService, NetworkInterface, Protocol, RegistrationFlags, ServiceRegistration,
+    ServiceRegistrationBuilder, ServiceType, TxtRecord,
+};
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::{Duration, Instant};
 
 #[derive(Default, Debug)]
 struct Context {
@@ -10,6 +15,14 @@ struct Context {
     txt: Option<TxtRecord>,
 }
 
+/// Returns a service registered only on the local machine, so the tests do not advertise to the
+/// network.
+fn local_service(service_type: ServiceType, port: u16) -> MdnsService {
+    let mut service = MdnsService::new(service_type, port);
+    service.set_network_interface(NetworkInterface::LocalOnly);
+    service
+}
+
 #[test]
 fn service_register_is_browsable() {
     super::setup();
@@ -17,7 +30,7 @@ fn service_register_is_browsable() {
     const TOTAL_TEST_TIME_S: u64 = 30;
     static SERVICE_NAME: &str = "service_register_is_browsable";
 
-    let mut service = MdnsService::new(
+    let mut service = local_service(
         ServiceType::with_sub_types("http", "tcp", vec!["printer"]).unwrap(),
         8080,
     );
@@ -26,6 +39,7 @@ fn service_register_is_browsable() {
 
     let mut txt = TxtRecord::new();
     txt.insert("foo", "bar").unwrap();
+    txt.insert_key_only("flag").unwrap();
 
     service.set_name(SERVICE_NAME);
     service.set_context(Box::new(context.clone()));
@@ -58,7 +72,7 @@ fn service_register_is_browsable() {
                     .lock()
                     .unwrap();
 
-                mtx.txt.clone_from(service.txt());
+                mtx.txt = service.txt().cloned();
                 mtx.is_discovered = true;
 
                 debug!("Service discovered");
@@ -91,7 +105,9 @@ fn service_register_is_browsable() {
         let mut mtx = context.lock().unwrap();
 
         if mtx.is_discovered {
-            assert_eq!(txt, mtx.txt.take().unwrap());
+            let discovered_txt = mtx.txt.take().unwrap();
+            assert_eq!(txt.to_map(), discovered_txt.to_map());
+            assert_eq!(discovered_txt.get_opt("FLAG"), Some(None));
             break;
         }
 
@@ -103,3 +119,891 @@ fn service_register_is_browsable() {
 
     assert!(!context.lock().unwrap().timed_out);
 }
+
+#[test]
+fn service_register_is_browsable_with_closures() {
+    super::setup();
+
+    const TOTAL_TEST_TIME_S: u64 = 30;
+    static SERVICE_NAME: &str = "service_register_is_browsable_with_closures";
+
+    let service_type = ServiceType::new("http", "tcp").unwrap();
+    let mut service = local_service(service_type.clone(), 8083);
+    let (registered_tx, registered_rx) = mpsc::channel();
+
+    service.set_name(SERVICE_NAME);
+    service.set_on_registered(move |result| {
+        let _ = registered_tx.send(result.map(|r| r.name().clone()));
+    });
+
+    let mut browser = MdnsBrowser::new(service_type);
+    let (discovered_tx, discovered_rx) = mpsc::channel();
+    let mut discovered = 0;
+
+    browser.set_on_discovered(move |result| {
+        let service = result.unwrap();
+
+        discovered += 1;
+
+        if service.name() == SERVICE_NAME {
+            let _ = discovered_tx.send((discovered, *service.port()));
+        }
+    });
+
+    let service_loop = service.register().unwrap();
+    let browser_loop = browser.browse_services().unwrap();
+    let start = Instant::now();
+
+    let (discovered, port) = loop {
+        service_loop.poll(Duration::from_secs(0)).unwrap();
+        browser_loop.poll(Duration::from_secs(0)).unwrap();
+
+        if let Ok(result) = discovered_rx.try_recv() {
+            break result;
+        }
+
+        assert!(
+            start.elapsed().as_secs() < TOTAL_TEST_TIME_S,
+            "service was not discovered"
+        );
+    };
+
+    assert!(discovered >= 1);
+    assert_eq!(port, 8083);
+    assert_eq!(registered_rx.try_recv().unwrap().unwrap(), SERVICE_NAME);
+}
+
+#[test]
+fn service_register_is_browsable_with_multi_byte_name_and_txt() {
+    super::setup();
+
+    const TOTAL_TEST_TIME_S: u64 = 30;
+    static SERVICE_NAME: &str = "Büro-Drucker 🖨";
+
+    let service_type = ServiceType::new("http", "tcp").unwrap();
+    let mut service = local_service(service_type.clone(), 8099);
+
+    let mut txt = TxtRecord::new();
+    txt.insert("room", "Raum für Gäste 🛋").unwrap();
+
+    service.set_name(SERVICE_NAME);
+    service.set_txt_record(txt.clone());
+
+    let mut browser = MdnsBrowser::new(service_type);
+    let (discovered_tx, discovered_rx) = mpsc::channel();
+
+    browser.set_on_discovered(move |result| {
+        let service = result.unwrap();
+
+        if *service.port() == 8099 {
+            let _ = discovered_tx.send(service);
+        }
+    });
+
+    let (registration, service_loop) = service
+        .register_blocking(Duration::from_secs(TOTAL_TEST_TIME_S))
+        .unwrap();
+
+    assert_eq!(registration.name().as_bytes(), SERVICE_NAME.as_bytes());
+
+    let browser_loop = browser.browse_services().unwrap();
+    let start = Instant::now();
+
+    let discovery = loop {
+        service_loop.poll(Duration::from_secs(0)).unwrap();
+        browser_loop.poll(Duration::from_millis(10)).unwrap();
+
+        if let Ok(discovery) = discovered_rx.try_recv() {
+            break discovery;
+        }
+
+        assert!(
+            start.elapsed().as_secs() < TOTAL_TEST_TIME_S,
+            "service was not discovered"
+        );
+    };
+
+    assert_eq!(discovery.name().as_bytes(), SERVICE_NAME.as_bytes());
+    assert_eq!(
+        discovery.txt().unwrap().get_bytes("room"),
+        txt.get_bytes("room")
+    );
+}
+
+#[test]
+fn service_register_rejects_long_name() {
+    super::setup();
+
+    let name = "a".repeat(MAX_NAME_LEN + 1);
+    let mut service = local_service(ServiceType::new("http", "tcp").unwrap(), 8080);
+
+    service.set_name(&name);
+
+    assert!(!service.name_fitting());
+    assert!(matches!(
+        service.register(),
+        Err(Error::NameTooLong { bytes, max }) if bytes == MAX_NAME_LEN + 1 && max == MAX_NAME_LEN
+    ));
+    assert_eq!(service.name(), Some(name.as_str()));
+}
+
+#[test]
+fn service_set_port_replaces_port() {
+    let mut service = local_service(ServiceType::new("http", "tcp").unwrap(), 8080);
+
+    service.set_port(8081);
+
+    assert_eq!(service.port(), 8081);
+}
+
+#[test]
+fn service_registered_callback_is_invoked_promptly() {
+    super::setup();
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    let mut service = local_service(ServiceType::new("http", "tcp").unwrap(), 8085);
+    let (tx, rx) = mpsc::channel();
+
+    service.set_name("service_registered_callback_is_invoked_promptly");
+    service.set_on_registered(move |result| tx.send(result).unwrap());
+
+    let event_loop = service.register().unwrap();
+    let start = Instant::now();
+
+    let registration = loop {
+        event_loop.poll(Duration::from_millis(100)).unwrap();
+
+        if let Ok(result) = rx.try_recv() {
+            break result.unwrap();
+        }
+
+        assert!(
+            start.elapsed() < TIMEOUT,
+            "registered callback was not invoked within {:?}",
+            TIMEOUT
+        );
+    };
+
+    assert_eq!(
+        registration.name(),
+        "service_registered_callback_is_invoked_promptly"
+    );
+}
+
+#[test]
+fn service_dropped_from_registered_callback_keeps_polling() {
+    super::setup();
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    let slot: Arc<Mutex<Option<MdnsService>>> = Arc::default();
+    let handle = slot.clone();
+    let (tx, rx) = mpsc::channel();
+
+    let mut service = local_service(ServiceType::new("http", "tcp").unwrap(), 8089);
+
+    service.set_name("service_dropped_from_registered_callback_keeps_polling");
+    service.set_on_registered(move |result| {
+        drop(handle.lock().unwrap().take());
+        tx.send(result).unwrap();
+    });
+
+    let event_loop = service.register().unwrap();
+    *slot.lock().unwrap() = Some(service);
+
+    let start = Instant::now();
+
+    loop {
+        event_loop.poll(Duration::from_millis(100)).unwrap();
+
+        if let Ok(result) = rx.try_recv() {
+            assert!(result.is_ok());
+            break;
+        }
+
+        assert!(
+            start.elapsed() < TIMEOUT,
+            "registered callback was not invoked within {:?}",
+            TIMEOUT
+        );
+    }
+
+    assert!(slot.lock().unwrap().is_none());
+
+    for _ in 0..5 {
+        event_loop.poll(Duration::from_millis(10)).unwrap();
+    }
+}
+
+#[test]
+fn service_register_blocking_returns_registration() {
+    super::setup();
+
+    let mut service = local_service(ServiceType::new("http", "tcp").unwrap(), 8086);
+
+    service.set_name("service_register_blocking_returns_registration");
+
+    let (registration, _event_loop) = service.register_blocking(Duration::from_secs(5)).unwrap();
+
+    assert_eq!(
+        registration.name(),
+        "service_register_blocking_returns_registration"
+    );
+    assert_eq!(*registration.port(), 8086);
+}
+
+#[test]
+fn service_register_twice_restarts_registration() {
+    super::setup();
+
+    static SERVICE_NAME: &str = "service_register_twice_restarts_registration";
+
+    let mut service = local_service(ServiceType::new("http", "tcp").unwrap(), 8087);
+
+    service.set_name(SERVICE_NAME);
+    service.set_allow_name_conflicts(false);
+
+    let (first, _) = service.register_blocking(Duration::from_secs(5)).unwrap();
+
+    // the first registration is withdrawn, so the second does not collide with it
+    let (second, _event_loop) = service.register_blocking(Duration::from_secs(5)).unwrap();
+
+    assert_eq!(first.name(), SERVICE_NAME);
+    assert_eq!(second.name(), SERVICE_NAME);
+}
+
+#[test]
+fn service_register_blocking_reports_name_collision() {
+    super::setup();
+
+    static SERVICE_NAME: &str = "service_register_blocking_reports_name_collision";
+
+    let mut service = local_service(ServiceType::new("http", "tcp").unwrap(), 8087);
+    let mut conflicting = local_service(ServiceType::new("http", "tcp").unwrap(), 8088);
+
+    service.set_name(SERVICE_NAME);
+    conflicting.set_name(SERVICE_NAME);
+    conflicting.set_allow_name_conflicts(false);
+
+    let _registered = service.register_blocking(Duration::from_secs(5)).unwrap();
+
+    assert!(matches!(
+        conflicting.register_blocking(Duration::from_secs(5)),
+        Err(Error::NameCollision { name }) if name == SERVICE_NAME
+    ));
+}
+
+#[test]
+fn service_register_blocking_renames_on_name_collision() {
+    super::setup();
+
+    static SERVICE_NAME: &str = "service_register_blocking_renames_on_name_collision";
+
+    let mut event_loops = vec![];
+    let mut names = vec![];
+
+    for port in 8095..8098 {
+        let mut service = local_service(ServiceType::new("http", "tcp").unwrap(), port);
+
+        service.set_name(SERVICE_NAME);
+
+        let (registration, event_loop) = service.register_blocking(Duration::from_secs(5)).unwrap();
+
+        names.push(registration.name().clone());
+        event_loops.push((service, event_loop));
+    }
+
+    assert_eq!(names[0], SERVICE_NAME);
+    assert_ne!(names[1], names[0]);
+    assert_ne!(names[2], names[0]);
+    assert_ne!(names[2], names[1]);
+}
+
+#[test]
+fn service_register_on_port_zero_registers_placeholder() {
+    super::setup();
+
+    let mut service = local_service(ServiceType::new("http", "tcp").unwrap(), 0);
+
+    service.set_name("service_register_on_port_zero_registers_placeholder");
+
+    #[cfg(all(target_os = "linux", not(feature = "test-mock")))]
+    assert!(matches!(service.register(), Err(Error::Unsupported(_))));
+
+    #[cfg(any(not(target_os = "linux"), feature = "test-mock"))]
+    {
+        let (tx, rx) = mpsc::channel();
+
+        service.set_on_registered(move |result| tx.send(result).unwrap());
+
+        let event_loop = service.register().unwrap();
+        let start = Instant::now();
+
+        let registration = loop {
+            event_loop.poll(Duration::from_millis(100)).unwrap();
+
+            if let Ok(result) = rx.try_recv() {
+                break result.unwrap();
+            }
+
+            assert!(start.elapsed() < Duration::from_secs(10));
+        };
+
+        assert_eq!(*registration.port(), 0);
+    }
+}
+
+#[test]
+fn service_register_fits_long_name() {
+    super::setup();
+
+    const TOTAL_TEST_TIME: Duration = Duration::from_secs(10);
+
+    let name = format!("service_register_fits_long_name_{}", "漢".repeat(20));
+    let mut service = local_service(ServiceType::new("http", "tcp").unwrap(), 8084);
+    let registration: Arc<Mutex<Option<ServiceRegistration>>> = Arc::default();
+
+    service.set_name(&name);
+    service.set_name_fitting(true);
+    service.set_context(Box::new(registration.clone()));
+
+    service.set_registered_callback(Box::new(|result, context| {
+        *context
+            .as_ref()
+            .unwrap()
+            .downcast_ref::<Arc<Mutex<Option<ServiceRegistration>>>>()
+            .unwrap()
+            .lock()
+            .unwrap() = Some(result.unwrap());
+    }));
+
+    let event_loop = service.register().unwrap();
+    let start = Instant::now();
+
+    while registration.lock().unwrap().is_none() {
+        assert!(start.elapsed() < TOTAL_TEST_TIME, "test timed out");
+        event_loop.poll(Duration::from_millis(50)).unwrap();
+    }
+
+    let registration = registration.lock().unwrap().take().unwrap();
+
+    assert_eq!(registration.name(), &service_name::fit(&name));
+    assert!(registration.name_was_adjusted());
+    assert_eq!(*registration.port(), 8084);
+    assert_eq!(*registration.host(), None);
+    assert_eq!(*registration.txt(), None);
+}
+
+#[test]
+fn service_registration_defaults_new_fields() {
+    let registration = ServiceRegistration::builder()
+        .name("foo".to_string())
+        .service_type(ServiceType::new("http", "tcp").unwrap())
+        .domain("local".to_string())
+        .build()
+        .unwrap();
+
+    assert_eq!(*registration.port(), 0);
+    assert_eq!(*registration.host(), None);
+    assert_eq!(*registration.txt(), None);
+}
+
+#[test]
+fn service_registration_builder_accepts_str() {
+    let registration = ServiceRegistrationBuilder::default()
+        .name("foo")
+        .service_type(ServiceType::new("http", "tcp").unwrap())
+        .domain("local")
+        .port(8080)
+        .build()
+        .unwrap();
+
+    assert_eq!(registration.name(), "foo");
+    assert_eq!(registration.domain(), "local");
+    assert_eq!(*registration.port(), 8080);
+}
+
+#[test]
+#[cfg(feature = "serde")]
+fn service_registration_serializes_new_fields() {
+    let mut txt = TxtRecord::new();
+    txt.insert("foo", "bar").unwrap();
+
+    let registration = ServiceRegistration::builder()
+        .name("foo".to_string())
+        .service_type(ServiceType::new("http", "tcp").unwrap())
+        .domain("local".to_string())
+        .port(8080)
+        .host(Some("foo.local".to_string()))
+        .txt(Some(txt))
+        .build()
+        .unwrap();
+
+    let json = serde_json::to_string(&registration).unwrap();
+
+    assert_eq!(
+        serde_json::from_str::<ServiceRegistration>(&json).unwrap(),
+        registration
+    );
+}
+
+#[test]
+#[cfg(feature = "serde")]
+fn service_registration_deserializes_without_new_fields() {
+    let json = r#"{"name":"foo","service_type":{"name":"http","protocol":"tcp","sub_types":[]},"domain":"local"}"#;
+    let registration = serde_json::from_str::<ServiceRegistration>(json).unwrap();
+
+    assert_eq!(registration.name(), "foo");
+    assert_eq!(*registration.port(), 0);
+    assert_eq!(*registration.txt(), None);
+}
+
+#[test]
+fn service_registration_flags_are_returned() {
+    super::setup();
+
+    let mut service = MdnsService::new(ServiceType::new("http", "tcp").unwrap(), 8080);
+
+    assert_eq!(service.registration_flags(), RegistrationFlags::NONE);
+
+    service
+        .set_registration_flags(RegistrationFlags::NO_PROBE)
+        .unwrap();
+
+    assert_eq!(service.registration_flags(), RegistrationFlags::NO_PROBE);
+}
+
+#[test]
+#[cfg(all(
+    any(target_vendor = "apple", target_vendor = "pc"),
+    not(feature = "test-mock")
+))]
+fn service_registration_flags_rejects_unsupported() {
+    super::setup();
+
+    let mut service = MdnsService::new(ServiceType::new("http", "tcp").unwrap(), 8080);
+
+    assert!(matches!(
+        service.set_registration_flags(RegistrationFlags::UPDATE),
+        Err(Error::Unsupported(_))
+    ));
+    assert_eq!(service.registration_flags(), RegistrationFlags::NONE);
+}
+
+#[test]
+fn registration_flags_debug_lists_flags() {
+    assert_eq!(format!("{:?}", RegistrationFlags::NONE), "NONE");
+    assert_eq!(
+        format!(
+            "{:?}",
+            RegistrationFlags::USE_MULTICAST | RegistrationFlags::NO_PROBE
+        ),
+        "NO_PROBE | USE_MULTICAST"
+    );
+}
+
+#[test]
+fn service_allow_name_conflicts_is_returned() {
+    super::setup();
+
+    let mut service = MdnsService::new(ServiceType::new("http", "tcp").unwrap(), 8080);
+
+    assert!(service.allow_name_conflicts());
+
+    service.set_allow_name_conflicts(false);
+
+    assert!(!service.allow_name_conflicts());
+}
+
+#[test]
+fn service_auto_reconnect_defaults_to_false() {
+    let mut service = MdnsService::new(ServiceType::new("http", "tcp").unwrap(), 8080);
+
+    assert!(!service.auto_reconnect());
+
+    #[cfg(any(target_os = "linux", feature = "test-mock"))]
+    {
+        service.set_auto_reconnect(true).unwrap();
+        assert!(service.auto_reconnect());
+    }
+
+    #[cfg(not(any(target_os = "linux", feature = "test-mock")))]
+    assert!(matches!(
+        service.set_auto_reconnect(true),
+        Err(Error::Unsupported(_))
+    ));
+}
+
+#[test]
+fn service_protocol_defaults_to_any() {
+    let mut service = MdnsService::new(ServiceType::new("http", "tcp").unwrap(), 8080);
+
+    assert_eq!(service.protocol(), Protocol::Any);
+
+    #[cfg(any(target_os = "linux", feature = "test-mock"))]
+    {
+        service.set_protocol(Protocol::V4).unwrap();
+        assert_eq!(service.protocol(), Protocol::V4);
+    }
+
+    #[cfg(not(any(target_os = "linux", feature = "test-mock")))]
+    assert!(matches!(
+        service.set_protocol(Protocol::V4),
+        Err(Error::Unsupported(_))
+    ));
+}
+
+#[test]
+fn service_builder_sets_properties() {
+    let service = MdnsService::builder()
+        .service_type(ServiceType::new("http", "tcp").unwrap())
+        .port(8080)
+        .name("foo")
+        .host("foo.local")
+        .txt(TxtRecord::new())
+        .context(Box::new(42_u32))
+        .build()
+        .unwrap();
+
+    assert_eq!(service.name(), Some("foo"));
+    assert_eq!(service.host(), Some("foo.local"));
+    assert_eq!(service.domain(), None);
+    assert!(service.txt_record().is_some());
+    assert_eq!(
+        service.context().and_then(|c| c.downcast_ref::<u32>()),
+        Some(&42)
+    );
+}
+
+#[test]
+fn service_builder_requires_port() {
+    let result = MdnsService::builder()
+        .service_type(ServiceType::new("http", "tcp").unwrap())
+        .build();
+
+    assert!(result.is_err());
+}
+
+#[test]
+fn service_builder_rejects_port_zero_unless_placeholder() {
+    let builder = || {
+        MdnsService::builder()
+            .service_type(ServiceType::new("http", "tcp").unwrap())
+            .port(0)
+    };
+
+    assert!(builder().build().is_err());
+    assert_eq!(builder().placeholder(true).build().unwrap().port(), 0);
+}
+
+#[test]
+fn service_builder_rejects_invalid_name() {
+    let result = MdnsService::builder()
+        .service_type(ServiceType::new("http", "tcp").unwrap())
+        .port(8080)
+        .name("a".repeat(MAX_NAME_LEN + 1))
+        .build();
+
+    assert!(matches!(result, Err(Error::NameTooLong { .. })));
+}
+
+#[test]
+fn service_try_set_name_accepts_multi_byte_name_of_max_len() {
+    let mut service = MdnsService::new(ServiceType::new("http", "tcp").unwrap(), 8080);
+
+    // 3 bytes per char, 21 chars = 63 bytes
+    let name = "漢".repeat(21);
+
+    assert_eq!(service.try_set_name(&name), Ok(()));
+    assert_eq!(service.name(), Some(name.as_str()));
+}
+
+#[test]
+fn service_try_set_name_rejects_multi_byte_name_over_max_len() {
+    let mut service = MdnsService::new(ServiceType::new("http", "tcp").unwrap(), 8080);
+
+    service.set_name("foo");
+
+    // 4 bytes per char, 16 chars = 64 bytes
+    let name = "😀".repeat(16);
+
+    assert_eq!(
+        service.try_set_name(&name),
+        Err(Error::NameTooLong {
+            bytes: 64,
+            max: MAX_NAME_LEN
+        })
+    );
+    assert_eq!(service.name(), Some("foo"));
+
+    service.set_name_fitting(true);
+
+    assert_eq!(service.try_set_name(&name), Ok(()));
+}
+
+#[test]
+fn service_try_set_name_rejects_empty_name_and_nul() {
+    let mut service = MdnsService::new(ServiceType::new("http", "tcp").unwrap(), 8080);
+
+    assert!(matches!(
+        service.try_set_name(""),
+        Err(Error::InvalidName(_))
+    ));
+    assert!(matches!(
+        service.try_set_name("foo\0bar"),
+        Err(Error::InvalidName(_))
+    ));
+    assert_eq!(service.name(), None);
+}
+
+/// Registration on an Avahi daemon that starts before any network interface is up, as on embedded
+/// systems. The tests need root along with `ip`, `dbus-daemon` and `avahi-daemon`, so they are
+/// ignored by default and run by CI with `-- --ignored ::netns::`.
+#[cfg(all(target_os = "linux", not(feature = "test-mock")))]
+mod netns {
+    use super::*;
+    use crate::RegistrationState;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process::{Child, Command};
+
+    const NAMESPACE: &str = "zeroconf-test";
+    const HOST_INTERFACE: &str = "zc-host";
+    const NAMESPACE_INTERFACE: &str = "zc-ns";
+
+    const AVAHI_CONFIG: &str = "\
+[server]
+host-name=zeroconf-netns
+use-ipv4=yes
+use-ipv6=no
+
+[wide-area]
+enable-wide-area=no
+
+[publish]
+publish-hinfo=no
+publish-workstation=no
+";
+
+    /// Returns the configuration of a bus that lets everyone own and talk to any name, so that the
+    /// daemon of the namespace does not need the policy of the system bus.
+    fn bus_config(address: &str) -> String {
+        format!(
+            r#"<!DOCTYPE busconfig PUBLIC "-//freedesktop//DTD D-Bus Bus Configuration 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/busconfig.dtd">
+<busconfig>
+  <type>system</type>
+  <listen>{}</listen>
+  <auth>EXTERNAL</auth>
+  <policy context="default">
+    <allow user="*"/>
+    <allow own="*"/>
+    <allow send_type="method_call"/>
+    <allow send_type="signal"/>
+    <allow send_type="method_return"/>
+    <allow send_type="error"/>
+    <allow receive_type="method_call"/>
+    <allow receive_type="signal"/>
+    <allow receive_type="method_return"/>
+    <allow receive_type="error"/>
+  </policy>
+</busconfig>
+"#,
+            address
+        )
+    }
+
+    fn ip(args: &[&str]) {
+        let status = Command::new("ip").args(args).status().unwrap();
+        assert!(status.success(), "`ip {}` failed", args.join(" "));
+    }
+
+    fn wait_for(what: &str, mut f: impl FnMut() -> bool) {
+        let start = Instant::now();
+
+        while !f() {
+            assert!(
+                start.elapsed() < Duration::from_secs(10),
+                "timed out waiting for {}",
+                what
+            );
+            std::thread::sleep(Duration::from_millis(100));
+        }
+    }
+
+    /// A network namespace with only a loopback interface, running its own `avahi-daemon` on a
+    /// private bus so that it does not clash with the daemon of the host. The client of this
+    /// process talks to it through the bus, whichever namespace the process is in. Everything is
+    /// torn down on drop.
+    struct Namespace {
+        dir: PathBuf,
+        bus: Child,
+        daemon: Child,
+    }
+
+    impl Namespace {
+        fn start() -> Self {
+            let dir = std::env::temp_dir().join(format!("zeroconf-netns-{}", std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir(&dir).unwrap();
+
+            let bus_address = format!("unix:path={}", dir.join("bus").display());
+
+            fs::write(dir.join("bus.conf"), bus_config(&bus_address)).unwrap();
+            fs::write(dir.join("avahi-daemon.conf"), AVAHI_CONFIG).unwrap();
+
+            // a namespace left behind by an aborted run would make adding it fail
+            let _ = Command::new("ip")
+                .args(["netns", "del", NAMESPACE])
+                .status();
+
+            ip(&["netns", "add", NAMESPACE]);
+            ip(&["-n", NAMESPACE, "link", "set", "lo", "up"]);
+
+            let bus = Command::new("dbus-daemon")
+                .arg(format!("--config-file={}", dir.join("bus.conf").display()))
+                .arg("--nofork")
+                .spawn()
+                .unwrap();
+
+            wait_for("the bus", || dir.join("bus").exists());
+
+            // `ip netns exec` also runs the daemon in a mount namespace of its own, in which its
+            // pid file does not clash with the one of the host's daemon
+            let daemon = Command::new("ip")
+                .args(["netns", "exec", NAMESPACE, "sh", "-c"])
+                .arg(format!(
+                    "mount -t tmpfs tmpfs /run/avahi-daemon && exec avahi-daemon --no-drop-root \
+                     --no-chroot --no-rlimits -f {}",
+                    dir.join("avahi-daemon.conf").display()
+                ))
+                .env("DBUS_SYSTEM_BUS_ADDRESS", &bus_address)
+                .spawn()
+                .unwrap();
+
+            std::env::set_var("DBUS_SYSTEM_BUS_ADDRESS", &bus_address);
+
+            let namespace = Self { dir, bus, daemon };
+            wait_for("avahi-daemon", crate::is_available);
+            namespace
+        }
+
+        /// Connects the namespace to the host through a veth pair, which gives it its first
+        /// multicast capable interface.
+        fn add_interface(&self) {
+            ip(&[
+                "link",
+                "add",
+                HOST_INTERFACE,
+                "type",
+                "veth",
+                "peer",
+                "name",
+                NAMESPACE_INTERFACE,
+                "netns",
+                NAMESPACE,
+            ]);
+            ip(&["addr", "add", "10.231.0.1/24", "dev", HOST_INTERFACE]);
+            ip(&["link", "set", HOST_INTERFACE, "up"]);
+            ip(&[
+                "-n",
+                NAMESPACE,
+                "addr",
+                "add",
+                "10.231.0.2/24",
+                "dev",
+                NAMESPACE_INTERFACE,
+            ]);
+            ip(&["-n", NAMESPACE, "link", "set", NAMESPACE_INTERFACE, "up"]);
+        }
+    }
+
+    impl Drop for Namespace {
+        fn drop(&mut self) {
+            std::env::remove_var("DBUS_SYSTEM_BUS_ADDRESS");
+
+            let _ = self.daemon.kill();
+            let _ = self.daemon.wait();
+            let _ = self.bus.kill();
+            let _ = self.bus.wait();
+
+            // also removes the veth pair, once the daemon has left the namespace
+            let _ = Command::new("ip")
+                .args(["netns", "del", NAMESPACE])
+                .status();
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    #[test]
+    #[ignore = "needs root, ip, dbus-daemon and avahi-daemon"]
+    fn service_registered_without_interfaces_is_browsable_once_one_appears() {
+        super::super::setup();
+
+        static SERVICE_NAME: &str = "service_registered_without_interfaces";
+
+        let namespace = Namespace::start();
+
+        let states: Arc<Mutex<Vec<RegistrationState>>> = Arc::default();
+        let errors: Arc<Mutex<Vec<Error>>> = Arc::default();
+
+        let mut service = MdnsService::new(ServiceType::new("http", "tcp").unwrap(), 8080);
+        service.set_name(SERVICE_NAME);
+
+        let sink = states.clone();
+        service.set_registration_state_callback(Box::new(move |state, _| {
+            sink.lock().unwrap().push(state)
+        }));
+
+        let sink = errors.clone();
+        service.set_on_registered(move |result| {
+            if let Err(e) = result {
+                sink.lock().unwrap().push(e);
+            }
+        });
+
+        let service_event_loop = service.register().unwrap();
+
+        let (tx, rx) = mpsc::channel();
+        let mut browser = MdnsBrowser::new(ServiceType::new("http", "tcp").unwrap());
+
+        browser.set_on_discovered(move |result| match result {
+            Ok(discovery) if discovery.name() == SERVICE_NAME => {
+                let _ = tx.send(discovery);
+            }
+            _ => {}
+        });
+
+        let browser_event_loop = browser.browse_services().unwrap();
+
+        let poll_for = |timeout: Duration| {
+            let start = Instant::now();
+
+            while start.elapsed() < timeout {
+                service_event_loop.poll(Duration::from_millis(50)).unwrap();
+                browser_event_loop.poll(Duration::from_millis(50)).unwrap();
+
+                if let Ok(discovery) = rx.try_recv() {
+                    return Some(discovery);
+                }
+            }
+
+            None
+        };
+
+        assert!(
+            poll_for(Duration::from_secs(2)).is_none(),
+            "the service should not be published on loopback only"
+        );
+
+        namespace.add_interface();
+
+        let discovery = poll_for(Duration::from_secs(30))
+            .expect("the service should be discovered once an interface appeared");
+
+        assert_eq!(discovery.address(), "10.231.0.2");
+        assert!(errors.lock().unwrap().is_empty());
+        assert!(!states.lock().unwrap().is_empty());
+    }
+}
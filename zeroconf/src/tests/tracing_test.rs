@@ -0,0 +1,69 @@
+use crate::prelude::*;
+use crate::{MdnsBrowser, MdnsResolver, MdnsService, ServiceType};
+use std::time::Duration;
+
+#[test]
+fn browser_browse_services_is_traced_in_browse_span() {
+    super::setup();
+
+    let mut browser = MdnsBrowser::new(ServiceType::new("http", "tcp").unwrap());
+
+    // browsing fails without a running daemon, but is traced regardless
+    let event_loop = browser.browse_services();
+
+    assert!(super::logged(&format!(
+        "browse{{id={} service_type=\"_http._tcp\"}}",
+        browser.id()
+    )));
+    assert!(super::logged(&format!(
+        "[{}] Browsing services",
+        browser.id()
+    )));
+
+    #[cfg(all(target_os = "linux", not(feature = "test-mock")))]
+    assert!(super::logged(&format!(
+        "browse{{id={} service_type=\"_http._tcp\"}}:client{{",
+        browser.id()
+    )));
+
+    if let Ok(event_loop) = event_loop {
+        event_loop.poll(Duration::ZERO).unwrap();
+
+        assert!(super::logged(&format!(
+            "poll{{event_loop={}}}",
+            event_loop.id()
+        )));
+        assert!(super::logged(&format!("[{}] Polled for", event_loop.id())));
+    }
+}
+
+#[test]
+fn service_register_is_traced_in_register_span() {
+    super::setup();
+
+    let mut service = MdnsService::new(ServiceType::new("http", "tcp").unwrap(), 8080);
+    service.set_name("traced");
+
+    // registration fails without a running daemon, but is traced regardless
+    let _ = service.register();
+
+    assert!(super::logged(&format!(
+        "register{{id={} name=\"traced\" service_type=\"_http._tcp\"}}",
+        service.id()
+    )));
+}
+
+#[test]
+fn resolver_resolve_is_traced_in_resolve_span() {
+    super::setup();
+
+    let mut resolver = MdnsResolver::new();
+
+    // resolving fails without a running daemon, but is traced regardless
+    let _ = resolver.resolve("traced", ServiceType::new("http", "tcp").unwrap(), "local");
+
+    assert!(super::logged(&format!(
+        "resolve{{id={} name=\"traced\" service_type=\"_http._tcp\"",
+        resolver.id()
+    )));
+}
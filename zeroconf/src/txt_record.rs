@@ -1,5 +1,6 @@
 //! TxtRecord utilities common to all platforms
 
+use crate::error::Error;
 use crate::{Result, TxtRecord};
 #[cfg(feature = "serde")]
 use serde::de::{MapAccess, Visitor};
@@ -8,55 +9,301 @@ use serde::ser::SerializeMap;
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::collections::HashMap;
+use std::ffi::CString;
 use std::fmt::{self, Debug};
+use std::iter::FromIterator;
 #[cfg(feature = "serde")]
 use std::marker::PhantomData;
+use std::ops::Index;
+
+/// Maximum length in bytes of a single `key=value` entry in a TXT record (RFC 6763, section 6.1)
+pub const MAX_ENTRY_LEN: usize = 255;
 
 /// Interface for interacting with underlying mDNS implementation TXT record capabilities
+///
+/// Keys are compared case-insensitively, as required by RFC 6763. Entries are kept in the order
+/// they were inserted in, which is also the order they are sent in, with a replaced entry moving
+/// to the end.
+///
+/// Records received from the network may contain a key more than once. Lookups such as [`get()`]
+/// return the first occurrence (RFC 6763, section 6.4), while the iterators and [`get_all()`]
+/// yield every occurrence.
+///
+/// [`get()`]: #method.get
+/// [`get_all()`]: #method.get_all
 pub trait TTxtRecord: Clone + PartialEq + Eq + Debug {
     /// Constructs a new TXT record
     fn new() -> Self;
 
-    /// Inserts the specified value at the specified key.
-    fn insert(&mut self, key: &str, value: &str) -> Result<()>;
+    /// Inserts the specified value at the specified key, replacing every existing value for a key
+    /// that differs only in case.
+    ///
+    /// Returns `Error::InvalidTxtRecord` if the key is not ASCII, contains `=` or a NUL byte, or if
+    /// the encoded `key=value` entry is longer than [`MAX_ENTRY_LEN`] bytes.
+    ///
+    /// [`MAX_ENTRY_LEN`]: constant.MAX_ENTRY_LEN.html
+    fn insert(&mut self, key: &str, value: &str) -> Result<()> {
+        self.insert_bytes(key, value.as_bytes())
+    }
+
+    /// Inserts the specified binary value at the specified key. TXT record values are arbitrary
+    /// byte strings and do not need to be valid UTF-8.
+    ///
+    /// The same restrictions as [`insert()`] apply.
+    ///
+    /// [`insert()`]: #method.insert
+    fn insert_bytes(&mut self, key: &str, value: &[u8]) -> Result<()>;
+
+    /// Inserts the specified key without a value. Such keys are treated as boolean attributes
+    /// that are true when present (RFC 6763, section 6.4).
+    fn insert_key_only(&mut self, key: &str) -> Result<()>;
 
-    /// Returns the value at the specified key or `None` if no such key exists.
+    /// Returns the value at the specified key or `None` if no such key exists or the key has no
+    /// value. Use [`get_opt()`] to tell these cases apart.
+    ///
+    /// If the key occurs more than once, the value of the first occurrence is returned, as
+    /// specified by RFC 6763, section 6.4. Use [`get_all()`] to read every occurrence.
     ///
     /// This function returns an owned `String` because there are no guarantees that the
-    /// implementation provides access to the underlying value pointer.
-    fn get(&self, key: &str) -> Option<String>;
+    /// implementation provides access to the underlying value pointer. Values that are not valid
+    /// UTF-8 are converted lossily; use [`get_bytes()`] to read them unchanged.
+    ///
+    /// [`get_opt()`]: #method.get_opt
+    /// [`get_all()`]: #method.get_all
+    /// [`get_bytes()`]: #method.get_bytes
+    fn get(&self, key: &str) -> Option<String> {
+        self.get_bytes(key)
+            .map(|value| String::from_utf8_lossy(&value).into_owned())
+    }
+
+    /// Returns the raw bytes of the value at the specified key or `None` if no such key exists or
+    /// the key has no value. If the key occurs more than once, the first occurrence is returned.
+    fn get_bytes(&self, key: &str) -> Option<Vec<u8>>;
+
+    /// Returns `None` if the key is absent, `Some(None)` if the key is present without a value
+    /// and `Some(Some(value))` otherwise. An empty value (`key=`) is returned as
+    /// `Some(Some(""))`.
+    fn get_opt(&self, key: &str) -> Option<Option<String>> {
+        if self.contains_key(key) {
+            Some(self.get(key))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the value at the specified key, first inserting `default` if the key is absent.
+    /// Keys without a value return an empty `String` and are left unchanged.
+    ///
+    /// Returns `Error::InvalidTxtRecord` if `default` has to be inserted and can not be, see
+    /// [`insert()`].
+    ///
+    /// [`insert()`]: #method.insert
+    fn get_or_insert(&mut self, key: &str, default: &str) -> Result<String> {
+        if let Some(value) = self.get_opt(key) {
+            return Ok(value.unwrap_or_default());
+        }
+
+        self.insert(key, default)?;
+
+        Ok(default.to_string())
+    }
+
+    /// Inserts every entry of `other` into this record, replacing the values of keys that are
+    /// already present. Binary values and keys without a value are kept as they are, and the
+    /// merged entries move to the end in the order of `other`.
+    fn merge(&mut self, other: &Self) {
+        for key in other.keys() {
+            // the entries of `other` have been validated already, so they fit in any record
+            match other.get_bytes(&key) {
+                Some(value) => self.insert_bytes(&key, &value),
+                None => self.insert_key_only(&key),
+            }
+            .expect("could not merge entry");
+        }
+    }
 
-    /// Removes the value at the specified key, returning the previous value if present.
+    /// Removes every occurrence of the specified key, returning the value of the first one if
+    /// present. Keys without a value return an empty `String`.
     fn remove(&mut self, key: &str) -> Option<String>;
 
     /// Returns true if the TXT record contains the specified key.
     fn contains_key(&self, key: &str) -> bool;
 
-    /// Returns the amount of entries in the TXT record.
+    /// Returns the amount of entries in the TXT record, counting every occurrence of a key.
     fn len(&self) -> usize;
 
-    /// Returns a new iterator for iterating over the record as you would a `HashMap`.
+    /// Returns a new iterator for iterating over the record as you would a `HashMap`, in
+    /// insertion order.
+    ///
+    /// Values that are not valid UTF-8 are converted lossily.
     fn iter<'a>(&'a self) -> Box<dyn Iterator<Item = (String, String)> + 'a>;
 
     /// Returns a new iterator over the records keys.
     fn keys<'a>(&'a self) -> Box<dyn Iterator<Item = String> + 'a>;
 
     /// Returns a new iterator over the records values.
+    ///
+    /// Values that are not valid UTF-8 are converted lossily.
     fn values<'a>(&'a self) -> Box<dyn Iterator<Item = String> + 'a>;
 
+    /// Returns a new iterator over the record's keys and raw values.
+    fn iter_bytes<'a>(&'a self) -> Box<dyn Iterator<Item = (String, Vec<u8>)> + 'a>;
+
     /// Returns true if there are no entries in the record.
     fn is_empty(&self) -> bool {
         self.len() == 0
     }
 
-    /// Returns a new `HashMap` with this record's keys and values.
+    /// Encodes the record in the DNS wire format: a sequence of length-prefixed `key=value`
+    /// strings (RFC 6763, section 6). An empty record is encoded as a single empty string.
+    fn to_bytes(&self) -> Vec<u8>;
+
+    /// Decodes a record from the DNS wire format, as produced by [`to_bytes()`].
+    ///
+    /// Empty strings and strings without a key are ignored. A key that occurs more than once is
+    /// kept every time, see [`get_all()`]. Returns `Error::InvalidTxtRecord` if a string's
+    /// length prefix runs past the end of `data` or a key is not ASCII or contains a NUL byte.
+    /// Malformed data never causes a panic.
+    ///
+    /// [`to_bytes()`]: #tymethod.to_bytes
+    /// [`get_all()`]: #method.get_all
+    fn from_bytes(data: &[u8]) -> Result<Self>;
+
+    /// Returns the values of every occurrence of the specified key, in the order they appear in
+    /// the record, or an empty `Vec` if no such key exists. Keys without a value have an empty
+    /// value.
+    ///
+    /// Values that are not valid UTF-8 are converted lossily.
+    fn get_all(&self, key: &str) -> Vec<String> {
+        self.iter()
+            .filter(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value)
+            .collect()
+    }
+
+    /// Returns a new `HashMap` with this record's keys and values. Only the first occurrence of a
+    /// key is kept, see [`get_all()`] for the others.
+    ///
+    /// [`get_all()`]: #method.get_all
     fn to_map(&self) -> HashMap<String, String> {
         let mut m = HashMap::new();
         for (key, value) in self.iter() {
-            m.insert(key, value.to_string());
+            if !m.keys().any(|k: &String| k.eq_ignore_ascii_case(&key)) {
+                m.insert(key, value);
+            }
         }
         m
     }
+
+    /// Returns this record's keys and values in insertion order. Unlike [`to_map()`], this
+    /// preserves the order of the entries, which some devices depend on.
+    ///
+    /// Values that are not valid UTF-8 are converted lossily.
+    ///
+    /// [`to_map()`]: #method.to_map
+    fn to_vec(&self) -> Vec<(String, String)> {
+        self.iter().collect()
+    }
+}
+
+/// Checks that `key` and `value` can be encoded as a single TXT record entry.
+pub(crate) fn validate_entry(key: &str, value: &[u8]) -> Result<()> {
+    if !key.is_ascii() {
+        return Err(Error::InvalidTxtRecord(format!(
+            "key `{}` contains non-ASCII characters",
+            key
+        )));
+    }
+
+    if key.contains('=') {
+        return Err(Error::InvalidTxtRecord(format!(
+            "key `{}` must not contain `=`",
+            key
+        )));
+    }
+
+    if key.contains('\0') {
+        return Err(Error::InvalidTxtRecord(format!(
+            "key `{}` must not contain NUL bytes",
+            key.escape_debug()
+        )));
+    }
+
+    // key + '=' + value
+    let len = key.len() + 1 + value.len();
+
+    if len > MAX_ENTRY_LEN {
+        return Err(Error::InvalidTxtRecord(format!(
+            "entry for key `{}` is {} bytes long, exceeding the maximum of {}",
+            key, len, MAX_ENTRY_LEN
+        )));
+    }
+
+    Ok(())
+}
+
+/// Returns `key` as a `CString` for looking it up in a record, or `None` if it is empty or
+/// contains a NUL byte, in which case no record can contain it.
+pub(crate) fn lookup_key(key: &str) -> Option<CString> {
+    if key.is_empty() {
+        return None;
+    }
+
+    CString::new(key).ok()
+}
+
+/// Checks that `data` is a well-formed TXT record and returns it with the strings that
+/// [`TTxtRecord::from_bytes()`] ignores removed, so that every backend decodes it the same way.
+///
+/// [`TTxtRecord::from_bytes()`]: trait.TTxtRecord.html#tymethod.from_bytes
+pub(crate) fn sanitize_wire_format(data: &[u8]) -> Result<Vec<u8>> {
+    let mut sanitized = Vec::with_capacity(data.len());
+    let mut offset = 0;
+
+    while offset < data.len() {
+        let len = data[offset] as usize;
+        let start = offset + 1;
+        let end = start + len;
+
+        let entry = data.get(start..end).ok_or_else(|| {
+            Error::InvalidTxtRecord(format!(
+                "string at offset {} is {} bytes long, but only {} bytes remain",
+                offset,
+                len,
+                data.len() - start
+            ))
+        })?;
+
+        offset = end;
+
+        let key = match entry.iter().position(|b| *b == b'=') {
+            Some(i) => &entry[..i],
+            None => entry,
+        };
+
+        if key.is_empty() {
+            continue;
+        }
+
+        if !key.is_ascii() {
+            return Err(Error::InvalidTxtRecord(format!(
+                "key `{}` contains non-ASCII characters",
+                String::from_utf8_lossy(key)
+            )));
+        }
+
+        if key.contains(&0) {
+            return Err(Error::InvalidTxtRecord(format!(
+                "key `{}` contains NUL bytes",
+                String::from_utf8_lossy(key).escape_debug()
+            )));
+        }
+
+        sanitized.push(len as u8);
+        sanitized.extend_from_slice(entry);
+    }
+
+    Ok(sanitized)
 }
 
 impl From<HashMap<String, String>> for TxtRecord {
@@ -80,6 +327,57 @@ impl From<HashMap<&str, &str>> for TxtRecord {
     }
 }
 
+/// Collects the pairs into a record in the order they are yielded in.
+///
+/// # Panics
+/// Panics if a pair can not be inserted, see [`TTxtRecord::insert()`].
+///
+/// [`TTxtRecord::insert()`]: trait.TTxtRecord.html#method.insert
+impl FromIterator<(String, String)> for TxtRecord {
+    fn from_iter<I: IntoIterator<Item = (String, String)>>(iter: I) -> Self {
+        let mut record = TxtRecord::new();
+        record.extend(iter);
+        record
+    }
+}
+
+/// Inserts the pairs in the order they are yielded in, replacing the values of existing keys.
+///
+/// # Panics
+/// Panics if a pair can not be inserted, see [`TTxtRecord::insert()`].
+///
+/// [`TTxtRecord::insert()`]: trait.TTxtRecord.html#method.insert
+impl Extend<(String, String)> for TxtRecord {
+    fn extend<I: IntoIterator<Item = (String, String)>>(&mut self, iter: I) {
+        for (key, value) in iter {
+            self.insert(&key, &value)
+                .expect("could not insert key/value pair");
+        }
+    }
+}
+
+/// Returns the value at the specified key, like a `HashMap` does. Keys without a value return an
+/// empty string.
+///
+/// # Panics
+/// Panics if the key is not present or its value is not valid UTF-8. Use [`TTxtRecord::get()`]
+/// or [`TTxtRecord::get_bytes()`] to handle these cases.
+///
+/// [`TTxtRecord::get()`]: trait.TTxtRecord.html#method.get
+/// [`TTxtRecord::get_bytes()`]: trait.TTxtRecord.html#tymethod.get_bytes
+impl Index<&str> for TxtRecord {
+    type Output = str;
+
+    fn index(&self, key: &str) -> &str {
+        let value = self
+            .value_ref(key)
+            .unwrap_or_else(|| panic!("no entry found for key `{}`", key));
+
+        std::str::from_utf8(value)
+            .unwrap_or_else(|_| panic!("value of key `{}` is not valid UTF-8", key))
+    }
+}
+
 impl Eq for TxtRecord {}
 
 impl Default for TxtRecord {
@@ -247,6 +545,39 @@ mod tests {
         }
     }
 
+    #[test]
+    fn empty_record_has_no_entries() {
+        crate::tests::setup();
+
+        let record = TxtRecord::new();
+
+        assert_eq!(record.len(), 0);
+        assert!(record.is_empty());
+        assert_eq!(record.iter().count(), 0);
+        assert_eq!(record.iter_bytes().count(), 0);
+        assert_eq!(record.keys().count(), 0);
+        assert_eq!(record.values().count(), 0);
+        assert!(record.to_map().is_empty());
+        assert_eq!(record.get(""), None);
+        assert!(!record.contains_key(""));
+    }
+
+    #[test]
+    fn removing_only_key_empties_record() {
+        crate::tests::setup();
+
+        let mut record = TxtRecord::new();
+        record.insert("foo", "bar").unwrap();
+        record.remove("foo");
+
+        assert_eq!(record.len(), 0);
+        assert!(record.is_empty());
+        assert_eq!(record.iter().count(), 0);
+        assert_eq!(record.keys().count(), 0);
+        assert_eq!(record, TxtRecord::new());
+        assert_eq!(record.to_bytes(), TxtRecord::new().to_bytes());
+    }
+
     #[test]
     fn keys_success() {
         crate::tests::setup();
@@ -298,6 +629,220 @@ mod tests {
         assert_eq!(record.get("foo").unwrap(), "bar");
     }
 
+    #[test]
+    fn collect_success() {
+        crate::tests::setup();
+
+        let record = vec![("foo", "bar"), ("baz", "qux")]
+            .into_iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect::<TxtRecord>();
+
+        assert_eq!(record.len(), 2);
+        assert_eq!(record.get("foo").unwrap(), "bar");
+        assert_eq!(record.get("baz").unwrap(), "qux");
+    }
+
+    #[test]
+    fn extend_replaces_existing_keys() {
+        crate::tests::setup();
+
+        let mut record = TxtRecord::new();
+        record.insert("foo", "bar").unwrap();
+        record.extend(vec![
+            ("FOO".to_string(), "baz".to_string()),
+            ("qux".to_string(), "quux".to_string()),
+        ]);
+
+        assert_eq!(record.len(), 2);
+        assert_eq!(record.get("foo").unwrap(), "baz");
+        assert_eq!(record.get("qux").unwrap(), "quux");
+    }
+
+    #[test]
+    fn index_returns_value() {
+        crate::tests::setup();
+
+        let mut record = TxtRecord::new();
+        record.insert("foo", "bar").unwrap();
+        record.insert_key_only("flag").unwrap();
+        record.insert("empty", "").unwrap();
+
+        assert_eq!(&record["foo"], "bar");
+        assert_eq!(&record["FOO"], "bar");
+        assert_eq!(&record["flag"], "");
+        assert_eq!(&record["empty"], "");
+    }
+
+    #[test]
+    #[should_panic(expected = "no entry found for key `foo`")]
+    fn index_panics_on_missing_key() {
+        crate::tests::setup();
+
+        let _ = &TxtRecord::new()["foo"];
+    }
+
+    #[test]
+    #[should_panic(expected = "not valid UTF-8")]
+    fn index_panics_on_binary_value() {
+        crate::tests::setup();
+
+        let mut record = TxtRecord::new();
+        record.insert_bytes("foo", &[0xff, 0xfe]).unwrap();
+
+        let _ = &record["foo"];
+    }
+
+    #[test]
+    fn get_or_insert_keeps_existing_value() {
+        crate::tests::setup();
+
+        let mut record = TxtRecord::new();
+        record.insert("foo", "bar").unwrap();
+        record.insert_key_only("flag").unwrap();
+
+        assert_eq!(record.get_or_insert("FOO", "baz"), Ok("bar".to_string()));
+        assert_eq!(record.get_or_insert("flag", "baz"), Ok(String::new()));
+        assert_eq!(record.get("foo").unwrap(), "bar");
+        assert_eq!(record.get_opt("flag"), Some(None));
+        assert_eq!(record.len(), 2);
+    }
+
+    #[test]
+    fn get_or_insert_inserts_default() {
+        crate::tests::setup();
+
+        let mut record = TxtRecord::new();
+
+        assert_eq!(record.get_or_insert("foo", "bar"), Ok("bar".to_string()));
+        assert_eq!(&record["foo"], "bar");
+    }
+
+    #[test]
+    fn get_or_insert_rejects_default_over_max_len() {
+        crate::tests::setup();
+
+        let mut record = TxtRecord::new();
+        let value = "a".repeat(MAX_ENTRY_LEN - "foo=".len() + 1);
+
+        assert!(matches!(
+            record.get_or_insert("foo", &value),
+            Err(Error::InvalidTxtRecord(_))
+        ));
+        assert!(record.is_empty());
+
+        // an existing value is returned without validating the default
+        record.insert("foo", "bar").unwrap();
+        assert_eq!(record.get_or_insert("foo", &value), Ok("bar".to_string()));
+    }
+
+    #[test]
+    fn merge_overwrites_existing_keys() {
+        crate::tests::setup();
+
+        let mut record = TxtRecord::new();
+        record.insert("a", "1").unwrap();
+        record.insert("b", "2").unwrap();
+        record.insert("c", "3").unwrap();
+
+        let mut other = TxtRecord::new();
+        other.insert("B", "4").unwrap();
+        other.insert_key_only("flag").unwrap();
+        other.insert_bytes("bin", &[0xff]).unwrap();
+
+        record.merge(&other);
+
+        assert_eq!(record.len(), 5);
+        assert_eq!(
+            record.keys().collect::<Vec<_>>(),
+            vec!["a", "c", "B", "flag", "bin"]
+        );
+        assert_eq!(&record["b"], "4");
+        assert_eq!(record.get_opt("flag"), Some(None));
+        assert_eq!(record.get_bytes("bin"), Some(vec![0xff]));
+        assert_eq!(other.len(), 3);
+    }
+
+    #[test]
+    fn merge_accepts_entries_of_max_len() {
+        crate::tests::setup();
+
+        let value = "a".repeat(MAX_ENTRY_LEN - "foo=".len());
+
+        let mut record = TxtRecord::new();
+        record.insert("foo", "bar").unwrap();
+
+        let mut other = TxtRecord::new();
+        other.insert("foo", &value).unwrap();
+
+        record.merge(&other);
+
+        assert_eq!(&record["foo"], value);
+        assert_eq!(record.len(), 1);
+    }
+
+    #[test]
+    fn iter_preserves_insertion_order() {
+        crate::tests::setup();
+
+        let pairs = (0..10)
+            .rev()
+            .map(|i| (format!("key{}", i), format!("value{}", i)))
+            .collect::<Vec<_>>();
+
+        let mut record = TxtRecord::new();
+
+        for (key, value) in &pairs {
+            record.insert(key, value).unwrap();
+        }
+
+        assert_eq!(record.iter().collect::<Vec<_>>(), pairs);
+        assert_eq!(record.to_vec(), pairs);
+        assert_eq!(
+            record.keys().collect::<Vec<_>>(),
+            pairs.iter().map(|(k, _)| k.clone()).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            pairs.into_iter().collect::<TxtRecord>().to_vec(),
+            record.to_vec()
+        );
+    }
+
+    #[test]
+    fn replaced_entry_moves_to_end() {
+        crate::tests::setup();
+
+        let mut record = TxtRecord::new();
+        record.insert("a", "1").unwrap();
+        record.insert("b", "2").unwrap();
+        record.insert("c", "3").unwrap();
+        record.insert("a", "4").unwrap();
+        record.remove("b");
+
+        assert_eq!(
+            record.to_vec(),
+            vec![
+                ("c".to_string(), "3".to_string()),
+                ("a".to_string(), "4".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn wire_format_preserves_insertion_order() {
+        crate::tests::setup();
+
+        let mut record = TxtRecord::new();
+        record.insert("b", "1").unwrap();
+        record.insert("a", "2").unwrap();
+
+        assert_eq!(record.to_bytes(), b"\x03b=1\x03a=2");
+        assert_eq!(
+            TxtRecord::from_bytes(&record.to_bytes()).unwrap().to_vec(),
+            record.to_vec()
+        );
+    }
+
     #[test]
     fn clone_success() {
         crate::tests::setup();
@@ -321,4 +866,466 @@ mod tests {
 
         assert_eq!(txt, txt_de);
     }
+
+    #[test]
+    fn insert_accepts_entry_of_max_len() {
+        crate::tests::setup();
+        let mut record = TxtRecord::new();
+        let value = "a".repeat(MAX_ENTRY_LEN - "foo=".len());
+        record.insert("foo", &value).unwrap();
+        assert_eq!(record.get("foo").unwrap(), value);
+    }
+
+    #[test]
+    fn insert_rejects_entry_over_max_len() {
+        crate::tests::setup();
+        let mut record = TxtRecord::new();
+        let value = "a".repeat(MAX_ENTRY_LEN - "foo=".len() + 1);
+        assert!(matches!(
+            record.insert("foo", &value),
+            Err(Error::InvalidTxtRecord(_))
+        ));
+        assert!(record.is_empty());
+    }
+
+    #[test]
+    fn insert_rejects_key_with_equals() {
+        crate::tests::setup();
+        let mut record = TxtRecord::new();
+        assert!(matches!(
+            record.insert("foo=bar", "baz"),
+            Err(Error::InvalidTxtRecord(_))
+        ));
+    }
+
+    #[test]
+    fn insert_rejects_non_ascii_key() {
+        crate::tests::setup();
+        let mut record = TxtRecord::new();
+        assert!(matches!(
+            record.insert("föo", "bar"),
+            Err(Error::InvalidTxtRecord(_))
+        ));
+    }
+
+    #[test]
+    fn insert_bytes_get_bytes_success() {
+        crate::tests::setup();
+        let mut record = TxtRecord::new();
+        let value = vec![0x00, 0xff, 0xfe, b'=', 0x80];
+        record.insert_bytes("foo", &value).unwrap();
+        assert_eq!(record.get_bytes("foo").unwrap(), value);
+        assert_eq!(record.get_bytes("baz"), None);
+    }
+
+    #[test]
+    fn get_is_lossy_for_binary_value() {
+        crate::tests::setup();
+        let mut record = TxtRecord::new();
+        record.insert_bytes("foo", &[b'a', 0xff]).unwrap();
+        assert_eq!(record.get("foo").unwrap(), "a\u{fffd}");
+    }
+
+    #[test]
+    fn iter_bytes_success() {
+        crate::tests::setup();
+        let mut record = TxtRecord::new();
+        record.insert_bytes("foo", &[0xff]).unwrap();
+        record.insert("bar", "baz").unwrap();
+
+        let map = record.iter_bytes().collect::<HashMap<_, _>>();
+
+        assert_eq!(map.len(), 2);
+        assert_eq!(map["foo"], vec![0xff]);
+        assert_eq!(map["bar"], b"baz".to_vec());
+    }
+
+    #[test]
+    fn iter_does_not_panic_on_binary_value() {
+        crate::tests::setup();
+        let mut record = TxtRecord::new();
+        record.insert_bytes("foo", &[0xff]).unwrap();
+
+        assert_eq!(record.to_map()["foo"], "\u{fffd}");
+        assert_eq!(record.values().count(), 1);
+    }
+
+    #[test]
+    fn remove_preserves_binary_values() {
+        crate::tests::setup();
+        let mut record = TxtRecord::new();
+        record.insert_bytes("foo", &[0xff, 0x00]).unwrap();
+        record.insert("bar", "baz").unwrap();
+        record.remove("bar");
+        assert_eq!(record.get_bytes("foo").unwrap(), vec![0xff, 0x00]);
+    }
+
+    #[test]
+    fn insert_bytes_rejects_entry_over_max_len() {
+        crate::tests::setup();
+        let mut record = TxtRecord::new();
+        let value = vec![0; MAX_ENTRY_LEN - "foo=".len() + 1];
+        assert!(matches!(
+            record.insert_bytes("foo", &value),
+            Err(Error::InvalidTxtRecord(_))
+        ));
+    }
+
+    #[test]
+    fn get_is_case_insensitive() {
+        crate::tests::setup();
+        let mut record = TxtRecord::new();
+        record.insert("Foo", "bar").unwrap();
+        assert_eq!(record.get("foo").unwrap(), "bar");
+        assert_eq!(record.get("FOO").unwrap(), "bar");
+        assert!(record.contains_key("fOo"));
+    }
+
+    #[test]
+    fn insert_replaces_key_differing_in_case() {
+        crate::tests::setup();
+        let mut record = TxtRecord::new();
+        record.insert("foo", "bar").unwrap();
+        record.insert("FOO", "baz").unwrap();
+        assert_eq!(record.len(), 1);
+        assert_eq!(record.get("foo").unwrap(), "baz");
+    }
+
+    #[test]
+    fn remove_is_case_insensitive() {
+        crate::tests::setup();
+        let mut record = TxtRecord::new();
+        record.insert("foo", "bar").unwrap();
+        assert_eq!(record.remove("FOO").unwrap(), "bar");
+        assert!(record.is_empty());
+    }
+
+    #[test]
+    fn insert_key_only_success() {
+        crate::tests::setup();
+        let mut record = TxtRecord::new();
+        record.insert_key_only("flag").unwrap();
+        record.insert("empty", "").unwrap();
+
+        assert!(record.contains_key("flag"));
+        assert_eq!(record.get("flag"), None);
+        assert_eq!(record.get_opt("flag"), Some(None));
+        assert_eq!(record.get_opt("FLAG"), Some(None));
+        assert_eq!(record.get_opt("empty"), Some(Some(String::new())));
+        assert_eq!(record.get_opt("missing"), None);
+    }
+
+    #[test]
+    fn remove_key_only_success() {
+        crate::tests::setup();
+        let mut record = TxtRecord::new();
+        record.insert_key_only("flag").unwrap();
+        assert_eq!(record.remove("flag").unwrap(), "");
+        assert!(!record.contains_key("flag"));
+    }
+
+    #[test]
+    fn remove_preserves_other_values() {
+        crate::tests::setup();
+        let mut record = TxtRecord::new();
+
+        let entries = (0..10u8)
+            .map(|i| (format!("key{}", i), vec![i, 0xff, 0x00, 0x80 + i]))
+            .collect::<HashMap<_, _>>();
+
+        for (key, value) in &entries {
+            record.insert_bytes(key, value).unwrap();
+        }
+
+        record.remove("key5").unwrap();
+
+        assert_eq!(record.len(), 9);
+        assert!(!record.contains_key("key5"));
+
+        for (key, value) in entries.iter().filter(|(k, _)| *k != "key5") {
+            assert_eq!(&record.get_bytes(key).unwrap(), value);
+        }
+    }
+
+    #[test]
+    fn to_bytes_encodes_length_prefixed_strings() {
+        crate::tests::setup();
+
+        let mut record = TxtRecord::new();
+        record.insert("foo", "bar").unwrap();
+
+        assert_eq!(record.to_bytes(), b"\x07foo=bar");
+    }
+
+    #[test]
+    fn to_bytes_encodes_key_only_and_empty_values() {
+        crate::tests::setup();
+
+        let mut record = TxtRecord::new();
+        record.insert_key_only("flag").unwrap();
+
+        assert_eq!(record.to_bytes(), b"\x04flag");
+
+        record.remove("flag");
+        record.insert("empty", "").unwrap();
+
+        assert_eq!(record.to_bytes(), b"\x06empty=");
+    }
+
+    #[test]
+    fn to_bytes_encodes_empty_record_as_empty_string() {
+        crate::tests::setup();
+        assert_eq!(TxtRecord::new().to_bytes(), vec![0]);
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        crate::tests::setup();
+
+        let mut record = TxtRecord::new();
+        record.insert("foo", "bar").unwrap();
+        record.insert("baz", "").unwrap();
+        record.insert_bytes("bin", &[0, 159, 255]).unwrap();
+        record.insert_key_only("flag").unwrap();
+
+        let parsed = TxtRecord::from_bytes(&record.to_bytes()).unwrap();
+
+        assert_eq!(parsed, record);
+        assert_eq!(parsed.to_map(), record.to_map());
+        assert_eq!(parsed.get_opt("flag"), Some(None));
+        assert_eq!(parsed.get_opt("baz"), Some(Some(String::new())));
+        assert_eq!(parsed.get_bytes("bin"), Some(vec![0, 159, 255]));
+    }
+
+    #[test]
+    fn from_bytes_parses_foreign_data() {
+        crate::tests::setup();
+
+        let record = TxtRecord::from_bytes(b"\x06path=/\x00\x07txtvers\x03a=b").unwrap();
+
+        assert_eq!(record.len(), 3);
+        assert_eq!(record.get("path").unwrap(), "/");
+        assert_eq!(record.get_opt("txtvers"), Some(None));
+        assert_eq!(record.get("a").unwrap(), "b");
+    }
+
+    #[test]
+    fn from_bytes_parses_empty_record() {
+        crate::tests::setup();
+
+        assert!(TxtRecord::from_bytes(&[0]).unwrap().is_empty());
+        assert!(TxtRecord::from_bytes(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn from_bytes_keeps_duplicate_keys() {
+        crate::tests::setup();
+
+        let data = b"\x04md=a\x01b\x04MD=b\x02md";
+        let record = TxtRecord::from_bytes(data).unwrap();
+
+        assert_eq!(record.len(), 4);
+        assert_eq!(
+            record.to_vec(),
+            vec![
+                ("md".to_string(), "a".to_string()),
+                ("b".to_string(), "".to_string()),
+                ("MD".to_string(), "b".to_string()),
+                ("md".to_string(), "".to_string()),
+            ]
+        );
+        assert_eq!(record.to_bytes(), data);
+    }
+
+    #[test]
+    fn get_returns_first_duplicate_key() {
+        crate::tests::setup();
+
+        let record = TxtRecord::from_bytes(b"\x04md=a\x04MD=b").unwrap();
+
+        assert!(record.contains_key("md"));
+        assert_eq!(record.get("md").unwrap(), "a");
+        assert_eq!(record.get_bytes("MD").unwrap(), b"a");
+        assert_eq!(record.get_opt("md"), Some(Some("a".to_string())));
+        assert_eq!(&record["md"], "a");
+        assert_eq!(
+            record.to_map(),
+            vec![("md".to_string(), "a".to_string())]
+                .into_iter()
+                .collect::<HashMap<_, _>>()
+        );
+
+        let record = TxtRecord::from_bytes(b"\x02md\x04md=b").unwrap();
+
+        assert_eq!(record.get_opt("md"), Some(None));
+    }
+
+    #[test]
+    fn get_all_returns_every_occurrence() {
+        crate::tests::setup();
+
+        let record = TxtRecord::from_bytes(b"\x04md=a\x03a=b\x04MD=b\x02md").unwrap();
+
+        assert_eq!(record.get_all("md"), vec!["a", "b", ""]);
+        assert_eq!(record.get_all("a"), vec!["b"]);
+        assert!(record.get_all("c").is_empty());
+    }
+
+    #[test]
+    fn remove_removes_every_occurrence() {
+        crate::tests::setup();
+
+        let mut record = TxtRecord::from_bytes(b"\x04md=a\x03a=b\x04MD=b").unwrap();
+
+        assert_eq!(record.remove("md").unwrap(), "a");
+        assert!(!record.contains_key("md"));
+        assert_eq!(record.len(), 1);
+        assert_eq!(record.to_bytes(), b"\x03a=b");
+    }
+
+    #[test]
+    fn insert_replaces_every_occurrence() {
+        crate::tests::setup();
+
+        let mut record = TxtRecord::from_bytes(b"\x04md=a\x03a=b\x04MD=b").unwrap();
+
+        record.insert("md", "c").unwrap();
+
+        assert_eq!(record.get_all("md"), vec!["c"]);
+        assert_eq!(record.to_bytes(), b"\x03a=b\x04md=c");
+    }
+
+    #[test]
+    fn from_bytes_ignores_missing_key() {
+        crate::tests::setup();
+
+        let record = TxtRecord::from_bytes(b"\x04=foo\x05a=bar").unwrap();
+
+        assert_eq!(record.len(), 1);
+        assert_eq!(record.get("a").unwrap(), "bar");
+    }
+
+    #[test]
+    fn from_bytes_rejects_truncated_string() {
+        crate::tests::setup();
+
+        assert!(matches!(
+            TxtRecord::from_bytes(b"\x07foo=bar\x09a=b"),
+            Err(Error::InvalidTxtRecord(_))
+        ));
+    }
+
+    #[test]
+    fn from_bytes_rejects_non_ascii_key() {
+        crate::tests::setup();
+
+        assert!(matches!(
+            TxtRecord::from_bytes("\x05ké=v".as_bytes()),
+            Err(Error::InvalidTxtRecord(_))
+        ));
+    }
+
+    #[test]
+    fn insert_rejects_nul_in_key() {
+        crate::tests::setup();
+
+        let mut record = TxtRecord::new();
+
+        assert!(matches!(
+            record.insert("foo\0bar", "baz"),
+            Err(Error::InvalidTxtRecord(_))
+        ));
+        assert!(matches!(
+            record.insert_key_only("foo\0"),
+            Err(Error::InvalidTxtRecord(_))
+        ));
+        assert!(record.is_empty());
+    }
+
+    #[test]
+    fn lookup_of_key_with_nul_misses() {
+        crate::tests::setup();
+
+        let mut record = TxtRecord::new();
+        record.insert("foo", "bar").unwrap();
+
+        assert_eq!(record.get("foo\0"), None);
+        assert!(!record.contains_key("foo\0"));
+        assert_eq!(record.remove("foo\0"), None);
+        assert_eq!(record.len(), 1);
+    }
+
+    #[test]
+    fn insert_accepts_nul_in_value() {
+        crate::tests::setup();
+
+        let mut record = TxtRecord::new();
+        record.insert("foo", "bar\0baz").unwrap();
+
+        assert_eq!(record.get_bytes("foo"), Some(b"bar\0baz".to_vec()));
+    }
+
+    #[test]
+    fn from_bytes_rejects_nul_in_key() {
+        crate::tests::setup();
+
+        assert!(matches!(
+            TxtRecord::from_bytes(b"\x05a\0b=c"),
+            Err(Error::InvalidTxtRecord(_))
+        ));
+    }
+
+    #[test]
+    fn from_bytes_parses_longest_key() {
+        crate::tests::setup();
+
+        let key = "k".repeat(MAX_ENTRY_LEN);
+        let mut data = vec![MAX_ENTRY_LEN as u8];
+        data.extend_from_slice(key.as_bytes());
+
+        let record = TxtRecord::from_bytes(&data).unwrap();
+
+        assert_eq!(record.keys().collect::<Vec<_>>(), vec![key.clone()]);
+        assert_eq!(record.get_opt(&key), Some(None));
+    }
+
+    #[test]
+    fn from_bytes_never_panics_on_arbitrary_data() {
+        crate::tests::setup();
+
+        // xorshift, so that failures are reproducible
+        let mut state: u32 = 0x2545_f491;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state
+        };
+
+        for _ in 0..2000 {
+            let len = next() as usize % 300;
+            let mut data = (0..len).map(|_| next() as u8).collect::<Vec<_>>();
+
+            // make well-formed strings likely, so that more than the length check is exercised
+            let mut offset = 0;
+            while offset < data.len() && next() % 4 != 0 {
+                data[offset] = (data.len() - offset - 1).min(next() as usize % 16) as u8;
+                offset += data[offset] as usize + 1;
+            }
+
+            let record = match TxtRecord::from_bytes(&data) {
+                Ok(record) => record,
+                Err(_) => continue,
+            };
+
+            let entries = record.iter_bytes().collect::<Vec<_>>();
+            assert_eq!(entries.len(), record.len());
+
+            for (key, value) in &entries {
+                assert_eq!(record.get_bytes(key).unwrap_or_default(), *value);
+            }
+
+            assert_eq!(TxtRecord::from_bytes(&record.to_bytes()).unwrap(), record);
+        }
+    }
 }